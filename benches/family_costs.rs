@@ -0,0 +1,101 @@
+//! Seconds a single thread takes per voxel to map every feature of each family, next to the
+//! estimate of [`Family::seconds_per_voxel`] the runtime estimates and the relative costs shown
+//! with the features are based on. Run with `cargo bench --bench family_costs` and refit the
+//! coefficients of the estimate when the two drift apart
+
+use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use array_lib::ArrayDim;
+use glcm::ui::MapOpts;
+use radmap::mapper::compute;
+use radmap::texture::{Family, Feature, TextureOpts};
+
+const SHAPE: [usize; 3] = [20, 20, 20];
+const RADII: [usize; 3] = [1, 2, 3];
+const N_BINS: usize = 32;
+const RUNS: usize = 2;
+
+/// noise, the same on every run
+fn volume() -> Vec<f64> {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    (0..SHAPE.iter().product::<usize>())
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % 1000) as f64
+        })
+        .collect()
+}
+
+/// fastest of a few runs mapping every feature of `family` on one thread
+fn fastest(family: Family, kernel_radius: usize, vol: &[f64], dims: ArrayDim) -> Duration {
+    let features = family.features();
+    let opts = MapOpts {
+        n_bins: N_BINS,
+        kernel_radius,
+        max_threads: Some(1),
+        features: features
+            .iter()
+            .filter_map(|f| match f {
+                Feature::Glcm(g) => Some((*g, g.to_string())),
+                _ => None,
+            })
+            .collect(),
+        ..Default::default()
+    };
+    let texture = TextureOpts {
+        features: features
+            .iter()
+            .filter(|f| !matches!(f, Feature::Glcm(_)))
+            .map(|f| (*f, f.to_string()))
+            .collect(),
+        ..Default::default()
+    };
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .expect("failed to build a thread pool");
+    (0..RUNS)
+        .map(|_| {
+            let opts = opts.clone();
+            let start = Instant::now();
+            pool.install(|| {
+                compute(
+                    opts,
+                    &texture,
+                    vol.to_vec(),
+                    None,
+                    None,
+                    dims,
+                    Arc::new(AtomicUsize::new(0)),
+                )
+            });
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let vol = volume();
+    let dims = ArrayDim::from_shape(&SHAPE);
+    let n_voxels = SHAPE.iter().product::<usize>() as f64;
+    println!(
+        "{n_voxels} voxels, {N_BINS} bins, one thread, fastest of {RUNS} runs, seconds per voxel"
+    );
+    println!("family      radius  measured  estimated  estimated/measured");
+    for family in Family::ALL {
+        for radius in RADII {
+            let measured = fastest(family, radius, &vol, dims).as_secs_f64() / n_voxels;
+            let estimated = family.seconds_per_voxel(N_BINS, radius);
+            println!(
+                "{:<11} {radius:>6}  {measured:>8.2e}  {estimated:>9.2e}  {:>18.2}",
+                family.as_str(),
+                estimated / measured
+            );
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Time to map the averaged GLCM features with one feature selected and with every feature radmap
+//! computes itself, three ways: with the glcm crate, the baseline default runs took before radmap
+//! mapped them, with radmap's own matrices working out the quantities several features share for
+//! each feature again, and with radmap's own matrices keeping them, as runs do now. Run with
+//! `cargo bench --bench glcm_features`
+
+use std::hint::black_box;
+use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use array_lib::ArrayDim;
+use glcm::core::GLCMFeature;
+use glcm::run_glcm_map;
+use glcm::ui::MapOpts;
+use radmap::mapper::compute;
+use radmap::texture::cooccurrence::{is_computed, keep_shared_quantities};
+use radmap::texture::TextureOpts;
+use strum::IntoEnumIterator;
+
+const SHAPE: [usize; 3] = [40, 40, 40];
+const RUNS: usize = 5;
+
+/// noise, the same on every run
+fn volume() -> Vec<f64> {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    (0..SHAPE.iter().product::<usize>())
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % 1000) as f64
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy)]
+enum Mapper {
+    GlcmCrate,
+    Radmap { keep_shared: bool },
+}
+
+impl Mapper {
+    fn name(&self) -> &'static str {
+        match self {
+            Mapper::GlcmCrate => "glcm crate (baseline)",
+            Mapper::Radmap { keep_shared: false } => "radmap, shared quantities per feature",
+            Mapper::Radmap { keep_shared: true } => "radmap, shared quantities kept",
+        }
+    }
+}
+
+/// fastest of a few runs mapping `features`
+fn fastest(mapper: Mapper, features: &[GLCMFeature], vol: &[f64], dims: ArrayDim) -> Duration {
+    let opts = MapOpts {
+        features: features.iter().map(|f| (*f, f.to_string())).collect(),
+        ..Default::default()
+    };
+    let texture = TextureOpts::default();
+    (0..RUNS)
+        .map(|_| {
+            let (opts, vol, done) = (opts.clone(), vol.to_vec(), Arc::new(AtomicUsize::new(0)));
+            let start = Instant::now();
+            match mapper {
+                Mapper::GlcmCrate => {
+                    black_box(run_glcm_map(opts, vol, None, dims, done));
+                }
+                Mapper::Radmap { keep_shared } => {
+                    keep_shared_quantities(keep_shared);
+                    black_box(compute(opts, &texture, vol, None, None, dims, done));
+                }
+            }
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let vol = volume();
+    let dims = ArrayDim::from_shape(&SHAPE);
+    let features: Vec<GLCMFeature> = GLCMFeature::iter().filter(|f| is_computed(*f)).collect();
+    println!(
+        "{} voxels, averaged over 13 directions, fastest of {RUNS} runs",
+        SHAPE.iter().product::<usize>()
+    );
+    let mappers = [
+        Mapper::GlcmCrate,
+        Mapper::Radmap { keep_shared: false },
+        Mapper::Radmap { keep_shared: true },
+    ];
+    for mapper in mappers {
+        let one = fastest(mapper, &features[..1], &vol, dims);
+        let every = fastest(mapper, &features, &vol, dims);
+        let added = every.saturating_sub(one).as_secs_f64() / (features.len() - 1).max(1) as f64;
+        println!(
+            "{:<40} 1 feature: {:8.1} ms, {} features: {:8.1} ms, {:.2} ms per feature added",
+            mapper.name(),
+            one.as_secs_f64() * 1e3,
+            features.len(),
+            every.as_secs_f64() * 1e3,
+            added * 1e3
+        );
+    }
+    keep_shared_quantities(true);
+}
@@ -0,0 +1,34 @@
+// generates the gRPC server and client of the job api. The messages are plain prost structs in
+// src/grpc.rs, so no protoc is needed. proto/radmap.proto describes the same api for clients
+fn main() {
+    let method = |name: &str, route: &str, input: &str, output: &str| {
+        tonic_build::manual::Method::builder()
+            .name(name)
+            .route_name(route)
+            .input_type(format!("crate::grpc::{input}"))
+            .output_type(format!("crate::grpc::{output}"))
+            .codec_path("tonic_prost::ProstCodec")
+    };
+    let service = tonic_build::manual::Service::builder()
+        .name("RadMap")
+        .package("radmap")
+        .method(method("submit_job", "SubmitJob", "JobRequest", "JobHandle").build())
+        .method(
+            method(
+                "stream_progress",
+                "StreamProgress",
+                "JobHandle",
+                "JobProgress",
+            )
+            .server_streaming()
+            .build(),
+        )
+        .method(method("get_result", "GetResult", "JobHandle", "JobResult").build())
+        .method(
+            method("upload_file", "UploadFile", "FileChunk", "UploadedFile")
+                .client_streaming()
+                .build(),
+        )
+        .build();
+    tonic_build::manual::Builder::new().compile(&[service]);
+}
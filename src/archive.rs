@@ -0,0 +1,120 @@
+//! Bundles the outputs of a case into an AES-256 encrypted zip, for studies whose data transfer
+//! agreements require derived data to be encrypted at rest.
+//!
+//! The archive is built inside the staging directory and replaces the staged outputs. Encrypted
+//! runs stage outside the output directory (see [stage_root]), so unencrypted maps never reach
+//! it, not even while they are being written.
+
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use zip::write::SimpleFileOptions;
+use zip::{AesMode, CompressionMethod, ZipWriter};
+
+/// where the outputs of an encrypted run are staged: the requested root, or the system temp
+/// directory rather than the default hidden directory inside the output directory
+pub fn stage_root(requested: Option<&Path>) -> PathBuf {
+    requested.map_or_else(std::env::temp_dir, Path::to_path_buf)
+}
+
+pub enum ArchiveKey {
+    /// password typed into the gui
+    Password(String),
+    /// password taken from the named environment variable, so it never shows up in the process
+    /// list or shell history
+    PasswordEnv(String),
+    /// text file holding the password. A trailing newline is ignored
+    KeyFile(PathBuf),
+}
+
+impl ArchiveKey {
+    pub fn password(&self) -> Result<String, String> {
+        let password = match self {
+            ArchiveKey::Password(password) => password.clone(),
+            ArchiveKey::PasswordEnv(var) => std::env::var(var)
+                .map_err(|_| format!("archive password variable {var} is not set"))?,
+            ArchiveKey::KeyFile(path) => std::fs::read_to_string(path)
+                .map_err(|e| format!("failed to read key file {}: {e}", path.display()))?
+                .trim_end_matches(['\r', '\n'])
+                .to_string(),
+        };
+        if password.is_empty() {
+            return Err("archive password is empty".to_string());
+        }
+        Ok(password)
+    }
+}
+
+/// zips every file in `dir` into `dir/<stem>_outputs.zip` with AES-256 encryption and deletes
+/// the originals. Returns the path of the archive
+pub fn encrypt_dir(dir: &Path, stem: &OsStr, key: &ArchiveKey) -> Result<PathBuf, String> {
+    let password = key.password()?;
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(|e| format!("failed to list {}: {e}", dir.display()))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.is_file())
+        .collect();
+    files.sort();
+
+    let archive = crate::io::output_path(dir, stem, "outputs.zip");
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .with_aes_encryption(AesMode::Aes256, &password);
+    let write = || -> Result<(), Box<dyn std::error::Error>> {
+        let mut zip = ZipWriter::new(BufWriter::new(File::create(&archive)?));
+        for file in &files {
+            zip.start_file(file.file_name().unwrap().to_string_lossy(), options)?;
+            std::io::copy(&mut File::open(file)?, &mut zip)?;
+        }
+        zip.finish()?.flush()?;
+        Ok(())
+    };
+    write().map_err(|e| {
+        let _ = std::fs::remove_file(&archive);
+        format!("failed to write archive {}: {e}", archive.display())
+    })?;
+
+    for file in &files {
+        std::fs::remove_file(file)
+            .map_err(|e| format!("failed to remove {}: {e}", file.display()))?;
+    }
+    Ok(archive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use zip::ZipArchive;
+
+    #[test]
+    fn encrypted_outputs_need_the_password() {
+        let dir = std::env::temp_dir().join("radmap_archive_encrypt");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let files = [("case_contrast.nii", "contrast map"), ("case.json", "{}")];
+        for (name, contents) in files {
+            std::fs::write(dir.join(name), contents).unwrap();
+        }
+
+        let key = ArchiveKey::Password("correct horse".to_string());
+        let archive = encrypt_dir(&dir, OsStr::new("case"), &key).unwrap();
+        for (name, _) in files {
+            assert!(!dir.join(name).exists(), "{name} was left unencrypted");
+        }
+
+        let mut zip = ZipArchive::new(File::open(&archive).unwrap()).unwrap();
+        for (name, contents) in files {
+            let mut read = String::new();
+            zip.by_name_decrypt(name, b"correct horse")
+                .unwrap()
+                .read_to_string(&mut read)
+                .unwrap();
+            assert_eq!(read, contents);
+            assert!(zip.by_name(name).is_err());
+            assert!(zip.by_name_decrypt(name, b"wrong horse").is_err());
+        }
+    }
+}
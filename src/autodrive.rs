@@ -0,0 +1,193 @@
+//! Scripts that drive the GUI without a user, for end to end tests and demo recordings. A script
+//! is a json file of actions run in order, one per frame, started with
+//! `radmap-gui --autodrive script.json`:
+//!
+//! ```json
+//! {"actions": [
+//!     {"action": "input", "path": "scan.nii.gz"},
+//!     {"action": "output_dir", "path": "maps"},
+//!     {"action": "option", "name": "n-bins", "value": "64"},
+//!     {"action": "features", "features": ["contrast", "glrlm_run_entropy"]},
+//!     {"action": "launch"},
+//!     {"action": "wait"},
+//!     {"action": "exit"}
+//! ]}
+//! ```
+//!
+//! Options are named as on the command line, see [`crate::options::MapOption`]. What happens is
+//! written to stdout as json lines, one [`Event`] each, so a test can follow the run. The first
+//! action that fails stops the script and closes the window, and the GUI then exits with 1.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// something for the GUI to do, as a user would
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    /// the volume to map
+    Input { path: PathBuf },
+    /// the mask, none to map the whole volume
+    Mask { path: Option<PathBuf> },
+    /// an existing directory to write the maps to
+    OutputDir { path: PathBuf },
+    /// a map option by its command line name, e.g. `n-bins` set to `64`
+    Option { name: String, value: String },
+    /// selects these features and no others, by name
+    Features { features: Vec<String> },
+    /// launches a run on this machine
+    Launch,
+    /// waits for the run to be computed and written. The script fails if the run does, or
+    /// takes longer than the timeout
+    Wait {
+        #[serde(default)]
+        timeout_s: Option<f64>,
+    },
+    /// does nothing for a while, to pace a recording
+    Pause { seconds: f64 },
+    /// closes the window
+    Exit,
+}
+
+/// what a script did, written to stdout as a json line
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// an action was applied, counted from 0
+    Action {
+        index: usize,
+        action: &'a Action,
+    },
+    Launched,
+    /// the run was computed and its outputs written to `output_dir`
+    Finished {
+        output_dir: &'a Path,
+        elapsed_s: f64,
+    },
+    /// the script stopped at a failed action or run
+    Failed {
+        reason: &'a str,
+    },
+    /// every action was applied
+    Done,
+}
+
+impl Event<'_> {
+    pub fn emit(&self) {
+        match serde_json::to_string(self) {
+            Ok(line) => println!("{line}"),
+            Err(e) => eprintln!("failed to write an autodrive event: {e}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Script {
+    actions: Vec<Action>,
+}
+
+/// the actions of a script left to run
+pub struct Autodrive {
+    actions: VecDeque<Action>,
+    /// actions applied so far
+    applied: usize,
+    /// nothing more is run before this, while pausing
+    resume_at: Option<Instant>,
+    /// the wait for a run going on, and its deadline if there is one
+    waiting: Option<(Action, Option<Instant>)>,
+    failed: Arc<AtomicBool>,
+    stopped: bool,
+}
+
+impl Autodrive {
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        let script: Script = serde_json::from_str(&text)
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        Ok(Self::new(script.actions))
+    }
+
+    pub fn new(actions: impl IntoIterator<Item = Action>) -> Self {
+        Autodrive {
+            actions: actions.into_iter().collect(),
+            applied: 0,
+            resume_at: None,
+            waiting: None,
+            failed: Arc::new(AtomicBool::new(false)),
+            stopped: false,
+        }
+    }
+
+    /// set once the script fails, to be read after the window has closed
+    pub fn failed_flag(&self) -> Arc<AtomicBool> {
+        self.failed.clone()
+    }
+
+    /// the next action to apply. None while pausing, while `busy` with a run that is waited
+    /// for, or once the script is over. Pauses are handled here, a wait is returned once the GUI
+    /// is no longer busy, for it to check how the run went
+    pub fn next(&mut self, busy: bool) -> Option<Action> {
+        if self.stopped {
+            return None;
+        }
+        if let Some(resume_at) = self.resume_at {
+            if Instant::now() < resume_at {
+                return None;
+            }
+            self.resume_at = None;
+        }
+        if let Some((wait, deadline)) = self.waiting.take() {
+            if !busy {
+                return Some(wait);
+            }
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                self.fail("timed out waiting for the run");
+            } else {
+                self.waiting = Some((wait, deadline));
+            }
+            return None;
+        }
+        let Some(action) = self.actions.pop_front() else {
+            self.stopped = true;
+            Event::Done.emit();
+            return None;
+        };
+        Event::Action {
+            index: self.applied,
+            action: &action,
+        }
+        .emit();
+        self.applied += 1;
+        match action {
+            Action::Pause { seconds } => {
+                self.resume_at = Some(Instant::now() + Duration::from_secs_f64(seconds.max(0.)));
+                None
+            }
+            Action::Wait { timeout_s } => {
+                let deadline = timeout_s.map(|t| Instant::now() + Duration::from_secs_f64(t));
+                self.waiting = Some((action, deadline));
+                None
+            }
+            action => Some(action),
+        }
+    }
+
+    /// stops the script, the GUI exits with 1 once its window closes
+    pub fn fail(&mut self, reason: &str) {
+        Event::Failed { reason }.emit();
+        self.failed.store(true, Ordering::Relaxed);
+        self.stopped = true;
+        self.actions.clear();
+    }
+
+    /// whether the script stopped at a failure
+    pub fn has_failed(&self) -> bool {
+        self.failed.load(Ordering::Relaxed)
+    }
+}
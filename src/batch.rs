@@ -0,0 +1,324 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::failure::FailureKind;
+use crate::locale::ReportFormat;
+use crate::memory::{format_bytes, process_rss};
+use crate::schema::Format;
+
+/// how often the watchdog checks on a running case
+const WATCHDOG_POLL: Duration = Duration::from_millis(250);
+
+/// a single volume to process as part of a batch
+#[derive(Debug, Clone)]
+pub struct Case {
+    pub input_vol: PathBuf,
+    pub output_dir: PathBuf,
+    pub mask: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaseStatus {
+    Succeeded,
+    Failed,
+    TimedOut,
+    OutOfMemory,
+    /// left alone by an incremental batch, its outputs being up to date
+    Skipped,
+}
+
+impl CaseStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CaseStatus::Succeeded => "succeeded",
+            CaseStatus::Failed => "failed",
+            CaseStatus::TimedOut => "timed_out",
+            CaseStatus::OutOfMemory => "out_of_memory",
+            CaseStatus::Skipped => "skipped",
+        }
+    }
+}
+
+/// what happened to a case once the watchdog let go of it
+#[derive(Debug, Clone)]
+pub struct CaseOutcome {
+    pub status: CaseStatus,
+    pub exit_code: Option<i32>,
+    pub elapsed: Duration,
+    pub message: String,
+}
+
+/// reads a cases file with one `input_vol,output_dir[,mask]` entry per line. Blank lines,
+/// `#` comments and a leading header line starting with `input` are skipped
+pub fn read_cases(path: impl AsRef<Path>) -> Result<Vec<Case>, String> {
+    let path = path.as_ref();
+    let f = File::open(path)
+        .map_err(|e| format!("failed to open cases file {}: {e}", path.display()))?;
+    let mut cases = vec![];
+    // lines are split as bytes so paths that aren't valid UTF-8 survive
+    for (i, line) in BufReader::new(f).split(b'\n').enumerate() {
+        let line =
+            line.map_err(|e| format!("failed to read cases file {}: {e}", path.display()))?;
+        let line = line.trim_ascii();
+        if line.is_empty() || line.starts_with(b"#") || (i == 0 && line.starts_with(b"input")) {
+            continue;
+        }
+        let fields: Vec<&[u8]> = line.split(|b| *b == b',').map(|s| s.trim_ascii()).collect();
+        if fields.len() < 2 || fields.len() > 3 {
+            return Err(format!(
+                "line {} of {}: expected input_vol,output_dir[,mask] but found {}",
+                i + 1,
+                path.display(),
+                String::from_utf8_lossy(line)
+            ));
+        }
+        cases.push(Case {
+            input_vol: path_from_bytes(fields[0]),
+            output_dir: path_from_bytes(fields[1]),
+            mask: fields
+                .get(2)
+                .filter(|s| !s.is_empty())
+                .map(|s| path_from_bytes(s)),
+        });
+    }
+    Ok(cases)
+}
+
+#[cfg(unix)]
+fn path_from_bytes(b: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(b))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(b: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(b).into_owned())
+}
+
+/// parses durations like `2h`, `45m`, `90s` or `1h30m`. A bare number is taken as seconds
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    if let Ok(secs) = s.parse::<f64>() {
+        return Duration::try_from_secs_f64(secs).map_err(|e| e.to_string());
+    }
+    let mut total = 0.;
+    let mut num = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() || c == '.' {
+            num.push(c);
+            continue;
+        }
+        let scale = match c {
+            'h' => 3600.,
+            'm' => 60.,
+            's' => 1.,
+            _ => return Err(format!("unknown time unit '{c}' in {s}")),
+        };
+        let value: f64 = num
+            .parse()
+            .map_err(|_| format!("expected a number before '{c}' in {s}"))?;
+        total += value * scale;
+        num.clear();
+    }
+    if !num.is_empty() {
+        return Err(format!("missing time unit after {num} in {s}"));
+    }
+    Duration::try_from_secs_f64(total).map_err(|e| e.to_string())
+}
+
+/// limits enforced on each case while it runs
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Watchdog {
+    /// kill the case if it is still running after this long
+    pub timeout: Option<Duration>,
+    /// kill the case if its resident memory exceeds this many bytes
+    pub memory_budget: Option<u64>,
+}
+
+impl Watchdog {
+    /// runs the command to completion, killing it if it breaks any of the limits
+    pub fn run(&self, mut cmd: Command) -> CaseOutcome {
+        let start = Instant::now();
+        let mut child = match cmd.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                return CaseOutcome {
+                    status: CaseStatus::Failed,
+                    exit_code: None,
+                    elapsed: start.elapsed(),
+                    message: format!("failed to launch case: {e}"),
+                };
+            }
+        };
+
+        loop {
+            match child.try_wait() {
+                Ok(Some(exit)) => {
+                    let (status, message) = if exit.success() {
+                        (CaseStatus::Succeeded, String::new())
+                    } else if let Some(signal) = oom_signal(&exit) {
+                        (
+                            CaseStatus::OutOfMemory,
+                            format!("killed by signal {signal}, most likely an allocation failure"),
+                        )
+                    } else if let Some(kind) = exit.code().and_then(FailureKind::from_exit_code) {
+                        let status = match kind {
+                            FailureKind::OutOfMemory => CaseStatus::OutOfMemory,
+                            _ => CaseStatus::Failed,
+                        };
+                        (status, kind.as_str().to_string())
+                    } else {
+                        (CaseStatus::Failed, String::new())
+                    };
+                    return CaseOutcome {
+                        status,
+                        exit_code: exit.code(),
+                        elapsed: start.elapsed(),
+                        message,
+                    };
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    let _ = child.kill();
+                    return CaseOutcome {
+                        status: CaseStatus::Failed,
+                        exit_code: None,
+                        elapsed: start.elapsed(),
+                        message: format!("lost track of case process: {e}"),
+                    };
+                }
+            }
+
+            // killing the process takes all of its worker threads with it
+            if let Some(timeout) = self.timeout
+                && start.elapsed() > timeout
+            {
+                let _ = child.kill();
+                let _ = child.wait();
+                return CaseOutcome {
+                    status: CaseStatus::TimedOut,
+                    exit_code: None,
+                    elapsed: start.elapsed(),
+                    message: format!("exceeded timeout of {:.0} seconds", timeout.as_secs_f64()),
+                };
+            }
+
+            if let Some(budget) = self.memory_budget
+                && let Some(rss) = process_rss(child.id())
+                && rss > budget
+            {
+                let _ = child.kill();
+                let _ = child.wait();
+                return CaseOutcome {
+                    status: CaseStatus::OutOfMemory,
+                    exit_code: None,
+                    elapsed: start.elapsed(),
+                    message: format!(
+                        "resident memory of {} exceeded budget of {}",
+                        format_bytes(rss),
+                        format_bytes(budget)
+                    ),
+                };
+            }
+
+            thread::sleep(WATCHDOG_POLL);
+        }
+    }
+}
+
+/// rust aborts (SIGABRT) when an allocation fails and the linux OOM killer sends SIGKILL, so either
+/// signal is treated as running out of memory
+#[cfg(unix)]
+fn oom_signal(exit: &std::process::ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    exit.signal().filter(|&s| s == 6 || s == 9)
+}
+
+#[cfg(not(unix))]
+fn oom_signal(_exit: &std::process::ExitStatus) -> Option<i32> {
+    None
+}
+
+/// csv record of every case in a batch. Each row is flushed as soon as the case is done so the
+/// manifest is still useful if the batch itself is interrupted
+pub struct BatchManifest {
+    file: File,
+    format: ReportFormat,
+}
+
+impl BatchManifest {
+    pub fn create(path: impl AsRef<Path>, format: ReportFormat) -> Result<Self, String> {
+        let path = path.as_ref();
+        let mut file = File::create(path)
+            .map_err(|e| format!("failed to create batch manifest {}: {e}", path.display()))?;
+        let header = [
+            "input_vol",
+            "output_dir",
+            "mask",
+            "status",
+            "exit_code",
+            "elapsed_s",
+            "finished",
+            "message",
+            "format_version",
+        ];
+        writeln!(file, "{}", format.header(&header))
+            .map_err(|e| format!("failed to write batch manifest {}: {e}", path.display()))?;
+        Ok(BatchManifest { file, format })
+    }
+
+    pub fn record(&mut self, case: &Case, outcome: &CaseOutcome) -> Result<(), String> {
+        let finished = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let row = self.format.row(&[
+            case.input_vol.display().to_string(),
+            case.output_dir.display().to_string(),
+            case.mask
+                .as_ref()
+                .map(|m| m.display().to_string())
+                .unwrap_or_default(),
+            outcome.status.as_str().to_string(),
+            outcome.exit_code.map(|c| c.to_string()).unwrap_or_default(),
+            self.format.number(outcome.elapsed.as_secs_f64(), 3),
+            self.format.date(finished),
+            outcome.message.clone(),
+            Format::BatchManifest.current().to_string(),
+        ]);
+        writeln!(self.file, "{row}")
+            .and_then(|_| self.file.flush())
+            .map_err(|e| format!("failed to write to batch manifest: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_with_units() {
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("45m"), Ok(Duration::from_secs(2700)));
+        assert_eq!(parse_duration("1.5h"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration(" 2h "), Ok(Duration::from_secs(7200)));
+    }
+
+    #[test]
+    fn bare_numbers_are_seconds() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("0.5"), Ok(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn bad_durations_are_refused() {
+        assert!(parse_duration("2x").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("10m5").is_err());
+        assert!(parse_duration("-5").is_err());
+    }
+}
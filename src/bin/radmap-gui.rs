@@ -1,34 +1,90 @@
 #![cfg_attr(target_os = "windows", windows_subsystem = "windows")]
 
-use array_lib::io_nifti::{write_nifti_with_header, NiftiHeader};
-use array_lib::io_nrrd::{write_nrrd, Encoding, NRRD};
-use array_lib::{io_nifti, io_nrrd, ArrayDim};
+use array_lib::ArrayDim;
+use clap::Parser;
 use eframe::egui::{vec2, Color32, Context, IconData, ProgressBar, RichText, Ui};
 use eframe::{egui, Frame, NativeOptions};
 use egui_file_dialog::FileDialog;
-use glcm::core::GLCMFeature;
-use glcm::run_glcm_map;
 use glcm::ui::MapOpts;
+use notify_rust::Notification;
+use radmap::archive::{self, encrypt_dir, ArchiveKey};
+use radmap::autodrive::{Action, Autodrive, Event};
+use radmap::crash;
+use radmap::diff::{self, FieldDiff, RunSettings};
+use radmap::discretize::{
+    integer_levels, integer_levels_warning, level_indices, levels_to_f64, BinEdges, Binning,
+    DiscretizationScope,
+};
+use radmap::failure::FailureKind;
+use radmap::filter::gabor::Orientation;
+use radmap::filter::wavelet::{SubBand, WaveletKind};
+use radmap::filter::{FilterOpts, FilterUse, ImageFilter};
+use radmap::grpc::{JobResult, JobState, RemoteInput, RemoteJob, RemoteUpdate};
+use radmap::header::{describe_kernel, volume_shape, voxel_spacing};
+use radmap::history::{self, History, HistoryQuery, RunRecord};
+use radmap::io::{
+    feature_suffix, input_stem, is_in_directory, output_path, read_header, read_volume,
+    scrubbed_header, write_volume, Header,
+};
+use radmap::locale::{format_count, format_duration, format_elapsed, format_utc, NumberFormat};
+use radmap::mapper::{estimated_runtime, map_features, FeatureMaps};
+use radmap::memory::{format_bytes, MemoryTracker};
+use radmap::options::{
+    parse_kernel_radii, MapOption, MapOptsBuilder, DEFAULT_KERNEL_RADIUS, DEFAULT_N_BINS,
+};
+use radmap::preview::Preview;
+use radmap::progress::{Progress, ProgressSink, RunStage};
+use radmap::protocol::{self, Protocol};
+use radmap::provenance::{find_previous_runs, PreviousRun, Provenance};
+use radmap::scene::write_slicer_scene;
+use radmap::spill::{find_spilled, Spill, SpilledRun};
+use radmap::staging::{RetryPolicy, Staging};
+use radmap::stats::{summarize, Summary};
+use radmap::taskbar::{Taskbar, TaskbarState};
+use radmap::tensor::write_tensor;
+use radmap::texture::catalog::FeatureInfo;
+use radmap::texture::cooccurrence::{
+    self, Angles, Anisotropy, DirectionAggregation, DistanceAggregation, DistanceWeighting,
+};
+use radmap::texture::{ngldm, Family, Feature, KernelShape, TextureOpts, DIRECTIONS};
+use radmap::tray::{Tray, TrayAction};
+use radmap::usage;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{channel, Receiver};
 use std::sync::Arc;
 use std::thread::JoinHandle;
-use std::time::{Duration, Instant};
-use strum::IntoEnumIterator;
+use std::time::{Duration, Instant, SystemTime};
 
 const ICON_BYTES: &[u8] = include_bytes!("../../assets/icon.png");
 
+#[derive(Parser, Debug)]
+struct Args {
+    /// json script of actions to drive the window with, for tests and demos. Events are written
+    /// to stdout and a failed script exits with 1
+    #[clap(long)]
+    autodrive: Option<PathBuf>,
+}
+
 fn main() {
+    crash::install_panic_hook("radmap-gui");
+    let args = Args::parse();
+    let autodrive = args.autodrive.map(|path| {
+        Autodrive::read(&path).unwrap_or_else(|e| {
+            eprintln!("{e}");
+            std::process::exit(2);
+        })
+    });
+    let script_failed = autodrive.as_ref().map(|a| a.failed_flag());
+
     let icon = {
-        let img = image::load_from_memory(ICON_BYTES)
-            .expect("Failed to decode embedded PNG")
-            .into_rgba8();
-        let (width, height) = img.dimensions();
+        let (rgba, width, height) = icon_rgba();
         IconData {
             width,
             height,
-            rgba: img.into_raw(),
+            rgba,
         }
     };
 
@@ -42,9 +98,23 @@ fn main() {
     eframe::run_native(
         "RadMap",
         native_options,
-        Box::new(|_cc| Ok(Box::new(GUI::default()))),
+        Box::new(|_cc| {
+            Ok(Box::new(GUI {
+                protocol: protocol::site_protocol(),
+                output_selector: OutputSelector {
+                    spill_results: true,
+                    spilled: find_spilled(),
+                    ..Default::default()
+                },
+                autodrive,
+                ..Default::default()
+            }))
+        }),
     )
     .unwrap();
+    if script_failed.is_some_and(|f| f.load(Ordering::Relaxed)) {
+        std::process::exit(1);
+    }
 }
 
 #[derive(Default)]
@@ -54,465 +124,3619 @@ pub struct GUI {
     opts_selector: MapOptSelector,
     feature_selector: FeatureSelector,
     glcm_launcher: GLCMLauncher,
+    remote: RemoteSelector,
+    run_browser: RunBrowser,
+    history: HistoryBrowser,
+    /// locked protocol of the site, or why it can't be used
+    protocol: Option<Result<Protocol, String>>,
     progress: Progress,
     map_opts: MapOpts,
     file_dialog: FileDialog,
+    crash_report: Option<PathBuf>,
+    /// progress on the icon of the window, set up with the first frame
+    taskbar: Option<Taskbar>,
+    /// keep a run going in the tray when the window is closed, without asking first
+    close_to_tray: bool,
+    /// the window was closed during a run and the user is asked what to do with it
+    confirm_exit: bool,
+    /// the user chose to give up the run, closing is no longer intercepted
+    exiting: bool,
+    /// where the window went when it was closed during a run, None on platforms without a tray
+    tray: Option<Tray>,
+    tray_status: String,
+    /// script driving the window in place of a user
+    autodrive: Option<Autodrive>,
+}
+
+/// RGBA pixels, width and height of the application icon
+fn icon_rgba() -> (Vec<u8>, u32, u32) {
+    let img = image::load_from_memory(ICON_BYTES)
+        .expect("Failed to decode embedded PNG")
+        .into_rgba8();
+    let (width, height) = img.dimensions();
+    (img.into_raw(), width, height)
+}
+
+impl GUI {
+    /// true while a local run computes or writes its outputs
+    fn is_busy(&self) -> bool {
+        self.glcm_launcher.is_running
+            || self.glcm_launcher.result.is_some()
+            || self.output_selector.is_writing_output
+    }
+
+    /// sends the window to the tray, or minimizes it where there is no tray
+    fn send_to_background(&mut self, ctx: &Context) {
+        let (rgba, width, height) = icon_rgba();
+        self.tray = Tray::new(rgba, width, height);
+        self.tray_status.clear();
+        match self.tray {
+            Some(_) => ctx.send_viewport_cmd(egui::ViewportCommand::Visible(false)),
+            None => ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(true)),
+        }
+    }
+
+    /// holds on to the window when it is closed while a run goes on, asking whether to give up
+    /// the run or keep it going in the background
+    fn confirm_exit(&mut self, ctx: &Context) {
+        if ctx.input(|i| i.viewport().close_requested()) && !self.exiting && self.is_busy() {
+            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            if self.close_to_tray {
+                self.send_to_background(ctx);
+            } else {
+                self.confirm_exit = true;
+            }
+        }
+        // nothing is lost by closing anymore, so there is nothing left to ask
+        if !self.is_busy() {
+            self.confirm_exit = false;
+        }
+        if !self.confirm_exit {
+            return;
+        }
+        let writing = self.output_selector.is_writing_output || self.glcm_launcher.result.is_some();
+        let mut choice = None;
+        egui::Modal::new(egui::Id::new("confirm exit")).show(ctx, |ui| {
+            ui.heading("A run is still going");
+            ui.label(if writing {
+                "The results of the run haven't been written yet. Closing now loses them."
+            } else {
+                "Closing now throws away the calculation done so far."
+            });
+            ui.checkbox(&mut self.close_to_tray, "don't ask again, keep runs going");
+            ui.horizontal(|ui| {
+                if ui.button("keep running in the background").clicked() {
+                    choice = Some(true);
+                }
+                if ui.button("cancel the run and exit").clicked() {
+                    choice = Some(false);
+                }
+                if ui.button("go back").clicked() {
+                    self.confirm_exit = false;
+                }
+            });
+        });
+        match choice {
+            Some(true) => {
+                self.confirm_exit = false;
+                self.send_to_background(ctx);
+            }
+            Some(false) => {
+                self.confirm_exit = false;
+                self.exiting = true;
+                self.progress.cancel();
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            }
+            None => {}
+        }
+    }
+
+    /// applies the next action of the script driving the window, closing it once the script
+    /// fails
+    fn autodrive(&mut self, ctx: &Context) {
+        let Some(mut driver) = self.autodrive.take() else {
+            return;
+        };
+        if let Some(reason) = self.glcm_launcher.refused.take() {
+            driver.fail(&format!("can't launch: {reason}"));
+        }
+        if let Some(action) = driver.next(self.is_busy())
+            && let Err(e) = self.apply_action(action, ctx)
+        {
+            driver.fail(&e);
+        }
+        if driver.has_failed() && !self.exiting {
+            self.exiting = true;
+            self.progress.cancel();
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+        }
+        self.autodrive = Some(driver);
+    }
+
+    /// does what a user would to the form for an action of a script
+    fn apply_action(&mut self, action: Action, ctx: &Context) -> Result<(), String> {
+        let locked = || "the options and features are locked by the site protocol".to_string();
+        match action {
+            Action::Input { path } => {
+                if !path.is_file() {
+                    return Err(format!("{} is not a file", path.display()));
+                }
+                self.data_loader.set_volume(path);
+            }
+            Action::Mask { path } => {
+                if let Some(path) = &path
+                    && !path.is_file()
+                {
+                    return Err(format!("{} is not a file", path.display()));
+                }
+                self.data_loader.set_mask(path);
+            }
+            Action::OutputDir { path } => {
+                if !path.is_dir() {
+                    return Err(format!("{} is not a directory", path.display()));
+                }
+                self.output_selector.set_output_dir(path);
+            }
+            Action::Option { name, value } => {
+                if self.protocol.is_some() {
+                    return Err(locked());
+                }
+                self.opts_selector.set(&name, &value)?;
+            }
+            Action::Features { features } => {
+                if self.protocol.is_some() {
+                    return Err(locked());
+                }
+                self.feature_selector.selected_features = features
+                    .iter()
+                    .map(|name| Feature::from_str(name).map(|f| (f, f.to_string())))
+                    .collect::<Result<_, _>>()?;
+            }
+            Action::Launch => {
+                if matches!(self.protocol, Some(Err(_))) {
+                    return Err("the site protocol can't be used, launching is disabled".into());
+                }
+                if self.remote.enabled {
+                    return Err("only runs on this machine can be launched by a script".into());
+                }
+                if self.is_busy() {
+                    return Err("a run is already going".into());
+                }
+                self.glcm_launcher.launch_requested = true;
+            }
+            // handed back once the run is over, to check how it went
+            Action::Wait { .. } => {
+                if self.glcm_launcher.failed {
+                    return Err("feature extraction failed".into());
+                }
+                if self.glcm_launcher.cancelled {
+                    return Err("feature extraction was cancelled".into());
+                }
+                let Some(output_dir) = self
+                    .output_selector
+                    .output_dir
+                    .as_deref()
+                    .filter(|_| self.output_selector.is_complete)
+                else {
+                    return Err("the outputs of the run were not written".into());
+                };
+                Event::Finished {
+                    output_dir,
+                    elapsed_s: self
+                        .output_selector
+                        .total_time
+                        .unwrap_or_default()
+                        .as_secs_f64(),
+                }
+                .emit();
+            }
+            Action::Pause { .. } => {}
+            Action::Exit => {
+                self.exiting = true;
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            }
+        }
+        Ok(())
+    }
+
+    /// answers the menu of the tray icon while the window is in the background
+    fn keep_running_in_background(&mut self, ctx: &Context) {
+        let Some(tray) = &self.tray else {
+            return;
+        };
+        let status = match taskbar_state(&self.progress, self.glcm_launcher.failed) {
+            TaskbarState::Progress(percent) => {
+                format!("{percent}% - {}", self.progress.stage().label())
+            }
+            TaskbarState::Busy => self.progress.stage().label().to_string(),
+            TaskbarState::Failed => "the run failed".to_string(),
+            TaskbarState::Idle if self.glcm_launcher.cancelled => {
+                "the run was cancelled".to_string()
+            }
+            TaskbarState::Idle => "the run is done".to_string(),
+        };
+        if status != self.tray_status {
+            tray.set_status(&status);
+            tray.set_cancellable(self.is_busy());
+            self.tray_status = status;
+        }
+        match tray.poll() {
+            Some(TrayAction::Restore) => {
+                ctx.send_viewport_cmd(egui::ViewportCommand::Visible(true));
+                ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+                self.tray = None;
+            }
+            Some(TrayAction::Cancel) => self.progress.cancel(),
+            None => {}
+        }
+    }
 }
 
 impl eframe::App for GUI {
-    fn update(&mut self, ctx: &Context, _frame: &mut Frame) {
+    fn update(&mut self, ctx: &Context, frame: &mut Frame) {
+        self.autodrive(ctx);
+        if let Some(report) = crash::take_last_report() {
+            self.crash_report = Some(report);
+        }
+        show_crash_dialog(&mut self.crash_report, ctx);
+        show_spilled_runs(&mut self.output_selector, ctx);
+        self.confirm_exit(ctx);
+        self.keep_running_in_background(ctx);
+
+        if let Some(Ok(protocol)) = &self.protocol {
+            apply_protocol(
+                protocol,
+                &mut self.opts_selector,
+                &mut self.feature_selector,
+            );
+        }
+        self.data_loader.logic(ctx);
+        self.output_selector.logic(
+            &mut self.file_dialog,
+            &self.data_loader,
+            &self.feature_selector,
+            &mut self.glcm_launcher,
+            &self.progress,
+            ctx,
+        );
+        self.glcm_launcher.logic(
+            &mut self.map_opts,
+            &mut self.progress,
+            &self.opts_selector,
+            &self.feature_selector,
+            &self.data_loader,
+            &self.output_selector,
+            ctx,
+        );
+        self.remote.logic(&self.progress);
+        self.run_browser.logic(ctx);
+
+        let locked = self.protocol.is_some();
+        // a site protocol that can't be verified blocks launching altogether
+        let protocol_invalid = matches!(self.protocol, Some(Err(_)));
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.columns(2, |columns| {
                 columns[0].vertical(|ui| {
-                    update_map_options(&mut self.opts_selector, ctx, ui);
-                    update_feature_selector(&mut self.feature_selector, ui);
+                    match &self.protocol {
+                        Some(Ok(protocol)) => {
+                            ui.label(
+                                RichText::new(format!("🔒 locked protocol: {}", protocol.name))
+                                    .strong(),
+                            )
+                            .on_hover_text(
+                                "the options and features are set by your site admin, \
+                                 only inputs and outputs can be chosen",
+                            );
+                        }
+                        Some(Err(e)) => {
+                            ui.label(
+                                RichText::new(format!(
+                                    "the site protocol can't be used, launching is disabled: {e}"
+                                ))
+                                .color(Color32::RED),
+                            );
+                        }
+                        None => {}
+                    }
+                    self.opts_selector
+                        .ui(self.data_loader.volume_spacing, locked, ui);
+                    ui.add_enabled_ui(!locked, |ui| {
+                        self.feature_selector.ui(
+                            &self.opts_selector,
+                            self.data_loader.volume_shape,
+                            ui,
+                        );
+                    });
                 });
 
                 columns[1].vertical(|ui| {
-                    update_data_loader(&mut self.data_loader, ctx, ui);
-                    update_output_selector(
-                        &mut self.output_selector,
-                        &mut self.file_dialog,
-                        &self.data_loader,
-                        &self.feature_selector,
-                        &mut self.glcm_launcher,
-                        ctx,
-                        ui,
+                    self.data_loader.ui(ui);
+                    self.output_selector
+                        .ui(&mut self.file_dialog, &self.data_loader, ui);
+
+                    self.remote.ui(ui);
+                    ui.add_enabled_ui(!protocol_invalid, |ui| {
+                        if self.remote.enabled {
+                            self.remote.launch_ui(
+                                &mut self.progress,
+                                &self.opts_selector,
+                                &self.feature_selector,
+                                &self.data_loader,
+                                &self.output_selector,
+                                ui,
+                            );
+                        } else {
+                            self.glcm_launcher.ui(
+                                &self.progress,
+                                &self.data_loader,
+                                &self.output_selector,
+                                ui,
+                            );
+                        }
+                    });
+
+                    update_progress(&mut self.progress, ui);
+                    self.glcm_launcher.viewer.ui(ui);
+                    ui.checkbox(
+                        &mut self.close_to_tray,
+                        "keep running in the background when the window is closed",
+                    )
+                    .on_hover_text(
+                        "closing the window during a run puts radmap in the system tray, or \
+                         minimizes it where there is no tray, until the run is done. Without \
+                         it you are asked what to do with the run",
                     );
 
-                    update_glcm_launcher(
-                        &mut self.map_opts,
-                        &mut self.progress,
-                        &mut self.glcm_launcher,
-                        &self.opts_selector,
-                        &self.feature_selector,
-                        &self.data_loader,
-                        &self.output_selector,
+                    self.run_browser.ui(
+                        &mut self.opts_selector,
+                        &mut self.feature_selector,
+                        &mut self.data_loader,
                         ui,
                     );
 
-                    update_progress(&mut self.progress, ui);
+                    self.history.ui(ui);
                 });
             });
         });
+        let taskbar = self.taskbar.get_or_insert_with(|| Taskbar::new(&*frame));
+        let state = taskbar_state(&self.progress, self.glcm_launcher.failed);
+        if taskbar.set(state) {
+            let title = match state {
+                TaskbarState::Idle => "RadMap".to_string(),
+                TaskbarState::Busy => "RadMap - running".to_string(),
+                TaskbarState::Progress(percent) => format!("RadMap - {percent}%"),
+                TaskbarState::Failed => "RadMap - failed".to_string(),
+            };
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(title));
+        }
+
         ctx.request_repaint();
     }
 }
 
-pub fn update_options(opts: &mut MapOpts, map_opts: &MapOptSelector, features: &FeatureSelector) {
-    opts.kernel_radius = map_opts.kernel_radius;
-    opts.features = features.selected_features.clone();
-    opts.n_bins = map_opts.num_bins;
-    opts.separator = None;
-    opts.max_threads = map_opts.max_threads;
-}
-
-/****************************
-******* GLCM LAUNCHER *******
-****************************/
-#[derive(Default)]
-pub struct GLCMLauncher {
-    result: Option<(Vec<f32>, ArrayDim)>,
-    ref_header: Option<Header>,
-    handle: Option<JoinHandle<(Vec<f32>, ArrayDim)>>,
-    is_running: bool,
-    succeeded: bool,
-    start: Option<Instant>,
-    elapsed: Option<Duration>,
-}
-
-pub enum Header {
-    Nrrd(Box<NRRD>),
-    Nifti(Box<NiftiHeader>),
+/// what the taskbar icon shows of the current run
+fn taskbar_state(progress: &Progress, failed: bool) -> TaskbarState {
+    match progress.stage() {
+        RunStage::Idle | RunStage::Done if failed => TaskbarState::Failed,
+        RunStage::Idle | RunStage::Done => TaskbarState::Idle,
+        RunStage::Loading => TaskbarState::Busy,
+        RunStage::Computing => TaskbarState::running(progress.voxels.fraction()),
+        RunStage::Writing => TaskbarState::running(progress.bytes.fraction()),
+    }
 }
 
-#[allow(clippy::too_many_arguments)]
-pub fn update_glcm_launcher(
-    map_opts: &mut MapOpts,
-    progress: &mut Progress,
-    launcher: &mut GLCMLauncher,
-    opts_selector: &MapOptSelector,
-    features: &FeatureSelector,
-    data_selector: &InputSelector,
-    output_selector: &OutputSelector,
-    ui: &mut Ui,
-) {
-    // check that files have been selected
-    if data_selector.volume_path.is_some() && output_selector.output_dir.is_some() {
-        update_options(map_opts, opts_selector, features);
-
-        if ui.button("LAUNCH").clicked() {
-            launcher.elapsed = None;
-            launcher.start = Some(Instant::now());
-
-            let vol_path = data_selector.volume_path.as_ref().unwrap().clone();
-            let vol_handle = std::thread::spawn(move || {
-                if vol_path.extension().unwrap() == "nii"
-                    || vol_path.extension().unwrap() == "nii.gz"
-                {
-                    let (data, dims, header) = io_nifti::read_nifti::<f64>(vol_path);
-                    (data, dims, Header::Nifti(Box::new(header)))
-                } else {
-                    let (data, dims, header) = io_nrrd::read_nrrd(vol_path);
-                    (data, dims, Header::Nrrd(Box::new(header)))
-                }
-            });
-
-            let mask_handle = if let Some(mask_path) = &data_selector.mask_path {
-                let mp = mask_path.clone();
-                let h = std::thread::spawn(move || {
-                    if mp.extension().unwrap() == "nii" || mp.extension().unwrap() == "nii.gz" {
-                        let (data, dims, header) = io_nifti::read_nifti::<f64>(mp);
-                        (data, dims, Header::Nifti(Box::new(header)))
-                    } else {
-                        let (data, dims, header) = io_nrrd::read_nrrd(mp);
-                        (data, dims, Header::Nrrd(Box::new(header)))
+/// offers the maps of runs that were computed but never written, to move into the output
+/// directory or throw away
+fn show_spilled_runs(output_selector: &mut OutputSelector, ctx: &Context) {
+    if output_selector.spilled.is_empty() || output_selector.spilled_hidden {
+        return;
+    }
+    let output_dir = output_selector.output_dir.clone();
+    let mut done = None;
+    egui::Window::new("Recovered maps")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, vec2(0., 0.))
+        .show(ctx, |ui| {
+            ui.label("These runs finished computing but their maps were never written:");
+            for (i, run) in output_selector.spilled.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    let name = run.input.file_name().unwrap_or_default().to_string_lossy();
+                    ui.label(format!(
+                        "{name}: {} maps, {}",
+                        run.maps.len(),
+                        format_age(run.created_unix_s)
+                    ))
+                    .on_hover_text(run.input.display().to_string());
+                    let save = ui
+                        .add_enabled(output_dir.is_some(), egui::Button::new("save"))
+                        .on_hover_text("move the maps into the output directory")
+                        .on_disabled_hover_text("choose an output directory first");
+                    if save.clicked()
+                        && let Some(dir) = &output_dir
+                    {
+                        done = Some((i, run.recover(dir).map(|_| ())));
+                    }
+                    if ui.button("discard").clicked() {
+                        done = Some((i, run.discard()));
                     }
                 });
-                Some(h)
-            } else {
-                None
-            };
-
-            let (vol, vol_dims, vol_header) = vol_handle
-                .join()
-                .expect("failed to retrieve volume from loader thread");
-            let mask = mask_handle.map(|h| {
-                h.join()
-                    .expect("failed to retrieve mask from loader thread")
-            });
-
-            progress.total_vox_to_compute = Some(vol_dims.numel());
-
-            let t_map_opts = map_opts.clone();
-            progress.progress = Arc::new(AtomicUsize::new(0));
-            let t_progress = progress.progress.clone();
-            let glcm_calc_handle = std::thread::spawn(move || {
-                // check that the mask and volume have compatible shapes
-                let mask = mask.map(|(mask_data, mask_dims, _)| {
-                    assert_eq!(
-                        mask_dims.shape_ns(),
-                        vol_dims.shape_ns(),
-                        "mask and volume have different shapes"
-                    );
-                    mask_data
-                });
-                run_glcm_map(t_map_opts, vol, mask, vol_dims, t_progress)
-            });
-
-            launcher.is_running = true;
-            launcher.succeeded = false;
-            launcher.ref_header = Some(vol_header);
-            launcher.handle = Some(glcm_calc_handle);
+            }
+            if let Some(e) = &output_selector.spilled_error {
+                ui.label(RichText::new(e).color(Color32::RED));
+            }
+            if ui
+                .button("later")
+                .on_hover_text("ask again on the next launch")
+                .clicked()
+            {
+                output_selector.spilled_hidden = true;
+            }
+        });
+    match done {
+        Some((i, Ok(()))) => {
+            output_selector.spilled.remove(i);
+            output_selector.spilled_error = None;
         }
+        Some((_, Err(e))) => output_selector.spilled_error = Some(e),
+        None => {}
     }
+}
 
-    if let Some(h) = launcher.handle.take() {
-        if h.is_finished() {
-            let result = h
-                .join()
-                .expect("failed to retrieve handle from calc thread");
-            launcher.result = Some(result);
-            launcher.is_running = false;
-            launcher.succeeded = true;
-            launcher.elapsed = launcher.start.map(|s| s.elapsed());
-        } else {
-            launcher.handle = Some(h);
-        }
+/// lets the user know where the crash report of a failed calculation was written
+pub fn show_crash_dialog(crash_report: &mut Option<PathBuf>, ctx: &Context) {
+    let Some(report) = crash_report.as_ref() else {
+        return;
+    };
+    let mut dismissed = false;
+    egui::Window::new("Something went wrong")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, vec2(0., 0.))
+        .show(ctx, |ui| {
+            ui.label("RadMap ran into an unexpected error. A crash report was written to:");
+            ui.monospace(report.display().to_string());
+            ui.label("Please attach it to your bug report. It contains no image data.");
+            ui.horizontal(|ui| {
+                if ui.button("copy path").clicked() {
+                    ui.ctx().copy_text(report.display().to_string());
+                }
+                if ui.button("dismiss").clicked() {
+                    dismissed = true;
+                }
+            });
+        });
+    if dismissed {
+        *crash_report = None;
     }
+}
 
-    if launcher.is_running {
-        ui.label("running ...");
+/// sets `opts` from the form, leaving it as it was if the form breaks a rule of
+/// [`MapOptsBuilder`]
+pub fn update_options(
+    opts: &mut MapOpts,
+    map_opts: &MapOptSelector,
+    features: &FeatureSelector,
+) -> Result<(), String> {
+    let (built, _) = map_opts.builder(features).build()?;
+    let binning = map_opts.binning(built.n_bins);
+    binning.validate()?;
+    let filters = map_opts.filter_opts();
+    if binning.needs_fit() && filters.usage == FilterUse::Map && !filters.is_empty() {
+        return Err(
+            "a bin width or range, binning from the mask or ranked bins can't be used with \
+             mapped filters, filtered images don't have the intensities of the input"
+                .to_string(),
+        );
     }
-
-    if launcher.succeeded {
-        ui.label("feature extraction succeeded!");
-        ui.label(format!(
-            "calculation time: {:.3} minutes",
-            launcher.elapsed.unwrap().as_secs_f64() / 60.
-        ));
+    if map_opts.discretization.is_local() && !binning.is_native() {
+        return Err(
+            "local discretization bins each kernel between its own minimum and maximum into \
+             half-open bins, it can't be used with a bin width or range, binning from the mask, \
+             ranked bins or inclusive bin edges"
+                .to_string(),
+        );
     }
+    *opts = built;
+    Ok(())
 }
 
 /****************************
-********** PROGRESS *********
+******* GLCM LAUNCHER *******
 ****************************/
-pub struct Progress {
-    progress: Arc<AtomicUsize>,
-    total_vox_to_compute: Option<usize>,
+#[derive(Default)]
+pub struct GLCMLauncher {
+    result: Option<Arc<FeatureMaps>>,
+    /// copy of the maps of `result` kept until they are written
+    spill: Option<Spill>,
+    ref_header: Option<Arc<Header>>,
+    handle: Option<JoinHandle<Option<Computed>>>,
+    is_running: bool,
+    succeeded: bool,
+    failed: bool,
+    cancelled: bool,
+    start: Option<Instant>,
+    elapsed: Option<Duration>,
+    memory: MemoryTracker,
+    last_memory_sample: Option<Instant>,
+    /// set when the last input was already quantized and its levels were used as the bins, or
+    /// was split by a bin width
+    binning_note: Option<String>,
+    /// written next to the outputs of the current run
+    provenance: Option<Provenance>,
+    /// entry of the current run in the run history
+    history_run: Option<i64>,
+    /// feature statistics over blocks sampled from the input, computed next to the run
+    preview: Option<Preview>,
+    /// keep the loaded volume and mask around to skip re-reading them on the next launch
+    cache_inputs: bool,
+    volume_cache: Option<CachedInput>,
+    mask_cache: Option<CachedInput>,
+    viewer: MapViewer,
+    /// why the options of the form can't be used
+    invalid: Option<String>,
+    /// LAUNCH was clicked, the run starts with the next frame
+    launch_clicked: bool,
+    /// launch as if LAUNCH was clicked, set by a script driving the window
+    launch_requested: bool,
+    /// why a requested launch couldn't happen
+    refused: Option<String>,
 }
 
-impl Default for Progress {
-    fn default() -> Self {
-        Self {
-            progress: Arc::new(AtomicUsize::new(0)),
-            total_vox_to_compute: None,
-        }
-    }
+/// the maps of a run and the copy kept of them until they are written
+type Computed = (FeatureMaps, Option<Spill>);
+
+/// identifies the state of a file on disk so a cached copy is dropped once the file changes
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FileKey {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    len: u64,
 }
 
-pub fn update_progress(progress: &mut Progress, ui: &mut Ui) {
-    if let Some(total_vox) = progress.total_vox_to_compute {
-        let state = progress.progress.load(Ordering::Relaxed);
-        let progress = state as f64 / total_vox as f64;
-        ui.add(ProgressBar::new(progress as f32).show_percentage());
+impl FileKey {
+    fn new(path: &Path) -> Self {
+        let meta = std::fs::metadata(path).ok();
+        FileKey {
+            path: path.to_path_buf(),
+            modified: meta.as_ref().and_then(|m| m.modified().ok()),
+            len: meta.map(|m| m.len()).unwrap_or(0),
+        }
     }
 }
 
-/****************************
-***** FEATURE SELECTION *****
-****************************/
-
-pub struct FeatureSelector {
-    selected_features: HashMap<GLCMFeature, String>,
+#[derive(Clone)]
+struct CachedInput {
+    key: FileKey,
+    data: Arc<Vec<f64>>,
+    dims: ArrayDim,
+    header: Arc<Header>,
 }
 
-impl FeatureSelector {
-    pub fn features_aliases(&self) -> Vec<(GLCMFeature, String)> {
-        let mut f: Vec<_> = self
-            .selected_features
-            .iter()
-            .map(|(k, v)| (*k, v.clone()))
-            .collect();
-        f.sort_by_key(|k| k.1.clone());
-        f
+/// loads an input on a background thread, or hands back the cached copy if the file is unchanged
+fn spawn_input_load(path: &Path, cache: &Option<CachedInput>) -> JoinHandle<CachedInput> {
+    let key = FileKey::new(path);
+    match cache {
+        Some(cached) if cached.key == key => {
+            let cached = cached.clone();
+            std::thread::spawn(move || cached)
+        }
+        _ => std::thread::spawn(move || {
+            let (data, dims, header) = read_volume(&key.path);
+            CachedInput {
+                key,
+                data: Arc::new(data),
+                dims,
+                header: Arc::new(header),
+            }
+        }),
     }
 }
 
-impl Default for FeatureSelector {
-    fn default() -> Self {
-        let selected_features = GLCMFeature::iter()
-            .map(|f| (f, f.to_string().replace("_", " ")))
-            .collect();
-        FeatureSelector { selected_features }
+/// how often resident memory is sampled while a calculation is running
+const MEMORY_SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+/// runs shorter than this finish before anyone has looked away, so they don't notify
+const NOTIFY_AFTER: Duration = Duration::from_secs(30);
+
+/// tells the user a long run is over with a desktop notification, unless the window has focus
+fn notify_finished(ctx: &Context, elapsed: Option<Duration>, outcome: &str) {
+    let in_background = ctx.input(|i| i.viewport().focused) == Some(false);
+    let Some(elapsed) = elapsed.filter(|e| *e >= NOTIFY_AFTER && in_background) else {
+        return;
+    };
+    // a desktop without a notification service just doesn't get one
+    if let Err(e) = Notification::new()
+        .appname("RadMap")
+        .summary(&format!("radmap {outcome}"))
+        .body(&format!("after {}", format_elapsed(elapsed)))
+        .show()
+    {
+        eprintln!("failed to show a notification: {e}");
     }
 }
 
-pub fn update_feature_selector(feature_selector: &mut FeatureSelector, ui: &mut Ui) {
-    if ui.button("deselect all").clicked() {
-        feature_selector.selected_features.clear();
+impl GLCMLauncher {
+    /// why the form can't be launched as it is, None once it can
+    fn blocked_by(
+        &self,
+        data_selector: &InputSelector,
+        output_selector: &OutputSelector,
+    ) -> Option<String> {
+        if data_selector.volume_path.is_none() || output_selector.output_dir.is_none() {
+            return Some("choose an input volume and an output directory first".to_string());
+        }
+        self.invalid
+            .clone()
+            .or_else(|| {
+                output_selector
+                    .blocked_in_place(data_selector)
+                    .then(|| "the output directory holds an input".to_string())
+            })
+            .or_else(|| {
+                (output_selector.encrypt_outputs && output_selector.archive_password.is_empty())
+                    .then(|| "the outputs are to be encrypted but there is no password".to_string())
+            })
     }
 
-    if ui.button("select all").clicked() {
-        for feature in GLCMFeature::iter() {
-            feature_selector
-                .selected_features
-                .insert(feature, feature.to_string().replace("_", " "));
+    /// checks the form, launches a run once LAUNCH was clicked or a script asked for one and
+    /// follows the run going on
+    #[allow(clippy::too_many_arguments)]
+    pub fn logic(
+        &mut self,
+        map_opts: &mut MapOpts,
+        progress: &mut Progress,
+        opts_selector: &MapOptSelector,
+        features: &FeatureSelector,
+        data_selector: &InputSelector,
+        output_selector: &OutputSelector,
+        ctx: &Context,
+    ) {
+        self.invalid = update_options(map_opts, opts_selector, features).err();
+        if !self.cache_inputs {
+            self.volume_cache = None;
+            self.mask_cache = None;
         }
-    }
 
-    for feature in GLCMFeature::iter() {
-        let mut is_selected = feature_selector.selected_features.contains_key(&feature);
-        if ui
-            .checkbox(&mut is_selected, feature.to_string().replace("_", " "))
-            .changed()
-        {
-            if is_selected {
-                feature_selector
-                    .selected_features
-                    .insert(feature, feature.to_string().to_lowercase());
-            } else {
-                feature_selector.selected_features.remove(&feature);
+        let blocked_by = self.blocked_by(data_selector, output_selector);
+        let clicked = std::mem::take(&mut self.launch_clicked);
+        let requested = std::mem::take(&mut self.launch_requested);
+        if requested {
+            match &blocked_by {
+                Some(reason) => self.refused = Some(reason.clone()),
+                None => Event::Launched.emit(),
             }
         }
+        if (clicked || requested) && blocked_by.is_none() {
+            self.launch(
+                map_opts,
+                progress,
+                opts_selector,
+                features,
+                data_selector,
+                output_selector,
+            );
+        }
+
+        self.poll(map_opts, progress, opts_selector, features, ctx);
+
+        if self.is_running
+            && self
+                .last_memory_sample
+                .is_none_or(|t| t.elapsed() >= MEMORY_SAMPLE_INTERVAL)
+        {
+            self.memory.sample();
+            self.last_memory_sample = Some(Instant::now());
+        }
     }
-}
 
-/****************************
-******* MAP OPTIONS *******
-****************************/
+    /// loads the inputs and starts computing the maps on a background thread
+    fn launch(
+        &mut self,
+        map_opts: &MapOpts,
+        progress: &mut Progress,
+        opts_selector: &MapOptSelector,
+        features: &FeatureSelector,
+        data_selector: &InputSelector,
+        output_selector: &OutputSelector,
+    ) {
+        let (Some(volume_path), Some(output_dir)) =
+            (&data_selector.volume_path, &output_selector.output_dir)
+        else {
+            return;
+        };
+        self.elapsed = None;
+        self.start = Some(Instant::now());
+        self.memory = MemoryTracker::default();
+        self.last_memory_sample = None;
+        self.failed = false;
+        self.cancelled = false;
+        crash::clear_inputs();
+        let texture = TextureOpts {
+            voxel_spacing: data_selector.volume_spacing,
+            ..features.texture_opts(opts_selector)
+        };
+        crash::set_options(map_opts, &texture);
+        crash::set_report_dir(output_dir);
+        crash::set_scrub_phi(output_selector.scrub_phi);
 
-pub struct MapOptSelector {
-    kernel_radius: usize,
-    num_bins: usize,
-    kernel_radius_buf: String,
-    num_bins_buf: String,
-    max_threads: Option<usize>,
-    max_threads_buf: String,
-}
+        let vol_handle = spawn_input_load(volume_path, &self.volume_cache);
+        let mask_handle = data_selector
+            .mask_path
+            .as_ref()
+            .map(|mask_path| spawn_input_load(mask_path, &self.mask_cache));
 
-impl Default for MapOptSelector {
-    fn default() -> Self {
-        MapOptSelector {
-            kernel_radius: 1,
-            num_bins: 32,
-            kernel_radius_buf: String::new(),
-            num_bins_buf: String::new(),
-            max_threads: None,
-            max_threads_buf: String::new(),
+        // a panic in a loader thread has already been written to a crash report
+        let (Ok(vol), Ok(mask)) = (vol_handle.join(), mask_handle.map(|h| h.join()).transpose())
+        else {
+            self.failed = true;
+            return;
+        };
+        crash::record_input("input volume", volume_path, &vol.dims);
+        if let (Some(mask_path), Some(mask)) = (&data_selector.mask_path, &mask) {
+            crash::record_input("mask", mask_path, &mask.dims);
         }
-    }
-}
 
-pub fn update_map_options(map_opts: &mut MapOptSelector, _ctx: &Context, ui: &mut Ui) {
-    ui.horizontal(|ui| {
-        ui.label(format!("Kernel Radius: [{}]\t ", map_opts.kernel_radius));
-        let te = egui::TextEdit::singleline(&mut map_opts.kernel_radius_buf).desired_width(40.0);
-        let h = ui.add(te);
-        if h.lost_focus() {
-            if let Ok(parsed) = map_opts.kernel_radius_buf.parse::<i32>() {
-                if parsed == 0 {
-                    map_opts.kernel_radius = 1;
-                } else {
-                    map_opts.kernel_radius = parsed.unsigned_abs() as usize;
+        let vol_dims = vol.dims;
+        let vol_header = vol.header.clone();
+        if self.cache_inputs {
+            self.volume_cache = Some(vol.clone());
+            self.mask_cache = mask.clone();
+        }
+        // without a cached copy the buffers are moved rather than cloned
+        let vol = Arc::unwrap_or_clone(vol.data);
+        let mask = mask.map(|m| (Arc::unwrap_or_clone(m.data), m.dims));
+
+        *progress = Progress::default();
+
+        let mut t_map_opts = map_opts.clone();
+        let mut binning = opts_selector.binning(t_map_opts.n_bins);
+        let levels = if opts_selector.rebin_integer_input
+            || binning.needs_fit()
+            || texture.discretization.is_local()
+        {
+            None
+        } else {
+            integer_levels(&vol)
+        };
+        // filters see the intensities before binning
+        let spacing = data_selector.volume_spacing.unwrap_or([1.; 3]);
+        let mut images = texture.filters.apply(&vol, &vol_dims, spacing);
+        self.binning_note = levels.as_ref().map(|levels| {
+            let n_levels = levels.len();
+            integer_levels_warning(n_levels, t_map_opts.n_bins).unwrap_or(format!(
+                "input is already quantized to {n_levels} integer levels, skipped discretization"
+            ))
+        });
+        // the families of the intensities need them next to any gray levels binned here
+        let keep = texture.maps_intensities();
+        let (vol, intensities) = if let Some(levels) = levels {
+            t_map_opts.n_bins = levels.len();
+            binning.n_bins = levels.len();
+            crash::set_options(&t_map_opts, &texture);
+            (level_indices(&vol, &levels), keep.then_some(vol))
+        } else if let Some(fit) = binning.describe_fit() {
+            let mask = mask.as_ref().map(|(mask_data, _)| mask_data.as_slice());
+            match binning.fit(&vol, mask) {
+                Ok(levels) => {
+                    self.binning_note =
+                        Some(format!("the input spans {} of the {fit}", binning.n_bins));
+                    t_map_opts.n_bins = binning.n_bins;
+                    crash::set_options(&t_map_opts, &texture);
+                    (levels_to_f64(&levels), keep.then_some(vol))
                 }
+                Err(e) => {
+                    self.binning_note = Some(e);
+                    self.failed = true;
+                    return;
+                }
+            }
+        } else if !binning.is_native() {
+            (
+                levels_to_f64(&binning.discretize(&vol)),
+                keep.then_some(vol),
+            )
+        } else {
+            (vol, None)
+        };
+        if texture.filters.usage == FilterUse::Map && !binning.is_native() {
+            for image in &mut images {
+                image.bin(levels_to_f64(&binning.discretize(&image.data)), keep);
             }
         }
-    });
+        let mut provenance = Provenance::new(
+            "radmap-gui",
+            volume_path,
+            data_selector.mask_path.as_deref(),
+            &t_map_opts,
+            &texture,
+            binning,
+        );
+        provenance.phi_scrubbed = output_selector.scrub_phi;
+        self.history_run = history::start_run(&provenance, output_dir);
+        self.provenance = Some(provenance);
+        if let Some(preview) = self.preview.take() {
+            preview.stop();
+        }
+        self.preview = match &mask {
+            Some((mask_data, mask_dims)) if mask_dims.shape_ns() == vol_dims.shape_ns() => {
+                Preview::start(&t_map_opts, &texture, &vol, Some(mask_data), &vol_dims)
+            }
+            Some(_) => None,
+            None => Preview::start(&t_map_opts, &texture, &vol, None, &vol_dims),
+        };
+        let t_progress = progress.clone();
+        // headers of radmap's own parsers, whose writers return errors rather than panic
+        let spill = output_selector.spills().then(|| {
+            let header = match output_selector.scrub_phi {
+                true => scrubbed_header(volume_path),
+                false => read_header(volume_path),
+            };
+            (volume_path.clone(), header, features.features_aliases())
+        });
+        let glcm_calc_handle = std::thread::spawn(move || {
+            // check that the mask and volume have compatible shapes
+            let mask = mask.map(|(mask_data, mask_dims)| {
+                assert_eq!(
+                    mask_dims.shape_ns(),
+                    vol_dims.shape_ns(),
+                    "mask and volume have different shapes"
+                );
+                mask_data
+            });
+            let maps = map_features(
+                t_map_opts,
+                &texture,
+                vol,
+                intensities,
+                images,
+                mask,
+                vol_dims,
+                &t_progress,
+            )?;
+            // on disk before the maps go back to the window, which may not be drawn for a
+            // while, so a crash from here on leaves them to recover
+            let spill = spill.and_then(|(input, header, aliases)| {
+                let written = Spill::create(&input).and_then(|spill| {
+                    let header = header?;
+                    match spill.write(&maps.outputs(&aliases), maps.dims(), &header) {
+                        Ok(()) => Ok(spill),
+                        Err(e) => {
+                            spill.discard();
+                            Err(e)
+                        }
+                    }
+                });
+                written
+                    .inspect_err(|e| eprintln!("not keeping a copy of the maps: {e}"))
+                    .ok()
+            });
+            Some((maps, spill))
+        });
 
-    ui.horizontal(|ui| {
-        ui.label(format!("Number of Bins: [{}]\t ", map_opts.num_bins));
-        let te = egui::TextEdit::singleline(&mut map_opts.num_bins_buf).desired_width(40.0);
-        let h = ui.add(te);
-        if h.lost_focus() {
-            if let Ok(parsed) = map_opts.num_bins_buf.parse::<i32>() {
-                if parsed < 1 {
-                    map_opts.num_bins = 4;
-                } else {
-                    map_opts.num_bins = parsed.unsigned_abs() as usize;
+        self.is_running = true;
+        self.succeeded = false;
+        self.ref_header = Some(vol_header);
+        self.handle = Some(glcm_calc_handle);
+    }
+
+    /// picks up the maps of the run once its thread is done, or records why there are none
+    fn poll(
+        &mut self,
+        map_opts: &MapOpts,
+        progress: &Progress,
+        opts_selector: &MapOptSelector,
+        features: &FeatureSelector,
+        ctx: &Context,
+    ) {
+        let Some(h) = self.handle.take() else {
+            return;
+        };
+        if !h.is_finished() {
+            self.handle = Some(h);
+            return;
+        }
+        self.is_running = false;
+        self.elapsed = self.start.map(|s| s.elapsed());
+        if let Some(preview) = &self.preview {
+            preview.stop();
+        }
+        match h.join() {
+            Ok(Some((result, spill))) => {
+                usage::record_run(
+                    "radmap-gui",
+                    map_opts,
+                    &features.texture_opts(opts_selector),
+                    progress.voxels.total(),
+                );
+                let result = Arc::new(result);
+                let label = self.provenance.as_ref().map(run_label);
+                self.viewer.add_run(
+                    result.clone(),
+                    features.features_aliases(),
+                    label.unwrap_or_default(),
+                );
+                self.result = Some(result);
+                self.spill = spill;
+                self.succeeded = true;
+            }
+            Ok(None) => {
+                self.cancelled = true;
+                progress.set_stage(RunStage::Idle);
+                if let Some(id) = self.history_run.take() {
+                    history::fail_run(id, FailureKind::Cancelled, "cancelled by the user");
                 }
             }
+            Err(_) => {
+                self.failed = true;
+                progress.set_stage(RunStage::Idle);
+                if let Some(id) = self.history_run.take() {
+                    history::fail_run(id, FailureKind::Internal, "feature extraction failed");
+                }
+                notify_finished(ctx, self.elapsed, "feature extraction failed");
+            }
         }
-    });
+    }
 
-    ui.horizontal(|ui| {
-        let max_workers = map_opts
-            .max_threads
-            .map(|x| x.to_string())
-            .unwrap_or("all available".to_string());
-        ui.label(format!("Max Threads: [{max_workers}]\t "));
-        let te = egui::TextEdit::singleline(&mut map_opts.max_threads_buf).desired_width(40.0);
-        let h = ui.add(te);
-        if h.lost_focus() {
-            if let Ok(parsed) = map_opts.max_threads_buf.parse::<i32>() {
-                map_opts.max_threads = Some(parsed.unsigned_abs() as usize);
-            } else {
-                map_opts.max_threads = None;
+    pub fn ui(
+        &mut self,
+        progress: &Progress,
+        data_selector: &InputSelector,
+        output_selector: &OutputSelector,
+        ui: &mut Ui,
+    ) {
+        // check that files have been selected
+        if data_selector.volume_path.is_some() && output_selector.output_dir.is_some() {
+            ui.checkbox(&mut self.cache_inputs, "keep inputs loaded between runs")
+                .on_hover_text(
+                    "keeps the last volume and mask in memory, so launching again with other \
+                     options skips reading them. They are read again once the files change",
+                );
+            if let Some(e) = &self.invalid {
+                ui.label(RichText::new(format!("can't launch: {e}")).color(Color32::RED));
+            }
+            let blocked = self.blocked_by(data_selector, output_selector).is_some();
+            if ui
+                .add_enabled(!blocked, egui::Button::new("LAUNCH"))
+                .clicked()
+            {
+                self.launch_clicked = true;
             }
         }
-    });
-}
-
-/****************************
-***** OUTPUT SELECTION ******
-****************************/
-#[derive(Default)]
-pub struct OutputSelector {
-    output_dir_buf: String,
-    output_dir: Option<PathBuf>,
-    //output_dir_dialog: FileDialog,
-    handle: Option<JoinHandle<bool>>,
-    is_writing_output: bool,
-    is_complete: bool,
-}
 
-pub fn update_output_selector(
-    output_selector: &mut OutputSelector,
-    file_dialog: &mut FileDialog,
-    input_selector: &InputSelector,
-    features: &FeatureSelector,
-    launcher: &mut GLCMLauncher,
-    ctx: &Context,
-    ui: &mut Ui,
-) {
-    ui.horizontal(|ui| {
-        ui.label("Output Directory:");
-        if output_selector.output_dir.is_some() {
-            ui.label(RichText::new("✅").color(Color32::GREEN));
-        } else {
-            ui.label(RichText::new("x").color(Color32::RED));
+        if self.is_running {
+            ui.horizontal(|ui| {
+                let elapsed = self.start.map(|s| s.elapsed()).unwrap_or_default();
+                ui.label(format!("running ... {}", format_elapsed(elapsed)));
+                if ui.button("cancel").clicked() {
+                    progress.cancel();
+                }
+            });
+            if let Some(mem) = self.memory.summary() {
+                ui.label(format!("memory: {mem}"));
+            }
         }
 
-        let h = ui.text_edit_singleline(&mut output_selector.output_dir_buf);
+        if let Some(preview) = &self.preview {
+            update_preview(preview, self.is_running, ui);
+        }
 
-        if ui.button("browse").clicked() {
-            //output_selector.output_dir_dialog.pick_directory()
-            file_dialog.pick_directory();
+        if let Some(note) = &self.binning_note {
+            ui.label(RichText::new(note).color(Color32::YELLOW));
         }
 
-        if h.lost_focus() {
-            output_selector.output_dir = None;
-            let p = Path::new(&output_selector.output_dir_buf);
-            if p.exists() {
-                output_selector.output_dir = Some(p.to_path_buf());
-            }
+        if self.failed {
+            ui.label(RichText::new("feature extraction failed").color(Color32::RED));
         }
-    });
 
-    file_dialog.update(ctx);
+        if self.cancelled {
+            ui.label(RichText::new("feature extraction cancelled").color(Color32::YELLOW));
+        }
 
-    if let Some(path) = file_dialog.take_picked() {
-        output_selector.output_dir_buf = path.display().to_string();
-        output_selector.output_dir = Some(path);
+        if self.succeeded {
+            ui.label("feature extraction succeeded!");
+            ui.label(format!(
+                "calculation time: {}",
+                format_elapsed(self.elapsed.unwrap())
+            ));
+            if let Some(peak) = self.memory.peak {
+                ui.label(format!("peak memory: {}", format_bytes(peak)));
+            }
+        }
     }
+}
+
+/// running min/max/mean of each feature over the sampled blocks, with features that are mostly
+/// NaN or flat highlighted
+fn update_preview(preview: &Preview, is_running: bool, ui: &mut Ui) {
+    let voxels = preview.voxels();
+    let title = if is_running && !preview.is_finished() {
+        format!(
+            "feature statistics so far ({} sampled voxels)",
+            format_count(voxels)
+        )
+    } else {
+        format!(
+            "feature statistics of {} sampled voxels",
+            format_count(voxels)
+        )
+    };
+    egui::CollapsingHeader::new(title)
+        .id_salt("preview_stats")
+        .default_open(true)
+        .show(ui, |ui| {
+            if voxels == 0 {
+                ui.label("mapping the first sample ...");
+                return;
+            }
+            egui::Grid::new("preview_stats_grid")
+                .striped(true)
+                .show(ui, |ui| {
+                    for header in ["feature", "min", "max", "mean", "NaN"] {
+                        ui.label(RichText::new(header).strong());
+                    }
+                    ui.end_row();
+                    for (feature, stats) in preview.features() {
+                        let color = if stats.nan_fraction() > 0.5 {
+                            Color32::RED
+                        } else if stats.is_flat() {
+                            Color32::YELLOW
+                        } else {
+                            ui.visuals().text_color()
+                        };
+                        ui.label(RichText::new(feature).color(color));
+                        match stats.mean() {
+                            Some(mean) => {
+                                for x in [stats.min, stats.max, mean] {
+                                    ui.label(RichText::new(format!("{x:.4}")).color(color));
+                                }
+                            }
+                            None => {
+                                for _ in 0..3 {
+                                    ui.label(RichText::new("-").color(color));
+                                }
+                            }
+                        }
+                        ui.label(
+                            RichText::new(format!("{:.1}%", 100. * stats.nan_fraction()))
+                                .color(color),
+                        );
+                        ui.end_row();
+                    }
+                });
+        });
+}
+
+/****************************
+********* MAP VIEWER ********
+****************************/
+/// the maps of a finished run, kept to look at
+struct ViewedRun {
+    maps: Arc<FeatureMaps>,
+    features: Vec<(Feature, String)>,
+    /// the options that set the run apart, e.g. `32 bins, radius 1`
+    label: String,
+}
+
+impl ViewedRun {
+    fn shape(&self) -> [usize; 3] {
+        [0, 1, 2].map(|d| self.maps.dims().shape_ns()[d])
+    }
+
+    /// axial slice `z` of the map of `feature`, if it was selected
+    fn slice(&self, feature: Feature, z: usize) -> Option<&[f32]> {
+        let [nx, ny, _] = self.shape();
+        self.features
+            .iter()
+            .any(|(f, _)| *f == feature)
+            .then(|| self.maps.map(feature))
+            .flatten()
+            .map(|m| &m[z * nx * ny..(z + 1) * nx * ny])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum ViewMode {
+    #[default]
+    Current,
+    Previous,
+    /// the previous run faded into the current one
+    Blend,
+    /// the current run less the previous one
+    Difference,
+}
+
+/// what the image of the viewer was drawn from, so it is only drawn again when it changes
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ViewKey {
+    runs: usize,
+    feature: Feature,
+    slice: usize,
+    mode: ViewMode,
+    blend: f32,
+}
+
+/// a slice of a map of the last run, next to the same map of the run before it so the effect
+/// of changing the options can be judged without writing both runs out
+#[derive(Default)]
+pub struct MapViewer {
+    /// keep the maps of the last two runs in memory
+    enabled: bool,
+    current: Option<ViewedRun>,
+    previous: Option<ViewedRun>,
+    /// runs added so far, to tell when the image is stale
+    runs: usize,
+    feature: Option<Feature>,
+    slice: usize,
+    mode: ViewMode,
+    /// weight of the current run in the blend, 0 shows the previous run only
+    blend: f32,
+    image: Option<(ViewKey, egui::TextureHandle, [f32; 2])>,
+}
+
+impl MapViewer {
+    /// shows the maps of a run that just finished, keeping the last one to compare them
+    fn add_run(&mut self, maps: Arc<FeatureMaps>, features: Vec<(Feature, String)>, label: String) {
+        if !self.enabled {
+            return;
+        }
+        let run = ViewedRun {
+            maps,
+            features,
+            label,
+        };
+        if self
+            .feature
+            .is_none_or(|f| !run.features.iter().any(|(g, _)| *g == f))
+        {
+            self.feature = run.features.first().map(|(f, _)| *f);
+        }
+        let [_, _, nz] = run.shape();
+        if self.slice >= nz {
+            self.slice = nz / 2;
+        }
+        self.previous = self.current.replace(run);
+        self.runs += 1;
+    }
+
+    /// the previous run, if its maps can be compared with the current ones
+    fn comparable(&self, feature: Feature) -> Result<&ViewedRun, &'static str> {
+        let (Some(current), Some(previous)) = (&self.current, &self.previous) else {
+            return Err("run again with other options to compare with this run");
+        };
+        if previous.shape() != current.shape() {
+            return Err("the previous run mapped a volume of another shape");
+        }
+        if previous.slice(feature, 0).is_none() {
+            return Err("the previous run didn't map this feature");
+        }
+        Ok(previous)
+    }
+
+    /// gray levels of the slice shown, and the values black and white stand for
+    fn render(&self, current: &ViewedRun, feature: Feature) -> (Vec<u8>, [f32; 2]) {
+        let z = self.slice;
+        let cur = current
+            .slice(feature, z)
+            .expect("the feature is one of the run");
+        let prev = self
+            .comparable(feature)
+            .ok()
+            .and_then(|p| p.slice(feature, z));
+        let values: Vec<f32> = match (self.mode, prev) {
+            (ViewMode::Previous, Some(prev)) => prev.to_vec(),
+            (ViewMode::Blend, Some(prev)) => cur
+                .iter()
+                .zip(prev)
+                .map(|(c, p)| self.blend * c + (1. - self.blend) * p)
+                .collect(),
+            (ViewMode::Difference, Some(prev)) => {
+                cur.iter().zip(prev).map(|(c, p)| c - p).collect()
+            }
+            _ => cur.to_vec(),
+        };
+        let finite = |v: &[f32]| {
+            v.iter()
+                .copied()
+                .filter(|x| x.is_finite())
+                .collect::<Vec<_>>()
+        };
+        let [lo, hi] = match (self.mode, prev) {
+            // centred on no change
+            (ViewMode::Difference, Some(_)) => {
+                let m = finite(&values)
+                    .into_iter()
+                    .fold(0f32, |m, x| m.max(x.abs()));
+                [-m, m]
+            }
+            // one scale for both runs, so switching between them shows the change
+            _ => finite(cur)
+                .into_iter()
+                .chain(prev.map(finite).unwrap_or_default())
+                .fold([f32::INFINITY, f32::NEG_INFINITY], |[lo, hi], x| {
+                    [lo.min(x), hi.max(x)]
+                }),
+        };
+        let gray = values
+            .iter()
+            .map(|&v| match v.is_finite() {
+                true if hi > lo => ((v - lo) / (hi - lo) * 255.).round() as u8,
+                true => 128,
+                false => 0,
+            })
+            .collect();
+        (gray, [lo, hi])
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui) {
+        egui::CollapsingHeader::new("map viewer")
+            .id_salt("map_viewer")
+            .show(ui, |ui| {
+                ui.checkbox(
+                    &mut self.enabled,
+                    "keep the maps of the last two runs to view and compare",
+                )
+                .on_hover_text(
+                    "holds the maps of the last run and the one before it in memory, so a change \
+                     of bins or radius can be judged side by side without writing both runs",
+                );
+                if !self.enabled {
+                    self.current = None;
+                    self.previous = None;
+                    self.image = None;
+                    return;
+                }
+                let (Some(current), Some(feature)) = (&self.current, self.feature) else {
+                    ui.label("the maps of the next run are shown here");
+                    return;
+                };
+                let [nx, ny, nz] = current.shape();
+
+                let mut selected = feature;
+                egui::ComboBox::from_label("feature")
+                    .selected_text(
+                        current
+                            .features
+                            .iter()
+                            .find(|(f, _)| *f == feature)
+                            .map(|(_, alias)| alias.as_str())
+                            .unwrap_or_default(),
+                    )
+                    .show_ui(ui, |ui| {
+                        for (f, alias) in &current.features {
+                            ui.selectable_value(&mut selected, *f, alias);
+                        }
+                    });
+                let mut slice = self.slice;
+                ui.add(egui::Slider::new(&mut slice, 0..=nz.saturating_sub(1)).text("axial slice"));
+
+                let mut mode = self.mode;
+                let mut blend = self.blend;
+                match self.comparable(selected) {
+                    Ok(previous) => {
+                        ui.horizontal(|ui| {
+                            ui.radio_value(&mut mode, ViewMode::Current, "this run")
+                                .on_hover_text(&current.label);
+                            ui.radio_value(&mut mode, ViewMode::Previous, "previous run")
+                                .on_hover_text(&previous.label);
+                            ui.radio_value(&mut mode, ViewMode::Blend, "blend");
+                            ui.radio_value(&mut mode, ViewMode::Difference, "difference")
+                                .on_hover_text("this run less the previous one, gray is no change");
+                        });
+                        ui.label(format!(
+                            "this run: {}\tprevious run: {}",
+                            current.label, previous.label
+                        ));
+                        if mode == ViewMode::Blend {
+                            ui.add(
+                                egui::Slider::new(&mut blend, 0.0..=1.0)
+                                    .text("previous ⟷ this run"),
+                            );
+                        }
+                    }
+                    Err(why) => {
+                        ui.label(format!("this run: {}", current.label));
+                        ui.label(RichText::new(why).weak());
+                    }
+                }
+                self.feature = Some(selected);
+                self.slice = slice;
+                self.mode = mode;
+                self.blend = blend;
+
+                let key = ViewKey {
+                    runs: self.runs,
+                    feature: selected,
+                    slice,
+                    mode,
+                    blend,
+                };
+                if self.image.as_ref().is_none_or(|(k, ..)| *k != key) {
+                    let current = self.current.as_ref().expect("checked above");
+                    let (gray, range) = self.render(current, selected);
+                    let image = egui::ColorImage::from_gray([nx, ny], &gray);
+                    let texture =
+                        ui.ctx()
+                            .load_texture("map_viewer", image, egui::TextureOptions::NEAREST);
+                    self.image = Some((key, texture, range));
+                }
+                if let Some((_, texture, [lo, hi])) = &self.image {
+                    // scaled up to fit the column, keeping square voxels
+                    let scale = (ui.available_width() / nx as f32).clamp(0.25, 4.);
+                    ui.add(
+                        egui::Image::new(texture)
+                            .fit_to_exact_size(vec2(nx as f32 * scale, ny as f32 * scale)),
+                    );
+                    ui.label(format!("black {lo:.4}, white {hi:.4}"));
+                }
+            });
+    }
+}
+
+/// the options of a run that change its maps the most, e.g. `32 bins, radius 1, distance 2`
+fn run_label(provenance: &Provenance) -> String {
+    let mut label = format!(
+        "{} bins, radius {}",
+        provenance.binning.n_bins, provenance.kernel_radius
+    );
+    if provenance.discretization.is_local() {
+        label.push_str(", local bins");
+    }
+    if provenance.binning.rank {
+        label.push_str(", ranked bins");
+    }
+    if let Some([rx, ry, rz]) = provenance.kernel_radii {
+        label.push_str(&format!(" ({rx},{ry},{rz})"));
+    }
+    if let KernelShape::Sphere { radius_mm } = provenance.kernel_shape {
+        label.push_str(&format!(", sphere {radius_mm} mm"));
+    }
+    if provenance.slice_wise {
+        label.push_str(", 2D");
+    }
+    if let Some(angles) = provenance.glcm_angles {
+        label.push_str(&format!(", angles {angles}"));
+    }
+    if let Some(distances) = &provenance.glcm_distances {
+        let distances: Vec<String> = distances.iter().map(|d| d.to_string()).collect();
+        label.push_str(&format!(", distance {}", distances.join(",")));
+    }
+    if let Some(aggregation) = provenance.glcm_aggregation {
+        label.push_str(&format!(" {aggregation}"));
+    }
+    if let Some(aggregation) = provenance.glcm_direction_aggregation {
+        label.push_str(&format!(", directions {aggregation}"));
+    }
+    if let Some(weighting) = provenance.glcm_weighting {
+        label.push_str(&format!(", {weighting} weighting"));
+    }
+    label
+}
+
+/****************************
+******** REMOTE JOBS ********
+****************************/
+pub struct RemoteSelector {
+    /// run jobs on a `radmap serve` instance instead of this machine
+    enabled: bool,
+    server: String,
+    /// token the server was started with, empty for none
+    token: String,
+    /// certificate authority of a TLS server whose certificate the system doesn't trust
+    ca_cert: String,
+    /// upload the selected inputs rather than naming files on the server
+    upload_inputs: bool,
+    server_volume: String,
+    server_mask: String,
+    server_output_dir: String,
+    updates: Option<Receiver<RemoteUpdate>>,
+    handle: Option<JoinHandle<Result<JobResult, String>>>,
+    /// what the running job is doing
+    status: Option<String>,
+    result: Option<Result<JobResult, String>>,
+}
+
+impl Default for RemoteSelector {
+    fn default() -> Self {
+        RemoteSelector {
+            enabled: false,
+            server: "http://127.0.0.1:50051".to_string(),
+            token: String::new(),
+            ca_cert: String::new(),
+            upload_inputs: true,
+            server_volume: String::new(),
+            server_mask: String::new(),
+            server_output_dir: String::new(),
+            updates: None,
+            handle: None,
+            status: None,
+            result: None,
+        }
+    }
+}
+
+/// command line options of the server side run, from the current settings
+fn remote_args(
+    opts: &MapOptSelector,
+    features: &FeatureSelector,
+    outputs: &OutputSelector,
+) -> Vec<String> {
+    let mut args = vec![
+        format!("--n-bins={}", opts.num_bins),
+        format!("--kernel-radius={}", opts.kernel_radius),
+        format!("--bin-edges={}", opts.bin_edges),
+        format!("--gldm-alpha={}", opts.gldm_alpha),
+        format!("--ngldm-alpha={}", opts.ngldm_alpha),
+        format!("--ngldm-distance={}", opts.ngldm_distance),
+    ];
+    if let Some(width) = opts.bin_width {
+        // the bin width takes the place of the number of bins
+        args[0] = format!("--bin-width={width}");
+    }
+    if let Some(min) = opts.bin_min {
+        args.push(format!("--bin-min={min}"));
+    }
+    if let Some(max) = opts.bin_max {
+        args.push(format!("--bin-max={max}"));
+    }
+    if opts.bin_from_mask {
+        args.push("--bin-from-mask".to_string());
+    }
+    if opts.bin_rank {
+        args.push("--bin-rank".to_string());
+    }
+    if let Some([rx, ry, rz]) = opts.kernel_radii {
+        args.push(format!("--kernel-radii={rx},{ry},{rz}"));
+    }
+    if opts.kernel_shape.is_sphere() {
+        args.push(format!("--kernel-shape={}", opts.kernel_shape));
+    }
+    if opts.discretization.is_local() {
+        args.push(format!("--discretization={}", opts.discretization));
+    }
+    if opts.glcm_per_direction {
+        args.push("--glcm-per-direction".to_string());
+    }
+    if let Some(anisotropy) = opts.glcm_anisotropy {
+        args.push(format!("--glcm-anisotropy={anisotropy}"));
+    }
+    if !opts.glcm_angles.is_all() {
+        args.push(format!("--angles={}", opts.glcm_angles));
+    }
+    if opts.glcm_distances != [cooccurrence::DEFAULT_DISTANCE] {
+        let distances: Vec<String> = opts.glcm_distances.iter().map(|d| d.to_string()).collect();
+        args.push(format!("--distance={}", distances.join(",")));
+    }
+    if opts.glcm_aggregation != DistanceAggregation::Mean {
+        args.push(format!("--distance-aggregation={}", opts.glcm_aggregation));
+    }
+    if opts.glcm_direction_aggregation != DirectionAggregation::Mean {
+        args.push(format!(
+            "--direction-aggregation={}",
+            opts.glcm_direction_aggregation
+        ));
+    }
+    if opts.glcm_weighting != DistanceWeighting::None {
+        args.push(format!("--distance-weighting={}", opts.glcm_weighting));
+    }
+    if opts.slice_wise {
+        args.push("--slice-wise".to_string());
+    }
+    for f in &opts.gabor_frequencies {
+        args.push(format!("--gabor-frequency={f}"));
+    }
+    if !opts.gabor_frequencies.is_empty() {
+        for o in &opts.gabor_orientations {
+            args.push(format!(
+                "--gabor-orientation={}",
+                o.to_string().replace('_', ",")
+            ));
+        }
+    }
+    if opts.gradient {
+        args.push("--gradient".to_string());
+    }
+    if opts.laplacian {
+        args.push("--laplacian".to_string());
+    }
+    for sigma in &opts.log_sigmas {
+        args.push(format!("--log-sigma={sigma}"));
+    }
+    if let Some(kind) = opts.wavelet {
+        args.push(format!("--wavelet={kind}"));
+    }
+    if !opts.filter_opts().is_empty() {
+        args.push(format!("--filter-use={}", opts.filter_use));
+    }
+    for (f, _) in features.features_aliases() {
+        args.push(format!("--feature={}", f.to_string().to_lowercase()));
+    }
+    if let Some(threads) = opts.max_threads {
+        args.push(format!("--max-threads={threads}"));
+    }
+    if opts.rebin_integer_input {
+        args.push("--rebin-integer-input".to_string());
+    }
+    if outputs.allow_in_place {
+        args.push("--allow-in-place".to_string());
+    }
+    if outputs.scrub_phi {
+        args.push("--scrub-phi".to_string());
+    }
+    if outputs.writes_scene() {
+        args.push("--slicer-scene".to_string());
+    }
+    if outputs.export_tensor {
+        args.push("--export-tensor".to_string());
+    }
+    args
+}
+
+impl RemoteSelector {
+    pub fn ui(&mut self, ui: &mut Ui) {
+        ui.checkbox(&mut self.enabled, "run on a radmap server")
+            .on_hover_text(
+                "submit the job to a machine running `radmap serve` instead of computing here",
+            );
+        if !self.enabled {
+            return;
+        }
+        ui.horizontal(|ui| {
+            ui.label("Server:");
+            ui.text_edit_singleline(&mut self.server)
+                .on_hover_text("address of the server, https:// for one serving TLS");
+        });
+        ui.horizontal(|ui| {
+            ui.label("Token:");
+            ui.add(egui::TextEdit::singleline(&mut self.token).password(true))
+                .on_hover_text("token the server was started with (--token-file), if any");
+        });
+        if self.server.trim().starts_with("https://") {
+            ui.horizontal(|ui| {
+                ui.label("CA Certificate:");
+                ui.text_edit_singleline(&mut self.ca_cert).on_hover_text(
+                    "pem certificate that signed the certificate of the server, when the system \
+                     doesn't trust it already",
+                );
+            });
+        }
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.upload_inputs, true, "upload the inputs");
+            ui.radio_value(&mut self.upload_inputs, false, "use files on the server");
+        });
+        if !self.upload_inputs {
+            ui.horizontal(|ui| {
+                ui.label("Server Volume:");
+                ui.text_edit_singleline(&mut self.server_volume);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Server Mask:");
+                ui.text_edit_singleline(&mut self.server_mask);
+            });
+        }
+        ui.horizontal(|ui| {
+            ui.label("Server Output Directory:");
+            ui.text_edit_singleline(&mut self.server_output_dir);
+        });
+    }
+
+    /// follows the job going on, from the updates of the server
+    pub fn logic(&mut self, progress: &Progress) {
+        if let Some(updates) = &self.updates {
+            for update in updates.try_iter() {
+                self.status = Some(match update {
+                    RemoteUpdate::Uploading(path) => format!("uploading {} ...", path.display()),
+                    RemoteUpdate::Submitted(id) => format!("job {id} queued on the server"),
+                    RemoteUpdate::Progress(p) => {
+                        if p.voxels_total > 0 {
+                            progress.voxels.set_total(p.voxels_total as usize);
+                            progress.voxels.set(p.voxels_done as usize);
+                        }
+                        match p.state() {
+                            JobState::Queued => format!("job {} queued on the server", p.id),
+                            _ => format!("job {} running on the server ...", p.id),
+                        }
+                    }
+                });
+            }
+        }
+
+        if let Some(h) = self.handle.take() {
+            if h.is_finished() {
+                self.updates = None;
+                self.status = None;
+                progress.set_stage(RunStage::Done);
+                self.result = Some(
+                    h.join()
+                        .unwrap_or(Err("the server connection crashed".to_string())),
+                );
+            } else {
+                self.handle = Some(h);
+            }
+        }
+    }
+
+    /// offers to launch the job on the server, with how the job going on is doing
+    pub fn launch_ui(
+        &mut self,
+        progress: &mut Progress,
+        opts_selector: &MapOptSelector,
+        features: &FeatureSelector,
+        data_selector: &InputSelector,
+        output_selector: &OutputSelector,
+        ui: &mut Ui,
+    ) {
+        let inputs = if self.upload_inputs {
+            data_selector.volume_path.clone().map(|volume| {
+                (
+                    RemoteInput::Upload(volume),
+                    data_selector.mask_path.clone().map(RemoteInput::Upload),
+                )
+            })
+        } else {
+            let mask = self.server_mask.trim();
+            (!self.server_volume.trim().is_empty()).then(|| {
+                (
+                    RemoteInput::OnServer(self.server_volume.trim().to_string()),
+                    (!mask.is_empty()).then(|| RemoteInput::OnServer(mask.to_string())),
+                )
+            })
+        };
+
+        // the password would have to be sent to the server
+        if output_selector.encrypt_outputs {
+            ui.label(
+                RichText::new("encrypted outputs are not available for server runs")
+                    .color(Color32::RED),
+            );
+        }
+
+        if let Some((input_vol, mask)) = inputs
+            && !self.server_output_dir.trim().is_empty()
+        {
+            let blocked = self.handle.is_some() || output_selector.encrypt_outputs;
+            if ui
+                .add_enabled(!blocked, egui::Button::new("LAUNCH ON SERVER"))
+                .clicked()
+            {
+                let job = RemoteJob {
+                    server: self.server.trim().to_string(),
+                    token: Some(self.token.trim().to_string()).filter(|t| !t.is_empty()),
+                    ca_cert: Some(self.ca_cert.trim())
+                        .filter(|c| !c.is_empty())
+                        .map(PathBuf::from),
+                    input_vol,
+                    mask,
+                    output_dir: self.server_output_dir.trim().to_string(),
+                    args: remote_args(opts_selector, features, output_selector),
+                };
+                let (tx, rx) = channel();
+                self.updates = Some(rx);
+                self.handle = Some(std::thread::spawn(move || job.run(&tx)));
+                self.status = Some("connecting ...".to_string());
+                self.result = None;
+                *progress = Progress::default();
+                progress.set_stage(RunStage::Computing);
+            }
+        }
+
+        if let Some(status) = &self.status {
+            ui.label(status);
+        }
+
+        match &self.result {
+            Some(Ok(result)) if result.state() == JobState::Succeeded => {
+                ui.label("feature extraction succeeded!");
+                ui.label(format!(
+                    "{} files written to {} on the server",
+                    result.outputs.len(),
+                    self.server_output_dir.trim()
+                ));
+            }
+            Some(Ok(result)) => {
+                ui.label(
+                    RichText::new(format!("feature extraction failed: {}", result.message))
+                        .color(Color32::RED),
+                );
+            }
+            Some(Err(e)) => {
+                ui.label(RichText::new(e).color(Color32::RED));
+            }
+            None => {}
+        }
+    }
+}
+
+/****************************
+******** RUN BROWSER ********
+****************************/
+/// summary of every map of a run, by feature
+type MapSummaries = Vec<(String, Option<Summary>)>;
+
+#[derive(Default)]
+pub struct RunBrowser {
+    dir_buf: String,
+    dir_dialog: FileDialog,
+    runs: Vec<PreviousRun>,
+    selected: usize,
+    error: Option<String>,
+    /// summaries of the maps of the selected run
+    stats: MapSummaries,
+    stats_handle: Option<JoinHandle<MapSummaries>>,
+    /// run the selected run is compared against
+    compare_with: usize,
+    comparison: Option<RunComparison>,
+}
+
+impl RunBrowser {
+    fn open(&mut self, dir: &Path) {
+        self.runs.clear();
+        self.stats.clear();
+        self.selected = 0;
+        self.error = match find_previous_runs(dir) {
+            Ok(runs) if runs.is_empty() => {
+                Some(format!("no provenance sidecars found in {}", dir.display()))
+            }
+            Ok(runs) => {
+                self.runs = runs;
+                None
+            }
+            Err(e) => Some(e),
+        };
+        self.load_stats();
+    }
+
+    /// opens the directory picked in the dialog and picks up the summaries of the maps once
+    /// they are read
+    pub fn logic(&mut self, ctx: &Context) {
+        self.dir_dialog.update(ctx);
+        if let Some(dir) = self.dir_dialog.take_picked() {
+            self.dir_buf = dir.display().to_string();
+            self.open(&dir);
+        }
+        if let Some(h) = self.stats_handle.take() {
+            if h.is_finished() {
+                // a map that can't be read has already been written to a crash report
+                self.stats = h.join().unwrap_or_default();
+            } else {
+                self.stats_handle = Some(h);
+            }
+        }
+    }
+
+    /// summarizes the maps of the selected run on a background thread
+    fn load_stats(&mut self) {
+        self.stats.clear();
+        let Some(run) = self.runs.get(self.selected) else {
+            return;
+        };
+        let maps = run.maps.clone();
+        self.stats_handle = Some(std::thread::spawn(move || {
+            maps.into_iter()
+                .map(|(feature, path)| {
+                    let (data, ..) = read_volume(&path);
+                    (feature, summarize(&data))
+                })
+                .collect()
+        }));
+    }
+
+    pub fn ui(
+        &mut self,
+        opts_selector: &mut MapOptSelector,
+        feature_selector: &mut FeatureSelector,
+        data_loader: &mut InputSelector,
+        ui: &mut Ui,
+    ) {
+        ui.collapsing("Open previous run", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Output Directory:");
+                ui.text_edit_singleline(&mut self.dir_buf);
+                if ui.button("browse").clicked() {
+                    self.dir_dialog.pick_directory();
+                }
+                if ui.button("open").clicked() {
+                    let dir = PathBuf::from(&self.dir_buf);
+                    self.open(&dir);
+                }
+            });
+
+            if let Some(err) = &self.error {
+                ui.label(RichText::new(err).color(Color32::RED));
+            }
+            if self.runs.is_empty() {
+                return;
+            }
+
+            let label = |run: &PreviousRun| {
+                format!(
+                    "{} ({})",
+                    run.sidecar
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy(),
+                    format_age(run.created_unix_s)
+                )
+            };
+            let before = self.selected;
+            egui::ComboBox::from_id_salt("previous_run")
+                .selected_text(label(&self.runs[self.selected]))
+                .show_ui(ui, |ui| {
+                    for (i, run) in self.runs.iter().enumerate() {
+                        ui.selectable_value(&mut self.selected, i, label(run));
+                    }
+                });
+            if self.selected != before {
+                self.load_stats();
+            }
+
+            let run = &self.runs[self.selected];
+            let p = &run.provenance;
+            egui::Grid::new("previous_run_settings").show(ui, |ui| {
+                let mut row = |name: &str, value: String| {
+                    ui.label(name);
+                    ui.label(value);
+                    ui.end_row();
+                };
+                row("written by", format!("{} {}", p.app, run.radmap_version));
+                if p.phi_scrubbed {
+                    row("input", "(removed)".to_string());
+                } else {
+                    row("input", p.input.display().to_string());
+                    row(
+                        "mask",
+                        p.mask
+                            .as_ref()
+                            .map(|m| m.display().to_string())
+                            .unwrap_or("none".to_string()),
+                    );
+                }
+                row("kernel radius", p.kernel_radius.to_string());
+                if let Some([rx, ry, rz]) = p.kernel_radii {
+                    row("kernel radii", format!("{rx},{ry},{rz}"));
+                }
+                row("kernel shape", p.kernel_shape.to_string());
+                row("number of bins", p.binning.n_bins.to_string());
+                row("bin edges", p.binning.edges.to_string());
+                row("features", p.features.len().to_string());
+            });
+
+            if self.runs.len() > 1 {
+                ui.horizontal(|ui| {
+                    let label = |run: &PreviousRun| {
+                        run.sidecar
+                            .file_name()
+                            .unwrap_or_default()
+                            .to_string_lossy()
+                            .to_string()
+                    };
+                    self.compare_with = self.compare_with.min(self.runs.len() - 1);
+                    egui::ComboBox::from_id_salt("compare_with")
+                        .selected_text(label(&self.runs[self.compare_with]))
+                        .show_ui(ui, |ui| {
+                            for (i, run) in self.runs.iter().enumerate() {
+                                ui.selectable_value(&mut self.compare_with, i, label(run));
+                            }
+                        });
+                    if ui.button("compare").clicked() {
+                        self.comparison = Some(RunComparison::new(
+                            RunSettings::from_previous_run(run),
+                            RunSettings::from_previous_run(&self.runs[self.compare_with]),
+                        ));
+                    }
+                });
+            }
+
+            if ui
+                .button("use these settings")
+                .on_hover_text("copy the options, features and inputs of this run into the form")
+                .clicked()
+            {
+                use_previous_settings(p, opts_selector, feature_selector, data_loader);
+            }
+
+            if self.stats_handle.is_some() {
+                ui.label("reading maps ...");
+            } else if run.maps.is_empty() {
+                ui.label("none of the feature maps of this run were found");
+            } else {
+                egui::Grid::new("previous_run_stats")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for header in ["feature", "min", "max", "mean", "std"] {
+                            ui.label(RichText::new(header).strong());
+                        }
+                        ui.end_row();
+                        for (feature, summary) in &self.stats {
+                            ui.label(feature);
+                            match summary {
+                                Some(s) => {
+                                    for x in [s.min, s.max, s.mean, s.std] {
+                                        ui.label(format!("{x:.4}"));
+                                    }
+                                }
+                                None => {
+                                    ui.label("no finite values");
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+            }
+        });
+
+        show_comparison(&mut self.comparison, ui.ctx());
+    }
+}
+
+/// copies the options, features and inputs of a previous run into the form
+fn use_previous_settings(
+    p: &Provenance,
+    opts_selector: &mut MapOptSelector,
+    feature_selector: &mut FeatureSelector,
+    data_loader: &mut InputSelector,
+) {
+    opts_selector.kernel_radius = p.kernel_radius;
+    opts_selector.kernel_radii = p.kernel_radii;
+    opts_selector.kernel_shape = p.kernel_shape;
+    opts_selector.num_bins = p.binning.n_bins;
+    opts_selector.bin_edges = p.binning.edges;
+    opts_selector.bin_width = p.binning.width;
+    opts_selector.bin_min = p.binning.min;
+    opts_selector.bin_max = p.binning.max;
+    opts_selector.bin_from_mask = p.binning.from_mask;
+    opts_selector.bin_rank = p.binning.rank;
+    opts_selector.discretization = p.discretization;
+    opts_selector.glcm_angles = p.glcm_angles.unwrap_or_default();
+    opts_selector.glcm_distances = p
+        .glcm_distances
+        .clone()
+        .unwrap_or(vec![cooccurrence::DEFAULT_DISTANCE]);
+    opts_selector.glcm_aggregation = p.glcm_aggregation.unwrap_or_default();
+    opts_selector.glcm_direction_aggregation = p.glcm_direction_aggregation.unwrap_or_default();
+    opts_selector.glcm_weighting = p.glcm_weighting.unwrap_or_default();
+    opts_selector.slice_wise = p.slice_wise;
+    // maps of GLCM and Haralick features are named after their distances
+    let texture = feature_selector.texture_opts(opts_selector);
+    feature_selector.selected_features = Feature::all()
+        .into_iter()
+        .map(|f| (f, f.to_string()))
+        .filter(|(f, name)| {
+            let tagged = texture.output_alias(*f, name);
+            p.features.iter().any(|n| feature_suffix(n) == tagged)
+        })
+        .collect();
+    if p.input.is_file() {
+        data_loader.set_volume(p.input.clone());
+    }
+    data_loader.mask_path_buf = p
+        .mask
+        .as_ref()
+        .map(|m| m.display().to_string())
+        .unwrap_or_default();
+    data_loader.commit_mask_buf();
+}
+
+/// how long ago a unix time was, roughly
+fn format_age(unix_s: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    match now.saturating_sub(unix_s) {
+        s if s < 120 => "just now".to_string(),
+        s if s < 2 * 3600 => format!("{} minutes ago", s / 60),
+        s if s < 2 * 86400 => format!("{} hours ago", s / 3600),
+        s => format!("{} days ago", s / 86400),
+    }
+}
+
+/// settings of two runs side by side
+pub struct RunComparison {
+    a: String,
+    b: String,
+    fields: Vec<FieldDiff>,
+}
+
+impl RunComparison {
+    fn new(a: RunSettings, b: RunSettings) -> Self {
+        RunComparison {
+            fields: diff::compare(&a, &b),
+            a: a.label,
+            b: b.label,
+        }
+    }
+}
+
+/// window listing the settings of two runs, with the ones that differ highlighted
+pub fn show_comparison(comparison: &mut Option<RunComparison>, ctx: &Context) {
+    let Some(c) = comparison.as_ref() else {
+        return;
+    };
+    let mut open = true;
+    egui::Window::new("Compare runs")
+        .open(&mut open)
+        .collapsible(false)
+        .show(ctx, |ui| {
+            ui.label(format!("a: {}", c.a));
+            ui.label(format!("b: {}", c.b));
+            let n_differing = c.fields.iter().filter(|f| f.differs()).count();
+            if n_differing == 0 {
+                ui.label("the runs have the same settings and inputs");
+            } else {
+                ui.label(
+                    RichText::new(format!("{n_differing} setting(s) differ"))
+                        .color(Color32::YELLOW),
+                );
+            }
+            egui::Grid::new("run_comparison")
+                .striped(true)
+                .show(ui, |ui| {
+                    for header in ["setting", "a", "b"] {
+                        ui.label(RichText::new(header).strong());
+                    }
+                    ui.end_row();
+                    for field in &c.fields {
+                        let text = |s: &str| {
+                            if field.differs() {
+                                RichText::new(s).color(Color32::YELLOW)
+                            } else {
+                                RichText::new(s)
+                            }
+                        };
+                        ui.label(text(field.name));
+                        ui.label(text(&field.a));
+                        ui.label(text(&field.b));
+                        ui.end_row();
+                    }
+                });
+        });
+    if !open {
+        *comparison = None;
+    }
+}
+
+/****************************
+******** RUN HISTORY ********
+****************************/
+#[derive(Default)]
+pub struct HistoryBrowser {
+    /// text searched for in the input paths, output paths and options hashes
+    search: String,
+    runs: Vec<RunRecord>,
+    /// id of the run shown in full
+    selected: Option<i64>,
+    /// run marked to be compared against
+    marked: Option<RunRecord>,
+    comparison: Option<RunComparison>,
+    error: Option<String>,
+}
+
+impl HistoryBrowser {
+    /// most recent runs matching the search text in any of the searchable fields
+    fn search(&mut self) {
+        let search = Some(self.search.trim().to_string()).filter(|s| !s.is_empty());
+        let queries = [
+            HistoryQuery {
+                input: search.clone(),
+                ..Default::default()
+            },
+            HistoryQuery {
+                output: search.clone(),
+                ..Default::default()
+            },
+            HistoryQuery {
+                options_hash: search.clone(),
+                ..Default::default()
+            },
+        ];
+        let queries = if search.is_some() {
+            &queries[..]
+        } else {
+            &queries[..1]
+        };
+        let runs = History::open_default().and_then(|h| {
+            let mut runs = vec![];
+            for query in queries {
+                runs.extend(h.query(&HistoryQuery {
+                    limit: Some(HISTORY_ROWS),
+                    ..query.clone()
+                })?);
+            }
+            Ok(runs)
+        });
+        match runs {
+            Ok(mut runs) => {
+                runs.sort_by_key(|r| std::cmp::Reverse(r.id));
+                runs.dedup_by_key(|r| r.id);
+                runs.truncate(HISTORY_ROWS);
+                self.runs = runs;
+                self.error = None;
+            }
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui) {
+        ui.collapsing("Run history", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Search:");
+                let h = ui.text_edit_singleline(&mut self.search).on_hover_text(
+                    "part of an input or output path, or the start of an options hash",
+                );
+                let entered = h.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if ui.button("search").clicked() || entered {
+                    self.search();
+                }
+            });
+
+            if let Some(err) = &self.error {
+                ui.label(RichText::new(err).color(Color32::RED));
+            }
+            if self.runs.is_empty() {
+                ui.label("no runs to show, search to list the most recent ones");
+                return;
+            }
+
+            egui::ScrollArea::vertical()
+                .id_salt("history_runs")
+                .max_height(200.)
+                .show(ui, |ui| {
+                    egui::Grid::new("history_runs_grid")
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for header in ["started", "status", "options", "input"] {
+                                ui.label(RichText::new(header).strong());
+                            }
+                            ui.end_row();
+                            for run in &self.runs {
+                                let selected = self.selected == Some(run.id);
+                                if ui
+                                    .selectable_label(
+                                        selected,
+                                        format_utc(run.started_unix_s as u64),
+                                    )
+                                    .clicked()
+                                {
+                                    self.selected = (!selected).then_some(run.id);
+                                }
+                                ui.label(run.status.to_string());
+                                ui.monospace(&run.options_hash);
+                                ui.label(run.input.as_deref().unwrap_or("(removed)"));
+                                ui.end_row();
+                            }
+                        });
+                });
+
+            let Some(run) = self
+                .selected
+                .and_then(|id| self.runs.iter().find(|r| r.id == id))
+            else {
+                return;
+            };
+            ui.separator();
+            egui::Grid::new("history_run_details").show(ui, |ui| {
+                let mut row = |name: &str, value: String| {
+                    ui.label(name);
+                    ui.label(value);
+                    ui.end_row();
+                };
+                row("run", run.id.to_string());
+                row("written by", format!("{} {}", run.app, run.radmap_version));
+                row(
+                    "duration",
+                    run.duration_s()
+                        .map(|d| format!("{:.1} minutes", d / 60.))
+                        .unwrap_or("still running or interrupted".to_string()),
+                );
+                if let Some(kind) = &run.failure_kind {
+                    row(
+                        "failure",
+                        format!("{kind}: {}", run.message.as_deref().unwrap_or("")),
+                    );
+                }
+                row("output directory", run.output_dir.clone());
+                row("options", run.options.clone());
+            });
+            for output in &run.outputs {
+                ui.monospace(output.display().to_string());
+            }
+            ui.horizontal(|ui| {
+                if ui.button("copy options").clicked() {
+                    ui.ctx().copy_text(run.options.clone());
+                }
+                if ui.button("mark for comparison").clicked() {
+                    self.marked = Some(run.clone());
+                }
+                if let Some(marked) = self.marked.as_ref().filter(|m| m.id != run.id)
+                    && ui
+                        .button(format!("compare with run {}", marked.id))
+                        .clicked()
+                {
+                    self.comparison = match (
+                        RunSettings::from_record(marked),
+                        RunSettings::from_record(run),
+                    ) {
+                        (Ok(a), Ok(b)) => Some(RunComparison::new(a, b)),
+                        (Err(e), _) | (_, Err(e)) => {
+                            self.error = Some(e);
+                            None
+                        }
+                    };
+                }
+            });
+        });
+        show_comparison(&mut self.comparison, ui.ctx());
+    }
+}
+
+/// number of runs listed in the history panel
+const HISTORY_ROWS: usize = 50;
+
+/****************************
+********** PROGRESS *********
+****************************/
+pub fn update_progress(progress: &mut Progress, ui: &mut Ui) {
+    if progress.stage() == RunStage::Idle {
+        return;
+    }
+    ui.label(RichText::new(progress.stage().label()).strong());
+    if let Some(fraction) = progress.voxels.fraction() {
+        ui.add(ProgressBar::new(fraction).show_percentage());
+        ui.label(format!(
+            "{} / {} voxels",
+            format_count(progress.voxels.done()),
+            format_count(progress.voxels.total())
+        ));
+    }
+    if let Some(fraction) = progress.bytes.fraction() {
+        ui.add(ProgressBar::new(fraction).show_percentage());
+        ui.label(format!(
+            "{} / {} written ({} / {} feature maps)",
+            format_bytes(progress.bytes.done() as u64),
+            format_bytes(progress.bytes.total() as u64),
+            progress.features.done(),
+            progress.features.total()
+        ));
+    }
+}
+
+/****************************
+***** FEATURE SELECTION *****
+****************************/
+
+pub struct FeatureSelector {
+    selected_features: HashMap<Feature, String>,
+}
+
+impl FeatureSelector {
+    /// the selected features of the families other than GLCM, with the options of `map_opts`
+    pub fn texture_opts(&self, map_opts: &MapOptSelector) -> TextureOpts {
+        TextureOpts {
+            gldm_alpha: map_opts.gldm_alpha,
+            ngldm_alpha: map_opts.ngldm_alpha,
+            ngldm_distance: map_opts.ngldm_distance,
+            filters: map_opts.filter_opts(),
+            glcm_per_direction: map_opts.glcm_per_direction,
+            glcm_anisotropy: map_opts.glcm_anisotropy,
+            discretization: map_opts.discretization,
+            glcm_angles: map_opts.glcm_angles,
+            glcm_distances: map_opts.glcm_distances.clone(),
+            glcm_aggregation: map_opts.glcm_aggregation,
+            glcm_direction_aggregation: map_opts.glcm_direction_aggregation,
+            glcm_weighting: map_opts.glcm_weighting,
+            slice_wise: map_opts.slice_wise,
+            kernel_radii: TextureOpts::anisotropic_radii(map_opts.kernel_radii),
+            kernel_shape: map_opts.kernel_shape,
+            ..TextureOpts::split(&self.selected_features).1
+        }
+    }
+
+    pub fn features_aliases(&self) -> Vec<(Feature, String)> {
+        let mut f: Vec<_> = self
+            .selected_features
+            .iter()
+            .map(|(k, v)| (*k, v.clone()))
+            .collect();
+        f.sort_by_key(|k| k.1.clone());
+        f
+    }
+}
+
+impl Default for FeatureSelector {
+    fn default() -> Self {
+        let selected_features = Family::Glcm
+            .features()
+            .into_iter()
+            .map(|f| (f, f.name().replace("_", " ")))
+            .collect();
+        FeatureSelector { selected_features }
+    }
+}
+
+/// fixes the options and features to those of a locked protocol
+pub fn apply_protocol(
+    protocol: &Protocol,
+    map_opts: &mut MapOptSelector,
+    features: &mut FeatureSelector,
+) {
+    map_opts.kernel_radius = protocol.kernel_radius;
+    map_opts.kernel_radii = None;
+    map_opts.kernel_radii_buf.clear();
+    map_opts.kernel_shape = KernelShape::Cube;
+    map_opts.sphere_radius_buf.clear();
+    map_opts.num_bins = protocol.n_bins;
+    map_opts.bin_edges = protocol.bin_edges;
+    // protocols bin into a number of bins
+    map_opts.bin_width = None;
+    map_opts.bin_min = None;
+    map_opts.bin_max = None;
+    map_opts.bin_from_mask = false;
+    map_opts.bin_rank = false;
+    map_opts.discretization = DiscretizationScope::Global;
+    map_opts.glcm_angles = Angles::ALL;
+    map_opts.glcm_distances = vec![cooccurrence::DEFAULT_DISTANCE];
+    map_opts.glcm_distances_buf.clear();
+    map_opts.glcm_aggregation = DistanceAggregation::Mean;
+    map_opts.glcm_direction_aggregation = DirectionAggregation::Mean;
+    map_opts.glcm_weighting = DistanceWeighting::None;
+    map_opts.slice_wise = false;
+    map_opts.rebin_integer_input = protocol.rebin_integer_input;
+    map_opts.kernel_radius_buf.clear();
+    map_opts.num_bins_buf.clear();
+    map_opts.bin_width_buf.clear();
+    map_opts.bin_min_buf.clear();
+    map_opts.bin_max_buf.clear();
+    features.selected_features = protocol
+        .features
+        .iter()
+        .map(|f| (*f, f.to_string()))
+        .collect();
+}
+
+/// description, range, IBSI identifier and cost of a feature
+fn feature_tooltip(info: &FeatureInfo) -> String {
+    let mut text = format!("{}\nvalues: {}", info.description, info.range);
+    if let Some(code) = info.ibsi {
+        text.push_str(&format!("\nIBSI: {code}"));
+    }
+    text.push_str(&format!("\ncost: {}", info.cost));
+    text
+}
+
+impl FeatureSelector {
+    pub fn select_all(&mut self) {
+        for feature in Feature::all() {
+            self.selected_features
+                .insert(feature, feature.to_string().replace("_", " "));
+        }
+    }
+
+    pub fn deselect_all(&mut self) {
+        self.selected_features.clear();
+    }
+
+    pub fn set_selected(&mut self, feature: Feature, selected: bool) {
+        if selected {
+            self.selected_features.insert(feature, feature.to_string());
+        } else {
+            self.selected_features.remove(&feature);
+        }
+    }
+
+    /// estimated runtime of the selected features over the whole selected volume, e.g.
+    /// `up to 2 min for 262,144 voxels`. None if the options are invalid
+    fn runtime_estimate(
+        &self,
+        map_opts: &MapOptSelector,
+        volume_shape: [usize; 3],
+    ) -> Option<String> {
+        let (opts, texture) = map_opts.builder(self).build().ok()?;
+        let n_voxels = volume_shape.iter().product();
+        Some(format!(
+            "up to {} for {} voxels",
+            format_duration(estimated_runtime(&opts, &texture, n_voxels)),
+            format_count(n_voxels)
+        ))
+    }
+
+    pub fn ui(&mut self, map_opts: &MapOptSelector, volume_shape: Option<[usize; 3]>, ui: &mut Ui) {
+        if ui.button("deselect all").clicked() {
+            self.deselect_all();
+        }
+
+        if ui.button("select all").clicked() {
+            self.select_all();
+        }
+
+        if let Some(estimate) =
+            volume_shape.and_then(|shape| self.runtime_estimate(map_opts, shape))
+        {
+            ui.label(format!("estimated runtime: {estimate}"))
+                .on_hover_text(
+                "from a fixed cost model of each family on this machine's threads. A mask cuts \
+                     it down by the share of voxels it keeps",
+            );
+        }
+
+        for family in Family::ALL {
+            let relative = family.relative_cost(map_opts.num_bins, map_opts.kernel_radius);
+            egui::CollapsingHeader::new(format!(
+                "{} - {} cost, {relative:.1}x",
+                family.label(),
+                family.cost()
+            ))
+            .id_salt(family.as_str())
+            .default_open(family == Family::Glcm)
+            .show(ui, |ui| {
+                for feature in family.features() {
+                    let mut is_selected = self.selected_features.contains_key(&feature);
+                    if ui
+                        .checkbox(&mut is_selected, feature.name().replace("_", " "))
+                        .on_hover_text(feature_tooltip(&feature.info()))
+                        .changed()
+                    {
+                        self.set_selected(feature, is_selected);
+                    }
+                }
+            });
+        }
+    }
+}
+
+/****************************
+******* MAP OPTIONS *******
+****************************/
+
+pub struct MapOptSelector {
+    kernel_radius: usize,
+    /// a radius along x, y and z in place of `kernel_radius`
+    kernel_radii: Option<[usize; 3]>,
+    kernel_radii_buf: String,
+    /// a sphere in mm in place of the cube of `kernel_radius`
+    kernel_shape: KernelShape,
+    sphere_radius_buf: String,
+    num_bins: usize,
+    /// bin already quantized inputs like any other instead of using their levels as the bins
+    rebin_integer_input: bool,
+    bin_edges: BinEdges,
+    /// split the input into bins of this width instead of into `num_bins` bins
+    bin_width: Option<f64>,
+    /// intensities binned, the range of the input when unset
+    bin_min: Option<f64>,
+    bin_max: Option<f64>,
+    /// take the range from the voxels inside the mask
+    bin_from_mask: bool,
+    /// bin the ranks of the intensities
+    bin_rank: bool,
+    /// bin the whole volume or each kernel on its own
+    discretization: DiscretizationScope,
+    bin_width_buf: String,
+    bin_min_buf: String,
+    bin_max_buf: String,
+    kernel_radius_buf: String,
+    num_bins_buf: String,
+    max_threads: Option<usize>,
+    max_threads_buf: String,
+    gldm_alpha: usize,
+    gldm_alpha_buf: String,
+    ngldm_alpha: usize,
+    ngldm_alpha_buf: String,
+    ngldm_distance: usize,
+    ngldm_distance_buf: String,
+    glcm_per_direction: bool,
+    glcm_anisotropy: Option<Anisotropy>,
+    /// directions the co-occurrence matrices are counted along
+    glcm_angles: Angles,
+    glcm_distances: Vec<usize>,
+    glcm_distances_buf: String,
+    glcm_aggregation: DistanceAggregation,
+    glcm_direction_aggregation: DirectionAggregation,
+    glcm_weighting: DistanceWeighting,
+    /// keep the kernels within the axial slice of their voxel
+    slice_wise: bool,
+    gabor_frequencies: Vec<f64>,
+    gabor_frequencies_buf: String,
+    gabor_orientations: Vec<Orientation>,
+    gabor_orientations_buf: String,
+    gradient: bool,
+    laplacian: bool,
+    log_sigmas: Vec<f64>,
+    log_sigmas_buf: String,
+    /// every sub-band of this wavelet when set
+    wavelet: Option<WaveletKind>,
+    filter_use: FilterUse,
+    usage_stats_enabled: bool,
+    number_format: NumberFormat,
+    parse_error: Option<String>,
+}
+
+impl Default for MapOptSelector {
+    fn default() -> Self {
+        MapOptSelector {
+            kernel_radius: DEFAULT_KERNEL_RADIUS,
+            kernel_radii: None,
+            kernel_radii_buf: String::new(),
+            kernel_shape: KernelShape::Cube,
+            sphere_radius_buf: String::new(),
+            num_bins: DEFAULT_N_BINS,
+            rebin_integer_input: false,
+            bin_edges: BinEdges::default(),
+            bin_width: None,
+            bin_min: None,
+            bin_max: None,
+            bin_from_mask: false,
+            bin_rank: false,
+            discretization: DiscretizationScope::Global,
+            bin_width_buf: String::new(),
+            bin_min_buf: String::new(),
+            bin_max_buf: String::new(),
+            kernel_radius_buf: String::new(),
+            num_bins_buf: String::new(),
+            max_threads: None,
+            max_threads_buf: String::new(),
+            gldm_alpha: 0,
+            gldm_alpha_buf: String::new(),
+            ngldm_alpha: 0,
+            ngldm_alpha_buf: String::new(),
+            ngldm_distance: ngldm::DEFAULT_DISTANCE,
+            ngldm_distance_buf: String::new(),
+            glcm_per_direction: false,
+            glcm_anisotropy: None,
+            glcm_angles: Angles::ALL,
+            glcm_distances: vec![cooccurrence::DEFAULT_DISTANCE],
+            glcm_distances_buf: String::new(),
+            glcm_aggregation: DistanceAggregation::Mean,
+            glcm_direction_aggregation: DirectionAggregation::Mean,
+            glcm_weighting: DistanceWeighting::None,
+            slice_wise: false,
+            gabor_frequencies: vec![],
+            gabor_frequencies_buf: String::new(),
+            gabor_orientations: vec![Orientation::X, Orientation::Y, Orientation::Z],
+            gabor_orientations_buf: String::new(),
+            gradient: false,
+            laplacian: false,
+            log_sigmas: vec![],
+            log_sigmas_buf: String::new(),
+            wavelet: None,
+            filter_use: FilterUse::default(),
+            usage_stats_enabled: usage::is_enabled(),
+            number_format: NumberFormat::from_env(),
+            parse_error: None,
+        }
+    }
+}
+
+impl MapOptSelector {
+    /// binning of the form with `n_bins`, which gives way to the bin width if there is one
+    pub fn binning(&self, n_bins: usize) -> Binning {
+        Binning {
+            n_bins,
+            edges: self.bin_edges,
+            width: self.bin_width,
+            min: self.bin_min,
+            max: self.bin_max,
+            from_mask: self.bin_from_mask,
+            rank: self.bin_rank,
+        }
+    }
+
+    /// the Gabor filter bank and derivative filters of the form
+    pub fn filter_opts(&self) -> FilterOpts {
+        let mut filters = FilterOpts {
+            usage: self.filter_use,
+            ..Default::default()
+        };
+        filters.add_gabor_bank(&self.gabor_frequencies, &self.gabor_orientations);
+        if self.gradient {
+            filters.filters.push(ImageFilter::GradientMagnitude);
+        }
+        if self.laplacian {
+            filters.filters.push(ImageFilter::Laplacian);
+        }
+        filters.add_log(&self.log_sigmas);
+        if let Some(kind) = self.wavelet {
+            filters.add_wavelet(kind, &SubBand::ALL);
+        }
+        filters
+    }
+
+    /// sets an option by its command line name, as a script of [`Autodrive`] does. Values are
+    /// written as on the command line, with lists split by commas and an empty value unsetting
+    /// an optional one
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        fn parse<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+            value
+                .trim()
+                .parse()
+                .map_err(|_| format!("{value} is not a valid value for {name}"))
+        }
+        fn optional<T: FromStr>(name: &str, value: &str) -> Result<Option<T>, String> {
+            match value.trim() {
+                "" => Ok(None),
+                v => parse(name, v).map(Some),
+            }
+        }
+        fn list<T: FromStr>(name: &str, value: &str) -> Result<Vec<T>, String> {
+            value
+                .split(',')
+                .filter(|v| !v.trim().is_empty())
+                .map(|v| parse(name, v))
+                .collect()
+        }
+        let Some(option) = MapOption::ALL.into_iter().find(|o| o.info().name == name) else {
+            return Err(format!("there is no option named {name}"));
+        };
+        match option {
+            MapOption::NBins => {
+                self.num_bins = parse(name, value)?;
+                self.num_bins_buf.clear();
+            }
+            MapOption::KernelRadius => {
+                self.kernel_radius = parse(name, value)?;
+                self.kernel_radius_buf.clear();
+            }
+            MapOption::KernelRadii => {
+                self.kernel_radii = match value.trim() {
+                    "" => None,
+                    v => Some(parse_kernel_radii(v)?),
+                };
+                self.kernel_radii_buf.clear();
+            }
+            MapOption::KernelShape => {
+                self.kernel_shape = parse(name, value)?;
+                self.sphere_radius_buf.clear();
+            }
+            MapOption::BinEdges => self.bin_edges = parse(name, value)?,
+            MapOption::BinWidth => {
+                self.bin_width = optional(name, value)?;
+                self.bin_width_buf.clear();
+            }
+            MapOption::BinMin => {
+                self.bin_min = optional(name, value)?;
+                self.bin_min_buf.clear();
+            }
+            MapOption::BinMax => {
+                self.bin_max = optional(name, value)?;
+                self.bin_max_buf.clear();
+            }
+            MapOption::BinFromMask => self.bin_from_mask = parse(name, value)?,
+            MapOption::BinRank => self.bin_rank = parse(name, value)?,
+            MapOption::Discretization => self.discretization = parse(name, value)?,
+            MapOption::RebinIntegerInput => self.rebin_integer_input = parse(name, value)?,
+            MapOption::GldmAlpha => {
+                self.gldm_alpha = parse(name, value)?;
+                self.gldm_alpha_buf.clear();
+            }
+            MapOption::NgldmAlpha => {
+                self.ngldm_alpha = parse(name, value)?;
+                self.ngldm_alpha_buf.clear();
+            }
+            MapOption::NgldmDistance => {
+                self.ngldm_distance = parse(name, value)?;
+                self.ngldm_distance_buf.clear();
+            }
+            MapOption::MaxThreads => {
+                self.max_threads = optional(name, value)?;
+                self.max_threads_buf.clear();
+            }
+            MapOption::GaborFrequency => {
+                self.gabor_frequencies = list(name, value)?;
+                self.gabor_frequencies_buf.clear();
+            }
+            MapOption::GaborOrientation => {
+                self.gabor_orientations = list(name, value)?;
+                self.gabor_orientations_buf.clear();
+            }
+            MapOption::Gradient => self.gradient = parse(name, value)?,
+            MapOption::Laplacian => self.laplacian = parse(name, value)?,
+            MapOption::LogSigma => {
+                self.log_sigmas = list(name, value)?;
+                self.log_sigmas_buf.clear();
+            }
+            MapOption::Wavelet => self.wavelet = optional(name, value)?,
+            MapOption::WaveletBand => {
+                return Err(format!("{name} can't be set, the GUI maps every sub-band"));
+            }
+            MapOption::FilterUse => self.filter_use = parse(name, value)?,
+            MapOption::GlcmPerDirection => self.glcm_per_direction = parse(name, value)?,
+            MapOption::GlcmAnisotropy => self.glcm_anisotropy = optional(name, value)?,
+            MapOption::GlcmAngles => self.glcm_angles = parse(name, value)?,
+            MapOption::GlcmDistance => {
+                let distances: Vec<usize> = list(name, value)?;
+                if distances.is_empty() {
+                    return Err(format!("{name} needs at least one distance"));
+                }
+                self.glcm_distances = distances;
+                self.glcm_distances_buf.clear();
+            }
+            MapOption::GlcmAggregation => self.glcm_aggregation = parse(name, value)?,
+            MapOption::GlcmDirectionAggregation => {
+                self.glcm_direction_aggregation = parse(name, value)?
+            }
+            MapOption::GlcmWeighting => self.glcm_weighting = parse(name, value)?,
+            MapOption::SliceWise => self.slice_wise = parse(name, value)?,
+        }
+        Ok(())
+    }
+
+    /// the options of the form with the selected features, to be checked and built
+    pub fn builder(&self, features: &FeatureSelector) -> MapOptsBuilder {
+        MapOptsBuilder::new()
+            .n_bins(self.num_bins)
+            .kernel_radius(self.kernel_radius)
+            .kernel_radii(self.kernel_radii)
+            .kernel_shape(self.kernel_shape)
+            .max_threads(self.max_threads)
+            .gldm_alpha(self.gldm_alpha)
+            .ngldm_alpha(self.ngldm_alpha)
+            .ngldm_distance(self.ngldm_distance)
+            .glcm_per_direction(self.glcm_per_direction)
+            .glcm_anisotropy(self.glcm_anisotropy)
+            .discretization(self.discretization)
+            .glcm_angles(self.glcm_angles)
+            .glcm_distances(self.glcm_distances.iter().copied())
+            .glcm_aggregation(self.glcm_aggregation)
+            .glcm_direction_aggregation(self.glcm_direction_aggregation)
+            .glcm_weighting(self.glcm_weighting)
+            .slice_wise(self.slice_wise)
+            .filters(self.filter_opts())
+            .features(features.selected_features.clone())
+    }
+
+    /// label, value and text field of the bin width, minimum or maximum
+    fn bin_field(&mut self, option: MapOption) -> (&'static str, &mut Option<f64>, &mut String) {
+        match option {
+            MapOption::BinWidth => ("Bin Width", &mut self.bin_width, &mut self.bin_width_buf),
+            MapOption::BinMin => ("Bin Minimum", &mut self.bin_min, &mut self.bin_min_buf),
+            MapOption::BinMax => ("Bin Maximum", &mut self.bin_max, &mut self.bin_max_buf),
+            _ => unreachable!("{} has no bin field", option.info().name),
+        }
+    }
+
+    /// reads the text field of `option` once it loses focus, in the number format of the
+    /// locale. A field that can't be read leaves the option as it was and sets `parse_error`
+    fn commit_buf(&mut self, option: MapOption) {
+        self.parse_error = self.read_buf(option).err();
+    }
+
+    fn read_buf(&mut self, option: MapOption) -> Result<(), String> {
+        let fmt = self.number_format;
+        // a whole number, None for an empty field
+        let integer = |buf: &str, field: &str| match buf.trim() {
+            "" => Ok(None),
+            _ => fmt
+                .parse_integer(buf)
+                .map(Some)
+                .ok_or_else(|| parse_error(field, buf)),
+        };
+        // values separated by commas, none for an empty field
+        fn list<T: FromStr>(buf: &str, what: &str) -> Result<Vec<T>, String> {
+            buf.split(',')
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|_| format!("could not read \"{buf}\" as {what} separated by commas"))
+        }
+        match option {
+            MapOption::KernelRadius => {
+                if let Some(r) = integer(&self.kernel_radius_buf, "kernel radius")? {
+                    self.kernel_radius =
+                        usize::try_from(r).map_err(|_| "the kernel radius can't be negative")?;
+                }
+            }
+            MapOption::KernelRadii => {
+                // an empty field turns the radius per axis off
+                self.kernel_radii = match self.kernel_radii_buf.trim() {
+                    "" => None,
+                    buf => Some(parse_kernel_radii(buf)?),
+                };
+            }
+            MapOption::KernelShape => {
+                // the field holds the radius of the sphere, an empty field is a cube
+                let buf = self.sphere_radius_buf.trim();
+                self.kernel_shape = match fmt.parse(buf) {
+                    None if buf.is_empty() => KernelShape::Cube,
+                    Some(radius_mm) if radius_mm.is_finite() && radius_mm > 0. => {
+                        KernelShape::Sphere { radius_mm }
+                    }
+                    _ => {
+                        return Err(format!(
+                            "could not read \"{buf}\" as a radius above 0 mm for the sphere"
+                        ));
+                    }
+                };
+            }
+            MapOption::NBins => {
+                if let Some(n) = integer(&self.num_bins_buf, "number of bins")? {
+                    self.num_bins =
+                        usize::try_from(n).map_err(|_| "the number of bins can't be negative")?;
+                }
+            }
+            MapOption::BinWidth | MapOption::BinMin | MapOption::BinMax => {
+                let (field, value, buf) = self.bin_field(option);
+                // an empty field turns it off
+                match fmt.parse(buf) {
+                    None if buf.trim().is_empty() => *value = None,
+                    Some(parsed) if option == MapOption::BinWidth && parsed <= 0. => {
+                        return Err("the bin width must be above 0".into());
+                    }
+                    Some(parsed) if parsed.is_finite() => *value = Some(parsed),
+                    _ => {
+                        return Err(format!(
+                            "could not read \"{buf}\" as a number for the {}",
+                            field.to_lowercase()
+                        ));
+                    }
+                }
+            }
+            MapOption::GldmAlpha => {
+                if let Some(a) = integer(&self.gldm_alpha_buf, "GLDM alpha")? {
+                    self.gldm_alpha =
+                        usize::try_from(a).map_err(|_| "the GLDM alpha can't be negative")?;
+                }
+            }
+            MapOption::NgldmAlpha => {
+                if let Some(a) = integer(&self.ngldm_alpha_buf, "NGLDM alpha")? {
+                    self.ngldm_alpha =
+                        usize::try_from(a).map_err(|_| "the NGLDM alpha can't be negative")?;
+                }
+            }
+            MapOption::NgldmDistance => {
+                match integer(&self.ngldm_distance_buf, "NGLDM distance")? {
+                    Some(d) if d < 1 => return Err("the NGLDM distance must be at least 1".into()),
+                    Some(d) => self.ngldm_distance = d as usize,
+                    None => {}
+                }
+            }
+            MapOption::MaxThreads => {
+                // an empty field means all available threads
+                self.max_threads = integer(&self.max_threads_buf, "max threads")?
+                    .map(|n| usize::try_from(n).map_err(|_| "max threads can't be negative"))
+                    .transpose()?;
+            }
+            MapOption::GlcmDistance => {
+                let distances: Vec<usize> = list(&self.glcm_distances_buf, "distances")?;
+                if distances.contains(&0) {
+                    return Err("the GLCM distance must be at least 1".into());
+                }
+                // an empty field keeps the distances
+                if !distances.is_empty() {
+                    self.glcm_distances = distances;
+                }
+            }
+            // an empty field means no Gabor filters
+            MapOption::GaborFrequency => {
+                self.gabor_frequencies = list(&self.gabor_frequencies_buf, "frequencies")?;
+            }
+            MapOption::GaborOrientation => {
+                // orientations are separated by spaces, as vectors hold commas
+                let orientations = self
+                    .gabor_orientations_buf
+                    .split_whitespace()
+                    .map(Orientation::from_str)
+                    .collect::<Result<Vec<_>, _>>()?;
+                if !orientations.is_empty() {
+                    self.gabor_orientations = orientations;
+                }
+            }
+            // an empty field means no LoG filters
+            MapOption::LogSigma => self.log_sigmas = list(&self.log_sigmas_buf, "sigmas")?,
+            // the other options are set with checkboxes and buttons
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// turns the local usage statistics on or off as the checkbox says, deleting them when off
+    fn apply_usage_stats(&mut self) {
+        let res = if self.usage_stats_enabled {
+            usage::enable().map(|_| ())
+        } else {
+            usage::disable()
+        };
+        if let Err(e) = res {
+            eprintln!("{e}");
+            self.usage_stats_enabled = usage::is_enabled();
+        }
+    }
+
+    pub fn ui(&mut self, volume_spacing: Option<[f64; 3]>, locked: bool, ui: &mut Ui) {
+        // a locked protocol fixes everything that decides the values of the maps
+        ui.add_enabled_ui(!locked, |ui| {
+            ui.horizontal(|ui| {
+                let help = MapOption::KernelRadius.help();
+                ui.label(format!("Kernel Radius: [{}]\t ", self.kernel_radius))
+                    .on_hover_text(&help);
+                let te =
+                    egui::TextEdit::singleline(&mut self.kernel_radius_buf).desired_width(40.0);
+                let h = ui.add(te).on_hover_text(help);
+                if h.lost_focus() {
+                    self.commit_buf(MapOption::KernelRadius);
+                }
+            });
+
+            ui.horizontal(|ui| {
+                let help = MapOption::KernelRadii.help();
+                let radii = match self.kernel_radii {
+                    Some([rx, ry, rz]) => format!("{rx},{ry},{rz}"),
+                    None => "off".to_string(),
+                };
+                ui.label(format!("Radius per Axis: [{radii}]\t "))
+                    .on_hover_text(&help);
+                let te = egui::TextEdit::singleline(&mut self.kernel_radii_buf).desired_width(60.0);
+                let h = ui.add(te).on_hover_text(help);
+                if h.lost_focus() {
+                    self.commit_buf(MapOption::KernelRadii);
+                }
+            });
+
+            ui.horizontal(|ui| {
+                let help = MapOption::KernelShape.help();
+                let shape = match self.kernel_shape {
+                    KernelShape::Sphere { radius_mm } => format!("{radius_mm} mm"),
+                    KernelShape::Cube => "off".to_string(),
+                };
+                ui.label(format!("Sphere Radius: [{shape}]\t "))
+                    .on_hover_text(&help);
+                let te =
+                    egui::TextEdit::singleline(&mut self.sphere_radius_buf).desired_width(40.0);
+                let h = ui.add(te).on_hover_text(help);
+                if h.lost_focus() {
+                    self.commit_buf(MapOption::KernelShape);
+                }
+            });
+
+            ui.checkbox(
+                &mut self.slice_wise,
+                "2D: keep kernels within their axial slice",
+            )
+            .on_hover_text(MapOption::SliceWise.help());
+
+            // kernels are cubes in voxel space unless given a radius per axis, which can be far
+            // from cubic on anisotropic scans, or spheres in mm bounded by a box of voxels
+            let [rx, ry, rz] = match self.kernel_shape {
+                KernelShape::Sphere { radius_mm } => {
+                    KernelShape::sphere_radii(radius_mm, volume_spacing.unwrap_or([1.; 3]))
+                }
+                KernelShape::Cube => self.kernel_radii.unwrap_or([self.kernel_radius; 3]),
+            };
+            let radius = [rx, ry, if self.slice_wise { 0 } else { rz }];
+            let size = match self.kernel_shape {
+                KernelShape::Sphere { radius_mm } => format!(
+                    "sphere of {radius_mm} mm within {}",
+                    describe_kernel(radius, volume_spacing)
+                ),
+                KernelShape::Cube => describe_kernel(radius, volume_spacing),
+            };
+            ui.label(RichText::new(format!("kernel size: {size}")).weak());
+
+            ui.horizontal(|ui| {
+                let help = MapOption::NBins.help();
+                ui.label(format!("Number of Bins: [{}]\t ", self.num_bins))
+                    .on_hover_text(&help);
+                let te = egui::TextEdit::singleline(&mut self.num_bins_buf).desired_width(40.0);
+                let h = ui.add(te).on_hover_text(help);
+                if h.lost_focus() {
+                    self.commit_buf(MapOption::NBins);
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Bin Edges: ")
+                    .on_hover_text(MapOption::BinEdges.help());
+                egui::ComboBox::from_id_salt("bin_edges")
+                    .selected_text(self.bin_edges.as_str())
+                    .show_ui(ui, |ui| {
+                        for edges in BinEdges::ALL {
+                            ui.selectable_value(&mut self.bin_edges, edges, edges.as_str())
+                                .on_hover_text(edges.describe());
+                        }
+                    });
+            });
+
+            for option in [MapOption::BinWidth, MapOption::BinMin, MapOption::BinMax] {
+                ui.horizontal(|ui| {
+                    let help = option.help();
+                    let (field, value, buf) = self.bin_field(option);
+                    let current = value.map_or("off".to_string(), |v| v.to_string());
+                    ui.label(format!("{field}: [{current}]\t "))
+                        .on_hover_text(&help);
+                    let te = egui::TextEdit::singleline(buf)
+                        .hint_text("off")
+                        .desired_width(60.0);
+                    let h = ui.add(te).on_hover_text(help);
+                    if h.lost_focus() {
+                        self.commit_buf(option);
+                    }
+                });
+            }
+
+            ui.checkbox(
+                &mut self.bin_from_mask,
+                "take the range of the bins from the masked voxels",
+            )
+            .on_hover_text(MapOption::BinFromMask.help());
+
+            ui.checkbox(&mut self.bin_rank, "bin the ranks of the intensities")
+                .on_hover_text(MapOption::BinRank.help());
+
+            ui.horizontal(|ui| {
+                ui.label("Discretization: ")
+                    .on_hover_text(MapOption::Discretization.help());
+                for scope in DiscretizationScope::ALL {
+                    ui.radio_value(&mut self.discretization, scope, scope.as_str());
+                }
+            });
+
+            ui.checkbox(
+                &mut self.rebin_integer_input,
+                "re-bin inputs already quantized to integer levels",
+            )
+            .on_hover_text(MapOption::RebinIntegerInput.help());
+
+            ui.horizontal(|ui| {
+                let help = MapOption::GldmAlpha.help();
+                ui.label(format!("GLDM Alpha: [{}]\t ", self.gldm_alpha))
+                    .on_hover_text(&help);
+                let te = egui::TextEdit::singleline(&mut self.gldm_alpha_buf).desired_width(40.0);
+                let h = ui.add(te).on_hover_text(help);
+                if h.lost_focus() {
+                    self.commit_buf(MapOption::GldmAlpha);
+                }
+            });
+
+            ui.horizontal(|ui| {
+                let help = MapOption::NgldmAlpha.help();
+                ui.label(format!("NGLDM Alpha: [{}]\t ", self.ngldm_alpha))
+                    .on_hover_text(&help);
+                let te = egui::TextEdit::singleline(&mut self.ngldm_alpha_buf).desired_width(40.0);
+                let h = ui.add(te).on_hover_text(help);
+                if h.lost_focus() {
+                    self.commit_buf(MapOption::NgldmAlpha);
+                }
+            });
+
+            ui.horizontal(|ui| {
+                let help = MapOption::NgldmDistance.help();
+                ui.label(format!("NGLDM Distance: [{}]\t ", self.ngldm_distance))
+                    .on_hover_text(&help);
+                let te =
+                    egui::TextEdit::singleline(&mut self.ngldm_distance_buf).desired_width(40.0);
+                let h = ui.add(te).on_hover_text(help);
+                if h.lost_focus() {
+                    self.commit_buf(MapOption::NgldmDistance);
+                }
+            });
+
+            ui.checkbox(
+                &mut self.glcm_per_direction,
+                "also map GLCM features along each direction",
+            )
+            .on_hover_text(MapOption::GlcmPerDirection.help());
+
+            ui.horizontal(|ui| {
+                ui.label("GLCM Anisotropy: ")
+                    .on_hover_text(MapOption::GlcmAnisotropy.help());
+                ui.radio_value(&mut self.glcm_anisotropy, None, "none");
+                for a in Anisotropy::ALL {
+                    ui.radio_value(&mut self.glcm_anisotropy, Some(a), a.as_str());
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label(format!("GLCM Directions: {}\t ", self.glcm_angles))
+                    .on_hover_text(MapOption::GlcmAngles.help());
+                if ui.button("all").clicked() {
+                    self.glcm_angles = Angles::ALL;
+                }
+                if ui
+                    .button("in-plane")
+                    .on_hover_text("the 4 directions within an axial slice")
+                    .clicked()
+                {
+                    self.glcm_angles = Angles::IN_PLANE;
+                }
+            });
+            ui.horizontal_wrapped(|ui| {
+                for (d, [x, y, z]) in DIRECTIONS.iter().enumerate() {
+                    let mut on = self.glcm_angles.contains(d);
+                    if ui
+                        .checkbox(&mut on, format!("{:02}", d + 1))
+                        .on_hover_text(format!("{x},{y},{z} in voxels along x,y,z"))
+                        .changed()
+                    {
+                        self.glcm_angles.set(d, on);
+                    }
+                }
+            });
+
+            ui.horizontal(|ui| {
+                let help = MapOption::GlcmDistance.help();
+                let current: Vec<String> =
+                    self.glcm_distances.iter().map(|d| d.to_string()).collect();
+                ui.label(format!("GLCM Distances: [{}]\t ", current.join(", ")))
+                    .on_hover_text(&help);
+                let te = egui::TextEdit::singleline(&mut self.glcm_distances_buf)
+                    .hint_text("1, 2, 3")
+                    .desired_width(80.0);
+                let h = ui.add(te).on_hover_text(help);
+                if h.lost_focus() {
+                    self.commit_buf(MapOption::GlcmDistance);
+                }
+            });
+
+            if self.glcm_distances.len() > 1 {
+                ui.horizontal(|ui| {
+                    ui.label("Distance Aggregation: ")
+                        .on_hover_text(MapOption::GlcmAggregation.help());
+                    for a in DistanceAggregation::ALL {
+                        ui.radio_value(&mut self.glcm_aggregation, a, a.as_str());
+                    }
+                });
+            }
+
+            ui.horizontal(|ui| {
+                ui.label("Direction Aggregation: ")
+                    .on_hover_text(MapOption::GlcmDirectionAggregation.help());
+                for a in DirectionAggregation::ALL {
+                    ui.radio_value(&mut self.glcm_direction_aggregation, a, a.as_str());
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Distance Weighting: ")
+                    .on_hover_text(MapOption::GlcmWeighting.help());
+                for w in DistanceWeighting::ALL {
+                    ui.radio_value(&mut self.glcm_weighting, w, w.as_str());
+                }
+            });
+
+            ui.horizontal(|ui| {
+                let help = MapOption::GaborFrequency.help();
+                let current: Vec<String> = self
+                    .gabor_frequencies
+                    .iter()
+                    .map(|f| f.to_string())
+                    .collect();
+                ui.label(format!("Gabor Frequencies: [{}]\t ", current.join(", ")))
+                    .on_hover_text(&help);
+                let te = egui::TextEdit::singleline(&mut self.gabor_frequencies_buf)
+                    .hint_text("0.1, 0.25")
+                    .desired_width(80.0);
+                let h = ui.add(te).on_hover_text(help);
+                if h.lost_focus() {
+                    self.commit_buf(MapOption::GaborFrequency);
+                }
+            });
+
+            ui.horizontal(|ui| {
+                let help = MapOption::GaborOrientation.help();
+                let current: Vec<String> = self
+                    .gabor_orientations
+                    .iter()
+                    .map(|o| o.to_string())
+                    .collect();
+                ui.label(format!("Gabor Orientations: [{}]\t ", current.join(" ")))
+                    .on_hover_text(&help);
+                let te = egui::TextEdit::singleline(&mut self.gabor_orientations_buf)
+                    .hint_text("x y 1,1,0")
+                    .desired_width(80.0);
+                let h = ui.add(te).on_hover_text(help);
+                if h.lost_focus() {
+                    self.commit_buf(MapOption::GaborOrientation);
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.gradient, "gradient magnitude")
+                    .on_hover_text(MapOption::Gradient.help());
+                ui.checkbox(&mut self.laplacian, "Laplacian")
+                    .on_hover_text(MapOption::Laplacian.help());
+            });
+
+            ui.horizontal(|ui| {
+                let help = MapOption::LogSigma.help();
+                let current: Vec<String> = self.log_sigmas.iter().map(|s| s.to_string()).collect();
+                ui.label(format!("LoG Sigmas (mm): [{}]\t ", current.join(", ")))
+                    .on_hover_text(&help);
+                let te = egui::TextEdit::singleline(&mut self.log_sigmas_buf)
+                    .hint_text("1.0, 3.0, 5.0")
+                    .desired_width(80.0);
+                let h = ui.add(te).on_hover_text(help);
+                if h.lost_focus() {
+                    self.commit_buf(MapOption::LogSigma);
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Wavelet: ")
+                    .on_hover_text(MapOption::Wavelet.help());
+                ui.radio_value(&mut self.wavelet, None, "none");
+                for kind in WaveletKind::ALL {
+                    ui.radio_value(&mut self.wavelet, Some(kind), kind.as_str());
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Filter Responses: ")
+                    .on_hover_text(MapOption::FilterUse.help());
+                ui.radio_value(&mut self.filter_use, FilterUse::Export, "write as maps");
+                ui.radio_value(&mut self.filter_use, FilterUse::Map, "map features on them");
+            });
+        });
+
+        ui.horizontal(|ui| {
+            let max_workers = self
+                .max_threads
+                .map(|x| x.to_string())
+                .unwrap_or("all available".to_string());
+            let help = MapOption::MaxThreads.help();
+            ui.label(format!("Max Threads: [{max_workers}]\t "))
+                .on_hover_text(&help);
+            let te = egui::TextEdit::singleline(&mut self.max_threads_buf).desired_width(40.0);
+            let h = ui.add(te).on_hover_text(help);
+            if h.lost_focus() {
+                self.commit_buf(MapOption::MaxThreads);
+            }
+        });
+
+        if let Some(err) = &self.parse_error {
+            ui.label(RichText::new(err).color(Color32::RED));
+        }
+
+        let h = ui
+            .checkbox(&mut self.usage_stats_enabled, "keep local usage statistics")
+            .on_hover_text(
+                "Counts runs, features and bucketed volume sizes in a local file only. \
+                 No paths or image data are stored and nothing is sent anywhere. \
+                 Unchecking deletes the collected statistics.",
+            );
+        if h.changed() {
+            self.apply_usage_stats();
+        }
+    }
+}
+
+/// message shown when a whole number field can't be read
+fn parse_error(field: &str, value: &str) -> String {
+    format!("could not read \"{value}\" as a whole number for the {field}")
+}
+
+/****************************
+***** OUTPUT SELECTION ******
+****************************/
+#[derive(Default)]
+pub struct OutputSelector {
+    output_dir_buf: String,
+    output_dir: Option<PathBuf>,
+    //output_dir_dialog: FileDialog,
+    handle: Option<JoinHandle<Result<(), String>>>,
+    is_writing_output: bool,
+    is_complete: bool,
+    /// why the outputs of the last run couldn't be written, for failures that aren't bugs like a
+    /// bad archive password
+    write_error: Option<String>,
+    /// from the launch to the outputs being written, once they are
+    total_time: Option<Duration>,
+    /// allow writing outputs into the directory holding the inputs
+    allow_in_place: bool,
+    /// strip patient identifying strings from output headers and provenance
+    scrub_phi: bool,
+    /// bundle the outputs into an encrypted zip with this password
+    encrypt_outputs: bool,
+    archive_password: String,
+    /// also write a 3D Slicer scene with the inputs and maps preloaded
+    slicer_scene: bool,
+    /// also write the maps stacked into one tensor for deep learning pipelines
+    export_tensor: bool,
+    /// entry of the run being written in the run history
+    history_run: Option<i64>,
+    /// keep a copy of the maps in the user data directory until they are written
+    spill_results: bool,
+    /// runs of earlier sessions whose maps were kept but never written
+    spilled: Vec<SpilledRun>,
+    spilled_hidden: bool,
+    spilled_error: Option<String>,
+}
+
+impl OutputSelector {
+    /// whether the maps are kept in the user data directory until they are written. Never for
+    /// encrypted outputs, the copy would be in the clear
+    fn spills(&self) -> bool {
+        self.spill_results && !self.encrypt_outputs
+    }
+
+    /// whether a 3D Slicer scene is written. Never with patient information removed, the scene
+    /// bundles the inputs as they are
+    fn writes_scene(&self) -> bool {
+        self.slicer_scene && !self.scrub_phi
+    }
+
+    /// the output directory holds one of the inputs and writing there hasn't been allowed
+    fn blocked_in_place(&self, inputs: &InputSelector) -> bool {
+        let Some(output_dir) = &self.output_dir else {
+            return false;
+        };
+        !self.allow_in_place
+            && [&inputs.volume_path, &inputs.mask_path]
+                .into_iter()
+                .flatten()
+                .any(|input| is_in_directory(input, output_dir))
+    }
+
+    fn set_output_dir(&mut self, path: PathBuf) {
+        self.output_dir_buf = path.display().to_string();
+        self.output_dir = Some(path);
+    }
+
+    /// takes the typed output directory, which has to exist
+    fn commit_output_dir_buf(&mut self) {
+        let p = PathBuf::from(&self.output_dir_buf);
+        self.output_dir = p.is_dir().then_some(p);
+    }
+
+    /// takes the directory picked in the dialog, starts writing the maps of a finished run and
+    /// follows the writing
+    pub fn logic(
+        &mut self,
+        file_dialog: &mut FileDialog,
+        input_selector: &InputSelector,
+        features: &FeatureSelector,
+        launcher: &mut GLCMLauncher,
+        progress: &Progress,
+        ctx: &Context,
+    ) {
+        file_dialog.update(ctx);
+        if let Some(path) = file_dialog.take_picked() {
+            self.set_output_dir(path);
+        }
 
-    // try to write output volumes
-    if let Some(output_dir) = &output_selector.output_dir {
-        if let Some(results) = launcher.result.take() {
-            output_selector.is_writing_output = false;
-            output_selector.is_complete = false;
+        // try to write output volumes
+        if let Some(output_dir) = &self.output_dir
+            && let Some(results) = launcher.result.take()
+        {
+            self.is_writing_output = false;
+            self.is_complete = false;
+            self.write_error = None;
 
             let header = launcher.ref_header.take().unwrap();
+            let volume_path = input_selector.volume_path.clone().unwrap();
+            let scrub_phi = self.scrub_phi;
+            let slicer_scene = self.writes_scene();
+            let export_tensor = self.export_tensor.then_some(input_selector.volume_spacing);
+            let mask_path = input_selector.mask_path.clone();
+            let archive_key = self
+                .encrypt_outputs
+                .then(|| ArchiveKey::Password(self.archive_password.clone()));
 
-            let (data, dims) = results;
+            let dims = results.dims();
 
-            let input_path = input_selector.volume_path.as_ref().unwrap().to_path_buf();
-            let file_stem = input_path
-                .file_stem()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_string();
+            let file_stem = input_stem(input_selector.volume_path.as_ref().unwrap());
 
             let feature_aliases = features.features_aliases();
+            let mut provenance = launcher.provenance.take();
+            if let Some(p) = provenance.as_mut() {
+                p.phi_scrubbed = scrub_phi;
+            }
+            let history_run = launcher.history_run.take();
+            self.history_run = history_run;
+            let spill = launcher.spill.take();
             let t_output_dir = output_dir.to_path_buf();
+            let inputs: Vec<PathBuf> = [&input_selector.volume_path, &input_selector.mask_path]
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect();
+            let progress = progress.clone();
+            // encrypted outputs are staged outside the output directory, never in the clear in it
+            let stage_root = archive_key.as_ref().map(|_| archive::stage_root(None));
             let h = std::thread::spawn(move || {
-                let vol_stride: usize = dims.shape_ns()[0..3].iter().product();
-                for (f, alias) in feature_aliases {
-                    let i = f as usize;
-                    let vol = &data[i * vol_stride..(i + 1) * vol_stride];
-                    let path = t_output_dir.join(format!(
-                        "{}{}{}",
-                        file_stem,
-                        "_",
-                        alias.to_lowercase().replace(" ", "_")
-                    ));
-                    let vol_dims = ArrayDim::from_shape(&dims.shape()[0..3]);
-                    match &header {
-                        Header::Nrrd(nhdr) => {
-                            write_nrrd(path, vol, vol_dims, Some(nhdr), false, Encoding::raw)
-                        }
-                        Header::Nifti(nii) => write_nifti_with_header(path, vol, vol_dims, nii),
-                    };
+                let mut staging = Staging::new(&t_output_dir, stage_root.as_deref())
+                    .unwrap_or_else(|e| panic!("{e}"));
+                for input in &inputs {
+                    staging.protect(input);
+                }
+                let header = if scrub_phi {
+                    Arc::new(scrubbed_header(&volume_path).unwrap_or_else(|e| panic!("{e}")))
+                } else {
+                    header
+                };
+                let vol_stride = dims.numel();
+                let maps = results.outputs(&feature_aliases);
+                progress
+                    .bytes
+                    .set_total(maps.len() * vol_stride * size_of::<f32>());
+                progress.features.set_total(maps.len());
+                progress.set_stage(RunStage::Writing);
+                let mut suffixes = vec![];
+                for (suffix, vol) in &maps {
+                    let path = output_path(staging.dir(), &file_stem, suffix);
+                    write_volume(path, vol, dims, &header).unwrap_or_else(|e| panic!("{e}"));
+                    progress.bytes.add(size_of_val(*vol));
+                    progress.on_feature_done(suffix);
+                    suffixes.push(suffix.clone());
+                }
+                if let Some(spacing) = export_tensor {
+                    let shape = [0, 1, 2].map(|d| dims.shape_ns()[d]);
+                    write_tensor(staging.dir(), &file_stem, &maps, shape, spacing)
+                        .unwrap_or_else(|e| panic!("{e}"));
                 }
-                true
+                if slicer_scene {
+                    write_slicer_scene(
+                        staging.dir(),
+                        &file_stem,
+                        &volume_path,
+                        mask_path.as_deref(),
+                        &suffixes,
+                    )
+                    .unwrap_or_else(|e| panic!("{e}"));
+                }
+                if let Some(Err(e)) = provenance
+                    .as_ref()
+                    .map(|p| p.write(staging.dir(), &file_stem))
+                {
+                    eprintln!("{e}");
+                }
+                if let Some(key) = &archive_key
+                    && let Err(e) = encrypt_dir(staging.dir(), &file_stem, key)
+                {
+                    staging.discard();
+                    return Err(e);
+                }
+                let outputs = staging
+                    .commit(&RetryPolicy::default())
+                    .unwrap_or_else(|e| panic!("{e}"));
+                if let (Some(id), Some(p)) = (history_run, &provenance) {
+                    history::finish_run(id, p, &outputs);
+                }
+                if let Some(spill) = spill {
+                    spill.discard();
+                }
+                progress.set_stage(RunStage::Done);
+                Ok(())
             });
-            output_selector.is_writing_output = true;
-            output_selector.handle = Some(h);
+            self.is_writing_output = true;
+            self.handle = Some(h);
         }
-    }
 
-    if let Some(h) = output_selector.handle.take() {
-        if h.is_finished() {
-            output_selector.is_writing_output = false;
-            output_selector.is_complete = true;
-        } else {
-            output_selector.handle = Some(h);
+        if let Some(h) = self.handle.take() {
+            if h.is_finished() {
+                self.is_writing_output = false;
+                // a panic while writing has already been written to a crash report
+                match h.join() {
+                    Ok(Ok(())) => self.is_complete = true,
+                    Ok(Err(e)) => self.write_error = Some(e),
+                    Err(_) => {}
+                }
+                self.total_time = launcher.start.map(|s| s.elapsed());
+                if self.is_complete {
+                    notify_finished(ctx, self.total_time, "finished");
+                } else {
+                    progress.set_stage(RunStage::Idle);
+                    if let Some(id) = self.history_run.take() {
+                        history::fail_run(
+                            id,
+                            FailureKind::WriteFailure,
+                            self.write_error
+                                .as_deref()
+                                .unwrap_or("writing the outputs failed"),
+                        );
+                    }
+                    // the copy of the maps is left behind by the failed write
+                    self.spilled = find_spilled();
+                    self.spilled_hidden = false;
+                    notify_finished(ctx, self.total_time, "failed to write the outputs");
+                }
+            } else {
+                self.handle = Some(h);
+            }
         }
     }
 
-    if output_selector.is_writing_output {
-        ui.label("writing output ...");
-    }
+    pub fn ui(
+        &mut self,
+        file_dialog: &mut FileDialog,
+        input_selector: &InputSelector,
+        ui: &mut Ui,
+    ) {
+        ui.horizontal(|ui| {
+            ui.label("Output Directory:");
+            if self.output_dir.is_some() {
+                ui.label(RichText::new("✅").color(Color32::GREEN));
+            } else {
+                ui.label(RichText::new("x").color(Color32::RED));
+            }
+
+            let h = ui
+                .text_edit_singleline(&mut self.output_dir_buf)
+                .on_hover_text(
+                    "existing directory the maps and the provenance file are written to",
+                );
+
+            if ui.button("browse").clicked() {
+                file_dialog.pick_directory();
+            }
+
+            if h.lost_focus() {
+                self.commit_output_dir_buf();
+            }
+        });
 
-    if output_selector.is_complete {
-        ui.label("writing complete");
+        ui.checkbox(
+            &mut self.allow_in_place,
+            "allow writing into the input folder",
+        )
+        .on_hover_text("maps are written next to the inputs, which can mix them up with the data");
+        ui.checkbox(
+            &mut self.scrub_phi,
+            "remove patient information from outputs",
+        )
+        .on_hover_text(
+            "clears free text header fields and leaves input paths out of the provenance file",
+        );
+        ui.add_enabled(
+            !self.encrypt_outputs,
+            egui::Checkbox::new(
+                &mut self.spill_results,
+                "keep a copy of the maps until they are written",
+            ),
+        )
+        .on_hover_text(
+            "computed maps are saved to the radmap data folder first, so they can be recovered if \
+             writing them fails or radmap closes before they are written",
+        )
+        .on_disabled_hover_text("encrypted outputs are never copied unencrypted");
+        ui.add_enabled(
+            !self.scrub_phi,
+            egui::Checkbox::new(&mut self.slicer_scene, "write a 3D Slicer scene"),
+        )
+        .on_disabled_hover_text("the scene bundles the inputs with their patient information")
+        .on_hover_text("a .mrml scene next to the maps that opens the input, the mask and every map in 3D Slicer");
+        ui.checkbox(&mut self.export_tensor, "write a stacked tensor")
+            .on_hover_text("every map in one float32 .npy of shape (features, z, y, x) with a .json of the channel names, for PyTorch and ONNX pipelines");
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.encrypt_outputs, "encrypt outputs into a zip")
+                .on_hover_text(
+                    "writes every output into a zip archive encrypted with the password",
+                );
+            if self.encrypt_outputs {
+                ui.label("password:");
+                ui.add(egui::TextEdit::singleline(&mut self.archive_password).password(true));
+            }
+        });
+        if self.blocked_in_place(input_selector) {
+            ui.label(
+                RichText::new("the output directory holds an input, choose another one")
+                    .color(Color32::RED),
+            );
+        }
+
+        if self.is_writing_output {
+            ui.label("writing output ...");
+        }
+
+        if let Some(e) = &self.write_error {
+            ui.label(
+                RichText::new(format!("failed to write the outputs: {e}")).color(Color32::RED),
+            );
+        }
+
+        if self.is_complete {
+            ui.label("writing complete");
+            // from the launch, including loading the inputs and writing the outputs
+            if let Some(total) = self.total_time {
+                ui.label(format!("total run time: {}", format_elapsed(total)));
+            }
+        }
     }
 }
 
 /***************************
 ****** DATA SELECTION ******
 ****************************/
+
 pub struct InputSelector {
     /// buffer to hold the volume path ui
     volume_path_buf: String,
@@ -522,69 +3746,108 @@ pub struct InputSelector {
     volume_path: Option<PathBuf>,
     /// validated mask path
     mask_path: Option<PathBuf>,
+    /// voxel spacing of the selected volume in mm, if its header could be read
+    volume_spacing: Option<[f64; 3]>,
+    /// size in voxels of the selected volume, if its header could be read
+    volume_shape: Option<[usize; 3]>,
 
     /// file dialog box objects
     volume_file_dialog: FileDialog,
     mask_file_dialog: FileDialog,
 }
 
-pub fn update_data_loader(data_loader: &mut InputSelector, ctx: &Context, ui: &mut Ui) {
-    ui.horizontal(|ui| {
-        ui.label("Input Volume:");
-        if data_loader.volume_path.is_some() {
-            ui.label(RichText::new("✅").color(Color32::GREEN));
+impl InputSelector {
+    /// selects the volume to map, reading its spacing and shape from its header
+    fn set_volume(&mut self, path: PathBuf) {
+        self.volume_path_buf = path.display().to_string();
+        self.volume_spacing = voxel_spacing(&path);
+        self.volume_shape = volume_shape(&path);
+        self.volume_path = Some(path);
+    }
+
+    /// selects the mask, None to map the whole volume
+    fn set_mask(&mut self, path: Option<PathBuf>) {
+        self.mask_path_buf = path
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_default();
+        self.mask_path = path;
+    }
+
+    /// takes the typed volume path, which has to name a file
+    fn commit_volume_buf(&mut self) {
+        let p = PathBuf::from(&self.volume_path_buf);
+        if p.is_file() {
+            self.set_volume(p);
         } else {
-            ui.label(RichText::new("x").color(Color32::RED));
+            self.volume_path = None;
+            self.volume_spacing = None;
+            self.volume_shape = None;
         }
+    }
 
-        let h = ui.text_edit_singleline(&mut data_loader.volume_path_buf);
+    /// takes the typed mask path, which has to name a file
+    fn commit_mask_buf(&mut self) {
+        let p = PathBuf::from(&self.mask_path_buf);
+        self.mask_path = p.is_file().then_some(p);
+    }
+
+    /// takes the files picked in the dialogs
+    pub fn logic(&mut self, ctx: &Context) {
+        self.volume_file_dialog.update(ctx);
+        self.mask_file_dialog.update(ctx);
 
-        if ui.button("browse").clicked() {
-            data_loader.volume_file_dialog.pick_file();
+        if let Some(path) = self.volume_file_dialog.take_picked() {
+            self.set_volume(path);
         }
 
-        if h.lost_focus() {
-            data_loader.volume_path = None;
-            let p = Path::new(&data_loader.volume_path_buf);
-            if p.exists() {
-                data_loader.volume_path = Some(p.to_path_buf());
-            }
+        if let Some(path) = self.mask_file_dialog.take_picked() {
+            self.set_mask(Some(path));
         }
-    });
+    }
 
-    data_loader.volume_file_dialog.update(ctx);
+    pub fn ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Input Volume:");
+            if self.volume_path.is_some() {
+                ui.label(RichText::new("✅").color(Color32::GREEN));
+            } else {
+                ui.label(RichText::new("x").color(Color32::RED));
+            }
 
-    ui.horizontal(|ui| {
-        ui.label("Input Mask:");
-        if data_loader.mask_path.is_some() {
-            ui.label(RichText::new("✅").color(Color32::GREEN));
-        }
+            let h = ui
+                .text_edit_singleline(&mut self.volume_path_buf)
+                .on_hover_text("nifti or nrrd volume to map, confirmed when the field loses focus");
 
-        let h = ui.text_edit_singleline(&mut data_loader.mask_path_buf);
+            if ui.button("browse").clicked() {
+                self.volume_file_dialog.pick_file();
+            }
 
-        if ui.button("browse").clicked() {
-            data_loader.mask_file_dialog.pick_file();
-        }
+            if h.lost_focus() {
+                self.commit_volume_buf();
+            }
+        });
 
-        if h.lost_focus() {
-            data_loader.mask_path = None;
-            let p = Path::new(&data_loader.mask_path_buf);
-            if p.exists() {
-                data_loader.mask_path = Some(p.to_path_buf());
+        ui.horizontal(|ui| {
+            ui.label("Input Mask:");
+            if self.mask_path.is_some() {
+                ui.label(RichText::new("✅").color(Color32::GREEN));
             }
-        }
-    });
 
-    data_loader.mask_file_dialog.update(ctx);
+            let h = ui
+                .text_edit_singleline(&mut self.mask_path_buf)
+                .on_hover_text(
+                    "optional volume of the same grid, only voxels where it is non-zero are mapped",
+                );
 
-    if let Some(path) = data_loader.volume_file_dialog.take_picked() {
-        data_loader.volume_path_buf = path.display().to_string();
-        data_loader.volume_path = Some(path);
-    }
+            if ui.button("browse").clicked() {
+                self.mask_file_dialog.pick_file();
+            }
 
-    if let Some(path) = data_loader.mask_file_dialog.take_picked() {
-        data_loader.mask_path_buf = path.display().to_string();
-        data_loader.mask_path = Some(path);
+            if h.lost_focus() {
+                self.commit_mask_buf();
+            }
+        });
     }
 }
 
@@ -595,8 +3858,544 @@ impl Default for InputSelector {
             mask_path_buf: String::new(),
             volume_path: None,
             mask_path: None,
+            volume_spacing: None,
+            volume_shape: None,
             volume_file_dialog: FileDialog::new(),
             mask_file_dialog: FileDialog::new(),
         }
     }
 }
+
+/// the logic of the panels, run without drawing them
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// an empty directory of its own under the system temp directory, holding an empty file
+    fn scratch_dir(name: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("radmap_gui_{name}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("volume.txt");
+        std::fs::write(&file, b"").unwrap();
+        (dir, file)
+    }
+
+    /// a run thread that has already returned, or panicked
+    fn finished_run(
+        run: impl FnOnce() -> Option<Computed> + Send + 'static,
+    ) -> JoinHandle<Option<Computed>> {
+        let handle = std::thread::spawn(run);
+        while !handle.is_finished() {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        handle
+    }
+
+    /// one frame of the launcher with a form left as it is on a fresh launch
+    fn launcher_frame(launcher: &mut GLCMLauncher, progress: &mut Progress) {
+        launcher.logic(
+            &mut MapOpts::default(),
+            progress,
+            &MapOptSelector::default(),
+            &FeatureSelector::default(),
+            &InputSelector::default(),
+            &OutputSelector::default(),
+            &Context::default(),
+        );
+    }
+
+    #[test]
+    fn input_paths_have_to_name_files() {
+        let (dir, file) = scratch_dir("input_paths");
+        let mut input = InputSelector {
+            volume_path_buf: dir.join("missing.nii").display().to_string(),
+            ..Default::default()
+        };
+        input.commit_volume_buf();
+        assert_eq!(input.volume_path, None);
+
+        input.volume_path_buf = dir.display().to_string();
+        input.commit_volume_buf();
+        assert_eq!(input.volume_path, None);
+
+        input.volume_path_buf = file.display().to_string();
+        input.commit_volume_buf();
+        assert_eq!(input.volume_path, Some(file.clone()));
+
+        input.mask_path_buf = dir.join("missing.nii").display().to_string();
+        input.commit_mask_buf();
+        assert_eq!(input.mask_path, None);
+
+        input.mask_path_buf = file.display().to_string();
+        input.commit_mask_buf();
+        assert_eq!(input.mask_path, Some(file));
+
+        // an emptied field maps the whole volume
+        input.mask_path_buf.clear();
+        input.commit_mask_buf();
+        assert_eq!(input.mask_path, None);
+    }
+
+    #[test]
+    fn output_dir_has_to_be_a_directory() {
+        let (dir, file) = scratch_dir("output_dir");
+        let mut output = OutputSelector {
+            output_dir_buf: file.display().to_string(),
+            ..Default::default()
+        };
+        output.commit_output_dir_buf();
+        assert_eq!(output.output_dir, None);
+
+        output.output_dir_buf = dir.join("missing").display().to_string();
+        output.commit_output_dir_buf();
+        assert_eq!(output.output_dir, None);
+
+        output.output_dir_buf = dir.display().to_string();
+        output.commit_output_dir_buf();
+        assert_eq!(output.output_dir, Some(dir));
+    }
+
+    #[test]
+    fn output_dir_holding_an_input_blocks_launching() {
+        let (dir, file) = scratch_dir("in_place");
+        let mut input = InputSelector::default();
+        input.set_volume(file);
+        let mut output = OutputSelector::default();
+        output.set_output_dir(dir);
+        let launcher = GLCMLauncher::default();
+
+        assert_eq!(
+            launcher.blocked_by(&input, &output).as_deref(),
+            Some("the output directory holds an input")
+        );
+        output.allow_in_place = true;
+        assert_eq!(launcher.blocked_by(&input, &output), None);
+        output.encrypt_outputs = true;
+        assert_eq!(
+            launcher.blocked_by(&input, &output).as_deref(),
+            Some("the outputs are to be encrypted but there is no password")
+        );
+    }
+
+    #[test]
+    fn encrypted_outputs_are_not_spilled() {
+        let mut output = OutputSelector {
+            spill_results: true,
+            ..Default::default()
+        };
+        assert!(output.spills());
+        output.encrypt_outputs = true;
+        assert!(!output.spills());
+    }
+
+    #[test]
+    fn scrubbed_runs_write_no_scene() {
+        let mut output = OutputSelector {
+            slicer_scene: true,
+            ..Default::default()
+        };
+        assert!(output.writes_scene());
+        output.scrub_phi = true;
+        assert!(!output.writes_scene());
+    }
+
+    #[test]
+    fn options_set_by_name_reach_the_built_options() {
+        let mut opts = MapOptSelector::default();
+        opts.set("n-bins", "64").unwrap();
+        opts.set("kernel-radius", "2").unwrap();
+        opts.set("distance", "3,1").unwrap();
+        opts.set("slice-wise", "true").unwrap();
+        assert!(opts.set("no-such-option", "1").is_err());
+        assert!(opts.set("n-bins", "many").is_err());
+        assert!(opts.set("kernel-radii", "1,1").is_err());
+
+        let (built, texture) = opts.builder(&FeatureSelector::default()).build().unwrap();
+        assert_eq!(built.n_bins, 64);
+        assert_eq!(built.kernel_radius, 2);
+        assert_eq!(texture.glcm_distances, vec![1, 3]);
+        assert!(texture.slice_wise);
+        assert_eq!(texture.kernel_radii, None);
+    }
+
+    #[test]
+    fn a_radius_per_axis_shapes_the_kernel() {
+        let mut opts = MapOptSelector {
+            kernel_radii_buf: "3, 3, 1".to_string(),
+            ..Default::default()
+        };
+        opts.commit_buf(MapOption::KernelRadii);
+        assert_eq!(opts.kernel_radii, Some([3, 3, 1]));
+
+        // the glcm crate only maps cubes, so radmap maps the GLCM features
+        let features = FeatureSelector::default();
+        let (built, texture) = opts.builder(&features).build().unwrap();
+        assert_eq!(built.kernel_radius, 3);
+        assert_eq!(texture.kernel_radius(built.kernel_radius), [3, 3, 1]);
+        assert!(texture.averages_glcm_directions());
+
+        // the same radius along every axis is a cube
+        opts.set("kernel-radii", "2,2,2").unwrap();
+        let (built, texture) = opts.builder(&features).build().unwrap();
+        assert_eq!((built.kernel_radius, texture.kernel_radii), (2, None));
+
+        opts.kernel_radii_buf.clear();
+        opts.commit_buf(MapOption::KernelRadii);
+        assert_eq!(opts.kernel_radii, None);
+    }
+
+    #[test]
+    fn a_sphere_is_measured_with_the_voxel_spacing() {
+        let mut opts = MapOptSelector {
+            sphere_radius_buf: "3".to_string(),
+            ..Default::default()
+        };
+        opts.commit_buf(MapOption::KernelShape);
+        assert_eq!(opts.kernel_shape, KernelShape::Sphere { radius_mm: 3. });
+
+        let features = FeatureSelector::default();
+        let (built, texture) = opts.builder(&features).build().unwrap();
+        let texture = TextureOpts {
+            voxel_spacing: Some([1., 1., 2.5]),
+            ..texture
+        };
+        assert_eq!(texture.kernel_radius(built.kernel_radius), [3, 3, 1]);
+        assert!(texture.averages_glcm_directions());
+
+        // a sphere has no radius per axis of its own
+        opts.set("kernel-radii", "1,1,0").unwrap();
+        assert!(opts.builder(&features).build().is_err());
+        opts.set("kernel-radii", "").unwrap();
+
+        opts.sphere_radius_buf = "-1".to_string();
+        opts.commit_buf(MapOption::KernelShape);
+        assert!(opts.parse_error.is_some());
+        opts.set("kernel-shape", "cube").unwrap();
+        assert_eq!(opts.kernel_shape, KernelShape::Cube);
+    }
+
+    #[test]
+    fn typed_fields_are_read_in_the_number_format_of_the_locale() {
+        let mut opts = MapOptSelector {
+            number_format: NumberFormat::from_locale("de_DE.UTF-8"),
+            ..Default::default()
+        };
+        opts.bin_width_buf = "0,5".to_string();
+        opts.commit_buf(MapOption::BinWidth);
+        assert_eq!(opts.bin_width, Some(0.5));
+        assert_eq!(opts.parse_error, None);
+
+        // an emptied field turns the bin width off
+        opts.bin_width_buf.clear();
+        opts.commit_buf(MapOption::BinWidth);
+        assert_eq!(opts.bin_width, None);
+
+        opts.kernel_radius_buf = "-1".to_string();
+        opts.commit_buf(MapOption::KernelRadius);
+        assert_eq!(opts.kernel_radius, DEFAULT_KERNEL_RADIUS);
+        assert!(opts.parse_error.is_some());
+
+        // a field read after an error clears it
+        opts.kernel_radius_buf = "3".to_string();
+        opts.commit_buf(MapOption::KernelRadius);
+        assert_eq!(opts.kernel_radius, 3);
+        assert_eq!(opts.parse_error, None);
+
+        opts.glcm_distances_buf = "1, 0".to_string();
+        opts.commit_buf(MapOption::GlcmDistance);
+        assert_eq!(opts.glcm_distances, vec![cooccurrence::DEFAULT_DISTANCE]);
+        assert!(opts.parse_error.is_some());
+    }
+
+    #[test]
+    fn invalid_form_leaves_the_options_as_they_were() {
+        let features = FeatureSelector::default();
+        let mut opts = MapOptSelector::default();
+        let mut built = MapOpts::default();
+
+        opts.set("n-bins", "64").unwrap();
+        update_options(&mut built, &opts, &features).unwrap();
+        assert_eq!(built.n_bins, 64);
+
+        opts.set("n-bins", "1").unwrap();
+        assert!(update_options(&mut built, &opts, &features).is_err());
+        assert_eq!(built.n_bins, 64);
+    }
+
+    #[test]
+    fn ranked_bins_ignore_the_scale_of_the_intensities() {
+        let mut opts = MapOptSelector::default();
+        opts.set("bin-rank", "true").unwrap();
+        let vol: [f64; 6] = [1., 10., 100., 1000., 10000., 100000.];
+        let mut binning = opts.binning(3);
+        let levels = binning.fit(&vol, None).unwrap();
+        assert_eq!(levels, [0, 0, 1, 1, 2, 2]);
+        let logs: Vec<f64> = vol.iter().map(|v| v.log10()).collect();
+        assert_eq!(binning.fit(&logs, None).unwrap(), levels);
+
+        // the ranks follow the order of the intensities, not a width or range
+        opts.set("bin-width", "25").unwrap();
+        assert!(opts.binning(3).validate().is_err());
+    }
+
+    #[test]
+    fn directions_can_be_merged_or_maxed() {
+        let mut opts = MapOptSelector::default();
+        opts.set("direction-aggregation", "max").unwrap();
+        assert_eq!(opts.glcm_direction_aggregation, DirectionAggregation::Max);
+
+        // only radmap sums the directions up other than by their mean
+        let features = FeatureSelector::default();
+        let (_, texture) = opts.builder(&features).build().unwrap();
+        assert!(texture.averages_glcm_directions());
+        assert_eq!(
+            DirectionAggregation::Max.combine([1., f64::NAN, 3., 2.]),
+            3.
+        );
+
+        // a merged matrix has no directions left to map one by one
+        opts.set("direction-aggregation", "merged").unwrap();
+        opts.glcm_per_direction = true;
+        assert!(opts.builder(&features).build().is_err());
+    }
+
+    #[test]
+    fn distance_weighting_needs_a_merged_matrix() {
+        let mut opts = MapOptSelector::default();
+        opts.set("distance-weighting", "inverse-square").unwrap();
+        assert_eq!(opts.glcm_weighting, DistanceWeighting::InverseSquare);
+
+        // a matrix along one direction at one distance is normalized, weights cancel out
+        let features = FeatureSelector::default();
+        assert!(opts.builder(&features).build().is_err());
+        opts.set("direction-aggregation", "merged").unwrap();
+        let (_, texture) = opts.builder(&features).build().unwrap();
+        assert_eq!(texture.glcm_weighting, DistanceWeighting::InverseSquare);
+
+        // measured in mm, so a step across thick slices counts less
+        let w = |offset| texture.glcm_weighting.weight(offset, [1., 1., 2.]);
+        assert_eq!(w([1, 0, 0]), 1.);
+        assert_eq!(w([0, 0, 1]), 0.25);
+        assert_eq!(w([1, 1, 0]), 0.5);
+    }
+
+    #[test]
+    fn launch_requested_without_paths_is_refused() {
+        let mut launcher = GLCMLauncher {
+            launch_requested: true,
+            ..Default::default()
+        };
+        launcher_frame(&mut launcher, &mut Progress::default());
+        assert_eq!(
+            launcher.refused.as_deref(),
+            Some("choose an input volume and an output directory first")
+        );
+        assert!(!launcher.launch_requested);
+        assert!(!launcher.is_running);
+        assert!(launcher.handle.is_none());
+    }
+
+    #[test]
+    fn cancelled_run_stops_running() {
+        let mut progress = Progress::default();
+        progress.set_stage(RunStage::Computing);
+        let mut launcher = GLCMLauncher {
+            handle: Some(finished_run(|| None)),
+            is_running: true,
+            start: Some(Instant::now()),
+            ..Default::default()
+        };
+        launcher_frame(&mut launcher, &mut progress);
+        assert!(!launcher.is_running);
+        assert!(launcher.cancelled);
+        assert!(!launcher.failed && !launcher.succeeded);
+        assert!(launcher.elapsed.is_some());
+        assert_eq!(progress.stage(), RunStage::Idle);
+    }
+
+    #[test]
+    fn panicked_run_fails() {
+        let mut progress = Progress::default();
+        progress.set_stage(RunStage::Computing);
+        let mut launcher = GLCMLauncher {
+            handle: Some(finished_run(|| panic!("the run crashed"))),
+            is_running: true,
+            ..Default::default()
+        };
+        launcher_frame(&mut launcher, &mut progress);
+        assert!(!launcher.is_running);
+        assert!(launcher.failed);
+        assert!(!launcher.cancelled && !launcher.succeeded);
+        assert_eq!(progress.stage(), RunStage::Idle);
+    }
+
+    #[test]
+    fn running_run_is_left_running() {
+        let (tx, rx) = channel::<()>();
+        let mut launcher = GLCMLauncher {
+            handle: Some(std::thread::spawn(move || {
+                let _ = rx.recv();
+                None
+            })),
+            is_running: true,
+            ..Default::default()
+        };
+        launcher_frame(&mut launcher, &mut Progress::default());
+        assert!(launcher.is_running);
+        assert!(launcher.handle.is_some());
+        drop(tx);
+    }
+}
+
+/// snapshots of the panels, drawn headless. A panel that lost a widget or changed its layout
+/// fails against the images in `tests/snapshots`, which are written again with
+/// `UPDATE_SNAPSHOTS=1 cargo test --features gui-snapshots` once the change is intended.
+///
+/// Taking a snapshot needs a wgpu adapter, a software one such as llvmpipe will do, so the tests
+/// taking them are only built with the `gui-snapshots` feature. Machines and CI jobs that can
+/// render run `cargo test --features gui-snapshots`. The checks of what the panels do run
+/// everywhere
+#[cfg(test)]
+mod snapshot_tests {
+    use super::*;
+    use egui_kittest::kittest::Queryable;
+    use egui_kittest::Harness;
+
+    /// the form as it is on a fresh launch, without the locale or saved settings of the machine
+    fn map_opts() -> MapOptSelector {
+        MapOptSelector {
+            usage_stats_enabled: false,
+            number_format: NumberFormat::default(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "gui-snapshots")]
+    fn data_loader() {
+        let mut harness = Harness::new_ui_state(
+            |ui, loader: &mut InputSelector| loader.ui(ui),
+            InputSelector::default(),
+        );
+        harness.fit_contents();
+        harness.get_by_label("Input Volume:");
+        harness.get_by_label("Input Mask:");
+        harness.snapshot("data_loader");
+    }
+
+    #[test]
+    #[cfg(feature = "gui-snapshots")]
+    fn output_selector() {
+        struct Panel {
+            output: OutputSelector,
+            dialog: FileDialog,
+            input: InputSelector,
+        }
+        let mut harness = Harness::new_ui_state(
+            |ui, p: &mut Panel| p.output.ui(&mut p.dialog, &p.input, ui),
+            Panel {
+                output: OutputSelector::default(),
+                dialog: FileDialog::new(),
+                input: InputSelector::default(),
+            },
+        );
+        harness.fit_contents();
+        harness.get_by_label("Output Directory:");
+        harness.snapshot("output_selector");
+    }
+
+    #[test]
+    #[cfg(feature = "gui-snapshots")]
+    fn map_options() {
+        let mut harness = Harness::new_ui_state(
+            |ui, opts: &mut MapOptSelector| opts.ui(Some([1., 1., 1.]), false, ui),
+            map_opts(),
+        );
+        harness.fit_contents();
+        harness.get_by_label_contains("Kernel Radius: [1]");
+        harness.get_by_label_contains("Number of Bins: [32]");
+        harness.get_by_label_contains("GLCM Distances: [1]");
+        harness.get_by_label_contains("Max Threads: [all available]");
+        harness.snapshot("map_options");
+    }
+
+    #[test]
+    #[cfg(feature = "gui-snapshots")]
+    fn map_options_locked() {
+        let mut harness = Harness::new_ui_state(
+            |ui, opts: &mut MapOptSelector| opts.ui(None, true, ui),
+            map_opts(),
+        );
+        harness.fit_contents();
+        harness.snapshot("map_options_locked");
+    }
+
+    #[test]
+    #[cfg(feature = "gui-snapshots")]
+    fn feature_selector() {
+        let opts = map_opts();
+        let mut harness = Harness::new_ui_state(
+            |ui, features: &mut FeatureSelector| features.ui(&opts, None, ui),
+            FeatureSelector::default(),
+        );
+        harness.fit_contents();
+        harness.snapshot("feature_selector");
+    }
+
+    #[test]
+    fn feature_selector_selects_all() {
+        let opts = map_opts();
+        let mut harness = Harness::new_ui_state(
+            |ui, features: &mut FeatureSelector| features.ui(&opts, None, ui),
+            FeatureSelector::default(),
+        );
+        harness.get_by_label("deselect all").click();
+        harness.run();
+        assert!(harness.state().selected_features.is_empty());
+        harness.get_by_label("select all").click();
+        harness.run();
+        assert_eq!(
+            harness.state().selected_features.len(),
+            Feature::all().len()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "gui-snapshots")]
+    fn progress() {
+        let progress = Progress::default();
+        progress.set_stage(RunStage::Computing);
+        progress.voxels.set_total(800);
+        progress.voxels.set(200);
+        let mut harness = Harness::new_ui_state(
+            |ui, progress: &mut Progress| update_progress(progress, ui),
+            progress,
+        );
+        harness.fit_contents();
+        harness.get_by_label(RunStage::Computing.label());
+        harness.get_by_label("200 / 800 voxels");
+        harness.snapshot("progress_computing");
+
+        let progress = harness.state_mut();
+        progress.set_stage(RunStage::Writing);
+        progress.bytes.set_total(400);
+        progress.bytes.set(100);
+        progress.features.set_total(4);
+        progress.features.set(1);
+        harness.run();
+        harness.fit_contents();
+        harness.get_by_label(RunStage::Writing.label());
+        harness.snapshot("progress_writing");
+    }
+
+    #[test]
+    fn progress_idle_draws_nothing() {
+        let mut harness = Harness::new_ui_state(
+            |ui, progress: &mut Progress| update_progress(progress, ui),
+            Progress::default(),
+        );
+        harness.run();
+        assert!(harness.query_by_label(RunStage::Idle.label()).is_none());
+    }
+}
@@ -1,56 +1,289 @@
+use std::ffi::{OsStr, OsString};
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
 use std::str::FromStr;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicUsize, Ordering};
 use std::thread;
 use std::time::{Duration, Instant};
-use array_lib::{io_nifti, io_nrrd, ArrayDim};
-use array_lib::io_nifti::{write_nifti_with_header, NiftiHeader};
-use array_lib::io_nrrd::{write_nrrd, Encoding, NRRD};
-use indicatif::{ProgressBar, ProgressStyle};
-use clap::Parser;
-use glcm::core::GLCMFeature;
-use glcm::run_glcm_map;
+use array_lib::ArrayDim;
 use glcm::ui::MapOpts;
-use strum::IntoEnumIterator;
+use indicatif::{ProgressBar, ProgressStyle};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use rayon::prelude::*;
 use rayon::current_num_threads;
+use radmap::crash;
+use radmap::failure::{self, FailureKind, FailureSummary, Stage};
+use radmap::cache::DiscretizedCache;
+use radmap::checks::{self, edge_voxels, non_finite_voxels, orientation_mismatch, Check};
+use radmap::discretize::{integer_levels, integer_levels_rebinned, integer_levels_warning, level_indices, levels_to_f64, BinEdges, Binning, DiscretizationScope};
+use radmap::archive::{self, encrypt_dir, ArchiveKey};
+use radmap::config::config_args;
+use radmap::container::{self, find_cases, log_event};
+use radmap::correlation::Correlation;
+use radmap::dicom::{write_parametric_map, SourceSeries};
+use radmap::filter::{FilterOpts, FilterUse, FilteredImage, ImageFilter};
+use radmap::filter::gabor::Orientation;
+use radmap::filter::wavelet::{SubBand, WaveletKind};
+use radmap::diff::{self, RunSettings};
+use radmap::discrimination::{feature_map_files, Ranking, MI_BINS};
+use radmap::preview::Preview;
+use radmap::mapper::{estimated_runtime, map_features, n_passes, FeatureMaps};
+use radmap::options::{parse_kernel_radii, MapOptsBuilder, DEFAULT_KERNEL_RADIUS, DEFAULT_N_BINS};
+use radmap::patches::{write_patches, PatchGrid};
+use radmap::preset::Preset;
+use radmap::texture::cooccurrence::{Angles, Anisotropy, DirectionAggregation, DistanceAggregation, DistanceWeighting, DEFAULT_DISTANCE};
+use radmap::texture::{selected_features, Family, Feature, KernelShape, TextureOpts};
+use radmap::texture::catalog::{catalog, FeatureInfo};
+use radmap::progress::{Progress, ProgressSink, RunStage};
+use radmap::protocol::{self, Protocol};
+use radmap::provenance::{find_previous_runs, find_up_to_date_run, OptionsTag, PreviousRun, Provenance};
+use radmap::queue;
+use radmap::scene::write_slicer_scene;
+use radmap::shape::ShapeReport;
+use radmap::study::StudyDb;
+use radmap::table::{write_voxel_table, TableFormat};
+use radmap::tensor::write_tensor;
+use radmap::xnat::{convert_dicom, XnatClient, XnatSession};
+use radmap::grpc::{self, ServeOpts};
+use radmap::jobs::{progress_line, JobRunner};
+use radmap::workflow::{self, WorkflowKind, WorkflowParams};
+use radmap::staging::{RetryPolicy, Staging};
+use radmap::wizard::{shell_quote, Wizard};
+use radmap::io::{format_names, input_stem, is_in_directory, is_volume, output_path, read_header, read_volume, scrubbed_header, try_read_volume, voxel_to_lps, write_volume, Header};
+use radmap::header::{describe_kernel, volume_shape, voxel_spacing};
+use radmap::history::{self, History, HistoryQuery, RunRecord, RunStatus};
+use radmap::locale::{format_count, format_duration, format_utc, parse_decimal_separator, DateFormat, Delimiter, Quoting, ReportFormat};
+use radmap::usage::{self, UsageStats};
+use radmap::batch::{parse_duration, read_cases, BatchManifest, Case, CaseOutcome, CaseStatus, Watchdog};
+use radmap::memory::{format_bytes, parse_bytes, peak_rss, MemoryTracker};
+use radmap::pipeline::{self, CaseStages};
 
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Args {
+    #[command(subcommand)]
+    command: Option<Cmd>,
+
     /// input volume to generate feature maps from
-    #[arg(required_unless_present = "list_features")]
+    #[arg(required_unless_present_any = ["list_features", "batch", "usage_stats", "export_usage_stats", "save_preset"])]
     input_vol: Option<PathBuf>,
 
     /// output directory to write results
-    #[arg(required_unless_present = "list_features")]
+    #[arg(required_unless_present_any = ["list_features", "batch", "usage_stats", "export_usage_stats", "save_preset"])]
     output_dir: Option<PathBuf>,
 
     /// optional mask to limit number of voxels to accelerate calculations
     #[clap(short, long)]
     mask: Option<PathBuf>,
 
-    /// list all glcm features for reference
+    /// list the features of every family for reference, with the cost of each family relative
+    /// to the cheapest at the given kernel radius and bin count. `radmap features` describes them
     #[clap(short, long)]
     list_features: bool,
 
-    /// number of bins for the GLCM, 32 bins is default
-    #[clap(short, long)]
+    /// take the options and features from a preset file written by --save-preset. Options
+    /// given on the command line take precedence, and features selected on the command line
+    /// replace those of the preset
+    #[clap(long)]
+    preset: Option<PathBuf>,
+
+    /// write the options and features given on the command line to a preset file and exit
+    #[clap(long)]
+    save_preset: Option<PathBuf>,
+
+    /// number of bins for the GLCM, between 2 and 65536. 32 bins is default
+    #[clap(short = 'b', long)]
     n_bins: Option<usize>,
 
-    /// determines the shell of voxels considered to be neighbors. Default is 1
+    /// which bin a value exactly on a bin edge goes to: `half-open` bins are [lower, upper) and
+    /// put the maximum in the top bin, `inclusive` bins are (lower, upper] and put the minimum in
+    /// the bottom bin. The convention used is recorded in the provenance sidecar. Default is
+    /// half-open
+    #[clap(long, value_parser = BinEdges::from_str)]
+    bin_edges: Option<BinEdges>,
+
+    /// width of the bins in the units of the input, instead of a number of bins. Gray levels are
+    /// counted from --bin-min and the number of bins follows from the range of the input
+    #[clap(long, conflicts_with = "n_bins")]
+    bin_width: Option<f64>,
+
+    /// lower end of the intensities binned, e.g. -100 HU, so the bins are the same for every
+    /// input. Lower intensities go to the first bin. Default is the minimum of the input
+    #[clap(long, allow_negative_numbers = true)]
+    bin_min: Option<f64>,
+
+    /// upper end of the intensities binned, e.g. 300 HU. Higher intensities go to the last bin.
+    /// Default is the maximum of the input
+    #[clap(long, allow_negative_numbers = true)]
+    bin_max: Option<f64>,
+
+    /// take the range of the bins from the voxels inside --mask only, so background like the air
+    /// around a CT scan doesn't take up most of the bins
+    #[clap(long)]
+    bin_from_mask: bool,
+
+    /// bin the ranks of the intensities among the voxels, inside --mask with --bin-from-mask, in
+    /// place of the intensities, so every bin holds about as many voxels whatever their scale.
+    /// For inputs with no calibrated units like raw MRI. Can't be used with a bin width or range
+    #[clap(long, conflicts_with_all = ["bin_width", "bin_min", "bin_max"])]
+    bin_rank: bool,
+
+    /// `global` bins the whole input once, `local` bins the intensities of each kernel on their
+    /// own between the minimum and maximum of the kernel. Local bins can't be combined with a bin
+    /// width or range, --bin-from-mask, --bin-rank or inclusive --bin-edges. Default is global
+    #[clap(long, value_parser = DiscretizationScope::from_str)]
+    discretization: Option<DiscretizationScope>,
+
+    /// determines the shell of voxels considered to be neighbors, at least 1. Default is 1
     #[clap(short, long)]
-    kernel_radius: Option<i32>,
+    kernel_radius: Option<usize>,
+
+    /// a radius along x, y and z in place of --kernel-radius, for voxels far from cubic, e.g.
+    /// `3,3,1` on 0.5 x 0.5 x 1.5 mm voxels or `1,1,0` for 3x3x1 kernels within each slice. A
+    /// radius may be 0 but not along every axis. Unless it is the same along every axis, GLCM
+    /// features are then mapped by radmap like with --angles
+    #[clap(long, value_parser = parse_kernel_radii)]
+    kernel_radii: Option<[usize; 3]>,
 
-    /// include all features. If `omit` is specified for some features, they will be
-    /// removed from the collection
+    /// `cube` for the voxels within --kernel-radius along each axis, or `sphere:<mm>`, e.g.
+    /// `sphere:4.5`, for the voxels whose centres lie within that many mm of the voxel mapped,
+    /// measured with the voxel spacing of the header. A sphere keeps kernels the same size in mm
+    /// along every axis, and GLCM features are then mapped by radmap like with --angles
+    #[clap(long, value_parser = KernelShape::from_str, conflicts_with = "kernel_radii")]
+    kernel_shape: Option<KernelShape>,
+
+    /// include all GLCM features. If `omit` is specified for some features, they will be
+    /// removed from the collection. Other families are added with --family
     #[clap(short, long)]
     all_features: bool,
 
-    /// supply a single feature to include (multiple can be included with additional -f flags)
+    /// supply a single feature to include (multiple can be included with additional -f flags).
+    /// Features of families other than GLCM are prefixed with the family, e.g.
+    /// `glrlm_short_run_emphasis`
     #[clap(short, long)]
     feature: Vec<String>,
 
+    /// include every feature of a family (glcm, glrlm, glszm, gldzm, ngtdm, gldm, ngldm, firstorder, percentile, lbp, fractal, haralick). Can be given more than once and combined
+    /// with --feature and --omit
+    #[clap(long)]
+    family: Vec<String>,
+
+    /// largest gray level difference for which neighbouring voxels count as dependent in the
+    /// GLDM features, less than the number of bins. Default is 0
+    #[clap(long)]
+    gldm_alpha: Option<usize>,
+
+    /// coarseness of the NGLDM features: the largest gray level difference for which neighbouring
+    /// voxels count as dependent, less than the number of bins. Default is 0
+    #[clap(long)]
+    ngldm_alpha: Option<usize>,
+
+    /// how many steps along the 26 neighbour directions the neighbourhood of a voxel reaches for
+    /// the NGLDM features, cut to the kernel. Default is 1
+    #[clap(long)]
+    ngldm_distance: Option<usize>,
+
+    /// also map each selected GLCM feature along each of the 13 directions on its own, written
+    /// as `<input>_<feature>_dir01` to `_dir13`. Directions are, in voxels along x,y,z: 1,0,0
+    /// 0,1,0 1,1,0 1,-1,0 0,0,1 1,0,1 1,0,-1 0,1,1 0,1,-1 1,1,1 1,1,-1 1,-1,1 1,-1,-1
+    #[clap(long)]
+    glcm_per_direction: bool,
+
+    /// also map how much each selected GLCM feature changes with direction, as the variance or
+    /// the range (largest less smallest) of its values along the 13 directions, written as
+    /// `<input>_<feature>_anisotropy_variance` or `_anisotropy_range`
+    #[clap(long, value_parser = Anisotropy::from_str)]
+    glcm_anisotropy: Option<Anisotropy>,
+
+    /// directions the co-occurrence matrices of the GLCM and Haralick features are counted
+    /// along: `all`, `in-plane` for the 4 directions within an axial slice, or direction numbers
+    /// as in --glcm-per-direction, e.g. `1,2,5`. GLCM features are then mapped by radmap as the
+    /// average over these directions, so only those --glcm-per-direction supports can be used.
+    /// Default is all
+    #[clap(long, value_parser = Angles::from_str)]
+    angles: Option<Angles>,
+
+    /// how many steps apart along each direction the voxels paired in the co-occurrence matrices
+    /// of the GLCM and Haralick features are, at most twice the kernel radius. Several distances
+    /// like `1,2,3` are combined as --distance-aggregation says. Like --angles, a distance other
+    /// than 1 has radmap map the GLCM features itself, and their maps are named after the
+    /// distances, e.g. `<input>_contrast_d2` or `<input>_contrast_d1-2-3_mean`. Default is 1
+    #[clap(long, value_delimiter = ',')]
+    distance: Vec<usize>,
+
+    /// `mean` averages the features of the co-occurrence matrix at each of several --distance,
+    /// `merged` takes them from one matrix of the pairs at every distance. Default is mean
+    #[clap(long, value_parser = DistanceAggregation::from_str)]
+    distance_aggregation: Option<DistanceAggregation>,
+
+    /// how the co-occurrence matrices along the directions are summed up in the maps of the GLCM
+    /// and Haralick features: `mean` averages the features of the matrix along each direction,
+    /// `merged` takes them from one matrix of the pairs along every direction of --angles and
+    /// `max` keeps the largest over the directions. Like --angles, anything but the mean has
+    /// radmap map the GLCM features itself. Default is mean
+    #[clap(long, value_parser = DirectionAggregation::from_str)]
+    direction_aggregation: Option<DirectionAggregation>,
+
+    /// weigh the pairs of voxels pooled in a merged co-occurrence matrix by the distance between
+    /// them in mm: `inverse` counts a pair one over the distance, `inverse-square` one over its
+    /// square, `manhattan` one over the sum of its lengths along each axis and `gaussian`
+    /// exp(-d²), like pyradiomics' weightingNorm. Needs `--direction-aggregation merged`, or
+    /// several --distance with `--distance-aggregation merged`. Default is none
+    #[clap(long, value_parser = DistanceWeighting::from_str)]
+    distance_weighting: Option<DistanceWeighting>,
+
+    /// 2D mode for thick slice acquisitions: keep every kernel within the axial slice of its
+    /// voxel, so neighbours are only found in-plane. Co-occurrence matrices are counted along the
+    /// in-plane directions among --angles, and like them GLCM features are then mapped by radmap
+    #[clap(long, visible_alias = "2d")]
+    slice_wise: bool,
+
+    /// frequency of a bank of Gabor filters in cycles per voxel, above 0 and at most 0.5. One
+    /// filter is made per frequency and --gabor-orientation, and its response magnitude is
+    /// written as `<input>_gabor_f<frequency>_<orientation>`, or mapped with --filter-use map
+    #[clap(long, value_delimiter = ',')]
+    gabor_frequency: Vec<f64>,
+
+    /// direction of the Gabor filters, an axis (x, y or z) or a vector in voxels like 1,1,0.
+    /// Repeat for several directions. Default is x, y and z
+    #[clap(long, value_parser = Orientation::from_str)]
+    gabor_orientation: Vec<Orientation>,
+
+    /// filter the input with the magnitude of its Sobel gradient, in intensity per unit of the
+    /// voxel spacing, written as `<input>_gradient_magnitude` or mapped with --filter-use map
+    #[clap(long)]
+    gradient: bool,
+
+    /// filter the input with its Laplacian, in intensity per unit of the voxel spacing squared,
+    /// written as `<input>_laplacian` or mapped with --filter-use map
+    #[clap(long)]
+    laplacian: bool,
+
+    /// widths of Laplacian of Gaussian filters in the units of the voxel spacing, usually mm, comma
+    /// delimited like 1.0,3.0,5.0. The response at each sigma is written as
+    /// `<input>_log_sigma<sigma>mm`, or mapped with --filter-use map
+    #[clap(long, value_delimiter = ',')]
+    log_sigma: Vec<f64>,
+
+    /// filter the input into the sub-bands of its stationary wavelet transform at one level with
+    /// this wavelet (haar, db2 or coif1), like the wavelet images of pyradiomics. Each sub-band is
+    /// written as `<input>_wavelet_<wavelet>_<band>`, e.g. `wavelet_coif1_llh` for low pass along
+    /// x and y and high pass along z, or mapped with --filter-use map
+    #[clap(long, value_parser = WaveletKind::from_str)]
+    wavelet: Option<WaveletKind>,
+
+    /// sub-bands of the wavelet transform to keep, comma delimited like llh,hhh. Default is all 8
+    #[clap(long, requires = "wavelet", value_delimiter = ',', value_parser = SubBand::from_str)]
+    wavelet_band: Vec<SubBand>,
+
+    /// what becomes of the filter responses. export writes them as maps of their own, map maps
+    /// the selected features on each of them like on the input, written as
+    /// `<input>_<filter>_<feature>`. Default is export
+    #[clap(long, value_parser = FilterUse::from_str)]
+    filter_use: Option<FilterUse>,
+
     /// supply a single feature to omit from calculations (multiple can be omitted with additional --omit flags)
     #[clap(long)]
     omit: Vec<String>,
@@ -60,31 +293,1127 @@ pub struct Args {
     max_threads:Option<usize>,
 
     /// disable printing the progress bar
-    #[clap(long,short)]
+    #[clap(short = 'n', long)]
     no_progress_bar: bool,
 
+    /// print progress as json lines instead of the progress bar, e.g.
+    /// `{"event": "progress", "voxels_done": 10, "voxels_total": 24}`
+    #[clap(long)]
+    json_progress: bool,
+
+    /// directory to cache discretized volumes in, keyed by the input file and number of bins.
+    /// Later runs on the same input and bins skip loading and binning it, e.g. when sweeping
+    /// features or kernel sizes. Runs with first-order, percentile, LBP or fractal features read
+    /// the input as usual, as those are of its intensities
+    #[clap(long)]
+    discretized_cache: Option<PathBuf>,
+
+    /// bin inputs that are already quantized to a few integer levels (at most 64 distinct values,
+    /// like label maps or gray levels binned by another tool) like any other input. By default
+    /// their levels are used as the bins, unless --n-bins is given
+    #[clap(long)]
+    rebin_integer_input: bool,
+
+    /// write outputs to this (local) directory first and move them to the output directory once
+    /// they are all complete. Useful when the output directory is on a network share. By default
+    /// outputs are staged in a hidden directory inside the output directory, or in the temp
+    /// directory when they are encrypted
+    #[clap(long)]
+    stage_outputs: Option<PathBuf>,
+
+    /// tag the outputs with the start of the options hash, so maps of different options can
+    /// share an output directory: `name` writes `<input>_<hash>_<feature>`, `dir` writes into a
+    /// `<hash>` directory inside the output directory. --incremental then looks for the outputs
+    /// of the same options only
+    #[clap(long, value_parser = OptionsTag::from_str)]
+    options_tag: Option<OptionsTag>,
+
+    /// how many times to retry moving an output into the output directory, with increasing
+    /// waits in between
+    #[clap(long, default_value_t = 3)]
+    write_retries: u32,
+
+    /// allow writing outputs into the directory holding the input volume or mask. Inputs are
+    /// never overwritten either way
+    #[clap(long)]
+    allow_in_place: bool,
+
+    /// strip patient identifying strings from the output headers (nifti description, aux file and
+    /// intent name, nrrd content and key/value pairs) and leave the input paths out of the
+    /// provenance sidecar, for sharing maps outside the institution. Output file names are still
+    /// derived from the input file name
+    #[clap(long)]
+    scrub_phi: bool,
+
+    /// bundle the outputs of each case into an AES-256 encrypted `<input>_outputs.zip`, using
+    /// the password held in this environment variable. The unencrypted maps are not kept
+    #[clap(long, conflicts_with = "archive_keyfile")]
+    archive_password_env: Option<String>,
+
+    /// like --archive-password-env, with the password read from this text file
+    #[clap(long)]
+    archive_keyfile: Option<PathBuf>,
+
+    /// directory of the DICOM series the input was converted from. Each feature map is also
+    /// written as a DICOM Parametric Map (`<input>_<feature>.dcm`) referencing that series, for
+    /// pushing results to PACS. Not with --batch, as every case has a series of its own
+    #[clap(long, conflicts_with = "scrub_phi")]
+    dicom_source: Option<PathBuf>,
+
+    /// also write a 3D Slicer scene (`<input>_scene.mrml`) with the input, mask and feature maps
+    /// preloaded. Single file inputs are copied next to the scene as they are, so it can't be
+    /// written with --scrub-phi
+    #[clap(long, conflicts_with = "scrub_phi")]
+    slicer_scene: bool,
+
+    /// also write every map stacked into one float32 tensor `<input>_tensor.npy` of shape
+    /// (features, z, y, x), with the channel names in `<input>_tensor.json`, for PyTorch and ONNX
+    /// pipelines
+    #[clap(long)]
+    export_tensor: bool,
+
+    /// also write a table with a row per voxel of the mask, or of the volume without one, giving
+    /// its position x, y and z and a column per map: csv as `<input>_voxels.csv`, or arrow for an
+    /// Arrow IPC (Feather v2) file `<input>_voxels.arrow` that pandas, polars and pyarrow
+    /// memory-map instead of parsing millions of csv rows
+    #[clap(long, value_parser = TableFormat::from_str)]
+    voxel_table: Option<TableFormat>,
+
+    /// append the statistics of every map within the mask (voxels, min, max, mean and standard
+    /// deviation) to this SQLite study database as the run finishes, creating it if needed. The
+    /// cases of a batch can share one file, to query the whole cohort from its case_features view
+    #[clap(long)]
+    study_db: Option<PathBuf>,
+
+    /// also cut the input, mask and maps into aligned cubic patches of this many voxels a side,
+    /// written as `<input>_patch_<n>.npz` with an index `<input>_patches.csv` and the channel
+    /// names in `<input>_patches.json`. Patches without any voxel of the mask are left out
+    #[clap(long)]
+    export_patches: Option<usize>,
+
+    /// voxels between the starts of neighbouring patches of --export-patches. Default is the
+    /// patch size, for patches that don't overlap
+    #[clap(long, requires = "export_patches")]
+    stride: Option<usize>,
+
+    /// after the run, also write the correlations between the feature maps within the mask as
+    /// `<input>_correlation.csv` and a heatmap `<input>_correlation.png` (blue -1, white 0, red 1,
+    /// in the order of the csv) and list the pairs of near duplicate features
+    #[clap(long)]
+    correlation_report: bool,
+
+    /// smallest absolute correlation for which --correlation-report lists two features as near
+    /// duplicates
+    #[clap(long, default_value_t = 0.95, requires = "correlation_report")]
+    redundant_above: f64,
+
+    /// abort on any warning about the inputs (NaN voxels, an empty mask, a mask placed or oriented
+    /// differently from the input, kernels cut off by the edge of the volume) instead of carrying
+    /// on, for pipelines that should fail fast. The exit code is that of the input at fault
+    #[clap(long)]
+    strict: bool,
+
+    /// abort with exit code 8 once at least this fraction (0 to 1) of the values of any feature are
+    /// NaN, or a feature has the same value everywhere, e.g. from a misaligned mask or a single
+    /// bin. Checked on blocks sampled across the volume, mapped next to the full volume
+    #[clap(long)]
+    abort_if_degenerate: Option<f64>,
+
+    /// thousands of sampled voxels to map before --abort-if-degenerate decides. Small volumes
+    /// and masks are decided on every sampled voxel
+    #[clap(long, default_value_t = 5, requires = "abort_if_degenerate")]
+    degenerate_after: usize,
+
+    /// csv file of cases to process one after the other, one `input_vol,output_dir[,mask]` per line.
+    /// All other options are applied to every case
+    #[clap(long, conflicts_with_all = ["input_vol", "output_dir", "mask"])]
+    batch: Option<PathBuf>,
+
+    /// give up on a batch case after this long (e.g. 2h, 45m, 1h30m) and move on to the next one
+    #[clap(long, requires = "batch", value_parser = parse_duration)]
+    timeout_per_case: Option<Duration>,
+
+    /// kill a batch case once its resident memory grows past this size (e.g. 16G). Only enforced on linux
+    #[clap(long, requires = "batch", value_parser = parse_bytes)]
+    memory_budget: Option<u64>,
+
+    /// when a batch case runs out of memory, retry it with half as many worker threads until it
+    /// fits or is down to a single thread. Only the per-thread working memory shrinks, the volume
+    /// and its maps take as much memory as before, so a case whose maps alone don't fit still fails
+    #[clap(long, requires = "batch")]
+    retry_fewer_threads: bool,
+
+    /// where to write the batch manifest. Defaults to <cases file>_manifest.csv next to the cases file
+    #[clap(long, requires = "batch")]
+    batch_manifest: Option<PathBuf>,
+
+    /// only process the cases of the batch that are new or changed: a case is skipped when its
+    /// output directory holds a run of the same input and mask (by content) with the same
+    /// options and all of its maps. Skipped cases are recorded as such in the manifest
+    #[clap(long, requires = "batch")]
+    incremental: bool,
+
+    /// run the cases of the batch in this process as a pipeline, reading the next case and
+    /// writing the one before while a case is mapped, so a case takes about as long as its
+    /// slowest stage. Pays off on network storage. A case can then not be timed out or killed
+    /// for its memory and a crash ends the batch
+    #[clap(long, requires = "batch", conflicts_with_all = ["timeout_per_case", "memory_budget", "retry_fewer_threads", "abort_if_degenerate", "dicom_source", "discretized_cache", "correlation_report", "voxel_table", "export_patches", "study_db", "strict"])]
+    pipeline: bool,
+
+
+    /// opt in to (or out of) local usage statistics: run counts, feature counts and bucketed
+    /// volume sizes, never paths or image data. Nothing is collected unless enabled and nothing
+    /// is ever sent anywhere. Disabling deletes everything collected so far
+    #[clap(long, value_enum)]
+    usage_stats: Option<UsageStatsAction>,
+
+    /// write the local usage statistics to a csv file, e.g. to share them with your lab
+    #[clap(long)]
+    export_usage_stats: Option<PathBuf>,
+
+    /// leave this run out of the local run history. See `radmap history`
+    #[clap(long)]
+    no_history: bool,
+
+    #[command(flatten)]
+    report: ReportArgs,
+}
+
+/// how numbers and dates are written in the csv reports, and their dialect: the correlations,
+/// patch index, batch manifest, shape features and rankings. Json reports always use `.` decimals
+#[derive(clap::Args, Debug, Clone, Default)]
+struct ReportArgs {
+    /// decimal separator of the csv reports, . or ,. With , the fields are separated by ; as spreadsheets in those locales expect, unless --csv-delimiter says otherwise. Defaults to that of the locale in LC_ALL, LC_NUMERIC or LANG
+    #[clap(long, value_parser = parse_decimal_separator)]
+    decimal_separator: Option<char>,
+
+    /// how dates in the csv reports are written, in UTC: iso (2024-03-05T14:07:09Z), dmy (05/03/2024 14:07:09), mdy (03/05/2024 14:07:09) or unix (seconds since 1970). Default is iso
+    #[clap(long, value_parser = DateFormat::from_str)]
+    date_format: Option<DateFormat>,
+
+    /// write the csv reports with . decimals and iso dates whatever the locale, so they read the same at every site of a collaboration
+    #[clap(long, conflicts_with_all = ["decimal_separator", "date_format"])]
+    c_locale: bool,
+
+    /// field delimiter of the csv reports: comma, semicolon or tab. Defaults to semicolon with , decimals and comma otherwise
+    #[clap(long, value_parser = Delimiter::from_str)]
+    csv_delimiter: Option<Delimiter>,
+
+    /// when csv fields are put in double quotes: never (a delimiter within a field is replaced), minimal (fields holding a delimiter, quote or line break, as RFC 4180 has it) or all. Default is never
+    #[clap(long, value_parser = Quoting::from_str)]
+    csv_quoting: Option<Quoting>,
+
+    /// start the csv reports with a UTF-8 byte order mark, so Excel reads names with accents or other non-ascii characters right
+    #[clap(long)]
+    csv_bom: bool,
+}
+
+impl ReportArgs {
+    fn format(&self) -> ReportFormat {
+        let mut format = if self.c_locale {
+            ReportFormat::C
+        } else {
+            ReportFormat::from_env()
+        };
+        if let Some(decimal) = self.decimal_separator {
+            format.decimal = decimal;
+        }
+        if let Some(date) = self.date_format {
+            format.date = date;
+        }
+        format.delimiter = self.csv_delimiter;
+        format.quoting = self.csv_quoting.unwrap_or_default();
+        format.bom = self.csv_bom;
+        format.validate().unwrap_or_else(|e| fail(FailureKind::Usage, e));
+        format
+    }
+
+    /// the options as given, to pass them on to another radmap
+    fn to_args(&self) -> Vec<OsString> {
+        let mut a: Vec<OsString> = vec![];
+        if let Some(decimal) = self.decimal_separator {
+            a.push(format!("--decimal-separator={decimal}").into());
+        }
+        if let Some(date) = self.date_format {
+            a.push(format!("--date-format={date}").into());
+        }
+        if self.c_locale {
+            a.push("--c-locale".into());
+        }
+        if let Some(delimiter) = self.csv_delimiter {
+            a.push(format!("--csv-delimiter={delimiter}").into());
+        }
+        if let Some(quoting) = self.csv_quoting {
+            a.push(format!("--csv-quoting={quoting}").into());
+        }
+        if self.csv_bom {
+            a.push("--csv-bom".into());
+        }
+        a
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Cmd {
+    /// answer a few questions to build a full radmap command, then print it and optionally run it
+    Wizard,
+    /// download a session from XNAT, convert it to nifti with dcm2niix and map every series.
+    /// The server and credentials are read from XNAT_HOST, XNAT_USER and XNAT_PASS
+    Fetch {
+        /// session to fetch, as xnat://project/subject/session
+        #[clap(value_parser = XnatSession::from_str)]
+        session: XnatSession,
+        /// toml file of radmap options applied to every series, e.g. `n_bins = 32`
+        #[clap(long)]
+        run: PathBuf,
+        /// scan id to fetch. Can be given more than once. All scans are fetched by default
+        #[clap(long)]
+        scan: Vec<String>,
+        /// where downloads, converted volumes and results are kept. Defaults to a directory
+        /// under the system temp directory
+        #[clap(long)]
+        work_dir: Option<PathBuf>,
+        /// upload the results to the session as a `<session>_radmap` assessor
+        #[clap(long)]
+        upload: bool,
+    },
+    /// headless mode for docker and singularity: maps every volume in /in to /out with the
+    /// options in /config/radmap.toml, logging json lines to stdout
+    ContainerEntrypoint {
+        #[clap(long, default_value = container::DEFAULT_IN_DIR)]
+        in_dir: PathBuf,
+        #[clap(long, default_value = container::DEFAULT_OUT_DIR)]
+        out_dir: PathBuf,
+        #[clap(long, default_value = container::DEFAULT_CONFIG)]
+        config: PathBuf,
+        /// let outputs be written into a directory holding the inputs, e.g. when /in and /out
+        /// are the same mount
+        #[clap(long)]
+        allow_in_place: bool,
+    },
+    /// run as a compute service: jobs submitted over gRPC (see proto/radmap.proto) are mapped
+    /// by radmap child processes on this machine. Jobs only read uploads and files within
+    /// --input-root, only write within --output-root and only take the options of the
+    /// grpc module's allow-list
+    Serve {
+        /// address the gRPC api listens on. Addresses other than loopback need --token-file
+        #[clap(long, default_value = "127.0.0.1:50051")]
+        grpc: SocketAddr,
+        /// number of jobs run at the same time
+        #[clap(long, default_value_t = 1)]
+        max_jobs: usize,
+        /// where inputs uploaded by clients are kept until their job finishes. Defaults to a
+        /// directory under the system temp directory
+        #[clap(long)]
+        upload_dir: Option<PathBuf>,
+        /// directory holding the inputs clients may name on this machine. Without it, jobs can
+        /// only read uploaded inputs
+        #[clap(long)]
+        input_root: Option<PathBuf>,
+        /// directory the output directories of jobs must lie within, relative ones being taken
+        /// from it. Defaults to a directory under the system temp directory
+        #[clap(long)]
+        output_root: Option<PathBuf>,
+        /// file holding a token clients must send as `authorization: Bearer <token>`
+        #[clap(long)]
+        token_file: Option<PathBuf>,
+        /// pem certificate to serve the api over TLS with
+        #[clap(long, requires = "tls_key")]
+        tls_cert: Option<PathBuf>,
+        /// pem private key of --tls-cert
+        #[clap(long, requires = "tls_cert")]
+        tls_key: Option<PathBuf>,
+        /// largest file a client may upload (e.g. 4G)
+        #[clap(long, value_parser = parse_bytes, default_value = "4G")]
+        max_upload: u64,
+        /// how long the status of a finished job is kept for clients to read (e.g. 1h, 30m)
+        #[clap(long, value_parser = parse_duration, default_value = "1h")]
+        job_retention: Duration,
+    },
+    /// take jobs from a redis queue and post their status and results back, for processing
+    /// farms sharing one queue. See the queue module for the keys and the job format
+    Worker {
+        /// queue server, e.g. redis://localhost:6379/0
+        #[clap(long)]
+        queue: String,
+        /// list the jobs are taken from
+        #[clap(long, default_value = "radmap:jobs")]
+        queue_name: String,
+        /// name of this worker, which must stay the same across restarts for a restarted worker
+        /// to put the unfinished jobs of the one before it back on the queue. Defaults to the
+        /// host name, so workers running on one machine at the same time need names of their own
+        #[clap(long)]
+        worker_name: Option<String>,
+        /// number of jobs run at the same time
+        #[clap(long, default_value_t = 1)]
+        max_jobs: usize,
+    },
+    /// print a nextflow process or snakemake rule wrapping `radmap container-entrypoint`, with
+    /// its options as pipeline parameters and retries driven by the exit codes
+    EmitWorkflow {
+        /// `nextflow` or `snakemake`
+        #[clap(value_parser = WorkflowKind::from_str)]
+        engine: WorkflowKind,
+        /// default number of bins of the generated definition
+        #[clap(short, long, default_value_t = 32)]
+        n_bins: usize,
+        /// default kernel radius of the generated definition
+        #[clap(short, long, default_value_t = 1)]
+        kernel_radius: usize,
+        /// default feature of the generated definition, can be given more than once. All
+        /// features are computed by default
+        #[clap(short, long)]
+        feature: Vec<String>,
+        #[clap(long, value_parser = BinEdges::from_str, default_value_t = BinEdges::HalfOpen)]
+        bin_edges: BinEdges,
+        /// write the definition to a file instead of stdout
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// search the local history of runs, e.g. to find which settings produced a map. The
+    /// history is kept in the user data directory, or the file named by RADMAP_HISTORY_DB
+    History {
+        /// runs whose input path contains this text
+        #[clap(long)]
+        input: Option<String>,
+        /// runs whose output directory or any output file path contains this text
+        #[clap(long)]
+        output: Option<String>,
+        /// runs whose options hash starts with this
+        #[clap(long)]
+        hash: Option<String>,
+        /// `running`, `succeeded` or `failed`
+        #[clap(long, value_parser = RunStatus::from_str)]
+        status: Option<RunStatus>,
+        /// number of most recent matching runs to list
+        #[clap(long, default_value_t = 20)]
+        limit: usize,
+        /// print everything recorded for the run with this id, including its options and outputs
+        #[clap(long, conflicts_with_all = ["input", "output", "hash", "status"])]
+        show: Option<i64>,
+    },
+    /// compare the settings and input hashes of two runs, each given as a provenance sidecar or
+    /// the id of a run in the history. Only the settings that differ are listed by default
+    DiffRuns {
+        a: String,
+        b: String,
+        /// list every setting, not just those that differ
+        #[clap(long)]
+        all: bool,
+    },
+    /// describe every feature: what it measures, the values it takes, its IBSI identifier and the
+    /// cost of its family
+    Features {
+        /// print the catalog as json, for pipelines building their own feature pickers
+        #[clap(long)]
+        json: bool,
+        /// only the features of this family
+        #[clap(long, value_parser = Family::from_str)]
+        family: Option<Family>,
+    },
+    /// compute the shape features of the region in a mask, like its volume, surface area,
+    /// sphericity, elongation and maximum 3D diameter. Writes csv to stdout by default
+    Shape {
+        /// masks to describe, a row each. Every non-zero voxel is in the region
+        #[clap(required = true)]
+        mask: Vec<PathBuf>,
+        /// write to this file instead of stdout
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+        /// write json instead of csv. Implied by an output file ending in .json
+        #[clap(long)]
+        json: bool,
+        #[command(flatten)]
+        report: ReportArgs,
+    },
+    /// rank feature maps by how well they separate the regions of a label map, e.g. tumor from
+    /// edema, by the mutual information between feature and labels and the AUC of one label
+    /// against the others. Writes csv to stdout by default
+    Rank {
+        /// label map on the grid of the feature maps. Voxels labelled 0 are left out
+        labels: PathBuf,
+        /// feature maps to rank, or directories of them such as the output directory of a run
+        #[clap(required = true)]
+        maps: Vec<PathBuf>,
+        /// label the AUC is computed for, against all other non-zero labels. Default is the
+        /// largest label
+        #[clap(long)]
+        positive: Option<i64>,
+        /// write to this file instead of stdout
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+        /// write json instead of csv. Implied by an output file ending in .json
+        #[clap(long)]
+        json: bool,
+        #[command(flatten)]
+        report: ReportArgs,
+    },
+    /// explain a feature: what it measures, its formula, IBSI identifier, range and caveats
+    Explain {
+        /// name of the feature, e.g. `contrast` or `glrlm_run_entropy`. The family prefix can be
+        /// left out if only one family has the feature
+        feature: String,
+    },
+    /// create and sign locked protocols, which fix the binning, kernel and features of the GUI
+    /// for every user of a site. See the protocol module for the files involved
+    Protocol {
+        #[command(subcommand)]
+        action: ProtocolCmd,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ProtocolCmd {
+    /// generate a site admin key pair, `<stem>.key` to sign with and `<stem>.pub` for the site
+    /// directory
+    Keygen {
+        stem: PathBuf,
+    },
+    /// sign a protocol file, writing `<protocol>.sig` next to it
+    Sign {
+        protocol: PathBuf,
+        /// signing key from `radmap protocol keygen`
+        #[clap(long)]
+        key: PathBuf,
+    },
+    /// check the signature of a protocol and print its settings. Checks the protocol of the
+    /// site directory by default
+    Verify {
+        protocol: Option<PathBuf>,
+        /// public key to check against. Defaults to the one in the site directory
+        #[clap(long)]
+        public_key: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum UsageStatsAction {
+    Enable,
+    Disable,
+    Show,
+}
+
+impl Args {
+    /// checks options against each other and the file system before anything is loaded, so
+    /// mistakes are reported as usage errors rather than failures halfway through a run
+    fn validate(&self) -> Result<(), (FailureKind, String)> {
+        let err = |kind: FailureKind, msg: String| Err((kind, msg));
+
+        if self.command.is_some() || self.list_features || self.usage_stats.is_some() || self.export_usage_stats.is_some() {
+            return Ok(())
+        }
+
+        if let Some(fraction) = self.abort_if_degenerate && !(fraction > 0. && fraction <= 1.) {
+            return err(FailureKind::Usage, format!("--abort-if-degenerate must be a fraction between 0 and 1, got {fraction}"));
+        }
+
+        for f in self.feature.iter().chain(&self.omit) {
+            if Feature::from_str(f).is_err() {
+                return err(FailureKind::Usage, format!("unknown feature '{f}'. Run with --list-features to see the available features"));
+            }
+        }
+        for family in &self.family {
+            if let Err(e) = Family::from_str(family) {
+                return err(FailureKind::Usage, e);
+            }
+        }
+        if self.all_features && self.feature.iter().any(|f| matches!(Feature::from_str(f), Ok(Feature::Glcm(_)))) {
+            return err(FailureKind::Usage, "--feature has no effect on GLCM features with --all-features. Use --omit to leave features out".to_string());
+        }
+        if let Some(preset) = &self.preset && let Err(e) = Preset::read(preset) {
+            return err(FailureKind::Usage, e);
+        }
+        let exports_filters = self.has_filters() && self.filter_use.unwrap_or_default() == FilterUse::Export;
+        if !self.all_features && self.feature.is_empty() && self.family.is_empty() && self.preset.is_none() && !exports_filters {
+            return err(FailureKind::Usage, "no features selected. Pass --all-features, --family, at least one --feature or filters to export".to_string());
+        }
+        if self.feature.iter().any(|f| self.omit.iter().any(|o| o.eq_ignore_ascii_case(f))) {
+            return err(FailureKind::Usage, "a feature can't be both included with --feature and left out with --omit".to_string());
+        }
+        if let Err(e) = self.map_opts_builder().validate() {
+            return err(FailureKind::Usage, e);
+        }
+        let binning = self.binning(2);
+        if let Err(e) = binning.validate() {
+            return err(FailureKind::Usage, e);
+        }
+        if binning.needs_fit() && self.has_filters() && self.filter_use.unwrap_or_default() == FilterUse::Map {
+            return err(FailureKind::Usage, "a bin width or range, --bin-from-mask or --bin-rank can't be used with --filter-use map, filtered images don't have the intensities of the input".to_string());
+        }
+        if binning.from_mask && self.mask.is_none() && self.batch.is_none() {
+            return err(FailureKind::Usage, "--bin-from-mask needs a --mask".to_string());
+        }
+        if let Ok((_, texture)) = self.map_opts_builder().build() && texture.discretization.is_local() && !binning.is_native() {
+            return err(FailureKind::Usage, "--discretization local bins each kernel between its own minimum and maximum into half-open bins, it can't be used with a bin width or range, --bin-from-mask, --bin-rank or inclusive --bin-edges".to_string());
+        }
+
+        if let Some(cases_file) = &self.batch {
+            if !cases_file.is_file() {
+                return err(FailureKind::Usage, format!("batch cases file {} does not exist", cases_file.display()));
+            }
+            if self.dicom_source.is_some() {
+                return err(FailureKind::Usage, "--dicom-source names the series of a single input, it can't be used with --batch. Write the parametric maps of each case with a run of its own".to_string());
+            }
+            return Ok(())
+        }
+
+        if let Some(input_vol) = &self.input_vol {
+            if !input_vol.is_file() {
+                return err(FailureKind::BadInput, format!("input volume {} does not exist", input_vol.display()));
+            }
+            if !is_volume(input_vol) {
+                return err(FailureKind::BadInput, format!("input volume {} is not a {}", input_vol.display(), format_names()));
+            }
+        }
+        if let Some(mask) = &self.mask {
+            if !mask.is_file() {
+                return err(FailureKind::BadMask, format!("mask {} does not exist", mask.display()));
+            }
+            if !is_volume(mask) {
+                return err(FailureKind::BadMask, format!("mask {} is not a {}", mask.display(), format_names()));
+            }
+        }
+        if let Some(output_dir) = &self.output_dir && !output_dir.is_dir() {
+            return err(FailureKind::Usage, format!("output directory {} does not exist", output_dir.display()));
+        }
+        if let Some(output_dir) = &self.output_dir && !self.allow_in_place {
+            for (label, input) in [("input volume", self.input_vol.as_deref()), ("mask", self.mask.as_deref())] {
+                if let Some(input) = input && is_in_directory(input, output_dir) {
+                    return err(FailureKind::Usage, format!("output directory {} holds the {label}. Choose another output directory or pass --allow-in-place", output_dir.display()));
+                }
+            }
+        }
+        if let Some(dir) = &self.dicom_source && !dir.is_dir() {
+            return err(FailureKind::Usage, format!("dicom source {} is not a directory", dir.display()));
+        }
+        if let Some(grid) = self.patch_grid() && let Err(e) = grid.validate() {
+            return err(FailureKind::Usage, e);
+        }
+        if let Some(key) = self.archive_key() && let Err(e) = key.password() {
+            return err(FailureKind::Usage, e);
+        }
+        Ok(())
+    }
+
+    /// map options and feature selection of the command line. Feature and family names must
+    /// have been checked by `validate`
+    fn map_opts_builder(&self) -> MapOptsBuilder {
+        let mut builder = self.preset().map(|p| p.options).unwrap_or_default();
+        if let Some(n_bins) = self.n_bins {
+            builder = builder.n_bins(n_bins);
+        }
+        if let Some(r) = self.kernel_radius {
+            builder = builder.kernel_radius(r);
+        }
+        if self.kernel_radii.is_some() {
+            builder = builder.kernel_radii(self.kernel_radii);
+        }
+        if let Some(shape) = self.kernel_shape {
+            builder = builder.kernel_shape(shape);
+        }
+        if self.max_threads.is_some() {
+            builder = builder.max_threads(self.max_threads);
+        }
+        if let Some(alpha) = self.gldm_alpha {
+            builder = builder.gldm_alpha(alpha);
+        }
+        if let Some(alpha) = self.ngldm_alpha {
+            builder = builder.ngldm_alpha(alpha);
+        }
+        if let Some(distance) = self.ngldm_distance {
+            builder = builder.ngldm_distance(distance);
+        }
+        if self.glcm_per_direction {
+            builder = builder.glcm_per_direction(true);
+        }
+        if self.glcm_anisotropy.is_some() {
+            builder = builder.glcm_anisotropy(self.glcm_anisotropy);
+        }
+        if let Some(angles) = self.angles {
+            builder = builder.glcm_angles(angles);
+        }
+        if !self.distance.is_empty() {
+            builder = builder.glcm_distances(self.distance.iter().copied());
+        }
+        if let Some(aggregation) = self.distance_aggregation {
+            builder = builder.glcm_aggregation(aggregation);
+        }
+        if let Some(aggregation) = self.direction_aggregation {
+            builder = builder.glcm_direction_aggregation(aggregation);
+        }
+        if let Some(weighting) = self.distance_weighting {
+            builder = builder.glcm_weighting(weighting);
+        }
+        if self.slice_wise {
+            builder = builder.slice_wise(true);
+        }
+        if let Some(scope) = self.discretization {
+            builder = builder.discretization(scope);
+        }
+        if self.has_filters() {
+            let mut filters = FilterOpts { usage: self.filter_use.unwrap_or_default(), ..Default::default() };
+            let orientations = if self.gabor_orientation.is_empty() {
+                vec![Orientation::X, Orientation::Y, Orientation::Z]
+            }else {
+                self.gabor_orientation.clone()
+            };
+            filters.add_gabor_bank(&self.gabor_frequency, &orientations);
+            if self.gradient {
+                filters.filters.push(ImageFilter::GradientMagnitude);
+            }
+            if self.laplacian {
+                filters.filters.push(ImageFilter::Laplacian);
+            }
+            filters.add_log(&self.log_sigma);
+            if let Some(kind) = self.wavelet {
+                let bands = if self.wavelet_band.is_empty() { &SubBand::ALL[..] } else { &self.wavelet_band };
+                filters.add_wavelet(kind, bands);
+            }
+            builder = builder.filters(filters);
+        }
+        if self.all_features || !self.feature.is_empty() || !self.family.is_empty() {
+            builder = builder.clear_features();
+        }
+        if self.all_features {
+            builder = builder.features(Family::Glcm.features().into_iter().map(|f| (f, f.to_string())));
+        }
+        for family in &self.family {
+            let family = Family::from_str(family).unwrap_or_else(|e| fail(FailureKind::Usage, e));
+            builder = builder.features(family.features().into_iter().map(|f| (f, f.to_string())));
+        }
+        for f in &self.feature {
+            let feature = Feature::from_str(f).unwrap_or_else(|e| fail(FailureKind::Usage, e));
+            builder = builder.feature(feature, feature.to_string());
+        }
+        for to_omit in &self.omit {
+            let feature = Feature::from_str(to_omit).unwrap_or_else(|e| fail(FailureKind::Usage, e));
+            builder = builder.omit(feature);
+        }
+        builder
+    }
+
+    fn patch_grid(&self) -> Option<PatchGrid> {
+        self.export_patches.map(|size| PatchGrid { size, stride: self.stride.unwrap_or(size) })
+    }
+
+    fn has_filters(&self) -> bool {
+        !self.gabor_frequency.is_empty() || self.gradient || self.laplacian || !self.log_sigma.is_empty() || self.wavelet.is_some()
+    }
+
+    /// the preset given with --preset, which must have been checked by `validate`
+    fn preset(&self) -> Option<Preset> {
+        self.preset.as_ref().map(|p| Preset::read(p).unwrap_or_else(|e| fail(FailureKind::Usage, e)))
+    }
+
+    fn bin_edges(&self) -> BinEdges {
+        self.bin_edges.or_else(|| self.preset().map(|p| p.bin_edges)).unwrap_or_default()
+    }
+
+    /// binning of the input with `n_bins`, or with the bin width of --bin-width or the preset, over
+    /// the range of --bin-min and --bin-max or the preset. The preset's width gives way to --n-bins
+    fn binning(&self, n_bins: usize) -> Binning {
+        let preset = self.preset();
+        let width = self.bin_width.or_else(|| preset.as_ref().filter(|_| self.n_bins.is_none()).and_then(|p| p.bin_width));
+        let min = self.bin_min.or_else(|| preset.as_ref().and_then(|p| p.bin_min));
+        let max = self.bin_max.or_else(|| preset.as_ref().and_then(|p| p.bin_max));
+        let from_mask = self.bin_from_mask || preset.as_ref().is_some_and(|p| p.bin_from_mask);
+        let rank = self.bin_rank || preset.as_ref().is_some_and(|p| p.bin_rank);
+        Binning { n_bins, edges: self.bin_edges(), width, min, max, from_mask, rank }
+    }
+
+    fn rebin_integer_input(&self) -> bool {
+        self.rebin_integer_input || self.preset().is_some_and(|p| p.rebin_integer_input)
+    }
+
+    fn archive_key(&self) -> Option<ArchiveKey> {
+        match (&self.archive_password_env, &self.archive_keyfile) {
+            (Some(var), _) => Some(ArchiveKey::PasswordEnv(var.clone())),
+            (None, Some(keyfile)) => Some(ArchiveKey::KeyFile(keyfile.clone())),
+            (None, None) => None,
+        }
+    }
+
+    /// the stages the cases of a pipelined batch go through, with the options of the command line
+    fn case_stages(&self) -> CaseStages {
+        let (opts, texture) = self.map_opts_builder().build().unwrap_or_else(|e| fail(FailureKind::Usage, e));
+        let binning = self.binning(opts.n_bins);
+        CaseStages {
+            // an explicit --n-bins bins quantized inputs like any other
+            rebin_integer_input: self.rebin_integer_input() || self.n_bins.is_some(),
+            allow_in_place: self.allow_in_place,
+            scrub_phi: self.scrub_phi,
+            slicer_scene: self.slicer_scene,
+            export_tensor: self.export_tensor,
+            options_tag: self.options_tag,
+            archive_key: self.archive_key(),
+            stage_root: self.stage_outputs.clone(),
+            retry: RetryPolicy { attempts: self.write_retries + 1, ..Default::default() },
+            history: !self.no_history,
+            ..CaseStages::new(opts, texture, binning)
+        }
+    }
+
+    /// command line options forwarded to each case of a batch
+    fn case_args(&self, case: &Case, max_threads: Option<usize>) -> Vec<OsString> {
+        let mut a: Vec<OsString> = vec![case.input_vol.clone().into(), case.output_dir.clone().into()];
+        if let Some(mask) = &case.mask {
+            a.extend(["--mask".into(), mask.clone().into()]);
+        }
+        if let Some(n_bins) = self.n_bins {
+            a.extend(["--n-bins".into(), n_bins.to_string().into()]);
+        }
+        if let Some(preset) = &self.preset {
+            a.extend(["--preset".into(), preset.clone().into()]);
+        }
+        if let Some(edges) = self.bin_edges {
+            a.push(format!("--bin-edges={edges}").into());
+        }
+        if let Some(width) = self.bin_width {
+            a.push(format!("--bin-width={width}").into());
+        }
+        if let Some(min) = self.bin_min {
+            a.push(format!("--bin-min={min}").into());
+        }
+        if let Some(max) = self.bin_max {
+            a.push(format!("--bin-max={max}").into());
+        }
+        if self.bin_from_mask {
+            a.push("--bin-from-mask".into());
+        }
+        if self.bin_rank {
+            a.push("--bin-rank".into());
+        }
+        if let Some(scope) = self.discretization {
+            a.push(format!("--discretization={scope}").into());
+        }
+        if let Some(r) = self.kernel_radius {
+            a.push(format!("--kernel-radius={r}").into());
+        }
+        if let Some([rx, ry, rz]) = self.kernel_radii {
+            a.push(format!("--kernel-radii={rx},{ry},{rz}").into());
+        }
+        if let Some(shape) = self.kernel_shape {
+            a.push(format!("--kernel-shape={shape}").into());
+        }
+        if self.all_features {
+            a.push("--all-features".into());
+        }
+        for f in &self.feature {
+            a.extend(["--feature".into(), f.into()]);
+        }
+        for family in &self.family {
+            a.extend(["--family".into(), family.into()]);
+        }
+        if let Some(alpha) = self.gldm_alpha {
+            a.push(format!("--gldm-alpha={alpha}").into());
+        }
+        a.extend(self.report.to_args());
+        if let Some(alpha) = self.ngldm_alpha {
+            a.push(format!("--ngldm-alpha={alpha}").into());
+        }
+        if let Some(distance) = self.ngldm_distance {
+            a.push(format!("--ngldm-distance={distance}").into());
+        }
+        if self.glcm_per_direction {
+            a.push("--glcm-per-direction".into());
+        }
+        if let Some(anisotropy) = self.glcm_anisotropy {
+            a.push(format!("--glcm-anisotropy={anisotropy}").into());
+        }
+        if let Some(angles) = self.angles {
+            a.push(format!("--angles={angles}").into());
+        }
+        if !self.distance.is_empty() {
+            let distances: Vec<String> = self.distance.iter().map(|d| d.to_string()).collect();
+            a.push(format!("--distance={}", distances.join(",")).into());
+        }
+        if let Some(aggregation) = self.distance_aggregation {
+            a.push(format!("--distance-aggregation={aggregation}").into());
+        }
+        if let Some(aggregation) = self.direction_aggregation {
+            a.push(format!("--direction-aggregation={aggregation}").into());
+        }
+        if let Some(weighting) = self.distance_weighting {
+            a.push(format!("--distance-weighting={weighting}").into());
+        }
+        if self.slice_wise {
+            a.push("--slice-wise".into());
+        }
+        for f in &self.gabor_frequency {
+            a.push(format!("--gabor-frequency={f}").into());
+        }
+        for o in &self.gabor_orientation {
+            a.push(format!("--gabor-orientation={}", o.to_string().replace('_', ",")).into());
+        }
+        if self.gradient {
+            a.push("--gradient".into());
+        }
+        if self.laplacian {
+            a.push("--laplacian".into());
+        }
+        for sigma in &self.log_sigma {
+            a.push(format!("--log-sigma={sigma}").into());
+        }
+        if let Some(kind) = self.wavelet {
+            a.push(format!("--wavelet={kind}").into());
+        }
+        for band in &self.wavelet_band {
+            a.push(format!("--wavelet-band={band}").into());
+        }
+        if let Some(usage) = self.filter_use {
+            a.push(format!("--filter-use={usage}").into());
+        }
+        for f in &self.omit {
+            a.extend(["--omit".into(), f.into()]);
+        }
+        if let Some(threads) = max_threads {
+            a.extend(["--max-threads".into(), threads.to_string().into()]);
+        }
+        if self.no_progress_bar {
+            a.push("--no-progress-bar".into());
+        }
+        if self.json_progress {
+            a.push("--json-progress".into());
+        }
+        if self.rebin_integer_input {
+            a.push("--rebin-integer-input".into());
+        }
+        if self.allow_in_place {
+            a.push("--allow-in-place".into());
+        }
+        if self.scrub_phi {
+            a.push("--scrub-phi".into());
+        }
+        if let Some(var) = &self.archive_password_env {
+            a.extend(["--archive-password-env".into(), var.into()]);
+        }
+        if let Some(keyfile) = &self.archive_keyfile {
+            a.extend(["--archive-keyfile".into(), keyfile.clone().into()]);
+        }
+        if self.slicer_scene {
+            a.push("--slicer-scene".into());
+        }
+        if self.export_tensor {
+            a.push("--export-tensor".into());
+        }
+        if let Some(table) = self.voxel_table {
+            a.push(format!("--voxel-table={table}").into());
+        }
+        if let Some(db) = &self.study_db {
+            a.extend(["--study-db".into(), db.clone().into()]);
+        }
+        if let Some(size) = self.export_patches {
+            a.push(format!("--export-patches={size}").into());
+        }
+        if let Some(stride) = self.stride {
+            a.push(format!("--stride={stride}").into());
+        }
+        if self.correlation_report {
+            a.push("--correlation-report".into());
+            a.push(format!("--redundant-above={}", self.redundant_above).into());
+        }
+        if let Some(tag) = self.options_tag {
+            a.push(format!("--options-tag={tag}").into());
+        }
+        if let Some(dir) = &self.stage_outputs {
+            a.extend(["--stage-outputs".into(), dir.clone().into()]);
+        }
+        a.push(format!("--write-retries={}", self.write_retries).into());
+        if let Some(dir) = &self.discretized_cache {
+            a.extend(["--discretized-cache".into(), dir.clone().into()]);
+        }
+        if self.no_history {
+            a.push("--no-history".into());
+        }
+        if self.strict {
+            a.push("--strict".into());
+        }
+        if let Some(fraction) = self.abort_if_degenerate {
+            a.push(format!("--abort-if-degenerate={fraction}").into());
+            a.push(format!("--degenerate-after={}", self.degenerate_after).into());
+        }
+        a
+    }
 }
 
 fn main() {
 
+    crash::install_panic_hook("radmap");
     let args = Args::parse();
+    crash::set_scrub_phi(args.scrub_phi);
+    failure::exit_on_panic();
+    failure::exit_on_signal();
+    if let Err((kind, msg)) = args.validate() {
+        failure::set_stage(Stage::Validation);
+        if let Some(output_dir) = args.output_dir.as_ref().filter(|d| d.is_dir()) {
+            failure::set_failure_dir(output_dir);
+        }
+        let _ = Args::command().error(ErrorKind::ValueValidation, &msg).print();
+        failure::exit_with(kind, msg);
+    }
+
+    match &args.command {
+        Some(Cmd::Wizard) => {
+            run_wizard();
+            return
+        }
+        Some(Cmd::Fetch { session, run, scan, work_dir, upload }) => {
+            run_fetch(session, run, scan, work_dir.as_deref(), *upload);
+            return
+        }
+        Some(Cmd::ContainerEntrypoint { in_dir, out_dir, config, allow_in_place }) => {
+            run_container(in_dir, out_dir, config, *allow_in_place);
+            return
+        }
+        Some(Cmd::Serve { grpc, max_jobs, upload_dir, input_root, output_root, token_file, tls_cert, tls_key, max_upload, job_retention }) => {
+            let exe = std::env::current_exe().expect("failed to locate the radmap executable");
+            let token = token_file.as_ref().map(|f| {
+                let token = std::fs::read_to_string(f).unwrap_or_else(|e| fail(FailureKind::Usage, format!("failed to read {}: {e}", f.display())));
+                let token = token.trim().to_string();
+                if token.is_empty() {
+                    fail(FailureKind::Usage, format!("{} holds no token", f.display()));
+                }
+                token
+            });
+            let opts = ServeOpts {
+                upload_dir: upload_dir.clone().unwrap_or(std::env::temp_dir().join("radmap-uploads")),
+                input_root: input_root.clone(),
+                output_root: output_root.clone().unwrap_or(std::env::temp_dir().join("radmap-outputs")),
+                token,
+                tls: tls_cert.clone().zip(tls_key.clone()),
+                max_upload: *max_upload,
+            };
+            println!("serving the gRPC job api on {grpc}, running up to {max_jobs} job(s) at a time");
+            let runner = JobRunner::new(exe, *max_jobs).with_retention(*job_retention);
+            grpc::serve(*grpc, runner, opts).unwrap_or_else(|e| fail(FailureKind::Internal, e));
+            return
+        }
+        Some(Cmd::Worker { queue, queue_name, worker_name, max_jobs }) => {
+            let exe = std::env::current_exe().expect("failed to locate the radmap executable");
+            let name = worker_name.clone().unwrap_or_else(queue::default_worker_name);
+            println!("taking jobs from {queue_name} on {queue} as {name}");
+            queue::run_worker(queue, queue_name, &name, &JobRunner::new(exe, *max_jobs), *max_jobs).unwrap_or_else(|e| fail(FailureKind::Internal, e));
+            return
+        }
+        Some(Cmd::EmitWorkflow { engine, n_bins, kernel_radius, feature, bin_edges, output }) => {
+            // the definition runs every GLCM feature when none are given
+            let features = if feature.is_empty() {
+                Family::Glcm.features()
+            }else {
+                feature.iter().map(|f| Feature::from_str(f).unwrap_or_else(|e| fail(FailureKind::Usage, e))).collect()
+            };
+            let builder = MapOptsBuilder::new().n_bins(*n_bins).kernel_radius(*kernel_radius).features(features.into_iter().map(|f| (f, f.to_string())));
+            if let Err(e) = builder.validate() {
+                fail(FailureKind::Usage, e);
+            }
+            let params = WorkflowParams {
+                n_bins: *n_bins,
+                kernel_radius: *kernel_radius,
+                features: (!feature.is_empty()).then(|| feature.iter().map(|f| f.to_lowercase()).collect()),
+                bin_edges: *bin_edges,
+            };
+            let definition = workflow::emit(*engine, &params);
+            match output {
+                Some(path) => {
+                    std::fs::write(path, definition).unwrap_or_else(|e| fail(FailureKind::WriteFailure, format!("failed to write {}: {e}", path.display())));
+                    println!("{engine} definition written to {}", path.display());
+                }
+                None => print!("{definition}"),
+            }
+            return
+        }
+        Some(Cmd::History { input, output, hash, status, limit, show }) => {
+            let query = HistoryQuery { input: input.clone(), output: output.clone(), options_hash: hash.clone(), status: *status, limit: Some(*limit) };
+            run_history(&query, *show);
+            return
+        }
+        Some(Cmd::DiffRuns { a, b, all }) => {
+            run_diff(a, b, *all);
+            return
+        }
+        Some(Cmd::Protocol { action }) => {
+            run_protocol(action);
+            return
+        }
+        Some(Cmd::Features { json, family }) => {
+            run_features(*json, *family);
+            return
+        }
+        Some(Cmd::Shape { mask, output, json, report }) => {
+            run_shape(mask, output.as_deref(), *json, &report.format());
+            return
+        }
+        Some(Cmd::Rank { labels, maps, positive, output, json, report }) => {
+            run_rank(labels, maps, *positive, output.as_deref(), *json, &report.format());
+            return
+        }
+        Some(Cmd::Explain { feature }) => {
+            run_explain(feature);
+            return
+        }
+        None => {}
+    }
 
     if args.list_features {
-        for f in GLCMFeature::iter() {
-            println!("{}",f.to_string().to_lowercase());
+        let n_bins = args.n_bins.unwrap_or(DEFAULT_N_BINS);
+        let kernel_radius = args.kernel_radius.unwrap_or(DEFAULT_KERNEL_RADIUS);
+        for family in Family::ALL {
+            println!("{} - {} cost, {:.1}x the cheapest:", family.label(), family.cost(), family.relative_cost(n_bins, kernel_radius));
+            for f in family.features() {
+                println!("  {f}");
+            }
         }
         return
     }
 
-    let mut opts = MapOpts {
-        n_bins: args.n_bins.unwrap_or(32),
-        kernel_radius: args.kernel_radius.map(|r| r.unsigned_abs() as usize).unwrap_or(1),
-        max_threads: args.max_threads,
-        ..Default::default()
-    };
+    if let Some(path) = &args.save_preset {
+        let mut preset = Preset::new(args.map_opts_builder(), args.bin_edges(), args.rebin_integer_input());
+        let binning = args.binning(0);
+        (preset.bin_width, preset.bin_min, preset.bin_max) = (binning.width, binning.min, binning.max);
+        preset.bin_from_mask = binning.from_mask;
+        preset.bin_rank = binning.rank;
+        preset.write(path).unwrap_or_else(|e| fail(FailureKind::WriteFailure, e));
+        println!("preset written to {}", path.display());
+        return
+    }
+
+    if args.usage_stats.is_some() || args.export_usage_stats.is_some() {
+        manage_usage_stats(args.usage_stats, args.export_usage_stats.as_deref());
+        return
+    }
+
+    if let Some(cases_file) = &args.batch {
+        run_batch(&args, cases_file);
+        return
+    }
+
+    run_map(&args);
+}
+
+/// a mask read on its own thread: its path, voxels, dimensions and the number of voxels set
+type LoadedMask = (PathBuf, Vec<f64>, ArrayDim, usize);
+
+/// the input of a run, binned for the mappers, along with its mask
+struct LoadedInput {
+    vol: Vec<f64>,
+    intensities: Option<Vec<f64>>,
+    images: Vec<FilteredImage>,
+    mask: Option<Vec<f64>>,
+    masked_voxels: usize,
+    dims: ArrayDim,
+    header: Header,
+}
+
+/// maps the features of a single input volume: loads and bins it, maps it, then writes the maps
+fn run_map(args: &Args) {
+    let (mut opts, mut texture) = args.map_opts_builder().build().unwrap_or_else(|e| fail(FailureKind::Usage, e));
 
     println!("num bins: {}",opts.n_bins);
-    println!("kernel radius: {}",opts.kernel_radius);
+    match (texture.kernel_shape, texture.kernel_radii) {
+        (KernelShape::Sphere { radius_mm }, _) => println!("kernel: sphere of {radius_mm} mm"),
+        (KernelShape::Cube, Some([rx, ry, rz])) => println!("kernel radius: {rx},{ry},{rz} along x,y,z"),
+        (KernelShape::Cube, None) => println!("kernel radius: {}",opts.kernel_radius),
+    }
     if let Some(threads) = opts.max_threads {
         println!("limiting max logical cores to {threads}");
     }else {
@@ -96,120 +1425,938 @@ fn main() {
     let input_vol = args.input_vol.as_ref().unwrap();
 
     if !output_dir.is_dir() {
-        panic!("Output directory {} does not exist",output_dir.display());
+        fail(FailureKind::Usage, format!("output directory {} does not exist", output_dir.display()));
     }
 
     if !input_vol.is_file() {
-        panic!("Input volume {} file does not exist",input_vol.display());
+        fail(FailureKind::BadInput, format!("input volume {} does not exist", input_vol.display()));
     }
 
-    let input_stem = input_vol.file_stem().unwrap().to_str().unwrap();
+    crash::set_report_dir(output_dir);
+    failure::set_failure_dir(output_dir);
 
-    if !args.all_features {
-        opts.features.clear();
-        for f in args.feature {
-            let feature = GLCMFeature::from_str(&f.to_lowercase()).unwrap_or_else(|_| panic!("Invalid GLCM feature: {f}"));
-            opts.features.insert(feature,feature.to_string().to_lowercase());
+    // spherical kernels are measured in mm with the spacing of the input
+    texture.voxel_spacing = voxel_spacing(input_vol);
+    println!("kernel size: {}", describe_kernel(texture.kernel_radius(opts.kernel_radius), texture.voxel_spacing));
+    if let Some(shape) = volume_shape(input_vol) {
+        let n_voxels = shape.iter().product();
+        println!("estimated runtime: up to {} for {} voxels", format_duration(estimated_runtime(&opts, &texture, n_voxels)), format_count(n_voxels));
+    }
+
+    crash::set_options(&opts, &texture);
+
+    // the mask is loaded and its voxels counted on another thread while the volume loads
+    let mask_handle = args.mask.clone().map(|mask| {
+        println!("loading mask ...");
+        thread::spawn(move ||{
+            failure::set_thread_stage(Stage::LoadMask);
+            let (mask_vol, mask_dims, ..) = read_volume(&mask);
+            let masked_voxels = mask_vol.par_iter().filter(|x| **x != 0.).count();
+            (mask, mask_vol, mask_dims, masked_voxels)
+        })
+    });
+
+    let mut binning = args.binning(opts.n_bins);
+    println!("bin edges: {}", binning.edges.describe());
+    if let Some(fit) = binning.describe_fit() {
+        println!("binning: {fit}");
+    }
+    if texture.discretization.is_local() {
+        println!("binning: each kernel into {} bins between its own minimum and maximum", opts.n_bins);
+    }
+    if texture.slice_wise {
+        println!("kernels: kept to the axial slice of their voxel");
+    }
+    if !texture.angles().is_all() {
+        println!("co-occurrence directions: {}", texture.angles());
+    }
+    if texture.glcm_distances != [DEFAULT_DISTANCE] {
+        let distances: Vec<String> = texture.glcm_distances.iter().map(|d| d.to_string()).collect();
+        match texture.glcm_distances.len() {
+            1 => println!("co-occurrence distance: {} voxels", distances[0]),
+            _ => println!("co-occurrence distances: {} voxels, {} over the distances", distances.join(", "), texture.glcm_aggregation),
         }
     }
+    if texture.glcm_direction_aggregation != DirectionAggregation::Mean {
+        println!("co-occurrence directions summed up: {}", texture.glcm_direction_aggregation);
+    }
+    if texture.glcm_weighting != DistanceWeighting::None {
+        println!("co-occurrence pairs weighted by distance: {}", texture.glcm_weighting);
+    }
 
-    for to_omit in args.omit {
-        let feature = GLCMFeature::from_str(&to_omit.to_lowercase()).unwrap_or_else(|_| panic!("Invalid GLCM feature: {to_omit}"));
-        opts.features.remove(&feature);
+    let mut provenance = Provenance::new("radmap", input_vol, args.mask.as_deref(), &opts, &texture, binning);
+    provenance.phi_scrubbed = args.scrub_phi;
+    let history_run = if args.no_history { None } else { history::start_run(&provenance, output_dir) };
+    history::set_current_run(history_run);
+
+    let progress = Progress::default();
+    progress.set_stage(RunStage::Loading);
+    println!("loading volume ...");
+    failure::set_stage(Stage::LoadInput);
+    // read the dicom source and the map geometry up front so a bad series fails before the compute
+    let dicom_source = args.dicom_source.as_ref().map(|dir| {
+        let source = SourceSeries::read_dir(dir).unwrap_or_else(|e| fail(FailureKind::BadInput, e));
+        let header = read_header(input_vol).unwrap_or_else(|e| fail(FailureKind::BadInput, e));
+        let voxel_to_lps = voxel_to_lps(&header).unwrap_or_else(|| fail(FailureKind::BadInput, format!("{} has no patient space orientation", input_vol.display())));
+        (source, voxel_to_lps)
+    });
+    let input = load_input(args, input_vol, &mut opts, &texture, &mut binning, mask_handle);
+
+    let features = selected_features(&opts, &texture);
+    println!("launching feature mappers for {} feature(s) over {} voxels ...", features.len(), format_count(input.masked_voxels));
+
+    // the mask goes to the mappers, the report only needs to know which voxels it holds
+    let in_mask = (args.correlation_report || args.export_patches.is_some() || args.voxel_table.is_some() || args.study_db.is_some()).then(|| input.mask.as_ref().map(|m| m.iter().map(|x| *x != 0.).collect::<Vec<_>>())).flatten();
+
+    let now = Instant::now();
+    let results = map_input(args, &opts, &texture, input.vol, input.intensities, input.images, input.mask, input.dims, &progress);
+    let duration = now.elapsed();
+    println!("{} voxels processed in {:.03} minutes", format_count(input.masked_voxels), duration.as_secs_f64() / 60.);
+    if let Some(peak) = peak_rss() {
+        println!("peak memory usage: {}", format_bytes(peak));
     }
 
-    if opts.features.is_empty() {
-        panic!("No features specified!");
+    // the bins may have been replaced by the levels of an already quantized input
+    provenance.binning = binning;
+    let written = write_outputs(args, &provenance, &results, &features, input.dims, input.header, dicom_source.as_ref(), in_mask.as_deref(), &progress);
+
+    if let Some(id) = history_run {
+        history::finish_run(id, &provenance, &written);
+        history::set_current_run(None);
     }
+    usage::record_run("radmap", &opts, &texture, input.dims.numel());
+}
 
-    println!("loading volume ...");
-    let (vol, dims, header) = read_volume(input_vol);
-    let mask = if let Some(mask) = &args.mask {
-        println!("loading mask ...");
-        let (mask_vol, mask_dims, ..) = read_volume(mask);
-        assert_eq!(dims.shape_ns(), mask_dims.shape_ns(), "input volume and mask must have the same shape");
-        Some(mask_vol)
+/// reads the input volume, from the discretized cache if it's there, and the mask read on
+/// `mask_handle`, then filters and bins the input for the mappers
+fn load_input(args: &Args, input_vol: &Path, opts: &mut MapOpts, texture: &TextureOpts, binning: &mut Binning, mut mask_handle: Option<thread::JoinHandle<LoadedMask>>) -> LoadedInput {
+    // bins that follow the masked voxels need the mask before the volume is binned
+    let early_mask = binning.from_mask.then(|| mask_handle.take()).flatten().map(|handle| {
+        failure::set_stage(Stage::LoadMask);
+        handle.join().expect("failed to load mask")
+    });
+    // kernels binned on their own and the families of the intensities need them, the cache only
+    // keeps gray levels
+    let keep = texture.maps_intensities();
+    let cache = args.discretized_cache.as_ref().filter(|_| !texture.discretization.is_local() && !keep).map(|dir| DiscretizedCache::new(dir).unwrap_or_else(|e| fail(FailureKind::WriteFailure, e)));
+    // filters need the intensities, which the cache doesn't keep, and the number of bins of a bin
+    // width is only known once the volume is read
+    let mut images = vec![];
+    let (vol, intensities, dims, header) = match cache.as_ref().filter(|_| texture.filters.is_empty() && !binning.needs_fit()).and_then(|c| c.load(input_vol, binning)) {
+        Some((levels, dims)) => {
+            println!("using cached discretized volume");
+            let header = read_header(input_vol).unwrap_or_else(|e| fail(FailureKind::BadInput, e));
+            (levels_to_f64(&levels), None, dims, header)
+        }
+        None => {
+            let (vol, dims, header) = read_volume(input_vol);
+            let non_finite = non_finite_voxels(&vol);
+            if non_finite > 0 {
+                checks::warn(args.strict, Check::NonFinite, &format!("input has {} NaN or infinite voxels", format_count(non_finite)));
+            }
+            if !texture.filters.is_empty() {
+                println!("filtering the input with {} filter(s) ...", texture.filters.filters.len());
+                images = texture.filters.apply(&vol, &dims, voxel_spacing(input_vol).unwrap_or([1.; 3]));
+            }
+            let mask = early_mask.as_ref().map(|(_, mask_vol, ..)| mask_vol.as_slice());
+            let (vol, intensities) = bin_input(args, input_vol, vol, dims, opts, texture, binning, mask, cache.as_ref());
+            (vol, intensities, dims, header)
+        }
+    };
+    crash::record_input("input volume", input_vol, &dims);
+    if texture.filters.usage == FilterUse::Map && !binning.is_native() {
+        for image in &mut images {
+            image.bin(levels_to_f64(&binning.discretize(&image.data)), keep);
+        }
+    }
+    let loaded_mask = early_mask.or_else(|| mask_handle.map(|handle| {
+        failure::set_stage(Stage::LoadMask);
+        handle.join().expect("failed to load mask")
+    }));
+    let (mask, masked_voxels) = if let Some((mask, mask_vol, mask_dims, masked_voxels)) = loaded_mask {
+        crash::record_input("mask", &mask, &mask_dims);
+        if dims.shape_ns() != mask_dims.shape_ns() {
+            fail(FailureKind::BadMask, format!("mask {} has shape {:?}, the input volume {:?}", mask.display(), mask_dims.shape_ns(), dims.shape_ns()));
+        }
+        if masked_voxels == 0 {
+            checks::warn(args.strict, Check::EmptyMask, &format!("mask {} has no voxels set, the maps will be empty", mask.display()));
+        }
+        if let Some(mismatch) = orientation_mismatch(input_vol, &mask) {
+            checks::warn(args.strict, Check::Orientation, &mismatch);
+        }
+        let widest = texture.kernel_radius(opts.kernel_radius).into_iter().max().unwrap_or(opts.kernel_radius);
+        let at_edge = edge_voxels(&mask_vol, &mask_dims, widest);
+        if at_edge > 0 {
+            checks::warn(args.strict, Check::EdgeKernels, &format!("{} voxels of the mask are within the kernel radius of the edge of the volume, their kernels are cut off", format_count(at_edge)));
+        }
+        (Some(mask_vol), masked_voxels)
     }else {
-        None
+        (None, dims.numel())
     };
+    LoadedInput { vol, intensities, images, mask, masked_voxels, dims, header }
+}
 
-    let vox_to_process = dims.numel() as u64;
+/// bins the intensities of `vol` to gray levels, keeping the intensities alongside if the
+/// mappers need them. Inputs quantized to integer levels keep their levels and fitted bins are
+/// fitted to the input, either replacing the number of bins of `opts` and `binning`
+#[allow(clippy::too_many_arguments)]
+fn bin_input(args: &Args, input_vol: &Path, vol: Vec<f64>, dims: ArrayDim, opts: &mut MapOpts, texture: &TextureOpts, binning: &mut Binning, mask: Option<&[f64]>, cache: Option<&DiscretizedCache>) -> (Vec<f64>, Option<Vec<f64>>) {
+    let keep = texture.maps_intensities();
+    let levels = if args.rebin_integer_input() || binning.needs_fit() || texture.discretization.is_local() { None } else { integer_levels(&vol) };
+    // an explicit --n-bins is honoured, with a note if the input looks binned already
+    let levels = match levels {
+        Some(levels) if args.n_bins.is_some() => {
+            if let Some(note) = integer_levels_rebinned(levels.len(), opts.n_bins) {
+                println!("note: {note}");
+            }
+            None
+        }
+        levels => levels,
+    };
+    if let Some(levels) = levels {
+        let n_levels = levels.len();
+        match integer_levels_warning(n_levels, opts.n_bins) {
+            Some(warning) => println!("{warning}"),
+            None => println!("input is already quantized to {n_levels} integer levels, skipping discretization"),
+        }
+        opts.n_bins = n_levels;
+        binning.n_bins = n_levels;
+        crash::set_options(opts, texture);
+        let gray = level_indices(&vol, &levels);
+        (gray, keep.then_some(vol))
+    }else if binning.needs_fit() {
+        let levels = binning.fit(&vol, mask).unwrap_or_else(|e| fail(FailureKind::Usage, e));
+        println!("the input spans {} of the bins", binning.n_bins);
+        opts.n_bins = binning.n_bins;
+        crash::set_options(opts, texture);
+        (levels_to_f64(&levels), keep.then_some(vol))
+    }else if cache.is_some() || !binning.is_native() {
+        let levels = binning.discretize(&vol);
+        if let Some(cache) = cache {
+            match cache.store(input_vol, binning, &levels, &dims) {
+                Ok(entry) => println!("cached discretized volume to {}", entry.display()),
+                Err(e) => println!("warning: {e}"),
+            }
+        }
+        (levels_to_f64(&levels), keep.then_some(vol))
+    }else {
+        (vol, None)
+    }
+}
 
-    let masked_voxels = mask.as_ref().map(|mask| mask.par_iter().filter(|x| **x != 0.).count()).unwrap_or(dims.numel());
-    let n_features = opts.features.len();
-    println!("launching GLCM mapper for {n_features} feature(s) over {masked_voxels} voxels ...");
+/// maps the features of the binned input, showing the progress of the mappers and aborting on
+/// degenerate maps with --abort-if-degenerate
+#[allow(clippy::too_many_arguments)]
+fn map_input(args: &Args, opts: &MapOpts, texture: &TextureOpts, vol: Vec<f64>, intensities: Option<Vec<f64>>, images: Vec<FilteredImage>, mask: Option<Vec<f64>>, dims: ArrayDim, progress: &Progress) -> FeatureMaps {
+    let vox_to_process = (dims.numel() * n_passes(opts, texture)) as u64;
 
-    let progress = Arc::new(AtomicUsize::new(0));
     let t_progress = progress.clone();
-    let t_dims = dims;
     let t_opts = opts.clone();
-    let now = Instant::now();
+    let t_texture = texture.clone();
+    // the sampled blocks are mapped on their own thread and aborted on from another, as the
+    // full map can't be interrupted
+    let preview = args.abort_if_degenerate.and_then(|max_nan_fraction| {
+        let Some(preview) = Preview::start(opts, texture, &vol, mask.as_deref(), &dims) else {
+            println!("warning: the input is too small to sample, skipping --abort-if-degenerate");
+            return None
+        };
+        let t_preview = preview.clone();
+        let after = args.degenerate_after * 1000;
+        thread::spawn(move ||{
+            if t_preview.wait_for(after) && let Some(problem) = t_preview.degenerate_feature(max_nan_fraction) {
+                fail(FailureKind::DegenerateResult, format!("aborting on degenerate results, {problem}. Check that the mask lines up with the input and the number of bins"));
+            }
+        });
+        Some(preview)
+    });
+    failure::set_stage(Stage::Compute);
     let h = thread::spawn(move||{
-        run_glcm_map(t_opts, vol, mask, t_dims, t_progress)
+        map_features(t_opts, &t_texture, vol, intensities, images, mask, dims, &t_progress)
     });
 
-    if !args.no_progress_bar {
+    if args.json_progress {
+        loop {
+            let val = progress.voxels.done() as u64;
+            println!("{}", progress_line(val.min(vox_to_process), vox_to_process));
+            if val >= vox_to_process {
+                break
+            }
+            thread::sleep(Duration::from_millis(500));
+        }
+    }else if !args.no_progress_bar {
         let pb = ProgressBar::new(vox_to_process);
-        pb.set_style(ProgressStyle::with_template("[{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
+        pb.set_prefix(RunStage::Computing.label());
+        pb.set_style(ProgressStyle::with_template("{prefix:22} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta}) {msg}")
             .unwrap()
             .progress_chars("##-"));
+        let mut memory = MemoryTracker::default();
         while pb.position() < vox_to_process {
-            let val = progress.load(Ordering::Relaxed) as u64;
+            let val = progress.voxels.done() as u64;
             pb.set_position(val);
+            memory.sample();
+            if let Some(mem) = memory.summary() {
+                pb.set_message(mem);
+            }
             thread::sleep(Duration::from_millis(100));
         }
         pb.finish_with_message("all voxels mapped successfully");
         println!();
     }
 
-    let (results,..) = h.join().expect("Failed to join thread");
+    let results = h.join().expect("Failed to join thread").expect("runs of the command line tool are never cancelled");
+    if let Some(preview) = &preview {
+        preview.stop();
+    }
+    results
+}
 
-    let duration = now.elapsed();
-    println!("{} voxels processed in {:.03} minutes", masked_voxels, duration.as_secs_f64() / 60.);
+/// writes the maps of a run along with the reports, exports and provenance asked for, staged
+/// and then committed to the output directory, returning the files committed
+#[allow(clippy::too_many_arguments)]
+fn write_outputs(args: &Args, provenance: &Provenance, results: &FeatureMaps, features: &[(Feature, String)], dims: ArrayDim, header: Header, dicom_source: Option<&(SourceSeries, [[f64; 4]; 3])>, in_mask: Option<&[bool]>, progress: &Progress) -> Vec<PathBuf> {
+    let input_vol = args.input_vol.as_ref().unwrap();
+    let input_stem = input_stem(input_vol);
+    let (output_dir, input_stem) = match args.options_tag {
+        Some(tag) => tag.apply(args.output_dir.as_ref().unwrap(), &input_stem, &provenance.options_hash()),
+        None => (args.output_dir.clone().unwrap(), input_stem),
+    };
+    let output_dir = &output_dir;
+    if !output_dir.is_dir() {
+        std::fs::create_dir(output_dir).unwrap_or_else(|e| fail(FailureKind::WriteFailure, format!("failed to create {}: {e}", output_dir.display())));
+    }
 
     println!("writing outputs to {}",output_dir.display());
+    failure::set_stage(Stage::WriteOutput);
+    let archive_key = args.archive_key();
+    // encrypted outputs are never written into the output directory in the clear
+    let stage_root = match &archive_key {
+        Some(_) => Some(archive::stage_root(args.stage_outputs.as_deref())),
+        None => args.stage_outputs.clone(),
+    };
+    let mut staging = Staging::new(output_dir, stage_root.as_deref()).unwrap_or_else(|e| fail(FailureKind::WriteFailure, e));
+    staging.protect(input_vol);
+    if let Some(mask) = &args.mask {
+        staging.protect(mask);
+    }
+    let header = if args.scrub_phi {
+        scrubbed_header(input_vol).unwrap_or_else(|e| write_failed(&staging, FailureKind::BadInput, e))
+    }else {
+        header
+    };
     let vol_stride = dims.numel();
-    for (&f, alias) in opts.features.iter() {
-        let i = f as usize;
-        let vol = &results[i * vol_stride..(i + 1) * vol_stride];
-        let path = output_dir.join(format!(
-            "{}{}{}",
-            input_stem,
-            "_",
-            alias.to_lowercase().replace(" ", "_")
-        ));
-        write_volume(path, vol, dims, &header);
+    // map data is written once per feature, again for the dicom copies and the tensor
+    let copies = 1 + dicom_source.is_some() as usize + args.export_tensor as usize;
+    let outputs = results.outputs(features);
+    progress.bytes.set_total(outputs.len() * vol_stride * size_of::<f32>() * copies);
+    progress.set_stage(RunStage::Writing);
+    let write_pb = (!args.no_progress_bar && !args.json_progress).then(|| {
+        let pb = ProgressBar::new(progress.bytes.total() as u64);
+        pb.set_prefix(RunStage::Writing.label());
+        pb.set_style(ProgressStyle::with_template("{prefix:22} [{elapsed_precise}] [{bar:40.green/blue}] {bytes}/{total_bytes}")
+            .unwrap()
+            .progress_chars("##-"));
+        pb
+    });
+    for (suffix, vol) in &outputs {
+        let path = output_path(staging.dir(), &input_stem, suffix);
+        write_volume(path, vol, dims, &header).unwrap_or_else(|e| write_failed(&staging, FailureKind::WriteFailure, e));
+        progress.bytes.add(size_of_val(*vol));
+        if let Some((source, voxel_to_lps)) = dicom_source {
+            let path = output_path(staging.dir(), &input_stem, &format!("{suffix}.dcm"));
+            write_parametric_map(&path, source, suffix, vol, dims, *voxel_to_lps).unwrap_or_else(|e| write_failed(&staging, FailureKind::WriteFailure, e));
+            progress.bytes.add(size_of_val(*vol));
+        }
+        progress.on_feature_done(suffix);
+        if let Some(pb) = &write_pb {
+            pb.set_position(progress.bytes.done() as u64);
+        }
+    }
+    if let Some(pb) = write_pb {
+        pb.finish();
+    }
+
+    if args.correlation_report {
+        let correlation = Correlation::compute(&outputs, in_mask);
+        correlation.write(staging.dir(), &input_stem, &args.report.format()).unwrap_or_else(|e| write_failed(&staging, FailureKind::WriteFailure, e));
+        println!("correlations between the feature maps written, over {} voxels", format_count(correlation.n_voxels));
+        for (a, b, r) in correlation.redundant_pairs(args.redundant_above) {
+            println!("  {a} and {b} are near duplicates (r = {r:.3})");
+        }
+    }
+
+    if args.export_tensor {
+        let shape = [0, 1, 2].map(|d| dims.shape_ns()[d]);
+        write_tensor(staging.dir(), &input_stem, &outputs, shape, voxel_spacing(input_vol)).unwrap_or_else(|e| write_failed(&staging, FailureKind::WriteFailure, e));
+        progress.bytes.add(outputs.len() * vol_stride * size_of::<f32>());
+        println!("{} maps stacked into a tensor", outputs.len());
+    }
+
+    if let Some(table) = args.voxel_table {
+        let shape = [0, 1, 2].map(|d| dims.shape_ns()[d]);
+        let (_, n) = write_voxel_table(staging.dir(), &input_stem, &outputs, shape, in_mask, table, &args.report.format()).unwrap_or_else(|e| write_failed(&staging, FailureKind::WriteFailure, e));
+        println!("voxel table of {} rows written", format_count(n));
+    }
+
+    if let Some(grid) = args.patch_grid() {
+        // the patches hold the intensities, not the gray levels mapped
+        let (input, ..) = read_volume(input_vol);
+        let shape = [0, 1, 2].map(|d| dims.shape_ns()[d]);
+        let n = write_patches(staging.dir(), &input_stem, grid, shape, &input, in_mask, &outputs, &args.report.format()).unwrap_or_else(|e| write_failed(&staging, FailureKind::WriteFailure, e));
+        println!("{n} patches of {0}x{0}x{0} voxels written", grid.size);
+    }
+
+    if args.slicer_scene {
+        let suffixes: Vec<String> = outputs.iter().map(|(suffix, _)| suffix.clone()).collect();
+        write_slicer_scene(staging.dir(), &input_stem, input_vol, args.mask.as_deref(), &suffixes).unwrap_or_else(|e| write_failed(&staging, FailureKind::WriteFailure, e));
+    }
+
+    if let Err(e) = provenance.write(staging.dir(), &input_stem) {
+        println!("warning: {e}");
+    }
+    if let Some(key) = &archive_key {
+        let archive = encrypt_dir(staging.dir(), &input_stem, key).unwrap_or_else(|e| write_failed(&staging, FailureKind::WriteFailure, e));
+        println!("outputs encrypted into {}", archive.file_name().unwrap().to_string_lossy());
+    }
+    let retry = RetryPolicy { attempts: args.write_retries + 1, ..Default::default() };
+    let written = staging.commit(&retry).unwrap_or_else(|e| fail(FailureKind::WriteFailure, e));
+
+    if let Some(db) = &args.study_db {
+        match StudyDb::open(db).and_then(|mut db| db.append(provenance, output_dir, &outputs, in_mask)) {
+            Ok(id) => println!("statistics of {} maps appended to {} as case {id}", outputs.len(), db.display()),
+            Err(e) => println!("warning: {e}"),
+        }
+    }
+    written
+}
+
+fn run_fetch(session: &XnatSession, run: &Path, scans: &[String], work_dir: Option<&Path>, upload: bool) {
+    let run_args = config_args(run).unwrap_or_else(|e| fail(FailureKind::Usage, e));
+    // check the options against the parser before downloading anything
+    let check = ["radmap", "input.nii.gz", "output"].into_iter().map(String::from).chain(run_args.iter().cloned());
+    if let Err(e) = Args::try_parse_from(check) {
+        let _ = e.print();
+        fail(FailureKind::Usage, format!("invalid options in {}", run.display()));
+    }
+    let client = XnatClient::from_env().unwrap_or_else(|e| fail(FailureKind::Usage, e));
+
+    let work_dir = work_dir.map(Path::to_path_buf).unwrap_or_else(|| std::env::temp_dir().join(format!("radmap-xnat-{}", session.session)));
+    let dicom_dir = work_dir.join("dicom");
+    let nifti_dir = work_dir.join("nifti");
+    let results_dir = work_dir.join("results");
+
+    failure::set_stage(Stage::LoadInput);
+    println!("downloading {session} to {}", dicom_dir.display());
+    client.download_scans(session, scans, &dicom_dir).unwrap_or_else(|e| fail(FailureKind::BadInput, e));
+    let volumes = convert_dicom(&dicom_dir, &nifti_dir).unwrap_or_else(|e| fail(FailureKind::BadInput, e));
+    if volumes.is_empty() {
+        fail(FailureKind::BadInput, format!("no series of {session} could be converted"));
+    }
+
+    std::fs::create_dir_all(&results_dir).unwrap_or_else(|e| fail(FailureKind::WriteFailure, format!("failed to create {}: {e}", results_dir.display())));
+    let exe = std::env::current_exe().expect("failed to locate the radmap executable");
+    for volume in &volumes {
+        println!("processing {}", volume.display());
+        let status = Command::new(&exe).arg(volume).arg(&results_dir).args(&run_args).status().expect("failed to launch radmap");
+        if !status.success() {
+            std::process::exit(status.code().unwrap_or(1));
+        }
+    }
+    println!("results written to {}", results_dir.display());
+
+    if upload {
+        failure::set_stage(Stage::WriteOutput);
+        let label = format!("{}_radmap", session.session);
+        client.upload_assessor(session, &label, &results_dir).unwrap_or_else(|e| fail(FailureKind::WriteFailure, e));
+        println!("results uploaded to {session} as assessor {label}");
+    }
+}
+
+fn run_container(in_dir: &Path, out_dir: &Path, config: &Path, allow_in_place: bool) {
+    let exit = |kind: FailureKind, msg: &str| -> ! {
+        log_event("error", "failed", &[("kind", kind.as_str()), ("message", msg)]);
+        std::process::exit(kind.exit_code())
+    };
+    if !out_dir.is_dir() {
+        exit(FailureKind::Usage, &format!("output directory {} is not mounted", out_dir.display()));
+    }
+    let run_args = if config.is_file() {
+        config_args(config).unwrap_or_else(|e| exit(FailureKind::Usage, &e))
+    }else {
+        vec!["--all-features".to_string()]
+    };
+    let cases = find_cases(in_dir, out_dir).unwrap_or_else(|e| exit(FailureKind::BadInput, &e));
+    log_event("info", "started", &[("cases", &cases.len().to_string()), ("options", &run_args.join(" "))]);
+
+    let exe = std::env::current_exe().expect("failed to locate the radmap executable");
+    let mut first_failure = None;
+    for case in &cases {
+        let input = case.input_vol.to_string_lossy();
+        log_event("info", "case_start", &[("input", &input)]);
+        let mut cmd = Command::new(&exe);
+        cmd.arg(&case.input_vol).arg(&case.output_dir);
+        if let Some(mask) = &case.mask {
+            cmd.arg("--mask").arg(mask);
+        }
+        cmd.args(&run_args).arg("--no-progress-bar");
+        if allow_in_place {
+            cmd.arg("--allow-in-place");
+        }
+        let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().expect("failed to launch radmap");
+
+        // relay the output of the case as log lines
+        let relays: Vec<_> = [("info", child.stdout.take().map(|s| Box::new(s) as Box<dyn std::io::Read + Send>)), ("warn", child.stderr.take().map(|s| Box::new(s) as Box<dyn std::io::Read + Send>))]
+            .into_iter()
+            .filter_map(|(level, stream)| stream.map(|s| (level, s)))
+            .map(|(level, stream)| {
+                let input = input.to_string();
+                thread::spawn(move || {
+                    for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                        log_event(level, "output", &[("input", &input), ("line", &line)]);
+                    }
+                })
+            })
+            .collect();
+        let status = child.wait().expect("failed to wait on radmap");
+        for relay in relays {
+            let _ = relay.join();
+        }
+
+        let kind = match status.code() {
+            Some(0) => None,
+            Some(code) => Some(FailureKind::from_exit_code(code).unwrap_or(FailureKind::Internal)),
+            None => Some(FailureKind::Cancelled),
+        };
+        match kind {
+            None => log_event("info", "case_done", &[("input", &input)]),
+            Some(kind) => {
+                log_event("error", "case_failed", &[("input", &input), ("kind", kind.as_str())]);
+                first_failure.get_or_insert(kind);
+            }
+        }
     }
+
+    match first_failure {
+        None => log_event("info", "finished", &[("status", "succeeded")]),
+        Some(kind) => {
+            log_event("error", "finished", &[("status", "failed"), ("kind", kind.as_str())]);
+            std::process::exit(kind.exit_code());
+        }
+    }
+}
+
+/// prints the error and exits with the code for its kind
+fn fail(kind: FailureKind, msg: String) -> ! {
+    eprintln!("error: {msg}");
+    failure::exit_with(kind, msg)
+}
+
+/// ends the run like [`fail`], removing the outputs staged so far
+fn write_failed(staging: &Staging, kind: FailureKind, msg: String) -> ! {
+    staging.discard();
+    fail(kind, msg)
 }
 
-enum Header {
-    Nrrd(Box<NRRD>),
-    Nifti(Box<NiftiHeader>),
+fn run_wizard() {
+    let mut wizard = Wizard::new(std::io::stdin().lock(), std::io::stdout());
+    let Some(wizard_args) = wizard.run() else {
+        return
+    };
+    let command:Vec<String> = std::iter::once("radmap".to_string()).chain(wizard_args.iter().map(|a| shell_quote(a))).collect();
+    println!();
+    println!("{}", command.join(" "));
+    println!();
+    if wizard.confirm("run it now?", true) == Some(true) {
+        let exe = std::env::current_exe().expect("failed to locate the radmap executable");
+        let status = Command::new(exe).args(&wizard_args).status().expect("failed to launch radmap");
+        std::process::exit(status.code().unwrap_or(1));
+    }
+}
+
+fn manage_usage_stats(action: Option<UsageStatsAction>, export: Option<&Path>) {
+    match action {
+        Some(UsageStatsAction::Enable) => {
+            let file = usage::enable().unwrap_or_else(|e| fail(FailureKind::WriteFailure, format!("failed to enable usage statistics: {e}")));
+            println!("usage statistics enabled, stored locally in {}", file.display());
+        }
+        Some(UsageStatsAction::Disable) => {
+            usage::disable().unwrap_or_else(|e| fail(FailureKind::WriteFailure, format!("failed to disable usage statistics: {e}")));
+            println!("usage statistics disabled and deleted");
+        }
+        Some(UsageStatsAction::Show) => {
+            match usage::usage_file().filter(|_| usage::is_enabled()) {
+                Some(file) => {
+                    println!("usage statistics stored in {}", file.display());
+                    let stats = UsageStats::load(&file).unwrap_or_else(|e| fail(FailureKind::Internal, e));
+                    for (k, v) in stats.counters {
+                        println!("{k}: {v}");
+                    }
+                }
+                None => println!("usage statistics are disabled"),
+            }
+        }
+        None => {}
+    }
+
+    if let Some(export) = export {
+        let file = usage::usage_file().filter(|_| usage::is_enabled()).unwrap_or_else(|| fail(FailureKind::Usage, "usage statistics are disabled, nothing to export".to_string()));
+        let stats = UsageStats::load(&file).unwrap_or_else(|e| fail(FailureKind::Internal, e));
+        stats.export_csv(export).unwrap_or_else(|e| fail(FailureKind::WriteFailure, e));
+        println!("usage statistics exported to {}", export.display());
+    }
 }
 
-fn read_volume(path:impl AsRef<Path>) -> (Vec<f64>, ArrayDim, Header) {
-    let vol_path = path.as_ref().to_path_buf();
+fn run_history(query: &HistoryQuery, show: Option<i64>) {
+    let history = History::open_default().unwrap_or_else(|e| fail(FailureKind::Internal, e));
+    if let Some(id) = show {
+        let run = history.get(id).unwrap_or_else(|e| fail(FailureKind::Internal, e)).unwrap_or_else(|| fail(FailureKind::Usage, format!("no run {id} in the history")));
+        print_run(&run);
+        return
+    }
+    let runs = history.query(query).unwrap_or_else(|e| fail(FailureKind::Internal, e));
+    if runs.is_empty() {
+        println!("no matching runs");
+        return
+    }
+    for run in &runs {
+        let input = run.input.as_deref().unwrap_or("(removed)");
+        println!("{:>5}  {}  {:<9}  {}  {input} -> {}", run.id, format_utc(run.started_unix_s as u64), run.status.as_str(), run.options_hash, run.output_dir);
+    }
+}
 
-    let f_ext = vol_path.extension().expect("file has no extension").to_str().unwrap();
+fn print_run(run: &RunRecord) {
+    println!("run {}", run.id);
+    println!("app: {} {}", run.app, run.radmap_version);
+    println!("started: {}", format_utc(run.started_unix_s as u64));
+    match run.duration_s() {
+        Some(duration) => println!("duration: {:.1} minutes", duration / 60.),
+        None => println!("duration: still running or interrupted"),
+    }
+    println!("status: {}", run.status);
+    if let Some(kind) = &run.failure_kind {
+        println!("failure: {kind}: {}", run.message.as_deref().unwrap_or(""));
+    }
+    println!("input: {}", run.input.as_deref().unwrap_or("(removed)"));
+    println!("mask: {}", run.mask.as_deref().unwrap_or(if run.input.is_none() { "(removed)" } else { "none" }));
+    println!("output directory: {}", run.output_dir);
+    println!("options hash: {}", run.options_hash);
+    println!("options: {}", run.options);
+    println!("outputs:");
+    for output in &run.outputs {
+        println!("  {}", output.display());
+    }
+}
 
-    if f_ext == "nii" || f_ext == "nii.gz" {
-        let (data, dims, header) = io_nifti::read_nifti::<f64>(vol_path);
-        (data, dims, Header::Nifti(Box::new(header)))
-    } else if f_ext == "nhdr" || f_ext == "nrrd" {
-        let (data, dims, header) = io_nrrd::read_nrrd(vol_path);
-        (data, dims, Header::Nrrd(Box::new(header)))
+fn run_diff(a: &str, b: &str, all: bool) {
+    let a = RunSettings::load(a).unwrap_or_else(|e| fail(FailureKind::Usage, e));
+    let b = RunSettings::load(b).unwrap_or_else(|e| fail(FailureKind::Usage, e));
+    println!("a: {}", a.label);
+    println!("b: {}", b.label);
+    let fields = diff::compare(&a, &b);
+    let n_differing = fields.iter().filter(|f| f.differs()).count();
+    for field in fields.iter().filter(|f| all || f.differs()) {
+        let marker = if field.differs() { "*" } else { " " };
+        println!("{marker} {}:", field.name);
+        println!("    a: {}", field.a);
+        println!("    b: {}", field.b);
+    }
+    match n_differing {
+        0 => println!("the runs have the same settings and inputs"),
+        n => println!("{n} setting(s) differ"),
+    }
+}
+
+fn run_features(json: bool, family: Option<Family>) {
+    let catalog: Vec<FeatureInfo> = catalog().into_iter().filter(|f| family.is_none_or(|family| f.family == family)).collect();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&catalog).expect("the catalog is always representable as json"));
+        return
+    }
+    for family in Family::ALL.into_iter().filter(|f| family.is_none_or(|family| *f == family)) {
+        println!("{} (cost: {}):", family.label(), family.cost());
+        for info in catalog.iter().filter(|f| f.family == family) {
+            let ibsi = info.ibsi.map(|code| format!(", IBSI {code}")).unwrap_or_default();
+            println!("  {} {}{ibsi}", info.name, info.range);
+            println!("      {}", info.description);
+        }
+    }
+}
+
+fn run_shape(masks: &[PathBuf], output: Option<&Path>, json: bool, format: &ReportFormat) {
+    let mut reports = vec![];
+    for mask in masks {
+        if voxel_spacing(mask).is_none() {
+            eprintln!("warning: no voxel spacing in {}, using 1 along every axis", mask.display());
+        }
+        reports.push(ShapeReport::compute(mask).unwrap_or_else(|e| fail(FailureKind::BadMask, e)));
+    }
+    let json = json || output.is_some_and(|o| o.extension().is_some_and(|e| e.eq_ignore_ascii_case("json")));
+    let text = if json {
+        serde_json::to_string_pretty(&reports).expect("shape reports are always representable as json") + "\n"
+    } else {
+        let mut text = ShapeReport::csv_header(format) + "\n";
+        for report in &reports {
+            text.push_str(&report.csv_row(format));
+            text.push('\n');
+        }
+        text
+    };
+    match output {
+        Some(path) => {
+            std::fs::write(path, text).unwrap_or_else(|e| fail(FailureKind::WriteFailure, format!("failed to write {}: {e}", path.display())));
+            println!("shape features written to {}", path.display());
+        }
+        None => print!("{text}"),
+    }
+}
+
+fn run_rank(labels: &Path, maps: &[PathBuf], positive: Option<i64>, output: Option<&Path>, json: bool, format: &ReportFormat) {
+    if !labels.is_file() {
+        fail(FailureKind::BadMask, format!("label map {} does not exist", labels.display()));
+    }
+    let maps = feature_map_files(maps).unwrap_or_else(|e| fail(FailureKind::BadInput, e));
+    // a label map in a directory of maps is not a feature
+    let maps: Vec<PathBuf> = maps.into_iter().filter(|m| !m.canonicalize().is_ok_and(|m| labels.canonicalize().is_ok_and(|l| l == m))).collect();
+    if let Some(missing) = maps.iter().find(|m| !m.is_file()) {
+        fail(FailureKind::BadInput, format!("feature map {} does not exist", missing.display()));
+    }
+    if maps.is_empty() {
+        fail(FailureKind::Usage, "no feature maps to rank".to_string());
+    }
+    let ranking = Ranking::compute(labels, &maps, positive).unwrap_or_else(|e| fail(FailureKind::BadInput, e));
+    let counts: Vec<String> = ranking.label_counts.iter().map(|(l, n)| format!("{l}: {}", format_count(*n))).collect();
+    eprintln!("voxels per label {}, AUC of label {} against the others, mutual information over {MI_BINS} bins", counts.join(", "), ranking.positive);
+    let json = json || output.is_some_and(|o| o.extension().is_some_and(|e| e.eq_ignore_ascii_case("json")));
+    let text = if json {
+        serde_json::to_string_pretty(&ranking).expect("rankings are always representable as json") + "\n"
     } else {
-        panic!("Only nrrds and niftis are supported. Sorry :)");
+        ranking.to_csv(format)
+    };
+    match output {
+        Some(path) => {
+            std::fs::write(path, text).unwrap_or_else(|e| fail(FailureKind::WriteFailure, format!("failed to write {}: {e}", path.display())));
+            println!("ranking of {} feature maps written to {}", ranking.features.len(), path.display());
+        }
+        None => print!("{text}"),
     }
 }
 
-fn write_volume(path:impl AsRef<Path>, vol:&[f32], vol_dims:ArrayDim, header:&Header) {
+fn run_explain(name: &str) {
+    let feature = Feature::from_str(name).or_else(|e| {
+        // e.g. `coarseness` for `ngtdm_coarseness`
+        let suffix = format!("_{}", name.to_lowercase());
+        let matches: Vec<Feature> = Feature::all().into_iter().filter(|f| f.to_string().ends_with(&suffix)).collect();
+        match matches.as_slice() {
+            [f] => Ok(*f),
+            [] => Err(e),
+            _ => {
+                let names: Vec<String> = matches.iter().map(|f| f.to_string()).collect();
+                Err(format!("{name} is a feature of several families, pick one of {}", names.join(", ")))
+            }
+        }
+    }).unwrap_or_else(|e| fail(FailureKind::Usage, e));
+    let info = feature.info();
+    println!("{} ({})", info.name, info.family.label());
+    println!();
+    println!("{}", info.description);
+    println!();
+    println!("formula: {}", info.reference);
+    println!("IBSI: {}", info.ibsi.unwrap_or("none"));
+    println!("range: {}", info.range);
+    println!("cost: {}", info.cost);
+    if !info.caveats.is_empty() {
+        println!();
+        println!("caveats:");
+        for caveat in &info.caveats {
+            println!("  - {caveat}");
+        }
+    }
+}
 
-    match &header {
-        Header::Nrrd(nhdr) => {
-            write_nrrd(path, vol, vol_dims, Some(nhdr), false, Encoding::raw)
+fn run_protocol(action: &ProtocolCmd) {
+    match action {
+        ProtocolCmd::Keygen { stem } => {
+            let (key, public_key) = protocol::generate_key(stem).unwrap_or_else(|e| fail(FailureKind::WriteFailure, e));
+            println!("signing key written to {}, keep it private", key.display());
+            println!("public key written to {}, copy it to the site directory as {}", public_key.display(), protocol::PUBLIC_KEY_FILE);
         }
-        Header::Nifti(nii) => write_nifti_with_header(path, vol, vol_dims, nii),
+        ProtocolCmd::Sign { protocol, key } => {
+            let sig = protocol::sign(protocol, key).unwrap_or_else(|e| fail(FailureKind::Usage, e));
+            println!("signature written to {}", sig.display());
+        }
+        ProtocolCmd::Verify { protocol, public_key } => {
+            let site_dir = protocol::site_dir();
+            let in_site_dir = |file: &str| site_dir.as_ref().map(|d| d.join(file)).unwrap_or_else(|| fail(FailureKind::Usage, "unable to determine the site directory".to_string()));
+            let protocol = protocol.clone().unwrap_or_else(|| in_site_dir(protocol::PROTOCOL_FILE));
+            let public_key = public_key.clone().unwrap_or_else(|| in_site_dir(protocol::PUBLIC_KEY_FILE));
+            let p = Protocol::read_signed(&protocol, &public_key).unwrap_or_else(|e| fail(FailureKind::Usage, e));
+            println!("{} is signed by {}", protocol.display(), public_key.display());
+            println!("name: {}", p.name);
+            println!("number of bins: {}", p.n_bins);
+            println!("kernel radius: {}", p.kernel_radius);
+            println!("bin edges: {}", p.bin_edges);
+            println!("re-bin integer inputs: {}", p.rebin_integer_input);
+            let features: Vec<String> = p.features.iter().map(|f| f.to_string().to_lowercase()).collect();
+            println!("features: {}", features.join(", "));
+        }
+    }
+}
+
+/// the run in the output directory of `case` that `expected` would repeat, for --incremental
+fn up_to_date_run(args: &Args, case: &Case, mut expected: Provenance) -> Option<PreviousRun> {
+    // runs tagged with their options in a directory of their own are only looked for there
+    let find = |expected: &Provenance| match args.options_tag {
+        Some(OptionsTag::Dir) => {
+            let (dir, _) = OptionsTag::Dir.apply(&case.output_dir, OsStr::new(""), &expected.options_hash());
+            find_up_to_date_run(&dir, expected)
+        }
+        _ => find_up_to_date_run(&case.output_dir, expected),
     };
+    if let Some(run) = find(&expected) {
+        return Some(run);
+    }
+    // a quantized input is mapped with a bin per level whatever the number of bins asked for, and
+    // bins fixed in intensity or following the mask are fitted to the input, so look at the
+    // input, but only if the directory has a run of it to begin with
+    let mut dirs = vec![case.output_dir.clone()];
+    if args.options_tag == Some(OptionsTag::Dir) {
+        let entries = std::fs::read_dir(&case.output_dir).ok()?;
+        dirs.extend(entries.filter_map(|e| e.ok().map(|e| e.path())).filter(|p| p.is_dir()));
+    }
+    let same_input = dirs.iter().filter_map(|d| find_previous_runs(d).ok()).flatten().any(|r| {
+        r.provenance.input_hash.is_some() && r.provenance.input_hash == expected.input_hash
+    });
+    let fit = expected.binning.needs_fit();
+    // kernels binned on their own are never taken as quantized
+    if ((args.rebin_integer_input() || args.n_bins.is_some() || expected.discretization.is_local()) && !fit) || !same_input {
+        return None;
+    }
+    // an input or mask that can't be read is left for the run itself to report
+    let (vol, ..) = try_read_volume(&case.input_vol).ok()?;
+    if fit {
+        let mask = case.mask.as_ref().filter(|_| expected.binning.from_mask);
+        let mask = mask.map(|m| try_read_volume(m).map(|(m, ..)| m)).transpose().ok()?;
+        expected.binning.fit(&vol, mask.as_deref()).ok()?;
+    } else {
+        expected.binning.n_bins = integer_levels(&vol)?.len();
+    }
+    find(&expected)
+}
 
-}
\ No newline at end of file
+fn run_batch(args: &Args, cases_file: &Path) {
+    let cases = read_cases(cases_file).unwrap_or_else(|e| fail(FailureKind::Usage, e));
+    let manifest_path = args.batch_manifest.clone().unwrap_or_else(|| {
+        let stem = cases_file.file_stem().unwrap_or_default().to_string_lossy();
+        cases_file.with_file_name(format!("{stem}_manifest.csv"))
+    });
+    let mut manifest = BatchManifest::create(&manifest_path, args.report.format()).unwrap_or_else(|e| fail(FailureKind::WriteFailure, e));
+    let exe = std::env::current_exe().expect("failed to locate the radmap executable");
+    let watchdog = Watchdog {
+        timeout: args.timeout_per_case,
+        memory_budget: args.memory_budget,
+    };
+    if let Some(budget) = args.memory_budget {
+        println!("memory budget per case: {}", format_bytes(budget));
+    }
+
+    // what a run of each case would record, to find the cases already done
+    let expected = args.incremental.then(|| {
+        let (opts, texture) = args.map_opts_builder().build().unwrap_or_else(|e| fail(FailureKind::Usage, e));
+        let binning = args.binning(opts.n_bins);
+        move |case: &Case| Provenance::new("radmap", &case.input_vol, case.mask.as_deref(), &opts, &texture, binning)
+    });
+
+    let n_cases = cases.len();
+    let mut n_failed = 0;
+    let mut n_skipped = 0;
+    let mut pipelined = vec![];
+    for (i, case) in cases.iter().enumerate() {
+        println!("case {}/{n_cases}: {}", i + 1, case.input_vol.display());
+        if let Some(expected) = &expected && let Some(run) = up_to_date_run(args, case, expected(case)) {
+            println!("case {} is up to date with {}, skipped", i + 1, run.sidecar.display());
+            let outcome = CaseOutcome {
+                status: CaseStatus::Skipped,
+                exit_code: None,
+                elapsed: Duration::ZERO,
+                message: format!("up to date with {}", run.sidecar.display()),
+            };
+            manifest.record(case, &outcome).unwrap_or_else(|e| fail(FailureKind::WriteFailure, e));
+            n_skipped += 1;
+            continue;
+        }
+        if args.pipeline {
+            pipelined.push(case.clone());
+            continue;
+        }
+        let mut max_threads = args.max_threads;
+        let mut outcome = loop {
+            let mut cmd = Command::new(&exe);
+            cmd.args(args.case_args(case, max_threads));
+            let outcome = watchdog.run(cmd);
+            let threads = max_threads.unwrap_or_else(current_num_threads);
+            if outcome.status != CaseStatus::OutOfMemory || !args.retry_fewer_threads || threads <= 1 {
+                break outcome;
+            }
+            let reduced = threads / 2;
+            println!("case {} ran out of memory with {threads} thread(s) ({}), retrying with {reduced}", i + 1, outcome.message);
+            manifest.record(case, &outcome).unwrap_or_else(|e| fail(FailureKind::WriteFailure, e));
+            max_threads = Some(reduced);
+        };
+        if max_threads != args.max_threads && outcome.status == CaseStatus::Succeeded {
+            outcome.message = format!("downgraded to {} thread(s) after running out of memory", max_threads.unwrap());
+        }
+        match outcome.status {
+            CaseStatus::Succeeded => {},
+            CaseStatus::Failed => println!("case {} failed with exit code {:?} {}", i + 1, outcome.exit_code, outcome.message),
+            CaseStatus::TimedOut => println!("case {} cancelled: {}", i + 1, outcome.message),
+            CaseStatus::OutOfMemory => println!("case {} ran out of memory: {}", i + 1, outcome.message),
+            CaseStatus::Skipped => unreachable!("cases are skipped before they run"),
+        }
+        if outcome.status != CaseStatus::Succeeded {
+            n_failed += 1;
+        }
+        // a case killed by the watchdog never got the chance to summarize its own failure
+        let killed = match outcome.status {
+            CaseStatus::TimedOut => Some(FailureKind::Cancelled),
+            CaseStatus::OutOfMemory if outcome.exit_code.is_none() => Some(FailureKind::OutOfMemory),
+            _ => None,
+        };
+        if let Some(kind) = killed && case.output_dir.is_dir() {
+            let summary = FailureSummary { kind, stage: None, message: outcome.message.clone(), crash_report: None };
+            if let Err(e) = summary.write(&case.output_dir) {
+                println!("warning: {e}");
+            }
+        }
+        manifest.record(case, &outcome).unwrap_or_else(|e| fail(FailureKind::WriteFailure, e));
+    }
+
+    if !pipelined.is_empty() {
+        println!("loading, mapping and writing {} case(s) in a pipeline ...", pipelined.len());
+        pipeline::run(&args.case_stages(), pipelined, |case, result, elapsed| {
+            let outcome = match result {
+                Ok(()) => {
+                    println!("case {} done in {}", case.input_vol.display(), format_duration(elapsed));
+                    CaseOutcome { status: CaseStatus::Succeeded, exit_code: Some(0), elapsed, message: String::new() }
+                }
+                Err(failure) => {
+                    println!("case {} failed: {}", case.input_vol.display(), failure.message);
+                    n_failed += 1;
+                    if case.output_dir.is_dir() {
+                        let summary = FailureSummary { kind: failure.kind(), stage: Some(failure.stage), message: failure.message.clone(), crash_report: None };
+                        if let Err(e) = summary.write(&case.output_dir) {
+                            println!("warning: {e}");
+                        }
+                    }
+                    CaseOutcome { status: CaseStatus::Failed, exit_code: Some(failure.kind().exit_code()), elapsed, message: failure.message }
+                }
+            };
+            manifest.record(&case, &outcome).unwrap_or_else(|e| fail(FailureKind::WriteFailure, e));
+        });
+    }
+
+    if args.incremental {
+        println!("batch finished: {} of {n_cases} case(s) succeeded, {n_skipped} of them already up to date", n_cases - n_failed);
+    } else {
+        println!("batch finished: {} of {n_cases} case(s) succeeded", n_cases - n_failed);
+    }
+    println!("batch manifest written to {}", manifest_path.display());
+}
@@ -0,0 +1,146 @@
+//! On-disk cache of discretized volumes.
+//!
+//! Parameter sweeps over features and kernel sizes re-read and re-bin the same input every run.
+//! Cached gray levels are keyed by the input file (path, size and modification time) and the
+//! binning settings, and are stored as 16 bit levels which are a fraction of the size of the input.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use array_lib::ArrayDim;
+
+use crate::discretize::Binning;
+
+const MAGIC: &[u8; 8] = b"RADMAPLV";
+/// bump when the binning or file layout changes so stale entries are never reused
+const FORMAT_VERSION: u32 = 1;
+
+pub struct DiscretizedCache {
+    dir: PathBuf,
+}
+
+impl DiscretizedCache {
+    pub fn new(dir: impl AsRef<Path>) -> Result<Self, String> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("failed to create cache directory {}: {e}", dir.display()))?;
+        Ok(DiscretizedCache {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// cache file for an input and binning, or None if the input can't be inspected
+    pub fn entry_path(&self, input: &Path, binning: &Binning) -> Option<PathBuf> {
+        let input = input.canonicalize().ok()?;
+        let meta = std::fs::metadata(&input).ok()?;
+        let mtime = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+
+        let mut hash = Fnv1a::default();
+        hash.write(input.as_os_str().as_encoded_bytes());
+        hash.write(&meta.len().to_le_bytes());
+        hash.write(&mtime.to_le_bytes());
+        hash.write(&binning.key_bytes());
+        hash.write(&FORMAT_VERSION.to_le_bytes());
+        Some(self.dir.join(format!("{:016x}.levels", hash.0)))
+    }
+
+    /// gray levels and shape of a previously cached input, if there is a valid entry
+    pub fn load(&self, input: &Path, binning: &Binning) -> Option<(Vec<u16>, ArrayDim)> {
+        let path = self.entry_path(input, binning)?;
+        let mut r = BufReader::new(File::open(path).ok()?);
+
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic).ok()?;
+        let version = read_u32(&mut r)?;
+        let cached_bins = read_u32(&mut r)?;
+        if &magic != MAGIC || version != FORMAT_VERSION || cached_bins as usize != binning.n_bins {
+            return None;
+        }
+        let ndim = read_u32(&mut r)? as usize;
+        let shape = (0..ndim)
+            .map(|_| read_u64(&mut r).map(|s| s as usize))
+            .collect::<Option<Vec<_>>>()?;
+        let numel: usize = shape.iter().product();
+
+        let mut raw = vec![0u8; numel * 2];
+        r.read_exact(&mut raw).ok()?;
+        let levels = raw
+            .chunks_exact(2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        Some((levels, ArrayDim::from_shape(&shape)))
+    }
+
+    /// writes the gray levels of an input. The entry is written to a temporary file first so
+    /// concurrent runs never see a partial entry
+    pub fn store(
+        &self,
+        input: &Path,
+        binning: &Binning,
+        levels: &[u16],
+        dims: &ArrayDim,
+    ) -> Result<PathBuf, String> {
+        let path = self
+            .entry_path(input, binning)
+            .ok_or_else(|| format!("failed to inspect {}", input.display()))?;
+        let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+        let write = || -> std::io::Result<()> {
+            let mut w = BufWriter::new(File::create(&tmp)?);
+            w.write_all(MAGIC)?;
+            w.write_all(&FORMAT_VERSION.to_le_bytes())?;
+            w.write_all(&(binning.n_bins as u32).to_le_bytes())?;
+            let shape = dims.shape();
+            w.write_all(&(shape.len() as u32).to_le_bytes())?;
+            for s in shape {
+                w.write_all(&(*s as u64).to_le_bytes())?;
+            }
+            for l in levels {
+                w.write_all(&l.to_le_bytes())?;
+            }
+            w.flush()?;
+            std::fs::rename(&tmp, &path)
+        };
+        write().map_err(|e| {
+            let _ = std::fs::remove_file(&tmp);
+            format!("failed to write cache entry {}: {e}", path.display())
+        })?;
+        Ok(path)
+    }
+}
+
+fn read_u32(r: &mut impl Read) -> Option<u32> {
+    let mut b = [0u8; 4];
+    r.read_exact(&mut b).ok()?;
+    Some(u32::from_le_bytes(b))
+}
+
+fn read_u64(r: &mut impl Read) -> Option<u64> {
+    let mut b = [0u8; 8];
+    r.read_exact(&mut b).ok()?;
+    Some(u64::from_le_bytes(b))
+}
+
+/// 64 bit FNV-1a, used over `DefaultHasher` because cache keys must be stable across builds
+pub(crate) struct Fnv1a(pub(crate) u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf29ce484222325)
+    }
+}
+
+impl Fnv1a {
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
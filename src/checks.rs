@@ -0,0 +1,84 @@
+//! Sanity checks on the inputs of a run. By default a failed check prints a warning and the run
+//! carries on. With `--strict` the first one ends the run, for pipelines that would rather fail
+//! fast than find out from the maps.
+
+use std::path::Path;
+
+use array_lib::ArrayDim;
+
+use crate::failure::{self, FailureKind};
+use crate::io::{read_header, voxel_to_lps};
+
+/// largest difference in mm between the voxel to patient transforms of the input and the mask
+/// still taken as the same geometry
+const GEOMETRY_TOLERANCE_MM: f64 = 1e-3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    /// the input has NaN or infinite voxels
+    NonFinite,
+    /// the mask has no voxels set
+    EmptyMask,
+    /// the mask is placed or oriented differently from the input
+    Orientation,
+    /// voxels in the mask whose kernel reaches past the edge of the volume
+    EdgeKernels,
+}
+
+impl Check {
+    /// how a run ends when this check fails in strict mode
+    pub fn failure_kind(&self) -> FailureKind {
+        match self {
+            Check::NonFinite => FailureKind::BadInput,
+            Check::EmptyMask | Check::Orientation | Check::EdgeKernels => FailureKind::BadMask,
+        }
+    }
+}
+
+/// prints a warning for a failed check, or ends the run if `strict`
+pub fn warn(strict: bool, check: Check, message: &str) {
+    if strict {
+        eprintln!("error: {message}");
+        failure::exit_with(check.failure_kind(), format!("{message} (--strict)"));
+    }
+    println!("warning: {message}");
+}
+
+/// number of NaN or infinite voxels
+pub fn non_finite_voxels(vol: &[f64]) -> usize {
+    vol.iter().filter(|x| !x.is_finite()).count()
+}
+
+/// number of voxels in the mask within the kernel radius of the edge of the volume, whose
+/// kernels are cut off
+pub fn edge_voxels(mask: &[f64], dims: &ArrayDim, kernel_radius: usize) -> usize {
+    let shape = &dims.shape_ns()[0..3];
+    let near_edge = |i: usize, n: usize| i < kernel_radius || i + kernel_radius >= n;
+    (0..shape.iter().product::<usize>())
+        .filter(|&i| mask[i] != 0.)
+        .filter(|&i| {
+            near_edge(i % shape[0], shape[0])
+                || near_edge(i / shape[0] % shape[1], shape[1])
+                || near_edge(i / (shape[0] * shape[1]), shape[2])
+        })
+        .count()
+}
+
+/// how far apart the voxel grids of the input and mask are, if they differ. None if they line
+/// up or either header has no patient space orientation
+pub fn orientation_mismatch(input: &Path, mask: &Path) -> Option<String> {
+    let input_lps = read_header(input).ok().as_ref().and_then(voxel_to_lps)?;
+    let mask_lps = read_header(mask).ok().as_ref().and_then(voxel_to_lps)?;
+    let max_diff = input_lps
+        .iter()
+        .flatten()
+        .zip(mask_lps.iter().flatten())
+        .map(|(a, b)| (a - b).abs())
+        .fold(0., f64::max);
+    (max_diff > GEOMETRY_TOLERANCE_MM).then(|| {
+        format!(
+            "mask {} is placed or oriented differently from the input (transforms differ by up to {max_diff:.3} mm)",
+            mask.display()
+        )
+    })
+}
@@ -0,0 +1,53 @@
+//! Run configuration files: a toml table of command line options, so a pipeline can be written
+//! down once and reused by launchers like `radmap fetch`.
+//!
+//! Keys are the long option names (`n_bins` or `n-bins`). `true` turns a flag on, arrays repeat
+//! an option and anything else is passed as the option's value:
+//!
+//! ```toml
+//! n_bins = 32
+//! kernel_radius = 2
+//! feature = ["contrast", "energy"]
+//! no_progress_bar = true
+//! ```
+
+use std::path::Path;
+
+use toml::{Table, Value};
+
+/// options that belong to a single case and are filled in by the launcher
+const CASE_OPTIONS: [&str; 4] = ["input-vol", "output-dir", "mask", "batch"];
+
+/// command line options described by a run configuration file
+pub fn config_args(path: &Path) -> Result<Vec<String>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    let table: Table = text
+        .parse()
+        .map_err(|e| format!("failed to parse {}: {e}", path.display()))?;
+
+    let mut args = vec![];
+    for (key, value) in &table {
+        let option = key.replace('_', "-");
+        if CASE_OPTIONS.contains(&option.as_str()) {
+            return Err(format!(
+                "{key} is set per case and can't be part of a run configuration"
+            ));
+        }
+        let values = match value {
+            Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            match value {
+                Value::Boolean(true) => args.push(format!("--{option}")),
+                Value::Boolean(false) => {}
+                Value::String(s) => args.push(format!("--{option}={s}")),
+                Value::Integer(i) => args.push(format!("--{option}={i}")),
+                Value::Float(f) => args.push(format!("--{option}={f}")),
+                _ => return Err(format!("unsupported value for {key} in {}", path.display())),
+            }
+        }
+    }
+    Ok(args)
+}
@@ -0,0 +1,83 @@
+//! Conventions for running radmap headless in a Docker or Singularity container.
+//!
+//! | path | contents |
+//! |------|----------|
+//! | `/in` | input volumes (nrrd or nifti). `<name>_mask.<ext>` is the mask of `<name>.<ext>`, a lone `mask.<ext>` is the mask of every input |
+//! | `/out` | feature maps, provenance and `failure.json` of every input |
+//! | `/config/radmap.toml` | options applied to every input, see [`crate::config`]. Optional, all features are computed without it |
+//!
+//! Everything written to stdout is one JSON object per line, so cluster log collectors can parse
+//! it. The exit code is the one defined in [`crate::failure`] for the first input that failed.
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::batch::Case;
+use crate::format::strip_volume_extension;
+use crate::io::is_volume;
+
+pub const DEFAULT_IN_DIR: &str = "/in";
+pub const DEFAULT_OUT_DIR: &str = "/out";
+pub const DEFAULT_CONFIG: &str = "/config/radmap.toml";
+
+/// one case per input volume found in `in_dir`, all writing to `out_dir`
+pub fn find_cases(in_dir: &Path, out_dir: &Path) -> Result<Vec<Case>, String> {
+    let mut volumes: Vec<PathBuf> = std::fs::read_dir(in_dir)
+        .map_err(|e| format!("failed to list {}: {e}", in_dir.display()))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.is_file() && is_volume(p))
+        .collect();
+    volumes.sort();
+
+    let shared_mask = volumes.iter().find(|p| volume_name(p) == "mask").cloned();
+    let masks: Vec<&PathBuf> = volumes
+        .iter()
+        .filter(|p| volume_name(p) == "mask" || volume_name(p).ends_with("_mask"))
+        .collect();
+
+    let cases: Vec<Case> = volumes
+        .iter()
+        .filter(|p| !masks.contains(p))
+        .map(|input| {
+            let own_mask = masks
+                .iter()
+                .find(|m| volume_name(m) == format!("{}_mask", volume_name(input)))
+                .map(|m| m.to_path_buf());
+            Case {
+                input_vol: input.clone(),
+                output_dir: out_dir.to_path_buf(),
+                mask: own_mask.or(shared_mask.clone()),
+            }
+        })
+        .collect();
+    if cases.is_empty() {
+        return Err(format!("no input volumes found in {}", in_dir.display()));
+    }
+    Ok(cases)
+}
+
+/// file name without the volume extension, e.g. `a` for `a.nii.gz`
+fn volume_name(path: &Path) -> String {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    strip_volume_extension(&name).to_string()
+}
+
+/// prints one structured log line, e.g.
+/// `{"ts": 1700000000.123, "level": "info", "event": "case_start", "input": "/in/a.nii"}`
+pub fn log_event(level: &str, event: &str, fields: &[(&str, &str)]) {
+    let ts = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.);
+    let string = |s: &str| serde_json::to_string(s).expect("strings are always representable as json");
+    let mut line = format!(
+        "{{\"ts\": {ts:.3}, \"level\": {}, \"event\": {}",
+        string(level),
+        string(event)
+    );
+    for (k, v) in fields {
+        line.push_str(&format!(", {}: {}", string(k), string(v)));
+    }
+    line.push('}');
+    println!("{line}");
+}
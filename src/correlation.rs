@@ -0,0 +1,212 @@
+//! Correlation between the feature maps of a run, to find features that carry the same
+//! information. Written after a run as `<stem>_correlation.csv` and a heatmap
+//! `<stem>_correlation.png` in the same order, so later runs can leave near duplicates out.
+//!
+//! Correlations are Pearson's, over the voxels of the mask where every map is finite. Larger
+//! volumes are sampled down to [`MAX_VOXELS`] voxels evenly spaced through the mask, which leaves
+//! the correlations within a few thousandths.
+
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use image::{Rgb, RgbImage};
+use rayon::prelude::*;
+
+use crate::io::output_path;
+use crate::locale::ReportFormat;
+
+/// most voxels the correlations are computed over
+pub const MAX_VOXELS: usize = 250_000;
+
+/// side of the square of a feature pair in the heatmap, in pixels
+const CELL_PX: u32 = 12;
+
+/// correlation matrix of the feature maps of a run
+#[derive(Debug, Clone, PartialEq)]
+pub struct Correlation {
+    /// aliases of the features, in the order of the rows and columns
+    pub names: Vec<String>,
+    /// row major, NaN for pairs with a map of a single value
+    pub values: Vec<f64>,
+    /// voxels the correlations are computed over
+    pub n_voxels: usize,
+}
+
+impl Correlation {
+    /// correlations between `maps` within the non-zero voxels of `mask`, or the whole volume
+    pub fn compute(maps: &[(String, &[f32])], mask: Option<&[bool]>) -> Correlation {
+        let numel = maps.first().map(|(_, m)| m.len()).unwrap_or(0);
+        let voxels: Vec<usize> = (0..numel)
+            .filter(|&i| mask.is_none_or(|m| m[i]))
+            .filter(|&i| maps.iter().all(|(_, m)| m[i].is_finite()))
+            .collect();
+        let step = voxels.len().div_ceil(MAX_VOXELS).max(1);
+        let voxels: Vec<usize> = voxels.into_iter().step_by(step).collect();
+
+        // centred and scaled to unit length, so the correlation of two maps is their dot product
+        let unit: Vec<Option<Vec<f32>>> = maps
+            .par_iter()
+            .map(|(_, m)| {
+                let n = voxels.len() as f64;
+                let mean = voxels.iter().map(|&i| m[i] as f64).sum::<f64>() / n;
+                let norm = voxels
+                    .iter()
+                    .map(|&i| (m[i] as f64 - mean).powi(2))
+                    .sum::<f64>()
+                    .sqrt();
+                (norm > 0.).then(|| {
+                    voxels
+                        .iter()
+                        .map(|&i| ((m[i] as f64 - mean) / norm) as f32)
+                        .collect()
+                })
+            })
+            .collect();
+
+        let n = maps.len();
+        let values = (0..n * n)
+            .into_par_iter()
+            .map(|ij| match (&unit[ij / n], &unit[ij % n]) {
+                (Some(a), Some(b)) => a
+                    .iter()
+                    .zip(b)
+                    .map(|(a, b)| *a as f64 * *b as f64)
+                    .sum::<f64>()
+                    .clamp(-1., 1.),
+                _ => f64::NAN,
+            })
+            .collect();
+        Correlation {
+            names: maps.iter().map(|(name, _)| name.clone()).collect(),
+            values,
+            n_voxels: voxels.len(),
+        }
+    }
+
+    pub fn get(&self, i: usize, j: usize) -> f64 {
+        self.values[i * self.names.len() + j]
+    }
+
+    /// pairs of features correlated at least as strongly as `threshold`, positively or
+    /// negatively, strongest first
+    pub fn redundant_pairs(&self, threshold: f64) -> Vec<(&str, &str, f64)> {
+        let n = self.names.len();
+        let mut pairs: Vec<_> = (0..n)
+            .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+            .map(|(i, j)| {
+                (
+                    self.names[i].as_str(),
+                    self.names[j].as_str(),
+                    self.get(i, j),
+                )
+            })
+            .filter(|(.., r)| r.abs() >= threshold)
+            .collect();
+        pairs.sort_by(|a, b| b.2.abs().total_cmp(&a.2.abs()));
+        pairs
+    }
+
+    /// the matrix with a header row and column of feature names
+    pub fn to_csv(&self, format: &ReportFormat) -> String {
+        let mut header = vec!["feature".to_string()];
+        header.extend(self.names.iter().cloned());
+        let mut csv = format.header(&header);
+        csv.push('\n');
+        for (i, name) in self.names.iter().enumerate() {
+            let mut row = vec![name.clone()];
+            row.extend((0..self.names.len()).map(|j| match self.get(i, j) {
+                r if r.is_nan() => String::new(),
+                r => format.number(r, 4),
+            }));
+            csv.push_str(&format.row(&row));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// one square per pair in the order of the csv, blue for -1 through white to red for 1 and
+    /// gray for undefined correlations
+    pub fn heatmap(&self) -> RgbImage {
+        let n = self.names.len() as u32;
+        RgbImage::from_fn(n * CELL_PX, n * CELL_PX, |x, y| {
+            color(self.get((y / CELL_PX) as usize, (x / CELL_PX) as usize))
+        })
+    }
+
+    /// writes the csv and the heatmap to `dir`, returning their paths
+    pub fn write(
+        &self,
+        dir: &Path,
+        stem: &OsStr,
+        format: &ReportFormat,
+    ) -> Result<[PathBuf; 2], String> {
+        let csv = output_path(dir, stem, "correlation.csv");
+        std::fs::write(&csv, self.to_csv(format))
+            .map_err(|e| format!("failed to write {}: {e}", csv.display()))?;
+        let png = output_path(dir, stem, "correlation.png");
+        self.heatmap()
+            .save(&png)
+            .map_err(|e| format!("failed to write {}: {e}", png.display()))?;
+        Ok([csv, png])
+    }
+}
+
+fn color(r: f64) -> Rgb<u8> {
+    if r.is_nan() {
+        return Rgb([128, 128, 128]);
+    }
+    let end = if r < 0. {
+        [33., 102., 172.]
+    } else {
+        [178., 24., 43.]
+    };
+    let t = r.abs();
+    Rgb(end.map(|c: f64| (255. + t * (c - 255.)).round() as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn correlation(maps: &[(&str, &[f32])], mask: Option<&[bool]>) -> Correlation {
+        let maps: Vec<(String, &[f32])> = maps.iter().map(|(n, m)| (n.to_string(), *m)).collect();
+        Correlation::compute(&maps, mask)
+    }
+
+    #[test]
+    fn correlations_are_pearsons() {
+        // deviations from the means -1.5, -0.5, 0.5, 1.5 and -3, -1, 0, 4 give 11 / sqrt(5 * 26)
+        let a: [f32; 4] = [1., 2., 3., 4.];
+        let b: [f32; 4] = [2., 4., 5., 9.];
+        let c: [f32; 4] = [-1., -2., -3., -4.];
+        let flat = [7f32; 4];
+        let r = correlation(&[("a", &a), ("b", &b), ("c", &c), ("flat", &flat)], None);
+        let expected = 11. / 130f64.sqrt();
+        assert_eq!(r.n_voxels, 4);
+        assert!((r.get(0, 1) - expected).abs() < 1e-6);
+        assert_eq!(r.get(0, 1), r.get(1, 0));
+        assert!((r.get(0, 2) + 1.).abs() < 1e-6);
+        assert!((r.get(1, 2) + expected).abs() < 1e-6);
+        for i in 0..3 {
+            assert!((r.get(i, i) - 1.).abs() < 1e-6);
+        }
+        // a map of a single value correlates with nothing
+        assert!((0..4).all(|i| r.get(i, 3).is_nan()));
+
+        let pairs = r.redundant_pairs(0.95);
+        let names: Vec<(&str, &str)> = pairs.iter().map(|(a, b, _)| (*a, *b)).collect();
+        assert_eq!(names, [("a", "c"), ("a", "b"), ("b", "c")]);
+        assert_eq!(r.redundant_pairs(0.99).len(), 1);
+    }
+
+    #[test]
+    fn only_finite_voxels_of_the_mask_count() {
+        // the outlier is masked out and the NaN left out, leaving the voxels of the example above
+        let a: [f32; 6] = [1., 2., 100., 3., 4., f32::NAN];
+        let b: [f32; 6] = [2., 4., -50., 5., 9., 1.];
+        let mask = [true, true, false, true, true, true];
+        let r = correlation(&[("a", &a), ("b", &b)], Some(&mask));
+        assert_eq!(r.n_voxels, 4);
+        assert!((r.get(0, 1) - 11. / 130f64.sqrt()).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,178 @@
+use std::backtrace::Backtrace;
+use std::fmt::Write as _;
+use std::panic::PanicHookInfo;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use array_lib::ArrayDim;
+use glcm::ui::MapOpts;
+
+use crate::texture::{selected_features, TextureOpts};
+
+/// everything that ends up in a crash report besides the panic itself. Only metadata is kept here,
+/// never pixel data
+#[derive(Debug, Default)]
+struct CrashContext {
+    app: &'static str,
+    options: Option<String>,
+    inputs: Vec<RecordedInput>,
+    report_dir: Option<PathBuf>,
+    /// leaves the paths of the inputs and the command line naming them out of the report, as
+    /// `--scrub-phi` does for everything else written next to the maps
+    scrub_phi: bool,
+}
+
+#[derive(Debug)]
+struct RecordedInput {
+    label: String,
+    path: PathBuf,
+    /// shape and size on disk
+    details: String,
+}
+
+static CONTEXT: Mutex<CrashContext> = Mutex::new(CrashContext {
+    app: "",
+    options: None,
+    inputs: vec![],
+    report_dir: None,
+    scrub_phi: false,
+});
+
+static LAST_REPORT: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// installs a panic hook that writes a crash report before handing off to the default hook
+pub fn install_panic_hook(app: &'static str) {
+    context().app = app;
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if let Some(path) = write_report(info) {
+            eprintln!("crash report written to {}", path.display());
+            *LAST_REPORT.lock().unwrap_or_else(|e| e.into_inner()) = Some(path);
+        }
+        default_hook(info);
+    }));
+}
+
+/// directory crash reports are written to. Falls back to the system temp directory if never set
+/// or not writable
+pub fn set_report_dir(dir: impl AsRef<Path>) {
+    context().report_dir = Some(dir.as_ref().to_path_buf());
+}
+
+pub fn set_options(opts: &MapOpts, texture: &TextureOpts) {
+    context().options = Some(describe_opts(opts, texture));
+}
+
+/// whether the outputs are scrubbed of patient information, in which case the report leaves out
+/// the paths of the inputs and the command line
+pub fn set_scrub_phi(scrub_phi: bool) {
+    context().scrub_phi = scrub_phi;
+}
+
+/// records an input by path and shape only
+pub fn record_input(label: &str, path: impl AsRef<Path>, dims: &ArrayDim) {
+    let path = path.as_ref();
+    let size = std::fs::metadata(path).map(|m| m.len()).ok();
+    context().inputs.push(RecordedInput {
+        label: label.to_string(),
+        path: path.to_path_buf(),
+        details: format!(
+            "shape {:?}, {} bytes on disk",
+            dims.shape(),
+            size.map(|s| s.to_string()).unwrap_or("?".to_string())
+        ),
+    });
+}
+
+pub fn clear_inputs() {
+    context().inputs.clear();
+}
+
+/// path of the most recent crash report, if one was written since the last call
+pub fn take_last_report() -> Option<PathBuf> {
+    LAST_REPORT.lock().unwrap_or_else(|e| e.into_inner()).take()
+}
+
+pub fn describe_opts(opts: &MapOpts, texture: &TextureOpts) -> String {
+    let mut features: Vec<String> = selected_features(opts, texture)
+        .into_iter()
+        .map(|(f, _)| f.to_string())
+        .collect();
+    features.sort();
+    format!(
+        "n_bins: {}, kernel_radius: {}, max_threads: {:?}, features: [{}]",
+        opts.n_bins,
+        opts.kernel_radius,
+        opts.max_threads,
+        features.join(", ")
+    )
+}
+
+fn context() -> std::sync::MutexGuard<'static, CrashContext> {
+    // a poisoned context is still good enough to report from
+    CONTEXT.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn write_report(info: &PanicHookInfo) -> Option<PathBuf> {
+    let backtrace = Backtrace::force_capture();
+    let ctx = context();
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let mut report = String::new();
+    let _ = writeln!(
+        report,
+        "{} {} crash report",
+        ctx.app,
+        env!("CARGO_PKG_VERSION")
+    );
+    let _ = writeln!(report, "time: {timestamp} (unix seconds)");
+    let _ = writeln!(
+        report,
+        "os: {} {}",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    );
+    let _ = writeln!(
+        report,
+        "thread: {}",
+        std::thread::current().name().unwrap_or("<unnamed>")
+    );
+    if ctx.scrub_phi {
+        let _ = writeln!(report, "command line: <left out, outputs are scrubbed of PHI>");
+    } else {
+        let _ = writeln!(
+            report,
+            "command line: {:?}",
+            std::env::args_os().collect::<Vec<_>>()
+        );
+    }
+    let _ = writeln!(report, "\npanic: {info}");
+    let _ = writeln!(
+        report,
+        "\noptions: {}",
+        ctx.options.as_deref().unwrap_or("<not set>")
+    );
+    let _ = writeln!(report, "\ninputs:");
+    for input in &ctx.inputs {
+        let path = match ctx.scrub_phi {
+            true => "<path left out>".to_string(),
+            false => input.path.display().to_string(),
+        };
+        let _ = writeln!(report, "  {}: {path} ({})", input.label, input.details);
+    }
+    let _ = writeln!(report, "\nbacktrace:\n{backtrace}");
+
+    let file_name = format!("{}_crash_{timestamp}.txt", ctx.app);
+    let candidates = ctx.report_dir.iter().cloned().chain([std::env::temp_dir()]);
+    for dir in candidates {
+        let path = dir.join(&file_name);
+        if std::fs::write(&path, &report).is_ok() {
+            return Some(path);
+        }
+    }
+    None
+}
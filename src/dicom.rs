@@ -0,0 +1,647 @@
+//! DICOM Parametric Map output, so feature maps can be pushed to PACS and opened in clinical
+//! viewers next to the series they were computed from.
+//!
+//! Only what that needs is implemented: reading the identifying attributes of the source series
+//! (uncompressed explicit or implicit VR little endian files) and writing one multi-frame float
+//! Parametric Map per feature in explicit VR little endian.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use array_lib::ArrayDim;
+use uuid::Uuid;
+
+use crate::locale::utc_date_time;
+
+const PARAMETRIC_MAP_SOP_CLASS: &str = "1.2.840.10008.5.1.4.1.1.30";
+const EXPLICIT_VR_LE: &str = "1.2.840.10008.1.2.1";
+const IMPLICIT_VR_LE: &str = "1.2.840.10008.1.2";
+/// radmap's implementation class uid, under the uuid derived root
+const IMPLEMENTATION_CLASS_UID: &str = "2.25.208034151795314283296457614416466426187";
+
+const fn tag(group: u16, element: u16) -> u32 {
+    (group as u32) << 16 | element as u32
+}
+
+const PATIENT_NAME: u32 = tag(0x0010, 0x0010);
+const PATIENT_ID: u32 = tag(0x0010, 0x0020);
+const PATIENT_BIRTH_DATE: u32 = tag(0x0010, 0x0030);
+const PATIENT_SEX: u32 = tag(0x0010, 0x0040);
+const STUDY_DATE: u32 = tag(0x0008, 0x0020);
+const STUDY_TIME: u32 = tag(0x0008, 0x0030);
+const ACCESSION_NUMBER: u32 = tag(0x0008, 0x0050);
+const MODALITY: u32 = tag(0x0008, 0x0060);
+const REFERRING_PHYSICIAN: u32 = tag(0x0008, 0x0090);
+const STUDY_ID: u32 = tag(0x0020, 0x0010);
+const SOP_CLASS_UID: u32 = tag(0x0008, 0x0016);
+const SOP_INSTANCE_UID: u32 = tag(0x0008, 0x0018);
+const STUDY_UID: u32 = tag(0x0020, 0x000D);
+const SERIES_UID: u32 = tag(0x0020, 0x000E);
+const FRAME_OF_REFERENCE_UID: u32 = tag(0x0020, 0x0052);
+const IMAGE_POSITION: u32 = tag(0x0020, 0x0032);
+const PLANE_POSITION_SEQUENCE: u32 = tag(0x0020, 0x9113);
+
+/// patient and study attributes copied from the source series, with their VRs
+const COPIED: [(u32, &[u8; 2]); 9] = [
+    (PATIENT_NAME, b"PN"),
+    (PATIENT_ID, b"LO"),
+    (PATIENT_BIRTH_DATE, b"DA"),
+    (PATIENT_SEX, b"CS"),
+    (STUDY_DATE, b"DA"),
+    (STUDY_TIME, b"TM"),
+    (ACCESSION_NUMBER, b"SH"),
+    (REFERRING_PHYSICIAN, b"PN"),
+    (STUDY_ID, b"SH"),
+];
+
+/// the series a map was computed from
+#[derive(Debug, Clone)]
+pub struct SourceSeries {
+    pub study_uid: String,
+    pub series_uid: String,
+    pub frame_of_reference_uid: String,
+    pub modality: String,
+    /// patient and study attributes, raw values keyed by tag
+    attributes: HashMap<u32, Vec<u8>>,
+    /// sop class and instance uid of every file of the series
+    pub instances: Vec<(String, String)>,
+}
+
+impl SourceSeries {
+    /// reads every DICOM file directly inside `dir`. All files must belong to the same series
+    pub fn read_dir(dir: &Path) -> Result<Self, String> {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+            .map_err(|e| format!("failed to list {}: {e}", dir.display()))?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.is_file())
+            .collect();
+        files.sort();
+
+        let mut series: Option<SourceSeries> = None;
+        for file in files {
+            // anything that isn't a part 10 file (e.g. DICOMDIR siblings, notes) is skipped
+            let Some(elements) = read_top_level(&file)? else {
+                continue;
+            };
+            let text = |t: u32| elements.get(&t).map(|v| trim_value(v)).unwrap_or_default();
+            let instance = (text(SOP_CLASS_UID), text(SOP_INSTANCE_UID));
+            match &mut series {
+                Some(s) => {
+                    if text(SERIES_UID) != s.series_uid {
+                        return Err(format!(
+                            "{} belongs to another series than the rest of {}",
+                            file.display(),
+                            dir.display()
+                        ));
+                    }
+                    s.instances.push(instance);
+                }
+                None => {
+                    series = Some(SourceSeries {
+                        study_uid: text(STUDY_UID),
+                        series_uid: text(SERIES_UID),
+                        frame_of_reference_uid: text(FRAME_OF_REFERENCE_UID),
+                        modality: text(MODALITY),
+                        attributes: COPIED
+                            .iter()
+                            .filter_map(|(t, _)| elements.get(t).map(|v| (*t, v.clone())))
+                            .collect(),
+                        instances: vec![instance],
+                    })
+                }
+            }
+        }
+        let series = series.ok_or_else(|| format!("no dicom files found in {}", dir.display()))?;
+        if series.study_uid.is_empty() || series.series_uid.is_empty() {
+            return Err(format!(
+                "the dicom files in {} have no study or series uid",
+                dir.display()
+            ));
+        }
+        Ok(series)
+    }
+}
+
+/// a new globally unique uid under the uuid derived root
+pub fn new_uid() -> String {
+    format!("2.25.{}", Uuid::new_v4().as_u128())
+}
+
+/// writes a Parametric Map of one feature. `voxel_to_lps` maps voxel indices to patient
+/// coordinates and must be in the frame of reference of the source series
+pub fn write_parametric_map(
+    path: &Path,
+    source: &SourceSeries,
+    feature: &str,
+    vol: &[f32],
+    dims: ArrayDim,
+    voxel_to_lps: [[f64; 4]; 3],
+) -> Result<(), String> {
+    let shape = dims.shape();
+    let (cols, rows) = (shape[0], shape[1]);
+    let frames = shape.get(2).copied().unwrap_or(1);
+    if rows > u16::MAX as usize || cols > u16::MAX as usize {
+        return Err(format!("{cols}x{rows} slices are too large for dicom"));
+    }
+
+    let axis = |i: usize| [0, 1, 2].map(|r| voxel_to_lps[r][i]);
+    let norm = |v: [f64; 3]| (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    let (di, dj, dk, origin) = (axis(0), axis(1), axis(2), axis(3));
+    if [di, dj, dk]
+        .iter()
+        .any(|v| norm(*v) == 0. || !norm(*v).is_finite())
+    {
+        return Err("the volume has a degenerate orientation".to_string());
+    }
+    let orientation: Vec<f64> = [di, dj]
+        .iter()
+        .flat_map(|v| v.map(|c| c / norm(*v)))
+        .collect();
+
+    let (min, max) = vol
+        .iter()
+        .filter(|v| v.is_finite())
+        .fold((f64::MAX, f64::MIN), |(lo, hi), &v| {
+            (lo.min(v as f64), hi.max(v as f64))
+        });
+    let (min, max) = if min <= max { (min, max) } else { (0., 0.) };
+
+    let sop_instance_uid = new_uid();
+    let dimension_organization_uid = new_uid();
+    let (date, time) = now_date_time();
+    let label: String = feature
+        .to_uppercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .take(16)
+        .collect();
+
+    let code = |value: &str, scheme: &str, meaning: &str| {
+        vec![
+            Element::text(tag(0x0008, 0x0100), b"SH", value),
+            Element::text(tag(0x0008, 0x0102), b"SH", scheme),
+            Element::text(tag(0x0008, 0x0104), b"LO", meaning),
+        ]
+    };
+
+    let shared = vec![
+        Element::sequence(
+            tag(0x0028, 0x9110),
+            vec![vec![
+                Element::text(tag(0x0028, 0x0030), b"DS", &ds(&[norm(dj), norm(di)])),
+                Element::text(tag(0x0018, 0x0050), b"DS", &ds(&[norm(dk)])),
+                Element::text(tag(0x0018, 0x0088), b"DS", &ds(&[norm(dk)])),
+            ]],
+        ),
+        Element::sequence(
+            tag(0x0020, 0x9116),
+            vec![vec![Element::text(
+                tag(0x0020, 0x0037),
+                b"DS",
+                &ds(&orientation),
+            )]],
+        ),
+        Element::sequence(
+            tag(0x0020, 0x9071),
+            vec![vec![
+                Element::sequence(
+                    tag(0x0008, 0x2218),
+                    vec![code("38266002", "SCT", "Entire body")],
+                ),
+                Element::text(tag(0x0020, 0x9072), b"CS", "U"),
+            ]],
+        ),
+        Element::sequence(
+            tag(0x0028, 0x9145),
+            vec![vec![
+                Element::text(tag(0x0028, 0x1052), b"DS", "0"),
+                Element::text(tag(0x0028, 0x1053), b"DS", "1"),
+                Element::text(tag(0x0028, 0x1054), b"LO", "US"),
+            ]],
+        ),
+        Element::sequence(
+            tag(0x0040, 0x9096),
+            vec![vec![
+                Element::text(tag(0x0028, 0x3003), b"LO", feature),
+                Element::text(tag(0x0040, 0x9210), b"SH", &label),
+                Element::sequence(tag(0x0040, 0x08EA), vec![code("1", "UCUM", "no units")]),
+                Element::float64(tag(0x0040, 0x9213), &[min]),
+                Element::float64(tag(0x0040, 0x9214), &[max]),
+                Element::float64(tag(0x0040, 0x9224), &[0.]),
+                Element::float64(tag(0x0040, 0x9225), &[1.]),
+            ]],
+        ),
+    ];
+
+    let per_frame = (0..frames)
+        .map(|k| {
+            let position = [0, 1, 2].map(|r| origin[r] + k as f64 * dk[r]);
+            vec![
+                Element::sequence(
+                    tag(0x0020, 0x9111),
+                    vec![vec![Element::uint32(tag(0x0020, 0x9157), &[k as u32 + 1])]],
+                ),
+                Element::sequence(
+                    PLANE_POSITION_SEQUENCE,
+                    vec![vec![Element::text(IMAGE_POSITION, b"DS", &ds(&position))]],
+                ),
+            ]
+        })
+        .collect();
+
+    let referenced_instances = source
+        .instances
+        .iter()
+        .map(|(class, instance)| {
+            vec![
+                Element::text(tag(0x0008, 0x1150), b"UI", class),
+                Element::text(tag(0x0008, 0x1155), b"UI", instance),
+            ]
+        })
+        .collect();
+
+    let mut dataset = vec![
+        Element::text(tag(0x0008, 0x0005), b"CS", "ISO_IR 192"),
+        Element::text(tag(0x0008, 0x0008), b"CS", "DERIVED\\PRIMARY"),
+        Element::text(SOP_CLASS_UID, b"UI", PARAMETRIC_MAP_SOP_CLASS),
+        Element::text(SOP_INSTANCE_UID, b"UI", &sop_instance_uid),
+        Element::text(tag(0x0008, 0x0023), b"DA", &date),
+        Element::text(tag(0x0008, 0x0033), b"TM", &time),
+        Element::text(MODALITY, b"CS", &source.modality),
+        Element::text(tag(0x0008, 0x0070), b"LO", "radmap"),
+        Element::text(tag(0x0008, 0x103E), b"LO", &format!("radmap {feature}")),
+        Element::text(tag(0x0008, 0x1090), b"LO", "radmap"),
+        Element::sequence(
+            tag(0x0008, 0x1115),
+            vec![vec![
+                Element::sequence(tag(0x0008, 0x1199), referenced_instances),
+                Element::text(SERIES_UID, b"UI", &source.series_uid),
+            ]],
+        ),
+        Element::text(tag(0x0008, 0x9205), b"CS", "MONOCHROME"),
+        Element::text(tag(0x0008, 0x9206), b"CS", "VOLUME"),
+        Element::text(tag(0x0008, 0x9207), b"CS", "NONE"),
+        Element::text(tag(0x0018, 0x1000), b"LO", "0"),
+        Element::text(tag(0x0018, 0x1020), b"LO", env!("CARGO_PKG_VERSION")),
+        Element::text(tag(0x0018, 0x9004), b"CS", "RESEARCH"),
+        Element::text(STUDY_UID, b"UI", &source.study_uid),
+        Element::text(SERIES_UID, b"UI", &new_uid()),
+        Element::text(tag(0x0020, 0x0011), b"IS", "1000"),
+        Element::text(tag(0x0020, 0x0013), b"IS", "1"),
+        Element::text(
+            FRAME_OF_REFERENCE_UID,
+            b"UI",
+            &source.frame_of_reference_uid,
+        ),
+        Element::text(tag(0x0020, 0x1040), b"LO", ""),
+        Element::sequence(
+            tag(0x0020, 0x9221),
+            vec![vec![Element::text(
+                tag(0x0020, 0x9164),
+                b"UI",
+                &dimension_organization_uid,
+            )]],
+        ),
+        Element::sequence(
+            tag(0x0020, 0x9222),
+            vec![vec![
+                Element::text(tag(0x0020, 0x9164), b"UI", &dimension_organization_uid),
+                Element::attribute_tag(tag(0x0020, 0x9165), IMAGE_POSITION),
+                Element::attribute_tag(tag(0x0020, 0x9167), PLANE_POSITION_SEQUENCE),
+            ]],
+        ),
+        Element::uint16(tag(0x0028, 0x0002), 1),
+        Element::text(tag(0x0028, 0x0004), b"CS", "MONOCHROME2"),
+        Element::text(tag(0x0028, 0x0008), b"IS", &frames.to_string()),
+        Element::uint16(tag(0x0028, 0x0010), rows as u16),
+        Element::uint16(tag(0x0028, 0x0011), cols as u16),
+        Element::uint16(tag(0x0028, 0x0100), 32),
+        Element::text(tag(0x0028, 0x0301), b"CS", "NO"),
+        Element::text(tag(0x0028, 0x0302), b"CS", "NO"),
+        Element::text(tag(0x0028, 0x2110), b"CS", "00"),
+        Element::text(tag(0x0070, 0x0080), b"CS", &label),
+        Element::text(tag(0x0070, 0x0081), b"LO", &format!("{feature} map")),
+        Element::text(tag(0x0070, 0x0084), b"PN", ""),
+        Element::text(tag(0x2050, 0x0020), b"CS", "IDENTITY"),
+        Element::sequence(tag(0x5200, 0x9229), vec![shared]),
+        Element::sequence(tag(0x5200, 0x9230), per_frame),
+        Element::bytes(
+            tag(0x7FE0, 0x0008),
+            b"OF",
+            vol.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        ),
+    ];
+    for (t, vr) in COPIED {
+        let value = source.attributes.get(&t).cloned().unwrap_or_default();
+        dataset.push(Element::bytes(t, vr, value));
+    }
+
+    let meta = vec![
+        Element::bytes(tag(0x0002, 0x0001), b"OB", vec![0, 1]),
+        Element::text(tag(0x0002, 0x0002), b"UI", PARAMETRIC_MAP_SOP_CLASS),
+        Element::text(tag(0x0002, 0x0003), b"UI", &sop_instance_uid),
+        Element::text(tag(0x0002, 0x0010), b"UI", EXPLICIT_VR_LE),
+        Element::text(tag(0x0002, 0x0012), b"UI", IMPLEMENTATION_CLASS_UID),
+        Element::text(tag(0x0002, 0x0013), b"SH", "RADMAP"),
+    ];
+    let meta = encode(meta);
+
+    let write = || -> std::io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        w.write_all(&[0u8; 128])?;
+        w.write_all(b"DICM")?;
+        w.write_all(&Element::uint32(tag(0x0002, 0x0000), &[meta.len() as u32]).encode())?;
+        w.write_all(&meta)?;
+        w.write_all(&encode(dataset))?;
+        w.flush()
+    };
+    write().map_err(|e| format!("failed to write {}: {e}", path.display()))
+}
+
+enum Value {
+    Bytes(Vec<u8>),
+    Sequence(Vec<Vec<Element>>),
+}
+
+struct Element {
+    tag: u32,
+    vr: [u8; 2],
+    value: Value,
+}
+
+impl Element {
+    fn bytes(tag: u32, vr: &[u8; 2], mut value: Vec<u8>) -> Self {
+        if value.len() % 2 == 1 {
+            // uids and binary values are padded with a null, text with a space
+            value.push(if matches!(vr, b"UI" | b"OB") { 0 } else { b' ' });
+        }
+        Element {
+            tag,
+            vr: *vr,
+            value: Value::Bytes(value),
+        }
+    }
+
+    fn text(tag: u32, vr: &[u8; 2], value: &str) -> Self {
+        Self::bytes(tag, vr, value.as_bytes().to_vec())
+    }
+
+    fn uint16(tag: u32, value: u16) -> Self {
+        Self::bytes(tag, b"US", value.to_le_bytes().to_vec())
+    }
+
+    fn uint32(tag: u32, values: &[u32]) -> Self {
+        Self::bytes(
+            tag,
+            b"UL",
+            values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        )
+    }
+
+    fn float64(tag: u32, values: &[f64]) -> Self {
+        Self::bytes(
+            tag,
+            b"FD",
+            values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        )
+    }
+
+    fn attribute_tag(tag: u32, value: u32) -> Self {
+        let (group, element) = ((value >> 16) as u16, value as u16);
+        let mut bytes = group.to_le_bytes().to_vec();
+        bytes.extend(element.to_le_bytes());
+        Self::bytes(tag, b"AT", bytes)
+    }
+
+    fn sequence(tag: u32, items: Vec<Vec<Element>>) -> Self {
+        Element {
+            tag,
+            vr: *b"SQ",
+            value: Value::Sequence(items),
+        }
+    }
+
+    /// explicit VR little endian with defined lengths
+    fn encode(self) -> Vec<u8> {
+        let value = match self.value {
+            Value::Bytes(b) => b,
+            Value::Sequence(items) => items
+                .into_iter()
+                .flat_map(|item| {
+                    let item = encode(item);
+                    let mut b = vec![0xFE, 0xFF, 0x00, 0xE0];
+                    b.extend((item.len() as u32).to_le_bytes());
+                    b.extend(item);
+                    b
+                })
+                .collect(),
+        };
+        let mut out = ((self.tag >> 16) as u16).to_le_bytes().to_vec();
+        out.extend((self.tag as u16).to_le_bytes());
+        out.extend(self.vr);
+        if long_length(&self.vr) {
+            out.extend([0, 0]);
+            out.extend((value.len() as u32).to_le_bytes());
+        } else {
+            out.extend((value.len() as u16).to_le_bytes());
+        }
+        out.extend(value);
+        out
+    }
+}
+
+/// a data set with its elements in ascending tag order
+fn encode(mut elements: Vec<Element>) -> Vec<u8> {
+    elements.sort_by_key(|e| e.tag);
+    elements.into_iter().flat_map(Element::encode).collect()
+}
+
+/// VRs with a 4 byte length in explicit VR encoding
+fn long_length(vr: &[u8]) -> bool {
+    matches!(
+        vr,
+        b"OB"
+            | b"OD"
+            | b"OF"
+            | b"OL"
+            | b"OV"
+            | b"OW"
+            | b"SQ"
+            | b"SV"
+            | b"UC"
+            | b"UN"
+            | b"UR"
+            | b"UT"
+            | b"UV"
+    )
+}
+
+/// decimal strings, at most 16 characters each
+fn ds(values: &[f64]) -> String {
+    values
+        .iter()
+        .map(|v| {
+            let v = if v.abs() < 1e-9 { 0. } else { *v };
+            (0..=10)
+                .rev()
+                .map(|precision| {
+                    let s = format!("{v:.precision$}");
+                    if s.contains('.') {
+                        s.trim_end_matches('0').trim_end_matches('.').to_string()
+                    } else {
+                        s
+                    }
+                })
+                .find(|s| s.len() <= 16)
+                .unwrap_or_else(|| format!("{v:.6e}"))
+        })
+        .collect::<Vec<_>>()
+        .join("\\")
+}
+
+fn trim_value(value: &[u8]) -> String {
+    String::from_utf8_lossy(value)
+        .trim_end_matches(['\0', ' '])
+        .trim_start()
+        .to_string()
+}
+
+/// DA and TM values for now, in UTC
+fn now_date_time() -> (String, String) {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let [year, month, day, hour, minute, second] = utc_date_time(secs);
+    (
+        format!("{year:04}{month:02}{day:02}"),
+        format!("{hour:02}{minute:02}{second:02}"),
+    )
+}
+
+/// top level elements of a part 10 file, without the contents of sequences. None if the file
+/// isn't a part 10 file
+fn read_top_level(path: &Path) -> Result<Option<HashMap<u32, Vec<u8>>>, String> {
+    let bytes =
+        std::fs::read(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    if bytes.len() < 132 || &bytes[128..132] != b"DICM" {
+        return Ok(None);
+    }
+    let err = |e: &str| format!("failed to parse {}: {e}", path.display());
+
+    let mut parser = Parser {
+        bytes: &bytes,
+        pos: 132,
+        explicit: true,
+    };
+    let mut elements = HashMap::new();
+    // the file meta group is always explicit VR little endian
+    while parser.peek_group() == Some(0x0002) {
+        let (t, value) = parser
+            .element()
+            .ok_or_else(|| err("truncated meta header"))?;
+        elements.insert(t, value.unwrap_or_default());
+    }
+    let syntax = elements
+        .get(&tag(0x0002, 0x0010))
+        .map(|v| trim_value(v))
+        .unwrap_or_default();
+    parser.explicit = match syntax.as_str() {
+        IMPLICIT_VR_LE => false,
+        // compressed transfer syntaxes only change the pixel data, which is never looked at
+        s if s.starts_with("1.2.840.10008.1.2.4") || s.starts_with("1.2.840.10008.1.2.5") => true,
+        EXPLICIT_VR_LE => true,
+        s => return Err(err(&format!("unsupported transfer syntax {s}"))),
+    };
+    while parser.pos < bytes.len() {
+        let (t, value) = parser.element().ok_or_else(|| err("truncated data set"))?;
+        if t == tag(0x7FE0, 0x0010) {
+            break;
+        }
+        if let Some(value) = value {
+            elements.insert(t, value);
+        }
+    }
+    Ok(Some(elements))
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    explicit: bool,
+}
+
+impl Parser<'_> {
+    fn take(&mut self, n: usize) -> Option<&[u8]> {
+        let b = self.bytes.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(b)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn peek_group(&self) -> Option<u16> {
+        let b = self.bytes.get(self.pos..self.pos + 2)?;
+        Some(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    /// the next element's tag and value. Sequences and undefined length values are skipped
+    /// and have no value
+    fn element(&mut self) -> Option<(u32, Option<Vec<u8>>)> {
+        let t = tag(self.u16()?, self.u16()?);
+        // item and delimitation tags have no VR
+        let is_delimiter = t >> 16 == 0xFFFE;
+        let (vr, len) = if self.explicit && !is_delimiter {
+            let vr: [u8; 2] = self.take(2)?.try_into().ok()?;
+            if long_length(&vr) {
+                self.take(2)?;
+                (Some(vr), self.u32()?)
+            } else {
+                (Some(vr), self.u16()? as u32)
+            }
+        } else {
+            (None, self.u32()?)
+        };
+        if len == u32::MAX {
+            self.skip_undefined()?;
+            return Some((t, None));
+        }
+        let value = self.take(len as usize)?.to_vec();
+        Some((t, (vr != Some(*b"SQ")).then_some(value)))
+    }
+
+    /// skips the items of an undefined length sequence, or the fragments of encapsulated
+    /// pixel data, up to and including the sequence delimiter
+    fn skip_undefined(&mut self) -> Option<()> {
+        loop {
+            let t = tag(self.u16()?, self.u16()?);
+            let len = self.u32()?;
+            match t {
+                // sequence delimitation
+                0xFFFE_E0DD => return Some(()),
+                // item with defined length
+                0xFFFE_E000 if len != u32::MAX => {
+                    self.take(len as usize)?;
+                }
+                // item of undefined length, read its elements up to the item delimiter
+                0xFFFE_E000 => loop {
+                    let (t, _) = self.element()?;
+                    if t == 0xFFFE_E00D {
+                        break;
+                    }
+                },
+                _ => return None,
+            }
+        }
+    }
+}
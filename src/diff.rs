@@ -0,0 +1,303 @@
+//! Side by side comparison of the settings of two runs, read from their provenance sidecars or
+//! the run history, for tracking down why two supposedly identical runs disagree.
+
+use std::path::Path;
+
+use crate::history::{History, RunRecord};
+use crate::provenance::{reproducibility_hash, PreviousRun, Provenance};
+
+/// the settings of one run as named, printable values
+pub struct RunSettings {
+    /// where the settings were read from
+    pub label: String,
+    pub fields: Vec<(&'static str, String)>,
+}
+
+impl RunSettings {
+    pub fn from_previous_run(run: &PreviousRun) -> Self {
+        RunSettings {
+            label: run.sidecar.display().to_string(),
+            fields: fields(&run.radmap_version, &run.provenance),
+        }
+    }
+
+    pub fn from_record(run: &RunRecord) -> Result<Self, String> {
+        let options: serde_json::Value = serde_json::from_str(&run.options)
+            .map_err(|e| format!("options of run {} can't be read: {e}", run.id))?;
+        let number = |key: &str| options.get(key).and_then(|v| v.as_u64());
+        let strings = |key: &str| -> Vec<String> {
+            options
+                .get(key)
+                .and_then(|f| f.as_array())
+                .map(|f| {
+                    f.iter()
+                        .filter_map(|f| f.as_str().map(String::from))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let features = strings("features");
+        let removed = || "(removed)".to_string();
+        Ok(RunSettings {
+            label: format!("history run {}", run.id),
+            fields: vec![
+                ("radmap version", run.radmap_version.clone()),
+                ("app", run.app.clone()),
+                ("input", run.input.clone().unwrap_or_else(removed)),
+                ("input hash", or_none(run.input_hash.clone())),
+                (
+                    "mask",
+                    if run.input.is_none() {
+                        removed()
+                    } else {
+                        or_none(run.mask.clone())
+                    },
+                ),
+                ("mask hash", or_none(run.mask_hash.clone())),
+                (
+                    "reproducibility hash",
+                    or_none(run.input_hash.as_deref().map(|input| {
+                        reproducibility_hash(input, run.mask_hash.as_deref(), &run.options_hash)
+                    })),
+                ),
+                ("kernel radius", or_none(number("kernel_radius"))),
+                (
+                    "kernel radii",
+                    or_none(
+                        options
+                            .get("kernel_radii")
+                            .and_then(|r| r.as_array())
+                            .map(|r| {
+                                let r: Vec<String> = r.iter().map(|r| r.to_string()).collect();
+                                r.join(",")
+                            }),
+                    ),
+                ),
+                (
+                    "kernel shape",
+                    options
+                        .get("kernel_shape")
+                        .and_then(|s| s.as_str())
+                        .unwrap_or("cube")
+                        .to_string(),
+                ),
+                (
+                    "slice-wise",
+                    options
+                        .get("slice_wise")
+                        .and_then(|s| s.as_bool())
+                        .unwrap_or(false)
+                        .to_string(),
+                ),
+                ("gldm alpha", or_none(number("gldm_alpha"))),
+                ("ngldm alpha", or_none(number("ngldm_alpha"))),
+                ("ngldm distance", or_none(number("ngldm_distance"))),
+                (
+                    "filters",
+                    filters_field(
+                        &strings("filters"),
+                        options.get("filter_use").and_then(|u| u.as_str()),
+                    ),
+                ),
+                ("number of bins", or_none(number("n_bins"))),
+                (
+                    "bin edges",
+                    or_none(options.get("bin_edges").and_then(|e| e.as_str())),
+                ),
+                (
+                    "bin width",
+                    or_none(options.get("bin_width").and_then(|w| w.as_f64())),
+                ),
+                (
+                    "bin minimum",
+                    or_none(options.get("bin_min").and_then(|m| m.as_f64())),
+                ),
+                (
+                    "bin maximum",
+                    or_none(options.get("bin_max").and_then(|m| m.as_f64())),
+                ),
+                (
+                    "bin range from mask",
+                    options
+                        .get("bin_from_mask")
+                        .and_then(|m| m.as_bool())
+                        .unwrap_or(false)
+                        .to_string(),
+                ),
+                (
+                    "ranked bins",
+                    options
+                        .get("bin_rank")
+                        .and_then(|r| r.as_bool())
+                        .unwrap_or(false)
+                        .to_string(),
+                ),
+                (
+                    "discretization",
+                    options
+                        .get("discretization")
+                        .and_then(|d| d.as_str())
+                        .unwrap_or("global")
+                        .to_string(),
+                ),
+                (
+                    "angles",
+                    options
+                        .get("angles")
+                        .and_then(|a| a.as_str())
+                        .unwrap_or("all")
+                        .to_string(),
+                ),
+                (
+                    "distances",
+                    options
+                        .get("distances")
+                        .and_then(|d| d.as_array())
+                        .map(|d| {
+                            let d: Vec<String> = d.iter().map(|d| d.to_string()).collect();
+                            d.join(",")
+                        })
+                        .unwrap_or("1".to_string()),
+                ),
+                (
+                    "distance aggregation",
+                    or_none(options.get("distance_aggregation").and_then(|a| a.as_str())),
+                ),
+                (
+                    "direction aggregation",
+                    options
+                        .get("direction_aggregation")
+                        .and_then(|a| a.as_str())
+                        .unwrap_or("mean")
+                        .to_string(),
+                ),
+                (
+                    "distance weighting",
+                    or_none(options.get("distance_weighting").and_then(|w| w.as_str())),
+                ),
+                ("features", features.join(", ")),
+            ],
+        })
+    }
+
+    /// settings of a run given as a provenance sidecar or the id of a run in the history
+    pub fn load(run: &str) -> Result<Self, String> {
+        let path = Path::new(run);
+        if path.is_file() {
+            return Ok(Self::from_previous_run(&PreviousRun::read(path)?));
+        }
+        let id: i64 = run
+            .trim_start_matches('#')
+            .parse()
+            .map_err(|_| format!("{run} is neither a provenance sidecar nor a history run id"))?;
+        let record = History::open_default()?
+            .get(id)?
+            .ok_or_else(|| format!("no run {id} in the history"))?;
+        Self::from_record(&record)
+    }
+}
+
+fn fields(version: &str, p: &Provenance) -> Vec<(&'static str, String)> {
+    let removed = || "(removed)".to_string();
+    let (input, mask) = if p.phi_scrubbed {
+        (removed(), removed())
+    } else {
+        (
+            p.input.display().to_string(),
+            or_none(p.mask.as_ref().map(|m| m.display())),
+        )
+    };
+    vec![
+        ("radmap version", version.to_string()),
+        ("app", p.app.clone()),
+        ("input", input),
+        ("input hash", or_none(p.input_hash.clone())),
+        ("mask", mask),
+        ("mask hash", or_none(p.mask_hash.clone())),
+        ("reproducibility hash", or_none(p.reproducibility_hash())),
+        ("kernel radius", p.kernel_radius.to_string()),
+        (
+            "kernel radii",
+            or_none(p.kernel_radii.map(|[rx, ry, rz]| format!("{rx},{ry},{rz}"))),
+        ),
+        ("kernel shape", p.kernel_shape.to_string()),
+        ("slice-wise", p.slice_wise.to_string()),
+        ("gldm alpha", or_none(p.gldm_alpha)),
+        ("ngldm alpha", or_none(p.ngldm_alpha)),
+        ("ngldm distance", or_none(p.ngldm_distance)),
+        (
+            "filters",
+            filters_field(&p.filters, p.filter_use.map(|u| u.as_str())),
+        ),
+        ("number of bins", p.binning.n_bins.to_string()),
+        ("bin edges", p.binning.edges.to_string()),
+        ("bin width", or_none(p.binning.width)),
+        ("bin minimum", or_none(p.binning.min)),
+        ("bin maximum", or_none(p.binning.max)),
+        ("bin range from mask", p.binning.from_mask.to_string()),
+        ("ranked bins", p.binning.rank.to_string()),
+        ("discretization", p.discretization.to_string()),
+        ("angles", p.glcm_angles.unwrap_or_default().to_string()),
+        (
+            "distances",
+            p.glcm_distances.as_ref().map_or("1".to_string(), |d| {
+                let d: Vec<String> = d.iter().map(|d| d.to_string()).collect();
+                d.join(",")
+            }),
+        ),
+        (
+            "distance aggregation",
+            or_none(p.glcm_aggregation.map(|a| a.as_str())),
+        ),
+        (
+            "direction aggregation",
+            p.glcm_direction_aggregation.unwrap_or_default().to_string(),
+        ),
+        (
+            "distance weighting",
+            or_none(p.glcm_weighting.map(|w| w.as_str())),
+        ),
+        ("features", p.features.join(", ")),
+    ]
+}
+
+/// e.g. `gabor_f0.25_x, gabor_f0.25_y (map)`
+fn filters_field(filters: &[String], usage: Option<&str>) -> String {
+    match usage {
+        Some(usage) if !filters.is_empty() => format!("{} ({usage})", filters.join(", ")),
+        _ => "none".to_string(),
+    }
+}
+
+fn or_none(value: Option<impl ToString>) -> String {
+    value.map(|v| v.to_string()).unwrap_or("none".to_string())
+}
+
+/// one setting of both runs
+pub struct FieldDiff {
+    pub name: &'static str,
+    pub a: String,
+    pub b: String,
+}
+
+impl FieldDiff {
+    pub fn differs(&self) -> bool {
+        self.a != self.b
+    }
+}
+
+/// every setting of the two runs, in the order they are listed in
+pub fn compare(a: &RunSettings, b: &RunSettings) -> Vec<FieldDiff> {
+    a.fields
+        .iter()
+        .map(|(name, value)| FieldDiff {
+            name,
+            a: value.clone(),
+            b: b.fields
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.clone())
+                .unwrap_or("none".to_string()),
+        })
+        .collect()
+}
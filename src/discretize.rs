@@ -0,0 +1,634 @@
+//! Intensity discretization done ahead of the GLCM mapper.
+//!
+//! The mapper bins intensities linearly between the volume minimum and maximum. Gray levels
+//! produced here span `0..n_bins` with both ends present, so the mapper's own binning leaves them
+//! unchanged and the discretized volume can be fed to it directly.
+//!
+//! Bins can also be fixed in intensity rather than follow the volume: a fixed width from a
+//! minimum, the fixed bin size discretization the IBSI recommends for calibrated intensities like
+//! CT, or a number of bins over a fixed range like -100 to 300 HU. Intensities outside the range
+//! go to the end bins. The mapper would stretch the levels of such bins to its own range, so
+//! [`Binning::fit`] numbers them from the lowest bin holding a voxel and takes as many bins as
+//! the volume spans. Differences between levels keep their meaning across volumes, absolute
+//! levels don't.
+//!
+//! The range the bins follow can also be taken from the voxels inside the mask only, so the
+//! background, like the air around a CT scan, doesn't take up most of the bins. Voxels outside the
+//! mask beyond that range go to the end bins.
+//!
+//! Ranked bins replace every intensity by its rank among the voxels, the masked ones when the
+//! range follows the mask, before binning the ranks linearly. Every bin then holds about as many
+//! voxels whatever the scale of the intensities, like raw MRI with no calibrated units, and
+//! anything that keeps the order of the intensities leaves the gray levels as they are. Tied
+//! intensities share the average of their ranks.
+//!
+//! All of the above bins the whole volume at once. With [`DiscretizationScope::Local`] the
+//! mappers instead bin the intensities of each kernel on their own, linearly between the minimum
+//! and maximum of the kernel, so every kernel uses all of its bins whatever the contrast around
+//! it. Local bins can't be fixed in intensity or follow the mask.
+
+use serde::{Deserialize, Serialize};
+
+/// whether gray levels come from binning the whole volume once or each kernel on its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiscretizationScope {
+    /// the volume is binned once between its minimum and maximum, or as the [`Binning`] says
+    #[default]
+    Global,
+    /// the intensities of each kernel are binned between the minimum and maximum of the kernel
+    Local,
+}
+
+impl DiscretizationScope {
+    pub const ALL: [DiscretizationScope; 2] =
+        [DiscretizationScope::Global, DiscretizationScope::Local];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiscretizationScope::Global => "global",
+            DiscretizationScope::Local => "local",
+        }
+    }
+
+    pub fn is_local(&self) -> bool {
+        *self == DiscretizationScope::Local
+    }
+}
+
+impl std::fmt::Display for DiscretizationScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for DiscretizationScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DiscretizationScope::ALL
+            .into_iter()
+            .find(|d| d.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown discretization scope {s}, expected global or local"))
+    }
+}
+
+/// which bin a value lying exactly on an edge between two bins belongs to. Reference
+/// implementations disagree on this, which shows up mostly on integer data where edges often
+/// land on voxel values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BinEdges {
+    /// bins are `[lower, upper)`, so edge values go to the upper bin and the maximum is placed in
+    /// the top bin. This is what the GLCM mapper does on its own
+    #[default]
+    HalfOpen,
+    /// bins are `(lower, upper]`, so edge values go to the lower bin and the minimum is placed
+    /// in the bottom bin
+    Inclusive,
+}
+
+impl BinEdges {
+    pub const ALL: [BinEdges; 2] = [BinEdges::HalfOpen, BinEdges::Inclusive];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BinEdges::HalfOpen => "half-open",
+            BinEdges::Inclusive => "inclusive",
+        }
+    }
+
+    /// plain description of the convention, written to the provenance sidecar
+    pub fn describe(&self) -> &'static str {
+        match self {
+            BinEdges::HalfOpen => {
+                "bins are [lower, upper): values on an edge go to the upper bin and the maximum is placed in the top bin"
+            }
+            BinEdges::Inclusive => {
+                "bins are (lower, upper]: values on an edge go to the lower bin and the minimum is placed in the bottom bin"
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for BinEdges {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for BinEdges {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BinEdges::ALL
+            .into_iter()
+            .find(|e| e.as_str() == s)
+            .ok_or_else(|| {
+                format!("unknown bin edge convention {s}, expected half-open or inclusive")
+            })
+    }
+}
+
+/// how intensities are mapped to gray levels
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Binning {
+    pub n_bins: usize,
+    pub edges: BinEdges,
+    /// width of the bins, in the units of the input, instead of a number of bins
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<f64>,
+    /// lower end of the intensities binned, the volume minimum when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    /// upper end of the intensities binned, the volume maximum when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    /// take the minimum and maximum from the voxels inside the mask rather than the whole volume
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub from_mask: bool,
+    /// bin the ranks of the intensities rather than the intensities themselves
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub rank: bool,
+}
+
+impl Binning {
+    pub fn new(n_bins: usize) -> Self {
+        Binning {
+            n_bins,
+            edges: BinEdges::default(),
+            width: None,
+            min: None,
+            max: None,
+            from_mask: false,
+            rank: false,
+        }
+    }
+
+    /// true if the bins are set in intensity by a width or range rather than follow the volume,
+    /// so the volume has to be binned with [`Binning::fit`]
+    pub fn is_fixed(&self) -> bool {
+        self.width.is_some() || self.min.is_some() || self.max.is_some()
+    }
+
+    /// plain description of bins binned with [`Binning::fit`], None for bins that follow the
+    /// whole volume
+    pub fn describe_fit(&self) -> Option<String> {
+        if !self.needs_fit() {
+            return None;
+        }
+        let of = if self.from_mask { "masked" } else { "input" };
+        if self.rank {
+            return Some(format!(
+                "{} bins over the ranks of the {of} intensities",
+                self.n_bins
+            ));
+        }
+        let lower = self
+            .min
+            .map_or(format!("the {of} minimum"), |m| m.to_string());
+        let upper = self
+            .max
+            .map_or(format!("the {of} maximum"), |m| m.to_string());
+        Some(match self.width {
+            Some(width) => format!("bins {width} wide from {lower} to {upper}"),
+            None => format!("{} bins from {lower} to {upper}", self.n_bins),
+        })
+    }
+
+    /// true if the volume has to be binned with [`Binning::fit`], for bins fixed in intensity,
+    /// that follow the masked voxels or bin ranks
+    pub fn needs_fit(&self) -> bool {
+        self.is_fixed() || self.from_mask || self.rank
+    }
+
+    /// true if the mapper's own binning gives the same gray levels, so there is no need to
+    /// discretize ahead of it
+    pub fn is_native(&self) -> bool {
+        self.edges == BinEdges::HalfOpen && !self.needs_fit()
+    }
+
+    /// bytes identifying the binning, for cache keys
+    pub fn key_bytes(&self) -> Vec<u8> {
+        let mut b = (self.n_bins as u64).to_le_bytes().to_vec();
+        b.extend(self.edges.as_str().bytes());
+        // left out of bins that follow the volume, so they keep the keys they had before
+        if self.is_fixed() {
+            b.extend(b"fixed");
+            for v in [self.width, self.min, self.max] {
+                b.extend(v.unwrap_or(f64::NAN).to_le_bytes());
+            }
+        }
+        if self.from_mask {
+            b.extend(b"mask");
+        }
+        if self.rank {
+            b.extend(b"rank");
+        }
+        b
+    }
+
+    /// checks the bin width and range, if any
+    pub fn validate(&self) -> Result<(), String> {
+        if self.rank && self.is_fixed() {
+            return Err(
+                "ranked bins follow the order of the intensities, they can't be given a width \
+                 or range in intensity"
+                    .to_string(),
+            );
+        }
+        if let Some(width) = self.width
+            && !(width.is_finite() && width > 0.)
+        {
+            return Err(format!("the bin width must be above 0, not {width}"));
+        }
+        for (end, value) in [("minimum", self.min), ("maximum", self.max)] {
+            if let Some(v) = value
+                && !v.is_finite()
+            {
+                return Err(format!("the bin {end} must be a finite number, not {v}"));
+            }
+        }
+        if let (Some(min), Some(max)) = (self.min, self.max)
+            && max <= min
+        {
+            return Err(format!(
+                "the bin maximum {max} must be above the bin minimum {min}"
+            ));
+        }
+        Ok(())
+    }
+
+    /// bins each voxel of `vol` into bins fixed in intensity, following the voxels inside `mask`
+    /// or over the ranks of the intensities, numbered from the lowest bin holding a voxel, and
+    /// sets the number of bins to the gray levels the volume spans. Fails if that is more than
+    /// fit in 16 bits or the mask doesn't match the volume. Bins that follow the whole volume are
+    /// left as they are and binned with [`Binning::discretize`]
+    pub fn fit(&mut self, vol: &[f64], mask: Option<&[f64]>) -> Result<Vec<u16>, String> {
+        if !self.needs_fit() {
+            return Ok(self.discretize(vol));
+        }
+        let mask = mask.filter(|_| self.from_mask);
+        if let Some(mask) = mask
+            && mask.len() != vol.len()
+        {
+            return Err(format!(
+                "the mask has {} voxels and the volume {}",
+                mask.len(),
+                vol.len()
+            ));
+        }
+        let ranked;
+        let vol = match self.rank {
+            true => {
+                ranked = ranks(vol, mask);
+                &ranked[..]
+            }
+            false => vol,
+        };
+        let (vol_min, vol_max) = finite_range(vol).unwrap_or((0., 0.));
+        // an empty mask leaves nothing to take the range from
+        let (data_min, data_max) = mask
+            .and_then(|mask| masked_range(vol, mask))
+            .unwrap_or((vol_min, vol_max));
+        let lower = self.min.unwrap_or(data_min);
+        let upper = self.max.unwrap_or(data_max).max(lower);
+        let raw_level = |v: f64| -> i64 {
+            let v = v.clamp(lower, upper);
+            let x = match self.width {
+                Some(width) => (v - lower) / width,
+                None if upper > lower => (v - lower) / (upper - lower) * self.n_bins as f64,
+                None => 0.,
+            };
+            let level = match self.edges {
+                BinEdges::HalfOpen => x.floor() as i64,
+                BinEdges::Inclusive => x.ceil() as i64 - 1,
+            };
+            match self.width {
+                Some(_) => level.max(0),
+                None => level.clamp(0, self.n_bins as i64 - 1),
+            }
+        };
+        let first = raw_level(vol_min);
+        let n_bins = raw_level(vol_max) - first + 1;
+        if n_bins > u16::MAX as i64 + 1 {
+            return Err(format!(
+                "the bins split the range {vol_min} to {vol_max} into {n_bins} gray levels, \
+                 more than the {} radmap can map",
+                u16::MAX as usize + 1
+            ));
+        }
+        let levels = vol
+            .iter()
+            .map(|&v| match v.is_finite() {
+                true => (raw_level(v) - first) as u16,
+                false => 0,
+            })
+            .collect();
+        self.n_bins = n_bins as usize;
+        Ok(levels)
+    }
+
+    /// bins each voxel into `0..n_bins` linearly between the volume minimum and maximum. A
+    /// constant volume maps entirely to level 0 and non-finite values are treated as the minimum.
+    /// Bins fixed in intensity are binned with [`Binning::fit`] instead
+    pub fn discretize(&self, vol: &[f64]) -> Vec<u16> {
+        let mut levels = Vec::with_capacity(vol.len());
+        self.discretize_into(vol, &mut levels);
+        levels
+    }
+
+    /// [`Binning::discretize`] into `levels`, replacing what they held, so the levels of many
+    /// small volumes like kernels can share one buffer
+    pub fn discretize_into(&self, vol: &[f64], levels: &mut Vec<u16>) {
+        let n_bins = self.n_bins;
+        assert!(
+            (1..=u16::MAX as usize + 1).contains(&n_bins),
+            "number of bins must be between 1 and {}",
+            u16::MAX as usize + 1
+        );
+        let (min, max) = finite_range(vol).unwrap_or((0., 0.));
+        let range = max - min;
+        levels.clear();
+        levels.extend(vol.iter().map(|&v| {
+            if !v.is_finite() || range <= 0. {
+                return 0;
+            }
+            let x = (v - min) / range * n_bins as f64;
+            let level = match self.edges {
+                BinEdges::HalfOpen => x.floor() as usize,
+                BinEdges::Inclusive => (x.ceil() as usize).saturating_sub(1),
+            };
+            level.min(n_bins - 1) as u16
+        }));
+    }
+}
+
+/// gray levels as the f64 values the mapper expects
+pub fn levels_to_f64(levels: &[u16]) -> Vec<f64> {
+    levels.iter().map(|&l| l as f64).collect()
+}
+
+/// the rank of each finite voxel of `vol` among the finite voxels set in `mask`, or among all of
+/// them, from 0. Ties share the average of their ranks and voxels outside the mask rank between
+/// the masked voxels they lie between. Non-finite voxels stay as they are
+pub fn ranks(vol: &[f64], mask: Option<&[f64]>) -> Vec<f64> {
+    let mut sorted: Vec<f64> = match mask {
+        Some(mask) => vol
+            .iter()
+            .zip(mask)
+            .filter(|(v, m)| v.is_finite() && **m != 0.)
+            .map(|(v, _)| *v)
+            .collect(),
+        None => vol.iter().copied().filter(|v| v.is_finite()).collect(),
+    };
+    sorted.sort_unstable_by(f64::total_cmp);
+    vol.iter()
+        .map(|&v| {
+            if !v.is_finite() {
+                return v;
+            }
+            let below = sorted.partition_point(|&s| s < v);
+            let up_to = sorted.partition_point(|&s| s <= v);
+            (below + up_to) as f64 / 2. - 0.5
+        })
+        .collect()
+}
+
+/// minimum and maximum of the finite values in the volume
+pub fn finite_range(vol: &[f64]) -> Option<(f64, f64)> {
+    vol.iter()
+        .filter(|v| v.is_finite())
+        .fold(None, |range, &v| match range {
+            None => Some((v, v)),
+            Some((min, max)) => Some((v.min(min), v.max(max))),
+        })
+}
+
+/// minimum and maximum of the finite values of the voxels set in `mask`
+pub fn masked_range(vol: &[f64], mask: &[f64]) -> Option<(f64, f64)> {
+    vol.iter()
+        .zip(mask)
+        .filter(|(v, m)| v.is_finite() && **m != 0.)
+        .fold(None, |range, (&v, _)| match range {
+            None => Some((v, v)),
+            Some((min, max)) => Some((v.min(min), v.max(max))),
+        })
+}
+
+/// most distinct values an input may hold to be taken as already binned. Label maps and gray
+/// levels binned by another tool hold a few dozen at most, while 8-bit images or narrow integer
+/// crops of CT easily hold more and are binned like any other input
+pub const MAX_INTEGER_LEVELS: usize = 64;
+
+/// the distinct values of an input that is already quantized, in ascending order: every finite
+/// voxel is an integer and they take between 2 and [MAX_INTEGER_LEVELS] distinct values. A
+/// constant input has a single level and is binned as usual. The levels may leave gaps, so
+/// [`level_indices`] turns them into gray levels
+pub fn integer_levels(vol: &[f64]) -> Option<Vec<f64>> {
+    let mut levels = std::collections::BTreeSet::new();
+    for v in vol.iter().filter(|v| v.is_finite()) {
+        if v.fract() != 0. {
+            return None;
+        }
+        levels.insert(*v as i64);
+        if levels.len() > MAX_INTEGER_LEVELS {
+            return None;
+        }
+    }
+    (levels.len() >= 2).then(|| levels.into_iter().map(|l| l as f64).collect())
+}
+
+/// gray levels `0..levels.len()` of an input quantized to `levels`, each voxel getting the index
+/// of its level. Non-finite voxels are left as they are
+pub fn level_indices(vol: &[f64], levels: &[f64]) -> Vec<f64> {
+    vol.iter()
+        .map(|v| match levels.binary_search_by(|l| l.total_cmp(v)) {
+            Ok(i) if v.is_finite() => i as f64,
+            _ => *v,
+        })
+        .collect()
+}
+
+/// message for a default bin count that doesn't match the levels of a quantized input, whose
+/// levels are used instead
+pub fn integer_levels_warning(n_levels: usize, requested_bins: usize) -> Option<String> {
+    (n_levels != requested_bins).then(|| {
+        format!(
+            "input is already quantized to {n_levels} integer levels, using {n_levels} bins instead of {requested_bins}"
+        )
+    })
+}
+
+/// message for a quantized input that is binned anyway, as the number of bins was asked for
+pub fn integer_levels_rebinned(n_levels: usize, n_bins: usize) -> Option<String> {
+    (n_levels != n_bins).then(|| {
+        format!(
+            "input looks quantized to {n_levels} integer levels, binning it into the {n_bins} bins asked for"
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fitted(binning: &mut Binning, vol: &[f64], mask: Option<&[f64]>) -> Vec<u16> {
+        binning.validate().unwrap();
+        binning.fit(vol, mask).unwrap()
+    }
+
+    #[test]
+    fn edge_values_go_up_in_half_open_bins_and_down_in_inclusive_ones() {
+        let vol = [0., 1., 2., 3., 4.];
+        for (edges, expected) in [
+            (BinEdges::HalfOpen, [0, 1, 2, 3, 3]),
+            (BinEdges::Inclusive, [0, 0, 1, 2, 3]),
+        ] {
+            let mut binning = Binning {
+                edges,
+                min: Some(0.),
+                max: Some(4.),
+                ..Binning::new(4)
+            };
+            assert_eq!(fitted(&mut binning, &vol, None), expected, "{edges:?}");
+            assert_eq!(binning.n_bins, 4);
+            // bins that follow the volume place the edges the same way
+            assert_eq!(Binning { edges, ..Binning::new(4) }.discretize(&vol), expected);
+        }
+    }
+
+    #[test]
+    fn bin_widths_round_to_whole_bins() {
+        let vol = [0., 2.4, 2.5, 7.4, 10.];
+        for (edges, expected, n_bins) in [
+            (BinEdges::HalfOpen, vec![0, 0, 1, 2, 4], 5),
+            (BinEdges::Inclusive, vec![0, 0, 0, 2, 3], 4),
+        ] {
+            let mut binning = Binning {
+                edges,
+                width: Some(2.5),
+                ..Binning::new(32)
+            };
+            assert_eq!(fitted(&mut binning, &vol, None), expected, "{edges:?}");
+            assert_eq!(binning.n_bins, n_bins, "{edges:?}");
+        }
+    }
+
+    #[test]
+    fn levels_count_from_the_lowest_bin_holding_a_voxel() {
+        let mut binning = Binning {
+            width: Some(5.),
+            min: Some(0.),
+            ..Binning::new(32)
+        };
+        assert_eq!(fitted(&mut binning, &[10., 12., 16.], None), [0, 0, 1]);
+        assert_eq!(binning.n_bins, 2);
+    }
+
+    #[test]
+    fn intensities_outside_the_range_are_clamped_to_the_end_bins() {
+        let mut binning = Binning {
+            min: Some(2.),
+            max: Some(6.),
+            ..Binning::new(4)
+        };
+        assert_eq!(
+            fitted(&mut binning, &[0., 2., 4., 6., 9.], None),
+            [0, 0, 2, 3, 3]
+        );
+        assert_eq!(binning.n_bins, 4);
+    }
+
+    #[test]
+    fn a_constant_volume_is_one_gray_level() {
+        let vol = [3.; 5];
+        assert_eq!(Binning::new(8).discretize(&vol), [0; 5]);
+        for mut binning in [
+            Binning {
+                width: Some(1.),
+                ..Binning::new(8)
+            },
+            Binning {
+                from_mask: true,
+                ..Binning::new(8)
+            },
+            Binning {
+                rank: true,
+                ..Binning::new(8)
+            },
+        ] {
+            assert_eq!(fitted(&mut binning, &vol, Some(&[1.; 5])), [0; 5]);
+            assert_eq!(binning.n_bins, 1);
+        }
+    }
+
+    #[test]
+    fn bins_that_follow_the_volume_are_left_as_they_are() {
+        let mut binning = Binning::new(4);
+        let vol = [0., 1., 2., 3., 4.];
+        assert_eq!(fitted(&mut binning, &vol, None), binning.discretize(&vol));
+        assert_eq!(binning.n_bins, 4);
+    }
+
+    #[test]
+    fn bins_from_the_mask_take_the_masked_range() {
+        let vol = [0., 10., 20., 30., 100.];
+        let mut binning = Binning {
+            from_mask: true,
+            ..Binning::new(4)
+        };
+        assert_eq!(
+            fitted(&mut binning, &vol, Some(&[0., 1., 1., 1., 0.])),
+            [0, 0, 2, 3, 3]
+        );
+        // an empty mask falls back to the whole volume
+        let mut binning = Binning {
+            from_mask: true,
+            ..Binning::new(4)
+        };
+        assert_eq!(fitted(&mut binning, &vol, Some(&[0.; 5])), [0, 0, 0, 1, 3]);
+        assert!(binning.fit(&vol, Some(&[1.; 2])).is_err());
+        // the mask is only read for bins from the mask
+        let mut binning = Binning {
+            min: Some(0.),
+            ..Binning::new(4)
+        };
+        assert_eq!(fitted(&mut binning, &vol, Some(&[1.; 2])), [0, 0, 0, 1, 3]);
+    }
+
+    #[test]
+    fn ranks_share_ties_and_place_unmasked_voxels_between_masked_ones() {
+        assert_eq!(
+            ranks(&[5., 1., 1., 100., 7.], None),
+            [2., 0.5, 0.5, 4., 3.]
+        );
+        let ranked = ranks(&[1., 2., 3., 4., f64::NAN], Some(&[1., 0., 1., 0., 1.]));
+        assert_eq!(ranked[..4], [0., 0.5, 1., 1.5]);
+        assert!(ranked[4].is_nan());
+    }
+
+    #[test]
+    fn ranked_bins_split_the_ranks() {
+        let mut binning = Binning {
+            rank: true,
+            ..Binning::new(4)
+        };
+        assert_eq!(
+            fitted(&mut binning, &[5., 1., 1., 100., 7.], None),
+            [1, 0, 0, 3, 2]
+        );
+        assert_eq!(binning.n_bins, 4);
+        binning.width = Some(1.);
+        assert!(binning.validate().is_err());
+    }
+
+    #[test]
+    fn too_many_levels_are_refused() {
+        let mut binning = Binning {
+            width: Some(1.),
+            ..Binning::new(32)
+        };
+        assert!(binning.fit(&[0., 1e6], None).is_err());
+    }
+}
@@ -0,0 +1,278 @@
+//! How well each feature map separates the regions of a label map, e.g. tumor from edema, as a
+//! first pass at choosing features before any modelling. Only labelled voxels where the map is
+//! finite count, and background (label 0) is left out.
+//!
+//! The area under the ROC curve is that of the positive label against every other label, with
+//! ties counted as half. Below 0.5 the feature is lower in the positive label. Mutual information
+//! is between the feature and all labels, in bits, with the feature binned into [`MI_BINS`] bins
+//! of equal counts so outliers don't crowd every voxel into a few bins. It is at most the entropy
+//! of the labels, 1 bit for two labels of the same size.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::format::strip_volume_extension;
+use crate::io::{is_volume, read_volume};
+use crate::locale::ReportFormat;
+
+/// bins of equal counts the feature is binned into for the mutual information
+pub const MI_BINS: usize = 16;
+
+/// discriminative statistics of one feature map
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FeatureScore {
+    pub feature: String,
+    /// area under the ROC curve of the positive label against the others, NaN without voxels of
+    /// both
+    pub auc: f64,
+    /// mutual information between the feature and the labels in bits
+    pub mutual_information: f64,
+    /// labelled voxels where the map is finite
+    pub n_voxels: usize,
+}
+
+impl FeatureScore {
+    /// distance of the AUC from chance, 0 to 1 whichever label is higher
+    pub fn separation(&self) -> f64 {
+        (2. * self.auc - 1.).abs()
+    }
+}
+
+/// the features of a label map, best first
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Ranking {
+    pub labels: PathBuf,
+    /// labelled voxels of each label
+    pub label_counts: BTreeMap<i64, usize>,
+    /// label the AUC is of
+    pub positive: i64,
+    /// by mutual information, then by separation
+    pub features: Vec<FeatureScore>,
+}
+
+impl Ranking {
+    /// scores `maps` against the label map in `labels`. Labels are the rounded values of the
+    /// voxels. The positive label defaults to the largest
+    pub fn compute(labels: &Path, maps: &[PathBuf], positive: Option<i64>) -> Result<Self, String> {
+        let (label_vol, label_dims, _) = read_volume(labels);
+        let label_of: Vec<i64> = label_vol.iter().map(|v| v.round() as i64).collect();
+        let mut label_counts = BTreeMap::new();
+        for &l in label_of.iter().filter(|l| **l != 0) {
+            *label_counts.entry(l).or_insert(0) += 1;
+        }
+        if label_counts.len() < 2 {
+            return Err(format!(
+                "{} needs at least two non-zero labels to compare, found {}",
+                labels.display(),
+                label_counts.len()
+            ));
+        }
+        let positive = match positive {
+            Some(p) if !label_counts.contains_key(&p) => {
+                return Err(format!("label {p} is not in {}", labels.display()));
+            }
+            Some(p) => p,
+            None => *label_counts.keys().next_back().unwrap(),
+        };
+
+        let mut features = maps
+            .iter()
+            .map(|path| {
+                let (map, dims, _) = read_volume(path);
+                if dims.shape_ns()[..3] != label_dims.shape_ns()[..3] {
+                    return Err(format!(
+                        "{} and {} have different shapes",
+                        path.display(),
+                        labels.display()
+                    ));
+                }
+                let samples: Vec<(f64, i64)> = map
+                    .iter()
+                    .zip(&label_of)
+                    .filter(|(v, l)| **l != 0 && v.is_finite())
+                    .map(|(v, l)| (*v, *l))
+                    .collect();
+                Ok(score(map_name(path), samples, positive))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        features.sort_by(|a, b| {
+            b.mutual_information
+                .total_cmp(&a.mutual_information)
+                .then(b.separation().total_cmp(&a.separation()))
+        });
+        Ok(Ranking {
+            labels: labels.to_path_buf(),
+            label_counts,
+            positive,
+            features,
+        })
+    }
+
+    pub fn to_csv(&self, format: &ReportFormat) -> String {
+        let mut csv = format.header(&["rank", "feature", "auc", "mutual_information", "n_voxels"]);
+        csv.push('\n');
+        for (i, f) in self.features.iter().enumerate() {
+            csv.push_str(&format.row(&[
+                (i + 1).to_string(),
+                f.feature.clone(),
+                format.number(f.auc, 4),
+                format.number(f.mutual_information, 4),
+                f.n_voxels.to_string(),
+            ]));
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+/// the feature maps among `paths`, taking every nifti and nrrd file of a directory
+pub fn feature_map_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let mut files = vec![];
+    for path in paths {
+        if path.is_dir() {
+            let mut in_dir: Vec<PathBuf> = std::fs::read_dir(path)
+                .map_err(|e| format!("failed to list {}: {e}", path.display()))?
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.is_file() && is_volume(p))
+                .collect();
+            in_dir.sort();
+            files.extend(in_dir);
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+/// file name of a map without its extension
+fn map_name(path: &Path) -> String {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    strip_volume_extension(&name).to_string()
+}
+
+fn score(feature: String, mut samples: Vec<(f64, i64)>, positive: i64) -> FeatureScore {
+    samples.par_sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+    FeatureScore {
+        feature,
+        auc: auc(&samples, positive),
+        mutual_information: mutual_information(&samples),
+        n_voxels: samples.len(),
+    }
+}
+
+/// Mann-Whitney estimate of the AUC from samples sorted by value, tied values sharing their
+/// average rank
+fn auc(sorted: &[(f64, i64)], positive: i64) -> f64 {
+    let n_pos = sorted.iter().filter(|(_, l)| *l == positive).count() as f64;
+    let n_neg = sorted.len() as f64 - n_pos;
+    let mut rank_sum = 0.;
+    let mut i = 0;
+    while i < sorted.len() {
+        let j = i + sorted[i..].partition_point(|s| s.0 == sorted[i].0);
+        // ranks i + 1 through j
+        let average_rank = (i + j + 1) as f64 / 2.;
+        let ties_pos = sorted[i..j].iter().filter(|(_, l)| *l == positive).count();
+        rank_sum += average_rank * ties_pos as f64;
+        i = j;
+    }
+    (rank_sum - n_pos * (n_pos + 1.) / 2.) / (n_pos * n_neg)
+}
+
+/// mutual information in bits from samples sorted by value, over bins of equal counts. Tied
+/// values stay in one bin
+fn mutual_information(sorted: &[(f64, i64)]) -> f64 {
+    let n = sorted.len();
+    if n == 0 {
+        return f64::NAN;
+    }
+    let mut joint: BTreeMap<(usize, i64), usize> = BTreeMap::new();
+    let mut bin_counts = [0; MI_BINS];
+    let mut label_counts: BTreeMap<i64, usize> = BTreeMap::new();
+    let mut bin = 0;
+    for (i, (v, l)) in sorted.iter().enumerate() {
+        if i > 0 && *v != sorted[i - 1].0 {
+            bin = (i * MI_BINS / n).max(bin);
+        }
+        *joint.entry((bin, *l)).or_insert(0) += 1;
+        bin_counts[bin] += 1;
+        *label_counts.entry(*l).or_insert(0) += 1;
+    }
+    let n = n as f64;
+    joint
+        .iter()
+        .map(|((bin, l), count)| {
+            let p = *count as f64 / n;
+            let independent = bin_counts[*bin] as f64 / n * label_counts[l] as f64 / n;
+            p * (p / independent).log2()
+        })
+        .sum::<f64>()
+        .max(0.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// samples of `values` labelled `labels`, sorted by value
+    fn samples(values: &[f64], labels: &[i64]) -> Vec<(f64, i64)> {
+        let mut samples: Vec<(f64, i64)> =
+            values.iter().copied().zip(labels.iter().copied()).collect();
+        samples.sort_by(|a, b| a.0.total_cmp(&b.0));
+        samples
+    }
+
+    #[test]
+    fn separable_labels_have_an_auc_of_1_or_0() {
+        let s = samples(&[1., 2., 3., 4.], &[0, 0, 1, 1]);
+        assert_eq!(auc(&s, 1), 1.);
+        assert_eq!(auc(&s, 0), 0.);
+    }
+
+    #[test]
+    fn identical_values_have_an_auc_of_one_half() {
+        let s = samples(&[5.; 4], &[0, 1, 0, 1]);
+        assert_eq!(auc(&s, 1), 0.5);
+        assert_eq!(mutual_information(&s), 0.);
+    }
+
+    #[test]
+    fn the_auc_counts_the_pairs_the_positive_label_wins() {
+        // positives at ranks 2, 4 and 5 win 5 of the 6 pairs
+        let s = samples(&[1., 2., 3., 4., 5.], &[0, 1, 0, 1, 1]);
+        assert!((auc(&s, 1) - 5. / 6.).abs() < 1e-12);
+        // a tie between labels counts half: 2 wins, 1 tie, 1 more win of 4 pairs
+        let s = samples(&[1., 2., 2., 3.], &[0, 1, 0, 1]);
+        assert_eq!(auc(&s, 1), 0.875);
+        // without voxels of both labels there is nothing to compare
+        assert!(auc(&samples(&[1., 2.], &[1, 1]), 1).is_nan());
+    }
+
+    #[test]
+    fn mutual_information_is_the_label_entropy_when_the_feature_decides_the_label() {
+        let values: Vec<f64> = (0..32).map(f64::from).collect();
+        // two labels of 16 voxels, split by the feature, fill two bins of two voxels each
+        let split: Vec<i64> = (0..32).map(|i| (i >= 16) as i64).collect();
+        let mi = mutual_information(&samples(&values, &split));
+        assert!((mi - 1.).abs() < 1e-12, "{mi}");
+        // four labels of 8 voxels make 2 bits
+        let quarters: Vec<i64> = (0..32).map(|i| i / 8).collect();
+        let mi = mutual_information(&samples(&values, &quarters));
+        assert!((mi - 2.).abs() < 1e-12, "{mi}");
+        // labels alternating within every bin tell nothing about the feature
+        let alternating: Vec<i64> = (0..32).map(|i| i % 2).collect();
+        let mi = mutual_information(&samples(&values, &alternating));
+        assert!(mi.abs() < 1e-12, "{mi}");
+        assert!(mutual_information(&[]).is_nan());
+    }
+
+    #[test]
+    fn scores_sort_their_samples() {
+        let score = score("f".to_string(), vec![(4., 1), (1., 0), (3., 1), (2., 0)], 1);
+        assert_eq!(score.auc, 1.);
+        assert_eq!(score.separation(), 1.);
+        assert_eq!(score.n_voxels, 4);
+    }
+}
@@ -0,0 +1,243 @@
+//! Exit code contract of the command line tool and the `failure.json` summary written next to the
+//! outputs when a run fails, so workflow engines can branch on the kind of failure.
+//!
+//! | code | meaning |
+//! |------|---------|
+//! | 0 | success |
+//! | 1 | internal error, see the crash report |
+//! | 2 | invalid command line options |
+//! | 3 | the input volume is missing or unreadable |
+//! | 4 | the mask is missing, unreadable or doesn't match the input |
+//! | 5 | out of memory (killed for exceeding the memory budget in batch mode) |
+//! | 6 | cancelled by a signal or the per-case timeout |
+//! | 7 | the outputs could not be written |
+//! | 8 | the features came out degenerate (mostly NaN or flat), with `--abort-if-degenerate` |
+
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::schema::Format;
+use serde::Serialize;
+
+use crate::{crash, history};
+
+pub const FAILURE_FILE: &str = "failure.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    Internal,
+    Usage,
+    BadInput,
+    BadMask,
+    OutOfMemory,
+    Cancelled,
+    WriteFailure,
+    DegenerateResult,
+}
+
+impl FailureKind {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            FailureKind::Internal => 1,
+            FailureKind::Usage => 2,
+            FailureKind::BadInput => 3,
+            FailureKind::BadMask => 4,
+            FailureKind::OutOfMemory => 5,
+            FailureKind::Cancelled => 6,
+            FailureKind::WriteFailure => 7,
+            FailureKind::DegenerateResult => 8,
+        }
+    }
+
+    pub fn from_exit_code(code: i32) -> Option<Self> {
+        Some(match code {
+            1 => FailureKind::Internal,
+            2 => FailureKind::Usage,
+            3 => FailureKind::BadInput,
+            4 => FailureKind::BadMask,
+            5 => FailureKind::OutOfMemory,
+            6 => FailureKind::Cancelled,
+            7 => FailureKind::WriteFailure,
+            8 => FailureKind::DegenerateResult,
+            _ => return None,
+        })
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureKind::Internal => "internal_error",
+            FailureKind::Usage => "invalid_options",
+            FailureKind::BadInput => "bad_input",
+            FailureKind::BadMask => "bad_mask",
+            FailureKind::OutOfMemory => "out_of_memory",
+            FailureKind::Cancelled => "cancelled",
+            FailureKind::WriteFailure => "write_failure",
+            FailureKind::DegenerateResult => "degenerate_result",
+        }
+    }
+}
+
+/// the part of a run in progress, used to classify panics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Validation,
+    LoadInput,
+    LoadMask,
+    Compute,
+    WriteOutput,
+}
+
+impl Stage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Validation => "validation",
+            Stage::LoadInput => "load_input",
+            Stage::LoadMask => "load_mask",
+            Stage::Compute => "compute",
+            Stage::WriteOutput => "write_output",
+        }
+    }
+
+    /// what a panic during this stage means for the caller
+    pub fn failure_kind(&self) -> FailureKind {
+        match self {
+            Stage::Validation => FailureKind::Usage,
+            Stage::LoadInput => FailureKind::BadInput,
+            Stage::LoadMask => FailureKind::BadMask,
+            Stage::Compute => FailureKind::Internal,
+            Stage::WriteOutput => FailureKind::WriteFailure,
+        }
+    }
+}
+
+struct FailureContext {
+    stage: Option<Stage>,
+    dir: Option<PathBuf>,
+}
+
+static CONTEXT: Mutex<FailureContext> = Mutex::new(FailureContext {
+    stage: None,
+    dir: None,
+});
+
+thread_local! {
+    /// stage of work running on a helper thread, e.g. loading the mask next to the volume
+    static THREAD_STAGE: Cell<Option<Stage>> = const { Cell::new(None) };
+}
+
+fn context() -> std::sync::MutexGuard<'static, FailureContext> {
+    CONTEXT.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// stage of the run as a whole, which also applies to the calling thread
+pub fn set_stage(stage: Stage) {
+    context().stage = Some(stage);
+    THREAD_STAGE.set(Some(stage));
+}
+
+/// stage of the work on the calling thread only, for helpers running next to the main stage
+pub fn set_thread_stage(stage: Stage) {
+    THREAD_STAGE.set(Some(stage));
+}
+
+/// stage of the calling thread, falling back to the stage of the run
+pub fn current_stage() -> Option<Stage> {
+    THREAD_STAGE.get().or(context().stage)
+}
+
+/// directory `failure.json` is written to. Nothing is written until this is set. A summary left
+/// over from an earlier run in the same directory is removed
+pub fn set_failure_dir(dir: impl AsRef<Path>) {
+    let dir = dir.as_ref();
+    let _ = std::fs::remove_file(dir.join(FAILURE_FILE));
+    context().dir = Some(dir.to_path_buf());
+}
+
+/// `failure.json` as it is written
+#[derive(Serialize)]
+struct SummaryJson<'a> {
+    format_version: u32,
+    kind: &'static str,
+    exit_code: i32,
+    stage: Option<&'static str>,
+    message: &'a str,
+    crash_report: Option<String>,
+}
+
+pub struct FailureSummary {
+    pub kind: FailureKind,
+    pub stage: Option<Stage>,
+    pub message: String,
+    pub crash_report: Option<PathBuf>,
+}
+
+impl FailureSummary {
+    pub fn to_json(&self) -> String {
+        let summary = SummaryJson {
+            format_version: Format::FailureSummary.current(),
+            kind: self.kind.as_str(),
+            exit_code: self.kind.exit_code(),
+            stage: self.stage.map(|s| s.as_str()),
+            message: &self.message,
+            crash_report: self
+                .crash_report
+                .as_ref()
+                .map(|p| p.to_string_lossy().into_owned()),
+        };
+        serde_json::to_string_pretty(&summary).expect("failure summaries are always representable as json")
+            + "\n"
+    }
+
+    pub fn write(&self, dir: &Path) -> Result<PathBuf, String> {
+        let path = dir.join(FAILURE_FILE);
+        std::fs::write(&path, self.to_json())
+            .map_err(|e| format!("failed to write {}: {e}", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// writes `failure.json` to the failure directory, if one was set, and exits with the code for
+/// the kind of failure
+pub fn exit_with(kind: FailureKind, message: impl Into<String>) -> ! {
+    let stage = current_stage();
+    let dir = context().dir.clone();
+    let summary = FailureSummary {
+        kind,
+        stage,
+        message: message.into(),
+        crash_report: crash::take_last_report(),
+    };
+    history::fail_current_run(kind, &summary.message);
+    if let Some(dir) = dir {
+        match summary.write(&dir) {
+            Ok(path) => eprintln!("failure summary written to {}", path.display()),
+            Err(e) => eprintln!("{e}"),
+        }
+    }
+    std::process::exit(kind.exit_code())
+}
+
+/// chains onto the crash report hook so any panic ends the process with the exit code of the
+/// stage it happened in, instead of unwinding into whichever thread joins it
+pub fn exit_on_panic() {
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        hook(info);
+        let message = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or("panic".to_string());
+        let kind = current_stage()
+            .map(|s| s.failure_kind())
+            .unwrap_or(FailureKind::Internal);
+        exit_with(kind, message)
+    }));
+}
+
+/// exits as cancelled on ctrl-c or a termination signal
+pub fn exit_on_signal() {
+    let _ = ctrlc::set_handler(|| exit_with(FailureKind::Cancelled, "cancelled by signal"));
+}
@@ -0,0 +1,268 @@
+//! 3D Gabor filters: a plane wave along an orientation under a Gaussian envelope. The Gaussian
+//! has a width of 0.56 wavelengths, a bandwidth of about one octave, and is cut off at 3 sigma.
+//! The kernel is made zero mean so regions of constant intensity respond with 0. Both the
+//! envelope and the wave factor over the axes, so the filter is applied as three 1D complex
+//! convolutions, with the volume extended by repeating its edge voxels. The response is the
+//! magnitude of the complex result.
+
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// width of the Gaussian envelope in wavelengths
+const SIGMA_WAVELENGTHS: f64 = 0.56;
+
+/// direction of the wave of a Gabor filter
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Orientation {
+    X,
+    Y,
+    Z,
+    /// any other direction in voxels, not necessarily of unit length
+    Vector([f64; 3]),
+}
+
+impl Orientation {
+    pub fn unit(&self) -> [f64; 3] {
+        let v = match self {
+            Orientation::X => [1., 0., 0.],
+            Orientation::Y => [0., 1., 0.],
+            Orientation::Z => [0., 0., 1.],
+            Orientation::Vector(v) => *v,
+        };
+        let len = v.iter().map(|c| c * c).sum::<f64>().sqrt();
+        v.map(|c| c / len)
+    }
+}
+
+/// `x`, `y`, `z` or the vector as `1_1_0`
+impl std::fmt::Display for Orientation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Orientation::X => f.write_str("x"),
+            Orientation::Y => f.write_str("y"),
+            Orientation::Z => f.write_str("z"),
+            Orientation::Vector([x, y, z]) => write!(f, "{x}_{y}_{z}"),
+        }
+    }
+}
+
+/// an axis, `x`, `y` or `z`, or a vector `x,y,z`
+impl FromStr for Orientation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "x" => return Ok(Orientation::X),
+            "y" => return Ok(Orientation::Y),
+            "z" => return Ok(Orientation::Z),
+            _ => {}
+        }
+        let v: Vec<f64> = s
+            .split(',')
+            .map(|c| c.trim().parse::<f64>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("orientation {s} is not x, y, z or a vector like 1,1,0"))?;
+        match v[..] {
+            [x, y, z] if v.iter().all(|c| c.is_finite()) && v.iter().any(|c| *c != 0.) => {
+                Ok(Orientation::Vector([x, y, z]))
+            }
+            _ => Err(format!(
+                "orientation {s} needs 3 finite components, not all 0"
+            )),
+        }
+    }
+}
+
+/// a Gabor filter of one frequency and orientation
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Gabor {
+    /// cycles per voxel, above 0 and at most 0.5
+    pub frequency: f64,
+    pub orientation: Orientation,
+}
+
+impl Gabor {
+    /// e.g. `gabor_f0.25_x`
+    pub fn name(&self) -> String {
+        format!("gabor_f{}_{}", self.frequency, self.orientation)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.frequency > 0. && self.frequency <= 0.5) {
+            return Err(format!(
+                "Gabor frequency {} must be above 0 and at most 0.5 cycles per voxel",
+                self.frequency
+            ));
+        }
+        Ok(())
+    }
+
+    /// magnitude of the response of `vol`, x fastest
+    pub fn apply(&self, vol: &[f64], shape: [usize; 3]) -> Vec<f64> {
+        let sigma = SIGMA_WAVELENGTHS / self.frequency;
+        let radius = (3. * sigma).ceil() as isize;
+        let u = self.orientation.unit();
+
+        // per axis, the envelope and the envelope times the wave, and the sum of the latter
+        let mut waves = vec![];
+        let mut envelopes = vec![];
+        let mut dc = [1., 0.];
+        for d in u {
+            let envelope: Vec<f64> = (-radius..=radius)
+                .map(|t| (-(t * t) as f64 / (2. * sigma * sigma)).exp())
+                .collect();
+            let total: f64 = envelope.iter().sum();
+            let envelope: Vec<f64> = envelope.iter().map(|g| g / total).collect();
+            let wave: Vec<[f64; 2]> = (-radius..=radius)
+                .zip(&envelope)
+                .map(|(t, g)| {
+                    let phase = 2. * PI * self.frequency * d * t as f64;
+                    [g * phase.cos(), g * phase.sin()]
+                })
+                .collect();
+            let sum = wave
+                .iter()
+                .fold([0., 0.], |a, w| [a[0] + w[0], a[1] + w[1]]);
+            dc = mul(dc, sum);
+            waves.push(wave);
+            envelopes.push(envelope.into_iter().map(|g| [g, 0.]).collect::<Vec<_>>());
+        }
+
+        let complex: Vec<[f64; 2]> = vol.iter().map(|v| [*v, 0.]).collect();
+        let mut response = complex.clone();
+        let mut smooth = complex;
+        for axis in 0..3 {
+            response = convolve_axis(&response, shape, axis, &waves[axis]);
+            smooth = convolve_axis(&smooth, shape, axis, &envelopes[axis]);
+        }
+        // subtracting the envelope scaled to the same sum as the wave makes the kernel zero mean
+        response
+            .par_iter()
+            .zip(&smooth)
+            .map(|(r, s)| {
+                let [re, im] = mul(dc, *s);
+                (r[0] - re).hypot(r[1] - im)
+            })
+            .collect()
+    }
+}
+
+fn mul(a: [f64; 2], b: [f64; 2]) -> [f64; 2] {
+    [a[0] * b[0] - a[1] * b[1], a[0] * b[1] + a[1] * b[0]]
+}
+
+/// convolution of `data` along `axis` with a kernel centred on its middle tap, repeating the
+/// edge voxels
+fn convolve_axis(
+    data: &[[f64; 2]],
+    shape: [usize; 3],
+    axis: usize,
+    kernel: &[[f64; 2]],
+) -> Vec<[f64; 2]> {
+    let stride = [1, shape[0], shape[0] * shape[1]][axis];
+    let len = shape[axis] as isize;
+    let radius = (kernel.len() / 2) as isize;
+    (0..data.len())
+        .into_par_iter()
+        .map(|i| {
+            let pos = ((i / stride) % shape[axis]) as isize;
+            let start = i - pos as usize * stride;
+            kernel.iter().enumerate().fold([0., 0.], |acc, (k, w)| {
+                let p = (pos + k as isize - radius).clamp(0, len - 1) as usize;
+                let v = mul(*w, data[start + p * stride]);
+                [acc[0] + v[0], acc[1] + v[1]]
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHAPE: [usize; 3] = [15, 15, 15];
+
+    fn index([x, y, z]: [usize; 3]) -> usize {
+        x + SHAPE[0] * (y + SHAPE[1] * z)
+    }
+
+    fn gabor(frequency: f64, orientation: Orientation) -> Gabor {
+        Gabor {
+            frequency,
+            orientation,
+        }
+    }
+
+    #[test]
+    fn a_constant_volume_has_no_response() {
+        let vol = vec![5.; SHAPE.iter().product()];
+        for orientation in [Orientation::X, Orientation::Z, Orientation::Vector([1., 1., 0.])] {
+            let response = gabor(0.25, orientation).apply(&vol, SHAPE);
+            assert!(response.iter().all(|r| r.abs() < 1e-12), "{orientation}");
+        }
+    }
+
+    #[test]
+    fn only_changes_along_the_orientation_respond() {
+        // a wave along y and z, constant along x
+        let vol: Vec<f64> = (0..SHAPE.iter().product())
+            .map(|i| {
+                let (y, z) = ((i / SHAPE[0]) % SHAPE[1], i / (SHAPE[0] * SHAPE[1]));
+                (y as f64 * 1.3).sin() + (z as f64 * 0.7).cos()
+            })
+            .collect();
+        let across = gabor(0.25, Orientation::X).apply(&vol, SHAPE);
+        assert!(across.iter().all(|r| r.abs() < 1e-12));
+        let along = gabor(0.25, Orientation::Y).apply(&vol, SHAPE);
+        assert!(along[index([7, 7, 7])] > 0.1);
+    }
+
+    #[test]
+    fn the_response_to_a_point_is_the_kernel() {
+        // sigma is 2.24 voxels, so the kernel reaches 7 voxels from its centre
+        let (frequency, sigma) = (0.25, SIGMA_WAVELENGTHS / 0.25);
+        let mut vol = vec![0.; SHAPE.iter().product()];
+        vol[index([7, 7, 7])] = 1.;
+        let response = gabor(frequency, Orientation::X).apply(&vol, SHAPE);
+        let at = |p| response[index(p)];
+
+        // the envelope, normalized along each axis, and the mean of the wave along x
+        let envelope: Vec<f64> = (-7..=7)
+            .map(|t: i32| (-(t * t) as f64 / (2. * sigma * sigma)).exp())
+            .collect();
+        let total: f64 = envelope.iter().sum();
+        let g = |t: i32| envelope[(t + 7) as usize] / total;
+        let dc: f64 = (-7..=7)
+            .map(|t| g(t) * (2. * PI * frequency * t as f64).cos())
+            .sum();
+        // |g(x) g(y) g(z) (exp(i 2 pi f x) - dc)|, the wave less its mean
+        let kernel = |[x, y, z]: [i32; 3]| {
+            let phase = 2. * PI * frequency * x as f64;
+            g(x) * g(y) * g(z) * (phase.cos() - dc).hypot(phase.sin())
+        };
+        for offset in [[0, 0, 0], [1, 0, 0], [-3, 2, 0], [2, -1, 4], [0, 7, -7]] {
+            let p = [0, 1, 2].map(|d| (7 + offset[d]) as usize);
+            assert!((at(p) - kernel(offset)).abs() < 1e-15, "{offset:?}");
+        }
+        // the magnitude is symmetric about the point
+        assert!((at([5, 7, 7]) - at([9, 7, 7])).abs() < 1e-15);
+    }
+
+    #[test]
+    fn waves_of_the_filter_frequency_respond_most() {
+        let wave = |frequency: f64| -> Vec<f64> {
+            (0..SHAPE.iter().product())
+                .map(|i| (2. * PI * frequency * (i % SHAPE[0]) as f64).cos())
+                .collect()
+        };
+        let filter = gabor(0.25, Orientation::X);
+        let centre = |frequency| filter.apply(&wave(frequency), SHAPE)[index([7, 7, 7])];
+        let tuned = centre(0.25);
+        for other in [0.05, 0.1, 0.45] {
+            assert!(centre(other) < tuned, "{other}");
+        }
+    }
+}
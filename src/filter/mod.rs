@@ -0,0 +1,201 @@
+//! Filtered copies of the input, each named after its filter in the output names. The responses
+//! are either written as maps of their own (`<input>_<filter>`), or mapped like the input with
+//! every selected feature written as `<input>_<filter>_<feature>`, next to the maps of the input.
+//!
+//! Filters see the intensities of the input before any binning. Filtered copies that are mapped
+//! are binned over their own range, with the bin edge convention of the input. Gabor filters and
+//! wavelet sub-bands work in voxels, derivative filters, the Laplacian of Gaussian among them, in
+//! the physical units of the voxel spacing.
+
+pub mod derivative;
+pub mod gabor;
+pub mod wavelet;
+
+use std::str::FromStr;
+
+use array_lib::ArrayDim;
+use serde::{Deserialize, Serialize};
+
+use gabor::{Gabor, Orientation};
+use wavelet::{SubBand, Wavelet, WaveletKind};
+
+/// a filter giving one derived image of the input
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageFilter {
+    Gabor(Gabor),
+    /// magnitude of the Sobel gradient
+    GradientMagnitude,
+    Laplacian,
+    /// Laplacian of a Gaussian of `sigma` in the units of the voxel spacing, usually mm
+    LaplacianOfGaussian {
+        sigma: f64,
+    },
+    /// a sub-band of the stationary wavelet transform
+    Wavelet(Wavelet),
+}
+
+impl ImageFilter {
+    /// name of the filtered image in the output names, e.g. `gabor_f0.25_x`
+    pub fn name(&self) -> String {
+        match self {
+            ImageFilter::Gabor(g) => g.name(),
+            ImageFilter::GradientMagnitude => "gradient_magnitude".to_string(),
+            ImageFilter::Laplacian => "laplacian".to_string(),
+            ImageFilter::LaplacianOfGaussian { sigma } => format!("log_sigma{sigma}mm"),
+            ImageFilter::Wavelet(w) => w.name(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ImageFilter::Gabor(g) => g.validate(),
+            ImageFilter::LaplacianOfGaussian { sigma } => {
+                if !(sigma.is_finite() && *sigma > 0.) {
+                    return Err(format!("LoG sigma {sigma} must be above 0"));
+                }
+                Ok(())
+            }
+            ImageFilter::GradientMagnitude | ImageFilter::Laplacian | ImageFilter::Wavelet(_) => {
+                Ok(())
+            }
+        }
+    }
+
+    /// the filtered image of `vol` with voxels of `spacing`, x fastest
+    pub fn apply(&self, vol: &[f64], shape: [usize; 3], spacing: [f64; 3]) -> Vec<f64> {
+        match self {
+            ImageFilter::Gabor(g) => g.apply(vol, shape),
+            ImageFilter::GradientMagnitude => derivative::gradient_magnitude(vol, shape, spacing),
+            ImageFilter::Laplacian => derivative::laplacian(vol, shape, spacing),
+            ImageFilter::LaplacianOfGaussian { sigma } => {
+                derivative::laplacian_of_gaussian(vol, shape, spacing, *sigma)
+            }
+            ImageFilter::Wavelet(w) => w.apply(vol, shape),
+        }
+    }
+}
+
+/// what becomes of the filtered images
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterUse {
+    /// written as maps of their own
+    #[default]
+    Export,
+    /// mapped with the selected features like the input
+    Map,
+}
+
+impl FilterUse {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FilterUse::Export => "export",
+            FilterUse::Map => "map",
+        }
+    }
+}
+
+impl std::fmt::Display for FilterUse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for FilterUse {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "export" => Ok(FilterUse::Export),
+            "map" => Ok(FilterUse::Map),
+            _ => Err(format!("unknown filter use {s}, expected export or map")),
+        }
+    }
+}
+
+/// the filters of a run and what becomes of their images
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FilterOpts {
+    pub filters: Vec<ImageFilter>,
+    #[serde(default, rename = "use")]
+    pub usage: FilterUse,
+}
+
+impl FilterOpts {
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    /// filtered images that are mapped with the selected features
+    pub fn n_mapped(&self) -> usize {
+        match self.usage {
+            FilterUse::Export => 0,
+            FilterUse::Map => self.filters.len(),
+        }
+    }
+
+    /// a Gabor filter for every pair of frequency and orientation
+    pub fn add_gabor_bank(&mut self, frequencies: &[f64], orientations: &[Orientation]) {
+        for &frequency in frequencies {
+            for &orientation in orientations {
+                self.filters.push(ImageFilter::Gabor(Gabor {
+                    frequency,
+                    orientation,
+                }));
+            }
+        }
+    }
+
+    /// a Laplacian of Gaussian for every sigma
+    pub fn add_log(&mut self, sigmas: &[f64]) {
+        self.filters.extend(
+            sigmas
+                .iter()
+                .map(|&sigma| ImageFilter::LaplacianOfGaussian { sigma }),
+        );
+    }
+
+    /// the sub-bands of the stationary wavelet transform with `kind`
+    pub fn add_wavelet(&mut self, kind: WaveletKind, bands: &[SubBand]) {
+        for &band in bands {
+            self.filters
+                .push(ImageFilter::Wavelet(Wavelet { kind, band }));
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.filters.iter().try_for_each(|f| f.validate())
+    }
+
+    /// every filtered image of `vol` with voxels of `spacing`
+    pub fn apply(&self, vol: &[f64], dims: &ArrayDim, spacing: [f64; 3]) -> Vec<FilteredImage> {
+        let shape = [0, 1, 2].map(|d| dims.shape_ns()[d]);
+        let n_vox: usize = shape.iter().product();
+        self.filters
+            .iter()
+            .map(|f| FilteredImage {
+                name: f.name(),
+                data: f.apply(&vol[..n_vox], shape, spacing),
+                intensities: None,
+            })
+            .collect()
+    }
+}
+
+/// a filtered copy of the input
+pub struct FilteredImage {
+    pub name: String,
+    pub data: Vec<f64>,
+    /// the filtered intensities when `data` holds the gray levels they were binned to
+    pub intensities: Option<Vec<f64>>,
+}
+
+impl FilteredImage {
+    /// replaces the data by `levels` binned from it, keeping the intensities for the families of
+    /// the intensities if `keep` says so
+    pub fn bin(&mut self, levels: Vec<f64>, keep: bool) {
+        let intensities = std::mem::replace(&mut self.data, levels);
+        self.intensities = keep.then_some(intensities);
+    }
+}
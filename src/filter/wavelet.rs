@@ -0,0 +1,274 @@
+//! 3D stationary wavelet transform at one level, the `wavelet` image type of pyradiomics. The
+//! volume is filtered with the low or high pass decomposition filter of the wavelet along each
+//! axis without downsampling, so each of the 8 sub-bands has the shape of the input. Sub-bands
+//! are named by the filter along x, y and z in that order, `llh` being low pass along x and y and
+//! high pass along z.
+//!
+//! As in `pywt.swtn`, which pyradiomics uses, the volume is extended periodically, so voxels on
+//! one face see the voxels of the opposite face.
+
+use std::str::FromStr;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// the wavelets the transform can use, those most used with pyradiomics
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WaveletKind {
+    Haar,
+    Db2,
+    /// the default wavelet of pyradiomics
+    #[default]
+    Coif1,
+}
+
+impl WaveletKind {
+    pub const ALL: [WaveletKind; 3] = [WaveletKind::Haar, WaveletKind::Db2, WaveletKind::Coif1];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WaveletKind::Haar => "haar",
+            WaveletKind::Db2 => "db2",
+            WaveletKind::Coif1 => "coif1",
+        }
+    }
+
+    /// low pass decomposition filter, as in pywt
+    fn low_pass(&self) -> &'static [f64] {
+        match self {
+            WaveletKind::Haar => &[std::f64::consts::FRAC_1_SQRT_2; 2],
+            WaveletKind::Db2 => &[
+                -0.12940952255092145,
+                0.22414386804185735,
+                0.836516303737469,
+                0.48296291314469025,
+            ],
+            WaveletKind::Coif1 => &[
+                -0.01565572813546454,
+                -0.0727326195128539,
+                0.38486484686420286,
+                0.8525720202122554,
+                0.3378976624578092,
+                -0.0727326195128539,
+            ],
+        }
+    }
+
+    /// high pass decomposition filter, the quadrature mirror of the low pass filter
+    fn high_pass(&self) -> Vec<f64> {
+        let low = self.low_pass();
+        let n = low.len();
+        (0..n)
+            .map(|k| {
+                let sign = if k % 2 == 0 { -1. } else { 1. };
+                sign * low[n - 1 - k]
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Display for WaveletKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for WaveletKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        WaveletKind::ALL
+            .into_iter()
+            .find(|w| w.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown wavelet {s}, expected haar, db2 or coif1"))
+    }
+}
+
+/// a sub-band of the transform, high pass along x, y and z where true
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubBand(pub [bool; 3]);
+
+impl SubBand {
+    /// every sub-band, in the order pyradiomics writes them
+    pub const ALL: [SubBand; 8] = [
+        SubBand([false, false, true]),
+        SubBand([false, true, false]),
+        SubBand([false, true, true]),
+        SubBand([true, false, false]),
+        SubBand([true, false, true]),
+        SubBand([true, true, false]),
+        SubBand([true, true, true]),
+        SubBand([false, false, false]),
+    ];
+}
+
+/// e.g. `llh`
+impl std::fmt::Display for SubBand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for high in self.0 {
+            f.write_str(if high { "h" } else { "l" })?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SubBand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("unknown sub-band {s}, expected three of l or h like llh");
+        let letters: Vec<char> = s.trim().to_lowercase().chars().collect();
+        if letters.len() != 3 {
+            return Err(err());
+        }
+        let mut band = [false; 3];
+        for (b, c) in band.iter_mut().zip(letters) {
+            *b = match c {
+                'l' => false,
+                'h' => true,
+                _ => return Err(err()),
+            };
+        }
+        Ok(SubBand(band))
+    }
+}
+
+/// one sub-band of the stationary wavelet transform
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Wavelet {
+    pub kind: WaveletKind,
+    pub band: SubBand,
+}
+
+impl Wavelet {
+    /// e.g. `wavelet_coif1_llh`
+    pub fn name(&self) -> String {
+        format!("wavelet_{}_{}", self.kind, self.band)
+    }
+
+    /// the sub-band of `vol`, x fastest
+    pub fn apply(&self, vol: &[f64], shape: [usize; 3]) -> Vec<f64> {
+        let low = self.kind.low_pass();
+        let high = self.kind.high_pass();
+        let mut out = vol.to_vec();
+        for axis in 0..3 {
+            let filter = if self.band.0[axis] { &high[..] } else { low };
+            out = convolve_axis(&out, shape, axis, filter);
+        }
+        out
+    }
+}
+
+/// `vol` filtered along `axis` as `pywt.swt` does at the first level, extended periodically
+fn convolve_axis(vol: &[f64], shape: [usize; 3], axis: usize, filter: &[f64]) -> Vec<f64> {
+    let len = shape[axis];
+    let stride = [1, shape[0], shape[0] * shape[1]][axis];
+    // pywt centres the filter half its length ahead of the voxel
+    let lead = filter.len() / 2;
+    (0..vol.len())
+        .into_par_iter()
+        .map(|i| {
+            let c = i / stride % len;
+            let start = i - c * stride;
+            filter
+                .iter()
+                .enumerate()
+                .map(|(j, f)| {
+                    let k = (c + lead + len * filter.len() - j) % len;
+                    f * vol[start + k * stride]
+                })
+                .sum()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wavelet(kind: WaveletKind, band: SubBand) -> Wavelet {
+        Wavelet { kind, band }
+    }
+
+    #[test]
+    fn the_filters_are_orthonormal() {
+        for kind in WaveletKind::ALL {
+            let (low, high) = (kind.low_pass(), kind.high_pass());
+            let dot = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(a, b)| a * b).sum::<f64>();
+            assert!((dot(low, low) - 1.).abs() < 1e-12, "{kind}");
+            assert!((dot(&high, &high) - 1.).abs() < 1e-12, "{kind}");
+            assert!(dot(low, &high).abs() < 1e-12, "{kind}");
+            assert!((low.iter().sum::<f64>() - 2f64.sqrt()).abs() < 1e-12, "{kind}");
+            assert!(high.iter().sum::<f64>().abs() < 1e-12, "{kind}");
+        }
+    }
+
+    #[test]
+    fn a_constant_volume_is_all_in_the_low_pass_band() {
+        let shape = [6, 5, 4];
+        let vol = vec![3.; 120];
+        for kind in WaveletKind::ALL {
+            for band in SubBand::ALL {
+                let out = wavelet(kind, band).apply(&vol, shape);
+                // the low pass filter sums to the square root of 2 along each axis
+                let expected = match band.0 {
+                    [false, false, false] => 3. * 2f64.sqrt().powi(3),
+                    _ => 0.,
+                };
+                assert!(
+                    out.iter().all(|v| (v - expected).abs() < 1e-12),
+                    "{kind} {band}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn the_response_to_a_point_is_the_filter() {
+        // starting half the filter length before the point, wrapping around the edge
+        let shape = [8, 1, 1];
+        for point in [3, 0] {
+            let mut vol = vec![0.; 8];
+            vol[point] = 1.;
+            for kind in WaveletKind::ALL {
+                for filter in [kind.low_pass().to_vec(), kind.high_pass()] {
+                    let out = convolve_axis(&vol, shape, 0, &filter);
+                    let mut expected = vec![0.; 8];
+                    for (j, f) in filter.iter().enumerate() {
+                        expected[(point + 8 + j - filter.len() / 2) % 8] = *f;
+                    }
+                    assert_eq!(out, expected, "{kind} at {point}");
+                }
+            }
+        }
+        // the haar sub-bands are the sums and differences of neighbours, as in pywt.swt
+        let mut vol = vec![0.; 8];
+        vol[3] = 1.;
+        let h = std::f64::consts::FRAC_1_SQRT_2;
+        assert_eq!(
+            convolve_axis(&vol, shape, 0, WaveletKind::Haar.low_pass()),
+            [0., 0., h, h, 0., 0., 0., 0.]
+        );
+        assert_eq!(
+            convolve_axis(&vol, shape, 0, &WaveletKind::Haar.high_pass()),
+            [0., 0., -h, h, 0., 0., 0., 0.]
+        );
+    }
+
+    #[test]
+    fn the_sub_bands_keep_the_energy_of_the_volume() {
+        let shape = [6, 5, 4];
+        let vol: Vec<f64> = (0..120).map(|i| ((i * 37 % 101) as f64 - 50.) / 7.).collect();
+        let energy = |v: &[f64]| v.iter().map(|v| v * v).sum::<f64>();
+        for kind in WaveletKind::ALL {
+            // each axis splits the energy between its two filters and doubles it
+            let total: f64 = SubBand::ALL
+                .iter()
+                .map(|&band| energy(&wavelet(kind, band).apply(&vol, shape)))
+                .sum();
+            let expected = 8. * energy(&vol);
+            assert!((total - expected).abs() < 1e-9 * expected, "{kind}");
+        }
+    }
+}
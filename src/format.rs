@@ -0,0 +1,204 @@
+//! Volume file formats. A format reads volumes through its [`VolumeReader`], and the header of a
+//! volume it read writes outputs in the same space and format through [`VolumeWriter`]. Adding a
+//! format is a reader, a writer for its header and an entry in [`FORMATS`], everything else looks
+//! formats up by file name with [`format_of`].
+
+use std::path::Path;
+
+use array_lib::io_nifti::{write_nifti_with_header, NiftiHeader};
+use array_lib::io_nrrd::{write_nrrd, Encoding, NRRD};
+use array_lib::{io_nifti, io_nrrd, ArrayDim};
+
+use crate::header::{nifti_shape, nifti_spacing, nrrd_shape, nrrd_spacing};
+use crate::io::Header;
+use crate::nifti::{is_nifti_gz, read_nifti_gz_streamed, write_nifti_like, NiftiRawHeader};
+use crate::nrrd::{needs_detached_reader, read_detached_nrrd, write_nrrd_like, NrrdHeader};
+
+/// every format volumes are read from and written to, looked up in order
+pub const FORMATS: &[&dyn VolumeReader] = &[&Nifti, &Nrrd];
+
+/// reads the volumes of a file format
+pub trait VolumeReader: Sync {
+    /// name of the format in messages, e.g. `nifti`
+    fn name(&self) -> &'static str;
+
+    /// extensions of the files of the format without the leading dot, those ending in another
+    /// one first, e.g. `nii.gz` before `nii`
+    fn extensions(&self) -> &'static [&'static str];
+
+    /// the image data, its dimensions and the header to write outputs with
+    fn read(&self, path: &Path) -> Result<(Vec<f64>, ArrayDim, Header), String>;
+
+    /// only the header, without any image data, read with a parser that knows every field so
+    /// the header can be scrubbed
+    fn read_header(&self, path: &Path) -> Result<Header, String>;
+
+    /// voxel spacing in mm along the first three axes, read from the header alone
+    fn spacing(&self, path: &Path) -> Option<[f64; 3]>;
+
+    /// size in voxels along the first three axes, read from the header alone
+    fn shape(&self, path: &Path) -> Option<[usize; 3]>;
+}
+
+/// writes volumes in the space and format of the input a header was read from
+pub trait VolumeWriter: Send + Sync {
+    /// writes `vol` to `path`, which the format adds its extension to. The writers of array_lib
+    /// panic on I/O errors rather than returning them, headers from `read_header` never use them
+    fn write(&self, path: &Path, vol: &[f32], dims: ArrayDim) -> Result<(), String>;
+
+    /// removes patient identifying strings, an error for headers of parsers that don't know
+    /// every field
+    fn scrub(&mut self) -> Result<(), String> {
+        Err("this header can't be scrubbed, read it with read_header".to_string())
+    }
+
+    /// voxel index to LPS (DICOM patient) coordinates in mm, if the header has them
+    fn voxel_to_lps(&self) -> Option<[[f64; 4]; 3]> {
+        None
+    }
+}
+
+/// the format of a file, by its extension
+pub fn format_of(path: &Path) -> Option<&'static dyn VolumeReader> {
+    let name = path.file_name()?.to_string_lossy().to_ascii_lowercase();
+    FORMATS.iter().copied().find(|format| {
+        format
+            .extensions()
+            .iter()
+            .any(|ext| name.strip_suffix(ext).is_some_and(|s| s.ends_with('.')))
+    })
+}
+
+/// every extension of [`FORMATS`], without the leading dot
+pub fn volume_extensions() -> impl Iterator<Item = &'static str> {
+    FORMATS
+        .iter()
+        .flat_map(|format| format.extensions().iter().copied())
+}
+
+/// file name without its volume extension, e.g. `a` for `a.nii.gz`
+pub fn strip_volume_extension(name: &str) -> &str {
+    // ascii lowercase keeps the byte offsets of the name
+    let lower = name.to_ascii_lowercase();
+    volume_extensions()
+        .find_map(|ext| lower.strip_suffix(ext)?.strip_suffix('.'))
+        .map(|stem| &name[..stem.len()])
+        .unwrap_or(name)
+}
+
+/// NIfTI-1 and NIfTI-2, gzip compressed or not
+pub struct Nifti;
+
+impl VolumeReader for Nifti {
+    fn name(&self) -> &'static str {
+        "nifti"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["nii.gz", "nii"]
+    }
+
+    fn read(&self, path: &Path) -> Result<(Vec<f64>, ArrayDim, Header), String> {
+        // gzip compressed files are decoded in chunks, see [crate::nifti]
+        if is_nifti_gz(path) {
+            let (data, dims, header) = read_nifti_gz_streamed(path)?;
+            Ok((data, dims, Header::new(header)))
+        } else {
+            let (data, dims, header) = io_nifti::read_nifti::<f64>(path);
+            Ok((data, dims, Header::new(header)))
+        }
+    }
+
+    fn read_header(&self, path: &Path) -> Result<Header, String> {
+        NiftiRawHeader::read(path).map(Header::new)
+    }
+
+    fn spacing(&self, path: &Path) -> Option<[f64; 3]> {
+        nifti_spacing(path)
+    }
+
+    fn shape(&self, path: &Path) -> Option<[usize; 3]> {
+        nifti_shape(path)
+    }
+}
+
+/// NRRD, attached or with a detached header
+pub struct Nrrd;
+
+impl VolumeReader for Nrrd {
+    fn name(&self) -> &'static str {
+        "nrrd"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["nrrd", "nhdr"]
+    }
+
+    fn read(&self, path: &Path) -> Result<(Vec<f64>, ArrayDim, Header), String> {
+        // detached headers with gzip or out-of-directory data, see [crate::nrrd]
+        if needs_detached_reader(path) {
+            let (data, dims, header) = read_detached_nrrd(path)?;
+            Ok((data, dims, Header::new(header)))
+        } else {
+            let (data, dims, header) = io_nrrd::read_nrrd(path);
+            Ok((data, dims, Header::new(header)))
+        }
+    }
+
+    fn read_header(&self, path: &Path) -> Result<Header, String> {
+        NrrdHeader::read(path).map(Header::new)
+    }
+
+    fn spacing(&self, path: &Path) -> Option<[f64; 3]> {
+        nrrd_spacing(path)
+    }
+
+    fn shape(&self, path: &Path) -> Option<[usize; 3]> {
+        nrrd_shape(path)
+    }
+}
+
+impl VolumeWriter for NiftiHeader {
+    fn write(&self, path: &Path, vol: &[f32], dims: ArrayDim) -> Result<(), String> {
+        write_nifti_with_header(path, vol, dims, self);
+        Ok(())
+    }
+}
+
+impl VolumeWriter for NRRD {
+    fn write(&self, path: &Path, vol: &[f32], dims: ArrayDim) -> Result<(), String> {
+        write_nrrd(path, vol, dims, Some(self), false, Encoding::raw);
+        Ok(())
+    }
+}
+
+impl VolumeWriter for NiftiRawHeader {
+    fn write(&self, path: &Path, vol: &[f32], dims: ArrayDim) -> Result<(), String> {
+        write_nifti_like(path, vol, dims, self)
+    }
+
+    fn scrub(&mut self) -> Result<(), String> {
+        self.scrub_strings();
+        Ok(())
+    }
+
+    fn voxel_to_lps(&self) -> Option<[[f64; 4]; 3]> {
+        let [x, y, z] = self.voxel_to_ras();
+        Some([x.map(|v| -v), y.map(|v| -v), z])
+    }
+}
+
+impl VolumeWriter for NrrdHeader {
+    fn write(&self, path: &Path, vol: &[f32], dims: ArrayDim) -> Result<(), String> {
+        write_nrrd_like(path, vol, dims, self)
+    }
+
+    fn scrub(&mut self) -> Result<(), String> {
+        NrrdHeader::scrub(self);
+        Ok(())
+    }
+
+    fn voxel_to_lps(&self) -> Option<[[f64; 4]; 3]> {
+        NrrdHeader::voxel_to_lps(self)
+    }
+}
@@ -0,0 +1,781 @@
+//! gRPC job api over the [`JobRunner`], for platforms that integrate services through typed
+//! service meshes. The api is described in `proto/radmap.proto`. [`RemoteJob`] is the client side
+//! used by the GUI to run jobs on a lab server.
+//!
+//! Clients only name paths within the input and output roots of the server and options from
+//! [`ALLOWED_ARGS`], so a job can't read or write anything else on the server. With a token,
+//! every call must carry it as `authorization: Bearer <token>`, and with a certificate the api is
+//! served over TLS.
+
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+use tonic::metadata::MetadataValue;
+use tonic::transport::{Certificate, ClientTlsConfig, Endpoint, Identity, ServerTlsConfig};
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::jobs::{JobRunner, JobSpec, JobState as RunnerState, JobStatus};
+use crate::nrrd::{self, NrrdHeader};
+
+include!(concat!(env!("OUT_DIR"), "/radmap.RadMap.rs"));
+
+pub use rad_map_client::RadMapClient;
+use rad_map_server::{RadMap, RadMapServer};
+
+/// how often streamed progress is refreshed
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// uploads are sent in chunks well below the default 4 MiB message limit
+const UPLOAD_CHUNK: usize = 1 << 20;
+
+/// options a client may pass on to the radmap child. They only change what is computed and
+/// written into the output directory. Options naming other files on the server (presets,
+/// caches, staging and study databases, archive keys, usage statistics, batches) are left out
+pub const ALLOWED_ARGS: &[&str] = &[
+    "n-bins",
+    "bin-edges",
+    "bin-width",
+    "bin-min",
+    "bin-max",
+    "bin-from-mask",
+    "bin-rank",
+    "discretization",
+    "kernel-radius",
+    "kernel-radii",
+    "kernel-shape",
+    "all-features",
+    "feature",
+    "family",
+    "omit",
+    "gldm-alpha",
+    "ngldm-alpha",
+    "ngldm-distance",
+    "glcm-per-direction",
+    "glcm-anisotropy",
+    "angles",
+    "distance",
+    "distance-aggregation",
+    "direction-aggregation",
+    "distance-weighting",
+    "slice-wise",
+    "2d",
+    "gabor-frequency",
+    "gabor-orientation",
+    "gradient",
+    "laplacian",
+    "log-sigma",
+    "wavelet",
+    "wavelet-band",
+    "filter-use",
+    "max-threads",
+    "rebin-integer-input",
+    "options-tag",
+    "write-retries",
+    "allow-in-place",
+    "scrub-phi",
+    "slicer-scene",
+    "export-tensor",
+    "voxel-table",
+    "export-patches",
+    "stride",
+    "correlation-report",
+    "redundant-above",
+    "strict",
+    "abort-if-degenerate",
+    "degenerate-after",
+    "no-history",
+    "decimal-separator",
+    "date-format",
+    "c-locale",
+    "csv-delimiter",
+    "csv-quoting",
+    "csv-bom",
+];
+
+/// checks that every option is a long option from [`ALLOWED_ARGS`], written as `--name` or
+/// `--name=value`. Values given as separate arguments are refused, as they can't be told apart
+/// from paths
+pub fn check_args(args: &[String]) -> Result<(), String> {
+    for arg in args {
+        let name = arg
+            .strip_prefix("--")
+            .map(|a| a.split_once('=').map_or(a, |(name, _)| name))
+            .ok_or_else(|| format!("option {arg} must be written as --name or --name=value"))?;
+        if !ALLOWED_ARGS.contains(&name) {
+            return Err(format!("option --{name} is not allowed for server jobs"));
+        }
+    }
+    Ok(())
+}
+
+/// `path` resolved against `root`, if it lies within it. `root` must be canonical. Relative
+/// paths are taken from the root, `..` is refused, and the part of the path that exists is
+/// canonicalized so a link can't lead out of the root
+pub fn confine(root: &Path, path: &Path) -> Option<PathBuf> {
+    let path = root.join(path);
+    if path.components().any(|c| c == Component::ParentDir) {
+        return None;
+    }
+    let mut existing = path.as_path();
+    let mut rest = vec![];
+    let resolved = loop {
+        match existing.canonicalize() {
+            Ok(resolved) => break resolved,
+            Err(_) => {
+                rest.push(existing.file_name()?);
+                existing = existing.parent()?;
+            }
+        }
+    };
+    let resolved = rest.into_iter().rev().fold(resolved, |p, c| p.join(c));
+    resolved.starts_with(root).then_some(resolved)
+}
+
+/// where a server keeps uploads and lets jobs read and write, and how it lets clients in
+#[derive(Debug, Clone)]
+pub struct ServeOpts {
+    /// every upload gets its own directory under this one
+    pub upload_dir: PathBuf,
+    /// inputs on the server must lie within this directory. Without it only uploads are read
+    pub input_root: Option<PathBuf>,
+    /// output directories must lie within this directory
+    pub output_root: PathBuf,
+    /// token every call must carry
+    pub token: Option<String>,
+    /// pem certificate and private key to serve over TLS with
+    pub tls: Option<(PathBuf, PathBuf)>,
+    /// largest upload accepted, in bytes
+    pub max_upload: u64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct JobRequest {
+    #[prost(string, tag = "1")]
+    pub input_vol: String,
+    #[prost(string, optional, tag = "2")]
+    pub mask: Option<String>,
+    #[prost(string, tag = "3")]
+    pub output_dir: String,
+    #[prost(string, repeated, tag = "4")]
+    pub args: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, prost::Message)]
+pub struct JobHandle {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum JobState {
+    Queued = 0,
+    Running = 1,
+    Succeeded = 2,
+    Failed = 3,
+}
+
+impl From<RunnerState> for JobState {
+    fn from(state: RunnerState) -> Self {
+        match state {
+            RunnerState::Queued => JobState::Queued,
+            RunnerState::Running => JobState::Running,
+            RunnerState::Succeeded => JobState::Succeeded,
+            RunnerState::Failed => JobState::Failed,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, prost::Message)]
+pub struct JobProgress {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(enumeration = "JobState", tag = "2")]
+    pub state: i32,
+    #[prost(uint64, tag = "3")]
+    pub voxels_done: u64,
+    #[prost(uint64, tag = "4")]
+    pub voxels_total: u64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct JobResult {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(enumeration = "JobState", tag = "2")]
+    pub state: i32,
+    #[prost(string, repeated, tag = "3")]
+    pub outputs: Vec<String>,
+    #[prost(int32, tag = "4")]
+    pub exit_code: i32,
+    #[prost(string, tag = "5")]
+    pub failure_kind: String,
+    #[prost(string, tag = "6")]
+    pub message: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FileChunk {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(bytes = "vec", tag = "2")]
+    pub data: Vec<u8>,
+    #[prost(string, tag = "3")]
+    pub dir: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct UploadedFile {
+    #[prost(string, tag = "1")]
+    pub path: String,
+}
+
+impl JobProgress {
+    fn new(id: u64, status: &JobStatus) -> Self {
+        JobProgress {
+            id,
+            state: JobState::from(status.state) as i32,
+            voxels_done: status.voxels_done,
+            voxels_total: status.voxels_total,
+        }
+    }
+}
+
+struct Service {
+    runner: JobRunner,
+    /// every upload gets its own directory under this one
+    upload_dir: PathBuf,
+    input_root: Option<PathBuf>,
+    output_root: PathBuf,
+    max_upload: u64,
+}
+
+impl Service {
+    /// an input path of a job, which must be an upload or lie within the input root
+    fn input(&self, path: &str) -> Result<PathBuf, Status> {
+        [Some(&self.upload_dir), self.input_root.as_ref()]
+            .into_iter()
+            .flatten()
+            .find_map(|root| confine(root, Path::new(path)).filter(|p| p.is_file()))
+            .ok_or_else(|| {
+                Status::permission_denied(format!(
+                    "{path} is not an uploaded file or a file within the input root"
+                ))
+            })
+    }
+
+    fn status(&self, id: u64) -> Result<JobStatus, Status> {
+        self.runner
+            .status(id)
+            .ok_or_else(|| Status::not_found(format!("no job {id}")))
+    }
+
+    /// the upload directory holding `path`, if it is an uploaded file
+    fn upload_of(&self, path: &Path) -> Option<PathBuf> {
+        path.parent()
+            .filter(|dir| dir.parent() == Some(self.upload_dir.as_path()))
+            .map(Path::to_path_buf)
+    }
+
+    /// removes the upload directories once job `id` has finished with them
+    fn remove_uploads_after(&self, id: u64, uploads: Vec<PathBuf>) {
+        if uploads.is_empty() {
+            return;
+        }
+        let runner = self.runner.clone();
+        tokio::spawn(async move {
+            while runner.status(id).is_some_and(|s| !s.state.is_finished()) {
+                tokio::time::sleep(PROGRESS_INTERVAL).await;
+            }
+            for dir in uploads {
+                if let Err(e) = tokio::fs::remove_dir_all(&dir).await {
+                    eprintln!("failed to remove upload {}: {e}", dir.display());
+                }
+            }
+        });
+    }
+}
+
+#[tonic::async_trait]
+impl RadMap for Service {
+    async fn submit_job(
+        &self,
+        request: Request<JobRequest>,
+    ) -> Result<Response<JobHandle>, Status> {
+        let request = request.into_inner();
+        if request.input_vol.is_empty() || request.output_dir.is_empty() {
+            return Err(Status::invalid_argument(
+                "input_vol and output_dir are required",
+            ));
+        }
+        check_args(&request.args).map_err(Status::invalid_argument)?;
+        let output_dir = confine(&self.output_root, Path::new(&request.output_dir))
+            .ok_or_else(|| {
+                Status::permission_denied(format!(
+                    "output directory {} is not within the output root",
+                    request.output_dir
+                ))
+            })?;
+        let spec = JobSpec {
+            input_vol: self.input(&request.input_vol)?,
+            mask: request.mask.map(|m| self.input(&m)).transpose()?,
+            output_dir,
+            args: request.args,
+        };
+        let mut uploads: Vec<PathBuf> = [Some(&spec.input_vol), spec.mask.as_ref()]
+            .into_iter()
+            .flatten()
+            .filter_map(|p| self.upload_of(p))
+            .collect();
+        uploads.dedup();
+        let id = self.runner.submit(spec);
+        self.remove_uploads_after(id, uploads);
+        Ok(Response::new(JobHandle { id }))
+    }
+
+    type StreamProgressStream = Pin<Box<dyn Stream<Item = Result<JobProgress, Status>> + Send>>;
+
+    async fn stream_progress(
+        &self,
+        request: Request<JobHandle>,
+    ) -> Result<Response<Self::StreamProgressStream>, Status> {
+        let id = request.into_inner().id;
+        self.status(id)?;
+        let runner = self.runner.clone();
+        let (tx, rx) = tokio::sync::mpsc::channel(16);
+        tokio::spawn(async move {
+            let mut last = None;
+            while let Some(status) = runner.status(id) {
+                let progress = JobProgress::new(id, &status);
+                if last != Some(progress) {
+                    if tx.send(Ok(progress)).await.is_err() {
+                        // the client went away
+                        return;
+                    }
+                    last = Some(progress);
+                }
+                if status.state.is_finished() {
+                    return;
+                }
+                tokio::time::sleep(PROGRESS_INTERVAL).await;
+            }
+        });
+        Ok(Response::new(Box::pin(
+            tokio_stream::wrappers::ReceiverStream::new(rx),
+        )))
+    }
+
+    async fn get_result(&self, request: Request<JobHandle>) -> Result<Response<JobResult>, Status> {
+        let id = request.into_inner().id;
+        let status = self.status(id)?;
+        if !status.state.is_finished() {
+            return Err(Status::failed_precondition(format!(
+                "job {id} has not finished"
+            )));
+        }
+        Ok(Response::new(JobResult {
+            id,
+            state: JobState::from(status.state) as i32,
+            outputs: status
+                .outputs
+                .iter()
+                .map(|p| p.to_string_lossy().to_string())
+                .collect(),
+            exit_code: status.exit_code.unwrap_or_default(),
+            failure_kind: status
+                .failure_kind()
+                .map(|k| k.as_str().to_string())
+                .unwrap_or_default(),
+            message: status.message.unwrap_or_default(),
+        }))
+    }
+
+    async fn upload_file(
+        &self,
+        request: Request<Streaming<FileChunk>>,
+    ) -> Result<Response<UploadedFile>, Status> {
+        let mut chunks = request.into_inner();
+        let Some(first) = chunks.message().await? else {
+            return Err(Status::invalid_argument("empty upload"));
+        };
+        // only the file name is kept, so uploads can't be written outside their directory
+        let name = Path::new(&first.name)
+            .file_name()
+            .ok_or_else(|| Status::invalid_argument(format!("invalid file name {}", first.name)))?;
+        let first_dir_is_new = first.dir.is_empty();
+        let dir = if first_dir_is_new {
+            self.upload_dir.join(uuid::Uuid::new_v4().to_string())
+        } else {
+            // same for the directory, which must be one handed out by an earlier upload
+            Path::new(&first.dir)
+                .file_name()
+                .map(|d| self.upload_dir.join(d))
+                .filter(|d| d.is_dir())
+                .ok_or_else(|| Status::not_found(format!("no upload directory {}", first.dir)))?
+        };
+        let path = dir.join(name);
+        let internal = |e: std::io::Error| {
+            Status::internal(format!("failed to store {}: {e}", path.display()))
+        };
+        tokio::fs::create_dir_all(&dir).await.map_err(internal)?;
+        let mut file = tokio::fs::File::create(&path).await.map_err(internal)?;
+        let mut size = 0;
+        let mut chunk = Some(first);
+        while let Some(c) = chunk {
+            size += c.data.len() as u64;
+            if size > self.max_upload {
+                drop(file);
+                // a new directory only ever holds this file
+                let _ = if first_dir_is_new {
+                    tokio::fs::remove_dir_all(&dir).await
+                } else {
+                    tokio::fs::remove_file(&path).await
+                };
+                return Err(Status::resource_exhausted(format!(
+                    "uploads are limited to {} bytes",
+                    self.max_upload
+                )));
+            }
+            file.write_all(&c.data).await.map_err(internal)?;
+            chunk = chunks.message().await?;
+        }
+        file.flush().await.map_err(internal)?;
+        Ok(Response::new(UploadedFile {
+            path: path.to_string_lossy().to_string(),
+        }))
+    }
+}
+
+/// refuses calls without `token`, if there is one
+#[allow(clippy::result_large_err)]
+fn authorize(token: Option<&str>, request: Request<()>) -> Result<Request<()>, Status> {
+    let Some(token) = token else {
+        return Ok(request);
+    };
+    let given = request
+        .metadata()
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    // compared in full whatever the content, so the time taken gives nothing away
+    let same = given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0;
+    if same {
+        Ok(request)
+    } else {
+        Err(Status::unauthenticated("missing or wrong token"))
+    }
+}
+
+/// serves the job api on `addr` until the process is stopped. Uploaded inputs are kept in the
+/// upload directory until the job using them finishes. Without a token, only a loopback address
+/// is served
+pub fn serve(addr: SocketAddr, runner: JobRunner, opts: ServeOpts) -> Result<(), String> {
+    if opts.token.is_none() && !addr.ip().is_loopback() {
+        return Err(format!(
+            "refusing to serve {addr} without a token, as anyone who can reach it could run jobs"
+        ));
+    }
+    let canonical = |dir: &Path| {
+        std::fs::create_dir_all(dir)
+            .and_then(|_| dir.canonicalize())
+            .map_err(|e| format!("failed to use {}: {e}", dir.display()))
+    };
+    let service = Service {
+        runner,
+        upload_dir: canonical(&opts.upload_dir)?,
+        input_root: opts.input_root.as_deref().map(canonical).transpose()?,
+        output_root: canonical(&opts.output_root)?,
+        max_upload: opts.max_upload,
+    };
+    let mut server = tonic::transport::Server::builder();
+    if let Some((cert, key)) = &opts.tls {
+        let read = |p: &PathBuf| {
+            std::fs::read(p).map_err(|e| format!("failed to read {}: {e}", p.display()))
+        };
+        server = server
+            .tls_config(ServerTlsConfig::new().identity(Identity::from_pem(read(cert)?, read(key)?)))
+            .map_err(|e| format!("invalid TLS certificate or key: {e}"))?;
+    }
+    let token = opts.token;
+    let service = RadMapServer::with_interceptor(service, move |request: Request<()>| {
+        authorize(token.as_deref(), request)
+    });
+    let runtime = tokio::runtime::Runtime::new()
+        .map_err(|e| format!("failed to start the async runtime: {e}"))?;
+    runtime
+        .block_on(server.add_service(service).serve(addr))
+        .map_err(|e| format!("gRPC server on {addr} failed: {e}"))
+}
+
+/// where an input of a remote job comes from
+#[derive(Debug, Clone)]
+pub enum RemoteInput {
+    /// a local file, uploaded before the job is submitted
+    Upload(PathBuf),
+    /// a path on the server
+    OnServer(String),
+}
+
+/// what happened to a remote job so far
+#[derive(Debug, Clone)]
+pub enum RemoteUpdate {
+    Uploading(PathBuf),
+    Submitted(u64),
+    Progress(JobProgress),
+}
+
+/// a job to run on a `radmap serve` instance
+#[derive(Debug, Clone)]
+pub struct RemoteJob {
+    /// address of the server, e.g. `http://lab-server:50051`, or `https://` for one serving TLS
+    pub server: String,
+    /// token the server asks for, if any
+    pub token: Option<String>,
+    /// pem certificate of the authority that signed the certificate of the server, when it isn't
+    /// one the system trusts
+    pub ca_cert: Option<PathBuf>,
+    pub input_vol: RemoteInput,
+    pub mask: Option<RemoteInput>,
+    /// output directory on the server
+    pub output_dir: String,
+    pub args: Vec<String>,
+}
+
+impl RemoteJob {
+    /// uploads the inputs, submits the job and follows it until it finishes, sending updates
+    /// along the way. Blocks, so run it on a background thread
+    pub fn run(self, updates: &Sender<RemoteUpdate>) -> Result<JobResult, String> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| format!("failed to start the async runtime: {e}"))?;
+        runtime.block_on(self.run_async(updates))
+    }
+
+    /// a request carrying the token, if there is one
+    fn request<T>(&self, message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        if let Some(value) = self
+            .token
+            .as_ref()
+            .and_then(|t| MetadataValue::try_from(format!("Bearer {t}")).ok())
+        {
+            request.metadata_mut().insert("authorization", value);
+        }
+        request
+    }
+
+    async fn connect(&self) -> Result<RadMapClient<tonic::transport::Channel>, String> {
+        let connect_err = |e: tonic::transport::Error| {
+            format!("failed to connect to {}: {e}", self.server)
+        };
+        let mut endpoint = Endpoint::from_shared(self.server.clone())
+            .map_err(|e| format!("invalid server address {}: {e}", self.server))?;
+        if self.server.starts_with("https://") {
+            let mut tls = ClientTlsConfig::new().with_native_roots();
+            if let Some(ca) = &self.ca_cert {
+                let pem = std::fs::read(ca)
+                    .map_err(|e| format!("failed to read {}: {e}", ca.display()))?;
+                tls = tls.ca_certificate(Certificate::from_pem(pem));
+            }
+            endpoint = endpoint.tls_config(tls).map_err(connect_err)?;
+        }
+        Ok(RadMapClient::new(
+            endpoint.connect().await.map_err(connect_err)?,
+        ))
+    }
+
+    async fn run_async(self, updates: &Sender<RemoteUpdate>) -> Result<JobResult, String> {
+        let server = self.server.clone();
+        let status_err = |e: Status| format!("{server}: {}", e.message());
+        let mut client = self.connect().await?;
+
+        let mut resolve = async |input: RemoteInput| match input {
+            RemoteInput::OnServer(path) => Ok(path),
+            RemoteInput::Upload(path) => {
+                let _ = updates.send(RemoteUpdate::Uploading(path.clone()));
+                upload_input(&mut client, &self, &path).await
+            }
+        };
+        let input_vol = resolve(self.input_vol.clone()).await?;
+        let mask = match self.mask.clone() {
+            Some(mask) => Some(resolve(mask).await?),
+            None => None,
+        };
+
+        let handle = client
+            .submit_job(self.request(JobRequest {
+                input_vol,
+                mask,
+                output_dir: self.output_dir.clone(),
+                args: self.args.clone(),
+            }))
+            .await
+            .map_err(status_err)?
+            .into_inner();
+        let _ = updates.send(RemoteUpdate::Submitted(handle.id));
+
+        let mut progress = client
+            .stream_progress(self.request(handle))
+            .await
+            .map_err(status_err)?
+            .into_inner();
+        while let Some(p) = progress.message().await.map_err(status_err)? {
+            let _ = updates.send(RemoteUpdate::Progress(p));
+        }
+        Ok(client
+            .get_result(self.request(handle))
+            .await
+            .map_err(status_err)?
+            .into_inner())
+    }
+}
+
+/// uploads a local input and returns its path on the server. A detached nrrd header is sent
+/// along with its data file, which is stored next to it
+async fn upload_input(
+    client: &mut RadMapClient<tonic::transport::Channel>,
+    job: &RemoteJob,
+    path: &Path,
+) -> Result<String, String> {
+    let is_nhdr = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("nhdr"));
+    let data_file = if is_nhdr {
+        NrrdHeader::read(path)?.data_file()
+    } else {
+        None
+    };
+    let Some(data_file) = data_file else {
+        return upload(client, job, path, String::new()).await;
+    };
+    let stored = upload(client, job, &data_file, String::new()).await?;
+    let dir = Path::new(&stored)
+        .parent()
+        .unwrap_or(Path::new(""))
+        .to_string_lossy()
+        .to_string();
+    let header = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    let name = data_file.file_name().unwrap_or_default().to_string_lossy();
+    let header = nrrd::relink_data_file(&header, &name);
+    send(client, job, std::io::Cursor::new(header.into_bytes()), path, dir).await
+}
+
+/// streams a local file to the server and returns its path there. The file goes into the
+/// upload directory `dir`, or a new one if it is empty
+async fn upload(
+    client: &mut RadMapClient<tonic::transport::Channel>,
+    job: &RemoteJob,
+    path: &Path,
+    dir: String,
+) -> Result<String, String> {
+    let file = tokio::fs::File::open(path)
+        .await
+        .map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+    send(client, job, file, path, dir).await
+}
+
+/// streams `source` to the server under the file name of `path`
+async fn send(
+    client: &mut RadMapClient<tonic::transport::Channel>,
+    job: &RemoteJob,
+    source: impl AsyncRead + Unpin + Send + 'static,
+    path: &Path,
+    dir: String,
+) -> Result<String, String> {
+    let name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let (tx, rx) = tokio::sync::mpsc::channel(4);
+    let reader = tokio::spawn(send_chunks(source, name, dir, tx));
+    let uploaded = client
+        .upload_file(job.request(ReceiverStream::new(rx)))
+        .await;
+    reader
+        .await
+        .map_err(|e| format!("upload of {} failed: {e}", path.display()))?
+        .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    uploaded
+        .map(|r| r.into_inner().path)
+        .map_err(|e| format!("upload of {} failed: {}", path.display(), e.message()))
+}
+
+/// reads `source` into chunks for an upload, until it ends or the upload is dropped
+async fn send_chunks(
+    mut source: impl AsyncRead + Unpin,
+    name: String,
+    dir: String,
+    tx: tokio::sync::mpsc::Sender<FileChunk>,
+) -> std::io::Result<()> {
+    let mut first = true;
+    loop {
+        let mut data = vec![0u8; UPLOAD_CHUNK];
+        let n = source.read(&mut data).await?;
+        // an empty file still needs the chunk carrying its name
+        if n == 0 && !first {
+            return Ok(());
+        }
+        data.truncate(n);
+        let chunk = FileChunk {
+            name: if first { name.clone() } else { String::new() },
+            data,
+            dir: if first { dir.clone() } else { String::new() },
+        };
+        first = false;
+        if tx.send(chunk).await.is_err() || n == 0 {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_allowed_long_options_pass() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert!(check_args(&args(&["--n-bins=64", "--gradient", "--bin-min=-5"])).is_ok());
+        assert!(check_args(&args(&["--study-db=/tmp/x.db"])).is_err());
+        assert!(check_args(&args(&["--archive-keyfile=/etc/passwd"])).is_err());
+        // a value on its own could be a path read as a positional argument
+        assert!(check_args(&args(&["--n-bins", "64"])).is_err());
+        assert!(check_args(&args(&["-b=64"])).is_err());
+    }
+
+    #[test]
+    fn paths_stay_within_the_root() {
+        let root = std::env::temp_dir().join("radmap_confine_root");
+        std::fs::create_dir_all(root.join("case")).unwrap();
+        let root = root.canonicalize().unwrap();
+
+        assert_eq!(confine(&root, Path::new("case")), Some(root.join("case")));
+        assert_eq!(
+            confine(&root, Path::new("case/new/dir")),
+            Some(root.join("case/new/dir"))
+        );
+        assert_eq!(
+            confine(&root, &root.join("case")),
+            Some(root.join("case"))
+        );
+        assert_eq!(confine(&root, Path::new("../elsewhere")), None);
+        assert_eq!(confine(&root, Path::new("case/../../elsewhere")), None);
+        assert_eq!(confine(&root, &std::env::temp_dir()), None);
+    }
+}
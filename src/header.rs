@@ -0,0 +1,224 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+
+use crate::format::format_of;
+use crate::nrrd::space_vectors;
+
+/// voxel spacing in mm along the first three axes, read straight from the NIfTI or NRRD header
+/// without loading any image data
+pub fn voxel_spacing(path: impl AsRef<Path>) -> Option<[f64; 3]> {
+    let path = path.as_ref();
+    format_of(path)?.spacing(path)
+}
+
+/// physical size in mm of a kernel of `kernel_radius` voxels along each axis
+pub fn kernel_extent_mm(kernel_radius: [usize; 3], spacing: [f64; 3]) -> [f64; 3] {
+    [0, 1, 2].map(|d| (2 * kernel_radius[d] + 1) as f64 * spacing[d])
+}
+
+/// e.g. `3x3x3 voxels (1.50 x 1.50 x 15.00 mm)`, for a kernel of `kernel_radius` voxels along
+/// each axis
+pub fn describe_kernel(kernel_radius: [usize; 3], spacing: Option<[f64; 3]>) -> String {
+    let [wx, wy, wz] = kernel_radius.map(|r| 2 * r + 1);
+    match spacing {
+        Some(spacing) => {
+            let [x, y, z] = kernel_extent_mm(kernel_radius, spacing);
+            format!("{wx}x{wy}x{wz} voxels ({x:.2} x {y:.2} x {z:.2} mm)")
+        }
+        None => format!("{wx}x{wy}x{wz} voxels"),
+    }
+}
+
+fn open_maybe_gz(path: &Path) -> Option<Box<dyn Read>> {
+    let f = File::open(path).ok()?;
+    if path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("gz"))
+    {
+        Some(Box::new(GzDecoder::new(f)))
+    } else {
+        Some(Box::new(f))
+    }
+}
+
+/// size in voxels along the first three axes, read from the header like [`voxel_spacing`]
+pub fn volume_shape(path: impl AsRef<Path>) -> Option<[usize; 3]> {
+    let path = path.as_ref();
+    format_of(path)?.shape(path)
+}
+
+/// the first bytes of a NIfTI file, enough for either header version
+struct NiftiHeader {
+    hdr: [u8; 540],
+    le: bool,
+    version: u8,
+}
+
+impl NiftiHeader {
+    fn read(path: &Path) -> Option<NiftiHeader> {
+        let mut hdr = [0u8; 540];
+        let mut reader = open_maybe_gz(path)?;
+        let mut n = 0;
+        while n < hdr.len() {
+            match reader.read(&mut hdr[n..]).ok()? {
+                0 => break,
+                read => n += read,
+            }
+        }
+        if n < 348 {
+            return None;
+        }
+
+        // sizeof_hdr is 348 for NIfTI-1 and 540 for NIfTI-2 and doubles as the byte order check
+        let (le, version) = [true, false]
+            .into_iter()
+            .find_map(|le| match i32_at(&hdr, 0, le) {
+                348 => Some((le, 1)),
+                540 if n == 540 => Some((le, 2)),
+                _ => None,
+            })?;
+        Some(NiftiHeader { hdr, le, version })
+    }
+}
+
+fn i32_at(hdr: &[u8], o: usize, le: bool) -> i32 {
+    let b = hdr[o..o + 4].try_into().unwrap();
+    if le {
+        i32::from_le_bytes(b)
+    } else {
+        i32::from_be_bytes(b)
+    }
+}
+
+pub(crate) fn nifti_shape(path: &Path) -> Option<[usize; 3]> {
+    let NiftiHeader { hdr, le, version } = NiftiHeader::read(path)?;
+    // dim[1..4] are shorts from byte 42 in NIfTI-1 and longs from byte 24 in NIfTI-2. dim[0] is
+    // the number of dimensions and missing ones count as 1
+    let (n_dims, dims) = if version == 1 {
+        let i16_at = |o: usize| {
+            let b = hdr[o..o + 2].try_into().unwrap();
+            if le {
+                i16::from_le_bytes(b) as i64
+            } else {
+                i16::from_be_bytes(b) as i64
+            }
+        };
+        (i16_at(40), [i16_at(42), i16_at(44), i16_at(46)])
+    } else {
+        let i64_at = |o: usize| {
+            let b = hdr[o..o + 8].try_into().unwrap();
+            if le {
+                i64::from_le_bytes(b)
+            } else {
+                i64::from_be_bytes(b)
+            }
+        };
+        (i64_at(16), [i64_at(24), i64_at(32), i64_at(40)])
+    };
+    if !(1..=7).contains(&n_dims) {
+        return None;
+    }
+    let mut shape = [1; 3];
+    for d in 0..(n_dims as usize).min(3) {
+        shape[d] = usize::try_from(dims[d]).ok().filter(|s| *s > 0)?;
+    }
+    Some(shape)
+}
+
+pub(crate) fn nifti_spacing(path: &Path) -> Option<[f64; 3]> {
+    let NiftiHeader { hdr, le, version } = NiftiHeader::read(path)?;
+
+    let (pixdim, units) = if version == 1 {
+        let f32_at = |o: usize| {
+            let b = hdr[o..o + 4].try_into().unwrap();
+            if le {
+                f32::from_le_bytes(b)
+            } else {
+                f32::from_be_bytes(b)
+            }
+        };
+        // pixdim[1..4] starts at byte 80, xyzt_units is byte 123
+        (
+            [f32_at(80), f32_at(84), f32_at(88)].map(|v| v as f64),
+            hdr[123] as i32,
+        )
+    } else {
+        let f64_at = |o: usize| {
+            let b = hdr[o..o + 8].try_into().unwrap();
+            if le {
+                f64::from_le_bytes(b)
+            } else {
+                f64::from_be_bytes(b)
+            }
+        };
+        // pixdim[1..4] starts at byte 112, xyzt_units is the int at byte 500
+        (
+            [f64_at(112), f64_at(120), f64_at(128)],
+            i32_at(&hdr, 500, le),
+        )
+    };
+
+    // spatial units live in the lowest 3 bits: 1 = m, 2 = mm, 3 = um. Unknown is taken as mm
+    let to_mm = match units & 0x07 {
+        1 => 1000.,
+        3 => 0.001,
+        _ => 1.,
+    };
+    Some(pixdim.map(|p| p.abs() * to_mm))
+}
+
+pub(crate) fn nrrd_shape(path: &Path) -> Option<[usize; 3]> {
+    let reader = BufReader::new(File::open(path).ok()?);
+    for line in reader.lines() {
+        let Ok(line) = line else { break };
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some(v) = line.strip_prefix("sizes:") {
+            let sizes = v
+                .split_whitespace()
+                .map(|s| s.parse::<usize>().ok())
+                .collect::<Option<Vec<_>>>()?;
+            let mut shape = [1; 3];
+            for (s, size) in shape.iter_mut().zip(sizes) {
+                *s = size;
+            }
+            return Some(shape);
+        }
+    }
+    None
+}
+
+pub(crate) fn nrrd_spacing(path: &Path) -> Option<[f64; 3]> {
+    let reader = BufReader::new(File::open(path).ok()?);
+    let mut spacings = None;
+    let mut directions = None;
+    for line in reader.lines() {
+        // a non-utf8 line means we have run into the attached binary data
+        let Ok(line) = line else { break };
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some(v) = line.strip_prefix("spacings:") {
+            spacings = Some(
+                v.split_whitespace()
+                    .filter_map(|s| s.parse::<f64>().ok())
+                    .collect::<Vec<_>>(),
+            );
+        } else if let Some(v) = line.strip_prefix("space directions:") {
+            // e.g. (0.5,0,0) (0,0.5,0) (0,0,2) with "none" for non-spatial axes
+            directions = space_vectors(v).map(|vectors| {
+                vectors
+                    .into_iter()
+                    .flatten()
+                    .map(|d| d.iter().map(|c| c * c).sum::<f64>().sqrt())
+                    .collect::<Vec<_>>()
+            });
+        }
+    }
+    let s = directions.or(spacings)?;
+    (s.len() >= 3).then(|| [s[0], s[1], s[2]])
+}
@@ -0,0 +1,389 @@
+//! Local history of runs, kept in a small SQLite database in the user data directory, so which
+//! settings produced a map can still be answered months later.
+//!
+//! Every run records its inputs, an options hash (see [`Provenance::options_hash`]), the options
+//! themselves, the output directory and files, timings and how it ended. Runs with scrubbed PHI
+//! leave their input and mask out. Like the usage statistics, nothing ever leaves the machine.
+//! Failing to record is never an error worth interrupting the user for, so problems are only
+//! printed.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::failure::FailureKind;
+use crate::provenance::Provenance;
+use crate::schema::{Format, UNVERSIONED};
+use crate::usage::data_dir;
+
+const HISTORY_FILE_NAME: &str = "history.sqlite";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    app TEXT NOT NULL,
+    radmap_version TEXT NOT NULL,
+    started_unix_s REAL NOT NULL,
+    finished_unix_s REAL,
+    status TEXT NOT NULL,
+    input TEXT,
+    mask TEXT,
+    input_hash TEXT,
+    mask_hash TEXT,
+    output_dir TEXT NOT NULL,
+    options_hash TEXT NOT NULL,
+    options TEXT NOT NULL,
+    failure_kind TEXT,
+    message TEXT
+);
+CREATE TABLE IF NOT EXISTS outputs (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    path TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS runs_options_hash ON runs(options_hash);
+CREATE INDEX IF NOT EXISTS outputs_run_id ON outputs(run_id);
+";
+
+/// where the history is stored. `RADMAP_HISTORY_DB` overrides the default location in the user
+/// data directory
+pub fn history_file() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("RADMAP_HISTORY_DB") {
+        return Some(PathBuf::from(path));
+    }
+    data_dir().map(|d| d.join(HISTORY_FILE_NAME))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunStatus {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl RunStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RunStatus::Running => "running",
+            RunStatus::Succeeded => "succeeded",
+            RunStatus::Failed => "failed",
+        }
+    }
+}
+
+impl std::fmt::Display for RunStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for RunStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "running" => Ok(RunStatus::Running),
+            "succeeded" => Ok(RunStatus::Succeeded),
+            "failed" => Ok(RunStatus::Failed),
+            _ => Err(format!(
+                "unknown run status {s}, expected running, succeeded or failed"
+            )),
+        }
+    }
+}
+
+/// a run as stored in the history
+#[derive(Debug, Clone)]
+pub struct RunRecord {
+    pub id: i64,
+    pub app: String,
+    pub radmap_version: String,
+    pub started_unix_s: f64,
+    pub finished_unix_s: Option<f64>,
+    pub status: RunStatus,
+    /// None if the run scrubbed PHI
+    pub input: Option<String>,
+    pub mask: Option<String>,
+    /// see [`crate::provenance::file_hash`]
+    pub input_hash: Option<String>,
+    pub mask_hash: Option<String>,
+    pub output_dir: String,
+    pub options_hash: String,
+    /// the options as a json object, see [`Provenance::options_json`]
+    pub options: String,
+    pub failure_kind: Option<String>,
+    pub message: Option<String>,
+    pub outputs: Vec<PathBuf>,
+}
+
+impl RunRecord {
+    pub fn duration_s(&self) -> Option<f64> {
+        self.finished_unix_s.map(|f| f - self.started_unix_s)
+    }
+}
+
+/// filters of a history search. Text filters match anywhere in the value, the options hash
+/// matches by prefix
+#[derive(Debug, Clone, Default)]
+pub struct HistoryQuery {
+    pub input: Option<String>,
+    /// matches the output directory or the path of any output
+    pub output: Option<String>,
+    pub options_hash: Option<String>,
+    pub status: Option<RunStatus>,
+    /// most recent runs to return, all of them if None
+    pub limit: Option<usize>,
+}
+
+pub struct History {
+    conn: Connection,
+}
+
+impl History {
+    /// opens the history database, creating it if needed
+    pub fn open(path: &Path) -> Result<Self, String> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+        }
+        let err = |e: rusqlite::Error| format!("failed to open {}: {e}", path.display());
+        let conn = Connection::open(path).map_err(err)?;
+        // runs finishing in parallel, e.g. batch cases, wait for each other
+        conn.busy_timeout(std::time::Duration::from_secs(10))
+            .map_err(err)?;
+        // a new database and one written before versions were recorded both read 0. Version 1
+        // has the same tables, so either is brought up to date by creating what is missing
+        let version: u32 = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(err)?;
+        Format::History.check(version.max(UNVERSIONED), path.display())?;
+        conn.execute_batch(SCHEMA).map_err(err)?;
+        conn.pragma_update(None, "user_version", Format::History.current())
+            .map_err(err)?;
+        Ok(History { conn })
+    }
+
+    /// opens the history in its default location
+    pub fn open_default() -> Result<Self, String> {
+        let path = history_file().ok_or("unable to determine the user data directory")?;
+        Self::open(&path)
+    }
+
+    /// records a run that just started and returns its id
+    pub fn start(&self, provenance: &Provenance, output_dir: &Path) -> Result<i64, String> {
+        let (input, mask) = if provenance.phi_scrubbed {
+            (None, None)
+        } else {
+            (
+                Some(provenance.input.to_string_lossy().to_string()),
+                provenance
+                    .mask
+                    .as_ref()
+                    .map(|m| m.to_string_lossy().to_string()),
+            )
+        };
+        self.conn
+            .execute(
+                "INSERT INTO runs (app, radmap_version, started_unix_s, status, input, mask, input_hash, mask_hash, output_dir, options_hash, options)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                params![
+                    provenance.app,
+                    env!("CARGO_PKG_VERSION"),
+                    now(),
+                    RunStatus::Running.as_str(),
+                    input,
+                    mask,
+                    provenance.input_hash,
+                    provenance.mask_hash,
+                    output_dir.to_string_lossy(),
+                    provenance.options_hash(),
+                    provenance.options_json(),
+                ],
+            )
+            .map_err(|e| format!("failed to record run: {e}"))?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// marks a run as succeeded with the files it wrote. The options are recorded again, as they
+    /// can change once the input is loaded, e.g. for inputs that are already quantized
+    pub fn succeed(
+        &self,
+        id: i64,
+        provenance: &Provenance,
+        outputs: &[PathBuf],
+    ) -> Result<(), String> {
+        let err = |e: rusqlite::Error| format!("failed to record run {id}: {e}");
+        self.conn
+            .execute(
+                "UPDATE runs SET finished_unix_s = ?2, status = ?3, options_hash = ?4, options = ?5 WHERE id = ?1",
+                params![
+                    id,
+                    now(),
+                    RunStatus::Succeeded.as_str(),
+                    provenance.options_hash(),
+                    provenance.options_json(),
+                ],
+            )
+            .map_err(err)?;
+        for output in outputs {
+            self.conn
+                .execute(
+                    "INSERT INTO outputs (run_id, path) VALUES (?1, ?2)",
+                    params![id, output.to_string_lossy()],
+                )
+                .map_err(err)?;
+        }
+        Ok(())
+    }
+
+    pub fn fail(&self, id: i64, kind: FailureKind, message: &str) -> Result<(), String> {
+        self.conn
+            .execute(
+                "UPDATE runs SET finished_unix_s = ?2, status = ?3, failure_kind = ?4, message = ?5 WHERE id = ?1",
+                params![id, now(), RunStatus::Failed.as_str(), kind.as_str(), message],
+            )
+            .map_err(|e| format!("failed to record run {id}: {e}"))?;
+        Ok(())
+    }
+
+    /// runs matching the query, most recent first
+    pub fn query(&self, query: &HistoryQuery) -> Result<Vec<RunRecord>, String> {
+        let err = |e: rusqlite::Error| format!("failed to search the run history: {e}");
+        let contains = |s: &Option<String>| s.as_ref().map(|s| format!("%{}%", escape_like(s)));
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT * FROM runs
+                 WHERE (?1 IS NULL OR input LIKE ?1 ESCAPE '\\')
+                   AND (?2 IS NULL OR output_dir LIKE ?2 ESCAPE '\\'
+                        OR id IN (SELECT run_id FROM outputs WHERE path LIKE ?2 ESCAPE '\\'))
+                   AND (?3 IS NULL OR options_hash LIKE ?3 ESCAPE '\\')
+                   AND (?4 IS NULL OR status = ?4)
+                 ORDER BY started_unix_s DESC
+                 LIMIT ?5",
+            )
+            .map_err(err)?;
+        let mut runs = stmt
+            .query_map(
+                params![
+                    contains(&query.input),
+                    contains(&query.output),
+                    query
+                        .options_hash
+                        .as_ref()
+                        .map(|h| format!("{}%", escape_like(h))),
+                    query.status.map(|s| s.as_str()),
+                    query.limit.map(|l| l as i64).unwrap_or(-1),
+                ],
+                read_run,
+            )
+            .map_err(err)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(err)?;
+        for run in runs.iter_mut() {
+            run.outputs = self.outputs(run.id)?;
+        }
+        Ok(runs)
+    }
+
+    pub fn get(&self, id: i64) -> Result<Option<RunRecord>, String> {
+        let err = |e: rusqlite::Error| format!("failed to read run {id}: {e}");
+        let run = self
+            .conn
+            .query_row("SELECT * FROM runs WHERE id = ?1", [id], read_run)
+            .optional()
+            .map_err(err)?;
+        run.map(|mut run| {
+            run.outputs = self.outputs(id)?;
+            Ok(run)
+        })
+        .transpose()
+    }
+
+    fn outputs(&self, id: i64) -> Result<Vec<PathBuf>, String> {
+        let err = |e: rusqlite::Error| format!("failed to read the outputs of run {id}: {e}");
+        let mut stmt = self
+            .conn
+            .prepare("SELECT path FROM outputs WHERE run_id = ?1 ORDER BY path")
+            .map_err(err)?;
+        stmt.query_map([id], |row| row.get::<_, String>(0).map(PathBuf::from))
+            .map_err(err)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(err)
+    }
+}
+
+fn read_run(row: &rusqlite::Row) -> rusqlite::Result<RunRecord> {
+    let status: String = row.get("status")?;
+    Ok(RunRecord {
+        id: row.get("id")?,
+        app: row.get("app")?,
+        radmap_version: row.get("radmap_version")?,
+        started_unix_s: row.get("started_unix_s")?,
+        finished_unix_s: row.get("finished_unix_s")?,
+        status: RunStatus::from_str(&status).unwrap_or(RunStatus::Failed),
+        input: row.get("input")?,
+        mask: row.get("mask")?,
+        input_hash: row.get("input_hash")?,
+        mask_hash: row.get("mask_hash")?,
+        output_dir: row.get("output_dir")?,
+        options_hash: row.get("options_hash")?,
+        options: row.get("options")?,
+        failure_kind: row.get("failure_kind")?,
+        message: row.get("message")?,
+        outputs: vec![],
+    })
+}
+
+/// escapes the wildcards of a LIKE pattern
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.)
+}
+
+/// run of this process, marked as failed if the process exits through [`crate::failure`]
+static CURRENT_RUN: Mutex<Option<i64>> = Mutex::new(None);
+
+/// records a run that just started in the default history. Returns its id, or None if it
+/// couldn't be recorded
+pub fn start_run(provenance: &Provenance, output_dir: &Path) -> Option<i64> {
+    History::open_default()
+        .and_then(|h| h.start(provenance, output_dir))
+        .map_err(|e| eprintln!("failed to update the run history: {e}"))
+        .ok()
+}
+
+pub fn finish_run(id: i64, provenance: &Provenance, outputs: &[PathBuf]) {
+    if let Err(e) = History::open_default().and_then(|h| h.succeed(id, provenance, outputs)) {
+        eprintln!("failed to update the run history: {e}");
+    }
+}
+
+pub fn fail_run(id: i64, kind: FailureKind, message: &str) {
+    if let Err(e) = History::open_default().and_then(|h| h.fail(id, kind, message)) {
+        eprintln!("failed to update the run history: {e}");
+    }
+}
+
+/// makes `id` the run of this process, or clears it once the run is finished
+pub fn set_current_run(id: Option<i64>) {
+    *CURRENT_RUN.lock().unwrap_or_else(|e| e.into_inner()) = id;
+}
+
+/// marks the run of this process as failed, if there is one
+pub fn fail_current_run(kind: FailureKind, message: &str) {
+    let id = CURRENT_RUN.lock().unwrap_or_else(|e| e.into_inner()).take();
+    if let Some(id) = id {
+        fail_run(id, kind, message);
+    }
+}
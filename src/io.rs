@@ -0,0 +1,146 @@
+use std::ffi::{OsStr, OsString};
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+
+use array_lib::ArrayDim;
+
+use crate::format::{format_of, volume_extensions, VolumeWriter, FORMATS};
+
+/// header of an input volume, kept around to write outputs in the same space and format
+pub struct Header(Box<dyn VolumeWriter>);
+
+impl Header {
+    pub fn new(writer: impl VolumeWriter + 'static) -> Self {
+        Header(Box::new(writer))
+    }
+}
+
+impl Deref for Header {
+    type Target = dyn VolumeWriter;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl DerefMut for Header {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0.as_mut()
+    }
+}
+
+/// whether the file is of one of the [`FORMATS`], by its extension
+pub fn is_volume(path: &Path) -> bool {
+    format_of(path).is_some()
+}
+
+/// e.g. `nifti or nrrd`, for messages about files that are neither
+pub fn format_names() -> String {
+    let names: Vec<&str> = FORMATS.iter().map(|f| f.name()).collect();
+    names.join(" or ")
+}
+
+/// true if the file sits directly inside the directory, in which case outputs written there sit
+/// next to it
+pub fn is_in_directory(file: &Path, dir: &Path) -> bool {
+    let parent = file
+        .canonicalize()
+        .ok()
+        .and_then(|f| f.parent().map(Path::to_path_buf));
+    match (parent, dir.canonicalize()) {
+        (Some(parent), Ok(dir)) => parent == dir,
+        _ => false,
+    }
+}
+
+/// stem of the input file that output names are built from, e.g. `case` for `case.nii.gz`. Kept
+/// as an `OsStr` so names that aren't valid UTF-8 carry over to the outputs unchanged
+pub fn input_stem(input: &Path) -> OsString {
+    let Some(name) = input.file_name() else {
+        return OsString::from("volume");
+    };
+    // a volume extension like `nii.gz` goes as a unit, one file stem per part of it
+    let lower = name.as_encoded_bytes().to_ascii_lowercase();
+    let parts = volume_extensions()
+        .find(|ext| {
+            lower
+                .strip_suffix(ext.as_bytes())
+                .is_some_and(|rest| rest.ends_with(b"."))
+        })
+        .map_or(1, |ext| ext.split('.').count());
+    let mut stem = name;
+    for _ in 0..parts {
+        stem = Path::new(stem).file_stem().unwrap_or(stem);
+    }
+    stem.to_os_string()
+}
+
+/// `<output_dir>/<stem>_<suffix>` without going through `str`
+pub fn output_path(output_dir: &Path, stem: &OsStr, suffix: &str) -> PathBuf {
+    let mut name = stem.to_os_string();
+    name.push("_");
+    name.push(suffix);
+    output_dir.join(name)
+}
+
+/// the file written for an output path without extension, see [`output_path`]
+pub fn find_output_volume(base: &Path) -> Option<PathBuf> {
+    volume_extensions()
+        .map(|ext| {
+            let mut name = base.as_os_str().to_owned();
+            name.push(format!(".{ext}"));
+            PathBuf::from(name)
+        })
+        .find(|p| p.is_file())
+}
+
+/// the output name suffix for a feature alias
+pub fn feature_suffix(alias: &str) -> String {
+    alias.to_lowercase().replace(" ", "_")
+}
+
+/// reads a volume, panicking if it can't be read. See [`try_read_volume`]
+pub fn read_volume(path: impl AsRef<Path>) -> (Vec<f64>, ArrayDim, Header) {
+    try_read_volume(path).unwrap_or_else(|e| panic!("{e}"))
+}
+
+/// reads a volume, failing if it isn't in a known format or can't be read
+pub fn try_read_volume(path: impl AsRef<Path>) -> Result<(Vec<f64>, ArrayDim, Header), String> {
+    let path = path.as_ref();
+    let Some(format) = format_of(path) else {
+        return Err(format!("{} is not a {}", path.display(), format_names()));
+    };
+    format.read(path)
+}
+
+/// reads only the header of a volume, without any image data, with radmap's own parsers so every
+/// field written is known
+pub fn read_header(path: impl AsRef<Path>) -> Result<Header, String> {
+    let path = path.as_ref();
+    let Some(format) = format_of(path) else {
+        return Err(format!("{} is not a {}", path.display(), format_names()));
+    };
+    format.read_header(path)
+}
+
+/// header of the input with patient identifying strings removed, for outputs that are shared
+/// outside the institution
+pub fn scrubbed_header(path: impl AsRef<Path>) -> Result<Header, String> {
+    let mut header = read_header(path)?;
+    header.scrub()?;
+    Ok(header)
+}
+
+/// voxel index to LPS (DICOM patient) coordinates in mm, for headers read by radmap's own parsers
+pub fn voxel_to_lps(header: &Header) -> Option<[[f64; 4]; 3]> {
+    header.voxel_to_lps()
+}
+
+pub fn write_volume(
+    path: impl AsRef<Path>,
+    vol: &[f32],
+    vol_dims: ArrayDim,
+    header: &Header,
+) -> Result<(), String> {
+    header.write(path.as_ref(), vol, vol_dims)
+}
@@ -0,0 +1,295 @@
+//! Job runner shared by the service front ends. Every job is a run of the radmap command line tool
+//! in a child process, so a crash or an out of memory kill only takes down its own job. Progress
+//! is read from the json lines the child prints with `--json-progress`.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::failure::{FailureKind, FAILURE_FILE};
+use crate::io::input_stem;
+use crate::schema::{json_version, Format};
+
+/// one run of the command line tool. `args` are any further options, e.g. `--n-bins=64`
+#[derive(Debug, Clone)]
+pub struct JobSpec {
+    pub input_vol: PathBuf,
+    pub mask: Option<PathBuf>,
+    pub output_dir: PathBuf,
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl JobState {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobState::Succeeded | JobState::Failed)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JobStatus {
+    pub state: JobState,
+    pub voxels_done: u64,
+    pub voxels_total: u64,
+    /// exit code of the child, see [`crate::failure`]
+    pub exit_code: Option<i32>,
+    /// what went wrong, for failed jobs
+    pub message: Option<String>,
+    /// files written for the input, for succeeded jobs
+    pub outputs: Vec<PathBuf>,
+    /// when the job finished, after which it is kept for the retention period of the runner
+    finished_at: Option<Instant>,
+}
+
+impl JobStatus {
+    fn queued() -> Self {
+        JobStatus {
+            state: JobState::Queued,
+            voxels_done: 0,
+            voxels_total: 0,
+            exit_code: None,
+            message: None,
+            outputs: vec![],
+            finished_at: None,
+        }
+    }
+
+    /// finished longer than `retention` ago
+    fn expired(&self, retention: Duration) -> bool {
+        self.finished_at.is_some_and(|t| t.elapsed() >= retention)
+    }
+
+    pub fn failure_kind(&self) -> Option<FailureKind> {
+        self.exit_code.and_then(FailureKind::from_exit_code)
+    }
+}
+
+/// how long finished jobs are kept for their status to be read, unless the runner says otherwise
+pub const DEFAULT_RETENTION: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Default)]
+struct JobTable {
+    jobs: HashMap<u64, JobStatus>,
+    next_id: u64,
+}
+
+type Jobs = Arc<Mutex<JobTable>>;
+
+/// runs submitted jobs on a fixed number of worker threads, in submission order. Finished jobs
+/// are dropped once they are older than the retention period, so a long-lived runner doesn't
+/// grow without bounds
+#[derive(Clone)]
+pub struct JobRunner {
+    jobs: Jobs,
+    queue: Arc<Mutex<Sender<(u64, JobSpec)>>>,
+    retention: Duration,
+}
+
+impl JobRunner {
+    /// `exe` is the radmap command line tool. At most `max_jobs` jobs run at the same time, and
+    /// finished jobs are kept for [`DEFAULT_RETENTION`]
+    pub fn new(exe: PathBuf, max_jobs: usize) -> Self {
+        let jobs: Jobs = Arc::new(Mutex::new(JobTable::default()));
+        let (tx, rx) = channel();
+        let rx = Arc::new(Mutex::new(rx));
+        for _ in 0..max_jobs.max(1) {
+            let jobs = jobs.clone();
+            let rx = rx.clone();
+            let exe = exe.clone();
+            thread::spawn(move || worker(&exe, &rx, &jobs));
+        }
+        JobRunner {
+            jobs,
+            queue: Arc::new(Mutex::new(tx)),
+            retention: DEFAULT_RETENTION,
+        }
+    }
+
+    /// keeps finished jobs for `retention` instead of [`DEFAULT_RETENTION`]
+    pub fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// queues a job and returns its id. Jobs finished longer than the retention period ago are
+    /// dropped on the way
+    pub fn submit(&self, spec: JobSpec) -> u64 {
+        let id = {
+            let mut table = self.jobs.lock().unwrap();
+            table.jobs.retain(|_, s| !s.expired(self.retention));
+            let id = table.next_id;
+            table.next_id += 1;
+            table.jobs.insert(id, JobStatus::queued());
+            id
+        };
+        self.queue
+            .lock()
+            .unwrap()
+            .send((id, spec))
+            .expect("job workers have stopped");
+        id
+    }
+
+    /// None for unknown jobs and for finished jobs past the retention period
+    pub fn status(&self, id: u64) -> Option<JobStatus> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .jobs
+            .get(&id)
+            .filter(|s| !s.expired(self.retention))
+            .cloned()
+    }
+}
+
+fn worker(exe: &PathBuf, rx: &Mutex<Receiver<(u64, JobSpec)>>, jobs: &Jobs) {
+    loop {
+        let Ok((id, spec)) = rx.lock().unwrap().recv() else {
+            return;
+        };
+        let update = |f: &dyn Fn(&mut JobStatus)| {
+            if let Some(status) = jobs.lock().unwrap().jobs.get_mut(&id) {
+                f(status)
+            }
+        };
+        update(&|s| s.state = JobState::Running);
+        match run(exe, &spec, &|done, total| {
+            update(&|s| {
+                s.voxels_done = done;
+                s.voxels_total = total;
+            })
+        }) {
+            Ok(outputs) => update(&|s| {
+                s.state = JobState::Succeeded;
+                s.exit_code = Some(0);
+                s.voxels_done = s.voxels_total;
+                s.outputs = outputs.clone();
+                s.finished_at = Some(Instant::now());
+            }),
+            Err((code, message)) => update(&|s| {
+                s.state = JobState::Failed;
+                s.exit_code = Some(code);
+                s.message = Some(message.clone());
+                s.finished_at = Some(Instant::now());
+            }),
+        }
+    }
+}
+
+fn run(
+    exe: &PathBuf,
+    spec: &JobSpec,
+    on_progress: &dyn Fn(u64, u64),
+) -> Result<Vec<PathBuf>, (i32, String)> {
+    let mut cmd = Command::new(exe);
+    cmd.arg(&spec.input_vol).arg(&spec.output_dir);
+    if let Some(mask) = &spec.mask {
+        cmd.arg("--mask").arg(mask);
+    }
+    let mut child = cmd
+        .args(&spec.args)
+        .arg("--json-progress")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            (
+                FailureKind::Internal.exit_code(),
+                format!("failed to launch {}: {e}", exe.display()),
+            )
+        })?;
+
+    // stderr carries the reason of a failure
+    let stderr = child.stderr.take().unwrap();
+    let stderr = thread::spawn(move || {
+        BufReader::new(stderr)
+            .lines()
+            .map_while(Result::ok)
+            .filter(|l| !l.trim().is_empty())
+            .collect::<Vec<String>>()
+    });
+    for line in BufReader::new(child.stdout.take().unwrap())
+        .lines()
+        .map_while(Result::ok)
+    {
+        if let Some((done, total)) = parse_progress_line(&line) {
+            on_progress(done, total);
+        }
+    }
+    let status = child.wait().map_err(|e| {
+        (
+            FailureKind::Internal.exit_code(),
+            format!("failed to wait for the job: {e}"),
+        )
+    })?;
+    let stderr = stderr.join().unwrap_or_default();
+
+    if !status.success() {
+        let code = status.code().unwrap_or(FailureKind::Internal.exit_code());
+        // usage errors are reported on a line of their own, anything later gets a failure summary
+        let message = stderr
+            .iter()
+            .find(|l| l.starts_with("error:"))
+            .cloned()
+            .or_else(|| failure_message(&spec.output_dir))
+            .or(stderr.last().cloned())
+            .unwrap_or(format!("radmap exited with {status}"));
+        return Err((code, message));
+    }
+
+    let stem = input_stem(&spec.input_vol).to_string_lossy().to_string();
+    let mut outputs: Vec<PathBuf> = std::fs::read_dir(&spec.output_dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| {
+                    p.is_file()
+                        && p.file_name()
+                            .is_some_and(|n| n.to_string_lossy().starts_with(&format!("{stem}_")))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    outputs.sort();
+    Ok(outputs)
+}
+
+/// message of the `failure.json` the child left in `output_dir`
+fn failure_message(output_dir: &Path) -> Option<String> {
+    let summary = std::fs::read_to_string(output_dir.join(FAILURE_FILE)).ok()?;
+    let summary: serde_json::Value = serde_json::from_str(&summary).ok()?;
+    let version = json_version(&summary).ok()?;
+    if let Err(e) = Format::FailureSummary.check(version, FAILURE_FILE) {
+        return Some(e);
+    }
+    summary.get("message")?.as_str().map(String::from)
+}
+
+/// progress line printed by the command line tool with `--json-progress`
+pub fn progress_line(voxels_done: u64, voxels_total: u64) -> String {
+    format!(
+        "{{\"event\": \"progress\", \"voxels_done\": {voxels_done}, \"voxels_total\": {voxels_total}}}"
+    )
+}
+
+/// the inverse of [`progress_line`]. Any other line gives None
+pub fn parse_progress_line(line: &str) -> Option<(u64, u64)> {
+    let rest = line
+        .trim()
+        .strip_prefix("{\"event\": \"progress\", \"voxels_done\": ")?;
+    let (done, rest) = rest.split_once(", \"voxels_total\": ")?;
+    let total = rest.strip_suffix('}')?;
+    Some((done.parse().ok()?, total.parse().ok()?))
+}
@@ -0,0 +1,51 @@
+pub mod archive;
+pub mod autodrive;
+pub mod batch;
+pub mod cache;
+pub mod checks;
+pub mod config;
+pub mod container;
+pub mod correlation;
+pub mod crash;
+pub mod dicom;
+pub mod diff;
+pub mod discretize;
+pub mod discrimination;
+pub mod failure;
+pub mod filter;
+pub mod format;
+pub mod grpc;
+pub mod header;
+pub mod history;
+pub mod io;
+pub mod jobs;
+pub mod locale;
+pub mod mapper;
+pub mod memory;
+pub mod nifti;
+pub mod nrrd;
+pub mod options;
+pub mod patches;
+pub mod pipeline;
+pub mod preset;
+pub mod preview;
+pub mod progress;
+pub mod protocol;
+pub mod provenance;
+pub mod queue;
+pub mod scene;
+pub mod schema;
+pub mod shape;
+pub mod spill;
+pub mod staging;
+pub mod stats;
+pub mod study;
+pub mod table;
+pub mod taskbar;
+pub mod tensor;
+pub mod texture;
+pub mod tray;
+pub mod usage;
+pub mod wizard;
+pub mod workflow;
+pub mod xnat;
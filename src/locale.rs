@@ -0,0 +1,604 @@
+/// decimal and digit grouping separators of the user's locale
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberFormat {
+    pub decimal: char,
+    pub grouping: char,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        NumberFormat {
+            decimal: '.',
+            grouping: ',',
+        }
+    }
+}
+
+/// languages that write decimals with a comma, paired with how they group digits
+const COMMA_DECIMAL_LANGUAGES: &[(&str, char)] = &[
+    ("de", '.'),
+    ("es", '.'),
+    ("it", '.'),
+    ("nl", '.'),
+    ("pt", '.'),
+    ("da", '.'),
+    ("id", '.'),
+    ("tr", '.'),
+    ("el", '.'),
+    ("ro", '.'),
+    ("hr", '.'),
+    ("sl", '.'),
+    ("sr", '.'),
+    ("ca", '.'),
+    ("fr", ' '),
+    ("ru", ' '),
+    ("uk", ' '),
+    ("pl", ' '),
+    ("cs", ' '),
+    ("sk", ' '),
+    ("sv", ' '),
+    ("nb", ' '),
+    ("nn", ' '),
+    ("no", ' '),
+    ("fi", ' '),
+    ("hu", ' '),
+    ("bg", ' '),
+    ("lt", ' '),
+    ("lv", ' '),
+    ("et", ' '),
+];
+
+impl NumberFormat {
+    /// number format from the `LC_ALL`, `LC_NUMERIC` or `LANG` environment variables, e.g.
+    /// `de_DE.UTF-8`. Falls back to `.` decimals when no locale is set
+    pub fn from_env() -> Self {
+        let locale = ["LC_ALL", "LC_NUMERIC", "LANG"]
+            .iter()
+            .filter_map(|v| std::env::var(v).ok())
+            .find(|v| !v.is_empty());
+        locale.map(|l| Self::from_locale(&l)).unwrap_or_default()
+    }
+
+    pub fn from_locale(locale: &str) -> Self {
+        let tag = locale.split(['.', '@']).next().unwrap_or_default();
+        // swiss german and italian use a period for decimals
+        if tag == "de_CH" || tag == "it_CH" {
+            return NumberFormat {
+                decimal: '.',
+                grouping: '\'',
+            };
+        }
+        let language = tag.split(['_', '-']).next().unwrap_or_default();
+        COMMA_DECIMAL_LANGUAGES
+            .iter()
+            .find(|(l, _)| *l == language)
+            .map(|&(_, grouping)| NumberFormat {
+                decimal: ',',
+                grouping,
+            })
+            .unwrap_or_default()
+    }
+
+    /// parses a number written with either `.` or `,` as the decimal separator, with optional
+    /// digit grouping in groups of three. A single separator followed by exactly three digits
+    /// (`1,000`) is ambiguous and is resolved with the locale
+    pub fn parse(&self, s: &str) -> Option<f64> {
+        let s: String = s
+            .trim()
+            .chars()
+            .filter(|c| !(c.is_whitespace() || *c == '\'' || *c == '\u{202f}'))
+            .collect();
+        if s.is_empty() {
+            return None;
+        }
+        let n_commas = s.matches(',').count();
+        let n_periods = s.matches('.').count();
+        let decimal = match (n_commas, n_periods) {
+            (0, 0) => None,
+            (_, 0) | (0, _) => {
+                let sep = if n_commas > 0 { ',' } else { '.' };
+                let (count, last) = (n_commas.max(n_periods), s.rfind(sep).unwrap());
+                let digits_after = s.len() - last - 1;
+                if count > 1 {
+                    // repeated separators can only be grouping
+                    None
+                } else if digits_after == 3 {
+                    (sep == self.decimal).then_some(sep)
+                } else {
+                    Some(sep)
+                }
+            }
+            // both present: whichever comes last is the decimal separator
+            _ => Some(if s.rfind(',') > s.rfind('.') {
+                ','
+            } else {
+                '.'
+            }),
+        };
+        // grouping separators split the whole part into groups of three digits
+        let whole = match decimal {
+            Some(d) => &s[..s.rfind(d).unwrap()],
+            None => &s[..],
+        };
+        let groups: Vec<&str> = whole.trim_start_matches(['-', '+']).split([',', '.']).collect();
+        if groups.len() > 1
+            && (!(1..=3).contains(&groups[0].len()) || groups[1..].iter().any(|g| g.len() != 3))
+        {
+            return None;
+        }
+        let normalized: String = s
+            .chars()
+            .filter_map(|c| match c {
+                ',' | '.' if Some(c) == decimal => Some('.'),
+                ',' | '.' => None,
+                c => Some(c),
+            })
+            .collect();
+        normalized.parse().ok()
+    }
+
+    /// like [NumberFormat::parse] but only accepts whole numbers, so `3,0` is 3 and `2,5` is
+    /// rejected
+    pub fn parse_integer(&self, s: &str) -> Option<i64> {
+        let value = self.parse(s)?;
+        (value.fract() == 0. && value.abs() < i64::MAX as f64).then_some(value as i64)
+    }
+
+    /// formats a count with digit grouping, e.g. `12,345,678` or `12.345.678`
+    pub fn format_count(&self, n: u64) -> String {
+        let digits = n.to_string();
+        let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+        for (i, c) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                out.push(self.grouping);
+            }
+            out.push(c);
+        }
+        out
+    }
+}
+
+/// [NumberFormat::format_count] with the locale from the environment
+pub fn format_count(n: usize) -> String {
+    NumberFormat::from_env().format_count(n as u64)
+}
+
+/// how dates are written in reports, always in UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DateFormat {
+    /// ISO 8601, `2024-03-05T14:07:09Z`
+    #[default]
+    Iso,
+    /// `05/03/2024 14:07:09`
+    DayMonthYear,
+    /// `03/05/2024 14:07:09`
+    MonthDayYear,
+    /// seconds since 1970
+    Unix,
+}
+
+impl DateFormat {
+    pub const ALL: [DateFormat; 4] = [
+        DateFormat::Iso,
+        DateFormat::DayMonthYear,
+        DateFormat::MonthDayYear,
+        DateFormat::Unix,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DateFormat::Iso => "iso",
+            DateFormat::DayMonthYear => "dmy",
+            DateFormat::MonthDayYear => "mdy",
+            DateFormat::Unix => "unix",
+        }
+    }
+
+    pub fn format(&self, unix_s: u64) -> String {
+        let [year, month, day, hour, minute, second] = utc_date_time(unix_s);
+        match self {
+            DateFormat::Iso => {
+                format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}Z")
+            }
+            DateFormat::DayMonthYear => {
+                format!("{day:02}/{month:02}/{year:04} {hour:02}:{minute:02}:{second:02}")
+            }
+            DateFormat::MonthDayYear => {
+                format!("{month:02}/{day:02}/{year:04} {hour:02}:{minute:02}:{second:02}")
+            }
+            DateFormat::Unix => unix_s.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for DateFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for DateFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DateFormat::ALL
+            .into_iter()
+            .find(|d| d.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown date format {s}, expected iso, dmy, mdy or unix"))
+    }
+}
+
+/// separator between the fields of a csv row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delimiter {
+    Comma,
+    Semicolon,
+    Tab,
+}
+
+impl Delimiter {
+    pub const ALL: [Delimiter; 3] = [Delimiter::Comma, Delimiter::Semicolon, Delimiter::Tab];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Delimiter::Comma => "comma",
+            Delimiter::Semicolon => "semicolon",
+            Delimiter::Tab => "tab",
+        }
+    }
+
+    pub fn as_char(&self) -> char {
+        match self {
+            Delimiter::Comma => ',',
+            Delimiter::Semicolon => ';',
+            Delimiter::Tab => '\t',
+        }
+    }
+}
+
+impl std::fmt::Display for Delimiter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// a name like `semicolon`, or the character itself
+impl std::str::FromStr for Delimiter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Delimiter::ALL
+            .into_iter()
+            .find(|d| d.as_str().eq_ignore_ascii_case(s) || s == d.as_char().to_string())
+            .ok_or_else(|| format!("unknown delimiter {s}, expected comma, semicolon or tab"))
+    }
+}
+
+/// when fields of a csv row are put in double quotes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Quoting {
+    /// never, a delimiter in a field is replaced so it can't split the row
+    #[default]
+    Never,
+    /// fields holding a delimiter, a quote or a line break, as RFC 4180 has it
+    Minimal,
+    /// every field
+    All,
+}
+
+impl Quoting {
+    pub const ALL: [Quoting; 3] = [Quoting::Never, Quoting::Minimal, Quoting::All];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Quoting::Never => "never",
+            Quoting::Minimal => "minimal",
+            Quoting::All => "all",
+        }
+    }
+}
+
+impl std::fmt::Display for Quoting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Quoting {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Quoting::ALL
+            .into_iter()
+            .find(|q| q.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown quoting {s}, expected never, minimal or all"))
+    }
+}
+
+/// how numbers and dates are written in csv reports, and the dialect of the csv. Unless a
+/// delimiter is chosen, fields are separated by semicolons where decimals are written with a
+/// comma, as spreadsheets in those locales expect. Json reports always write `.` decimals, as
+/// json requires
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReportFormat {
+    pub decimal: char,
+    pub date: DateFormat,
+    /// None for that of the decimal separator
+    pub delimiter: Option<Delimiter>,
+    pub quoting: Quoting,
+    /// start every csv with the UTF-8 byte order mark, which Excel needs to read anything but
+    /// ascii as UTF-8
+    pub bom: bool,
+}
+
+impl Default for ReportFormat {
+    fn default() -> Self {
+        ReportFormat::C
+    }
+}
+
+impl ReportFormat {
+    /// `.` decimals and ISO dates whatever the locale, for reports shared between sites
+    pub const C: ReportFormat = ReportFormat {
+        decimal: '.',
+        date: DateFormat::Iso,
+        delimiter: None,
+        quoting: Quoting::Never,
+        bom: false,
+    };
+
+    /// decimals of the locale from the environment, see [NumberFormat::from_env], and ISO dates
+    pub fn from_env() -> Self {
+        ReportFormat {
+            decimal: NumberFormat::from_env().decimal,
+            ..ReportFormat::C
+        }
+    }
+
+    /// a delimiter that is also the decimal separator must be quoted around
+    pub fn validate(&self) -> Result<(), String> {
+        if self.quoting == Quoting::Never && self.delimiter() == self.decimal {
+            return Err(format!(
+                "the csv delimiter {} is also the decimal separator, pick another or quote the fields",
+                self.delimiter().escape_default()
+            ));
+        }
+        Ok(())
+    }
+
+    /// separator between the fields of a csv row
+    pub fn delimiter(&self) -> char {
+        match self.delimiter {
+            Some(d) => d.as_char(),
+            None if self.decimal == ',' => ';',
+            None => ',',
+        }
+    }
+
+    /// `x` with `precision` decimals
+    pub fn number(&self, x: f64, precision: usize) -> String {
+        self.localize(format!("{x:.precision$}"))
+    }
+
+    /// `x` with as many decimals as it takes to read it back exactly
+    pub fn float(&self, x: f64) -> String {
+        self.localize(x.to_string())
+    }
+
+    pub fn date(&self, unix_s: u64) -> String {
+        self.date.format(unix_s)
+    }
+
+    /// the fields joined by the delimiter, quoted as chosen. Without quotes, a delimiter in a
+    /// field is replaced so it can't split the row
+    pub fn row<S: AsRef<str>>(&self, fields: &[S]) -> String {
+        let delimiter = self.delimiter();
+        let quote = |f: &str| format!("\"{}\"", f.replace('"', "\"\""));
+        let fields: Vec<String> = fields
+            .iter()
+            .map(|f| {
+                let f = f.as_ref();
+                match self.quoting {
+                    Quoting::Never => {
+                        let replacement = match delimiter {
+                            ',' => ";",
+                            ';' => ",",
+                            _ => " ",
+                        };
+                        f.replace(delimiter, replacement)
+                    }
+                    Quoting::Minimal if f.contains([delimiter, '"', '\n', '\r']) => quote(f),
+                    Quoting::Minimal => f.to_string(),
+                    Quoting::All => quote(f),
+                }
+            })
+            .collect();
+        fields.join(&delimiter.to_string())
+    }
+
+    /// the first row of a csv, after the byte order mark when there is one
+    pub fn header<S: AsRef<str>>(&self, fields: &[S]) -> String {
+        let row = self.row(fields);
+        if self.bom {
+            format!("\u{feff}{row}")
+        } else {
+            row
+        }
+    }
+
+    fn localize(&self, number: String) -> String {
+        match self.decimal {
+            '.' => number,
+            decimal => number.replace('.', &decimal.to_string()),
+        }
+    }
+}
+
+/// a decimal separator given on the command line, `.` or `,`
+pub fn parse_decimal_separator(s: &str) -> Result<char, String> {
+    match s {
+        "." => Ok('.'),
+        "," => Ok(','),
+        _ => Err(format!("the decimal separator must be . or , got {s}")),
+    }
+}
+
+/// year, month, day, hours, minutes and seconds of a unix time, in UTC
+pub fn utc_date_time(unix_s: u64) -> [u64; 6] {
+    let (days, rem) = ((unix_s / 86400) as i64, unix_s % 86400);
+    // days since the epoch to a civil date, after Howard Hinnant's algorithm
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    [
+        year as u64,
+        month as u64,
+        day as u64,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60,
+    ]
+}
+
+/// a unix time as `YYYY-MM-DD HH:MM` in UTC
+pub fn format_utc(unix_s: u64) -> String {
+    let [year, month, day, hour, minute, _] = utc_date_time(unix_s);
+    format!("{year:04}-{month:02}-{day:02} {hour:02}:{minute:02} UTC")
+}
+
+/// a measured duration as a clock, e.g. `0:42`, `12:05` or `2:05:09`
+pub fn format_elapsed(d: std::time::Duration) -> String {
+    let s = d.as_secs();
+    match s {
+        0..3600 => format!("{}:{:02}", s / 60, s % 60),
+        _ => format!("{}:{:02}:{:02}", s / 3600, s / 60 % 60, s % 60),
+    }
+}
+
+/// a rough duration for estimates, e.g. `40 s`, `12 min` or `2 h 05 min`
+pub fn format_duration(d: std::time::Duration) -> String {
+    let s = d.as_secs_f64().round() as u64;
+    // rounded to minutes before picking the unit, so 59 min 45 s is `1 h 00 min`
+    let min = (s + 30) / 60;
+    match (s, min) {
+        (0..60, _) => format!("{s} s"),
+        (_, 0..60) => format!("{min} min"),
+        _ => format!("{} h {:02} min", min / 60, min % 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const EN: NumberFormat = NumberFormat {
+        decimal: '.',
+        grouping: ',',
+    };
+    const DE: NumberFormat = NumberFormat {
+        decimal: ',',
+        grouping: '.',
+    };
+    const FR: NumberFormat = NumberFormat {
+        decimal: ',',
+        grouping: ' ',
+    };
+    const CH: NumberFormat = NumberFormat {
+        decimal: '.',
+        grouping: '\'',
+    };
+
+    #[test]
+    fn locales_set_the_separators() {
+        for (locale, format) in [
+            ("en_US.UTF-8", EN),
+            ("C", EN),
+            ("", EN),
+            ("de_DE.UTF-8", DE),
+            ("pt-BR", DE),
+            ("fr_FR@euro", FR),
+            ("de_CH.UTF-8", CH),
+        ] {
+            assert_eq!(NumberFormat::from_locale(locale), format, "{locale}");
+        }
+    }
+
+    #[test]
+    fn numbers_parse_in_every_locale() {
+        // input, then the value read with en, de, fr and swiss formats
+        let table: &[(&str, [Option<f64>; 4])] = &[
+            ("42", [Some(42.); 4]),
+            ("2.5", [Some(2.5); 4]),
+            ("2,5", [Some(2.5); 4]),
+            ("-0,25", [Some(-0.25); 4]),
+            ("+7", [Some(7.); 4]),
+            (".5", [Some(0.5); 4]),
+            ("1e3", [Some(1000.); 4]),
+            // one separator before three digits follows the locale
+            ("1,000", [Some(1000.), Some(1.), Some(1.), Some(1000.)]),
+            ("1.000", [Some(1.), Some(1000.), Some(1000.), Some(1.)]),
+            ("-1.500", [Some(-1.5), Some(-1500.), Some(-1500.), Some(-1.5)]),
+            // both separators, or one repeated, group the digits
+            ("1,234,567.89", [Some(1234567.89); 4]),
+            ("1.234.567,89", [Some(1234567.89); 4]),
+            ("-1.234,5", [Some(-1234.5); 4]),
+            ("1,234,567", [Some(1234567.); 4]),
+            ("1 234,5", [Some(1234.5); 4]),
+            ("1\u{202f}234,5", [Some(1234.5); 4]),
+            ("1'234.5", [Some(1234.5); 4]),
+            ("  3.5 ", [Some(3.5); 4]),
+            // rejected
+            ("", [None; 4]),
+            (" ", [None; 4]),
+            ("abc", [None; 4]),
+            ("1,5x", [None; 4]),
+            ("--1", [None; 4]),
+            ("1,2,3", [None; 4]),
+            ("1..2", [None; 4]),
+            ("12,34.5", [None; 4]),
+            ("1.2.3,4", [None; 4]),
+            ("1234,567.8", [None; 4]),
+            (",", [None; 4]),
+        ];
+        for (input, expected) in table {
+            for (format, expected) in [EN, DE, FR, CH].iter().zip(expected) {
+                assert_eq!(format.parse(input), *expected, "{input:?} with {format:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn integers_must_be_whole() {
+        assert_eq!(EN.parse_integer("3,0"), Some(3));
+        assert_eq!(EN.parse_integer("2,5"), None);
+        assert_eq!(EN.parse_integer("-12,000"), Some(-12000));
+        assert_eq!(DE.parse_integer("12.000"), Some(12000));
+        assert_eq!(DE.parse_integer("12,000"), Some(12));
+        assert_eq!(DE.parse_integer("1e30"), None);
+    }
+
+    #[test]
+    fn durations_round_to_the_unit_shown() {
+        for (s, expected) in [
+            (0., "0 s"),
+            (59.4, "59 s"),
+            (59.6, "1 min"),
+            (89., "1 min"),
+            (90., "2 min"),
+            (3569., "59 min"),
+            (3570., "1 h 00 min"),
+            (3599., "1 h 00 min"),
+            (3600., "1 h 00 min"),
+            (7529., "2 h 05 min"),
+        ] {
+            assert_eq!(format_duration(Duration::from_secs_f64(s)), expected, "{s} s");
+        }
+    }
+}
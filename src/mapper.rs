@@ -0,0 +1,307 @@
+//! The feature mappers behind a [`ProgressSink`], so embedders get progress and cancellation
+//! without polling the voxel counter the mappers take. GLCM features are averaged from their
+//! maps along each direction, see [`crate::texture::cooccurrence`], which work out the
+//! quantities the features share once per matrix. Only runs selecting a GLCM feature radmap
+//! has no formula for go to the glcm crate, which takes every feature on its own. The other
+//! families go to [`crate::texture`]. Filtered copies of the input from [`crate::filter`] are
+//! mapped in the same run, as are GLCM features along each direction and their anisotropy when
+//! asked. Maps along directions left out aren't written.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use array_lib::ArrayDim;
+use glcm::core::GLCMFeature;
+use glcm::run_glcm_map;
+use glcm::ui::MapOpts;
+use strum::IntoEnumIterator;
+
+use crate::filter::{FilterUse, FilteredImage};
+use crate::io::feature_suffix;
+use crate::progress::{ProgressSink, RunStage};
+use crate::texture::cooccurrence::{is_computed, Anisotropy};
+use crate::texture::{map_glcm_directions, map_texture, tag_alias, Family, Feature, TextureOpts};
+
+/// how often the voxel count is passed on to the sink
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// the maps of a run
+pub struct FeatureMaps {
+    /// every GLCM feature one volume after the other, in the order of the feature enum. None if
+    /// no GLCM feature was selected
+    glcm: Option<Vec<f32>>,
+    /// the 13 maps of every GLCM feature along a single direction, if they were asked for. Maps
+    /// along directions left out of the run are empty
+    glcm_directions: Vec<(Feature, Vec<Vec<f32>>)>,
+    /// how every GLCM feature changes with direction, if it was asked for
+    glcm_anisotropy: Vec<(Feature, Anisotropy, Vec<f32>)>,
+    texture: Vec<(Feature, Vec<f32>)>,
+    /// added to the names of the GLCM and Haralick maps, see [`TextureOpts::cooccurrence_tag`]
+    cooccurrence_tag: Option<String>,
+    dims: ArrayDim,
+    /// filter responses written as maps of their own, by filter name
+    responses: Vec<(String, Vec<f32>)>,
+    /// the maps of every filtered copy of the input, by filter name
+    filtered: Vec<(String, FeatureMaps)>,
+}
+
+impl FeatureMaps {
+    /// dimensions of a single map
+    pub fn dims(&self) -> ArrayDim {
+        self.dims
+    }
+
+    /// the map of a feature, if it was selected
+    pub fn map(&self, feature: Feature) -> Option<&[f32]> {
+        let stride = self.dims.numel();
+        match feature {
+            Feature::Glcm(f) => {
+                let i = f as usize;
+                self.glcm.as_ref().map(|m| &m[i * stride..(i + 1) * stride])
+            }
+            _ => self
+                .texture
+                .iter()
+                .find(|(f, _)| *f == feature)
+                .map(|(_, m)| m.as_slice()),
+        }
+    }
+
+    /// every map of the run with the suffix of its output name: the selected features of the
+    /// input, the filter responses, then the features of each filtered copy as
+    /// `<filter>_<feature>`. GLCM maps along single directions follow the map of their feature
+    /// as `<feature>_dir<n>`, then its anisotropy as `<feature>_anisotropy_<variance|range>`.
+    /// GLCM and Haralick features paired at other distances than 1 are named after them
+    pub fn outputs<'a>(&'a self, features: &[(Feature, String)]) -> Vec<(String, &'a [f32])> {
+        let of_features = |maps: &'a FeatureMaps, prefix: &str| {
+            features
+                .iter()
+                .flat_map(|(f, alias)| {
+                    let map = maps.map(*f).expect("every selected feature is mapped");
+                    let alias = tag_alias(*f, alias, maps.cooccurrence_tag.as_deref());
+                    let suffix = format!("{prefix}{}", feature_suffix(&alias));
+                    let by_direction = maps
+                        .glcm_directions
+                        .iter()
+                        .filter(|(g, _)| g == f)
+                        .flat_map(|(_, m)| m.iter().enumerate())
+                        .filter(|(_, m)| !m.is_empty())
+                        .map(|(d, m)| (format!("{suffix}_dir{:02}", d + 1), m.as_slice()));
+                    let anisotropy = maps
+                        .glcm_anisotropy
+                        .iter()
+                        .filter(|(g, ..)| g == f)
+                        .map(|(_, a, m)| (format!("{suffix}_anisotropy_{a}"), m.as_slice()));
+                    let derived: Vec<_> = by_direction.chain(anisotropy).collect();
+                    std::iter::once((suffix, map)).chain(derived)
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut outputs = of_features(self, "");
+        outputs.extend(
+            self.responses
+                .iter()
+                .map(|(name, map)| (name.clone(), map.as_slice())),
+        );
+        for (name, maps) in &self.filtered {
+            outputs.extend(of_features(maps, &format!("{name}_")));
+        }
+        outputs
+    }
+}
+
+/// number of passes over the volume the selected features take, one for GLCM features, one
+/// more for their maps along each direction unless they are averaged from them, and one per
+/// other family, for the input and each
+/// filtered copy that is mapped. The voxel count of a run goes up to this many times the size
+/// of the volume
+pub fn n_passes(opts: &MapOpts, texture: &TextureOpts) -> usize {
+    (glcm_passes(opts, texture) + texture.families().len()) * (1 + texture.filters.n_mapped())
+}
+
+fn glcm_passes(opts: &MapOpts, texture: &TextureOpts) -> usize {
+    match (opts.features.is_empty(), texture.maps_glcm_directions()) {
+        (true, _) => 0,
+        (false, false) => 1,
+        (false, true) if averages_glcm(opts, texture) => 1,
+        (false, true) => 2,
+    }
+}
+
+/// whether the GLCM features are averaged from their maps along each direction rather than
+/// mapped by the glcm crate: whenever radmap computes every selected GLCM feature itself, and
+/// always for the options only radmap maps, see [`TextureOpts::averages_glcm_directions`]
+fn averages_glcm(opts: &MapOpts, texture: &TextureOpts) -> bool {
+    !opts.features.is_empty()
+        && (texture.averages_glcm_directions() || opts.features.keys().all(|f| is_computed(*f)))
+}
+
+/// rough wall time to map `n_voxels` voxels with the selected features, from the cost model of
+/// [`Family::seconds_per_voxel`] spread over the threads the run may use
+pub fn estimated_runtime(opts: &MapOpts, texture: &TextureOpts, n_voxels: usize) -> Duration {
+    let glcm = std::iter::repeat_n(Family::Glcm, glcm_passes(opts, texture));
+    let per_voxel: f64 = glcm
+        .chain(texture.families())
+        .map(|f| f.seconds_per_voxel(opts.n_bins, opts.kernel_radius))
+        .sum::<f64>()
+        * (1 + texture.filters.n_mapped()) as f64;
+    let threads = rayon::current_num_threads()
+        .min(opts.max_threads.unwrap_or(usize::MAX))
+        .max(1);
+    Duration::from_secs_f64(per_voxel * n_voxels as f64 / threads as f64)
+}
+
+/// maps every selected feature on the calling thread, counting voxels into `done`. `intensities`
+/// are those `vol` was binned from when it holds gray levels binned ahead of the mappers, for the
+/// families of the intensities, see [`map_texture`]
+pub fn compute(
+    opts: MapOpts,
+    texture: &TextureOpts,
+    vol: Vec<f64>,
+    intensities: Option<Vec<f64>>,
+    mask: Option<Vec<f64>>,
+    dims: ArrayDim,
+    done: Arc<AtomicUsize>,
+) -> FeatureMaps {
+    let map_dims = ArrayDim::from_shape(&dims.shape_ns()[0..3]);
+    let averaged = averages_glcm(&opts, texture);
+    let maps_directions = texture.maps_glcm_directions() && !opts.features.is_empty();
+    let mut glcm_directions = if averaged || maps_directions {
+        map_glcm_directions(&opts, texture, &vol, mask.as_deref(), &dims, &done)
+    } else {
+        vec![]
+    };
+    let glcm_anisotropy = match texture.glcm_anisotropy {
+        Some(a) => glcm_directions
+            .iter()
+            .map(|(f, by_direction)| (*f, a, a.map(by_direction)))
+            .collect(),
+        None => vec![],
+    };
+    let glcm = if averaged {
+        let stride = map_dims.numel();
+        let mut glcm = vec![0f32; GLCMFeature::iter().count() * stride];
+        for (f, by_direction) in &glcm_directions {
+            if let Feature::Glcm(g) = f {
+                let i = *g as usize;
+                glcm[i * stride..(i + 1) * stride]
+                    .copy_from_slice(&texture.glcm_direction_aggregation.map(by_direction));
+            }
+        }
+        Some(glcm)
+    } else {
+        None
+    };
+    if !texture.glcm_per_direction {
+        glcm_directions.clear();
+    }
+    for (_, by_direction) in &mut glcm_directions {
+        for (d, map) in by_direction.iter_mut().enumerate() {
+            if !texture.angles().contains(d) {
+                *map = vec![];
+            }
+        }
+    }
+    let texture_maps = if texture.features.is_empty() {
+        vec![]
+    } else {
+        let intensities = intensities.as_deref().unwrap_or(&vol);
+        map_texture(
+            &opts,
+            texture,
+            &vol,
+            intensities,
+            mask.as_deref(),
+            &dims,
+            &done,
+        )
+    };
+    let glcm = match glcm {
+        Some(glcm) => Some(glcm),
+        None => (!opts.features.is_empty()).then(|| run_glcm_map(opts, vol, mask, dims, done).0),
+    };
+    FeatureMaps {
+        glcm,
+        glcm_directions,
+        glcm_anisotropy,
+        cooccurrence_tag: texture.cooccurrence_tag(),
+        texture: texture_maps,
+        dims: map_dims,
+        responses: vec![],
+        filtered: vec![],
+    }
+}
+
+/// maps the features of `vol`, reporting to `sink`. `vol` holds the intensities, or the gray
+/// levels they were binned to ahead of the mappers along with the `intensities` themselves if
+/// [`TextureOpts::maps_intensities`]. `images` are the filtered copies of the input made with
+/// `texture.filters`, binned like `vol` if they are to be mapped. None if the
+/// sink cancelled the run. The mappers can't be interrupted, so a cancelled run keeps its worker
+/// threads busy until the maps are done and then drops them
+#[allow(clippy::too_many_arguments)]
+pub fn map_features(
+    opts: MapOpts,
+    texture: &TextureOpts,
+    vol: Vec<f64>,
+    intensities: Option<Vec<f64>>,
+    images: Vec<FilteredImage>,
+    mask: Option<Vec<f64>>,
+    dims: ArrayDim,
+    sink: &dyn ProgressSink,
+) -> Option<FeatureMaps> {
+    let total = dims.numel() * n_passes(&opts, texture);
+    let done = Arc::new(AtomicUsize::new(0));
+    sink.on_stage(RunStage::Computing);
+    sink.on_voxels(0, total);
+    let t_done = done.clone();
+    let texture = texture.clone();
+    let h = std::thread::spawn(move || {
+        let mut responses = vec![];
+        let mut filtered = vec![];
+        for image in images {
+            match texture.filters.usage {
+                // zero outside the mask like the feature maps
+                FilterUse::Export => {
+                    let response = image
+                        .data
+                        .iter()
+                        .enumerate()
+                        .map(|(i, v)| match &mask {
+                            Some(m) if m[i] == 0. => 0.,
+                            _ => *v as f32,
+                        })
+                        .collect();
+                    responses.push((image.name, response));
+                }
+                FilterUse::Map => {
+                    let maps = compute(
+                        opts.clone(),
+                        &texture,
+                        image.data,
+                        image.intensities,
+                        mask.clone(),
+                        dims,
+                        t_done.clone(),
+                    );
+                    filtered.push((image.name, maps));
+                }
+            }
+        }
+        FeatureMaps {
+            responses,
+            filtered,
+            ..compute(opts, &texture, vol, intensities, mask, dims, t_done)
+        }
+    });
+    while !h.is_finished() {
+        if sink.is_cancelled() {
+            return None;
+        }
+        sink.on_voxels(done.load(Ordering::Relaxed).min(total), total);
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    let result = h.join().unwrap_or_else(|e| std::panic::resume_unwind(e));
+    sink.on_voxels(total, total);
+    Some(result)
+}
@@ -0,0 +1,95 @@
+/// resident set size of a process in bytes. Only available on linux, where it is read from
+/// `/proc/<pid>/status`
+pub fn process_rss(pid: u32) -> Option<u64> {
+    read_status_field(&format!("/proc/{pid}/status"), "VmRSS:")
+}
+
+/// resident set size of this process in bytes
+pub fn current_rss() -> Option<u64> {
+    process_rss(std::process::id())
+}
+
+/// high water mark of the resident set size of this process in bytes
+pub fn peak_rss() -> Option<u64> {
+    read_status_field("/proc/self/status", "VmHWM:")
+}
+
+/// current and peak resident memory of this process sampled over the course of a run
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemoryTracker {
+    pub current: Option<u64>,
+    pub peak: Option<u64>,
+}
+
+impl MemoryTracker {
+    pub fn sample(&mut self) {
+        if let Some(rss) = current_rss() {
+            self.current = Some(rss);
+            self.peak = Some(self.peak.map_or(rss, |p| p.max(rss)));
+        }
+    }
+
+    /// e.g. `1.20 GiB (peak 3.41 GiB)`, or `None` if memory can't be measured on this platform
+    pub fn summary(&self) -> Option<String> {
+        Some(format!(
+            "{} (peak {})",
+            format_bytes(self.current?),
+            format_bytes(self.peak?)
+        ))
+    }
+}
+
+fn read_status_field(status_file: &str, field: &str) -> Option<u64> {
+    let status = std::fs::read_to_string(status_file).ok()?;
+    let line = status.lines().find(|l| l.starts_with(field))?;
+    // values are reported as e.g. "VmRSS:     1234 kB"
+    let kb: u64 = line[field.len()..]
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+/// parses sizes like `512M`, `16G` or `16GiB` (binary units). A bare number is taken as bytes
+pub fn parse_bytes(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let value: f64 = num
+        .parse()
+        .map_err(|_| format!("expected a number in memory size {s}"))?;
+    let scale = match unit
+        .trim()
+        .to_ascii_uppercase()
+        .trim_end_matches("IB")
+        .trim_end_matches('B')
+    {
+        "" => 1u64,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(format!("unknown memory unit '{unit}' in {s}")),
+    };
+    Ok((value * scale as f64) as u64)
+}
+
+/// formats a byte count with a binary unit suffix, e.g. `1.50 GiB`
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024. && unit < UNITS.len() - 1 {
+        value /= 1024.;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.2} {}", UNITS[unit])
+    }
+}
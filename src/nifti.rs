@@ -0,0 +1,542 @@
+//! Streaming reader for gzip compressed NIfTI volumes (`.nii.gz`).
+//!
+//! The compressed stream is decoded in small chunks that are converted to f64 as they arrive, so
+//! peak memory while loading is the output buffer plus one chunk rather than the full
+//! decompressed file on top of the converted volume.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use array_lib::ArrayDim;
+use flate2::read::GzDecoder;
+
+/// bytes of compressed data decoded and converted at a time
+const CHUNK_BYTES: usize = 1 << 20;
+
+/// the raw NIfTI-1 or NIfTI-2 header, kept as bytes so outputs can be written with the same
+/// orientation and metadata
+#[derive(Debug, Clone)]
+pub struct NiftiRawHeader {
+    bytes: Vec<u8>,
+    little_endian: bool,
+    version: u8,
+}
+
+impl NiftiRawHeader {
+    /// reads just the header of a `.nii` or `.nii.gz` file
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let f = File::open(path).map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+        let mut f = BufReader::new(f);
+        let result = if is_nifti_gz(path) {
+            Self::read_from(&mut GzDecoder::new(f))
+        } else {
+            Self::read_from(&mut f)
+        };
+        result.map_err(|e| format!("failed to read nifti header {}: {e}", path.display()))
+    }
+
+    /// reads a NIfTI-1 or NIfTI-2 header from the start of a stream
+    fn read_from(stream: &mut impl Read) -> std::io::Result<Self> {
+        // read enough for a NIfTI-1 header, then the rest if it turns out to be NIfTI-2
+        let mut hdr = vec![0u8; 540];
+        stream.read_exact(&mut hdr[..348])?;
+        let is_v2 = hdr[0..4] == 540i32.to_le_bytes() || hdr[0..4] == 540i32.to_be_bytes();
+        if is_v2 {
+            stream.read_exact(&mut hdr[348..])?;
+        }
+        Self::parse(hdr).map_err(std::io::Error::other)
+    }
+
+    fn parse(bytes: Vec<u8>) -> Result<Self, String> {
+        let sizeof_hdr = |le: bool| {
+            let b = bytes[0..4].try_into().unwrap();
+            if le {
+                i32::from_le_bytes(b)
+            } else {
+                i32::from_be_bytes(b)
+            }
+        };
+        let (little_endian, version) = [true, false]
+            .into_iter()
+            .find_map(|le| match sizeof_hdr(le) {
+                348 => Some((le, 1)),
+                540 => Some((le, 2)),
+                _ => None,
+            })
+            .ok_or("not a nifti header")?;
+        let len = if version == 1 { 348 } else { 540 };
+        let mut bytes = bytes;
+        bytes.truncate(len);
+        Ok(NiftiRawHeader {
+            bytes,
+            little_endian,
+            version,
+        })
+    }
+
+    fn get<const N: usize>(&self, offset: usize) -> [u8; N] {
+        let mut b: [u8; N] = self.bytes[offset..offset + N].try_into().unwrap();
+        if !self.little_endian {
+            b.reverse();
+        }
+        b
+    }
+
+    fn set<const N: usize>(&mut self, offset: usize, mut le_bytes: [u8; N]) {
+        if !self.little_endian {
+            le_bytes.reverse();
+        }
+        self.bytes[offset..offset + N].copy_from_slice(&le_bytes);
+    }
+
+    pub fn shape(&self) -> Vec<usize> {
+        let dim: Vec<i64> = if self.version == 1 {
+            (0..8)
+                .map(|i| i16::from_le_bytes(self.get(40 + 2 * i)) as i64)
+                .collect()
+        } else {
+            (0..8)
+                .map(|i| i64::from_le_bytes(self.get(16 + 8 * i)))
+                .collect()
+        };
+        let n = dim[0].clamp(1, 7) as usize;
+        dim[1..=n].iter().map(|d| (*d).max(1) as usize).collect()
+    }
+
+    fn datatype(&self) -> i16 {
+        let offset = if self.version == 1 { 70 } else { 12 };
+        i16::from_le_bytes(self.get(offset))
+    }
+
+    fn vox_offset(&self) -> usize {
+        if self.version == 1 {
+            f32::from_le_bytes(self.get(108)) as usize
+        } else {
+            i64::from_le_bytes(self.get(168)) as usize
+        }
+    }
+
+    /// slope and intercept to apply to stored values, if any
+    fn scaling(&self) -> Option<(f64, f64)> {
+        let (slope, inter) = if self.version == 1 {
+            (
+                f32::from_le_bytes(self.get(112)) as f64,
+                f32::from_le_bytes(self.get(116)) as f64,
+            )
+        } else {
+            (
+                f64::from_le_bytes(self.get(176)),
+                f64::from_le_bytes(self.get(184)),
+            )
+        };
+        (slope != 0. && slope.is_finite() && (slope, inter) != (1., 0.)).then_some((slope, inter))
+    }
+
+    /// blanks the free text fields (description, aux file, intent name and the unused analyze
+    /// names), which scanners and converters often fill with patient or study details
+    pub fn scrub_strings(&mut self) {
+        let fields: &[(usize, usize)] = if self.version == 1 {
+            // data_type, db_name, descrip, aux_file, intent_name
+            &[(4, 10), (14, 18), (148, 80), (228, 24), (328, 16)]
+        } else {
+            // descrip, aux_file, intent_name
+            &[(240, 80), (320, 24), (508, 16)]
+        };
+        for &(offset, len) in fields {
+            self.bytes[offset..offset + len].fill(0);
+        }
+    }
+
+    /// voxel index to RAS+ world coordinates in mm, from the sform if set, then the qform, then
+    /// the voxel sizes alone. Rows are x, y and z, the last column is the origin
+    pub fn voxel_to_ras(&self) -> [[f64; 4]; 3] {
+        let v1 = self.version == 1;
+        let float = |offset_v1: usize, offset_v2: usize| {
+            if v1 {
+                f32::from_le_bytes(self.get(offset_v1)) as f64
+            } else {
+                f64::from_le_bytes(self.get(offset_v2))
+            }
+        };
+        let (qform_code, sform_code) = if v1 {
+            (
+                i16::from_le_bytes(self.get(252)) as i32,
+                i16::from_le_bytes(self.get(254)) as i32,
+            )
+        } else {
+            (
+                i32::from_le_bytes(self.get(344)),
+                i32::from_le_bytes(self.get(348)),
+            )
+        };
+        // unset voxel sizes are taken as 1 mm
+        let pixdim = [1, 2, 3].map(|i| match float(76 + 4 * i, 104 + 8 * i).abs() {
+            p if p > 0. => p,
+            _ => 1.,
+        });
+
+        if sform_code > 0 {
+            let row =
+                |r: usize| [0, 1, 2, 3].map(|c| float(280 + 16 * r + 4 * c, 400 + 32 * r + 8 * c));
+            return [row(0), row(1), row(2)];
+        }
+        if qform_code > 0 {
+            let [b, c, d] = [0, 1, 2].map(|i| float(256 + 4 * i, 352 + 8 * i));
+            let offset = [0, 1, 2].map(|i| float(268 + 4 * i, 376 + 8 * i));
+            let a = (1. - (b * b + c * c + d * d)).max(0.).sqrt();
+            let qfac = if float(76, 104) < 0. { -1. } else { 1. };
+            let r = [
+                [
+                    a * a + b * b - c * c - d * d,
+                    2. * (b * c - a * d),
+                    2. * (b * d + a * c),
+                ],
+                [
+                    2. * (b * c + a * d),
+                    a * a + c * c - b * b - d * d,
+                    2. * (c * d - a * b),
+                ],
+                [
+                    2. * (b * d - a * c),
+                    2. * (c * d + a * b),
+                    a * a + d * d - c * c - b * b,
+                ],
+            ];
+            let scale = [pixdim[0], pixdim[1], qfac * pixdim[2]];
+            return [0, 1, 2].map(|i| {
+                [
+                    r[i][0] * scale[0],
+                    r[i][1] * scale[1],
+                    r[i][2] * scale[2],
+                    offset[i],
+                ]
+            });
+        }
+        [
+            [pixdim[0], 0., 0., 0.],
+            [0., pixdim[1], 0., 0.],
+            [0., 0., pixdim[2], 0.],
+        ]
+    }
+
+    /// header for a float32 volume of the given shape in the same space as this one
+    fn for_f32_output(&self, dims: &ArrayDim) -> Self {
+        let mut h = self.clone();
+        let shape = dims.shape();
+        let v1 = self.version == 1;
+        let (datatype, bitpix, dim, vox_offset) = if v1 {
+            (70, 72, 40, 108)
+        } else {
+            (12, 14, 16, 168)
+        };
+        h.set(datatype, 16i16.to_le_bytes());
+        h.set(bitpix, 32i16.to_le_bytes());
+        for i in 0..8 {
+            let d = match i {
+                0 => shape.len(),
+                i if i <= shape.len() => shape[i - 1],
+                _ => 1,
+            };
+            if v1 {
+                h.set(dim + 2 * i, (d as i16).to_le_bytes());
+            } else {
+                h.set(dim + 8 * i, (d as i64).to_le_bytes());
+            }
+        }
+        // the header is followed by 4 bytes of (empty) extension flags
+        let data_start = self.bytes.len() + 4;
+        if v1 {
+            h.set(vox_offset, (data_start as f32).to_le_bytes());
+            h.set(112, 1f32.to_le_bytes());
+            h.set(116, 0f32.to_le_bytes());
+            h.bytes[344..348].copy_from_slice(b"n+1\0");
+        } else {
+            h.set(vox_offset, (data_start as i64).to_le_bytes());
+            h.set(176, 1f64.to_le_bytes());
+            h.set(184, 0f64.to_le_bytes());
+            h.bytes[4..12].copy_from_slice(b"n+2\0\r\n\x1a\n");
+        }
+        h
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum DataType {
+    U8,
+    I8,
+    I16,
+    U16,
+    I32,
+    U32,
+    I64,
+    U64,
+    F32,
+    F64,
+}
+
+impl DataType {
+    fn from_code(code: i16) -> Result<Self, String> {
+        Ok(match code {
+            2 => DataType::U8,
+            4 => DataType::I16,
+            8 => DataType::I32,
+            16 => DataType::F32,
+            64 => DataType::F64,
+            256 => DataType::I8,
+            512 => DataType::U16,
+            768 => DataType::U32,
+            1024 => DataType::I64,
+            1280 => DataType::U64,
+            _ => return Err(format!("unsupported nifti datatype {code}")),
+        })
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            DataType::U8 | DataType::I8 => 1,
+            DataType::I16 | DataType::U16 => 2,
+            DataType::I32 | DataType::U32 | DataType::F32 => 4,
+            DataType::I64 | DataType::U64 | DataType::F64 => 8,
+        }
+    }
+
+    fn decode(&self, b: &[u8], le: bool) -> f64 {
+        macro_rules! decode {
+            ($t:ty) => {{
+                let b = b.try_into().unwrap();
+                if le {
+                    <$t>::from_le_bytes(b) as f64
+                } else {
+                    <$t>::from_be_bytes(b) as f64
+                }
+            }};
+        }
+        match self {
+            DataType::U8 => b[0] as f64,
+            DataType::I8 => b[0] as i8 as f64,
+            DataType::I16 => decode!(i16),
+            DataType::U16 => decode!(u16),
+            DataType::I32 => decode!(i32),
+            DataType::U32 => decode!(u32),
+            DataType::I64 => decode!(i64),
+            DataType::U64 => decode!(u64),
+            DataType::F32 => decode!(f32),
+            DataType::F64 => decode!(f64),
+        }
+    }
+}
+
+pub fn is_nifti_gz(path: impl AsRef<Path>) -> bool {
+    path.as_ref()
+        .file_name()
+        .is_some_and(|n| n.to_string_lossy().to_lowercase().ends_with(".nii.gz"))
+}
+
+/// reads a `.nii.gz` volume, decoding and converting it to f64 one chunk at a time
+pub fn read_nifti_gz_streamed(
+    path: impl AsRef<Path>,
+) -> Result<(Vec<f64>, ArrayDim, NiftiRawHeader), String> {
+    let path = path.as_ref();
+    let f = File::open(path).map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+    read_streamed(&mut GzDecoder::new(BufReader::new(f)), CHUNK_BYTES)
+        .map_err(|e| format!("failed to decompress {}: {e}", path.display()))
+}
+
+/// reads a volume from a decompressed NIfTI stream, converting `chunk_bytes` of it at a time,
+/// rounded down to whole voxels
+fn read_streamed(
+    stream: &mut impl Read,
+    chunk_bytes: usize,
+) -> std::io::Result<(Vec<f64>, ArrayDim, NiftiRawHeader)> {
+    let header = NiftiRawHeader::read_from(stream)?;
+    let dtype = DataType::from_code(header.datatype()).map_err(std::io::Error::other)?;
+    let shape = header.shape();
+    let numel: usize = shape.iter().product();
+
+    // skip extensions between the header and the start of the data
+    let to_skip = header.vox_offset().saturating_sub(header.bytes.len()) as u64;
+    std::io::copy(&mut stream.by_ref().take(to_skip), &mut std::io::sink())?;
+
+    let mut data = Vec::with_capacity(numel);
+    let sample = dtype.size();
+    let chunk_bytes = (chunk_bytes / sample).max(1) * sample;
+    let mut chunk = vec![0u8; chunk_bytes];
+    let le = header.little_endian;
+    while data.len() < numel {
+        let remaining = (numel - data.len()) * sample;
+        let buf = &mut chunk[..remaining.min(chunk_bytes)];
+        stream.read_exact(buf)?;
+        data.extend(buf.chunks_exact(sample).map(|b| dtype.decode(b, le)));
+    }
+
+    if let Some((slope, inter)) = header.scaling() {
+        data.iter_mut().for_each(|v| *v = *v * slope + inter);
+    }
+
+    Ok((data, ArrayDim::from_shape(&shape), header))
+}
+
+/// writes a float32 `.nii` in the space of the reference header. `.nii` is appended to the path
+pub fn write_nifti_like(
+    path: impl AsRef<Path>,
+    vol: &[f32],
+    dims: ArrayDim,
+    reference: &NiftiRawHeader,
+) -> Result<(), String> {
+    let mut path = path.as_ref().as_os_str().to_owned();
+    path.push(".nii");
+    let path = PathBuf::from(path);
+    let header = reference.for_f32_output(&dims);
+    let f = File::create(&path).map_err(|e| format!("failed to create {}: {e}", path.display()))?;
+    let mut w = BufWriter::new(f);
+    let le = header.little_endian;
+    w.write_all(&header.bytes)
+        .and_then(|_| w.write_all(&[0u8; 4]))
+        .and_then(|_| {
+            vol.iter()
+                .try_for_each(|v| w.write_all(&if le { v.to_le_bytes() } else { v.to_be_bytes() }))
+        })
+        .and_then(|_| w.flush())
+        .map_err(|e| format!("failed to write {}: {e}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    fn tmp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("radmap_nifti_{name}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// a blank header of the given version and byte order, with everything but its size zero
+    fn blank_header(version: u8, little_endian: bool) -> NiftiRawHeader {
+        let len: i32 = if version == 1 { 348 } else { 540 };
+        let mut bytes = vec![0u8; 540];
+        bytes[..4].copy_from_slice(&match little_endian {
+            true => len.to_le_bytes(),
+            false => len.to_be_bytes(),
+        });
+        NiftiRawHeader::parse(bytes).unwrap()
+    }
+
+    /// values that change with every voxel, so a voxel read from the wrong offset shows
+    fn ramp(n: usize) -> Vec<f32> {
+        (0..n).map(|i| i as f32 * 0.25 - 1000.).collect()
+    }
+
+    /// writes `vol` as `<name>.nii` and a gzip copy `<name>.nii.gz`, returning both paths
+    fn write_both(
+        dir: &Path,
+        name: &str,
+        vol: &[f32],
+        dims: ArrayDim,
+        reference: &NiftiRawHeader,
+    ) -> (PathBuf, PathBuf) {
+        let base = dir.join(name);
+        write_nifti_like(&base, vol, dims, reference).unwrap();
+        let nii = dir.join(format!("{name}.nii"));
+        let gz = dir.join(format!("{name}.nii.gz"));
+        let mut encoder = GzEncoder::new(File::create(&gz).unwrap(), Compression::fast());
+        encoder.write_all(&std::fs::read(&nii).unwrap()).unwrap();
+        encoder.finish().unwrap();
+        (nii, gz)
+    }
+
+    #[test]
+    fn headers_are_read_in_either_byte_order_and_version() {
+        for version in [1, 2] {
+            for little_endian in [true, false] {
+                let header = blank_header(version, little_endian);
+                assert_eq!((header.version, header.little_endian), (version, little_endian));
+                assert_eq!(header.bytes.len(), if version == 1 { 348 } else { 540 });
+                let dims = ArrayDim::from_shape(&[3, 4, 5]);
+                let out = header.for_f32_output(&dims);
+                assert_eq!(out.shape(), [3, 4, 5]);
+                assert_eq!(out.datatype(), 16);
+                assert_eq!(out.vox_offset(), out.bytes.len() + 4);
+                assert_eq!(out.scaling(), None);
+                // written headers parse back the same
+                let mut stream = &out.bytes[..];
+                let back = NiftiRawHeader::read_from(&mut stream).unwrap();
+                assert_eq!(back.bytes, out.bytes);
+            }
+        }
+        assert!(NiftiRawHeader::parse(vec![0u8; 540]).is_err());
+    }
+
+    #[test]
+    fn volumes_larger_than_a_chunk_read_the_same_as_uncompressed() {
+        let dir = tmp_dir("streamed");
+        // a little over one chunk of float32 voxels
+        let shape = [64, 64, 65];
+        let n: usize = shape.iter().product();
+        assert!(n * 4 > CHUNK_BYTES);
+        let vol = ramp(n);
+        let dims = ArrayDim::from_shape(&shape);
+        let (nii, gz) = write_both(&dir, "vol", &vol, dims, &blank_header(1, true));
+
+        let (streamed, streamed_dims, header) = read_nifti_gz_streamed(&gz).unwrap();
+        let (expected, expected_dims, _) = array_lib::io_nifti::read_nifti::<f64>(&nii);
+        assert_eq!(streamed_dims.shape(), expected_dims.shape());
+        assert_eq!(streamed, expected);
+        assert_eq!(header.shape(), shape);
+        assert!(streamed.iter().zip(&vol).all(|(a, b)| *a == *b as f64));
+    }
+
+    #[test]
+    fn big_endian_and_nifti_2_volumes_round_trip() {
+        let dir = tmp_dir("versions");
+        let shape = [5, 6, 7];
+        let vol = ramp(shape.iter().product());
+        let dims = ArrayDim::from_shape(&shape);
+        for version in [1, 2] {
+            for little_endian in [true, false] {
+                let name = format!("v{version}_{little_endian}");
+                let reference = blank_header(version, little_endian);
+                let (_, gz) = write_both(&dir, &name, &vol, dims, &reference);
+                let (read, read_dims, _) = read_nifti_gz_streamed(&gz).unwrap();
+                assert_eq!(read_dims.shape(), shape, "{name}");
+                assert!(read.iter().zip(&vol).all(|(a, b)| *a == *b as f64), "{name}");
+            }
+        }
+    }
+
+    /// hands out a few bytes per read, so voxels arrive split across reads
+    struct Trickle<'a>(&'a [u8], usize);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(self.1).min(self.0.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn voxels_split_across_reads_and_chunks_decode_whole() {
+        let dir = tmp_dir("split");
+        let shape = [7, 5, 3];
+        let vol = ramp(shape.iter().product());
+        let dims = ArrayDim::from_shape(&shape);
+        let (nii, _) = write_both(&dir, "vol", &vol, dims, &blank_header(1, true));
+        let bytes = std::fs::read(&nii).unwrap();
+        // 3 bytes a read never lines up with the 4 byte voxels, and 10 byte chunks round down to
+        // 2 voxels, so the last chunk is short
+        for (per_read, chunk_bytes) in [(3, 10), (1, 4), (5, 1), (bytes.len(), 1 << 20)] {
+            let (read, _, _) = read_streamed(&mut Trickle(&bytes, per_read), chunk_bytes).unwrap();
+            assert_eq!(read.len(), vol.len());
+            assert!(read.iter().zip(&vol).all(|(a, b)| *a == *b as f64));
+        }
+        // a stream that ends early is an error rather than a short volume
+        let short = &bytes[..bytes.len() - 2];
+        assert!(read_streamed(&mut Trickle(short, 3), 10).is_err());
+    }
+}
@@ -0,0 +1,389 @@
+//! Reader for detached NRRD headers (`.nhdr`) whose data file is gzip compressed or lives in
+//! another directory, and a matching writer for the feature maps derived from them.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use array_lib::ArrayDim;
+use flate2::read::GzDecoder;
+
+/// fields describing the data itself, which are rewritten for every output
+const DATA_FIELDS: &[&str] = &[
+    "type",
+    "dimension",
+    "sizes",
+    "endian",
+    "encoding",
+    "data file",
+    "datafile",
+    "byte skip",
+    "byteskip",
+    "line skip",
+    "lineskip",
+    "content",
+];
+
+#[derive(Debug, Clone)]
+pub struct NrrdHeader {
+    /// every `field: value` line in the order it appeared, with lowercase field names
+    pub fields: Vec<(String, String)>,
+    /// every `key:=value` line in the order it appeared
+    pub kv_pairs: Vec<(String, String)>,
+    /// directory of the header file, which relative data file paths are resolved against
+    pub header_dir: PathBuf,
+}
+
+impl NrrdHeader {
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let f = File::open(path).map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+        let mut lines = BufReader::new(f).lines();
+        let magic = lines
+            .next()
+            .and_then(|l| l.ok())
+            .ok_or_else(|| format!("{} is empty", path.display()))?;
+        if !magic.starts_with("NRRD") {
+            return Err(format!("{} is not a nrrd header", path.display()));
+        }
+        let mut fields = vec![];
+        let mut kv_pairs = vec![];
+        for line in lines {
+            let Ok(line) = line else { break };
+            if line.trim().is_empty() {
+                break;
+            }
+            if line.starts_with('#') {
+                continue;
+            }
+            if let Some((k, v)) = line.split_once(":=") {
+                kv_pairs.push((k.to_string(), v.to_string()));
+            } else if let Some((k, v)) = line.split_once(':') {
+                fields.push((k.trim().to_lowercase(), v.trim().to_string()));
+            }
+        }
+        Ok(NrrdHeader {
+            fields,
+            kv_pairs,
+            header_dir: path.parent().map(Path::to_path_buf).unwrap_or_default(),
+        })
+    }
+
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// path of the detached data file, resolved against the header directory
+    pub fn data_file(&self) -> Option<PathBuf> {
+        // the rest of the line, as a file name may hold spaces
+        let name = self.field("data file").or(self.field("datafile"))?;
+        // multi-file data sets, listed or named by a pattern followed by its indices, are not
+        // supported
+        let pattern = name.contains('%') && name.split_whitespace().count() >= 4;
+        if name.starts_with("LIST") || pattern {
+            return None;
+        }
+        Some(self.header_dir.join(name))
+    }
+
+    /// drops the free text content field and every key/value pair, which converters fill with
+    /// DICOM tags including patient details. Spatial fields are kept
+    pub fn scrub(&mut self) {
+        self.kv_pairs.clear();
+        self.fields.retain(|(k, _)| k != "content");
+    }
+
+    /// voxel index to LPS world coordinates in mm from the space directions and origin. Rows
+    /// are x, y and z, the last column is the origin. None without a 3D world space
+    pub fn voxel_to_lps(&self) -> Option<[[f64; 4]; 3]> {
+        let directions: Vec<[f64; 3]> = space_vectors(self.field("space directions")?)?
+            .into_iter()
+            .flatten()
+            .map(|v| v.try_into().ok())
+            .collect::<Option<_>>()?;
+        if directions.len() != 3 {
+            return None;
+        }
+        let origin = self
+            .field("space origin")
+            .and_then(space_vectors)
+            .and_then(|v| v.into_iter().next().flatten())
+            .and_then(|v| v.try_into().ok())
+            .unwrap_or([0.; 3]);
+        // flip the axes that point the other way in LPS
+        let flip = match self.field("space")? {
+            "left-posterior-superior" | "LPS" => [1., 1., 1.],
+            "right-anterior-superior" | "RAS" => [-1., -1., 1.],
+            "left-anterior-superior" | "LAS" => [1., -1., 1.],
+            _ => return None,
+        };
+        Some([0, 1, 2].map(|r| {
+            [
+                flip[r] * directions[0][r],
+                flip[r] * directions[1][r],
+                flip[r] * directions[2][r],
+                flip[r] * origin[r],
+            ]
+        }))
+    }
+
+    pub fn is_gzip(&self) -> bool {
+        matches!(self.field("encoding"), Some("gzip") | Some("gz"))
+    }
+
+    pub fn sizes(&self) -> Result<Vec<usize>, String> {
+        self.field("sizes")
+            .ok_or("nrrd header has no sizes field")?
+            .split_whitespace()
+            .map(|s| s.parse().map_err(|_| format!("invalid nrrd size {s}")))
+            .collect()
+    }
+}
+
+/// the vectors of a field like space directions, e.g. `(0.5, 0, 0) (0,0.5,0) none`, with None
+/// for `none`. Each vector is read up to its closing parenthesis, so it may hold spaces
+pub fn space_vectors(value: &str) -> Option<Vec<Option<Vec<f64>>>> {
+    let mut vectors = vec![];
+    let mut rest = value.trim();
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("none") {
+            vectors.push(None);
+            rest = after.trim_start();
+            continue;
+        }
+        let (vector, after) = rest.strip_prefix('(')?.split_once(')')?;
+        let components = vector
+            .split(',')
+            .map(|c| c.trim().parse().ok())
+            .collect::<Option<Vec<f64>>>()?;
+        vectors.push(Some(components));
+        rest = after.trim_start();
+    }
+    Some(vectors)
+}
+
+/// header text with its data file field replaced by `name`, for a header copied next to its data
+/// file
+pub fn relink_data_file(header: &str, name: &str) -> String {
+    let mut relinked = String::new();
+    for line in header.lines() {
+        let field = line
+            .split_once(':')
+            .filter(|_| !line.starts_with('#') && !line.contains(":="))
+            .map(|(k, _)| k.trim().to_lowercase());
+        match field.as_deref() {
+            Some("data file") | Some("datafile") => {
+                relinked.push_str(&format!("data file: {name}"))
+            }
+            _ => relinked.push_str(line),
+        }
+        relinked.push('\n');
+    }
+    relinked
+}
+
+/// true for detached headers the generic nrrd reader can't handle: gzip encoded data or a data
+/// file outside of the header's directory
+pub fn needs_detached_reader(path: impl AsRef<Path>) -> bool {
+    let path = path.as_ref();
+    if !path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("nhdr"))
+    {
+        return false;
+    }
+    let Ok(header) = NrrdHeader::read(path) else {
+        return false;
+    };
+    let data_file = header.field("data file").or(header.field("datafile"));
+    let elsewhere = data_file.is_some_and(|f| {
+        let f = Path::new(f);
+        f.is_absolute() || f.parent().is_some_and(|p| !p.as_os_str().is_empty())
+    });
+    header.is_gzip() || elsewhere
+}
+
+/// reads a detached nrrd volume, converting samples to f64
+pub fn read_detached_nrrd(
+    path: impl AsRef<Path>,
+) -> Result<(Vec<f64>, ArrayDim, NrrdHeader), String> {
+    let path = path.as_ref();
+    let header = NrrdHeader::read(path)?;
+    let sizes = header.sizes()?;
+    let numel: usize = sizes.iter().product();
+    let data_path = header
+        .data_file()
+        .ok_or_else(|| format!("{} has no single data file", path.display()))?;
+    let sample = SampleType::parse(header.field("type").ok_or("nrrd header has no type")?)?;
+    let big_endian = header.field("endian") == Some("big");
+
+    let f = File::open(&data_path)
+        .map_err(|e| format!("failed to open nrrd data file {}: {e}", data_path.display()))?;
+    let mut reader = BufReader::new(f);
+    let line_skip: usize = header
+        .field("line skip")
+        .or(header.field("lineskip"))
+        .map(|s| s.parse().unwrap_or(0))
+        .unwrap_or(0);
+    for _ in 0..line_skip {
+        let mut discard = String::new();
+        reader
+            .read_line(&mut discard)
+            .map_err(|e| format!("failed to skip lines in {}: {e}", data_path.display()))?;
+    }
+
+    let mut raw = vec![];
+    if header.is_gzip() {
+        GzDecoder::new(reader).read_to_end(&mut raw)
+    } else {
+        reader.read_to_end(&mut raw)
+    }
+    .map_err(|e| format!("failed to read nrrd data file {}: {e}", data_path.display()))?;
+
+    let n_bytes = numel * sample.size();
+    let byte_skip: i64 = header
+        .field("byte skip")
+        .or(header.field("byteskip"))
+        .map(|s| s.parse().unwrap_or(0))
+        .unwrap_or(0);
+    // a byte skip of -1 means the data sits at the very end of the file
+    let start = if byte_skip < 0 {
+        raw.len().checked_sub(n_bytes)
+    } else {
+        Some(byte_skip as usize)
+    }
+    .filter(|s| s + n_bytes <= raw.len())
+    .ok_or_else(|| {
+        format!(
+            "nrrd data file {} holds {} bytes but {n_bytes} are needed for shape {sizes:?}",
+            data_path.display(),
+            raw.len()
+        )
+    })?;
+
+    let data = raw[start..start + n_bytes]
+        .chunks_exact(sample.size())
+        .map(|b| sample.decode(b, big_endian))
+        .collect();
+    Ok((data, ArrayDim::from_shape(&sizes), header))
+}
+
+/// writes a 3D f32 volume as an attached raw nrrd, carrying over the spatial fields of the
+/// reference header. `.nrrd` is appended to the path
+pub fn write_nrrd_like(
+    path: impl AsRef<Path>,
+    vol: &[f32],
+    dims: ArrayDim,
+    reference: &NrrdHeader,
+) -> Result<(), String> {
+    let mut path = path.as_ref().as_os_str().to_owned();
+    path.push(".nrrd");
+    let path = PathBuf::from(path);
+    let f = File::create(&path).map_err(|e| format!("failed to create {}: {e}", path.display()))?;
+    let mut w = BufWriter::new(f);
+
+    let sizes: Vec<String> = dims.shape().iter().map(|s| s.to_string()).collect();
+    let mut text = format!(
+        "NRRD0004\ntype: float\ndimension: {}\nsizes: {}\nendian: little\nencoding: raw\n",
+        sizes.len(),
+        sizes.join(" ")
+    );
+    for (k, v) in &reference.fields {
+        if DATA_FIELDS.contains(&k.as_str()) {
+            continue;
+        }
+        text.push_str(&format!("{k}: {v}\n"));
+    }
+    for (k, v) in &reference.kv_pairs {
+        text.push_str(&format!("{k}:={v}\n"));
+    }
+    text.push('\n');
+
+    w.write_all(text.as_bytes())
+        .and_then(|_| vol.iter().try_for_each(|v| w.write_all(&v.to_le_bytes())))
+        .and_then(|_| w.flush())
+        .map_err(|e| format!("failed to write {}: {e}", path.display()))
+}
+
+#[derive(Debug, Clone, Copy)]
+enum SampleType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    I64,
+    U64,
+    F32,
+    F64,
+}
+
+impl SampleType {
+    fn parse(s: &str) -> Result<Self, String> {
+        Ok(match s {
+            "signed char" | "int8" | "int8_t" => SampleType::I8,
+            "uchar" | "unsigned char" | "uint8" | "uint8_t" => SampleType::U8,
+            "short" | "short int" | "signed short" | "signed short int" | "int16" | "int16_t" => {
+                SampleType::I16
+            }
+            "ushort" | "unsigned short" | "unsigned short int" | "uint16" | "uint16_t" => {
+                SampleType::U16
+            }
+            "int" | "signed int" | "int32" | "int32_t" => SampleType::I32,
+            "uint" | "unsigned int" | "uint32" | "uint32_t" => SampleType::U32,
+            "longlong"
+            | "long long"
+            | "long long int"
+            | "signed long long"
+            | "signed long long int"
+            | "int64"
+            | "int64_t" => SampleType::I64,
+            "ulonglong"
+            | "unsigned long long"
+            | "unsigned long long int"
+            | "uint64"
+            | "uint64_t" => SampleType::U64,
+            "float" => SampleType::F32,
+            "double" => SampleType::F64,
+            _ => return Err(format!("unsupported nrrd type {s}")),
+        })
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            SampleType::I8 | SampleType::U8 => 1,
+            SampleType::I16 | SampleType::U16 => 2,
+            SampleType::I32 | SampleType::U32 | SampleType::F32 => 4,
+            SampleType::I64 | SampleType::U64 | SampleType::F64 => 8,
+        }
+    }
+
+    fn decode(&self, b: &[u8], big_endian: bool) -> f64 {
+        macro_rules! decode {
+            ($t:ty) => {{
+                let b = b.try_into().unwrap();
+                if big_endian {
+                    <$t>::from_be_bytes(b) as f64
+                } else {
+                    <$t>::from_le_bytes(b) as f64
+                }
+            }};
+        }
+        match self {
+            SampleType::I8 => b[0] as i8 as f64,
+            SampleType::U8 => b[0] as f64,
+            SampleType::I16 => decode!(i16),
+            SampleType::U16 => decode!(u16),
+            SampleType::I32 => decode!(i32),
+            SampleType::U32 => decode!(u32),
+            SampleType::I64 => decode!(i64),
+            SampleType::U64 => decode!(u64),
+            SampleType::F32 => decode!(f32),
+            SampleType::F64 => decode!(f64),
+        }
+    }
+}
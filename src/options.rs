@@ -0,0 +1,1086 @@
+//! Map options built and checked in one place, so the command line, the GUI, protocols and
+//! library users all follow the same rules rather than each clamping values its own way:
+//!
+//! - the number of bins is between 2 and 65536, as gray levels are stored as u16
+//! - the kernel radius is at least 1, a kernel of radius 0 has no neighbours to pair with. A
+//!   radius per axis may be 0 along some axes but not along all of them
+//! - a spherical kernel has a radius above 0 mm and no radius per axis, its radius in voxels
+//!   along each axis comes from the voxel spacing
+//! - the thread limit, if set, is at least 1
+//! - the GLDM alpha is less than the number of bins, beyond that every neighbour depends on
+//!   every voxel, and so is the NGLDM alpha
+//! - the NGLDM distance is at least 1
+//! - at least one feature is selected, unless filter responses are written as maps
+//! - Gabor filters have a frequency above 0 and at most 0.5 cycles per voxel
+//! - GLCM maps per direction and anisotropy maps need at least one GLCM feature, each of which
+//!   radmap can compute itself
+//! - binning each kernel on its own needs every GLCM feature to be one radmap can compute itself,
+//!   the glcm crate only bins the whole volume
+//! - merging the directions into one matrix leaves nothing to map per direction or sum up in
+//!   anisotropy maps, and summing the directions up other than by their mean needs every GLCM
+//!   feature to be one radmap can compute itself
+//! - distance weighting needs a matrix pooling pairs at several offsets to weigh, the directions
+//!   merged or several distances merged, each matrix is normalized so a single offset is
+//!   weighted away
+//!
+//! GLCM offsets are fixed to the 13 neighbour directions of the kernel, so there is nothing to
+//! check for them.
+
+use std::collections::{BTreeMap, HashMap};
+
+use glcm::ui::MapOpts;
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::discretize::DiscretizationScope;
+use crate::filter::{FilterOpts, FilterUse};
+use crate::texture::cooccurrence::{
+    self, Angles, Anisotropy, DirectionAggregation, DistanceAggregation, DistanceWeighting,
+};
+use crate::texture::{ngldm, selected_features, Family, Feature, KernelShape, TextureOpts};
+
+pub const DEFAULT_N_BINS: usize = 32;
+pub const DEFAULT_KERNEL_RADIUS: usize = 1;
+/// most bins the gray levels can hold
+pub const MAX_N_BINS: usize = u16::MAX as usize + 1;
+
+/// serializes as a table of the options, with the features as a table of feature name to alias.
+/// Options left out when deserializing take their defaults
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MapOptsBuilder {
+    n_bins: usize,
+    kernel_radius: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kernel_radii: Option<[usize; 3]>,
+    #[serde(skip_serializing_if = "is_cube")]
+    kernel_shape: KernelShape,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_threads: Option<usize>,
+    #[serde(serialize_with = "sorted")]
+    features: HashMap<Feature, String>,
+    gldm_alpha: usize,
+    ngldm_alpha: usize,
+    ngldm_distance: usize,
+    #[serde(skip_serializing_if = "FilterOpts::is_empty")]
+    filters: FilterOpts,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    glcm_per_direction: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    glcm_anisotropy: Option<Anisotropy>,
+    #[serde(skip_serializing_if = "is_global")]
+    discretization: DiscretizationScope,
+    #[serde(skip_serializing_if = "Angles::is_all")]
+    glcm_angles: Angles,
+    #[serde(skip_serializing_if = "is_adjacent")]
+    glcm_distances: Vec<usize>,
+    #[serde(skip_serializing_if = "is_mean")]
+    glcm_aggregation: DistanceAggregation,
+    #[serde(skip_serializing_if = "is_direction_mean")]
+    glcm_direction_aggregation: DirectionAggregation,
+    #[serde(skip_serializing_if = "is_unweighted")]
+    glcm_weighting: DistanceWeighting,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    slice_wise: bool,
+}
+
+fn is_global(scope: &DiscretizationScope) -> bool {
+    !scope.is_local()
+}
+
+fn is_cube(shape: &KernelShape) -> bool {
+    *shape == KernelShape::Cube
+}
+
+fn is_adjacent(distances: &Vec<usize>) -> bool {
+    *distances == [cooccurrence::DEFAULT_DISTANCE]
+}
+
+fn is_mean(aggregation: &DistanceAggregation) -> bool {
+    *aggregation == DistanceAggregation::Mean
+}
+
+fn is_direction_mean(aggregation: &DirectionAggregation) -> bool {
+    *aggregation == DirectionAggregation::Mean
+}
+
+fn is_unweighted(weighting: &DistanceWeighting) -> bool {
+    *weighting == DistanceWeighting::None
+}
+
+/// writes the features in a fixed order, so saved options can be diffed
+fn sorted<S: Serializer>(features: &HashMap<Feature, String>, s: S) -> Result<S::Ok, S::Error> {
+    s.collect_map(features.iter().collect::<BTreeMap<_, _>>())
+}
+
+impl Default for MapOptsBuilder {
+    fn default() -> Self {
+        MapOptsBuilder {
+            n_bins: DEFAULT_N_BINS,
+            kernel_radius: DEFAULT_KERNEL_RADIUS,
+            kernel_radii: None,
+            kernel_shape: KernelShape::Cube,
+            max_threads: None,
+            features: HashMap::new(),
+            gldm_alpha: 0,
+            ngldm_alpha: 0,
+            ngldm_distance: ngldm::DEFAULT_DISTANCE,
+            filters: FilterOpts::default(),
+            glcm_per_direction: false,
+            glcm_anisotropy: None,
+            discretization: DiscretizationScope::Global,
+            glcm_angles: Angles::ALL,
+            glcm_distances: vec![cooccurrence::DEFAULT_DISTANCE],
+            glcm_aggregation: DistanceAggregation::Mean,
+            glcm_direction_aggregation: DirectionAggregation::Mean,
+            glcm_weighting: DistanceWeighting::None,
+            slice_wise: false,
+        }
+    }
+}
+
+impl MapOptsBuilder {
+    /// default bins and kernel radius, all threads and no features
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the builder of options built before, e.g. to save them
+    pub fn from_opts(opts: &MapOpts, texture: &TextureOpts) -> Self {
+        MapOptsBuilder {
+            n_bins: opts.n_bins,
+            kernel_radius: opts.kernel_radius,
+            kernel_radii: texture.kernel_radii,
+            kernel_shape: texture.kernel_shape,
+            max_threads: opts.max_threads,
+            features: selected_features(opts, texture).into_iter().collect(),
+            gldm_alpha: texture.gldm_alpha,
+            ngldm_alpha: texture.ngldm_alpha,
+            ngldm_distance: texture.ngldm_distance,
+            filters: texture.filters.clone(),
+            glcm_per_direction: texture.glcm_per_direction,
+            glcm_anisotropy: texture.glcm_anisotropy,
+            discretization: texture.discretization,
+            glcm_angles: texture.glcm_angles,
+            glcm_distances: texture.glcm_distances.clone(),
+            glcm_aggregation: texture.glcm_aggregation,
+            glcm_direction_aggregation: texture.glcm_direction_aggregation,
+            glcm_weighting: texture.glcm_weighting,
+            slice_wise: texture.slice_wise,
+        }
+    }
+
+    pub fn n_bins(mut self, n_bins: usize) -> Self {
+        self.n_bins = n_bins;
+        self
+    }
+
+    pub fn kernel_radius(mut self, kernel_radius: usize) -> Self {
+        self.kernel_radius = kernel_radius;
+        self
+    }
+
+    /// a radius along x, y and z in place of the kernel radius, e.g. `[1, 1, 0]` for 3x3x1
+    /// kernels on acquisitions with thick slices. None for a cube of the kernel radius
+    pub fn kernel_radii(mut self, kernel_radii: Option<[usize; 3]>) -> Self {
+        self.kernel_radii = kernel_radii;
+        self
+    }
+
+    /// a sphere in mm in place of the cube of the kernel radius, measured with the voxel spacing
+    /// of each volume mapped
+    pub fn kernel_shape(mut self, kernel_shape: KernelShape) -> Self {
+        self.kernel_shape = kernel_shape;
+        self
+    }
+
+    /// None to use every core
+    pub fn max_threads(mut self, max_threads: Option<usize>) -> Self {
+        self.max_threads = max_threads;
+        self
+    }
+
+    /// largest difference in gray levels of a neighbour that still depends on a voxel, for the
+    /// GLDM features. 0 by default, so only neighbours of the same gray level depend on it
+    pub fn gldm_alpha(mut self, gldm_alpha: usize) -> Self {
+        self.gldm_alpha = gldm_alpha;
+        self
+    }
+
+    /// largest difference in gray levels of a neighbour that still depends on a voxel, for the
+    /// NGLDM features. 0 by default
+    pub fn ngldm_alpha(mut self, ngldm_alpha: usize) -> Self {
+        self.ngldm_alpha = ngldm_alpha;
+        self
+    }
+
+    /// how many steps along the 26 neighbour directions the neighbourhood of a voxel reaches,
+    /// for the NGLDM features. 1 by default, the 26 neighbours
+    pub fn ngldm_distance(mut self, ngldm_distance: usize) -> Self {
+        self.ngldm_distance = ngldm_distance;
+        self
+    }
+
+    /// filtered copies of the input to write as maps or to map like the input
+    pub fn filters(mut self, filters: FilterOpts) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    /// also map the selected GLCM features along each of the 13 directions on its own, besides
+    /// their average over the directions
+    pub fn glcm_per_direction(mut self, glcm_per_direction: bool) -> Self {
+        self.glcm_per_direction = glcm_per_direction;
+        self
+    }
+
+    /// also map how much the selected GLCM features change with direction, as the variance or
+    /// range of their values along the 13 directions
+    pub fn glcm_anisotropy(mut self, glcm_anisotropy: Option<Anisotropy>) -> Self {
+        self.glcm_anisotropy = glcm_anisotropy;
+        self
+    }
+
+    /// bin the whole volume once, the default, or the intensities of each kernel on their own
+    /// between the minimum and maximum of the kernel
+    pub fn discretization(mut self, discretization: DiscretizationScope) -> Self {
+        self.discretization = discretization;
+        self
+    }
+
+    /// count the co-occurrence matrices of the GLCM and Haralick features along these
+    /// directions only, all 13 by default
+    pub fn glcm_angles(mut self, glcm_angles: Angles) -> Self {
+        self.glcm_angles = glcm_angles;
+        self
+    }
+
+    /// pair voxels these many steps apart in the co-occurrence matrices of the GLCM and
+    /// Haralick features, 1 by default
+    pub fn glcm_distances(mut self, glcm_distances: impl IntoIterator<Item = usize>) -> Self {
+        self.glcm_distances = glcm_distances.into_iter().collect();
+        self
+    }
+
+    /// average the features of the matrices at each of several distances, the default, or take
+    /// them from one matrix of the pairs at every distance
+    pub fn glcm_aggregation(mut self, glcm_aggregation: DistanceAggregation) -> Self {
+        self.glcm_aggregation = glcm_aggregation;
+        self
+    }
+
+    /// average the features along each direction, the default, take them from one matrix of the
+    /// pairs along every direction, or keep the largest over the directions
+    pub fn glcm_direction_aggregation(
+        mut self,
+        glcm_direction_aggregation: DirectionAggregation,
+    ) -> Self {
+        self.glcm_direction_aggregation = glcm_direction_aggregation;
+        self
+    }
+
+    /// weigh the pairs pooled in a merged co-occurrence matrix by the distance between their
+    /// voxels, see [`DistanceWeighting`]. None counts every pair once
+    pub fn glcm_weighting(mut self, glcm_weighting: DistanceWeighting) -> Self {
+        self.glcm_weighting = glcm_weighting;
+        self
+    }
+
+    /// keep every kernel and the neighbours of its voxels within the axial slice of the voxel,
+    /// for thick slice acquisitions. Co-occurrence matrices are then only counted along the
+    /// in-plane directions among the angles
+    pub fn slice_wise(mut self, slice_wise: bool) -> Self {
+        self.slice_wise = slice_wise;
+        self
+    }
+
+    /// selects a feature under `alias`, replacing its alias if it is already selected
+    pub fn feature(mut self, feature: Feature, alias: impl Into<String>) -> Self {
+        self.features.insert(feature, alias.into());
+        self
+    }
+
+    pub fn features(mut self, features: impl IntoIterator<Item = (Feature, String)>) -> Self {
+        self.features.extend(features);
+        self
+    }
+
+    /// leaves out every feature selected before
+    pub fn clear_features(mut self) -> Self {
+        self.features.clear();
+        self
+    }
+
+    /// leaves out a feature selected before
+    pub fn omit(mut self, feature: Feature) -> Self {
+        self.features.remove(&feature);
+        self
+    }
+
+    /// the first rule the options break, if any
+    pub fn validate(&self) -> Result<(), String> {
+        if !(2..=MAX_N_BINS).contains(&self.n_bins) {
+            return Err(format!(
+                "the number of bins must be between 2 and {MAX_N_BINS}, got {}",
+                self.n_bins
+            ));
+        }
+        if self.kernel_radius < 1 {
+            return Err("the kernel radius must be at least 1".to_string());
+        }
+        if self.kernel_radii == Some([0; 3]) {
+            return Err("the kernel radius must be at least 1 along one axis".to_string());
+        }
+        if let KernelShape::Sphere { radius_mm } = self.kernel_shape {
+            if !(radius_mm.is_finite() && radius_mm > 0.) {
+                return Err(format!(
+                    "the radius of a spherical kernel must be above 0 mm, got {radius_mm}"
+                ));
+            }
+            if self.kernel_radii.is_some() {
+                return Err(
+                    "a spherical kernel takes its size from its radius in mm, not from a radius \
+                     per axis"
+                        .to_string(),
+                );
+            }
+        }
+        if self.max_threads == Some(0) {
+            return Err("the thread limit must be at least 1".to_string());
+        }
+        let exports_filters = !self.filters.is_empty() && self.filters.usage == FilterUse::Export;
+        if self.features.is_empty() && !exports_filters {
+            return Err("no features selected".to_string());
+        }
+        self.filters.validate()?;
+        if self.gldm_alpha >= self.n_bins {
+            return Err(format!(
+                "the GLDM alpha must be less than the number of bins ({}), got {}",
+                self.n_bins, self.gldm_alpha
+            ));
+        }
+        if self.ngldm_alpha >= self.n_bins {
+            return Err(format!(
+                "the NGLDM alpha must be less than the number of bins ({}), got {}",
+                self.n_bins, self.ngldm_alpha
+            ));
+        }
+        if self.ngldm_distance < 1 {
+            return Err("the NGLDM distance must be at least 1".to_string());
+        }
+        let directional = self.glcm_per_direction || self.glcm_anisotropy.is_some();
+        let glcm: Vec<Feature> = self
+            .features
+            .keys()
+            .filter(|f| f.family() == Family::Glcm)
+            .copied()
+            .collect();
+        if directional && glcm.is_empty() {
+            return Err("GLCM maps per direction or anisotropy need a GLCM feature".to_string());
+        }
+        let own = glcm.iter().find(|f| match f {
+            Feature::Glcm(g) => !cooccurrence::is_computed(*g),
+            _ => false,
+        });
+        if let Some(f) = own.filter(|_| directional) {
+            return Err(format!(
+                "GLCM maps per direction or anisotropy are not available for {f}"
+            ));
+        }
+        if directional && self.glcm_direction_aggregation == DirectionAggregation::Merged {
+            return Err(
+                "GLCM maps per direction or anisotropy need the directions kept apart, not \
+                 merged into one matrix"
+                    .to_string(),
+            );
+        }
+        if let Some(f) =
+            own.filter(|_| self.glcm_direction_aggregation != DirectionAggregation::Mean)
+        {
+            return Err(format!(
+                "{f} can only be averaged over the directions, only the glcm crate computes it \
+                 and it takes the mean"
+            ));
+        }
+        if let Some(f) = own.filter(|_| self.discretization.is_local()) {
+            return Err(format!(
+                "{f} can't be mapped with each kernel binned on its own, only the glcm crate \
+                 computes it and it bins the whole volume"
+            ));
+        }
+        if self.glcm_angles.is_empty() {
+            return Err("at least one GLCM direction must be selected".to_string());
+        }
+        if self.slice_wise && self.glcm_angles.intersection(Angles::IN_PLANE).is_empty() {
+            return Err(
+                "mapping slice-wise needs at least one of the 4 in-plane GLCM directions"
+                    .to_string(),
+            );
+        }
+        if self.kernel_radii.is_some_and(|[_, _, rz]| rz == 0)
+            && self.glcm_angles.intersection(Angles::IN_PLANE).is_empty()
+        {
+            return Err(
+                "kernels of one slice need at least one of the 4 in-plane GLCM directions"
+                    .to_string(),
+            );
+        }
+        if let Some(f) = own.filter(|_| !self.glcm_angles.is_all()) {
+            return Err(format!(
+                "{f} can't be mapped along some directions only, only the glcm crate computes \
+                 it and it takes all 13"
+            ));
+        }
+        if let Some(f) = own.filter(|_| self.slice_wise) {
+            return Err(format!(
+                "{f} can't be mapped slice-wise, only the glcm crate computes it and it maps 3D \
+                 kernels"
+            ));
+        }
+        if let Some(f) = own.filter(|_| TextureOpts::anisotropic_radii(self.kernel_radii).is_some())
+        {
+            return Err(format!(
+                "{f} can't be mapped with a radius per axis, only the glcm crate computes it and \
+                 it maps cubic kernels"
+            ));
+        }
+        if let Some(f) = own.filter(|_| self.kernel_shape.is_sphere()) {
+            return Err(format!(
+                "{f} can't be mapped over a spherical kernel, only the glcm crate computes it \
+                 and it maps cubic kernels"
+            ));
+        }
+        if self.glcm_distances.is_empty() {
+            return Err("at least one GLCM distance must be given".to_string());
+        }
+        if self.glcm_distances.contains(&0) {
+            return Err("the GLCM distance must be at least 1".to_string());
+        }
+        // the radius of a sphere in voxels is only known once the spacing is
+        let widest = self.widest_radius();
+        if !self.kernel_shape.is_sphere()
+            && let Some(d) = self.glcm_distances.iter().find(|d| **d > 2 * widest)
+        {
+            return Err(format!(
+                "the GLCM distance can be at most twice the kernel radius ({}), no pairs of \
+                 voxels of a kernel are further apart, got {d}",
+                2 * widest,
+            ));
+        }
+        if let Some(f) = own.filter(|_| !is_adjacent(&self.glcm_distances)) {
+            return Err(format!(
+                "{f} can't be mapped at a distance other than 1, only the glcm crate computes it \
+                 and it pairs neighbouring voxels"
+            ));
+        }
+        let pooled = self.glcm_direction_aggregation == DirectionAggregation::Merged
+            || (self.glcm_aggregation == DistanceAggregation::Merged
+                && self.glcm_distances.len() > 1);
+        if self.glcm_weighting != DistanceWeighting::None && !pooled {
+            return Err(
+                "distance weighting needs the directions or several distances merged into one \
+                 matrix, a matrix of pairs at a single offset is weighted away"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    /// the kernel radius, or the largest radius per axis
+    fn widest_radius(&self) -> usize {
+        match self.kernel_radii {
+            Some(radii) => radii.into_iter().max().unwrap_or_default(),
+            None => self.kernel_radius,
+        }
+    }
+
+    /// options for the GLCM mapper and the other families
+    pub fn build(self) -> Result<(MapOpts, TextureOpts), String> {
+        self.validate()?;
+        // the same radius along every axis is a cube of that radius. Otherwise the widest
+        // radius bounds the kernel, for edges and previews
+        let kernel_radius = self.widest_radius();
+        let (glcm_features, mut texture) = TextureOpts::split(&self.features);
+        texture.gldm_alpha = self.gldm_alpha;
+        texture.ngldm_alpha = self.ngldm_alpha;
+        texture.ngldm_distance = self.ngldm_distance;
+        texture.filters = self.filters;
+        texture.glcm_per_direction = self.glcm_per_direction;
+        texture.glcm_anisotropy = self.glcm_anisotropy;
+        texture.discretization = self.discretization;
+        texture.glcm_angles = self.glcm_angles;
+        texture.glcm_distances = self.glcm_distances;
+        texture.glcm_distances.sort_unstable();
+        texture.glcm_distances.dedup();
+        texture.glcm_aggregation = self.glcm_aggregation;
+        texture.glcm_direction_aggregation = self.glcm_direction_aggregation;
+        texture.glcm_weighting = self.glcm_weighting;
+        texture.slice_wise = self.slice_wise;
+        texture.kernel_radii = TextureOpts::anisotropic_radii(self.kernel_radii);
+        texture.kernel_shape = self.kernel_shape;
+        let opts = MapOpts {
+            n_bins: self.n_bins,
+            kernel_radius,
+            max_threads: self.max_threads,
+            features: glcm_features,
+            ..Default::default()
+        };
+        Ok((opts, texture))
+    }
+}
+
+/// reads a radius per axis written as `rx,ry,rz`, e.g. `3,3,1`
+pub fn parse_kernel_radii(s: &str) -> Result<[usize; 3], String> {
+    let radii: Vec<usize> = s
+        .split(',')
+        .map(|r| r.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("could not read \"{s}\" as kernel radii, expected rx,ry,rz"))?;
+    radii
+        .try_into()
+        .map_err(|_| format!("expected a kernel radius along x, y and z like 3,3,1, got \"{s}\""))
+}
+
+/// the options that decide the values of the maps, described for tooltips and help text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapOption {
+    NBins,
+    KernelRadius,
+    KernelRadii,
+    KernelShape,
+    BinEdges,
+    BinWidth,
+    BinMin,
+    BinMax,
+    BinFromMask,
+    BinRank,
+    Discretization,
+    RebinIntegerInput,
+    GldmAlpha,
+    NgldmAlpha,
+    NgldmDistance,
+    MaxThreads,
+    GaborFrequency,
+    GaborOrientation,
+    Gradient,
+    Laplacian,
+    LogSigma,
+    Wavelet,
+    WaveletBand,
+    FilterUse,
+    GlcmPerDirection,
+    GlcmAnisotropy,
+    GlcmAngles,
+    GlcmDistance,
+    GlcmAggregation,
+    GlcmDirectionAggregation,
+    GlcmWeighting,
+    SliceWise,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OptionInfo {
+    /// name on the command line, without the dashes
+    pub name: &'static str,
+    pub description: &'static str,
+    /// values allowed, as shown to users
+    pub range: String,
+    pub default: &'static str,
+}
+
+impl MapOption {
+    pub const ALL: [MapOption; 32] = [
+        MapOption::NBins,
+        MapOption::KernelRadius,
+        MapOption::KernelRadii,
+        MapOption::KernelShape,
+        MapOption::BinEdges,
+        MapOption::BinWidth,
+        MapOption::BinMin,
+        MapOption::BinMax,
+        MapOption::BinFromMask,
+        MapOption::BinRank,
+        MapOption::Discretization,
+        MapOption::RebinIntegerInput,
+        MapOption::GldmAlpha,
+        MapOption::NgldmAlpha,
+        MapOption::NgldmDistance,
+        MapOption::MaxThreads,
+        MapOption::GaborFrequency,
+        MapOption::GaborOrientation,
+        MapOption::Gradient,
+        MapOption::Laplacian,
+        MapOption::LogSigma,
+        MapOption::Wavelet,
+        MapOption::WaveletBand,
+        MapOption::FilterUse,
+        MapOption::GlcmPerDirection,
+        MapOption::GlcmAnisotropy,
+        MapOption::GlcmAngles,
+        MapOption::GlcmDistance,
+        MapOption::GlcmAggregation,
+        MapOption::GlcmDirectionAggregation,
+        MapOption::GlcmWeighting,
+        MapOption::SliceWise,
+    ];
+
+    pub fn info(&self) -> OptionInfo {
+        let (name, description, range, default) = match self {
+            MapOption::NBins => (
+                "n-bins",
+                "number of gray levels the intensities are binned into before the matrices are \
+                 counted. Fewer bins smooth out noise, more bins keep subtle contrast but need \
+                 larger kernels to fill the matrices. 16 to 64 is typical",
+                format!("2 to {MAX_N_BINS}"),
+                "32",
+            ),
+            MapOption::KernelRadius => (
+                "kernel-radius",
+                "how many voxels around each voxel make up its neighbourhood. A radius of r is a \
+                 cube of 2r + 1 voxels a side. Larger kernels give smoother maps and take longer",
+                "at least 1".to_string(),
+                "1",
+            ),
+            MapOption::KernelRadii => (
+                "kernel-radii",
+                "a radius along x, y and z in place of the kernel radius, for acquisitions whose \
+                 voxels are far from cubic. 1,1,0 maps 3x3x1 kernels, each within its slice, and \
+                 3,3,1 makes kernels of about the same size in mm on 0.5 x 0.5 x 1.5 mm voxels",
+                "rx,ry,rz of at least 0, not all 0".to_string(),
+                "the kernel radius along every axis",
+            ),
+            MapOption::KernelShape => (
+                "kernel-shape",
+                "cube for every voxel within the kernel radius along each axis, counted in \
+                 voxels, or sphere:<mm> for every voxel whose centre lies within that many mm of \
+                 the voxel mapped, measured with the voxel spacing of the header. A sphere keeps \
+                 the neighbourhood the same size in mm along every axis on anisotropic voxels",
+                "cube, or sphere:<radius> with a radius above 0 mm".to_string(),
+                "cube",
+            ),
+            MapOption::BinEdges => (
+                "bin-edges",
+                "which bin a value exactly on a bin edge goes to. Only matters for intensities \
+                 that land on edges, like integer images",
+                "half-open or inclusive".to_string(),
+                "half-open",
+            ),
+            MapOption::BinWidth => (
+                "bin-width",
+                "width of the bins in the units of the input, instead of a number of bins. The \
+                 number of bins follows from the range of the input, so calibrated intensities \
+                 like CT are binned alike across scans",
+                "above 0".to_string(),
+                "off",
+            ),
+            MapOption::BinMin => (
+                "bin-min",
+                "lower end of the intensities binned, so the bins are the same for every input, \
+                 e.g. -100 HU. Lower intensities go to the first bin and gray levels are counted \
+                 from the lowest bin holding a voxel",
+                "any number".to_string(),
+                "the input minimum",
+            ),
+            MapOption::BinMax => (
+                "bin-max",
+                "upper end of the intensities binned, e.g. 300 HU. Higher intensities go to the \
+                 last bin",
+                "above the bin minimum".to_string(),
+                "the input maximum",
+            ),
+            MapOption::BinFromMask => (
+                "bin-from-mask",
+                "take the range of the bins from the voxels inside the mask only, so background \
+                 like the air around a CT scan doesn't take up most of the bins",
+                "on or off".to_string(),
+                "off",
+            ),
+            MapOption::BinRank => (
+                "bin-rank",
+                "replace every intensity by its rank among the voxels before binning, so each bin \
+                 holds about as many voxels whatever the scale of the intensities, for inputs \
+                 like raw MRI with no calibrated units. Ranks are taken among the voxels inside \
+                 the mask with bin-from-mask. Can't be used with a bin width or range",
+                "on or off".to_string(),
+                "off",
+            ),
+            MapOption::Discretization => (
+                "discretization",
+                "bin the whole volume once, or the intensities of each kernel on their own \
+                 between the minimum and maximum of the kernel. Local bins bring out texture \
+                 wherever the contrast is low, but gray levels no longer compare between voxels",
+                "global or local".to_string(),
+                "global",
+            ),
+            MapOption::RebinIntegerInput => (
+                "rebin-integer-input",
+                "bin inputs already quantized to a few integer levels like any other input, \
+                 instead of using their levels as the bins",
+                "on or off".to_string(),
+                "off",
+            ),
+            MapOption::GldmAlpha => (
+                "gldm-alpha",
+                "largest gray level difference for which a neighbour counts as dependent in the \
+                 GLDM features. 0 counts only neighbours of the same gray level",
+                "0 to the number of bins - 1".to_string(),
+                "0",
+            ),
+            MapOption::NgldmAlpha => (
+                "ngldm-alpha",
+                "coarseness of the NGLDM features, the largest gray level difference for which a \
+                 neighbour counts as dependent. 0 counts only neighbours of the same gray level",
+                "0 to the number of bins - 1".to_string(),
+                "0",
+            ),
+            MapOption::NgldmDistance => (
+                "ngldm-distance",
+                "how far the neighbourhood of each voxel reaches for the NGLDM features, in steps \
+                 along any of the 26 neighbour directions. Larger distances pick up coarser \
+                 textures, the neighbourhood is a cube of 2d + 1 voxels a side cut to the kernel",
+                "at least 1".to_string(),
+                "1",
+            ),
+            MapOption::MaxThreads => (
+                "max-threads",
+                "most cores used at once. Leave empty to use every core, limit it to keep the \
+                 machine responsive",
+                "at least 1".to_string(),
+                "all cores",
+            ),
+            MapOption::GaborFrequency => (
+                "gabor-frequency",
+                "frequencies of the Gabor filter bank in cycles per voxel. Each is filtered at \
+                 every orientation. Low frequencies pick up coarse stripes and take longer, as \
+                 the filter widens with the wavelength",
+                "above 0 and at most 0.5".to_string(),
+                "no Gabor filters",
+            ),
+            MapOption::GaborOrientation => (
+                "gabor-orientation",
+                "directions of the waves of the Gabor filter bank, an axis or a vector in voxels",
+                "x, y, z or a vector like 1,1,0".to_string(),
+                "x, y and z",
+            ),
+            MapOption::Gradient => (
+                "gradient",
+                "filter the input with the magnitude of its Sobel gradient, in intensity per unit \
+                 of the voxel spacing. Bright along edges",
+                "on or off".to_string(),
+                "off",
+            ),
+            MapOption::Laplacian => (
+                "laplacian",
+                "filter the input with its Laplacian, in intensity per unit of the voxel spacing \
+                 squared. Picks out blobs and ridges, negative on bright ones",
+                "on or off".to_string(),
+                "off",
+            ),
+            MapOption::LogSigma => (
+                "log-sigma",
+                "filter the input with the Laplacian of a Gaussian of each of these widths, in \
+                 the units of the voxel spacing, usually mm. Small sigmas pick out fine texture, \
+                 large ones coarse blobs",
+                "above 0".to_string(),
+                "no LoG filters",
+            ),
+            MapOption::Wavelet => (
+                "wavelet",
+                "filter the input into the sub-bands of its stationary wavelet transform, low or \
+                 high pass along each axis, like the wavelet images of pyradiomics. Each sub-band \
+                 is named after the filters along x, y and z, e.g. llh",
+                "haar, db2 or coif1".to_string(),
+                "no wavelet",
+            ),
+            MapOption::WaveletBand => (
+                "wavelet-band",
+                "sub-bands of the wavelet transform to keep, l or h along x, y and z",
+                "lll to hhh".to_string(),
+                "all 8",
+            ),
+            MapOption::FilterUse => (
+                "filter-use",
+                "whether the filter responses are written as maps of their own, or mapped with \
+                 the selected features like the input",
+                "export or map".to_string(),
+                "export",
+            ),
+            MapOption::GlcmPerDirection => (
+                "glcm-per-direction",
+                "also map the selected GLCM features along each of the 13 directions of the \
+                 kernel on its own, written as <feature>_dir01 to _dir13, to study anisotropy. \
+                 The usual maps average the directions",
+                "on or off".to_string(),
+                "off",
+            ),
+            MapOption::GlcmAnisotropy => (
+                "glcm-anisotropy",
+                "also map how much each selected GLCM feature changes with direction, as the \
+                 variance or the range (largest less smallest) of its values along the 13 \
+                 directions of the kernel, written as <feature>_anisotropy_<variance|range>",
+                "variance or range".to_string(),
+                "off",
+            ),
+            MapOption::GlcmAngles => (
+                "angles",
+                "directions the co-occurrence matrices of the GLCM and Haralick features are \
+                 counted along, numbered 1 to 13 as in the maps per direction. in-plane keeps \
+                 the 4 directions within a slice, for volumes with thick slices",
+                "all, in-plane or directions like 1,2,5".to_string(),
+                "all",
+            ),
+            MapOption::GlcmDistance => (
+                "distance",
+                "how many steps apart along each direction the voxels paired in the \
+                 co-occurrence matrices of the GLCM and Haralick features are. Pairs 2 or 3 \
+                 voxels apart often tell coarser textures apart better than neighbours. Several \
+                 distances are combined as --distance-aggregation says, and the maps are named \
+                 after the distances, e.g. <feature>_d1-2-3_mean",
+                "1 to twice the kernel radius, or several like 1,2,3".to_string(),
+                "1",
+            ),
+            MapOption::GlcmAggregation => (
+                "distance-aggregation",
+                "how the co-occurrence matrices at several distances are combined: mean averages \
+                 the features of the matrix at each distance, merged takes them from one matrix \
+                 of the pairs at every distance, as in the IBSI aggregation schemes",
+                "mean or merged".to_string(),
+                "mean",
+            ),
+            MapOption::GlcmDirectionAggregation => (
+                "direction-aggregation",
+                "how the co-occurrence matrices along the directions are summed up in the maps of \
+                 the GLCM and Haralick features: mean averages the features of the matrix along \
+                 each direction, merged takes them from one matrix of the pairs along every \
+                 direction, max keeps the largest over the directions, as in the IBSI \
+                 aggregation schemes",
+                "mean, merged or max".to_string(),
+                "mean",
+            ),
+            MapOption::GlcmWeighting => (
+                "distance-weighting",
+                "how much each pair of voxels counts in a co-occurrence matrix merging the \
+                 directions or several distances, from the distance between the voxels in mm: \
+                 one over it, one over its square, one over the sum of its lengths along each \
+                 axis or exp(-d²) of it, pyradiomics' weightingNorm",
+                "none, inverse, inverse-square, manhattan or gaussian".to_string(),
+                "none",
+            ),
+            MapOption::SliceWise => (
+                "slice-wise",
+                "keep every kernel within the axial slice of its voxel, a 2D mode for thick \
+                 slice acquisitions where 3D neighbourhoods mean little. Co-occurrence matrices \
+                 are only counted along the 4 in-plane directions and runs, zones and local \
+                 binary patterns are found within the slice",
+                "on or off".to_string(),
+                "off",
+            ),
+        };
+        OptionInfo {
+            name,
+            description,
+            range,
+            default,
+        }
+    }
+
+    /// description, allowed values and default, e.g. for a tooltip
+    pub fn help(&self) -> String {
+        let info = self.info();
+        format!(
+            "{}\nvalues: {}\ndefault: {}",
+            info.description, info.range, info.default
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use glcm::core::GLCMFeature;
+    use strum::IntoEnumIterator;
+
+    use super::*;
+    use crate::filter::gabor::{Gabor, Orientation};
+    use crate::filter::ImageFilter;
+    use crate::texture::gldm::GLDMFeature;
+    use crate::texture::ngldm::NGLDMFeature;
+
+    fn contrast() -> Feature {
+        Feature::from_str("contrast").unwrap()
+    }
+
+    /// default options mapping GLCM contrast
+    fn builder() -> MapOptsBuilder {
+        MapOptsBuilder::new().feature(contrast(), "contrast")
+    }
+
+    fn refused(builder: MapOptsBuilder, expected: &str) {
+        let e = builder.validate().unwrap_err();
+        assert!(e.contains(expected), "expected \"{expected}\" in \"{e}\"");
+    }
+
+    #[test]
+    fn valid_options_build() {
+        let (opts, texture) = builder()
+            .n_bins(64)
+            .kernel_radius(2)
+            .max_threads(Some(4))
+            .feature(Feature::Gldm(GLDMFeature::SmallDependenceEmphasis), "sde")
+            .gldm_alpha(1)
+            .glcm_per_direction(true)
+            .glcm_anisotropy(Some(Anisotropy::Range))
+            .glcm_distances([2, 1, 2])
+            .glcm_aggregation(DistanceAggregation::Merged)
+            .glcm_weighting(DistanceWeighting::Inverse)
+            .build()
+            .unwrap();
+        assert_eq!(opts.n_bins, 64);
+        assert_eq!(opts.kernel_radius, 2);
+        assert_eq!(opts.max_threads, Some(4));
+        assert_eq!(opts.features.len(), 1);
+        assert_eq!(texture.gldm_alpha, 1);
+        assert_eq!(texture.glcm_distances, [1, 2]);
+        assert!(texture.glcm_per_direction);
+    }
+
+    #[test]
+    fn bins_radius_and_threads_are_bounded() {
+        refused(builder().n_bins(1), "number of bins");
+        refused(builder().n_bins(MAX_N_BINS + 1), "number of bins");
+        assert!(builder().n_bins(MAX_N_BINS).validate().is_ok());
+        refused(builder().kernel_radius(0), "kernel radius must be at least 1");
+        refused(builder().kernel_radii(Some([0; 3])), "along one axis");
+        assert!(builder().kernel_radii(Some([1, 1, 0])).validate().is_ok());
+        refused(builder().max_threads(Some(0)), "thread limit");
+    }
+
+    #[test]
+    fn spherical_kernels_need_a_radius_and_no_radius_per_axis() {
+        let sphere = |radius_mm| KernelShape::Sphere { radius_mm };
+        refused(builder().kernel_shape(sphere(0.)), "above 0 mm");
+        refused(builder().kernel_shape(sphere(f64::NAN)), "above 0 mm");
+        refused(
+            builder()
+                .kernel_shape(sphere(3.))
+                .kernel_radii(Some([1, 1, 1])),
+            "not from a radius per axis",
+        );
+        assert!(builder().kernel_shape(sphere(3.)).validate().is_ok());
+    }
+
+    #[test]
+    fn something_must_be_mapped() {
+        refused(MapOptsBuilder::new(), "no features selected");
+        let mut filters = FilterOpts::default();
+        filters.filters.push(ImageFilter::Laplacian);
+        assert!(MapOptsBuilder::new().filters(filters.clone()).validate().is_ok());
+        filters.usage = FilterUse::Map;
+        refused(MapOptsBuilder::new().filters(filters), "no features selected");
+    }
+
+    #[test]
+    fn filters_are_checked() {
+        let mut filters = FilterOpts::default();
+        filters.filters.push(ImageFilter::Gabor(Gabor {
+            frequency: 0.,
+            orientation: Orientation::X,
+        }));
+        assert!(builder().filters(filters).validate().is_err());
+        let mut filters = FilterOpts::default();
+        filters.add_log(&[-1.]);
+        refused(builder().filters(filters), "LoG sigma");
+    }
+
+    #[test]
+    fn dependence_options_are_bounded() {
+        refused(builder().n_bins(8).gldm_alpha(8), "GLDM alpha");
+        refused(builder().n_bins(8).ngldm_alpha(8), "NGLDM alpha");
+        let ngldm = builder().feature(Feature::Ngldm(NGLDMFeature::LowDependenceEmphasis), "lde");
+        refused(ngldm.ngldm_distance(0), "NGLDM distance");
+    }
+
+    #[test]
+    fn directional_glcm_maps_need_separate_directions_of_a_glcm_feature() {
+        let no_glcm = MapOptsBuilder::new()
+            .feature(Feature::Gldm(GLDMFeature::SmallDependenceEmphasis), "sde");
+        refused(no_glcm.clone().glcm_per_direction(true), "need a GLCM feature");
+        refused(no_glcm.glcm_anisotropy(Some(Anisotropy::Variance)), "need a GLCM feature");
+        refused(
+            builder()
+                .glcm_per_direction(true)
+                .glcm_direction_aggregation(DirectionAggregation::Merged),
+            "kept apart",
+        );
+    }
+
+    #[test]
+    fn angles_must_leave_a_direction_to_count() {
+        refused(builder().glcm_angles(Angles::from_indices(Vec::new())), "at least one GLCM direction");
+        // direction 5 leaves the slice
+        let out_of_plane = Angles::from_indices([4]);
+        refused(
+            builder().slice_wise(true).glcm_angles(out_of_plane),
+            "mapping slice-wise",
+        );
+        refused(
+            builder().kernel_radii(Some([1, 1, 0])).glcm_angles(out_of_plane),
+            "kernels of one slice",
+        );
+        assert!(builder().slice_wise(true).glcm_angles(Angles::IN_PLANE).validate().is_ok());
+    }
+
+    #[test]
+    fn distances_fit_in_the_kernel() {
+        refused(builder().glcm_distances(Vec::new()), "at least one GLCM distance");
+        refused(builder().glcm_distances([0]), "at least 1");
+        refused(builder().kernel_radius(1).glcm_distances([3]), "at most twice");
+        assert!(builder().kernel_radius(1).glcm_distances([2]).validate().is_ok());
+    }
+
+    #[test]
+    fn weighting_needs_a_pooled_matrix() {
+        let weighted = || builder().glcm_weighting(DistanceWeighting::InverseSquare);
+        refused(weighted(), "distance weighting needs");
+        refused(
+            weighted()
+                .glcm_distances([1])
+                .glcm_aggregation(DistanceAggregation::Merged),
+            "distance weighting needs",
+        );
+        assert!(weighted()
+            .glcm_direction_aggregation(DirectionAggregation::Merged)
+            .validate()
+            .is_ok());
+        assert!(weighted()
+            .glcm_distances([1, 2])
+            .glcm_aggregation(DistanceAggregation::Merged)
+            .validate()
+            .is_ok());
+    }
+
+    /// only the glcm crate computes some GLCM features, and it maps them one way only
+    #[test]
+    fn features_only_the_glcm_crate_computes_keep_its_settings() {
+        let Some(feature) = GLCMFeature::iter().find(|f| !cooccurrence::is_computed(*f)) else {
+            // radmap computes every GLCM feature, so nothing is limited
+            return;
+        };
+        let own = || MapOptsBuilder::new().feature(Feature::Glcm(feature), "own");
+        assert!(own().validate().is_ok());
+        refused(own().glcm_per_direction(true), "not available for");
+        refused(
+            own().glcm_direction_aggregation(DirectionAggregation::Max),
+            "only be averaged",
+        );
+        refused(own().discretization(DiscretizationScope::Local), "binned on its own");
+        refused(own().glcm_angles(Angles::IN_PLANE), "along some directions only");
+        refused(own().slice_wise(true), "slice-wise");
+        refused(own().kernel_radii(Some([2, 2, 1])), "radius per axis");
+        refused(
+            own().kernel_shape(KernelShape::Sphere { radius_mm: 2. }),
+            "spherical kernel",
+        );
+        refused(own().glcm_distances([2]), "distance other than 1");
+    }
+}
@@ -0,0 +1,201 @@
+//! Export of aligned 3D patches of the input, the mask and the feature maps for patch based deep
+//! learning. Every patch is a `<stem>_patch_<n>.npz` holding `input` (z, y, x) float32 with the
+//! intensities before binning, `mask` (z, y, x) uint8 when there is a mask, and `features`
+//! (features, z, y, x) float32 in the channel order of `<stem>_patches.json`. The index
+//! `<stem>_patches.csv` gives the file, the corner of every patch in voxels and the fraction of
+//! it in the mask. The archives are compressed as `numpy.savez_compressed` would.
+//!
+//! Patches start every `stride` voxels along each axis. The last patch along an axis is moved
+//! back to end at the edge of the volume, so every patch has the full size and the volume is
+//! covered whatever the stride, with the last two overlapping more. Along an axis shorter than
+//! the patch size, patches are cut to the volume. With a mask, patches without any voxel of it
+//! are left out.
+
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use rayon::prelude::*;
+use serde::Serialize;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::io::output_path;
+use crate::locale::ReportFormat;
+use crate::tensor::npy_header;
+
+/// `<stem>_patches.json` as it is written
+#[derive(Serialize)]
+struct PatchesJson<'a> {
+    patch_size: usize,
+    stride: usize,
+    arrays: Vec<&'static str>,
+    layout: [&'static str; 4],
+    channels: Vec<&'a str>,
+}
+
+/// size of the patches and the step between them, in voxels along every axis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PatchGrid {
+    pub size: usize,
+    pub stride: usize,
+}
+
+impl PatchGrid {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.size == 0 || self.stride == 0 {
+            return Err("patch size and stride must be at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    /// first voxel of every patch along an axis of `len` voxels
+    fn starts(&self, len: usize) -> Vec<usize> {
+        if len <= self.size {
+            return vec![0];
+        }
+        let last = len - self.size;
+        let mut starts: Vec<usize> = (0..last).step_by(self.stride).collect();
+        starts.push(last);
+        starts
+    }
+
+    /// corners of the patches of a volume of `shape`, x fastest
+    pub fn corners(&self, shape: [usize; 3]) -> Vec<[usize; 3]> {
+        let [xs, ys, zs] = shape.map(|len| self.starts(len));
+        zs.iter()
+            .flat_map(|&z| {
+                let xs = &xs;
+                ys.iter()
+                    .flat_map(move |&y| xs.iter().map(move |&x| [x, y, z]))
+            })
+            .collect()
+    }
+}
+
+/// writes the patches of `input`, `mask` and `maps`, all of `shape` voxels (x, y, z), with
+/// their index and channel json to `dir`. Returns the number of patches written
+#[allow(clippy::too_many_arguments)]
+pub fn write_patches(
+    dir: &Path,
+    stem: &OsStr,
+    grid: PatchGrid,
+    shape: [usize; 3],
+    input: &[f64],
+    mask: Option<&[bool]>,
+    maps: &[(String, &[f32])],
+    format: &ReportFormat,
+) -> Result<usize, String> {
+    let extent = shape.map(|len| len.min(grid.size));
+    let n_patch: usize = extent.iter().product();
+    let index = |p: [usize; 3]| p[0] + shape[0] * (p[1] + shape[1] * p[2]);
+    // voxels of the patch at `corner` in C order of (z, y, x), which is x fastest
+    let voxels = move |corner: [usize; 3]| {
+        (0..extent[2]).flat_map(move |z| {
+            (0..extent[1]).flat_map(move |y| {
+                (0..extent[0]).map(move |x| index([corner[0] + x, corner[1] + y, corner[2] + z]))
+            })
+        })
+    };
+
+    let patches: Vec<([usize; 3], f64)> = grid
+        .corners(shape)
+        .into_iter()
+        .filter_map(|corner| match mask {
+            Some(m) => {
+                let inside = voxels(corner).filter(|&i| m[i]).count();
+                (inside > 0).then_some((corner, inside as f64 / n_patch as f64))
+            }
+            None => Some((corner, 1.)),
+        })
+        .collect();
+
+    let names: Vec<String> = (0..patches.len())
+        .map(|n| format!("patch_{n:05}.npz"))
+        .collect();
+    let zyx = [extent[2], extent[1], extent[0]];
+    patches
+        .par_iter()
+        .zip(&names)
+        .try_for_each(|((corner, _), name)| {
+            let mut arrays = vec![(
+                "input",
+                npy("<f4", &zyx, voxels(*corner).map(|i| input[i] as f32)),
+            )];
+            if let Some(m) = mask {
+                let mut data = npy_header("|u1", &zyx);
+                data.extend(voxels(*corner).map(|i| m[i] as u8));
+                arrays.push(("mask", data));
+            }
+            let features_shape = [maps.len(), zyx[0], zyx[1], zyx[2]];
+            let values = maps
+                .iter()
+                .flat_map(|(_, map)| voxels(*corner).map(|i| map[i]));
+            arrays.push(("features", npy("<f4", &features_shape, values)));
+            write_npz(&output_path(dir, stem, name), &arrays)
+        })?;
+
+    let mut csv = format.header(&[
+        "file",
+        "x",
+        "y",
+        "z",
+        "size_x",
+        "size_y",
+        "size_z",
+        "mask_fraction",
+    ]);
+    csv.push('\n');
+    for (name, (corner, fraction)) in names.iter().zip(&patches) {
+        let mut row = vec![format!("{}_{name}", stem.to_string_lossy())];
+        row.extend(corner.iter().chain(&extent).map(|n| n.to_string()));
+        row.push(format.number(*fraction, 4));
+        csv.push_str(&format.row(&row));
+        csv.push('\n');
+    }
+    let index_path = output_path(dir, stem, "patches.csv");
+    std::fs::write(&index_path, csv)
+        .map_err(|e| format!("failed to write {}: {e}", index_path.display()))?;
+
+    let description = PatchesJson {
+        patch_size: grid.size,
+        stride: grid.stride,
+        arrays: match mask {
+            Some(_) => vec!["input", "mask", "features"],
+            None => vec!["input", "features"],
+        },
+        layout: ["channel", "z", "y", "x"],
+        channels: maps.iter().map(|(name, _)| name.as_str()).collect(),
+    };
+    let text = serde_json::to_string_pretty(&description)
+        .expect("patch descriptions are always representable as json")
+        + "\n";
+    let channels_json = output_path(dir, stem, "patches.json");
+    std::fs::write(&channels_json, text)
+        .map_err(|e| format!("failed to write {}: {e}", channels_json.display()))?;
+    Ok(patches.len())
+}
+
+fn npy(descr: &str, shape: &[usize], values: impl Iterator<Item = f32>) -> Vec<u8> {
+    let mut data = npy_header(descr, shape);
+    for v in values {
+        data.extend(v.to_le_bytes());
+    }
+    data
+}
+
+/// a zip of `<name>.npy` entries, deflated
+fn write_npz(path: &Path, arrays: &[(&str, Vec<u8>)]) -> Result<(), String> {
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let write = || -> Result<(), Box<dyn std::error::Error>> {
+        let mut zip = ZipWriter::new(BufWriter::new(File::create(path)?));
+        for (name, data) in arrays {
+            zip.start_file(format!("{name}.npy"), options)?;
+            zip.write_all(data)?;
+        }
+        zip.finish()?.flush()?;
+        Ok(())
+    };
+    write().map_err(|e| format!("failed to write {}: {e}", path.display()))
+}
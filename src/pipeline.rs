@@ -0,0 +1,426 @@
+//! Pipelined batches: the cases of a batch flow through a loading, a mapping and a writing
+//! stage, each on a thread of its own and handing cases on over bounded channels. While one case
+//! is mapped the next is read and the one before is written, so once the pipeline is full a case
+//! takes about as long as its slowest stage rather than the sum of the three, which pays off
+//! most when the inputs and outputs sit on network storage.
+//!
+//! A stage done with a case waits for the next stage to take it, so no more than three cases
+//! are held in memory at once. Cases run in this process, unlike the child processes of
+//! [`crate::batch`], so they can't be timed out or killed for their memory. A case that fails with an error is passed on as
+//! failed and the others carry on, but a crash ends the batch.
+
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, sync_channel};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use array_lib::ArrayDim;
+use glcm::ui::MapOpts;
+
+use crate::archive::{self, encrypt_dir, ArchiveKey};
+use crate::batch::Case;
+use crate::discretize::{integer_levels, level_indices, levels_to_f64, Binning};
+use crate::failure::{self, FailureKind, Stage};
+use crate::filter::{FilterUse, FilteredImage};
+use crate::format::format_of;
+use crate::header::voxel_spacing;
+use crate::history;
+use crate::io::{
+    format_names, input_stem, is_in_directory, output_path, scrubbed_header, write_volume, Header,
+};
+use crate::mapper::{map_features, FeatureMaps};
+use crate::progress::Progress;
+use crate::provenance::{OptionsTag, Provenance};
+use crate::scene::write_slicer_scene;
+use crate::staging::{RetryPolicy, Staging};
+use crate::tensor::write_tensor;
+use crate::texture::{selected_features, Feature, TextureOpts};
+
+/// why a case didn't make it through the pipeline
+#[derive(Debug, Clone, PartialEq)]
+pub struct StageFailure {
+    pub stage: Stage,
+    pub message: String,
+}
+
+impl StageFailure {
+    pub fn new(stage: Stage, message: impl Into<String>) -> Self {
+        StageFailure {
+            stage,
+            message: message.into(),
+        }
+    }
+
+    /// the kind of failure a run of the case on its own would have exited with
+    pub fn kind(&self) -> FailureKind {
+        self.stage.failure_kind()
+    }
+}
+
+/// the three stages every item of a pipeline goes through, each called from its own thread
+pub trait Stages: Sync {
+    type Item: Send;
+    type Loaded: Send;
+    type Mapped: Send;
+
+    fn load(&self, item: &Self::Item) -> Result<Self::Loaded, StageFailure>;
+
+    fn map(&self, item: &Self::Item, loaded: Self::Loaded) -> Result<Self::Mapped, StageFailure>;
+
+    fn write(&self, item: &Self::Item, mapped: Self::Mapped) -> Result<(), StageFailure>;
+}
+
+/// runs every item through the stages, with `done` called on the calling thread for each item
+/// in the order given, with its result and how long it took from the start of its loading
+pub fn run<S: Stages>(
+    stages: &S,
+    items: Vec<S::Item>,
+    mut done: impl FnMut(S::Item, Result<(), StageFailure>, Duration),
+) {
+    // rendezvous channels, a stage done with an item waits for the next stage to take it
+    let (to_map, loaded) = sync_channel(0);
+    let (to_write, mapped) = sync_channel(0);
+    let (to_done, written) = channel();
+    thread::scope(|s| {
+        s.spawn(move || {
+            for item in items {
+                let start = Instant::now();
+                let result = stages.load(&item);
+                if to_map.send((item, start, result)).is_err() {
+                    break;
+                }
+            }
+        });
+        s.spawn(move || {
+            for (item, start, result) in loaded {
+                let result = result.and_then(|loaded| stages.map(&item, loaded));
+                if to_write.send((item, start, result)).is_err() {
+                    break;
+                }
+            }
+        });
+        s.spawn(move || {
+            for (item, start, result) in mapped {
+                let result = result.and_then(|mapped| stages.write(&item, mapped));
+                if to_done.send((item, start.elapsed(), result)).is_err() {
+                    break;
+                }
+            }
+        });
+        for (item, elapsed, result) in written {
+            done(item, result, elapsed);
+        }
+    });
+}
+
+/// the options every case of a pipelined batch is mapped and written with
+pub struct CaseStages {
+    pub opts: MapOpts,
+    pub texture: TextureOpts,
+    pub binning: Binning,
+    /// bin inputs already quantized to integer levels like any other instead of using their
+    /// levels as the bins
+    pub rebin_integer_input: bool,
+    /// let outputs be written to a directory holding an input
+    pub allow_in_place: bool,
+    pub scrub_phi: bool,
+    pub slicer_scene: bool,
+    pub export_tensor: bool,
+    pub options_tag: Option<OptionsTag>,
+    pub archive_key: Option<ArchiveKey>,
+    /// where outputs are staged before they are moved into the output directory, inside it if
+    /// None, or in the temp directory for encrypted outputs
+    pub stage_root: Option<PathBuf>,
+    pub retry: RetryPolicy,
+    /// record every case in the run history
+    pub history: bool,
+}
+
+impl CaseStages {
+    /// cases written with nothing but their maps and provenance, recorded in the run history
+    pub fn new(opts: MapOpts, texture: TextureOpts, binning: Binning) -> Self {
+        CaseStages {
+            opts,
+            texture,
+            binning,
+            rebin_integer_input: false,
+            allow_in_place: false,
+            scrub_phi: false,
+            slicer_scene: false,
+            export_tensor: false,
+            options_tag: None,
+            archive_key: None,
+            stage_root: None,
+            retry: RetryPolicy::default(),
+            history: true,
+        }
+    }
+}
+
+/// a case read and binned, ready to map
+pub struct LoadedCase {
+    opts: MapOpts,
+    /// the texture options with the voxel spacing of the case
+    texture: TextureOpts,
+    binning: Binning,
+    vol: Vec<f64>,
+    /// the intensities `vol` was binned from, for the families of the intensities
+    intensities: Option<Vec<f64>>,
+    images: Vec<FilteredImage>,
+    mask: Option<Vec<f64>>,
+    dims: ArrayDim,
+    header: Header,
+}
+
+/// the maps of a case, ready to write
+pub struct MappedCase {
+    maps: FeatureMaps,
+    features: Vec<(Feature, String)>,
+    header: Header,
+    provenance: Provenance,
+    history_run: Option<i64>,
+}
+
+impl Stages for CaseStages {
+    type Item = Case;
+    type Loaded = LoadedCase;
+    type Mapped = MappedCase;
+
+    fn load(&self, case: &Case) -> Result<LoadedCase, StageFailure> {
+        failure::set_thread_stage(Stage::LoadInput);
+        if !self.allow_in_place {
+            let mut inputs = std::iter::once(&case.input_vol).chain(&case.mask);
+            if let Some(input) = inputs.find(|input| is_in_directory(input, &case.output_dir)) {
+                return Err(StageFailure::new(
+                    Stage::Validation,
+                    format!(
+                        "the output directory holds the input {}, pass --allow-in-place to write \
+                         there anyway",
+                        input.display()
+                    ),
+                ));
+            }
+        }
+        // the mask is read while the volume is
+        let (vol, mask) = thread::scope(|s| {
+            let mask = case.mask.as_ref().map(|mask| {
+                s.spawn(move || {
+                    failure::set_thread_stage(Stage::LoadMask);
+                    read(mask)
+                })
+            });
+            let vol = read(&case.input_vol);
+            let mask = mask.map(|h| h.join().unwrap_or_else(|e| std::panic::resume_unwind(e)));
+            (vol, mask)
+        });
+        let (vol, dims, header) = vol.map_err(|e| StageFailure::new(Stage::LoadInput, e))?;
+        let mask = match mask.transpose() {
+            Ok(Some((_, mask_dims, _))) if mask_dims.shape_ns() != dims.shape_ns() => {
+                return Err(StageFailure::new(
+                    Stage::LoadMask,
+                    "input volume and mask must have the same shape",
+                ));
+            }
+            Ok(mask) => mask.map(|(mask, ..)| mask),
+            Err(e) => return Err(StageFailure::new(Stage::LoadMask, e)),
+        };
+
+        let mut opts = self.opts.clone();
+        let mut binning = self.binning;
+        let texture = TextureOpts {
+            voxel_spacing: voxel_spacing(&case.input_vol),
+            ..self.texture.clone()
+        };
+        // filters see the intensities before binning
+        let spacing = texture.voxel_spacing.unwrap_or([1.; 3]);
+        let mut images = texture.filters.apply(&vol, &dims, spacing);
+        let levels = if self.rebin_integer_input
+            || binning.needs_fit()
+            || self.texture.discretization.is_local()
+        {
+            None
+        } else {
+            integer_levels(&vol)
+        };
+        // the families of the intensities need them next to any gray levels binned here
+        let keep = texture.maps_intensities();
+        let (vol, intensities) = if let Some(levels) = levels {
+            opts.n_bins = levels.len();
+            binning.n_bins = levels.len();
+            (level_indices(&vol, &levels), keep.then_some(vol))
+        } else if binning.needs_fit() {
+            let levels = binning
+                .fit(&vol, mask.as_deref())
+                .map_err(|e| StageFailure::new(Stage::LoadInput, e))?;
+            opts.n_bins = binning.n_bins;
+            (levels_to_f64(&levels), keep.then_some(vol))
+        } else if !binning.is_native() {
+            (
+                levels_to_f64(&binning.discretize(&vol)),
+                keep.then_some(vol),
+            )
+        } else {
+            (vol, None)
+        };
+        if self.texture.filters.usage == FilterUse::Map && !binning.is_native() {
+            for image in &mut images {
+                image.bin(levels_to_f64(&binning.discretize(&image.data)), keep);
+            }
+        }
+        Ok(LoadedCase {
+            opts,
+            texture,
+            binning,
+            vol,
+            intensities,
+            images,
+            mask,
+            dims,
+            header,
+        })
+    }
+
+    fn map(&self, case: &Case, loaded: LoadedCase) -> Result<MappedCase, StageFailure> {
+        failure::set_thread_stage(Stage::Compute);
+        let LoadedCase {
+            opts,
+            texture,
+            binning,
+            vol,
+            intensities,
+            images,
+            mask,
+            dims,
+            header,
+        } = loaded;
+        let mut provenance = Provenance::new(
+            "radmap",
+            &case.input_vol,
+            case.mask.as_deref(),
+            &opts,
+            &texture,
+            binning,
+        );
+        provenance.phi_scrubbed = self.scrub_phi;
+        let history_run = self
+            .history
+            .then(|| history::start_run(&provenance, &case.output_dir))
+            .flatten();
+        let features = selected_features(&opts, &texture);
+        let maps = map_features(
+            opts,
+            &texture,
+            vol,
+            intensities,
+            images,
+            mask,
+            dims,
+            &Progress::default(),
+        )
+        .expect("pipelined cases are never cancelled");
+        Ok(MappedCase {
+            maps,
+            features,
+            header,
+            provenance,
+            history_run,
+        })
+    }
+
+    fn write(&self, case: &Case, mapped: MappedCase) -> Result<(), StageFailure> {
+        failure::set_thread_stage(Stage::WriteOutput);
+        let written = self.write_outputs(case, &mapped);
+        if let Some(id) = mapped.history_run {
+            match &written {
+                Ok(outputs) => history::finish_run(id, &mapped.provenance, outputs),
+                Err(e) => history::fail_run(id, FailureKind::WriteFailure, e),
+            }
+        }
+        written
+            .map(|_| ())
+            .map_err(|e| StageFailure::new(Stage::WriteOutput, e))
+    }
+}
+
+impl CaseStages {
+    /// writes the maps of a case and everything that goes with them, returning the files
+    /// written
+    fn write_outputs(&self, case: &Case, mapped: &MappedCase) -> Result<Vec<PathBuf>, String> {
+        let stem = input_stem(&case.input_vol);
+        let (output_dir, stem) = match self.options_tag {
+            Some(tag) => tag.apply(&case.output_dir, &stem, &mapped.provenance.options_hash()),
+            None => (case.output_dir.clone(), stem),
+        };
+        if !output_dir.is_dir() {
+            std::fs::create_dir_all(&output_dir)
+                .map_err(|e| format!("failed to create {}: {e}", output_dir.display()))?;
+        }
+        let stage_root = match self.archive_key {
+            Some(_) => Some(archive::stage_root(self.stage_root.as_deref())),
+            None => self.stage_root.clone(),
+        };
+        let mut staging = Staging::new(&output_dir, stage_root.as_deref())?;
+        staging.protect(&case.input_vol);
+        if let Some(mask) = &case.mask {
+            staging.protect(mask);
+        }
+        if let Err(e) = self.stage_outputs(case, mapped, &staging, &stem) {
+            // nothing of a case that failed to write is left staged
+            staging.discard();
+            return Err(e);
+        }
+        staging.commit(&self.retry)
+    }
+
+    /// writes the maps of a case and everything that goes with them into the staging directory
+    fn stage_outputs(
+        &self,
+        case: &Case,
+        mapped: &MappedCase,
+        staging: &Staging,
+        stem: &OsStr,
+    ) -> Result<(), String> {
+        let scrubbed;
+        let header = if self.scrub_phi {
+            scrubbed = scrubbed_header(&case.input_vol)?;
+            &scrubbed
+        } else {
+            &mapped.header
+        };
+        let dims = mapped.maps.dims();
+        let outputs = mapped.maps.outputs(&mapped.features);
+        for (suffix, vol) in &outputs {
+            write_volume(output_path(staging.dir(), stem, suffix), vol, dims, header)?;
+        }
+        if self.export_tensor {
+            let shape = [0, 1, 2].map(|d| dims.shape_ns()[d]);
+            let spacing = voxel_spacing(&case.input_vol);
+            write_tensor(staging.dir(), stem, &outputs, shape, spacing)?;
+        }
+        if self.slicer_scene {
+            let suffixes: Vec<String> = outputs.iter().map(|(suffix, _)| suffix.clone()).collect();
+            write_slicer_scene(
+                staging.dir(),
+                stem,
+                &case.input_vol,
+                case.mask.as_deref(),
+                &suffixes,
+            )?;
+        }
+        mapped.provenance.write(staging.dir(), stem)?;
+        if let Some(key) = &self.archive_key {
+            encrypt_dir(staging.dir(), stem, key)?;
+        }
+        Ok(())
+    }
+}
+
+/// reads a volume of any of the formats, an error rather than a panic for a file that can't be
+/// read
+fn read(path: &Path) -> Result<(Vec<f64>, ArrayDim, Header), String> {
+    let Some(format) = format_of(path) else {
+        return Err(format!("{} is not a {}", path.display(), format_names()));
+    };
+    format.read(path)
+}
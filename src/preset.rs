@@ -0,0 +1,268 @@
+//! Presets: the options that decide the values of the maps, saved to a toml file so they can be
+//! reused and shared. The file carries the version of its layout and older layouts are migrated
+//! when read, so presets keep working as options are added, see [`crate::schema`].
+//!
+//! Version 2, written by radmap:
+//!
+//! ```toml
+//! format_version = 2
+//! bin_edges = "half-open"
+//! rebin_integer_input = false
+//!
+//! [options]
+//! n_bins = 32
+//! kernel_radius = 1
+//!
+//! [options.features]
+//! contrast = "contrast"
+//! glrlm_run_entropy = "glrlm_run_entropy"
+//! ```
+//!
+//! An optional `bin_width` bins the input with a fixed width instead of into `n_bins` bins, and
+//! optional `bin_min` and `bin_max` fix the intensities binned. `bin_from_mask = true` takes the
+//! range from the voxels inside the mask and `bin_rank = true` bins the ranks of the intensities
+//! in place of the intensities. `discretization = "local"` under `[options]` bins each
+//! kernel on its own, `glcm_angles = "in-plane"` counts the co-occurrence matrices within slices
+//! only and `glcm_distances = [1, 2, 3]` pairs voxels up to three steps apart in them, combined as
+//! `glcm_aggregation` says, and `glcm_direction_aggregation = "max"` keeps the largest feature
+//! over the directions in place of their mean. `glcm_weighting = "inverse"` weighs the pairs of a
+//! merged matrix by one over their distance in mm. `slice_wise = true` keeps every kernel within
+//! its axial slice and `kernel_radii = [3, 3, 1]` gives the kernel a radius of its own along x, y
+//! and z.
+//! `kernel_shape = "sphere:4.5"` makes it a sphere of 4.5 mm instead.
+//!
+//! Version 1 has no `format_version` key and is the flat layout of run configuration files and
+//! protocols, with the features as a list: `n_bins = 32`, `feature = ["contrast"]`. Keys of
+//! version 1 files that are not map options, like `no_progress_bar`, are ignored.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+use crate::discretize::BinEdges;
+use crate::options::MapOptsBuilder;
+use crate::schema::{Format, UNVERSIONED};
+use crate::texture::{Family, Feature};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Preset {
+    pub format_version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub bin_edges: BinEdges,
+    #[serde(default)]
+    pub rebin_integer_input: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bin_width: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bin_min: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bin_max: Option<f64>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub bin_from_mask: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub bin_rank: bool,
+    pub options: MapOptsBuilder,
+}
+
+impl Preset {
+    pub fn new(options: MapOptsBuilder, bin_edges: BinEdges, rebin_integer_input: bool) -> Self {
+        Preset {
+            format_version: Format::Preset.current(),
+            name: None,
+            bin_edges,
+            rebin_integer_input,
+            bin_width: None,
+            bin_min: None,
+            bin_max: None,
+            bin_from_mask: false,
+            bin_rank: false,
+            options,
+        }
+    }
+
+    /// reads a preset of any version, checking its options
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let table: Table = text
+            .parse()
+            .map_err(|e| format!("failed to parse preset: {e}"))?;
+        let table = migrate(table)?;
+        let preset: Preset = Value::Table(table)
+            .try_into()
+            .map_err(|e| format!("invalid preset: {e}"))?;
+        preset
+            .options
+            .validate()
+            .map_err(|e| format!("invalid preset: {e}"))?;
+        Ok(preset)
+    }
+
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        Self::parse(&text).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).expect("presets are always representable as toml")
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_toml())
+            .map_err(|e| format!("failed to write {}: {e}", path.display()))
+    }
+}
+
+/// brings a preset of any version up to the current one
+fn migrate(mut table: Table) -> Result<Table, String> {
+    // the first version 2 presets called the key `version`
+    if !table.contains_key("format_version")
+        && let Some(version) = table.remove("version")
+    {
+        table.insert("format_version".to_string(), version);
+    }
+    let version = match table.get("format_version") {
+        None => UNVERSIONED,
+        Some(Value::Integer(v)) => u32::try_from(*v).unwrap_or(0),
+        Some(_) => return Err("format_version of the preset must be an integer".to_string()),
+    };
+    Format::Preset.check(version, "the preset")?;
+    if version < 2 {
+        table = migrate_v1(table)?;
+    }
+    Ok(table)
+}
+
+fn migrate_v1(v1: Table) -> Result<Table, String> {
+    // option names could be written as on the command line
+    let mut v1: Table = v1
+        .into_iter()
+        .map(|(k, v)| (k.replace('-', "_"), v))
+        .collect();
+
+    let mut features = Table::new();
+    if v1.remove("all_features") == Some(Value::Boolean(true)) {
+        for f in Family::Glcm.features() {
+            features.insert(f.to_string(), Value::String(f.to_string()));
+        }
+    }
+    let listed = match v1.remove("feature") {
+        None => vec![],
+        Some(Value::Array(listed)) => listed,
+        Some(name) => vec![name],
+    };
+    for name in listed {
+        let f = name
+            .as_str()
+            .and_then(|n| n.parse::<Feature>().ok())
+            .ok_or_else(|| format!("unknown feature {name} in the preset"))?;
+        features.insert(f.to_string(), Value::String(f.to_string()));
+    }
+
+    let mut options = Table::new();
+    for key in ["n_bins", "kernel_radius", "max_threads"] {
+        if let Some(value) = v1.remove(key) {
+            options.insert(key.to_string(), value);
+        }
+    }
+    options.insert("features".to_string(), Value::Table(features));
+
+    let mut v2 = Table::new();
+    v2.insert("format_version".to_string(), Value::Integer(2));
+    for key in ["name", "bin_edges", "rebin_integer_input"] {
+        if let Some(value) = v1.remove(key) {
+            v2.insert(key.to_string(), value);
+        }
+    }
+    v2.insert("options".to_string(), Value::Table(options));
+    Ok(v2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feature(name: &str) -> Feature {
+        name.parse().unwrap()
+    }
+
+    fn with_features(names: &[&str]) -> MapOptsBuilder {
+        names
+            .iter()
+            .fold(MapOptsBuilder::new(), |b, n| b.feature(feature(n), *n))
+    }
+
+    #[test]
+    fn version_1_presets_migrate_to_the_same_options() {
+        let all_glcm = Family::Glcm
+            .features()
+            .into_iter()
+            .fold(with_features(&["glrlm_run_entropy"]), |b, f| {
+                b.feature(f, f.to_string())
+            });
+        let named = Preset {
+            name: Some("ct".to_string()),
+            ..Preset::new(all_glcm, BinEdges::HalfOpen, false)
+        };
+        // version 1 files, then the preset they are read as
+        let table = [
+            (
+                "n_bins = 16\nkernel_radius = 2\nfeature = [\"contrast\", \"glrlm_run_entropy\"]\n",
+                Preset::new(
+                    with_features(&["contrast", "glrlm_run_entropy"])
+                        .n_bins(16)
+                        .kernel_radius(2),
+                    BinEdges::HalfOpen,
+                    false,
+                ),
+            ),
+            (
+                "n-bins = 8\nmax-threads = 4\nfeature = \"contrast\"\nbin-edges = \"inclusive\"\n\
+                 rebin-integer-input = true\nno-progress-bar = true\n",
+                Preset::new(
+                    with_features(&["contrast"]).n_bins(8).max_threads(Some(4)),
+                    BinEdges::Inclusive,
+                    true,
+                ),
+            ),
+            (
+                "name = \"ct\"\nall_features = true\nfeature = [\"glrlm_run_entropy\"]\n",
+                named,
+            ),
+        ];
+        for (v1, expected) in table {
+            let migrated = Preset::parse(v1).unwrap();
+            assert_eq!(migrated, expected, "{v1}");
+            // and read back the same once written at the current version
+            assert_eq!(Preset::parse(&migrated.to_toml()).unwrap(), expected, "{v1}");
+        }
+    }
+
+    #[test]
+    fn the_first_version_2_key_is_read() {
+        let text = "version = 2\n[options]\nn_bins = 8\n[options.features]\ncontrast = \"c\"\n";
+        let expected = Preset::new(
+            MapOptsBuilder::new().n_bins(8).feature(feature("contrast"), "c"),
+            BinEdges::HalfOpen,
+            false,
+        );
+        assert_eq!(Preset::parse(text).unwrap(), expected);
+    }
+
+    #[test]
+    fn broken_and_newer_presets_are_refused() {
+        for text in [
+            "feature = [\"not_a_feature\"]\n",
+            "format_version = \"2\"\n",
+            "format_version = 3\n[options]\n",
+            "format_version = 0\n",
+            // migrated options are checked like any other
+            "n_bins = 0\nfeature = [\"contrast\"]\n",
+        ] {
+            assert!(Preset::parse(text).is_err(), "{text}");
+        }
+    }
+}
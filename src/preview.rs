@@ -0,0 +1,294 @@
+//! Running statistics of each feature while a map is computed, so settings that give all zeros or
+//! NaNs show up within the first minute rather than after the whole volume is done.
+//!
+//! The mapper only hands back the maps once every voxel is done, so the statistics come from a
+//! second, single threaded mapper working through small blocks sampled across the volume. Each
+//! block carries enough surrounding voxels for the kernel, plus the minimum and maximum of the
+//! whole volume in two corners out of reach of the kernel, so its voxels are binned and mapped as
+//! in the full run.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use array_lib::ArrayDim;
+use glcm::ui::MapOpts;
+
+use crate::mapper::compute;
+use crate::texture::{selected_features, TextureOpts};
+
+/// number of blocks sampled from the volume
+const N_BLOCKS: usize = 32;
+/// side of the voxels of a block that are mapped
+const BLOCK_SIDE: usize = 6;
+
+/// min, max and mean of the values of a feature seen so far
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeatureStats {
+    /// number of values, NaNs included
+    pub n: usize,
+    pub n_nan: usize,
+    pub min: f64,
+    pub max: f64,
+    sum: f64,
+}
+
+impl Default for FeatureStats {
+    fn default() -> Self {
+        FeatureStats {
+            n: 0,
+            n_nan: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            sum: 0.,
+        }
+    }
+}
+
+impl FeatureStats {
+    pub fn add(&mut self, x: f64) {
+        self.n += 1;
+        if x.is_finite() {
+            self.min = self.min.min(x);
+            self.max = self.max.max(x);
+            self.sum += x;
+        } else {
+            self.n_nan += 1;
+        }
+    }
+
+    /// mean of the finite values, None if there are none
+    pub fn mean(&self) -> Option<f64> {
+        let n_finite = self.n - self.n_nan;
+        (n_finite > 0).then(|| self.sum / n_finite as f64)
+    }
+
+    /// fraction of the values that are NaN or infinite
+    pub fn nan_fraction(&self) -> f64 {
+        if self.n == 0 {
+            0.
+        } else {
+            self.n_nan as f64 / self.n as f64
+        }
+    }
+
+    /// there are finite values and they are all the same
+    pub fn is_flat(&self) -> bool {
+        self.n > self.n_nan && self.max == self.min
+    }
+}
+
+struct Block {
+    data: Vec<f64>,
+    /// the voxels of the block that are mapped
+    mask: Vec<f64>,
+    dims: ArrayDim,
+}
+
+#[derive(Default)]
+struct State {
+    voxels: usize,
+    features: Vec<(String, FeatureStats)>,
+}
+
+/// statistics of a preview running next to a map. Clones share the same statistics
+#[derive(Clone)]
+pub struct Preview {
+    state: Arc<Mutex<State>>,
+    stop: Arc<AtomicBool>,
+    blocks_done: Arc<AtomicUsize>,
+    n_blocks: usize,
+}
+
+impl Preview {
+    /// starts mapping blocks sampled from `vol`, which must be the volume exactly as it is given
+    /// to the mapper. Features of the intensities are previewed over `vol` too when it holds gray
+    /// levels, close enough to tell a degenerate map. None if the volume is too small to sample, in which case the full map
+    /// doesn't take long anyway
+    pub fn start(
+        opts: &MapOpts,
+        texture: &TextureOpts,
+        vol: &[f64],
+        mask: Option<&[f64]>,
+        dims: &ArrayDim,
+    ) -> Option<Preview> {
+        let blocks = sample_blocks(vol, mask, dims, opts.kernel_radius);
+        if blocks.is_empty() {
+            return None;
+        }
+        let features = selected_features(opts, texture);
+        let preview = Preview {
+            state: Arc::new(Mutex::new(State {
+                voxels: 0,
+                features: features
+                    .iter()
+                    .map(|(_, alias)| (alias.clone(), FeatureStats::default()))
+                    .collect(),
+            })),
+            stop: Arc::default(),
+            blocks_done: Arc::default(),
+            n_blocks: blocks.len(),
+        };
+        let mut opts = opts.clone();
+        opts.max_threads = Some(1);
+        let texture = texture.clone();
+        let t_preview = preview.clone();
+        std::thread::spawn(move || {
+            for block in blocks {
+                if t_preview.stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                let stride = block.dims.numel();
+                let selected: Vec<usize> = (0..stride).filter(|&i| block.mask[i] > 0.).collect();
+                let maps = compute(
+                    opts.clone(),
+                    &texture,
+                    block.data,
+                    None,
+                    Some(block.mask),
+                    block.dims,
+                    Arc::new(AtomicUsize::new(0)),
+                );
+                let mut state = t_preview.state.lock().unwrap();
+                state.voxels += selected.len();
+                for ((f, _), (_, stats)) in features.iter().zip(state.features.iter_mut()) {
+                    let Some(map) = maps.map(*f) else {
+                        continue;
+                    };
+                    for &i in &selected {
+                        stats.add(map[i] as f64);
+                    }
+                }
+                drop(state);
+                t_preview.blocks_done.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        Some(preview)
+    }
+
+    /// stops mapping blocks, keeping the statistics so far
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// number of voxels the statistics are over
+    pub fn voxels(&self) -> usize {
+        self.state.lock().unwrap().voxels
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.blocks_done.load(Ordering::Relaxed) == self.n_blocks
+    }
+
+    /// blocks until the statistics are over at least `voxels` voxels or every block is mapped.
+    /// False if the preview was stopped first
+    pub fn wait_for(&self, voxels: usize) -> bool {
+        while self.voxels() < voxels && !self.is_finished() {
+            if self.stop.load(Ordering::Relaxed) {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        !self.stop.load(Ordering::Relaxed)
+    }
+
+    /// what is wrong with the first feature that is NaN in at least `max_nan_fraction` of the
+    /// voxels so far, or the same in all of them. None if every feature looks fine
+    pub fn degenerate_feature(&self, max_nan_fraction: f64) -> Option<String> {
+        self.features().into_iter().find_map(|(feature, stats)| {
+            if stats.n > 0 && stats.nan_fraction() >= max_nan_fraction {
+                Some(format!(
+                    "{feature} is NaN in {:.1}% of {} sampled voxels",
+                    100. * stats.nan_fraction(),
+                    stats.n
+                ))
+            } else if stats.is_flat() {
+                Some(format!(
+                    "{feature} is {} in all {} sampled voxels",
+                    stats.min, stats.n
+                ))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// feature alias and statistics of each feature, sorted by alias
+    pub fn features(&self) -> Vec<(String, FeatureStats)> {
+        self.state.lock().unwrap().features.clone()
+    }
+}
+
+/// blocks of `BLOCK_SIDE` voxels a side spread evenly over the voxels in the mask, padded by the
+/// kernel radius plus one so the corners are out of reach of the kernel of any mapped voxel
+fn sample_blocks(
+    vol: &[f64],
+    mask: Option<&[f64]>,
+    dims: &ArrayDim,
+    kernel_radius: usize,
+) -> Vec<Block> {
+    let shape = &dims.shape_ns()[0..3];
+    let pad = kernel_radius + 1;
+    let side = BLOCK_SIDE + 2 * pad;
+    let n_vox: usize = shape.iter().product();
+    if shape.iter().any(|&n| n < side) || n_vox < 8 * N_BLOCKS * side.pow(3) {
+        return vec![];
+    }
+    let in_mask = |i: usize| mask.is_none_or(|m| m[i] > 0.);
+    let n_masked = (0..n_vox).filter(|&i| in_mask(i)).count();
+    if n_masked == 0 {
+        return vec![];
+    }
+
+    let (min, max) = vol[..n_vox]
+        .iter()
+        .filter(|x| x.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &x| {
+            (lo.min(x), hi.max(x))
+        });
+
+    // every n_masked / N_BLOCKS th voxel of the mask is the centre of a block
+    let step = n_masked.div_ceil(N_BLOCKS);
+    let centres: Vec<usize> = (0..n_vox).filter(|&i| in_mask(i)).step_by(step).collect();
+    // in bit reversed order, so the first few blocks are already spread over the volume
+    let mut order: Vec<usize> = (0..centres.len()).collect();
+    order.sort_by_key(|k| k.reverse_bits());
+
+    order
+        .into_iter()
+        .map(|k| centres[k])
+        .map(|centre| {
+            let c = [
+                centre % shape[0],
+                centre / shape[0] % shape[1],
+                centre / (shape[0] * shape[1]),
+            ];
+            let origin: Vec<usize> = (0..3)
+                .map(|d| {
+                    c[d].saturating_sub(pad + BLOCK_SIDE / 2)
+                        .min(shape[d] - side)
+                })
+                .collect();
+            let mut data = Vec::with_capacity(side.pow(3));
+            let mut block_mask = Vec::with_capacity(side.pow(3));
+            for z in 0..side {
+                for y in 0..side {
+                    for x in 0..side {
+                        let i =
+                            origin[0] + x + shape[0] * (origin[1] + y + shape[1] * (origin[2] + z));
+                        data.push(vol[i]);
+                        let inner = [x, y, z].iter().all(|&j| j >= pad && j < pad + BLOCK_SIDE);
+                        block_mask.push(if inner && in_mask(i) { 1. } else { 0. });
+                    }
+                }
+            }
+            data[0] = min;
+            data[side.pow(3) - 1] = max;
+            Block {
+                data,
+                mask: block_mask,
+                dims: ArrayDim::from_shape(&[side, side, side]),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,138 @@
+//! Progress of a run, counted separately for the computation and for writing the outputs, so the
+//! front ends can show which stage a run is in and how far along each is.
+
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunStage {
+    Idle,
+    Loading,
+    Computing,
+    Writing,
+    Done,
+}
+
+impl RunStage {
+    const ALL: [RunStage; 5] = [
+        RunStage::Idle,
+        RunStage::Loading,
+        RunStage::Computing,
+        RunStage::Writing,
+        RunStage::Done,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RunStage::Idle => "idle",
+            RunStage::Loading => "loading inputs",
+            RunStage::Computing => "computing feature maps",
+            RunStage::Writing => "writing outputs",
+            RunStage::Done => "done",
+        }
+    }
+}
+
+/// work done out of a total, shared between the thread doing the work and the one showing it
+#[derive(Debug, Clone, Default)]
+pub struct Counter {
+    done: Arc<AtomicUsize>,
+    total: Arc<AtomicUsize>,
+}
+
+impl Counter {
+    pub fn done(&self) -> usize {
+        self.done.load(Ordering::Relaxed)
+    }
+
+    pub fn total(&self) -> usize {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    pub fn set_total(&self, total: usize) {
+        self.total.store(total, Ordering::Relaxed);
+    }
+
+    pub fn set(&self, done: usize) {
+        self.done.store(done, Ordering::Relaxed);
+    }
+
+    pub fn add(&self, n: usize) {
+        self.done.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// the done count itself, for workers that count into an atomic like the glcm mapper
+    pub fn handle(&self) -> Arc<AtomicUsize> {
+        self.done.clone()
+    }
+
+    /// how far along the work is, None until the total is known
+    pub fn fraction(&self) -> Option<f32> {
+        match self.total() {
+            0 => None,
+            total => Some((self.done() as f64 / total as f64).min(1.) as f32),
+        }
+    }
+}
+
+/// how a run reports its progress to whatever embeds it (GUI, command line, bindings, server)
+/// and how it is asked to stop. Every method defaults to doing nothing
+pub trait ProgressSink: Send + Sync {
+    fn on_stage(&self, _stage: RunStage) {}
+
+    /// `done` out of `total` voxels are mapped
+    fn on_voxels(&self, _done: usize, _total: usize) {}
+
+    /// the map of a feature, by its alias, is written
+    fn on_feature_done(&self, _feature: &str) {}
+
+    /// checked between progress updates. A cancelled run stops reporting and gives no result
+    fn is_cancelled(&self) -> bool {
+        false
+    }
+}
+
+/// voxels computed, output bytes and feature maps written, with the stage the run is in. Clones
+/// share the same counters
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    pub voxels: Counter,
+    /// bytes of map data written
+    pub bytes: Counter,
+    pub features: Counter,
+    stage: Arc<AtomicU8>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Progress {
+    pub fn stage(&self) -> RunStage {
+        RunStage::ALL[self.stage.load(Ordering::Relaxed) as usize]
+    }
+
+    pub fn set_stage(&self, stage: RunStage) {
+        self.stage.store(stage as u8, Ordering::Relaxed);
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+impl ProgressSink for Progress {
+    fn on_stage(&self, stage: RunStage) {
+        self.set_stage(stage);
+    }
+
+    fn on_voxels(&self, done: usize, total: usize) {
+        self.voxels.set_total(total);
+        self.voxels.set(done);
+    }
+
+    fn on_feature_done(&self, _feature: &str) {
+        self.features.add(1);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
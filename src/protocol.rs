@@ -0,0 +1,263 @@
+//! Locked protocols for multi-operator studies. A site admin signs a preset of the options that
+//! decide the values of the maps and places it in the site directory, after which the GUI only
+//! lets users pick inputs and outputs.
+//!
+//! | file in the site directory | contents |
+//! |----------------------------|----------|
+//! | `protocol.toml` | the preset, see below |
+//! | `protocol.toml.sig` | ed25519 signature of `protocol.toml`, from `radmap protocol sign` |
+//! | `protocol.pub` | public key of the site admin, from `radmap protocol keygen` |
+//!
+//! ```toml
+//! name = "lung CT v2"
+//! n_bins = 64
+//! kernel_radius = 2
+//! bin_edges = "half-open"
+//! feature = ["contrast", "energy"]
+//! rebin_integer_input = false
+//! ```
+//!
+//! The signature covers the exact bytes of the file. A protocol that fails to verify blocks every
+//! launch rather than falling back to unlocked settings.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use toml::{Table, Value};
+
+use crate::discretize::BinEdges;
+use crate::options::MapOptsBuilder;
+use crate::texture::Feature;
+
+pub const PROTOCOL_FILE: &str = "protocol.toml";
+pub const PUBLIC_KEY_FILE: &str = "protocol.pub";
+
+/// directory holding the site protocol. `RADMAP_SITE_DIR` overrides the default of
+/// `%PROGRAMDATA%\radmap` on windows and `/etc/radmap` elsewhere
+pub fn site_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("RADMAP_SITE_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    if cfg!(windows) {
+        std::env::var_os("PROGRAMDATA").map(|d| PathBuf::from(d).join("radmap"))
+    } else {
+        Some(PathBuf::from("/etc/radmap"))
+    }
+}
+
+/// the signature file of a protocol, `<protocol>.sig`
+pub fn signature_path(protocol: &Path) -> PathBuf {
+    let mut name = protocol.as_os_str().to_os_string();
+    name.push(".sig");
+    PathBuf::from(name)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Protocol {
+    pub name: String,
+    pub n_bins: usize,
+    pub kernel_radius: usize,
+    pub bin_edges: BinEdges,
+    pub features: Vec<Feature>,
+    pub rebin_integer_input: bool,
+}
+
+impl Protocol {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let table: Table = text
+            .parse()
+            .map_err(|e| format!("failed to parse protocol: {e}"))?;
+        for key in table.keys() {
+            if ![
+                "name",
+                "n_bins",
+                "kernel_radius",
+                "bin_edges",
+                "feature",
+                "rebin_integer_input",
+            ]
+            .contains(&key.as_str())
+            {
+                return Err(format!("{key} can't be part of a protocol"));
+            }
+        }
+        let integer = |key: &str| match table.get(key) {
+            Some(Value::Integer(i)) if *i >= 1 => Ok(*i as usize),
+            Some(_) => Err(format!("{key} of the protocol must be a positive integer")),
+            None => Err(format!("the protocol has no {key}")),
+        };
+        let features = match table.get("feature") {
+            Some(Value::Array(features)) => features
+                .iter()
+                .map(|f| {
+                    f.as_str()
+                        .and_then(|f| Feature::from_str(f).ok())
+                        .ok_or_else(|| format!("unknown feature {f} in the protocol"))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => return Err("the protocol must list its features as feature = [...]".to_string()),
+        };
+        if features.is_empty() {
+            return Err("the protocol has no features".to_string());
+        }
+        let n_bins = integer("n_bins")?;
+        let kernel_radius = integer("kernel_radius")?;
+        MapOptsBuilder::new()
+            .n_bins(n_bins)
+            .kernel_radius(kernel_radius)
+            .features(features.iter().map(|f| (*f, f.to_string())))
+            .validate()
+            .map_err(|e| format!("invalid protocol: {e}"))?;
+        Ok(Protocol {
+            name: table
+                .get("name")
+                .and_then(|n| n.as_str())
+                .unwrap_or("unnamed protocol")
+                .to_string(),
+            n_bins,
+            kernel_radius,
+            bin_edges: match table.get("bin_edges") {
+                Some(edges) => BinEdges::from_str(
+                    edges
+                        .as_str()
+                        .ok_or("bin_edges of the protocol must be a string")?,
+                )?,
+                None => BinEdges::default(),
+            },
+            features,
+            rebin_integer_input: table
+                .get("rebin_integer_input")
+                .and_then(|r| r.as_bool())
+                .unwrap_or(false),
+        })
+    }
+
+    /// reads a protocol after checking its signature, `<protocol>.sig`, against the public key
+    pub fn read_signed(protocol: &Path, public_key: &Path) -> Result<Self, String> {
+        let text = std::fs::read(protocol)
+            .map_err(|e| format!("failed to read {}: {e}", protocol.display()))?;
+        let sig_path = signature_path(protocol);
+        let signature = Signature::from_bytes(&read_key_file::<64>(&sig_path)?);
+        let key = VerifyingKey::from_bytes(&read_key_file::<32>(public_key)?)
+            .map_err(|e| format!("{} is not a valid public key: {e}", public_key.display()))?;
+        key.verify(&text, &signature).map_err(|_| {
+            format!(
+                "the signature of {} doesn't match. Ask the site admin to sign it again",
+                protocol.display()
+            )
+        })?;
+        let text = String::from_utf8(text)
+            .map_err(|_| format!("{} is not a text file", protocol.display()))?;
+        Self::parse(&text).map_err(|e| format!("{}: {e}", protocol.display()))
+    }
+}
+
+/// the protocol in the site directory, or None if the site has none
+pub fn site_protocol() -> Option<Result<Protocol, String>> {
+    let dir = site_dir()?;
+    let protocol = dir.join(PROTOCOL_FILE);
+    protocol
+        .is_file()
+        .then(|| Protocol::read_signed(&protocol, &dir.join(PUBLIC_KEY_FILE)))
+}
+
+/// writes a new signing key to `<stem>.key` and its public key to `<stem>.pub`
+pub fn generate_key(stem: &Path) -> Result<(PathBuf, PathBuf), String> {
+    let mut secret = [0u8; 32];
+    getrandom::fill(&mut secret).map_err(|e| format!("failed to generate a key: {e}"))?;
+    let key = SigningKey::from_bytes(&secret);
+    let key_path = stem.with_extension("key");
+    let pub_path = stem.with_extension("pub");
+    if key_path.exists() {
+        return Err(format!("{} already exists", key_path.display()));
+    }
+    write_key_file(&key_path, &key.to_bytes())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&key_path, std::fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("failed to restrict {}: {e}", key_path.display()))?;
+    }
+    write_key_file(&pub_path, key.verifying_key().as_bytes())?;
+    Ok((key_path, pub_path))
+}
+
+/// signs a protocol, after checking it is valid, and writes `<protocol>.sig`
+pub fn sign(protocol: &Path, key: &Path) -> Result<PathBuf, String> {
+    let text = std::fs::read_to_string(protocol)
+        .map_err(|e| format!("failed to read {}: {e}", protocol.display()))?;
+    Protocol::parse(&text).map_err(|e| format!("{}: {e}", protocol.display()))?;
+    let key = SigningKey::from_bytes(&read_key_file::<32>(key)?);
+    let sig_path = signature_path(protocol);
+    write_key_file(&sig_path, &key.sign(text.as_bytes()).to_bytes())?;
+    Ok(sig_path)
+}
+
+fn read_key_file<const N: usize>(path: &Path) -> Result<[u8; N], String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    STANDARD
+        .decode(text.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| format!("{} is not a valid key or signature file", path.display()))
+}
+
+fn write_key_file(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    std::fs::write(path, format!("{}\n", STANDARD.encode(bytes)))
+        .map_err(|e| format!("failed to write {}: {e}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "name = \"lung CT v2\"\nn_bins = 64\nkernel_radius = 2\nfeature = [\"contrast\", \"energy\"]\n";
+
+    /// a site directory holding a protocol signed with a fresh key
+    fn signed_site(name: &str) -> (PathBuf, PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("radmap_protocol_{name}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let protocol = dir.join(PROTOCOL_FILE);
+        std::fs::write(&protocol, TEXT).unwrap();
+        let (key, public_key) = generate_key(&dir.join("admin")).unwrap();
+        sign(&protocol, &key).unwrap();
+        (dir, protocol, public_key)
+    }
+
+    #[test]
+    fn a_signed_protocol_reads_back() {
+        let (_, protocol, public_key) = signed_site("round_trip");
+        let read = Protocol::read_signed(&protocol, &public_key).unwrap();
+        assert_eq!(read, Protocol::parse(TEXT).unwrap());
+        assert_eq!(read.name, "lung CT v2");
+    }
+
+    #[test]
+    fn an_edited_protocol_is_rejected() {
+        let (_, protocol, public_key) = signed_site("edited");
+        std::fs::write(&protocol, TEXT.replace("n_bins = 64", "n_bins = 32")).unwrap();
+        let e = Protocol::read_signed(&protocol, &public_key).unwrap_err();
+        assert!(e.contains("doesn't match"), "{e}");
+    }
+
+    #[test]
+    fn another_public_key_is_rejected() {
+        let (dir, protocol, _) = signed_site("wrong_key");
+        let (_, other) = generate_key(&dir.join("someone_else")).unwrap();
+        let e = Protocol::read_signed(&protocol, &other).unwrap_err();
+        assert!(e.contains("doesn't match"), "{e}");
+    }
+
+    #[test]
+    fn an_unsigned_protocol_is_rejected() {
+        let (_, protocol, public_key) = signed_site("unsigned");
+        std::fs::remove_file(signature_path(&protocol)).unwrap();
+        let e = Protocol::read_signed(&protocol, &public_key).unwrap_err();
+        assert!(e.contains(".sig"), "{e}");
+    }
+}
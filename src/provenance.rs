@@ -0,0 +1,776 @@
+//! Provenance sidecar written next to the feature maps, recording how they were produced so
+//! results from different runs and tools can be compared.
+//!
+//! Nothing radmap does is random, the volumes it samples are evenly spaced, so runs need no seed
+//! to be repeated exactly. The reproducibility hash covers the contents of the input and mask and
+//! every option that decides the maps: two runs with the same hash and radmap version give the
+//! same maps.
+//!
+//! The start of the options hash can also tag the outputs of a run, in their names or as a
+//! directory of their own, see [`OptionsTag`], so maps of different protocols can share an output
+//! directory and runs of other options are never taken for up to date.
+
+use std::ffi::{OsStr, OsString};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use glcm::ui::MapOpts;
+use serde::Serialize;
+
+use crate::cache::Fnv1a;
+use crate::discretize::{BinEdges, Binning, DiscretizationScope};
+use crate::filter::FilterUse;
+use crate::io::{feature_suffix, find_output_volume, output_path};
+use crate::schema::{json_version, Format};
+use crate::texture::cooccurrence::{
+    Angles, DirectionAggregation, DistanceAggregation, DistanceWeighting, DEFAULT_DISTANCE,
+};
+use crate::texture::{selected_features, Family, KernelShape, TextureOpts};
+
+const SIDECAR_SUFFIX: &str = "provenance.json";
+
+/// characters of the options hash kept in an [`OptionsTag`]
+pub const OPTIONS_TAG_LEN: usize = 8;
+
+/// where the options hash of a run goes in its outputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionsTag {
+    /// after the input name, `<input>_<hash>_<feature>`
+    Name,
+    /// in a directory of its own, `<output dir>/<hash>/<input>_<feature>`
+    Dir,
+}
+
+impl OptionsTag {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OptionsTag::Name => "name",
+            OptionsTag::Dir => "dir",
+        }
+    }
+
+    /// the stem and directory of the outputs of a run with these options
+    pub fn apply(
+        &self,
+        output_dir: &Path,
+        stem: &OsStr,
+        options_hash: &str,
+    ) -> (PathBuf, OsString) {
+        let tag = &options_hash[..OPTIONS_TAG_LEN.min(options_hash.len())];
+        match self {
+            OptionsTag::Name => {
+                let mut tagged = stem.to_os_string();
+                tagged.push(format!("_{tag}"));
+                (output_dir.to_path_buf(), tagged)
+            }
+            OptionsTag::Dir => (output_dir.join(tag), stem.to_os_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for OptionsTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for OptionsTag {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "name" => Ok(OptionsTag::Name),
+            "dir" => Ok(OptionsTag::Dir),
+            _ => Err(format!("unknown options tag {s}, expected name or dir")),
+        }
+    }
+}
+
+/// the provenance sidecar as it is written, see [`Provenance::to_json`]
+#[derive(Serialize)]
+struct Sidecar<'a> {
+    format_version: u32,
+    radmap_version: &'static str,
+    app: &'a str,
+    created_unix_s: u64,
+    input: Option<String>,
+    mask: Option<String>,
+    input_hash: Option<&'a str>,
+    mask_hash: Option<&'a str>,
+    reproducibility_hash: Option<String>,
+    phi_scrubbed: bool,
+    #[serde(flatten)]
+    options: OptionsFields<'a>,
+    discretization: SidecarDiscretization,
+    features: &'a [String],
+}
+
+#[derive(Serialize)]
+struct SidecarDiscretization {
+    n_bins: usize,
+    bin_edges: BinEdges,
+    bin_edges_convention: &'static str,
+    bin_width: Option<f64>,
+    bin_min: Option<f64>,
+    bin_max: Option<f64>,
+    bin_from_mask: bool,
+    bin_rank: bool,
+    scope: DiscretizationScope,
+}
+
+/// the options of a run as recorded in the run history, see [`Provenance::options_json`]
+#[derive(Serialize)]
+struct OptionsJson<'a> {
+    #[serde(flatten)]
+    options: OptionsFields<'a>,
+    n_bins: usize,
+    bin_edges: BinEdges,
+    bin_width: Option<f64>,
+    bin_min: Option<f64>,
+    bin_max: Option<f64>,
+    bin_from_mask: bool,
+    bin_rank: bool,
+    discretization: DiscretizationScope,
+    features: &'a [String],
+}
+
+/// the options written both to the sidecar and to the run history, under the names they have in
+/// the sidecar
+#[derive(Serialize)]
+struct OptionsFields<'a> {
+    kernel_radius: usize,
+    kernel_radii: Option<[usize; 3]>,
+    kernel_shape: String,
+    slice_wise: bool,
+    gldm_alpha: Option<usize>,
+    ngldm_alpha: Option<usize>,
+    ngldm_distance: Option<usize>,
+    angles: Option<Angles>,
+    distances: Option<&'a [usize]>,
+    distance_aggregation: Option<DistanceAggregation>,
+    direction_aggregation: Option<DirectionAggregation>,
+    distance_weighting: Option<DistanceWeighting>,
+    filters: &'a [String],
+    filter_use: Option<FilterUse>,
+}
+
+pub struct Provenance {
+    pub app: String,
+    pub input: PathBuf,
+    pub mask: Option<PathBuf>,
+    /// hash of the contents of the input and mask files, see [`file_hash`]. Kept when the paths
+    /// are scrubbed, so runs on the same data can still be matched up
+    pub input_hash: Option<String>,
+    pub mask_hash: Option<String>,
+    pub kernel_radius: usize,
+    /// radius of the kernel along x, y and z when it wasn't the same along every axis, with
+    /// `kernel_radius` the largest of them
+    pub kernel_radii: Option<[usize; 3]>,
+    /// a cube of voxels, or a sphere in mm measured with the voxel spacing of the input
+    pub kernel_shape: KernelShape,
+    /// every kernel was kept to the axial slice of its voxel
+    pub slice_wise: bool,
+    pub binning: Binning,
+    /// whether the whole volume or each kernel was binned on its own
+    pub discretization: DiscretizationScope,
+    /// dependence threshold of the GLDM features, if any were mapped
+    pub gldm_alpha: Option<usize>,
+    /// coarseness and neighbourhood distance of the NGLDM features, if any were mapped
+    pub ngldm_alpha: Option<usize>,
+    pub ngldm_distance: Option<usize>,
+    /// directions the co-occurrence matrices were counted along, None when GLCM and Haralick
+    /// features took all 13 or none were mapped
+    pub glcm_angles: Option<Angles>,
+    /// steps between the voxels paired in the co-occurrence matrices, None when they were
+    /// neighbours or no GLCM and Haralick features were mapped
+    pub glcm_distances: Option<Vec<usize>>,
+    /// how the matrices at several distances were combined, None with a single distance
+    pub glcm_aggregation: Option<DistanceAggregation>,
+    /// how the directions were summed up, None when they were averaged or no GLCM and Haralick
+    /// features were mapped
+    pub glcm_direction_aggregation: Option<DirectionAggregation>,
+    /// how the pairs of merged matrices were weighted by distance, None without weighting or
+    /// GLCM and Haralick features
+    pub glcm_weighting: Option<DistanceWeighting>,
+    /// names of the filtered copies of the input, see [`crate::filter`]
+    pub filters: Vec<String>,
+    /// what became of the filtered copies, None without filters
+    pub filter_use: Option<FilterUse>,
+    /// feature names as they appear in the output file names
+    pub features: Vec<String>,
+    /// input and mask paths are left out, as they often carry patient names
+    pub phi_scrubbed: bool,
+}
+
+impl Provenance {
+    pub fn new(
+        app: &str,
+        input: &Path,
+        mask: Option<&Path>,
+        opts: &MapOpts,
+        texture: &TextureOpts,
+        binning: Binning,
+    ) -> Self {
+        let selected = selected_features(opts, texture);
+        let gldm_alpha = selected
+            .iter()
+            .any(|(f, _)| f.family() == Family::Gldm)
+            .then_some(texture.gldm_alpha);
+        let ngldm = selected.iter().any(|(f, _)| f.family() == Family::Ngldm);
+        let ngldm_alpha = ngldm.then_some(texture.ngldm_alpha);
+        let ngldm_distance = ngldm.then_some(texture.ngldm_distance);
+        let cooccurrence = selected
+            .iter()
+            .any(|(f, _)| matches!(f.family(), Family::Glcm | Family::Haralick));
+        let glcm_angles = cooccurrence
+            .then_some(texture.angles())
+            .filter(|a| !a.is_all());
+        let glcm_distances = cooccurrence
+            .then(|| texture.glcm_distances.clone())
+            .filter(|d| *d != [DEFAULT_DISTANCE]);
+        let glcm_aggregation = glcm_distances
+            .as_ref()
+            .filter(|d| d.len() > 1)
+            .map(|_| texture.glcm_aggregation);
+        let glcm_direction_aggregation = cooccurrence
+            .then_some(texture.glcm_direction_aggregation)
+            .filter(|a| *a != DirectionAggregation::Mean);
+        let glcm_weighting = cooccurrence
+            .then_some(texture.glcm_weighting)
+            .filter(|w| *w != DistanceWeighting::None);
+        let features: Vec<String> = selected
+            .into_iter()
+            .map(|(f, alias)| texture.output_alias(f, &alias))
+            .collect();
+        let filters: Vec<String> = texture.filters.filters.iter().map(|f| f.name()).collect();
+        let filter_use = (!filters.is_empty()).then_some(texture.filters.usage);
+        Provenance {
+            app: app.to_string(),
+            input: input.to_path_buf(),
+            mask: mask.map(Path::to_path_buf),
+            input_hash: file_hash(input),
+            mask_hash: mask.and_then(file_hash),
+            kernel_radius: opts.kernel_radius,
+            kernel_radii: texture.kernel_radii,
+            kernel_shape: texture.kernel_shape,
+            slice_wise: texture.slice_wise,
+            binning,
+            discretization: texture.discretization,
+            gldm_alpha,
+            ngldm_alpha,
+            ngldm_distance,
+            glcm_angles,
+            glcm_distances,
+            glcm_aggregation,
+            glcm_direction_aggregation,
+            glcm_weighting,
+            filters,
+            filter_use,
+            features,
+            phi_scrubbed: false,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let created_unix_s = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let path = |p: &Path| p.to_string_lossy().into_owned();
+        let sidecar = Sidecar {
+            format_version: Format::Provenance.current(),
+            radmap_version: env!("CARGO_PKG_VERSION"),
+            app: &self.app,
+            created_unix_s,
+            input: (!self.phi_scrubbed).then(|| path(&self.input)),
+            mask: self.mask.as_deref().filter(|_| !self.phi_scrubbed).map(path),
+            input_hash: self.input_hash.as_deref(),
+            mask_hash: self.mask_hash.as_deref(),
+            reproducibility_hash: self.reproducibility_hash(),
+            phi_scrubbed: self.phi_scrubbed,
+            options: self.options_fields(),
+            discretization: SidecarDiscretization {
+                n_bins: self.binning.n_bins,
+                bin_edges: self.binning.edges,
+                bin_edges_convention: self.binning.edges.describe(),
+                bin_width: self.binning.width,
+                bin_min: self.binning.min,
+                bin_max: self.binning.max,
+                bin_from_mask: self.binning.from_mask,
+                bin_rank: self.binning.rank,
+                scope: self.discretization,
+            },
+            features: &self.features,
+        };
+        serde_json::to_string_pretty(&sidecar).expect("provenance is always representable as json")
+            + "\n"
+    }
+
+    /// short hash of the options that decide the values of the maps, so runs with the same
+    /// settings can be found whatever their inputs
+    pub fn options_hash(&self) -> String {
+        let mut hash = Fnv1a::default();
+        hash.write(&(self.kernel_radius as u64).to_le_bytes());
+        // left out of cubic kernels, so they keep the hash they had before
+        if let Some(radii) = self.kernel_radii {
+            hash.write(b"radii");
+            for r in radii {
+                hash.write(&(r as u64).to_le_bytes());
+            }
+        }
+        // left out of cubic kernels, so they keep the hash they had before
+        if let KernelShape::Sphere { radius_mm } = self.kernel_shape {
+            hash.write(b"sphere");
+            hash.write(&radius_mm.to_le_bytes());
+        }
+        // left out of runs with 3D kernels, so they keep the hash they had before
+        if self.slice_wise {
+            hash.write(b"slice-wise");
+        }
+        hash.write(&self.binning.key_bytes());
+        // left out of runs binning the whole volume, so they keep the hash they had before
+        if self.discretization.is_local() {
+            hash.write(b"local");
+        }
+        // left out without GLDM features, so runs keep the hash they had before it existed
+        if let Some(alpha) = self.gldm_alpha {
+            hash.write(&(alpha as u64).to_le_bytes());
+        }
+        // and without NGLDM features
+        if let (Some(alpha), Some(distance)) = (self.ngldm_alpha, self.ngldm_distance) {
+            hash.write(b"ngldm");
+            hash.write(&(alpha as u64).to_le_bytes());
+            hash.write(&(distance as u64).to_le_bytes());
+        }
+        // and when every direction was taken
+        if let Some(angles) = self.glcm_angles {
+            hash.write(b"angles");
+            hash.write(angles.to_string().as_bytes());
+        }
+        // and at a distance of 1
+        if let Some(distances) = &self.glcm_distances {
+            hash.write(b"distance");
+            for d in distances {
+                hash.write(&(*d as u64).to_le_bytes());
+            }
+        }
+        if let Some(aggregation) = self.glcm_aggregation {
+            hash.write(aggregation.as_str().as_bytes());
+        }
+        // and when the directions were averaged
+        if let Some(aggregation) = self.glcm_direction_aggregation {
+            hash.write(b"directions");
+            hash.write(aggregation.as_str().as_bytes());
+        }
+        // and when every pair counted once
+        if let Some(weighting) = self.glcm_weighting {
+            hash.write(b"weighting");
+            hash.write(weighting.as_str().as_bytes());
+        }
+        // likewise left out without filters
+        if let Some(usage) = self.filter_use {
+            hash.write(usage.as_str().as_bytes());
+            hash.write(self.filters.join(",").as_bytes());
+        }
+        let mut features: Vec<String> = self.features.iter().map(|f| feature_suffix(f)).collect();
+        features.sort();
+        hash.write(features.join(",").as_bytes());
+        format!("{:016x}", hash.0)
+    }
+
+    /// short hash of the contents of the input and mask and of [`Provenance::options_hash`], so
+    /// runs giving the same maps can be found. None if the input couldn't be read to hash it
+    pub fn reproducibility_hash(&self) -> Option<String> {
+        Some(reproducibility_hash(
+            self.input_hash.as_deref()?,
+            self.mask_hash.as_deref(),
+            &self.options_hash(),
+        ))
+    }
+
+    /// the options as a json object, without the inputs
+    pub fn options_json(&self) -> String {
+        let options = OptionsJson {
+            options: self.options_fields(),
+            n_bins: self.binning.n_bins,
+            bin_edges: self.binning.edges,
+            bin_width: self.binning.width,
+            bin_min: self.binning.min,
+            bin_max: self.binning.max,
+            bin_from_mask: self.binning.from_mask,
+            bin_rank: self.binning.rank,
+            discretization: self.discretization,
+            features: &self.features,
+        };
+        serde_json::to_string(&options).expect("options are always representable as json")
+    }
+
+    /// the options the sidecar and [`Provenance::options_json`] share
+    fn options_fields(&self) -> OptionsFields<'_> {
+        OptionsFields {
+            kernel_radius: self.kernel_radius,
+            kernel_radii: self.kernel_radii,
+            kernel_shape: self.kernel_shape.to_string(),
+            slice_wise: self.slice_wise,
+            gldm_alpha: self.gldm_alpha,
+            ngldm_alpha: self.ngldm_alpha,
+            ngldm_distance: self.ngldm_distance,
+            angles: self.glcm_angles,
+            distances: self.glcm_distances.as_deref(),
+            distance_aggregation: self.glcm_aggregation,
+            direction_aggregation: self.glcm_direction_aggregation,
+            distance_weighting: self.glcm_weighting,
+            filters: &self.filters,
+            filter_use: self.filter_use,
+        }
+    }
+
+    /// writes `<stem>_provenance.json` to the output directory
+    pub fn write(&self, output_dir: &Path, stem: &OsStr) -> Result<PathBuf, String> {
+        let path = output_path(output_dir, stem, SIDECAR_SUFFIX);
+        std::fs::write(&path, self.to_json())
+            .map_err(|e| format!("failed to write {}: {e}", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// hash of the contents of a file, as `fnv1a64:<hex>`. None if the file can't be read
+pub fn file_hash(path: &Path) -> Option<String> {
+    let mut file = std::fs::File::open(path).ok()?;
+    let mut hash = Fnv1a::default();
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        match file.read(&mut buf).ok()? {
+            0 => break,
+            n => hash.write(&buf[..n]),
+        }
+    }
+    Some(format!("fnv1a64:{:016x}", hash.0))
+}
+
+/// see [`Provenance::reproducibility_hash`], from the hashes of the input and mask files and the
+/// options hash
+pub fn reproducibility_hash(
+    input_hash: &str,
+    mask_hash: Option<&str>,
+    options_hash: &str,
+) -> String {
+    let mut hash = Fnv1a::default();
+    hash.write(input_hash.as_bytes());
+    // a separator, so the input hash can't run into the mask hash
+    hash.write(&[0]);
+    hash.write(mask_hash.unwrap_or("").as_bytes());
+    hash.write(&[0]);
+    hash.write(options_hash.as_bytes());
+    format!("{:016x}", hash.0)
+}
+
+/// a run read back from its provenance sidecar
+pub struct PreviousRun {
+    pub sidecar: PathBuf,
+    pub radmap_version: String,
+    pub created_unix_s: u64,
+    /// `input` is empty if the run scrubbed it
+    pub provenance: Provenance,
+    /// feature name and map of every feature map still next to the sidecar
+    pub maps: Vec<(String, PathBuf)>,
+}
+
+impl PreviousRun {
+    pub fn read(sidecar: &Path) -> Result<Self, String> {
+        let err = |e: String| format!("failed to read {}: {e}", sidecar.display());
+        let text = std::fs::read_to_string(sidecar).map_err(|e| err(e.to_string()))?;
+        let value: serde_json::Value =
+            serde_json::from_str(&text).map_err(|e| err(e.to_string()))?;
+        // version 1 sidecars have the same fields, only without the version
+        let version = json_version(&value).map_err(err)?;
+        Format::Provenance.check(version, sidecar.display())?;
+        let string = |key: &str| value.get(key).and_then(|v| v.as_str());
+        let opt_number = |key: &str| value.get(key).and_then(|a| a.as_u64()).map(|a| a as usize);
+        let number = |v: Option<&serde_json::Value>, key: &str| {
+            v.and_then(|v| v.as_u64())
+                .ok_or_else(|| err(format!("{key} is missing")))
+        };
+        let discretization = value.get("discretization");
+        let edges = discretization
+            .and_then(|d| d.get("bin_edges"))
+            .and_then(|e| e.as_str())
+            .map(BinEdges::from_str)
+            .transpose()
+            .map_err(err)?
+            .unwrap_or_default();
+        let float = |key: &str| {
+            discretization
+                .and_then(|d| d.get(key))
+                .and_then(|v| v.as_f64())
+        };
+        let strings = |key: &str| -> Vec<String> {
+            value
+                .get(key)
+                .and_then(|f| f.as_array())
+                .map(|f| {
+                    f.iter()
+                        .filter_map(|f| f.as_str().map(String::from))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let features = strings("features");
+
+        let name = sidecar.file_name().unwrap_or_default().to_string_lossy();
+        let stem = name
+            .strip_suffix(&format!("_{SIDECAR_SUFFIX}"))
+            .ok_or_else(|| err("not a provenance sidecar".to_string()))?;
+        let dir = sidecar.parent().unwrap_or(Path::new("."));
+        let maps = features
+            .iter()
+            .filter_map(|f| {
+                let base = output_path(dir, OsStr::new(stem), &feature_suffix(f));
+                find_output_volume(&base).map(|map| (f.clone(), map))
+            })
+            .collect();
+
+        Ok(PreviousRun {
+            sidecar: sidecar.to_path_buf(),
+            radmap_version: string("radmap_version").unwrap_or("unknown").to_string(),
+            created_unix_s: value
+                .get("created_unix_s")
+                .and_then(|c| c.as_u64())
+                .unwrap_or(0),
+            provenance: Provenance {
+                app: string("app").unwrap_or("unknown").to_string(),
+                input: string("input").map(PathBuf::from).unwrap_or_default(),
+                mask: string("mask").map(PathBuf::from),
+                input_hash: string("input_hash").map(String::from),
+                mask_hash: string("mask_hash").map(String::from),
+                kernel_radius: number(value.get("kernel_radius"), "kernel_radius")? as usize,
+                kernel_radii: value
+                    .get("kernel_radii")
+                    .and_then(|r| r.as_array())
+                    .and_then(|r| {
+                        let radii: Vec<usize> = r
+                            .iter()
+                            .filter_map(|r| r.as_u64().map(|r| r as usize))
+                            .collect();
+                        radii.try_into().ok()
+                    }),
+                kernel_shape: string("kernel_shape")
+                    .map(KernelShape::from_str)
+                    .transpose()
+                    .map_err(err)?
+                    .unwrap_or_default(),
+                slice_wise: value
+                    .get("slice_wise")
+                    .and_then(|s| s.as_bool())
+                    .unwrap_or(false),
+                gldm_alpha: opt_number("gldm_alpha"),
+                ngldm_alpha: opt_number("ngldm_alpha"),
+                ngldm_distance: opt_number("ngldm_distance"),
+                glcm_angles: string("angles")
+                    .map(Angles::from_str)
+                    .transpose()
+                    .map_err(err)?,
+                glcm_distances: value.get("distances").and_then(|d| d.as_array()).map(|d| {
+                    d.iter()
+                        .filter_map(|d| d.as_u64().map(|d| d as usize))
+                        .collect()
+                }),
+                glcm_aggregation: string("distance_aggregation")
+                    .map(DistanceAggregation::from_str)
+                    .transpose()
+                    .map_err(err)?,
+                glcm_direction_aggregation: string("direction_aggregation")
+                    .map(DirectionAggregation::from_str)
+                    .transpose()
+                    .map_err(err)?,
+                glcm_weighting: string("distance_weighting")
+                    .map(DistanceWeighting::from_str)
+                    .transpose()
+                    .map_err(err)?,
+                filters: strings("filters"),
+                filter_use: string("filter_use")
+                    .map(FilterUse::from_str)
+                    .transpose()
+                    .map_err(err)?,
+                binning: Binning {
+                    n_bins: number(discretization.and_then(|d| d.get("n_bins")), "n_bins")?
+                        as usize,
+                    edges,
+                    width: float("bin_width"),
+                    min: float("bin_min"),
+                    max: float("bin_max"),
+                    from_mask: discretization
+                        .and_then(|d| d.get("bin_from_mask"))
+                        .and_then(|m| m.as_bool())
+                        .unwrap_or(false),
+                    rank: discretization
+                        .and_then(|d| d.get("bin_rank"))
+                        .and_then(|r| r.as_bool())
+                        .unwrap_or(false),
+                },
+                discretization: discretization
+                    .and_then(|d| d.get("scope"))
+                    .and_then(|s| s.as_str())
+                    .map(DiscretizationScope::from_str)
+                    .transpose()
+                    .map_err(err)?
+                    .unwrap_or_default(),
+                features,
+                phi_scrubbed: value
+                    .get("phi_scrubbed")
+                    .and_then(|p| p.as_bool())
+                    .unwrap_or(false),
+            },
+            maps,
+        })
+    }
+}
+
+/// the newest run in `dir` that mapped the same input and mask as `expected` with the same
+/// options, judged by [`Provenance::reproducibility_hash`], and whose maps are all still there.
+/// None if there is no such run or the input can't be hashed
+pub fn find_up_to_date_run(dir: &Path, expected: &Provenance) -> Option<PreviousRun> {
+    let hash = expected.reproducibility_hash()?;
+    find_previous_runs(dir).ok()?.into_iter().find(|run| {
+        run.provenance.reproducibility_hash().as_ref() == Some(&hash)
+            && run.maps.len() == run.provenance.features.len()
+    })
+}
+
+/// every run with a provenance sidecar in `dir`, newest first. Sidecars that can't be read are
+/// skipped
+pub fn find_previous_runs(dir: &Path) -> Result<Vec<PreviousRun>, String> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("failed to list {}: {e}", dir.display()))?;
+    let mut runs: Vec<PreviousRun> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| {
+            p.file_name()
+                .is_some_and(|n| n.to_string_lossy().ends_with(&format!("_{SIDECAR_SUFFIX}")))
+        })
+        .filter_map(|p| PreviousRun::read(&p).ok())
+        .collect();
+    runs.sort_by_key(|r| std::cmp::Reverse(r.created_unix_s));
+    Ok(runs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::MapOptsBuilder;
+    use crate::texture::Feature;
+
+    /// a run on a small input written to a directory of its own
+    fn run(name: &str) -> (PathBuf, Provenance) {
+        let dir = std::env::temp_dir().join(format!("radmap_provenance_{name}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("case.nii");
+        std::fs::write(&input, b"voxels").unwrap();
+        let contrast = Feature::from_str("contrast").unwrap();
+        let (opts, texture) = MapOptsBuilder::new()
+            .features([(contrast, contrast.to_string())])
+            .build()
+            .unwrap();
+        let provenance = Provenance::new("radmap", &input, None, &opts, &texture, Binning::new(32));
+        (dir, provenance)
+    }
+
+    #[test]
+    fn the_sidecar_reads_back() {
+        let (dir, mut provenance) = run("read_back");
+        provenance.glcm_distances = Some(vec![1, 2]);
+        provenance.glcm_aggregation = Some(DistanceAggregation::Merged);
+        provenance.binning.width = Some(2.5);
+        let sidecar = provenance.write(&dir, OsStr::new("case")).unwrap();
+
+        let read = PreviousRun::read(&sidecar).unwrap().provenance;
+        assert_eq!(read.options_json(), provenance.options_json());
+        assert_eq!(read.reproducibility_hash(), provenance.reproducibility_hash());
+        assert_eq!(read.input, provenance.input);
+    }
+
+    #[test]
+    fn scrubbed_sidecars_leave_out_the_paths() {
+        let (dir, mut provenance) = run("scrubbed");
+        provenance.phi_scrubbed = true;
+        let sidecar = provenance.write(&dir, OsStr::new("case")).unwrap();
+
+        let value: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&sidecar).unwrap()).unwrap();
+        assert!(value["input"].is_null());
+        assert!(value["mask"].is_null());
+        assert_eq!(value["input_hash"], provenance.input_hash.as_deref().unwrap());
+    }
+
+    /// reproducibility hash of the run of [`run`] after `change`
+    fn hash_after(change: impl Fn(&mut Provenance)) -> String {
+        let (_, mut provenance) = run("changes");
+        change(&mut provenance);
+        provenance.reproducibility_hash().unwrap()
+    }
+
+    #[test]
+    fn the_hash_changes_with_the_input_mask_and_options() {
+        let hash = hash_after(|_| ());
+        let changed = [
+            (
+                "input",
+                hash_after(|p| {
+                    std::fs::write(&p.input, b"other voxels").unwrap();
+                    p.input_hash = file_hash(&p.input);
+                }),
+            ),
+            (
+                "mask",
+                hash_after(|p| {
+                    let mask = p.input.with_file_name("mask.nii");
+                    std::fs::write(&mask, b"mask").unwrap();
+                    p.mask_hash = file_hash(&mask);
+                    p.mask = Some(mask);
+                }),
+            ),
+            ("kernel radius", hash_after(|p| p.kernel_radius += 1)),
+            ("kernel radii", hash_after(|p| p.kernel_radii = Some([1, 1, 0]))),
+            ("slice-wise", hash_after(|p| p.slice_wise = true)),
+            ("bins", hash_after(|p| p.binning.n_bins = 16)),
+            ("bin edges", hash_after(|p| p.binning.edges = BinEdges::Inclusive)),
+            ("bin width", hash_after(|p| p.binning.width = Some(2.5))),
+            ("distances", hash_after(|p| p.glcm_distances = Some(vec![1, 2]))),
+            (
+                "weighting",
+                hash_after(|p| p.glcm_weighting = Some(DistanceWeighting::Inverse)),
+            ),
+            (
+                "features",
+                hash_after(|p| p.features.push("dissimilarity".into())),
+            ),
+        ];
+        for (name, changed) in changed {
+            assert_ne!(changed, hash, "{name}");
+        }
+    }
+
+    #[test]
+    fn the_hash_is_otherwise_stable() {
+        let (_, provenance) = run("stable");
+        let hash = provenance.reproducibility_hash().unwrap();
+        assert_eq!(provenance.reproducibility_hash().unwrap(), hash);
+        // the same contents and options elsewhere, from another app and scrubbed of PHI
+        let (dir, mut elsewhere) = run("stable_elsewhere");
+        let moved = dir.join("renamed.nii");
+        std::fs::rename(&elsewhere.input, &moved).unwrap();
+        elsewhere.input = moved;
+        elsewhere.app = "radmap-gui".to_string();
+        elsewhere.phi_scrubbed = true;
+        assert_eq!(elsewhere.reproducibility_hash().unwrap(), hash);
+        // the order the features were selected in doesn't matter either
+        let (_, mut two) = run("stable_two");
+        two.features.push("dissimilarity".into());
+        let (_, mut swapped) = run("stable_swapped");
+        swapped.features.insert(0, "dissimilarity".into());
+        assert_eq!(two.reproducibility_hash(), swapped.reproducibility_hash());
+    }
+}
@@ -0,0 +1,300 @@
+//! Worker mode for processing farms: jobs are taken from a Redis list and run with the
+//! [`JobRunner`], so any number of machines can share one queue without a scheduler.
+//!
+//! | key | contents |
+//! |-----|----------|
+//! | `<queue>` | list of pending jobs. Producers `LPUSH` job descriptions, workers `BRPOPLPUSH` them into their processing list |
+//! | `<queue>:processing:<worker>` | jobs a worker took and hasn't finished. A worker restarted under the same name puts them back on the queue |
+//! | `<queue>:status:<id>` | hash with `state`, `voxels_done`, `voxels_total` and `worker`, updated while the job runs |
+//! | `<queue>:results` | list the result of every finished job is pushed to |
+//!
+//! A job description is a json object:
+//! `{"id": "case-1", "input_vol": "/data/a.nii", "mask": "/data/a_mask.nii", "output_dir": "/out", "args": ["--n-bins=64"]}`.
+//! `mask` and `args` are optional. Paths are paths on the worker.
+//!
+//! Workers are named after their host unless given a name, so a worker restarted on the same
+//! machine picks up the jobs of the one before it. Workers running on one machine at the same
+//! time need names of their own, or one starting would put back the jobs of the others.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use redis::Commands;
+use serde::Serialize;
+
+use crate::failure::FailureKind;
+use crate::jobs::{JobRunner, JobSpec, JobState, JobStatus};
+
+/// how long a pop blocks before the running jobs are checked again, in seconds
+const POP_TIMEOUT: f64 = 1.;
+
+/// status hashes of finished jobs are kept for a day
+const STATUS_TTL: i64 = 24 * 60 * 60;
+
+/// wait before reconnecting after the server failed, doubled on every failure in a row up to
+/// the most
+const RETRY_FIRST: Duration = Duration::from_secs(1);
+const RETRY_MAX: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct QueueJob {
+    pub id: String,
+    pub spec: JobSpec,
+}
+
+impl QueueJob {
+    pub fn parse(description: &str) -> Result<Self, String> {
+        let value: serde_json::Value = serde_json::from_str(description)
+            .map_err(|e| format!("job description is not valid json: {e}"))?;
+        let string = |key: &str| value.get(key).and_then(|v| v.as_str());
+        let id = string("id").ok_or("job description has no id")?;
+        let required = |key: &str| string(key).ok_or_else(|| format!("job {id} has no {key}"));
+        let args = match value.get("args") {
+            None => vec![],
+            Some(args) => args
+                .as_array()
+                .and_then(|a| a.iter().map(|v| v.as_str().map(String::from)).collect())
+                .ok_or_else(|| format!("args of job {id} must be a list of strings"))?,
+        };
+        Ok(QueueJob {
+            id: id.to_string(),
+            spec: JobSpec {
+                input_vol: PathBuf::from(required("input_vol")?),
+                mask: string("mask").map(PathBuf::from),
+                output_dir: PathBuf::from(required("output_dir")?),
+                args,
+            },
+        })
+    }
+}
+
+/// a job taken from the queue and handed to the runner
+struct RunningJob {
+    /// id of the job description
+    id: String,
+    /// the job description as taken from the queue, to remove it from the processing list
+    description: String,
+    runner_id: u64,
+    /// state and progress last written to the status hash
+    published: Option<(JobState, u64)>,
+}
+
+fn state_str(state: JobState) -> &'static str {
+    match state {
+        JobState::Queued => "queued",
+        JobState::Running => "running",
+        JobState::Succeeded => "succeeded",
+        JobState::Failed => "failed",
+    }
+}
+
+/// the result of a job as pushed to the results list
+#[derive(Serialize)]
+struct JobResult<'a> {
+    id: Option<&'a str>,
+    worker: &'a str,
+    state: &'static str,
+    exit_code: i32,
+    failure_kind: Option<&'static str>,
+    message: Option<&'a str>,
+    outputs: Vec<String>,
+}
+
+impl JobResult<'_> {
+    fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("job results are always representable as json")
+    }
+}
+
+/// the result pushed for a finished job
+fn result_json(id: &str, worker: &str, status: &JobStatus) -> String {
+    JobResult {
+        id: Some(id),
+        worker,
+        state: state_str(status.state),
+        exit_code: status.exit_code.unwrap_or_default(),
+        failure_kind: status.failure_kind().map(|k| k.as_str()),
+        message: status.message.as_deref(),
+        outputs: status
+            .outputs
+            .iter()
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect(),
+    }
+    .to_json()
+}
+
+/// result of a job that could not be parsed, so it never ran
+fn rejected_json(description: &str, worker: &str, message: &str) -> String {
+    let id = serde_json::from_str::<serde_json::Value>(description)
+        .ok()
+        .and_then(|v| v.get("id").and_then(|id| id.as_str()).map(String::from));
+    JobResult {
+        id: id.as_deref(),
+        worker,
+        state: "failed",
+        exit_code: FailureKind::Usage.exit_code(),
+        failure_kind: Some(FailureKind::Usage.as_str()),
+        message: Some(message),
+        outputs: vec![],
+    }
+    .to_json()
+}
+
+/// name a worker goes by unless given one, the host name. It stays the same across restarts,
+/// so a restarted worker finds the processing list of the one before it
+pub fn default_worker_name() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| {
+            std::fs::read_to_string("/etc/hostname")
+                .ok()
+                .map(|h| h.trim().to_string())
+        })
+        .filter(|h| !h.is_empty())
+        .unwrap_or("localhost".to_string())
+}
+
+/// takes jobs from `queue` on the redis server at `url` for as long as the process runs, running
+/// up to `max_jobs` of them at a time as worker `name`. Once connected, a failing server is
+/// retried with backoff while the jobs already taken keep running
+pub fn run_worker(
+    url: &str,
+    queue: &str,
+    name: &str,
+    runner: &JobRunner,
+    max_jobs: usize,
+) -> Result<(), String> {
+    if !url.starts_with("redis://") && !url.starts_with("rediss://") {
+        return Err(format!(
+            "unsupported queue {url}, expected a redis:// address"
+        ));
+    }
+    let connect = || redis::Client::open(url).and_then(|c| c.get_connection());
+    let mut conn = connect().map_err(|e| format!("failed to connect to {url}: {e}"))?;
+    let mut worker = Worker::new(queue, name, runner, max_jobs);
+    let requeued = worker
+        .requeue(&mut conn)
+        .map_err(|e| format!("queue {queue} on {url} failed: {e}"))?;
+    if requeued > 0 {
+        println!("put {requeued} unfinished jobs of an earlier run back on the queue");
+    }
+
+    let mut backoff = RETRY_FIRST;
+    loop {
+        match worker.poll(&mut conn) {
+            Ok(()) => backoff = RETRY_FIRST,
+            Err(e) => {
+                println!(
+                    "queue {queue} on {url} failed: {e}, retrying in {}s",
+                    backoff.as_secs()
+                );
+                std::thread::sleep(backoff);
+                backoff = (backoff * 2).min(RETRY_MAX);
+                if let Ok(c) = connect() {
+                    conn = c;
+                }
+            }
+        }
+    }
+}
+
+/// the keys of a queue and the jobs this worker took from it
+struct Worker<'a> {
+    queue: String,
+    /// jobs taken but not finished, so they outlive a crash of the worker
+    processing: String,
+    results: String,
+    name: String,
+    runner: &'a JobRunner,
+    max_jobs: usize,
+    running: Vec<RunningJob>,
+}
+
+impl<'a> Worker<'a> {
+    fn new(queue: &str, name: &str, runner: &'a JobRunner, max_jobs: usize) -> Self {
+        Worker {
+            queue: queue.to_string(),
+            processing: format!("{queue}:processing:{name}"),
+            results: format!("{queue}:results"),
+            name: name.to_string(),
+            runner,
+            max_jobs: max_jobs.max(1),
+            running: vec![],
+        }
+    }
+
+    /// puts the jobs left in the processing list by an earlier run of a worker of the same name
+    /// back on the queue. Returns how many there were
+    fn requeue(&self, conn: &mut redis::Connection) -> redis::RedisResult<usize> {
+        let mut n = 0;
+        while conn
+            .rpoplpush::<_, _, Option<String>>(&self.processing, &self.queue)?
+            .is_some()
+        {
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    /// takes a job if there is room for one and publishes the state of the running ones. A job
+    /// leaves the processing list once its result is pushed
+    fn poll(&mut self, conn: &mut redis::Connection) -> redis::RedisResult<()> {
+        if self.running.len() < self.max_jobs {
+            let popped: Option<String> =
+                conn.brpoplpush(&self.queue, &self.processing, POP_TIMEOUT)?;
+            if let Some(description) = popped {
+                match QueueJob::parse(&description) {
+                    Ok(job) => {
+                        println!("starting job {}", job.id);
+                        let runner_id = self.runner.submit(job.spec);
+                        self.running.push(RunningJob {
+                            id: job.id,
+                            description,
+                            runner_id,
+                            published: None,
+                        });
+                    }
+                    Err(e) => {
+                        println!("rejected job: {e}");
+                        let _: () =
+                            conn.lpush(&self.results, rejected_json(&description, &self.name, &e))?;
+                        let _: () = conn.lrem(&self.processing, 1, &description)?;
+                    }
+                }
+            }
+        } else {
+            std::thread::sleep(Duration::from_secs_f64(POP_TIMEOUT));
+        }
+
+        for job in self.running.iter_mut() {
+            let Some(status) = self.runner.status(job.runner_id) else {
+                continue;
+            };
+            if job.published == Some((status.state, status.voxels_done)) {
+                continue;
+            }
+            let key = format!("{}:status:{}", self.queue, job.id);
+            let _: () = conn.hset_multiple(
+                &key,
+                &[
+                    ("state", state_str(status.state).to_string()),
+                    ("voxels_done", status.voxels_done.to_string()),
+                    ("voxels_total", status.voxels_total.to_string()),
+                    ("worker", self.name.clone()),
+                ],
+            )?;
+            if status.state.is_finished() {
+                let _: () = conn.expire(&key, STATUS_TTL)?;
+                let _: () = conn.lpush(&self.results, result_json(&job.id, &self.name, &status))?;
+                let _: () = conn.lrem(&self.processing, 1, &job.description)?;
+                println!("job {} {}", job.id, state_str(status.state));
+            }
+            // only once every write went through, so a failed one is tried again
+            job.published = Some((status.state, status.voxels_done));
+        }
+        self.running
+            .retain(|job| !job.published.is_some_and(|(state, _)| state.is_finished()));
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+//! 3D Slicer scene export: a `.mrml` scene next to the feature maps with the input, the mask and
+//! the maps preloaded, so reviewers can open a case with one double click.
+//!
+//! Single file inputs are copied next to the scene so the output directory can be shared as is.
+//! Detached nrrd inputs are referenced where they are. Either way the inputs keep their patient
+//! information, so no scene is written for outputs scrubbed of it.
+
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use crate::io::{find_output_volume, output_path};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VolumeKind {
+    Anatomy,
+    FeatureMap,
+    Label,
+}
+
+struct SceneVolume {
+    name: String,
+    file: PathBuf,
+    kind: VolumeKind,
+}
+
+/// writes `<stem>_scene.mrml` to `dir`, which must already hold the feature maps named
+/// `<stem>_<suffix>` for every suffix given. Returns the path of the scene
+pub fn write_slicer_scene(
+    dir: &Path,
+    stem: &OsStr,
+    input: &Path,
+    mask: Option<&Path>,
+    feature_suffixes: &[String],
+) -> Result<PathBuf, String> {
+    let mut volumes = vec![SceneVolume {
+        name: "input".to_string(),
+        file: bundle_input(dir, stem, input, "input")?,
+        kind: VolumeKind::Anatomy,
+    }];
+    for suffix in feature_suffixes {
+        let base = output_path(dir, stem, suffix);
+        let file = find_output_volume(&base)
+            .ok_or_else(|| format!("no feature map found at {}", base.display()))?;
+        volumes.push(SceneVolume {
+            name: suffix.clone(),
+            file,
+            kind: VolumeKind::FeatureMap,
+        });
+    }
+    if let Some(mask) = mask {
+        volumes.push(SceneVolume {
+            name: "mask".to_string(),
+            file: bundle_input(dir, stem, mask, "mask")?,
+            kind: VolumeKind::Label,
+        });
+    }
+
+    let path = output_path(dir, stem, "scene.mrml");
+    std::fs::write(&path, mrml(&volumes, dir))
+        .map_err(|e| format!("failed to write {}: {e}", path.display()))?;
+    Ok(path)
+}
+
+/// copies a single file input to `<stem>_<role>.<ext>` in `dir`. Detached headers are left where
+/// they are and referenced by absolute path
+fn bundle_input(dir: &Path, stem: &OsStr, input: &Path, role: &str) -> Result<PathBuf, String> {
+    let name = input
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase();
+    let Some(ext) = ["nii.gz", "nii", "nrrd"]
+        .into_iter()
+        .find(|ext| name.ends_with(&format!(".{ext}")))
+    else {
+        return input
+            .canonicalize()
+            .map_err(|e| format!("failed to resolve {}: {e}", input.display()));
+    };
+    let copy = output_path(dir, stem, &format!("{role}.{ext}"));
+    std::fs::copy(input, &copy)
+        .map_err(|e| format!("failed to copy {} into the scene: {e}", input.display()))?;
+    Ok(copy)
+}
+
+fn mrml(volumes: &[SceneVolume], dir: &Path) -> String {
+    let mut xml = String::from("<MRML version=\"Slicer4.4.0\" userTags=\"\">\n");
+    let node_id = |v: &SceneVolume, n: usize| match v.kind {
+        VolumeKind::Label => format!("vtkMRMLLabelMapVolumeNode{n}"),
+        _ => format!("vtkMRMLScalarVolumeNode{n}"),
+    };
+    let active = volumes
+        .iter()
+        .position(|v| v.kind == VolumeKind::FeatureMap)
+        .unwrap_or(0);
+    let mut references = format!("ActiveVolume:{};", node_id(&volumes[active], active + 1));
+    if let Some(n) = volumes.iter().position(|v| v.kind == VolumeKind::Label) {
+        references.push_str(&format!(
+            "ActiveLabelVolume:{};",
+            node_id(&volumes[n], n + 1)
+        ));
+    }
+    xml.push_str(&format!(
+        " <Selection id=\"vtkMRMLSelectionNodeSingleton\" name=\"Selection\" references=\"{references}\" />\n"
+    ));
+
+    for (i, v) in volumes.iter().enumerate() {
+        let n = i + 1;
+        // paths inside the scene directory are stored relative to it
+        let file = v.file.strip_prefix(dir).unwrap_or(&v.file);
+        let (volume_tag, display_tag, display_id, color) = match v.kind {
+            VolumeKind::Anatomy => (
+                "Volume",
+                "VolumeDisplay",
+                format!("vtkMRMLScalarVolumeDisplayNode{n}"),
+                "vtkMRMLColorTableNodeGrey",
+            ),
+            VolumeKind::FeatureMap => (
+                "Volume",
+                "VolumeDisplay",
+                format!("vtkMRMLScalarVolumeDisplayNode{n}"),
+                "vtkMRMLColorTableNodeFileViridis.txt",
+            ),
+            VolumeKind::Label => (
+                "LabelMapVolume",
+                "LabelMapVolumeDisplay",
+                format!("vtkMRMLLabelMapVolumeDisplayNode{n}"),
+                "vtkMRMLColorTableNodeLabels",
+            ),
+        };
+        xml.push_str(&format!(
+            " <VolumeArchetypeStorage id=\"vtkMRMLVolumeArchetypeStorageNode{n}\" name=\"VolumeArchetypeStorage{n}\" hideFromEditors=\"true\" fileName=\"{}\" useCompression=\"1\" singleFile=\"0\" centerImage=\"0\" UseOrientationFromFile=\"1\" />\n",
+            escape(&file.to_string_lossy())
+        ));
+        xml.push_str(&format!(
+            " <{display_tag} id=\"{display_id}\" name=\"{display_tag}{n}\" hideFromEditors=\"true\" colorNodeID=\"{color}\" autoWindowLevel=\"1\" interpolate=\"1\" />\n"
+        ));
+        xml.push_str(&format!(
+            " <{volume_tag} id=\"{}\" name=\"{}\" references=\"display:{display_id};storage:vtkMRMLVolumeArchetypeStorageNode{n};\" />\n",
+            node_id(v, n),
+            escape(&v.name)
+        ));
+    }
+    xml.push_str("</MRML>\n");
+    xml
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,80 @@
+//! Format versions of the files radmap writes and reads back later, so a study running for
+//! months survives upgrades of the tool. Each file records the version of its layout:
+//!
+//! | format | version | recorded as |
+//! |--------|---------|-------------|
+//! | preset | 2 | `format_version` key, see [`crate::preset`] |
+//! | provenance sidecar | 2 | `format_version` field |
+//! | failure summary | 2 | `format_version` field |
+//! | batch manifest | 2 | `format_version` column |
+//! | history database | 2 | sqlite `user_version` |
+//! | study database | 1 | sqlite `user_version`, see [`crate::study`] |
+//!
+//! Files written before versions were recorded are version 1. Older versions are upgraded when
+//! read, newer ones are refused with a message naming both versions rather than misread.
+
+use std::fmt::Display;
+
+/// version of files written before their version was recorded
+pub const UNVERSIONED: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Preset,
+    Provenance,
+    FailureSummary,
+    BatchManifest,
+    History,
+    StudyDatabase,
+}
+
+impl Format {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Preset => "preset",
+            Format::Provenance => "provenance sidecar",
+            Format::FailureSummary => "failure summary",
+            Format::BatchManifest => "batch manifest",
+            Format::History => "history database",
+            Format::StudyDatabase => "study database",
+        }
+    }
+
+    /// version written by this radmap, and the newest it reads
+    pub fn current(&self) -> u32 {
+        match self {
+            Format::Preset => 2,
+            Format::Provenance => 2,
+            Format::FailureSummary => 2,
+            Format::BatchManifest => 2,
+            Format::History => 2,
+            Format::StudyDatabase => 1,
+        }
+    }
+
+    /// checks that `source`, of this format and `version`, can be read
+    pub fn check(&self, version: u32, source: impl Display) -> Result<(), String> {
+        if version > self.current() {
+            return Err(format!(
+                "{source} is a version {version} {}, written by a newer radmap. This radmap reads up to version {}, upgrade it to read the file",
+                self.name(),
+                self.current()
+            ));
+        }
+        if version == 0 {
+            return Err(format!("{source} has an invalid format version 0"));
+        }
+        Ok(())
+    }
+}
+
+/// the format version of a json document, [`UNVERSIONED`] if it has none
+pub fn json_version(value: &serde_json::Value) -> Result<u32, String> {
+    match value.get("format_version") {
+        None => Ok(UNVERSIONED),
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| format!("format_version must be a whole number, got {v}")),
+    }
+}
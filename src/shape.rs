@@ -0,0 +1,328 @@
+//! Shape features of the region in a mask, as in the morphology family of the IBSI. The surface
+//! is a triangle mesh at the boundary of the region, from marching cubes over the voxel centres,
+//! so its vertices lie halfway between a voxel in the region and one outside of it.
+//! Volume and surface area are those of the mesh, in mm³ and mm² when the spacing is in mm.
+//! Elongation and flatness come from the principal axes of the voxel centres and are 1 for a
+//! region that doesn't extend along any axis, like a single voxel.
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::header::voxel_spacing;
+use crate::io::read_volume;
+use crate::locale::ReportFormat;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ShapeFeatures {
+    pub mesh_volume: f64,
+    /// number of voxels in the region times the volume of a voxel
+    pub voxel_volume: f64,
+    pub surface_area: f64,
+    pub surface_volume_ratio: f64,
+    /// 1 for a sphere, less for any other shape
+    pub sphericity: f64,
+    /// square root of the ratio of the second to the largest principal axis variance
+    pub elongation: f64,
+    /// square root of the ratio of the smallest to the largest principal axis variance
+    pub flatness: f64,
+    /// largest distance between two vertices of the mesh
+    pub maximum_3d_diameter: f64,
+}
+
+impl ShapeFeatures {
+    pub const NAMES: [&str; 8] = [
+        "mesh_volume",
+        "voxel_volume",
+        "surface_area",
+        "surface_volume_ratio",
+        "sphericity",
+        "elongation",
+        "flatness",
+        "maximum_3d_diameter",
+    ];
+
+    /// values in the order of [`Self::NAMES`]
+    pub fn values(&self) -> [f64; 8] {
+        [
+            self.mesh_volume,
+            self.voxel_volume,
+            self.surface_area,
+            self.surface_volume_ratio,
+            self.sphericity,
+            self.elongation,
+            self.flatness,
+            self.maximum_3d_diameter,
+        ]
+    }
+}
+
+/// shape features of the mask in a file
+#[derive(Debug, Clone, Serialize)]
+pub struct ShapeReport {
+    pub mask: PathBuf,
+    /// voxel spacing used, 1 along every axis if the header has none
+    pub spacing: [f64; 3],
+    #[serde(flatten)]
+    pub features: ShapeFeatures,
+}
+
+impl ShapeReport {
+    /// reads `mask`, of which every non-zero voxel is in the region
+    pub fn compute(mask: &Path) -> Result<Self, String> {
+        let (data, dims, _) = read_volume(mask);
+        let shape = [0, 1, 2].map(|d| dims.shape_ns()[d]);
+        let spacing = voxel_spacing(mask).unwrap_or([1.; 3]);
+        let features = shape_features(&data, shape, spacing)
+            .map_err(|e| format!("{}: {e}", mask.display()))?;
+        Ok(ShapeReport {
+            mask: mask.to_path_buf(),
+            spacing,
+            features,
+        })
+    }
+
+    pub fn csv_header(format: &ReportFormat) -> String {
+        let mut names = vec!["mask"];
+        names.extend(ShapeFeatures::NAMES);
+        format.header(&names)
+    }
+
+    pub fn csv_row(&self, format: &ReportFormat) -> String {
+        let mut fields = vec![self.mask.display().to_string()];
+        fields.extend(self.features.values().iter().map(|v| format.float(*v)));
+        format.row(&fields)
+    }
+}
+
+/// shape features of the non-zero voxels of `mask`, x fastest. Fails on an empty mask
+pub fn shape_features(
+    mask: &[f64],
+    shape: [usize; 3],
+    spacing: [f64; 3],
+) -> Result<ShapeFeatures, String> {
+    let n_vox: usize = shape.iter().product();
+    let inside = |p: [i64; 3]| {
+        (0..3).all(|d| p[d] >= 0 && (p[d] as usize) < shape[d])
+            && mask[p[0] as usize + shape[0] * (p[1] as usize + shape[1] * p[2] as usize)] != 0.
+    };
+    let n_inside = mask[..n_vox].iter().filter(|v| **v != 0.).count();
+    if n_inside == 0 {
+        return Err("the mask is empty".to_string());
+    }
+
+    let mesh = Mesh::of_region(shape, inside);
+    let to_mm = |p: [f64; 3]| [0, 1, 2].map(|d| p[d] / 2. * spacing[d]);
+    let mut mesh_volume = 0.;
+    let mut surface_area = 0.;
+    for [a, b, c] in &mesh.triangles {
+        let [a, b, c] = [a, b, c].map(|v| to_mm(*v));
+        mesh_volume += dot(a, cross(b, c)) / 6.;
+        surface_area += norm(cross(sub(b, a), sub(c, a))) / 2.;
+    }
+
+    let [major, minor, least] = principal_variances(mask, shape, spacing);
+    let axis_ratio = |l: f64| if major > 0. { (l / major).sqrt() } else { 1. };
+
+    let vertices: Vec<[f64; 3]> = hull_candidates(&mesh)
+        .into_iter()
+        .map(|v| to_mm(v.map(|x| x as f64)))
+        .collect();
+    let maximum_3d_diameter = vertices
+        .par_iter()
+        .enumerate()
+        .map(|(i, a)| {
+            vertices[i + 1..]
+                .iter()
+                .map(|b| norm(sub(*a, *b)))
+                .fold(0., f64::max)
+        })
+        .reduce(|| 0., f64::max);
+
+    Ok(ShapeFeatures {
+        mesh_volume,
+        voxel_volume: n_inside as f64 * spacing.iter().product::<f64>(),
+        surface_area,
+        surface_volume_ratio: surface_area / mesh_volume,
+        sphericity: (36. * PI * mesh_volume * mesh_volume).cbrt() / surface_area,
+        elongation: axis_ratio(minor),
+        flatness: axis_ratio(least),
+        maximum_3d_diameter,
+    })
+}
+
+/// surface of the region, with vertices in voxel units doubled so the midpoints of the edges
+/// between voxel centres are whole numbers
+struct Mesh {
+    /// triangles facing out of the region
+    triangles: Vec<[[f64; 3]; 3]>,
+    /// every vertex but the centres of the loops, which lie between the others
+    edge_vertices: Vec<[i64; 3]>,
+}
+
+impl Mesh {
+    /// marching cubes over the voxel centres. Rather than from the usual table, the surface in a
+    /// cube is found from its faces: on each face, a segment cuts off every run of corners in the
+    /// region, so corners in the region that only touch diagonally are kept apart. The segments
+    /// of a cube join into loops, each closed by a fan of triangles around its centre. Cubes cut
+    /// their common face the same way, so the mesh is closed
+    fn of_region(shape: [usize; 3], inside: impl Fn([i64; 3]) -> bool) -> Self {
+        // corners of each face of the cube, counter-clockwise seen from outside the cube. Corner
+        // b is at (b & 1, b >> 1 & 1, b >> 2)
+        const FACES: [[usize; 4]; 6] = [
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+        ];
+        let mid = |a: [i64; 3], b: [i64; 3]| [0, 1, 2].map(|d| a[d] + b[d]);
+        let mut triangles = vec![];
+        let mut edge_vertices = vec![];
+        let mut segments = vec![];
+        // cubes hanging over the edges of the volume close the surface there
+        let [nx, ny, nz] = shape.map(|n| n as i64);
+        for z in -1..nz {
+            for y in -1..ny {
+                for x in -1..nx {
+                    let corners = [0, 1, 2, 3, 4, 5, 6, 7]
+                        .map(|b| [x + (b & 1), y + (b >> 1 & 1), z + (b >> 2)]);
+                    let states = corners.map(&inside);
+                    if states.iter().all(|s| *s == states[0]) {
+                        continue;
+                    }
+                    for face in FACES {
+                        let st = face.map(|c| states[c]);
+                        for k in (0..4).filter(|&k| st[k] && !st[(k + 3) % 4]) {
+                            let mut j = k;
+                            while st[(j + 1) % 4] {
+                                j = (j + 1) % 4;
+                            }
+                            segments.push((
+                                mid(corners[face[(k + 3) % 4]], corners[face[k]]),
+                                mid(corners[face[j]], corners[face[(j + 1) % 4]]),
+                            ));
+                        }
+                    }
+                    while let Some((first, mut next)) = segments.pop() {
+                        let mut polygon = vec![first];
+                        while next != first {
+                            let i = segments
+                                .iter()
+                                .position(|(a, _)| *a == next)
+                                .expect("the segments of a cube form closed loops");
+                            let (a, b) = segments.swap_remove(i);
+                            polygon.push(a);
+                            next = b;
+                        }
+                        let n = polygon.len() as f64;
+                        let centre =
+                            [0, 1, 2].map(|d| polygon.iter().map(|p| p[d] as f64).sum::<f64>() / n);
+                        for (i, a) in polygon.iter().enumerate() {
+                            let b = polygon[(i + 1) % polygon.len()];
+                            triangles.push([centre, a.map(|x| x as f64), b.map(|x| x as f64)]);
+                        }
+                        edge_vertices.extend(polygon);
+                    }
+                }
+            }
+        }
+        Mesh {
+            triangles,
+            edge_vertices,
+        }
+    }
+}
+
+/// vertices of the mesh that can be an end of its maximum diameter. A vertex between two others
+/// along a line parallel to an axis lies on the segment joining them, so it is never farther
+/// from any point than one of them and is left out
+fn hull_candidates(mesh: &Mesh) -> Vec<[i64; 3]> {
+    let mut vertices = mesh.edge_vertices.clone();
+    vertices.sort();
+    vertices.dedup();
+    for axis in 0..3 {
+        let mut extremes: HashMap<[i64; 2], ([i64; 3], [i64; 3])> = HashMap::new();
+        for v in vertices {
+            let others: Vec<i64> = (0..3).filter(|d| *d != axis).map(|d| v[d]).collect();
+            let e = extremes.entry([others[0], others[1]]).or_insert((v, v));
+            if v[axis] < e.0[axis] {
+                e.0 = v;
+            }
+            if v[axis] > e.1[axis] {
+                e.1 = v;
+            }
+        }
+        vertices = extremes
+            .into_values()
+            .flat_map(|(lo, hi)| [lo, hi])
+            .collect();
+        vertices.sort();
+        vertices.dedup();
+    }
+    vertices
+}
+
+/// variances of the voxel centres of the region along its principal axes, largest first
+fn principal_variances(mask: &[f64], shape: [usize; 3], spacing: [f64; 3]) -> [f64; 3] {
+    let centres: Vec<[f64; 3]> = (0..shape[2])
+        .flat_map(|z| (0..shape[1]).flat_map(move |y| (0..shape[0]).map(move |x| [x, y, z])))
+        .filter(|[x, y, z]| mask[x + shape[0] * (y + shape[1] * z)] != 0.)
+        .map(|p| [0, 1, 2].map(|d| p[d] as f64 * spacing[d]))
+        .collect();
+    let n = centres.len() as f64;
+    let mean = [0, 1, 2].map(|d| centres.iter().map(|c| c[d]).sum::<f64>() / n);
+    let mut cov = [[0.; 3]; 3];
+    for c in &centres {
+        let d = sub(*c, mean);
+        for i in 0..3 {
+            for j in 0..3 {
+                cov[i][j] += d[i] * d[j] / n;
+            }
+        }
+    }
+    symmetric_eigenvalues(cov)
+}
+
+/// eigenvalues of a symmetric 3x3 matrix, largest first
+fn symmetric_eigenvalues(a: [[f64; 3]; 3]) -> [f64; 3] {
+    let p1 = a[0][1].powi(2) + a[0][2].powi(2) + a[1][2].powi(2);
+    let q = (a[0][0] + a[1][1] + a[2][2]) / 3.;
+    let p2 = (0..3).map(|i| (a[i][i] - q).powi(2)).sum::<f64>() + 2. * p1;
+    let p = (p2 / 6.).sqrt();
+    if p == 0. {
+        return [q; 3];
+    }
+    let b = [0, 1, 2].map(|i| [0, 1, 2].map(|j| (a[i][j] - if i == j { q } else { 0. }) / p));
+    let det = b[0][0] * (b[1][1] * b[2][2] - b[1][2] * b[2][1])
+        - b[0][1] * (b[1][0] * b[2][2] - b[1][2] * b[2][0])
+        + b[0][2] * (b[1][0] * b[2][1] - b[1][1] * b[2][0]);
+    let phi = (det / 2.).clamp(-1., 1.).acos() / 3.;
+    let largest = q + 2. * p * phi.cos();
+    let smallest = q + 2. * p * (phi + 2. * PI / 3.).cos();
+    [largest, 3. * q - largest - smallest, smallest].map(|l| l.max(0.))
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn norm(a: [f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
@@ -0,0 +1,152 @@
+//! Maps of a GUI run spilled to the user data directory as soon as they are computed, before they
+//! are written to the output directory, so a crash, a closed window or an output share that
+//! drops out doesn't throw away hours of compute.
+//! Every run spills to a directory of its own under `spill/`, holding its maps under their output
+//! names and a `spill.json` describing the run, written last so a run spilled only in part is
+//! never offered. `RADMAP_SPILL_DIR` overrides the location. Runs with encrypted outputs aren't
+//! spilled, the copy would be in the clear.
+//!
+//! The directory is removed once the maps have been written to their output directory, so a
+//! spilled run found at launch never got that far and can be recovered by moving its maps into an
+//! output directory of the user's choosing.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use array_lib::ArrayDim;
+use serde::{Deserialize, Serialize};
+
+use crate::io::{input_stem, output_path, write_volume, Header};
+use crate::staging::{RetryPolicy, Staging};
+use crate::usage::data_dir;
+
+const MANIFEST: &str = "spill.json";
+
+/// the run a spill directory holds, written once every map is in it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Manifest {
+    input: PathBuf,
+    created_unix_s: u64,
+    /// suffixes of the maps, as in their output names
+    maps: Vec<String>,
+}
+
+/// where spilled runs are kept. `RADMAP_SPILL_DIR` overrides the default in the user data
+/// directory, the system temp directory is used without one
+pub fn spill_root() -> PathBuf {
+    if let Some(dir) = std::env::var_os("RADMAP_SPILL_DIR") {
+        return PathBuf::from(dir);
+    }
+    data_dir()
+        .map(|d| d.join("spill"))
+        .unwrap_or_else(|| std::env::temp_dir().join("radmap-spill"))
+}
+
+/// the maps of a run being spilled
+pub struct Spill {
+    dir: PathBuf,
+    input: PathBuf,
+    stem: OsString,
+}
+
+impl Spill {
+    /// a fresh spill directory for the maps of `input`
+    pub fn create(input: &Path) -> Result<Self, String> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let dir = spill_root().join(format!("{}-{nanos}", std::process::id()));
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| format!("failed to create spill directory {}: {e}", dir.display()))?;
+        Ok(Spill {
+            dir,
+            input: input.to_path_buf(),
+            stem: input_stem(input),
+        })
+    }
+
+    /// writes every map under its output name, then the manifest that marks the run complete.
+    /// `header` should come from [`crate::io::read_header`], so write errors are returned rather
+    /// than panicking in the writers of array_lib
+    pub fn write(
+        &self,
+        maps: &[(String, &[f32])],
+        dims: ArrayDim,
+        header: &Header,
+    ) -> Result<(), String> {
+        for (suffix, vol) in maps {
+            write_volume(
+                output_path(&self.dir, &self.stem, suffix),
+                vol,
+                dims,
+                header,
+            )?;
+        }
+        let manifest = Manifest {
+            input: self.input.clone(),
+            created_unix_s: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            maps: maps.iter().map(|(suffix, _)| suffix.clone()).collect(),
+        };
+        let json = serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
+        let path = self.dir.join(MANIFEST);
+        std::fs::write(&path, json).map_err(|e| format!("failed to write {}: {e}", path.display()))
+    }
+
+    /// removes the spilled maps, once they have been written where they belong
+    pub fn discard(self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// a run spilled by an earlier session that never wrote its outputs
+#[derive(Debug, Clone)]
+pub struct SpilledRun {
+    pub dir: PathBuf,
+    pub input: PathBuf,
+    pub created_unix_s: u64,
+    pub maps: Vec<String>,
+}
+
+impl SpilledRun {
+    /// moves the maps into `output_dir` and removes the spill directory. Returns the paths of the
+    /// maps
+    pub fn recover(&self, output_dir: &Path) -> Result<Vec<PathBuf>, String> {
+        let manifest = self.dir.join(MANIFEST);
+        std::fs::remove_file(&manifest)
+            .map_err(|e| format!("failed to remove {}: {e}", manifest.display()))?;
+        Staging::adopt(&self.dir, output_dir).commit(&RetryPolicy::default())
+    }
+
+    pub fn discard(&self) -> Result<(), String> {
+        std::fs::remove_dir_all(&self.dir)
+            .map_err(|e| format!("failed to remove {}: {e}", self.dir.display()))
+    }
+}
+
+/// every complete spilled run, newest first. Runs spilled only in part are left alone, they may
+/// still be being written by another session
+pub fn find_spilled() -> Vec<SpilledRun> {
+    let Ok(entries) = std::fs::read_dir(spill_root()) else {
+        return vec![];
+    };
+    let mut runs: Vec<SpilledRun> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter_map(|dir| {
+            let text = std::fs::read_to_string(dir.join(MANIFEST)).ok()?;
+            let manifest: Manifest = serde_json::from_str(&text).ok()?;
+            Some(SpilledRun {
+                dir,
+                input: manifest.input,
+                created_unix_s: manifest.created_unix_s,
+                maps: manifest.maps,
+            })
+        })
+        .collect();
+    runs.sort_by_key(|r| std::cmp::Reverse(r.created_unix_s));
+    runs
+}
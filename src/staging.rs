@@ -0,0 +1,257 @@
+//! Outputs are written to a staging directory first and moved into the output directory once a
+//! case is complete, so a half written map never shows up under its final name.
+//!
+//! Moves within a file system are a rename. Moves onto another file system (a local staging
+//! directory and an SMB/NFS output share) copy to a `.partial` file, check its size against the
+//! staged file, then rename it into place, retrying with backoff when the share hiccups.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// total number of tries, including the first
+    pub attempts: u32,
+    /// wait before the first retry, doubled for every retry after that
+    pub initial_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 4,
+            initial_backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn run<T>(
+        &self,
+        what: &str,
+        mut f: impl FnMut() -> std::io::Result<T>,
+    ) -> Result<T, String> {
+        let mut backoff = self.initial_backoff;
+        let attempts = self.attempts.max(1);
+        for attempt in 1..=attempts {
+            match f() {
+                Ok(v) => return Ok(v),
+                Err(e) if attempt == attempts => {
+                    return Err(format!("{what} failed after {attempts} attempt(s): {e}"));
+                }
+                Err(e) => {
+                    eprintln!("{what} failed ({e}), retrying in {backoff:?}");
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                }
+            }
+        }
+        unreachable!()
+    }
+}
+
+pub struct Staging {
+    dir: PathBuf,
+    output_dir: PathBuf,
+    /// files that must never be replaced by an output, i.e. the inputs
+    protected: Vec<PathBuf>,
+}
+
+impl Staging {
+    /// creates a fresh staging directory under `stage_root`, or a hidden one inside the output
+    /// directory if no root is given
+    pub fn new(output_dir: &Path, stage_root: Option<&Path>) -> Result<Self, String> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let name = format!(".radmap-staging-{}-{nanos}", std::process::id());
+        let dir = stage_root.unwrap_or(output_dir).join(name);
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| format!("failed to create staging directory {}: {e}", dir.display()))?;
+        Ok(Staging {
+            dir,
+            output_dir: output_dir.to_path_buf(),
+            protected: vec![],
+        })
+    }
+
+    /// takes over a directory of finished outputs written elsewhere, like spilled maps, to move
+    /// them into `output_dir`
+    pub fn adopt(dir: &Path, output_dir: &Path) -> Self {
+        Staging {
+            dir: dir.to_path_buf(),
+            output_dir: output_dir.to_path_buf(),
+            protected: vec![],
+        }
+    }
+
+    /// refuses to replace this file when committing, even if an output ends up with its name
+    pub fn protect(&mut self, path: &Path) {
+        if let Ok(path) = path.canonicalize() {
+            self.protected.push(path);
+        }
+    }
+
+    /// where outputs should be written
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// removes the staging directory and everything staged in it, for outputs that must not be
+    /// committed
+    pub fn discard(&self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+
+    /// moves every staged file into the output directory and removes the staging directory.
+    /// Returns the final paths. On an error whatever is still staged is discarded, so a failed
+    /// commit leaves no staging directory behind
+    pub fn commit(self, policy: &RetryPolicy) -> Result<Vec<PathBuf>, String> {
+        let committed = self.move_staged(policy);
+        match &committed {
+            Ok(_) => {
+                let _ = std::fs::remove_dir(&self.dir);
+            }
+            Err(_) => self.discard(),
+        }
+        committed
+    }
+
+    fn move_staged(&self, policy: &RetryPolicy) -> Result<Vec<PathBuf>, String> {
+        let entries = std::fs::read_dir(&self.dir)
+            .map_err(|e| format!("failed to list {}: {e}", self.dir.display()))?;
+        let mut committed = vec![];
+        for entry in entries {
+            let src = entry
+                .map_err(|e| format!("failed to list {}: {e}", self.dir.display()))?
+                .path();
+            let dst = self.output_dir.join(src.file_name().unwrap());
+            if dst
+                .canonicalize()
+                .is_ok_and(|d| self.protected.contains(&d))
+            {
+                return Err(format!("refusing to overwrite input {}", dst.display()));
+            }
+            move_file(&src, &dst, policy)?;
+            committed.push(dst);
+        }
+        Ok(committed)
+    }
+}
+
+fn move_file(src: &Path, dst: &Path, policy: &RetryPolicy) -> Result<(), String> {
+    if std::fs::rename(src, dst).is_ok() {
+        return Ok(());
+    }
+    let expected = std::fs::metadata(src)
+        .map_err(|e| format!("failed to read {}: {e}", src.display()))?
+        .len();
+    let mut partial_name = dst.file_name().unwrap().to_os_string();
+    partial_name.push(".partial");
+    let partial = dst.with_file_name(partial_name);
+
+    policy.run(&format!("writing {}", dst.display()), || {
+        copy_checked(src, &partial, dst, expected)
+    })?;
+    let _ = std::fs::remove_file(src);
+    Ok(())
+}
+
+/// copies `src` to `partial` and renames it to `dst` once it holds all `expected` bytes
+fn copy_checked(src: &Path, partial: &Path, dst: &Path, expected: u64) -> std::io::Result<()> {
+    std::fs::copy(src, partial)?;
+    File::open(partial)?.sync_all()?;
+    let written = std::fs::metadata(partial)?.len();
+    if written != expected {
+        let _ = std::fs::remove_file(partial);
+        return Err(std::io::Error::other(format!(
+            "partial write, {written} of {expected} bytes"
+        )));
+    }
+    std::fs::rename(partial, dst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::io::{Error, ErrorKind};
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("radmap_staging_{name}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn quick(attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            attempts,
+            initial_backoff: Duration::from_millis(1),
+        }
+    }
+
+    #[test]
+    fn retries_until_it_succeeds() {
+        let tries = Cell::new(0);
+        let result = quick(4).run("flaky", || {
+            tries.set(tries.get() + 1);
+            if tries.get() < 3 {
+                Err(Error::new(ErrorKind::TimedOut, "share went away"))
+            } else {
+                Ok(tries.get())
+            }
+        });
+        assert_eq!(result, Ok(3));
+    }
+
+    #[test]
+    fn gives_up_after_the_last_attempt() {
+        let tries = Cell::new(0);
+        let result: Result<(), String> = quick(3).run("hopeless", || {
+            tries.set(tries.get() + 1);
+            Err(Error::new(ErrorKind::TimedOut, "share went away"))
+        });
+        assert_eq!(tries.get(), 3);
+        let e = result.unwrap_err();
+        assert!(e.contains("after 3 attempt(s)"), "{e}");
+    }
+
+    #[test]
+    fn a_short_copy_is_not_renamed_into_place() {
+        let dir = test_dir("short_copy");
+        let src = dir.join("map.nii");
+        let partial = dir.join("out.nii.partial");
+        let dst = dir.join("out.nii");
+        std::fs::write(&src, [0u8; 64]).unwrap();
+
+        let e = copy_checked(&src, &partial, &dst, 128).unwrap_err();
+        assert!(e.to_string().contains("64 of 128 bytes"), "{e}");
+        assert!(!partial.exists());
+        assert!(!dst.exists());
+
+        copy_checked(&src, &partial, &dst, 64).unwrap();
+        assert!(!partial.exists());
+        assert_eq!(std::fs::read(&dst).unwrap().len(), 64);
+    }
+
+    #[test]
+    fn a_refused_commit_leaves_no_staging_directory() {
+        let dir = test_dir("refused_commit");
+        let input = dir.join("case.nii");
+        std::fs::write(&input, b"input").unwrap();
+
+        let mut staging = Staging::new(&dir, None).unwrap();
+        staging.protect(&input);
+        std::fs::write(staging.dir().join("case.nii"), b"output").unwrap();
+        let staged = staging.dir().to_path_buf();
+
+        let e = staging.commit(&quick(1)).unwrap_err();
+        assert!(e.contains("refusing to overwrite input"), "{e}");
+        assert!(!staged.exists());
+        assert_eq!(std::fs::read(&input).unwrap(), b"input");
+    }
+}
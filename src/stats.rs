@@ -0,0 +1,42 @@
+//! Summary statistics of feature maps.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    /// number of finite values the summary is over
+    pub n: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub std: f64,
+}
+
+/// summary of the finite values, or None if there are none
+pub fn summarize(values: &[f64]) -> Option<Summary> {
+    let mut n = 0usize;
+    let mut min = f64::INFINITY;
+    let mut max = f64::NEG_INFINITY;
+    let mut sum = 0.;
+    for &x in values.iter().filter(|x| x.is_finite()) {
+        n += 1;
+        min = min.min(x);
+        max = max.max(x);
+        sum += x;
+    }
+    if n == 0 {
+        return None;
+    }
+    let mean = sum / n as f64;
+    let var = values
+        .iter()
+        .filter(|x| x.is_finite())
+        .map(|x| (x - mean).powi(2))
+        .sum::<f64>()
+        / n as f64;
+    Some(Summary {
+        n,
+        min,
+        max,
+        mean,
+        std: var.sqrt(),
+    })
+}
@@ -0,0 +1,141 @@
+//! Study database: one SQLite file shared by the cases of a study, to which every run appends
+//! the summary statistics of its feature maps as it finishes, so cohort analyses can query the
+//! accumulated results instead of crawling output directories.
+//!
+//! Each run adds a row to `cases`, with its input, mask, hashes, output directory and options
+//! hash, and a row per map to `feature_stats` with the number of finite voxels and their
+//! minimum, maximum, mean and standard deviation within the mask. The view `case_features`
+//! joins the two, e.g. `SELECT input, mean FROM case_features WHERE feature = 'contrast'`. Runs
+//! with scrubbed PHI leave their input and mask out, like in the history.
+
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection};
+
+use crate::provenance::Provenance;
+use crate::schema::{Format, UNVERSIONED};
+use crate::stats::summarize;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS cases (
+    id INTEGER PRIMARY KEY,
+    radmap_version TEXT NOT NULL,
+    finished_unix_s REAL NOT NULL,
+    input TEXT,
+    mask TEXT,
+    input_hash TEXT,
+    mask_hash TEXT,
+    output_dir TEXT NOT NULL,
+    options_hash TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS feature_stats (
+    case_id INTEGER NOT NULL REFERENCES cases(id),
+    feature TEXT NOT NULL,
+    n_voxels INTEGER NOT NULL,
+    min REAL,
+    max REAL,
+    mean REAL,
+    std REAL
+);
+CREATE INDEX IF NOT EXISTS feature_stats_case_id ON feature_stats(case_id);
+CREATE INDEX IF NOT EXISTS feature_stats_feature ON feature_stats(feature);
+CREATE VIEW IF NOT EXISTS case_features AS
+    SELECT cases.*, feature_stats.feature, feature_stats.n_voxels, feature_stats.min,
+        feature_stats.max, feature_stats.mean, feature_stats.std
+    FROM cases JOIN feature_stats ON feature_stats.case_id = cases.id;
+";
+
+pub struct StudyDb {
+    conn: Connection,
+}
+
+impl StudyDb {
+    /// opens the study database at `path`, creating it if needed
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let err = |e: rusqlite::Error| format!("failed to open {}: {e}", path.display());
+        let conn = Connection::open(path).map_err(err)?;
+        // cases of a batch finish in parallel and wait for each other
+        conn.busy_timeout(std::time::Duration::from_secs(30))
+            .map_err(err)?;
+        let version: u32 = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(err)?;
+        Format::StudyDatabase.check(version.max(UNVERSIONED), path.display())?;
+        conn.execute_batch(SCHEMA).map_err(err)?;
+        conn.pragma_update(None, "user_version", Format::StudyDatabase.current())
+            .map_err(err)?;
+        Ok(StudyDb { conn })
+    }
+
+    /// appends the case of `provenance`, written to `output_dir`, with the statistics of `maps`
+    /// over the voxels in `mask`, or every voxel without one. Returns the id of the case
+    pub fn append(
+        &mut self,
+        provenance: &Provenance,
+        output_dir: &Path,
+        maps: &[(String, &[f32])],
+        mask: Option<&[bool]>,
+    ) -> Result<i64, String> {
+        let err = |e: rusqlite::Error| format!("failed to append to the study database: {e}");
+        let (input, mask_path) = if provenance.phi_scrubbed {
+            (None, None)
+        } else {
+            (
+                Some(provenance.input.to_string_lossy().to_string()),
+                provenance
+                    .mask
+                    .as_ref()
+                    .map(|m| m.to_string_lossy().to_string()),
+            )
+        };
+        let finished = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or(0.);
+
+        // a case shows up with all of its features or not at all
+        let tx = self.conn.transaction().map_err(err)?;
+        tx.execute(
+            "INSERT INTO cases (radmap_version, finished_unix_s, input, mask, input_hash, mask_hash, output_dir, options_hash)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                env!("CARGO_PKG_VERSION"),
+                finished,
+                input,
+                mask_path,
+                provenance.input_hash,
+                provenance.mask_hash,
+                output_dir.to_string_lossy(),
+                provenance.options_hash(),
+            ],
+        )
+        .map_err(err)?;
+        let id = tx.last_insert_rowid();
+        for (name, map) in maps {
+            let values: Vec<f64> = map
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| mask.is_none_or(|m| m[*i]))
+                .map(|(_, v)| *v as f64)
+                .collect();
+            let summary = summarize(&values);
+            tx.execute(
+                "INSERT INTO feature_stats (case_id, feature, n_voxels, min, max, mean, std)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    id,
+                    name,
+                    summary.map_or(0, |s| s.n) as i64,
+                    summary.map(|s| s.min),
+                    summary.map(|s| s.max),
+                    summary.map(|s| s.mean),
+                    summary.map(|s| s.std),
+                ],
+            )
+            .map_err(err)?;
+        }
+        tx.commit().map_err(err)?;
+        Ok(id)
+    }
+}
@@ -0,0 +1,153 @@
+//! Export of the feature maps as a voxel table, `<stem>_voxels.<ext>`: one row per voxel of the
+//! mask, or of the volume without one, with its position `x`, `y` and `z` in voxels and a column
+//! per map. Tables of whole volumes run into millions of rows, so besides csv they can be
+//! written as an Arrow IPC file (Feather v2), which pandas, polars and pyarrow read by memory
+//! mapping it instead of parsing text.
+//!
+//! The Arrow file is uncompressed so it can be mapped, with the positions as uint32 and the
+//! features as float32, split into record batches of at most [`ARROW_BATCH_ROWS`] rows. The
+//! shape of the volume is kept in the schema metadata as `volume_shape`, `x,y,z`.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, Float32Array, RecordBatch, UInt32Array};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{DataType, Field, Schema};
+
+use crate::io::output_path;
+use crate::locale::ReportFormat;
+
+/// rows per record batch of the Arrow file
+pub const ARROW_BATCH_ROWS: usize = 1 << 20;
+
+/// file format of the voxel table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableFormat {
+    Csv,
+    /// Arrow IPC file, also known as Feather v2
+    Arrow,
+}
+
+impl TableFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TableFormat::Csv => "csv",
+            TableFormat::Arrow => "arrow",
+        }
+    }
+}
+
+impl std::fmt::Display for TableFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for TableFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(TableFormat::Csv),
+            "arrow" | "feather" | "ipc" => Ok(TableFormat::Arrow),
+            _ => Err(format!("unknown table format {s}, expected csv or arrow")),
+        }
+    }
+}
+
+/// writes the voxel table of `maps`, each of `shape` voxels (x, y, z), to `dir`, returning its
+/// path and number of rows. Only voxels in `mask` are written when there is one
+pub fn write_voxel_table(
+    dir: &Path,
+    stem: &OsStr,
+    maps: &[(String, &[f32])],
+    shape: [usize; 3],
+    mask: Option<&[bool]>,
+    table: TableFormat,
+    format: &ReportFormat,
+) -> Result<(PathBuf, usize), String> {
+    let n_voxels: usize = shape.iter().product();
+    let rows: Vec<usize> = (0..n_voxels)
+        .filter(|&i| mask.is_none_or(|m| m[i]))
+        .collect();
+    let path = output_path(dir, stem, &format!("voxels.{table}"));
+    let written = match table {
+        TableFormat::Csv => write_csv(&path, maps, shape, &rows, format),
+        TableFormat::Arrow => write_arrow(&path, maps, shape, &rows),
+    };
+    written.map_err(|e| format!("failed to write {}: {e}", path.display()))?;
+    Ok((path, rows.len()))
+}
+
+fn position(i: usize, shape: [usize; 3]) -> [usize; 3] {
+    [
+        i % shape[0],
+        i / shape[0] % shape[1],
+        i / (shape[0] * shape[1]),
+    ]
+}
+
+fn write_csv(
+    path: &Path,
+    maps: &[(String, &[f32])],
+    shape: [usize; 3],
+    rows: &[usize],
+    format: &ReportFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut f = BufWriter::new(File::create(path)?);
+    let mut header = vec!["x", "y", "z"];
+    header.extend(maps.iter().map(|(name, _)| name.as_str()));
+    writeln!(f, "{}", format.header(&header))?;
+    for &i in rows {
+        let mut row: Vec<String> = position(i, shape).map(|c| c.to_string()).to_vec();
+        row.extend(maps.iter().map(|(_, map)| format.float(map[i] as f64)));
+        writeln!(f, "{}", format.row(&row))?;
+    }
+    f.flush()?;
+    Ok(())
+}
+
+fn write_arrow(
+    path: &Path,
+    maps: &[(String, &[f32])],
+    shape: [usize; 3],
+    rows: &[usize],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut fields: Vec<Field> = ["x", "y", "z"]
+        .iter()
+        .map(|c| Field::new(*c, DataType::UInt32, false))
+        .collect();
+    fields.extend(
+        maps.iter()
+            .map(|(name, _)| Field::new(name, DataType::Float32, false)),
+    );
+    let metadata = HashMap::from([(
+        "volume_shape".to_string(),
+        format!("{},{},{}", shape[0], shape[1], shape[2]),
+    )]);
+    let schema = Arc::new(Schema::new(fields).with_metadata(metadata));
+
+    let mut writer = FileWriter::try_new(BufWriter::new(File::create(path)?), &schema)?;
+    for chunk in rows.chunks(ARROW_BATCH_ROWS) {
+        let mut columns: Vec<ArrayRef> = (0..3)
+            .map(|axis| {
+                let c = chunk.iter().map(|&i| position(i, shape)[axis] as u32);
+                Arc::new(UInt32Array::from_iter_values(c)) as ArrayRef
+            })
+            .collect();
+        columns.extend(maps.iter().map(|(_, map)| {
+            Arc::new(Float32Array::from_iter_values(
+                chunk.iter().map(|&i| map[i]),
+            )) as ArrayRef
+        }));
+        writer.write(&RecordBatch::try_new(schema.clone(), columns)?)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
@@ -0,0 +1,167 @@
+//! Progress of a run on the icon of the GUI, so it can be followed with the window minimized: the
+//! progress bar of the taskbar button on Windows and a percentage badge on the dock icon on
+//! macOS. Other platforms have nothing like it and are left with the window title, which the GUI
+//! sets itself.
+
+use raw_window_handle::HasWindowHandle;
+
+/// what the icon shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskbarState {
+    /// no run, the icon is left as it is
+    Idle,
+    /// a run that can't tell how far along it is, like while the inputs load
+    Busy,
+    /// percent done
+    Progress(u8),
+    Failed,
+}
+
+impl TaskbarState {
+    /// `Some` fraction of a run is `Progress`, `None` is `Busy`
+    pub fn running(fraction: Option<f32>) -> Self {
+        match fraction {
+            Some(f) => TaskbarState::Progress((f.clamp(0., 1.) * 100.) as u8),
+            None => TaskbarState::Busy,
+        }
+    }
+}
+
+/// the icon of one window
+pub struct Taskbar {
+    shown: TaskbarState,
+    icon: platform::Icon,
+}
+
+impl Taskbar {
+    pub fn new(window: &impl HasWindowHandle) -> Self {
+        Taskbar {
+            shown: TaskbarState::Idle,
+            icon: platform::Icon::new(window),
+        }
+    }
+
+    /// shows `state` on the icon. The OS is only called when the state changes, which this
+    /// returns
+    pub fn set(&mut self, state: TaskbarState) -> bool {
+        if state == self.shown {
+            return false;
+        }
+        self.shown = state;
+        self.icon.show(state);
+        true
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+    use windows::Win32::Foundation::HWND;
+    use windows::Win32::System::Com::{
+        CoCreateInstance, CoInitializeEx, CLSCTX_INPROC_SERVER, COINIT_APARTMENTTHREADED,
+    };
+    use windows::Win32::UI::Shell::{
+        ITaskbarList3, TaskbarList, TBPF_ERROR, TBPF_INDETERMINATE, TBPF_NOPROGRESS, TBPF_NORMAL,
+    };
+
+    use super::TaskbarState;
+
+    /// the taskbar button of the window, None if the taskbar can't be reached
+    pub struct Icon(Option<(ITaskbarList3, HWND)>);
+
+    impl Icon {
+        pub fn new(window: &impl HasWindowHandle) -> Self {
+            let hwnd = match window.window_handle().map(|h| h.as_raw()) {
+                Ok(RawWindowHandle::Win32(h)) => HWND(h.hwnd.get() as *mut _),
+                _ => return Icon(None),
+            };
+            let list = unsafe {
+                // COM is already set up on the GUI thread by the windowing, so this does nothing
+                let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+                let list: windows::core::Result<ITaskbarList3> =
+                    CoCreateInstance(&TaskbarList, None, CLSCTX_INPROC_SERVER);
+                list.and_then(|list| list.HrInit().map(|_| list))
+            };
+            match list {
+                Ok(list) => Icon(Some((list, hwnd))),
+                Err(e) => {
+                    eprintln!("taskbar progress is not available: {e}");
+                    Icon(None)
+                }
+            }
+        }
+
+        pub fn show(&self, state: TaskbarState) {
+            let Some((list, hwnd)) = &self.0 else {
+                return;
+            };
+            let hwnd = *hwnd;
+            // a taskbar that went away, like when explorer restarts, just shows nothing
+            let _ = unsafe {
+                match state {
+                    TaskbarState::Idle => list.SetProgressState(hwnd, TBPF_NOPROGRESS),
+                    TaskbarState::Busy => list.SetProgressState(hwnd, TBPF_INDETERMINATE),
+                    TaskbarState::Progress(percent) => list
+                        .SetProgressState(hwnd, TBPF_NORMAL)
+                        .and_then(|_| list.SetProgressValue(hwnd, percent as u64, 100)),
+                    TaskbarState::Failed => list
+                        .SetProgressState(hwnd, TBPF_ERROR)
+                        .and_then(|_| list.SetProgressValue(hwnd, 100, 100)),
+                }
+            };
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use objc2::MainThreadMarker;
+    use objc2_app_kit::NSApplication;
+    use objc2_foundation::NSString;
+    use raw_window_handle::HasWindowHandle;
+
+    use super::TaskbarState;
+
+    /// the dock icon of the application, which has the one window
+    pub struct Icon;
+
+    impl Icon {
+        pub fn new(_window: &impl HasWindowHandle) -> Self {
+            Icon
+        }
+
+        pub fn show(&self, state: TaskbarState) {
+            // AppKit is only used from the main thread, which runs the GUI
+            let Some(mtm) = MainThreadMarker::new() else {
+                return;
+            };
+            let badge = match state {
+                TaskbarState::Idle => None,
+                TaskbarState::Busy => Some("…".to_string()),
+                TaskbarState::Progress(percent) => Some(format!("{percent}%")),
+                TaskbarState::Failed => Some("!".to_string()),
+            };
+            let badge = badge.map(|b| NSString::from_str(&b));
+            NSApplication::sharedApplication(mtm)
+                .dockTile()
+                .setBadgeLabel(badge.as_deref());
+        }
+    }
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+mod platform {
+    use raw_window_handle::HasWindowHandle;
+
+    use super::TaskbarState;
+
+    pub struct Icon;
+
+    impl Icon {
+        pub fn new(_window: &impl HasWindowHandle) -> Self {
+            Icon
+        }
+
+        pub fn show(&self, _state: TaskbarState) {}
+    }
+}
@@ -0,0 +1,91 @@
+//! Export of the feature maps of a run as one stacked tensor for deep learning pipelines:
+//! `<stem>_tensor.npy`, float32 of shape (features, z, y, x) in C order, and
+//! `<stem>_tensor.json` naming its channels. The array loads with `numpy.load` and goes
+//! straight to `torch.from_numpy` or an ONNX Runtime session, with the features as the channel
+//! axis of a 3D convolution input once a batch axis is added.
+//!
+//! The maps are x fastest, which is the last axis in C order, so each map is written as is
+//! after the one before it. Voxels outside the mask are 0 like in the maps, NaN voxels stay NaN.
+
+use std::ffi::OsStr;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::io::output_path;
+
+/// `<stem>_tensor.json` as it is written
+#[derive(Serialize)]
+struct TensorJson<'a> {
+    tensor: String,
+    dtype: &'static str,
+    layout: [&'static str; 4],
+    shape: [usize; 4],
+    spacing_zyx: Option<[f64; 3]>,
+    channels: Vec<&'a str>,
+}
+
+/// npy format version 1.0
+const NPY_MAGIC: &[u8] = b"\x93NUMPY\x01\x00";
+
+/// writes the tensor of `maps`, each of `shape` voxels (x, y, z), and its channel json to
+/// `dir`, returning their paths. `spacing` is recorded in the json when known
+pub fn write_tensor(
+    dir: &Path,
+    stem: &OsStr,
+    maps: &[(String, &[f32])],
+    shape: [usize; 3],
+    spacing: Option<[f64; 3]>,
+) -> Result<[PathBuf; 2], String> {
+    let tensor_shape = [maps.len(), shape[2], shape[1], shape[0]];
+    let n_voxels: usize = shape.iter().product();
+
+    let npy = output_path(dir, stem, "tensor.npy");
+    let write_err = |e: std::io::Error| format!("failed to write {}: {e}", npy.display());
+    let mut f = BufWriter::new(std::fs::File::create(&npy).map_err(write_err)?);
+    f.write_all(&npy_header("<f4", &tensor_shape))
+        .map_err(write_err)?;
+    for (_, map) in maps {
+        for v in &map[..n_voxels] {
+            f.write_all(&v.to_le_bytes()).map_err(write_err)?;
+        }
+    }
+    f.flush().map_err(write_err)?;
+
+    let channels = TensorJson {
+        tensor: npy.file_name().unwrap().to_string_lossy().into_owned(),
+        dtype: "float32",
+        layout: ["channel", "z", "y", "x"],
+        shape: tensor_shape,
+        spacing_zyx: spacing.map(|[x, y, z]| [z, y, x]),
+        channels: maps.iter().map(|(name, _)| name.as_str()).collect(),
+    };
+    let text = serde_json::to_string_pretty(&channels)
+        .expect("tensor descriptions are always representable as json")
+        + "\n";
+    let channels_json = output_path(dir, stem, "tensor.json");
+    std::fs::write(&channels_json, text)
+        .map_err(|e| format!("failed to write {}: {e}", channels_json.display()))?;
+    Ok([npy, channels_json])
+}
+
+/// magic, version and the header dict of a C order array of `descr`, e.g. `<f4`, padded with
+/// spaces so the data starts on a multiple of 64 bytes
+pub(crate) fn npy_header(descr: &str, shape: &[usize]) -> Vec<u8> {
+    let dims: Vec<String> = shape.iter().map(|n| n.to_string()).collect();
+    // a 1-tuple needs its trailing comma
+    let trailing = if shape.len() == 1 { "," } else { "" };
+    let mut dict = format!(
+        "{{'descr': '{descr}', 'fortran_order': False, 'shape': ({}{trailing}), }}",
+        dims.join(", ")
+    );
+    // magic and version, the header length, the dict and its newline
+    let unpadded = NPY_MAGIC.len() + 2 + dict.len() + 1;
+    dict.push_str(&" ".repeat(unpadded.next_multiple_of(64) - unpadded));
+    dict.push('\n');
+    let mut header = NPY_MAGIC.to_vec();
+    header.extend((dict.len() as u16).to_le_bytes());
+    header.extend(dict.bytes());
+    header
+}
@@ -0,0 +1,1126 @@
+//! Reference information about every feature: what it measures, where its formula is given,
+//! the values it can take, its IBSI identifier and how costly its family is to map. Printed by
+//! `radmap features` and shown as tooltips in the GUI, and meant for pipelines that build their
+//! own feature pickers.
+
+use serde::Serialize;
+
+use super::firstorder::FirstOrderFeature;
+use super::fractal::FractalFeature;
+use super::gldm::GLDMFeature;
+use super::gldzm::GLDZMFeature;
+use super::glrlm::GLRLMFeature;
+use super::glszm::GLSZMFeature;
+use super::haralick::HaralickFeature;
+use super::lbp::{LBPFeature, N_CODES};
+use super::ngldm::NGLDMFeature;
+use super::ngtdm::NGTDMFeature;
+use super::percentile::PercentileFeature;
+use super::cooccurrence::is_computed;
+use super::{Family, Feature};
+
+const PYRADIOMICS_DOCS: &str = "https://pyradiomics.readthedocs.io/en/latest/features.html";
+const IBSI_DOCS: &str = "https://ibsi.readthedocs.io/en/latest/03_Image_features.html";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FeatureInfo {
+    /// name on the command line and in output names
+    pub name: String,
+    pub family: Family,
+    pub description: &'static str,
+    /// documentation of the formula
+    pub reference: String,
+    pub range: ValueRange,
+    /// identifier in the IBSI reference manual, for features the IBSI standardizes
+    pub ibsi: Option<&'static str>,
+    pub cost: Cost,
+    /// what to keep in mind when reading or comparing the maps
+    pub caveats: Vec<&'static str>,
+}
+
+/// bounds of the values of a feature, `None` where it is unbounded
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ValueRange {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl ValueRange {
+    const ANY: ValueRange = ValueRange::new(None, None);
+    const NON_NEGATIVE: ValueRange = ValueRange::new(Some(0.), None);
+    const AT_LEAST_ONE: ValueRange = ValueRange::new(Some(1.), None);
+    const UNIT: ValueRange = ValueRange::new(Some(0.), Some(1.));
+
+    const fn new(min: Option<f64>, max: Option<f64>) -> Self {
+        ValueRange { min, max }
+    }
+}
+
+impl std::fmt::Display for ValueRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.min {
+            Some(min) => write!(f, "[{min}, ")?,
+            None => f.write_str("(-inf, ")?,
+        }
+        match self.max {
+            Some(max) => write!(f, "{max}]"),
+            None => f.write_str("inf)"),
+        }
+    }
+}
+
+/// relative cost of mapping a family, per voxel of the same kernel. Every feature of a family
+/// is computed from the same matrix, so selecting more features of a family costs little
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Cost {
+    Low,
+    Medium,
+    High,
+}
+
+impl Cost {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Cost::Low => "low",
+            Cost::Medium => "medium",
+            Cost::High => "high",
+        }
+    }
+}
+
+impl std::fmt::Display for Cost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Family {
+    pub fn cost(&self) -> Cost {
+        match self {
+            // 13 matrices of n_bins² entries per voxel
+            Family::Glcm => Cost::High,
+            Family::Glrlm | Family::Glszm | Family::Gldzm => Cost::Medium,
+            // the NGLDM at its default distance of 1
+            Family::Ngtdm
+            | Family::Gldm
+            | Family::Ngldm
+            | Family::FirstOrder
+            | Family::Percentile
+            | Family::Lbp => Cost::Low,
+            // boxes of every size up to the kernel, each over its corners
+            Family::Fractal => Cost::Medium,
+            // 13 matrices per voxel, each with an eigenvalue problem
+            Family::Haralick => Cost::High,
+        }
+    }
+
+    /// rough guess of the seconds a single thread takes to map every feature of the family at
+    /// one voxel, good for comparing families and the order of magnitude of a run rather than
+    /// for timing it. Every family scales with the voxels of the kernel, GLCM also with the size
+    /// of its 13 matrices. `cargo bench --bench family_costs` measures the real cost to check
+    /// the coefficients against
+    pub fn seconds_per_voxel(&self, n_bins: usize, kernel_radius: usize) -> f64 {
+        let kernel = ((2 * kernel_radius + 1) as f64).powi(3);
+        // seconds per voxel, per voxel of the kernel and per matrix entry
+        let (fixed, per_kernel_voxel, per_entry) = match self {
+            Family::Glcm => (0., 2.0e-7, 1.5e-8),
+            Family::Glrlm => (1.4e-6, 1.2e-7, 0.),
+            // the same flood fill, the distances of the GLDZM are found once for the volume
+            Family::Glszm | Family::Gldzm => (0.7e-6, 1.45e-7, 0.),
+            Family::Ngtdm => (1.7e-6, 8.8e-8, 0.),
+            // the same matrix, the NGLDM at a distance of 1
+            Family::Gldm | Family::Ngldm => (2.4e-6, 8.0e-8, 0.),
+            Family::FirstOrder => (0.85e-6, 9.5e-9, 0.),
+            // sorting the intensities of the kernel
+            Family::Percentile => (0.8e-6, 3.5e-8, 0.),
+            // the codes of the volume are found once, before the kernels
+            Family::Lbp => (2.2e-6, 5.0e-9, 0.),
+            Family::Fractal => (1.0e-6, 1.3e-7, 0.),
+            // the matrices only hold the gray levels present, so the eigenvalue problems grow
+            // with the kernel rather than the bins
+            Family::Haralick => (2.0e-6, 1.2e-6, 0.),
+        };
+        fixed + per_kernel_voxel * kernel + per_entry * (n_bins * n_bins) as f64
+    }
+
+    /// [`Family::seconds_per_voxel`] relative to the cheapest family with the same options
+    pub fn relative_cost(&self, n_bins: usize, kernel_radius: usize) -> f64 {
+        let cheapest = Family::ALL
+            .iter()
+            .map(|f| f.seconds_per_voxel(n_bins, kernel_radius))
+            .fold(f64::INFINITY, f64::min);
+        self.seconds_per_voxel(n_bins, kernel_radius) / cheapest
+    }
+}
+
+/// entry of the tables below: description, name of the pyradiomics feature, IBSI identifier and
+/// range. An empty pyradiomics name refers to the docs of the whole family
+type Entry = (&'static str, &'static str, Option<&'static str>, ValueRange);
+
+impl Feature {
+    pub fn info(&self) -> FeatureInfo {
+        let family = self.family();
+        let (description, pyradiomics, ibsi, range) = match self {
+            Feature::Glcm(_) => glcm_entry(&self.name()),
+            Feature::Glrlm(f) => glrlm_entry(*f),
+            Feature::Glszm(f) => glszm_entry(*f),
+            Feature::Gldzm(f) => gldzm_entry(*f),
+            Feature::Ngtdm(f) => ngtdm_entry(*f),
+            Feature::Gldm(f) => gldm_entry(*f),
+            Feature::Ngldm(f) => ngldm_entry(*f),
+            Feature::FirstOrder(f) => firstorder_entry(*f),
+            Feature::Percentile(f) => percentile_entry(*f),
+            Feature::Lbp(f) => lbp_entry(*f),
+            Feature::Fractal(f) => fractal_entry(*f),
+            Feature::Haralick(f) => haralick_entry(*f),
+        };
+        // pyradiomics has the percentiles among its first-order features, and the MCC among
+        // its GLCM features
+        let module = match family {
+            Family::Percentile => Family::FirstOrder.as_str(),
+            Family::Haralick => Family::Glcm.as_str(),
+            _ => family.as_str(),
+        };
+        let class = match family {
+            Family::FirstOrder | Family::Percentile => "FirstOrder".to_string(),
+            _ => module.to_uppercase(),
+        };
+        let reference = if family == Family::Lbp {
+            // pyradiomics has no LBP features, only an LBP filter encoding patterns differently
+            "the documentation of the radmap::texture::lbp module".to_string()
+        } else if family == Family::Gldzm {
+            // pyradiomics has no GLDZM features
+            format!("{IBSI_DOCS}#grey-level-distance-zone-based-features")
+        } else if family == Family::Ngldm {
+            // the IBSI version of the GLDM of pyradiomics
+            format!("{IBSI_DOCS}#neighbouring-grey-level-dependence-based-features")
+        } else if family == Family::Fractal {
+            "Sarkar and Chaudhuri, An efficient differential box-counting approach to compute \
+             fractal dimension of image, IEEE Trans. Syst. Man Cybern. 24(1), 1994"
+                .to_string()
+        } else if pyradiomics.is_empty() {
+            format!("{PYRADIOMICS_DOCS}#module-radiomics.{module}")
+        } else {
+            format!(
+                "{PYRADIOMICS_DOCS}#radiomics.{module}.Radiomics{class}.get{pyradiomics}FeatureValue"
+            )
+        };
+        FeatureInfo {
+            name: self.to_string(),
+            family,
+            description,
+            reference,
+            range,
+            ibsi,
+            cost: family.cost(),
+            caveats: self.caveats(),
+        }
+    }
+
+    fn caveats(&self) -> Vec<&'static str> {
+        let mut caveats = vec![];
+        let on_gray_levels = match self {
+            Feature::FirstOrder(f) => *f == FirstOrderFeature::Entropy,
+            Feature::Percentile(_) | Feature::Lbp(_) | Feature::Fractal(_) => false,
+            _ => true,
+        };
+        if on_gray_levels {
+            caveats.push(
+                "computed from binned gray levels, so values change with the number of bins and \
+                 the bin edges",
+            );
+        }
+        caveats.push(
+            "kernels are cut off at the edges of the volume, so voxels near the edges are \
+             computed from fewer voxels",
+        );
+        match self {
+            Feature::Glcm(g) if is_computed(*g) => caveats.push(
+                "averaged over the co-occurrence matrices along the 13 directions of the kernel, \
+                 by default at a distance of 1",
+            ),
+            Feature::Glcm(_) => caveats.push(
+                "computed by the glcm crate from the co-occurrences at a distance of 1 along the \
+                 13 directions of the kernel",
+            ),
+            Feature::Glrlm(GLRLMFeature::GrayLevelNonUniformity)
+            | Feature::Glrlm(GLRLMFeature::RunLengthNonUniformity)
+            | Feature::Glszm(GLSZMFeature::GrayLevelNonUniformity)
+            | Feature::Glszm(GLSZMFeature::ZoneSizeNonUniformity)
+            | Feature::Gldzm(GLDZMFeature::GrayLevelNonUniformity)
+            | Feature::Gldzm(GLDZMFeature::ZoneDistanceNonUniformity)
+            | Feature::Gldm(GLDMFeature::GrayLevelNonUniformity)
+            | Feature::Gldm(GLDMFeature::DependenceNonUniformity)
+            | Feature::Ngldm(NGLDMFeature::GrayLevelNonUniformity)
+            | Feature::Ngldm(NGLDMFeature::DependenceCountNonUniformity) => caveats.push(
+                "not normalized, so it grows with the kernel size. Only compare maps of the same \
+                 kernel radius, or use the normalized variant",
+            ),
+            Feature::Ngtdm(NGTDMFeature::Coarseness) => {
+                caveats.push("10^6 in kernels without any gray tone differences, like pyradiomics")
+            }
+            Feature::FirstOrder(FirstOrderFeature::Skewness) => {
+                caveats.push("0 in kernels of a single intensity")
+            }
+            Feature::FirstOrder(FirstOrderFeature::Kurtosis) => caveats.push(
+                "the excess kurtosis of the IBSI, 3 less than the kurtosis pyradiomics reports. 0 \
+                 in kernels of a single intensity",
+            ),
+            Feature::FirstOrder(FirstOrderFeature::Mean | FirstOrderFeature::Variance) => {
+                caveats.push("in the units of the input intensities")
+            }
+            Feature::FirstOrder(FirstOrderFeature::Energy) => caveats.push(
+                "in the squared units of the input intensities and grows with the kernel size. \
+                 pyradiomics shifts the intensities before squaring them",
+            ),
+            Feature::Percentile(_) => caveats.push(
+                "in the units of the input intensities, interpolated linearly between the sorted \
+                 intensities of the kernel like numpy.percentile",
+            ),
+            Feature::Lbp(_) => caveats.push(
+                "patterns are sampled at a radius of 1 voxel, not scaled by the voxel spacing, \
+                 and reach 1 voxel beyond the kernel",
+            ),
+            Feature::Gldzm(_) => caveats.push(
+                "distances are counted in steps between voxels to the edge of the mask, or of the \
+                 volume without a mask, not scaled by the voxel spacing. Changes with the mask, \
+                 and voxels outside the mask count as on its edge",
+            ),
+            Feature::Ngldm(NGLDMFeature::DependenceCountPercentage) => caveats.push(
+                "always 1, as every voxel of the kernel is counted with its neighbourhood cut to \
+                 the kernel. Kept for completeness with the IBSI",
+            ),
+            Feature::Fractal(_) => caveats.push(
+                "intensities are scaled by their range over the whole volume, so outliers flatten \
+                 every kernel. Can drop below 3 along a single sharp edge. A kernel radius of 1 \
+                 only gives two box sizes, use 2 or more for a stable estimate",
+            ),
+            Feature::Haralick(HaralickFeature::Mcc) => caveats.push(
+                "averaged over the 13 directions of the kernel like pyradiomics, from matrices \
+                 built by radmap rather than the glcm crate. 1 in kernels of a single gray level",
+            ),
+            _ => {}
+        }
+        if self.family() == Family::Gldm {
+            caveats.push("changes with the GLDM alpha, recorded in the provenance sidecar");
+        }
+        if self.family() == Family::Ngldm {
+            caveats.push(
+                "changes with the NGLDM alpha and distance, recorded in the provenance sidecar. \
+                 Neighbourhoods are cut to the kernel, so distances beyond the kernel radius \
+                 change nothing, and mapping takes about (2d + 1)³ / 27 times as long at a \
+                 distance of d",
+            );
+        }
+        caveats
+    }
+}
+
+/// information on every feature, family by family
+pub fn catalog() -> Vec<FeatureInfo> {
+    Feature::all().iter().map(|f| f.info()).collect()
+}
+
+/// GLCM features are defined by the glcm crate, so they are looked up by name
+fn glcm_entry(name: &str) -> Entry {
+    use ValueRange as R;
+    match name {
+        "autocorrelation" => (
+            "mean product of the gray levels of co-occurring voxels",
+            "Autocorrelation",
+            Some("QWB0"),
+            R::AT_LEAST_ONE,
+        ),
+        "cluster_prominence" => (
+            "fourth moment of the sum of gray levels about twice the mean, large for asymmetric textures",
+            "ClusterProminence",
+            Some("AE86"),
+            R::NON_NEGATIVE,
+        ),
+        "cluster_shade" => (
+            "third moment of the sum of gray levels about twice the mean, the skewness of the matrix",
+            "ClusterShade",
+            Some("7NFM"),
+            R::ANY,
+        ),
+        "cluster_tendency" => (
+            "second moment of the sum of gray levels about twice the mean",
+            "ClusterTendency",
+            Some("DG8W"),
+            R::NON_NEGATIVE,
+        ),
+        "contrast" => (
+            "mean squared gray level difference of co-occurring voxels, large for sharp local variation",
+            "Contrast",
+            Some("ACUI"),
+            R::NON_NEGATIVE,
+        ),
+        "correlation" => (
+            "linear dependence of the gray levels of co-occurring voxels",
+            "Correlation",
+            Some("NI2N"),
+            ValueRange::new(Some(-1.), Some(1.)),
+        ),
+        "difference_average" => (
+            "mean absolute gray level difference of co-occurring voxels",
+            "DifferenceAverage",
+            Some("TF7R"),
+            R::NON_NEGATIVE,
+        ),
+        "difference_entropy" => (
+            "randomness of the gray level differences of co-occurring voxels",
+            "DifferenceEntropy",
+            Some("NTRS"),
+            R::NON_NEGATIVE,
+        ),
+        "difference_variance" => (
+            "spread of the gray level differences of co-occurring voxels",
+            "DifferenceVariance",
+            Some("D3YU"),
+            R::NON_NEGATIVE,
+        ),
+        "dissimilarity" => (
+            "mean absolute gray level difference of co-occurring voxels",
+            "DifferenceAverage",
+            Some("8S9J"),
+            R::NON_NEGATIVE,
+        ),
+        "energy" | "joint_energy" => (
+            "sum of the squared matrix entries, large for uniform textures",
+            "JointEnergy",
+            Some("8ZQL"),
+            R::UNIT,
+        ),
+        "entropy" | "joint_entropy" => (
+            "randomness of the gray levels of co-occurring voxels",
+            "JointEntropy",
+            Some("TU9B"),
+            R::NON_NEGATIVE,
+        ),
+        "homogeneity" | "inverse_difference" | "id" => (
+            "closeness of co-occurring gray levels, large for smooth textures",
+            "Id",
+            Some("IB1Z"),
+            R::UNIT,
+        ),
+        "inverse_difference_moment" | "idm" => (
+            "closeness of co-occurring gray levels, weighting differences by their square",
+            "Idm",
+            Some("WF0Z"),
+            R::UNIT,
+        ),
+        "inverse_variance" => (
+            "co-occurrences of different gray levels weighted by their inverse squared difference",
+            "InverseVariance",
+            Some("E8JP"),
+            R::UNIT,
+        ),
+        "maximum_probability" | "joint_maximum" => (
+            "probability of the most frequent pair of co-occurring gray levels",
+            "MaximumProbability",
+            Some("GYBY"),
+            R::UNIT,
+        ),
+        "sum_average" => (
+            "mean sum of the gray levels of co-occurring voxels",
+            "SumAverage",
+            Some("ZGXS"),
+            ValueRange::new(Some(2.), None),
+        ),
+        "sum_entropy" => (
+            "randomness of the gray level sums of co-occurring voxels",
+            "SumEntropy",
+            Some("P6QZ"),
+            R::NON_NEGATIVE,
+        ),
+        "sum_squares" | "joint_variance" => (
+            "spread of the gray levels about the mean of the matrix",
+            "SumSquares",
+            Some("UR99"),
+            R::NON_NEGATIVE,
+        ),
+        "imc1" => (
+            "informational measure of correlation, mutual information of co-occurring gray levels relative to their entropy",
+            "Imc1",
+            Some("R8DG"),
+            ValueRange::new(Some(-1.), Some(0.)),
+        ),
+        "imc2" => (
+            "informational measure of correlation, from the gap between joint and independent entropies",
+            "Imc2",
+            Some("JN9H"),
+            R::UNIT,
+        ),
+        _ => (
+            "gray level co-occurrence feature mapped by the glcm crate",
+            "",
+            None,
+            R::ANY,
+        ),
+    }
+}
+
+fn glrlm_entry(f: GLRLMFeature) -> Entry {
+    use GLRLMFeature::*;
+    use ValueRange as R;
+    match f {
+        ShortRunEmphasis => (
+            "weight of short runs, large for fine textures",
+            "ShortRunEmphasis",
+            Some("22OV"),
+            R::UNIT,
+        ),
+        LongRunEmphasis => (
+            "weight of long runs, large for coarse textures",
+            "LongRunEmphasis",
+            Some("W4KF"),
+            R::AT_LEAST_ONE,
+        ),
+        GrayLevelNonUniformity => (
+            "how unevenly runs are spread over the gray levels",
+            "GrayLevelNonUniformity",
+            Some("R5YN"),
+            R::NON_NEGATIVE,
+        ),
+        GrayLevelNonUniformityNormalized => (
+            "gray level non-uniformity divided by the number of runs",
+            "GrayLevelNonUniformityNormalized",
+            Some("OVBL"),
+            R::UNIT,
+        ),
+        RunLengthNonUniformity => (
+            "how unevenly runs are spread over the run lengths",
+            "RunLengthNonUniformity",
+            Some("W92Y"),
+            R::NON_NEGATIVE,
+        ),
+        RunLengthNonUniformityNormalized => (
+            "run length non-uniformity divided by the number of runs",
+            "RunLengthNonUniformityNormalized",
+            Some("IC23"),
+            R::UNIT,
+        ),
+        RunPercentage => (
+            "number of runs per voxel of the kernel, large for fine textures",
+            "RunPercentage",
+            Some("9ZK5"),
+            R::UNIT,
+        ),
+        GrayLevelVariance => (
+            "variance of the gray levels of the runs",
+            "GrayLevelVariance",
+            Some("8CE5"),
+            R::NON_NEGATIVE,
+        ),
+        RunLengthVariance => (
+            "variance of the run lengths",
+            "RunVariance",
+            Some("SXLW"),
+            R::NON_NEGATIVE,
+        ),
+        RunEntropy => (
+            "randomness of the gray levels and lengths of the runs",
+            "RunEntropy",
+            Some("HJ9O"),
+            R::NON_NEGATIVE,
+        ),
+        LowGrayLevelRunEmphasis => (
+            "weight of runs of low gray levels",
+            "LowGrayLevelRunEmphasis",
+            Some("V3SW"),
+            R::UNIT,
+        ),
+        HighGrayLevelRunEmphasis => (
+            "weight of runs of high gray levels",
+            "HighGrayLevelRunEmphasis",
+            Some("G3QZ"),
+            R::AT_LEAST_ONE,
+        ),
+        ShortRunLowGrayLevelEmphasis => (
+            "weight of short runs of low gray levels",
+            "ShortRunLowGrayLevelEmphasis",
+            Some("HTZT"),
+            R::UNIT,
+        ),
+        ShortRunHighGrayLevelEmphasis => (
+            "weight of short runs of high gray levels",
+            "ShortRunHighGrayLevelEmphasis",
+            Some("GD3A"),
+            R::NON_NEGATIVE,
+        ),
+        LongRunLowGrayLevelEmphasis => (
+            "weight of long runs of low gray levels",
+            "LongRunLowGrayLevelEmphasis",
+            Some("IVPO"),
+            R::NON_NEGATIVE,
+        ),
+        LongRunHighGrayLevelEmphasis => (
+            "weight of long runs of high gray levels",
+            "LongRunHighGrayLevelEmphasis",
+            Some("3KUM"),
+            R::AT_LEAST_ONE,
+        ),
+    }
+}
+
+fn glszm_entry(f: GLSZMFeature) -> Entry {
+    use GLSZMFeature::*;
+    use ValueRange as R;
+    match f {
+        SmallZoneEmphasis => (
+            "weight of small zones, large for fine textures",
+            "SmallAreaEmphasis",
+            Some("5QRC"),
+            R::UNIT,
+        ),
+        LargeZoneEmphasis => (
+            "weight of large zones, large for coarse textures",
+            "LargeAreaEmphasis",
+            Some("48P8"),
+            R::AT_LEAST_ONE,
+        ),
+        GrayLevelNonUniformity => (
+            "how unevenly zones are spread over the gray levels",
+            "GrayLevelNonUniformity",
+            Some("JNSA"),
+            R::NON_NEGATIVE,
+        ),
+        GrayLevelNonUniformityNormalized => (
+            "gray level non-uniformity divided by the number of zones",
+            "GrayLevelNonUniformityNormalized",
+            Some("Y1RO"),
+            R::UNIT,
+        ),
+        ZoneSizeNonUniformity => (
+            "how unevenly zones are spread over the zone sizes",
+            "SizeZoneNonUniformity",
+            Some("4JP3"),
+            R::NON_NEGATIVE,
+        ),
+        ZoneSizeNonUniformityNormalized => (
+            "zone size non-uniformity divided by the number of zones",
+            "SizeZoneNonUniformityNormalized",
+            Some("VB3A"),
+            R::UNIT,
+        ),
+        ZonePercentage => (
+            "number of zones per voxel of the kernel, large for fine textures",
+            "ZonePercentage",
+            Some("P30P"),
+            R::UNIT,
+        ),
+        GrayLevelVariance => (
+            "variance of the gray levels of the zones",
+            "GrayLevelVariance",
+            Some("BYLV"),
+            R::NON_NEGATIVE,
+        ),
+        ZoneSizeVariance => (
+            "variance of the zone sizes",
+            "ZoneVariance",
+            Some("3NSA"),
+            R::NON_NEGATIVE,
+        ),
+        ZoneSizeEntropy => (
+            "randomness of the gray levels and sizes of the zones",
+            "ZoneEntropy",
+            Some("GU8N"),
+            R::NON_NEGATIVE,
+        ),
+        LowGrayLevelZoneEmphasis => (
+            "weight of zones of low gray levels",
+            "LowGrayLevelZoneEmphasis",
+            Some("XMSY"),
+            R::UNIT,
+        ),
+        HighGrayLevelZoneEmphasis => (
+            "weight of zones of high gray levels",
+            "HighGrayLevelZoneEmphasis",
+            Some("5GN9"),
+            R::AT_LEAST_ONE,
+        ),
+        SmallZoneLowGrayLevelEmphasis => (
+            "weight of small zones of low gray levels",
+            "SmallAreaLowGrayLevelEmphasis",
+            Some("5RAI"),
+            R::UNIT,
+        ),
+        SmallZoneHighGrayLevelEmphasis => (
+            "weight of small zones of high gray levels",
+            "SmallAreaHighGrayLevelEmphasis",
+            Some("HW1V"),
+            R::NON_NEGATIVE,
+        ),
+        LargeZoneLowGrayLevelEmphasis => (
+            "weight of large zones of low gray levels",
+            "LargeAreaLowGrayLevelEmphasis",
+            Some("YH51"),
+            R::NON_NEGATIVE,
+        ),
+        LargeZoneHighGrayLevelEmphasis => (
+            "weight of large zones of high gray levels",
+            "LargeAreaHighGrayLevelEmphasis",
+            Some("J17V"),
+            R::AT_LEAST_ONE,
+        ),
+    }
+}
+
+fn gldzm_entry(f: GLDZMFeature) -> Entry {
+    use GLDZMFeature::*;
+    use ValueRange as R;
+    match f {
+        SmallDistanceEmphasis => (
+            "weight of zones close to the edge of the region",
+            "",
+            Some("0GBI"),
+            R::UNIT,
+        ),
+        LargeDistanceEmphasis => (
+            "weight of zones deep inside the region",
+            "",
+            Some("MB4I"),
+            R::AT_LEAST_ONE,
+        ),
+        GrayLevelNonUniformity => (
+            "how unevenly zones are spread over the gray levels",
+            "",
+            Some("VFT7"),
+            R::NON_NEGATIVE,
+        ),
+        GrayLevelNonUniformityNormalized => (
+            "gray level non-uniformity divided by the number of zones",
+            "",
+            Some("7HP3"),
+            R::UNIT,
+        ),
+        ZoneDistanceNonUniformity => (
+            "how unevenly zones are spread over the distances",
+            "",
+            Some("V294"),
+            R::NON_NEGATIVE,
+        ),
+        ZoneDistanceNonUniformityNormalized => (
+            "zone distance non-uniformity divided by the number of zones",
+            "",
+            Some("IATH"),
+            R::UNIT,
+        ),
+        ZonePercentage => (
+            "number of zones per voxel of the kernel, large for fine textures",
+            "",
+            Some("VIWW"),
+            R::UNIT,
+        ),
+        GrayLevelVariance => (
+            "variance of the gray levels of the zones",
+            "",
+            Some("QK93"),
+            R::NON_NEGATIVE,
+        ),
+        ZoneDistanceVariance => (
+            "variance of the zone distances",
+            "",
+            Some("7WT1"),
+            R::NON_NEGATIVE,
+        ),
+        ZoneDistanceEntropy => (
+            "randomness of the gray levels and distances of the zones",
+            "",
+            Some("GBDU"),
+            R::NON_NEGATIVE,
+        ),
+        LowGrayLevelZoneEmphasis => (
+            "weight of zones of low gray levels",
+            "",
+            Some("S1RA"),
+            R::UNIT,
+        ),
+        HighGrayLevelZoneEmphasis => (
+            "weight of zones of high gray levels",
+            "",
+            Some("K26C"),
+            R::AT_LEAST_ONE,
+        ),
+        SmallDistanceLowGrayLevelEmphasis => (
+            "weight of zones of low gray levels close to the edge",
+            "",
+            Some("RUVG"),
+            R::UNIT,
+        ),
+        SmallDistanceHighGrayLevelEmphasis => (
+            "weight of zones of high gray levels close to the edge",
+            "",
+            Some("DKNJ"),
+            R::NON_NEGATIVE,
+        ),
+        LargeDistanceLowGrayLevelEmphasis => (
+            "weight of zones of low gray levels deep inside the region",
+            "",
+            Some("A7WM"),
+            R::NON_NEGATIVE,
+        ),
+        LargeDistanceHighGrayLevelEmphasis => (
+            "weight of zones of high gray levels deep inside the region",
+            "",
+            Some("KLTH"),
+            R::AT_LEAST_ONE,
+        ),
+    }
+}
+
+fn ngtdm_entry(f: NGTDMFeature) -> Entry {
+    use NGTDMFeature::*;
+    use ValueRange as R;
+    match f {
+        Coarseness => (
+            "inverse of the summed differences to the neighbourhood, large for uniform regions",
+            "Coarseness",
+            Some("QCDE"),
+            ValueRange::new(Some(0.), Some(1e6)),
+        ),
+        Contrast => (
+            "spread of the gray levels combined with the differences to the neighbourhood",
+            "Contrast",
+            Some("65HE"),
+            R::NON_NEGATIVE,
+        ),
+        Busyness => (
+            "rapid changes of gray level between neighbours",
+            "Busyness",
+            Some("NQ30"),
+            R::NON_NEGATIVE,
+        ),
+        Complexity => (
+            "how many rapid, non-uniform changes of gray level there are",
+            "Complexity",
+            Some("HDEZ"),
+            R::NON_NEGATIVE,
+        ),
+        Strength => (
+            "how large and distinct the primitives of the texture are",
+            "Strength",
+            Some("1X9X"),
+            R::NON_NEGATIVE,
+        ),
+    }
+}
+
+/// the IBSI calls the dependence matrix the neighbouring gray level dependence matrix
+fn gldm_entry(f: GLDMFeature) -> Entry {
+    use GLDMFeature::*;
+    use ValueRange as R;
+    match f {
+        SmallDependenceEmphasis => (
+            "weight of voxels with few dependent neighbours, large for heterogeneous textures",
+            "SmallDependenceEmphasis",
+            Some("SODN"),
+            R::UNIT,
+        ),
+        LargeDependenceEmphasis => (
+            "weight of voxels with many dependent neighbours, large for homogeneous textures",
+            "LargeDependenceEmphasis",
+            Some("IMOQ"),
+            R::AT_LEAST_ONE,
+        ),
+        GrayLevelNonUniformity => (
+            "how unevenly voxels are spread over the gray levels",
+            "GrayLevelNonUniformity",
+            Some("FP8K"),
+            R::NON_NEGATIVE,
+        ),
+        DependenceNonUniformity => (
+            "how unevenly voxels are spread over the dependence sizes",
+            "DependenceNonUniformity",
+            Some("Z87G"),
+            R::NON_NEGATIVE,
+        ),
+        DependenceNonUniformityNormalized => (
+            "dependence non-uniformity divided by the number of voxels",
+            "DependenceNonUniformityNormalized",
+            Some("OKJI"),
+            R::UNIT,
+        ),
+        GrayLevelVariance => (
+            "variance of the gray levels",
+            "GrayLevelVariance",
+            Some("1PFV"),
+            R::NON_NEGATIVE,
+        ),
+        DependenceVariance => (
+            "variance of the dependence sizes",
+            "DependenceVariance",
+            Some("DNX2"),
+            R::NON_NEGATIVE,
+        ),
+        DependenceEntropy => (
+            "randomness of the gray levels and dependence sizes",
+            "DependenceEntropy",
+            Some("FCBV"),
+            R::NON_NEGATIVE,
+        ),
+        LowGrayLevelEmphasis => (
+            "weight of low gray levels",
+            "LowGrayLevelEmphasis",
+            Some("TL9H"),
+            R::UNIT,
+        ),
+        HighGrayLevelEmphasis => (
+            "weight of high gray levels",
+            "HighGrayLevelEmphasis",
+            Some("OAE7"),
+            R::AT_LEAST_ONE,
+        ),
+        SmallDependenceLowGrayLevelEmphasis => (
+            "weight of low gray levels with few dependent neighbours",
+            "SmallDependenceLowGrayLevelEmphasis",
+            Some("EQ3F"),
+            R::UNIT,
+        ),
+        SmallDependenceHighGrayLevelEmphasis => (
+            "weight of high gray levels with few dependent neighbours",
+            "SmallDependenceHighGrayLevelEmphasis",
+            Some("JA6D"),
+            R::NON_NEGATIVE,
+        ),
+        LargeDependenceLowGrayLevelEmphasis => (
+            "weight of low gray levels with many dependent neighbours",
+            "LargeDependenceLowGrayLevelEmphasis",
+            Some("NBZI"),
+            R::NON_NEGATIVE,
+        ),
+        LargeDependenceHighGrayLevelEmphasis => (
+            "weight of high gray levels with many dependent neighbours",
+            "LargeDependenceHighGrayLevelEmphasis",
+            Some("9QMG"),
+            R::AT_LEAST_ONE,
+        ),
+    }
+}
+
+fn ngldm_entry(f: NGLDMFeature) -> Entry {
+    use NGLDMFeature::*;
+    use ValueRange as R;
+    match f {
+        LowDependenceEmphasis => (
+            "weight of voxels with few dependent neighbours, large for heterogeneous textures",
+            "",
+            Some("SODN"),
+            R::UNIT,
+        ),
+        HighDependenceEmphasis => (
+            "weight of voxels with many dependent neighbours, large for homogeneous textures",
+            "",
+            Some("IMOQ"),
+            R::AT_LEAST_ONE,
+        ),
+        LowGrayLevelCountEmphasis => ("weight of low gray levels", "", Some("TL9H"), R::UNIT),
+        HighGrayLevelCountEmphasis => (
+            "weight of high gray levels",
+            "",
+            Some("OAE7"),
+            R::AT_LEAST_ONE,
+        ),
+        LowDependenceLowGrayLevelEmphasis => (
+            "weight of low gray levels with few dependent neighbours",
+            "",
+            Some("EQ3F"),
+            R::UNIT,
+        ),
+        LowDependenceHighGrayLevelEmphasis => (
+            "weight of high gray levels with few dependent neighbours",
+            "",
+            Some("JA6D"),
+            R::NON_NEGATIVE,
+        ),
+        HighDependenceLowGrayLevelEmphasis => (
+            "weight of low gray levels with many dependent neighbours",
+            "",
+            Some("NBZI"),
+            R::NON_NEGATIVE,
+        ),
+        HighDependenceHighGrayLevelEmphasis => (
+            "weight of high gray levels with many dependent neighbours",
+            "",
+            Some("9QMG"),
+            R::AT_LEAST_ONE,
+        ),
+        GrayLevelNonUniformity => (
+            "how unevenly voxels are spread over the gray levels",
+            "",
+            Some("FP8K"),
+            R::NON_NEGATIVE,
+        ),
+        GrayLevelNonUniformityNormalized => (
+            "gray level non-uniformity divided by the number of voxels",
+            "",
+            Some("5SPA"),
+            R::UNIT,
+        ),
+        DependenceCountNonUniformity => (
+            "how unevenly voxels are spread over the dependence counts",
+            "",
+            Some("Z87G"),
+            R::NON_NEGATIVE,
+        ),
+        DependenceCountNonUniformityNormalized => (
+            "dependence count non-uniformity divided by the number of voxels",
+            "",
+            Some("OKJI"),
+            R::UNIT,
+        ),
+        DependenceCountPercentage => (
+            "fraction of the voxels of the kernel with a neighbourhood",
+            "",
+            Some("6XV8"),
+            R::UNIT,
+        ),
+        GrayLevelVariance => (
+            "variance of the gray levels",
+            "",
+            Some("1PFV"),
+            R::NON_NEGATIVE,
+        ),
+        DependenceCountVariance => (
+            "variance of the dependence counts",
+            "",
+            Some("DNX2"),
+            R::NON_NEGATIVE,
+        ),
+        DependenceCountEntropy => (
+            "randomness of the gray levels and dependence counts",
+            "",
+            Some("FCBV"),
+            R::NON_NEGATIVE,
+        ),
+        DependenceCountEnergy => (
+            "uniformity of the gray levels and dependence counts, large when a few combinations \
+             dominate",
+            "",
+            Some("CAS9"),
+            R::UNIT,
+        ),
+    }
+}
+
+fn firstorder_entry(f: FirstOrderFeature) -> Entry {
+    use FirstOrderFeature::*;
+    use ValueRange as R;
+    match f {
+        Mean => ("mean intensity", "Mean", Some("Q4LE"), R::ANY),
+        Variance => (
+            "spread of the intensities about their mean",
+            "Variance",
+            Some("ECT3"),
+            R::NON_NEGATIVE,
+        ),
+        Skewness => (
+            "asymmetry of the intensities about their mean",
+            "Skewness",
+            Some("KE2A"),
+            R::ANY,
+        ),
+        Kurtosis => (
+            "excess kurtosis, how heavy the tails of the intensities are compared to a normal distribution",
+            "Kurtosis",
+            Some("IPH6"),
+            ValueRange::new(Some(-2.), None),
+        ),
+        Entropy => (
+            "randomness of the gray levels",
+            "Entropy",
+            Some("TLU2"),
+            R::NON_NEGATIVE,
+        ),
+        Energy => (
+            "sum of the squared intensities",
+            "Energy",
+            Some("N8CA"),
+            R::NON_NEGATIVE,
+        ),
+    }
+}
+
+fn haralick_entry(f: HaralickFeature) -> Entry {
+    match f {
+        HaralickFeature::Mcc => (
+            "maximal correlation coefficient, how well the gray level of a voxel predicts that of \
+             its neighbour, linearly or not",
+            "MCC",
+            None,
+            ValueRange::UNIT,
+        ),
+    }
+}
+
+fn percentile_entry(f: PercentileFeature) -> Entry {
+    use PercentileFeature::*;
+    use ValueRange as R;
+    match f {
+        P10 => (
+            "10th percentile of the intensities",
+            "10Percentile",
+            Some("QG58"),
+            R::ANY,
+        ),
+        P50 => ("median of the intensities", "Median", Some("Y12H"), R::ANY),
+        P90 => (
+            "90th percentile of the intensities",
+            "90Percentile",
+            Some("8DWT"),
+            R::ANY,
+        ),
+        InterquartileRange => (
+            "spread of the middle half of the intensities, a robust alternative to the variance",
+            "InterquartileRange",
+            Some("SALO"),
+            R::NON_NEGATIVE,
+        ),
+    }
+}
+
+fn lbp_entry(f: LBPFeature) -> Entry {
+    use LBPFeature::*;
+    use ValueRange as R;
+    let max_code = (N_CODES - 1) as f64;
+    match f {
+        Mean => (
+            "mean number of samples around a voxel at least as bright as it, high in dark spots \
+             and low on bright ones",
+            "",
+            None,
+            ValueRange::new(Some(0.), Some(max_code)),
+        ),
+        Variance => (
+            "spread of the local binary patterns, large where spots, edges and flat regions mix",
+            "",
+            None,
+            ValueRange::new(Some(0.), Some(max_code * max_code / 4.)),
+        ),
+        Entropy => (
+            "randomness of the local binary patterns",
+            "",
+            None,
+            ValueRange::new(Some(0.), Some((N_CODES as f64).log2())),
+        ),
+        Energy => (
+            "sum of the squared pattern frequencies, large where one pattern dominates",
+            "",
+            None,
+            R::UNIT,
+        ),
+    }
+}
+
+fn fractal_entry(f: FractalFeature) -> Entry {
+    match f {
+        FractalFeature::Dimension => (
+            "differential box counting dimension of the intensities as a surface over the kernel, \
+             3 where they are flat and towards 4 where they are rough",
+            "",
+            None,
+            ValueRange::ANY,
+        ),
+    }
+}
@@ -0,0 +1,1117 @@
+//! GLCM features along single directions. The glcm crate maps GLCM features averaged over the
+//! 13 directions of the kernel, so to see how texture changes with direction radmap builds the
+//! matrix of each direction on its own. The matrix of a direction counts the pairs of voxels of
+//! the kernel one step apart along it, in both orders so it is symmetric as in pyradiomics, and
+//! is normalized to joint probabilities. Features follow the pyradiomics definitions with gray
+//! levels numbered from 1, and are NaN where the kernel is too thin to hold a pair along the
+//! direction.
+//!
+//! Only the entries of the matrix that occur are kept, sorted, so the cost grows with the voxels
+//! of the kernel and not with the number of bins. Quantities several features are built from,
+//! like the mean, variance and entropies of the matrix, are worked out once per matrix however
+//! many of those features are selected. [`crate::mapper`] maps the averaged GLCM features with
+//! these matrices rather than with the glcm crate whenever radmap knows every selected feature,
+//! see `benches/glcm_features.rs` for what that saves.
+//!
+//! The maximal correlation coefficient is the square root of the second largest eigenvalue of
+//! `Q(i, j) = sum_k p(i, k) p(j, k) / (px(i) px(k))`. As the matrix is symmetric, `Q` is similar
+//! to the square of the symmetric `A(i, j) = p(i, j) / sqrt(px(i) px(j))`, so the coefficient is
+//! taken as the second largest absolute eigenvalue of `A`, found with Jacobi rotations. This
+//! keeps the eigenvalues real and avoids squaring small ones away, where the eigenvalues of the
+//! non-symmetric `Q` that pyradiomics solves for can come out complex or slightly negative.
+//!
+//! How much a feature changes with direction is summed up in an anisotropy map, the variance or
+//! the range of the feature over the directions of each voxel.
+//!
+//! The matrices can be limited to some of the directions with [`Angles`], e.g. to the 4 within an
+//! axial slice for volumes with thick slices. Directions left out are NaN like those the kernel is
+//! too thin for, so they drop out of averages and anisotropy maps. Voxels are paired with the
+//! voxel a number of steps along each direction, 1 by default, so pairs can reach past the
+//! neighbours of a voxel to pick up coarser texture. With several distances the features are
+//! averaged over the matrices at each distance, or taken from one matrix merging the pairs at
+//! every distance, see [`DistanceAggregation`].
+//!
+//! The directions are summed up in the same ways, see [`DirectionAggregation`]: the features are
+//! averaged over the matrices of the directions, taken from one matrix merging the pairs along
+//! every direction, or the largest over the directions is kept. A merged matrix holds the pairs of
+//! every distance along every direction, combined over the distances as [`DistanceAggregation`]
+//! says, and its features stand for each of the directions.
+//!
+//! The pairs pooled in a merged matrix can be weighted by how far apart their voxels are in mm,
+//! see [`DistanceWeighting`]. [`DistanceWeighting::Gaussian`] weighs each offset by `exp(-d²)` of
+//! its euclidean length, as pyradiomics does with `weightingNorm: euclidean`, the others are
+//! radmap's own. The weights only change matrices that pool pairs at several offsets, as each
+//! matrix is normalized.
+
+use std::cell::OnceCell;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use glcm::core::GLCMFeature;
+use rayon::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{Levels, Window, DIRECTIONS};
+
+/// a GLCM feature radmap computes itself, matched to those of the glcm crate by name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Stat {
+    Autocorrelation,
+    ClusterProminence,
+    ClusterShade,
+    ClusterTendency,
+    Contrast,
+    Correlation,
+    DifferenceAverage,
+    DifferenceEntropy,
+    DifferenceVariance,
+    JointEnergy,
+    JointEntropy,
+    Id,
+    Idm,
+    InverseVariance,
+    MaximumProbability,
+    SumAverage,
+    SumEntropy,
+    SumSquares,
+    Imc1,
+    Imc2,
+    /// maximal correlation coefficient
+    Mcc,
+}
+
+impl Stat {
+    /// the statistic of a feature of the glcm crate, if radmap knows its formula
+    pub fn of(feature: GLCMFeature) -> Option<Stat> {
+        Some(match feature.to_string().to_lowercase().as_str() {
+            "autocorrelation" => Stat::Autocorrelation,
+            "cluster_prominence" => Stat::ClusterProminence,
+            "cluster_shade" => Stat::ClusterShade,
+            "cluster_tendency" => Stat::ClusterTendency,
+            "contrast" => Stat::Contrast,
+            "correlation" => Stat::Correlation,
+            "difference_average" | "dissimilarity" => Stat::DifferenceAverage,
+            "difference_entropy" => Stat::DifferenceEntropy,
+            "difference_variance" => Stat::DifferenceVariance,
+            "energy" | "joint_energy" => Stat::JointEnergy,
+            "entropy" | "joint_entropy" => Stat::JointEntropy,
+            "homogeneity" | "inverse_difference" | "id" => Stat::Id,
+            "inverse_difference_moment" | "idm" => Stat::Idm,
+            "inverse_variance" => Stat::InverseVariance,
+            "maximum_probability" | "joint_maximum" => Stat::MaximumProbability,
+            "sum_average" => Stat::SumAverage,
+            "sum_entropy" => Stat::SumEntropy,
+            "sum_squares" | "joint_variance" => Stat::SumSquares,
+            "imc1" => Stat::Imc1,
+            "imc2" => Stat::Imc2,
+            "mcc" | "maximal_correlation_coefficient" => Stat::Mcc,
+            _ => return None,
+        })
+    }
+}
+
+/// whether radmap computes `feature` itself, so it can be mapped along single directions
+pub fn is_computed(feature: GLCMFeature) -> bool {
+    Stat::of(feature).is_some()
+}
+
+/// how the values of a feature along the 13 directions are summed up in its anisotropy map
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Anisotropy {
+    /// population variance over the directions
+    Variance,
+    /// largest less smallest value over the directions
+    Range,
+}
+
+impl Anisotropy {
+    pub const ALL: [Anisotropy; 2] = [Anisotropy::Variance, Anisotropy::Range];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Anisotropy::Variance => "variance",
+            Anisotropy::Range => "range",
+        }
+    }
+
+    /// the anisotropy map of a feature from its maps along each direction. Directions without a
+    /// value at a voxel, where the kernel is too thin, are left out, and voxels without any are
+    /// NaN
+    pub fn map(&self, by_direction: &[Vec<f32>]) -> Vec<f32> {
+        let n_voxels = by_direction.first().map_or(0, |m| m.len());
+        (0..n_voxels)
+            .into_par_iter()
+            .map(|i| {
+                let values = by_direction
+                    .iter()
+                    .map(|m| m[i] as f64)
+                    .filter(|v| !v.is_nan());
+                let (mut n, mut sum, mut sum_sq) = (0., 0., 0.);
+                let (mut min, mut max) = (f64::INFINITY, f64::NEG_INFINITY);
+                for v in values {
+                    n += 1.;
+                    sum += v;
+                    sum_sq += v * v;
+                    min = min.min(v);
+                    max = max.max(v);
+                }
+                if n == 0. {
+                    return f32::NAN;
+                }
+                let value = match self {
+                    Anisotropy::Variance => (sum_sq / n - (sum / n).powi(2)).max(0.),
+                    Anisotropy::Range => max - min,
+                };
+                value as f32
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Display for Anisotropy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Anisotropy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Anisotropy::ALL
+            .into_iter()
+            .find(|a| a.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown anisotropy {s}, expected variance or range"))
+    }
+}
+
+/// steps between the voxels of a pair
+pub const DEFAULT_DISTANCE: usize = 1;
+
+/// the [`DIRECTIONS`] the co-occurrence matrices are counted along, one bit per direction.
+/// Written as `all`, `in-plane` for the 4 directions within an xy slice, or the numbers of the
+/// directions from 1 to 13 as in the names of the maps per direction, e.g. `1,2,5`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Angles(u16);
+
+impl Angles {
+    pub const ALL: Angles = Angles((1 << DIRECTIONS.len()) - 1);
+    /// x, y and the two diagonals between them
+    pub const IN_PLANE: Angles = Angles(0b1111);
+
+    /// the directions of `indices`, counted from 0 in the order of [`DIRECTIONS`]
+    pub fn from_indices(indices: impl IntoIterator<Item = usize>) -> Self {
+        Angles(
+            indices
+                .into_iter()
+                .filter(|&d| d < DIRECTIONS.len())
+                .fold(0, |bits, d| bits | 1 << d),
+        )
+    }
+
+    pub fn contains(&self, direction: usize) -> bool {
+        self.0 >> direction & 1 == 1
+    }
+
+    /// indices of the directions, counted from 0
+    pub fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..DIRECTIONS.len()).filter(|&d| self.contains(d))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn is_all(&self) -> bool {
+        *self == Angles::ALL
+    }
+
+    /// the directions in both sets
+    pub fn intersection(self, other: Angles) -> Angles {
+        Angles(self.0 & other.0)
+    }
+
+    /// sets or clears a direction, counted from 0
+    pub fn set(&mut self, direction: usize, on: bool) {
+        match on {
+            true => self.0 |= 1 << direction,
+            false => self.0 &= !(1 << direction),
+        }
+    }
+}
+
+impl Default for Angles {
+    fn default() -> Self {
+        Angles::ALL
+    }
+}
+
+impl std::fmt::Display for Angles {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Angles::ALL => f.write_str("all"),
+            Angles::IN_PLANE => f.write_str("in-plane"),
+            _ => {
+                let numbers: Vec<String> = self.indices().map(|d| (d + 1).to_string()).collect();
+                f.write_str(&numbers.join(","))
+            }
+        }
+    }
+}
+
+impl FromStr for Angles {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "all" => return Ok(Angles::ALL),
+            "in-plane" | "in_plane" => return Ok(Angles::IN_PLANE),
+            _ => {}
+        }
+        let mut angles = Angles(0);
+        for n in s.split(',') {
+            match n.trim().parse::<usize>() {
+                Ok(n) if (1..=DIRECTIONS.len()).contains(&n) => angles.set(n - 1, true),
+                _ => {
+                    return Err(format!(
+                        "unknown angle {n}, expected all, in-plane or direction numbers from 1 \
+                         to {}",
+                        DIRECTIONS.len()
+                    ));
+                }
+            }
+        }
+        Ok(angles)
+    }
+}
+
+impl Serialize for Angles {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Angles {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        String::deserialize(d)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// how the matrices at several distances are combined, after the aggregation schemes of the IBSI
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DistanceAggregation {
+    /// features of the matrix at each distance, averaged
+    #[default]
+    Mean,
+    /// features of one matrix of the pairs at every distance
+    Merged,
+}
+
+impl DistanceAggregation {
+    pub const ALL: [DistanceAggregation; 2] =
+        [DistanceAggregation::Mean, DistanceAggregation::Merged];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DistanceAggregation::Mean => "mean",
+            DistanceAggregation::Merged => "merged",
+        }
+    }
+}
+
+impl std::fmt::Display for DistanceAggregation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DistanceAggregation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DistanceAggregation::ALL
+            .into_iter()
+            .find(|a| a.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown distance aggregation {s}, expected mean or merged"))
+    }
+}
+
+/// how the features along the directions are summed up in the map of a feature, after the
+/// aggregation schemes of the IBSI
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DirectionAggregation {
+    /// features of the matrix along each direction, averaged
+    #[default]
+    Mean,
+    /// features of one matrix of the pairs along every direction
+    Merged,
+    /// largest feature of the matrices along each direction
+    Max,
+}
+
+impl DirectionAggregation {
+    pub const ALL: [DirectionAggregation; 3] = [
+        DirectionAggregation::Mean,
+        DirectionAggregation::Merged,
+        DirectionAggregation::Max,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DirectionAggregation::Mean => "mean",
+            DirectionAggregation::Merged => "merged",
+            DirectionAggregation::Max => "max",
+        }
+    }
+
+    /// the values of a feature along the directions summed up in one, leaving out directions
+    /// without a value, where the kernel is too thin or they weren't counted. NaN without any.
+    /// Merged directions all hold the value of the one matrix, so their mean is that value
+    pub fn combine(&self, values: impl IntoIterator<Item = f64>) -> f64 {
+        let (n, sum, max) = values
+            .into_iter()
+            .filter(|v| !v.is_nan())
+            .fold((0., 0., f64::NEG_INFINITY), |(n, sum, max), v| {
+                (n + 1., sum + v, v.max(max))
+            });
+        match self {
+            _ if n == 0. => f64::NAN,
+            DirectionAggregation::Mean | DirectionAggregation::Merged => sum / n,
+            DirectionAggregation::Max => max,
+        }
+    }
+
+    /// the map of a feature from its maps along each direction, see [`Self::combine`]
+    pub fn map(&self, by_direction: &[Vec<f32>]) -> Vec<f32> {
+        let n_voxels = by_direction.first().map_or(0, |m| m.len());
+        (0..n_voxels)
+            .into_par_iter()
+            .map(|i| self.combine(by_direction.iter().map(|m| m[i] as f64)) as f32)
+            .collect()
+    }
+}
+
+impl std::fmt::Display for DirectionAggregation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DirectionAggregation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DirectionAggregation::ALL
+            .into_iter()
+            .find(|a| a.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                format!("unknown direction aggregation {s}, expected mean, merged or max")
+            })
+    }
+}
+
+/// how much a pair of voxels counts in a merged matrix, from the distance between them in mm
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DistanceWeighting {
+    /// every pair counts once
+    #[default]
+    None,
+    /// one over the euclidean distance
+    Inverse,
+    /// one over the square of the euclidean distance
+    InverseSquare,
+    /// one over the Manhattan distance, the sum of the distances along each axis
+    Manhattan,
+    /// `exp(-d²)` of the euclidean distance, pyradiomics' weighting
+    Gaussian,
+}
+
+impl DistanceWeighting {
+    pub const ALL: [DistanceWeighting; 5] = [
+        DistanceWeighting::None,
+        DistanceWeighting::Inverse,
+        DistanceWeighting::InverseSquare,
+        DistanceWeighting::Manhattan,
+        DistanceWeighting::Gaussian,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DistanceWeighting::None => "none",
+            DistanceWeighting::Inverse => "inverse",
+            DistanceWeighting::InverseSquare => "inverse_square",
+            DistanceWeighting::Manhattan => "manhattan",
+            DistanceWeighting::Gaussian => "gaussian",
+        }
+    }
+
+    /// the weight of a pair of voxels `offset` voxels apart, with `spacing` mm between voxels
+    /// along x, y and z
+    pub fn weight(&self, offset: [isize; 3], spacing: [f64; 3]) -> f64 {
+        let mm = [0, 1, 2].map(|i| (offset[i] as f64 * spacing[i]).abs());
+        let squared: f64 = mm.iter().map(|d| d * d).sum();
+        match self {
+            DistanceWeighting::None => 1.,
+            DistanceWeighting::Inverse => 1. / squared.sqrt(),
+            DistanceWeighting::InverseSquare => 1. / squared,
+            DistanceWeighting::Manhattan => 1. / mm.iter().sum::<f64>(),
+            DistanceWeighting::Gaussian => (-squared).exp(),
+        }
+    }
+}
+
+impl std::fmt::Display for DistanceWeighting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DistanceWeighting {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().replace('-', "_");
+        DistanceWeighting::ALL
+            .into_iter()
+            .find(|w| w.as_str().eq_ignore_ascii_case(&name))
+            .ok_or_else(|| {
+                format!(
+                    "unknown distance weighting {s}, expected none, inverse, inverse-square, \
+                     manhattan or gaussian"
+                )
+            })
+    }
+}
+
+/// the pairs of voxels counted in the co-occurrence matrices
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Offsets<'a> {
+    pub angles: Angles,
+    /// steps between the voxels of a pair
+    pub distances: &'a [usize],
+    /// how the matrices at each of the distances are combined
+    pub aggregation: DistanceAggregation,
+    /// how the matrices along each of the directions are combined
+    pub directions: DirectionAggregation,
+    /// how much each pair counts, from the distance between its voxels
+    pub weighting: DistanceWeighting,
+    /// mm between voxels along x, y and z, which the weights are measured in
+    pub spacing: [f64; 3],
+}
+
+/// whether new [`Scratch`] keeps the quantities several statistics share, see
+/// [`keep_shared_quantities`]
+static KEEP_SHARED: AtomicBool = AtomicBool::new(true);
+
+/// turns keeping the quantities several statistics share on or off for the mappers started
+/// after, on by default. Off, every statistic works them out again as it did before they were
+/// kept, which is only of use to measure what keeping them saves
+#[doc(hidden)]
+pub fn keep_shared_quantities(keep: bool) {
+    KEEP_SHARED.store(keep, Ordering::Relaxed);
+}
+
+/// pairs and matrix of one kernel, kept between voxels so they are only allocated once per
+/// thread
+pub(crate) struct Scratch {
+    /// gray levels of the voxels of each pair and the weight of the pair
+    pairs: Vec<(u16, u16, f64)>,
+    /// gray levels numbered from 1 and their joint probability, sorted
+    entries: Vec<(f64, f64, f64)>,
+    /// marginal probability of each gray level present, sorted
+    marginal: Vec<(f64, f64)>,
+    /// probability of each sum and absolute difference of gray levels present, sorted
+    sums: Vec<(f64, f64)>,
+    diffs: Vec<(f64, f64)>,
+    /// the symmetric matrix of the maximal correlation coefficient, row by row
+    matrix: Vec<f64>,
+    /// the statistics of one matrix
+    values: Vec<f64>,
+    /// quantities of the matrix that several statistics share
+    shared: Shared,
+    /// whether `shared` is kept from one statistic to the next
+    keep_shared: bool,
+}
+
+impl Default for Scratch {
+    fn default() -> Self {
+        Scratch {
+            pairs: vec![],
+            entries: vec![],
+            marginal: vec![],
+            sums: vec![],
+            diffs: vec![],
+            matrix: vec![],
+            values: vec![],
+            shared: Shared::default(),
+            keep_shared: KEEP_SHARED.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// quantities of a matrix that several statistics are built from, each worked out the first time
+/// a statistic needs it and kept until the next matrix, so selecting more statistics doesn't
+/// repeat them
+#[derive(Default)]
+struct Shared {
+    /// mean gray level, the same for both marginals as the matrix is symmetric
+    mu: OnceCell<f64>,
+    /// variance of the gray levels around `mu`
+    var: OnceCell<f64>,
+    /// `sum p(i, j) i j`
+    autocorrelation: OnceCell<f64>,
+    /// mean absolute difference of the gray levels of a pair
+    diff_average: OnceCell<f64>,
+    /// entropy of the marginal
+    hx: OnceCell<f64>,
+    /// joint entropy
+    hxy: OnceCell<f64>,
+}
+
+/// writes `stats` of the kernel in `window` along each of the 13 [`DIRECTIONS`] to `out`, the 13
+/// directions of the first statistic, then of the second and so on. Voxels are paired as
+/// `offsets` says, directions left out of its angles are NaN. With merged directions each of
+/// them holds the statistics of the one matrix
+pub(crate) fn compute(
+    levels: &Levels,
+    window: &Window,
+    offsets: Offsets,
+    stats: &[Stat],
+    s: &mut Scratch,
+    out: &mut [f64],
+) {
+    let n_dirs = DIRECTIONS.len();
+    out[..stats.len() * n_dirs].fill(f64::NAN);
+    let mut values = std::mem::take(&mut s.values);
+    values.resize(stats.len(), f64::NAN);
+    let selected = || offsets.angles.indices().map(|d| DIRECTIONS[d]);
+    match offsets.directions {
+        DirectionAggregation::Merged => {
+            s.features(levels, window, selected, offsets, stats, &mut values);
+            for d in offsets.angles.indices() {
+                for (k, &value) in values.iter().enumerate() {
+                    out[k * n_dirs + d] = value;
+                }
+            }
+        }
+        DirectionAggregation::Mean | DirectionAggregation::Max => {
+            for d in offsets.angles.indices() {
+                let along = || std::iter::once(DIRECTIONS[d]);
+                s.features(levels, window, along, offsets, stats, &mut values);
+                for (k, &value) in values.iter().enumerate() {
+                    out[k * n_dirs + d] = value;
+                }
+            }
+        }
+    }
+    s.values = values;
+}
+
+/// eigenvalues of the symmetric `m` by `m` matrix `a`, row by row, by cyclic Jacobi rotations.
+/// `a` is left diagonalized
+fn symmetric_eigenvalues(a: &mut [f64], m: usize) -> impl Iterator<Item = f64> + '_ {
+    const MAX_SWEEPS: usize = 50;
+    let norm: f64 = a.iter().map(|x| x * x).sum();
+    for _ in 0..MAX_SWEEPS {
+        let off: f64 = (0..m)
+            .flat_map(|p| (0..m).filter(move |&q| q != p).map(move |q| (p, q)))
+            .map(|(p, q)| a[p * m + q].powi(2))
+            .sum();
+        if off <= 1e-24 * norm {
+            break;
+        }
+        for p in 0..m {
+            for q in p + 1..m {
+                let apq = a[p * m + q];
+                if apq == 0. {
+                    continue;
+                }
+                let theta = (a[q * m + q] - a[p * m + p]) / (2. * apq);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.).sqrt());
+                let c = 1. / (t * t + 1.).sqrt();
+                let s = t * c;
+                for k in 0..m {
+                    let (akp, akq) = (a[k * m + p], a[k * m + q]);
+                    a[k * m + p] = c * akp - s * akq;
+                    a[k * m + q] = s * akp + c * akq;
+                }
+                for k in 0..m {
+                    let (apk, aqk) = (a[p * m + k], a[q * m + k]);
+                    a[p * m + k] = c * apk - s * aqk;
+                    a[q * m + k] = s * apk + c * aqk;
+                }
+            }
+        }
+    }
+    (0..m).map(move |k| a[k * m + k])
+}
+
+/// `(key, p)` merged so each key appears once, summing `p`
+fn collapse(v: &mut Vec<(f64, f64)>) {
+    v.sort_by(|a, b| a.0.total_cmp(&b.0));
+    v.dedup_by(|next, kept| {
+        let same = next.0 == kept.0;
+        if same {
+            kept.1 += next.1;
+        }
+        same
+    });
+}
+
+fn entropy(p: impl Iterator<Item = f64>) -> f64 {
+    -p.filter(|p| *p > 0.).map(|p| p * p.log2()).sum::<f64>()
+}
+
+impl Scratch {
+    /// writes `stats` of the matrix of the pairs along `dirs` to `values`, the distances
+    /// combined as `offsets` says. NaN where the kernel is too thin to hold a pair
+    fn features<I: Iterator<Item = [isize; 3]>>(
+        &mut self,
+        levels: &Levels,
+        window: &Window,
+        dirs: impl Fn() -> I,
+        offsets: Offsets,
+        stats: &[Stat],
+        values: &mut [f64],
+    ) {
+        values.fill(f64::NAN);
+        match offsets.aggregation {
+            DistanceAggregation::Merged => {
+                self.pairs.clear();
+                for &distance in offsets.distances {
+                    for dir in dirs() {
+                        self.pair(levels, window, dir, distance, offsets);
+                    }
+                }
+                if self.pairs.is_empty() {
+                    return;
+                }
+                self.fill();
+                for (value, &stat) in values.iter_mut().zip(stats) {
+                    *value = self.value(stat);
+                }
+            }
+            DistanceAggregation::Mean => {
+                // distances the kernel is too thin for are left out
+                let mut n = 0.;
+                for &distance in offsets.distances {
+                    self.pairs.clear();
+                    for dir in dirs() {
+                        self.pair(levels, window, dir, distance, offsets);
+                    }
+                    if self.pairs.is_empty() {
+                        continue;
+                    }
+                    self.fill();
+                    n += 1.;
+                    for (mean, &stat) in values.iter_mut().zip(stats) {
+                        let value = self.value(stat);
+                        *mean = if n == 1. {
+                            value
+                        } else {
+                            *mean + (value - *mean) / n
+                        };
+                    }
+                }
+            }
+        }
+    }
+
+    /// adds the pairs of voxels of the kernel `distance` steps apart along `dir`, both ways round,
+    /// weighted as `offsets` says
+    fn pair(
+        &mut self,
+        levels: &Levels,
+        window: &Window,
+        dir: [isize; 3],
+        distance: usize,
+        offsets: Offsets,
+    ) {
+        let dir = dir.map(|c| c * distance as isize);
+        let w = offsets.weighting.weight(dir, offsets.spacing);
+        for p in window.voxels() {
+            if let Some(q) = window.step(p, dir) {
+                let (a, b) = (levels.at(p), levels.at(q));
+                self.pairs.push((a, b, w));
+                self.pairs.push((b, a, w));
+            }
+        }
+    }
+
+    /// a statistic of the matrix of the pairs
+    fn value(&mut self, stat: Stat) -> f64 {
+        if !self.keep_shared {
+            self.shared = Shared::default();
+        }
+        match stat {
+            Stat::Mcc => self.mcc(),
+            _ => self.stat(stat),
+        }
+    }
+
+    /// the entries, marginals, sums and differences of the pairs
+    fn fill(&mut self) {
+        self.pairs.sort_unstable_by_key(|&(a, b, _)| (a, b));
+        let total: f64 = self.pairs.iter().map(|(.., w)| w).sum();
+        self.entries.clear();
+        for &(a, b, w) in &self.pairs {
+            let (i, j) = (a as f64 + 1., b as f64 + 1.);
+            match self.entries.last_mut() {
+                Some(e) if e.0 == i && e.1 == j => e.2 += w / total,
+                _ => self.entries.push((i, j, w / total)),
+            }
+        }
+        self.marginal.clear();
+        self.sums.clear();
+        self.diffs.clear();
+        for &(i, j, p) in &self.entries {
+            self.marginal.push((i, p));
+            self.sums.push((i + j, p));
+            self.diffs.push(((i - j).abs(), p));
+        }
+        collapse(&mut self.marginal);
+        collapse(&mut self.sums);
+        collapse(&mut self.diffs);
+        self.shared = Shared::default();
+    }
+
+    fn mu(&self) -> f64 {
+        *self
+            .shared
+            .mu
+            .get_or_init(|| self.marginal.iter().map(|(i, p)| i * p).sum())
+    }
+
+    fn var(&self) -> f64 {
+        *self.shared.var.get_or_init(|| {
+            let mu = self.mu();
+            self.marginal
+                .iter()
+                .map(|(i, p)| (i - mu).powi(2) * p)
+                .sum()
+        })
+    }
+
+    fn autocorrelation(&self) -> f64 {
+        *self
+            .shared
+            .autocorrelation
+            .get_or_init(|| self.entries.iter().map(|&(i, j, p)| p * i * j).sum())
+    }
+
+    fn diff_average(&self) -> f64 {
+        *self
+            .shared
+            .diff_average
+            .get_or_init(|| self.diffs.iter().map(|(k, p)| k * p).sum())
+    }
+
+    fn hx(&self) -> f64 {
+        *self
+            .shared
+            .hx
+            .get_or_init(|| entropy(self.marginal.iter().map(|(_, p)| *p)))
+    }
+
+    fn hxy(&self) -> f64 {
+        *self
+            .shared
+            .hxy
+            .get_or_init(|| entropy(self.entries.iter().map(|e| e.2)))
+    }
+
+    /// the maximal correlation coefficient, 1 for a kernel of a single gray level like
+    /// pyradiomics
+    fn mcc(&mut self) -> f64 {
+        let m = self.marginal.len();
+        if m == 1 {
+            return 1.;
+        }
+        let index = |i: f64| self.marginal.partition_point(|(l, _)| *l < i);
+        self.matrix.clear();
+        self.matrix.resize(m * m, 0.);
+        for &(i, j, p) in &self.entries {
+            let (a, b) = (index(i), index(j));
+            self.matrix[a * m + b] = p / (self.marginal[a].1 * self.marginal[b].1).sqrt();
+        }
+        let mut eigenvalues: Vec<f64> = symmetric_eigenvalues(&mut self.matrix, m)
+            .map(f64::abs)
+            .collect();
+        eigenvalues.sort_unstable_by(|a, b| b.total_cmp(a));
+        // the largest is 1, for the eigenvector of the square roots of the marginal
+        eigenvalues[1].min(1.)
+    }
+
+    fn stat(&self, stat: Stat) -> f64 {
+        let sum = |f: &dyn Fn(f64, f64) -> f64| -> f64 {
+            self.entries.iter().map(|&(i, j, p)| p * f(i, j)).sum()
+        };
+        match stat {
+            Stat::Autocorrelation => self.autocorrelation(),
+            Stat::ClusterProminence | Stat::ClusterShade | Stat::ClusterTendency => {
+                let mu = self.mu();
+                let power = match stat {
+                    Stat::ClusterProminence => 4,
+                    Stat::ClusterShade => 3,
+                    _ => 2,
+                };
+                sum(&|i, j| (i + j - 2. * mu).powi(power))
+            }
+            Stat::Contrast => sum(&|i, j| (i - j).powi(2)),
+            // 1 for a kernel of a single gray level, like pyradiomics
+            Stat::Correlation if self.marginal.len() == 1 => 1.,
+            Stat::Correlation => (self.autocorrelation() - self.mu().powi(2)) / self.var(),
+            Stat::DifferenceAverage => self.diff_average(),
+            Stat::DifferenceEntropy => entropy(self.diffs.iter().map(|(_, p)| *p)),
+            Stat::DifferenceVariance => {
+                let diff_average = self.diff_average();
+                self.diffs
+                    .iter()
+                    .map(|(k, p)| (k - diff_average).powi(2) * p)
+                    .sum()
+            }
+            Stat::JointEnergy => self.entries.iter().map(|e| e.2 * e.2).sum(),
+            Stat::JointEntropy => self.hxy(),
+            Stat::Id => self.diffs.iter().map(|(k, p)| p / (1. + k)).sum(),
+            Stat::Idm => self.diffs.iter().map(|(k, p)| p / (1. + k * k)).sum(),
+            Stat::InverseVariance => self
+                .diffs
+                .iter()
+                .filter(|(k, _)| *k > 0.)
+                .map(|(k, p)| p / (k * k))
+                .sum(),
+            Stat::MaximumProbability => self.entries.iter().map(|e| e.2).fold(0., f64::max),
+            Stat::SumAverage => self.sums.iter().map(|(k, p)| k * p).sum(),
+            Stat::SumEntropy => entropy(self.sums.iter().map(|(_, p)| *p)),
+            Stat::SumSquares => self.var(),
+            Stat::Mcc => unreachable!("see Scratch::mcc"),
+            Stat::Imc1 | Stat::Imc2 => {
+                let (hx, hxy) = (self.hx(), self.hxy());
+                let px = |i: f64| {
+                    let k = self.marginal.partition_point(|(l, _)| *l < i);
+                    self.marginal[k].1
+                };
+                if stat == Stat::Imc1 {
+                    let hxy1 = -sum(&|i, j| (px(i) * px(j)).log2());
+                    if hx > 0. {
+                        (hxy - hxy1) / hx
+                    } else {
+                        0.
+                    }
+                } else {
+                    let hxy2 = entropy(
+                        self.marginal
+                            .iter()
+                            .flat_map(|(_, a)| self.marginal.iter().map(move |(_, b)| a * b)),
+                    );
+                    (1. - (-2. * (hxy2 - hxy).max(0.)).exp()).sqrt()
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::{assert_features, Phantom, N_IN_PLANE};
+
+    fn offsets() -> Offsets<'static> {
+        Offsets {
+            angles: Angles::ALL,
+            distances: &[DEFAULT_DISTANCE],
+            aggregation: DistanceAggregation::Mean,
+            directions: DirectionAggregation::Mean,
+            weighting: DistanceWeighting::None,
+            spacing: [1.; 3],
+        }
+    }
+
+    /// the matrix along x, and no pairs along the directions leaving the slice
+    #[test]
+    fn pyradiomics_example() {
+        let stats = [
+            Stat::Autocorrelation,
+            Stat::ClusterProminence,
+            Stat::ClusterShade,
+            Stat::Contrast,
+            Stat::Correlation,
+            Stat::DifferenceEntropy,
+            Stat::DifferenceVariance,
+            Stat::JointEntropy,
+            Stat::Idm,
+            Stat::SumEntropy,
+            Stat::SumSquares,
+            Stat::Imc1,
+            Stat::Imc2,
+            Stat::Mcc,
+        ];
+        let phantom = Phantom::pyradiomics();
+        let n_dirs = DIRECTIONS.len();
+        let mut out = vec![0.; stats.len() * n_dirs];
+        compute(
+            &phantom.levels,
+            &phantom.window(),
+            offsets(),
+            &stats,
+            &mut Scratch::default(),
+            &mut out,
+        );
+        let along_x: Vec<f64> = (0..stats.len()).map(|k| out[k * n_dirs]).collect();
+        assert_features(
+            &along_x,
+            &[
+                7.7,
+                31.9192,
+                0.108,
+                2.3,
+                0.262820512821,
+                1.84643934467,
+                1.09,
+                3.94643934467,
+                0.57,
+                2.80370169606,
+                1.56,
+                -0.204990774066,
+                0.77070547197,
+                0.630279555131,
+            ],
+        );
+        assert!((0..stats.len()).all(|k| out[k * n_dirs + N_IN_PLANE].is_nan()));
+    }
+
+    /// the shared quantities are those of the matrix the statistic is taken of, not of one
+    /// before it
+    #[test]
+    fn statistics_are_the_same_alone_or_together() {
+        let phantom = Phantom::pyradiomics();
+        let n_dirs = DIRECTIONS.len();
+        let mut s = Scratch::default();
+        let mut together = vec![0.; 3 * n_dirs];
+        let stats = [Stat::ClusterShade, Stat::Correlation, Stat::Imc1];
+        compute(
+            &phantom.levels,
+            &phantom.window(),
+            offsets(),
+            &stats,
+            &mut s,
+            &mut together,
+        );
+        for (k, stat) in stats.into_iter().enumerate() {
+            let mut alone = vec![0.; n_dirs];
+            compute(
+                &phantom.levels,
+                &phantom.window(),
+                offsets(),
+                &[stat],
+                &mut s,
+                &mut alone,
+            );
+            for d in 0..N_IN_PLANE {
+                assert_eq!(alone[d], together[k * n_dirs + d]);
+            }
+        }
+    }
+
+    /// keeping the shared quantities changes how often they are worked out, not the values
+    #[test]
+    fn every_statistic_is_the_same_with_or_without_shared_quantities() {
+        let stats = [
+            Stat::Autocorrelation,
+            Stat::ClusterProminence,
+            Stat::ClusterShade,
+            Stat::ClusterTendency,
+            Stat::Contrast,
+            Stat::Correlation,
+            Stat::DifferenceAverage,
+            Stat::DifferenceEntropy,
+            Stat::DifferenceVariance,
+            Stat::JointEnergy,
+            Stat::JointEntropy,
+            Stat::Id,
+            Stat::Idm,
+            Stat::InverseVariance,
+            Stat::MaximumProbability,
+            Stat::SumAverage,
+            Stat::SumEntropy,
+            Stat::SumSquares,
+            Stat::Imc1,
+            Stat::Imc2,
+            Stat::Mcc,
+        ];
+        #[rustfmt::skip]
+        let levels = [
+            1, 2, 2, 3,  4, 4, 1, 2,  3, 3, 3, 1,
+            2, 1, 4, 4,  1, 1, 2, 3,  4, 2, 1, 3,
+            3, 4, 1, 2,  2, 3, 4, 1,  1, 2, 3, 4,
+        ];
+        let phantom = Phantom::new(&levels, [4, 3, 3]);
+        let n_dirs = DIRECTIONS.len();
+        let run = |keep_shared: bool| {
+            let mut s = Scratch {
+                keep_shared,
+                ..Scratch::default()
+            };
+            let mut out = vec![0.; stats.len() * n_dirs];
+            compute(
+                &phantom.levels,
+                &phantom.window(),
+                offsets(),
+                &stats,
+                &mut s,
+                &mut out,
+            );
+            out
+        };
+        let (kept, worked_out) = (run(true), run(false));
+        for (k, stat) in stats.iter().enumerate() {
+            for d in 0..n_dirs {
+                let (a, b) = (kept[k * n_dirs + d], worked_out[k * n_dirs + d]);
+                assert!(a == b || a.is_nan() && b.is_nan(), "{stat:?} along {d}: {a} != {b}");
+            }
+        }
+        // every direction holds pairs in a kernel 3 voxels deep
+        assert!(kept.iter().all(|v| !v.is_nan()));
+    }
+
+    #[test]
+    fn weightings_follow_the_distance_in_mm() {
+        // a step along z is 2 mm, along x and y 1 mm
+        let spacing = [1., 1., 2.];
+        let weights = |w: DistanceWeighting| {
+            [[1, 0, 0], [0, 0, 1], [1, 1, 0], [1, 1, 1]].map(|offset| w.weight(offset, spacing))
+        };
+        assert_eq!(weights(DistanceWeighting::None), [1.; 4]);
+        assert_eq!(
+            weights(DistanceWeighting::Inverse),
+            [1., 0.5, 1. / 2f64.sqrt(), 1. / 6f64.sqrt()]
+        );
+        assert_eq!(
+            weights(DistanceWeighting::InverseSquare),
+            [1., 0.25, 0.5, 1. / 6.]
+        );
+        assert_eq!(
+            weights(DistanceWeighting::Manhattan),
+            [1., 0.5, 0.5, 0.25]
+        );
+        // as pyradiomics weighs the angles with weightingNorm: euclidean
+        assert_eq!(
+            weights(DistanceWeighting::Gaussian),
+            [(-1f64).exp(), (-4f64).exp(), (-2f64).exp(), (-6f64).exp()]
+        );
+        for w in DistanceWeighting::ALL {
+            assert_eq!(w.as_str().parse::<DistanceWeighting>(), Ok(w));
+        }
+        assert_eq!("inverse-square".parse(), Ok(DistanceWeighting::InverseSquare));
+    }
+}
@@ -0,0 +1,125 @@
+//! First-order statistics of the intensities in the kernel, a cheap alternative to the matrix
+//! families for local intensity maps. Every statistic but the entropy is of the raw intensities,
+//! the entropy is of the gray levels the other families use. Variance and the moments are those
+//! of the population, and the kurtosis is the excess kurtosis, 0 for a normal distribution.
+//! Skewness and kurtosis are 0 in a kernel of a single intensity.
+
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter, EnumString};
+
+use super::{Levels, Window};
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    EnumIter,
+    Display,
+    EnumString,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum FirstOrderFeature {
+    Mean,
+    Variance,
+    Skewness,
+    Kurtosis,
+    Entropy,
+    Energy,
+}
+
+/// gray level histogram of one kernel, kept between voxels so it is only allocated once per
+/// thread
+#[derive(Default)]
+pub(crate) struct Scratch {
+    counts: Vec<usize>,
+    /// gray levels present, numbered from 0
+    present: Vec<usize>,
+}
+
+/// writes every feature, in the order of [`FirstOrderFeature`], of the kernel in `window` to
+/// `out`. `vol` holds the intensities the levels were binned from
+pub(crate) fn compute(
+    levels: &Levels,
+    vol: &[f64],
+    window: &Window,
+    n_bins: usize,
+    s: &mut Scratch,
+    out: &mut [f64],
+) {
+    s.counts.clear();
+    s.counts.resize(n_bins, 0);
+    s.present.clear();
+
+    let n = window.n_voxels() as f64;
+    let mut sum = 0.;
+    let mut energy = 0.;
+    for p in window.voxels() {
+        let x = vol[levels.index(p)];
+        sum += x;
+        energy += x * x;
+        let level = levels.at(p) as usize;
+        if s.counts[level] == 0 {
+            s.present.push(level);
+        }
+        s.counts[level] += 1;
+    }
+    let mean = sum / n;
+
+    let [mut m2, mut m3, mut m4] = [0.; 3];
+    for p in window.voxels() {
+        let d = vol[levels.index(p)] - mean;
+        m2 += d * d;
+        m3 += d * d * d;
+        m4 += d * d * d * d;
+    }
+    let [m2, m3, m4] = [m2, m3, m4].map(|m| m / n);
+
+    let entropy: f64 = s
+        .present
+        .iter()
+        .map(|&i| {
+            let p = s.counts[i] as f64 / n;
+            -p * p.log2()
+        })
+        .sum();
+
+    out[0] = mean;
+    out[1] = m2;
+    out[2] = if m2 > 0. { m3 / m2.powf(1.5) } else { 0. };
+    out[3] = if m2 > 0. { m4 / (m2 * m2) - 3. } else { 0. };
+    out[4] = entropy;
+    out[5] = energy;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::{assert_features, Phantom};
+
+    #[test]
+    fn pyradiomics_example() {
+        let phantom = Phantom::pyradiomics();
+        let intensities: Vec<f64> = phantom.levels.data.iter().map(|l| *l as f64 + 1.).collect();
+        let mut out = [0.; 6];
+        compute(
+            &phantom.levels,
+            &intensities,
+            &phantom.window(),
+            5,
+            &mut Scratch::default(),
+            &mut out,
+        );
+        assert_features(
+            &out,
+            &[2.8, 1.44, 0.25, -0.666666666667, 2.18190118891, 232.],
+        );
+    }
+}
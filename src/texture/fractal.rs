@@ -0,0 +1,173 @@
+//! Local fractal dimension by differential box counting, extended to 3D. The intensities of the
+//! kernel are taken as a surface over its grid of voxels, with the range of intensities of the
+//! whole volume stretched to the side of the kernel. Boxes of side `s` cells tile the grid, and
+//! each is stacked with as many boxes of height `s` as it takes to cover the intensities at its
+//! corners. The dimension is the slope of the log of the number of boxes against the log of
+//! `1 / s`, for every `s` up to the side of the kernel. Like the first-order features it comes
+//! from the intensities, not the gray levels, so the number of bins doesn't change it.
+//!
+//! The dimension is 3 for a flat kernel and grows towards 4 as the intensities get rougher. A
+//! single step through the kernel can bring it below 3, as more of the larger boxes straddle the
+//! step. Boxes cut off by the edge of the kernel count in proportion to the cells they hold.
+
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter, EnumString};
+
+use super::{Levels, Window};
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    EnumIter,
+    Display,
+    EnumString,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum FractalFeature {
+    Dimension,
+}
+
+/// box counts of one kernel, kept between voxels so they are only allocated once per thread
+#[derive(Default)]
+pub(crate) struct Scratch {
+    /// (ln 1/s, ln N) of every box size
+    points: Vec<(f64, f64)>,
+}
+
+/// writes the fractal dimension of the kernel in `window` to `out`. `vol` holds the
+/// intensities, `range` their minimum and maximum over the volume. NaN if the kernel is too
+/// thin to hold two box sizes. Boxes are laid over the box around a spherical kernel, those
+/// without a voxel of the sphere are left out
+pub(crate) fn compute(
+    levels: &Levels,
+    vol: &[f64],
+    range: [f64; 2],
+    window: &Window,
+    s: &mut Scratch,
+    out: &mut [f64],
+) {
+    // cells lie between neighbouring voxels
+    let cells = [0, 1, 2].map(|d| (window.hi[d] - window.lo[d]).saturating_sub(1).max(1));
+    let side = *cells.iter().max().unwrap();
+    let scale = if range[1] > range[0] {
+        side as f64 / (range[1] - range[0])
+    } else {
+        0.
+    };
+    let height = |p: [usize; 3]| (vol[levels.index(p)] - range[0]) * scale;
+
+    s.points.clear();
+    for size in 1..=side {
+        let mut n_boxes = 0.;
+        let n_per_axis = cells.map(|c| c.div_ceil(size));
+        for bz in 0..n_per_axis[2] {
+            for by in 0..n_per_axis[1] {
+                for bx in 0..n_per_axis[0] {
+                    let b = [bx, by, bz];
+                    // corners of the cells of the box, one voxel past its last cell
+                    let lo = [0, 1, 2].map(|d| window.lo[d] + b[d] * size);
+                    let hi = [0, 1, 2].map(|d| (lo[d] + size + 1).min(window.hi[d]));
+                    let mut min = f64::INFINITY;
+                    let mut max = f64::NEG_INFINITY;
+                    for z in lo[2]..hi[2] {
+                        for y in lo[1]..hi[1] {
+                            for x in lo[0]..hi[0] {
+                                if !window.contains([x, y, z]) {
+                                    continue;
+                                }
+                                let h = height([x, y, z]);
+                                min = min.min(h);
+                                max = max.max(h);
+                            }
+                        }
+                    }
+                    if min > max {
+                        continue;
+                    }
+                    let stack = (max / size as f64).floor() - (min / size as f64).floor() + 1.;
+                    let filled: usize = (0..3)
+                        .map(|d| (hi[d] - lo[d]).saturating_sub(1).max(1))
+                        .product();
+                    n_boxes += stack * filled as f64 / size.pow(3) as f64;
+                }
+            }
+        }
+        s.points.push(((1. / size as f64).ln(), n_boxes.ln()));
+    }
+
+    out[0] = if s.points.len() < 2 {
+        f64::NAN
+    } else {
+        slope(&s.points)
+    };
+}
+
+/// least squares slope of `points`
+fn slope(points: &[(f64, f64)]) -> f64 {
+    let n = points.len() as f64;
+    let mx = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let my = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let sxy: f64 = points.iter().map(|p| (p.0 - mx) * (p.1 - my)).sum();
+    let sxx: f64 = points.iter().map(|p| (p.0 - mx).powi(2)).sum();
+    sxy / sxx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::{assert_features, Phantom};
+
+    /// the dimension of `intensities` over the whole of a phantom of `shape`
+    fn dimension(intensities: &[f64], shape: [usize; 3]) -> f64 {
+        let phantom = Phantom::new(&vec![1; intensities.len()], shape);
+        let range = intensities
+            .iter()
+            .fold([f64::INFINITY, f64::NEG_INFINITY], |[lo, hi], v| {
+                [lo.min(*v), hi.max(*v)]
+            });
+        let mut out = [0.];
+        compute(
+            &phantom.levels,
+            intensities,
+            range,
+            &phantom.window(),
+            &mut Scratch::default(),
+            &mut out,
+        );
+        out[0]
+    }
+
+    #[test]
+    fn flat_and_tilted_kernels_are_3_dimensional() {
+        // a box per cell at every size, in 3D and in a slice
+        assert_features(&[dimension(&[2.; 125], [5, 5, 5])], &[3.]);
+        assert_features(&[dimension(&[2.; 25], [5, 5, 1])], &[3.]);
+        // along a ramp every box needs a stack of two
+        let ramp: Vec<f64> = (0..27).map(|i| (i % 3) as f64 * 10. - 4.).collect();
+        assert_features(&[dimension(&ramp, [3, 3, 3])], &[3.]);
+    }
+
+    #[test]
+    fn a_checkerboard_is_rougher() {
+        // each of the 8 unit boxes needs a stack of 3, the box of side 2 a stack of 2, so the
+        // dimension is log2(24 / 2)
+        let checkerboard: Vec<f64> = (0..27)
+            .map(|i| ((i % 3 + i / 3 % 3 + i / 9) % 2) as f64)
+            .collect();
+        assert_features(&[dimension(&checkerboard, [3, 3, 3])], &[12f64.log2()]);
+    }
+
+    #[test]
+    fn kernels_of_one_box_size_have_no_dimension() {
+        assert!(dimension(&[0., 1., 2., 3., 4., 5., 6., 7.], [2, 2, 2]).is_nan());
+    }
+}
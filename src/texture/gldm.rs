@@ -0,0 +1,120 @@
+//! Gray level dependence matrix features. A neighbour depends on a voxel when their gray levels
+//! differ by at most alpha, and the dependence size of a voxel is the number of its 26
+//! neighbours inside the kernel that depend on it, plus one for the voxel itself. Gray levels
+//! are numbered from 1 in the formulas.
+//!
+//! These are the features pyradiomics computes from the NGLDM of the IBSI at a distance of 1,
+//! see [`super::ngldm`].
+
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter, EnumString};
+
+use super::ngldm::{self, DEFAULT_DISTANCE};
+use super::{Levels, Window};
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    EnumIter,
+    Display,
+    EnumString,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum GLDMFeature {
+    SmallDependenceEmphasis,
+    LargeDependenceEmphasis,
+    GrayLevelNonUniformity,
+    DependenceNonUniformity,
+    DependenceNonUniformityNormalized,
+    GrayLevelVariance,
+    DependenceVariance,
+    DependenceEntropy,
+    LowGrayLevelEmphasis,
+    HighGrayLevelEmphasis,
+    SmallDependenceLowGrayLevelEmphasis,
+    SmallDependenceHighGrayLevelEmphasis,
+    LargeDependenceLowGrayLevelEmphasis,
+    LargeDependenceHighGrayLevelEmphasis,
+}
+
+/// the NGLDM features that make up the GLDM features, in their order. The normalized gray level
+/// non-uniformity, the percentage and the energy aren't GLDM features
+const FROM_NGLDM: [usize; 14] = [0, 1, 8, 10, 11, 13, 14, 15, 2, 3, 4, 5, 6, 7];
+
+/// dependence matrix of one kernel, kept between voxels so it is only allocated once per thread
+#[derive(Default)]
+pub(crate) struct Scratch {
+    ngldm: ngldm::Scratch,
+    features: [f64; ngldm::N_FEATURES],
+}
+
+/// writes every feature, in the order of [`GLDMFeature`], of the kernel in `window` to `out`
+pub(crate) fn compute(
+    levels: &Levels,
+    window: &Window,
+    n_bins: usize,
+    alpha: usize,
+    s: &mut Scratch,
+    out: &mut [f64],
+) {
+    ngldm::compute(
+        levels,
+        window,
+        n_bins,
+        alpha,
+        DEFAULT_DISTANCE,
+        &mut s.ngldm,
+        &mut s.features,
+    );
+    for (o, &i) in out.iter_mut().zip(&FROM_NGLDM) {
+        *o = s.features[i];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::{assert_features, Phantom};
+
+    #[test]
+    fn pyradiomics_example() {
+        let phantom = Phantom::pyradiomics();
+        let mut out = [0.; 14];
+        compute(
+            &phantom.levels,
+            &phantom.window(),
+            5,
+            0,
+            &mut Scratch::default(),
+            &mut out,
+        );
+        assert_features(
+            &out,
+            &[
+                0.3725,
+                5.56,
+                6.04,
+                7.96,
+                0.3184,
+                1.44,
+                0.72,
+                3.3834651896,
+                0.2723,
+                9.28,
+                0.0591364197531,
+                5.15472222222,
+                2.14285555556,
+                35.12,
+            ],
+        );
+    }
+}
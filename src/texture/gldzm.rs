@@ -0,0 +1,221 @@
+//! Gray level distance zone matrix features. Zones are found as for the GLSZM, groups of voxels
+//! of the same gray level joined through any of their 26 neighbours within the kernel, but are
+//! counted by their distance to the edge of the region rather than by their size. Gray levels
+//! and distances are numbered from 1 in the formulas.
+//!
+//! The distance of a voxel is the number of steps between face neighbours it takes to leave the
+//! mask, or the volume without a mask, so voxels on the edge are at 1. It is found once for the
+//! whole volume, not per kernel, as in the IBSI definition the region is the mask. Voxels outside
+//! the mask that fall in a kernel count as on the edge. The distance of a zone is the smallest
+//! of its voxels.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter, EnumString};
+
+use super::matrix::SizeMatrix;
+use super::{Levels, Window, DIRECTIONS};
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    EnumIter,
+    Display,
+    EnumString,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum GLDZMFeature {
+    SmallDistanceEmphasis,
+    LargeDistanceEmphasis,
+    GrayLevelNonUniformity,
+    GrayLevelNonUniformityNormalized,
+    ZoneDistanceNonUniformity,
+    ZoneDistanceNonUniformityNormalized,
+    ZonePercentage,
+    GrayLevelVariance,
+    ZoneDistanceVariance,
+    ZoneDistanceEntropy,
+    LowGrayLevelZoneEmphasis,
+    HighGrayLevelZoneEmphasis,
+    SmallDistanceLowGrayLevelEmphasis,
+    SmallDistanceHighGrayLevelEmphasis,
+    LargeDistanceLowGrayLevelEmphasis,
+    LargeDistanceHighGrayLevelEmphasis,
+}
+
+/// distance of every voxel to the edge of the region, x fastest, and the largest distance
+pub(crate) struct Distances {
+    pub data: Vec<u32>,
+    pub max: u32,
+}
+
+impl Distances {
+    /// breadth first from the voxels on the edge of the non-zero voxels of `mask`, or of the
+    /// volume without a mask. With `planar` the edge is that of each slice on its own
+    pub fn to_edge(mask: Option<&[f64]>, shape: [usize; 3], planar: bool) -> Self {
+        let n: usize = shape.iter().product();
+        let inside = |i: usize| mask.is_none_or(|m| m[i] != 0.);
+        let index = |p: [usize; 3]| p[0] + shape[0] * (p[1] + shape[1] * p[2]);
+        // face neighbours inside the volume, None for those beyond its edge
+        let axes = if planar { 0..2 } else { 0..3 };
+        let neighbours = |p: [usize; 3]| {
+            axes.clone().flat_map(move |d| {
+                [-1isize, 1].map(|step| {
+                    let c = p[d].checked_add_signed(step).filter(|c| *c < shape[d])?;
+                    let mut q = p;
+                    q[d] = c;
+                    Some(q)
+                })
+            })
+        };
+
+        // voxels outside the mask stay at 1, like those on its edge
+        let mut data = vec![1; n];
+        let mut done = vec![false; n];
+        let mut queue = VecDeque::new();
+        for z in 0..shape[2] {
+            for y in 0..shape[1] {
+                for x in 0..shape[0] {
+                    let p = [x, y, z];
+                    let i = index(p);
+                    if !inside(i) {
+                        done[i] = true;
+                    } else if neighbours(p).any(|q| q.is_none_or(|q| !inside(index(q)))) {
+                        done[i] = true;
+                        queue.push_back(p);
+                    }
+                }
+            }
+        }
+        let mut max = 1;
+        while let Some(p) = queue.pop_front() {
+            let next = data[index(p)] + 1;
+            for q in neighbours(p).flatten() {
+                let j = index(q);
+                if !done[j] {
+                    done[j] = true;
+                    data[j] = next;
+                    max = max.max(next);
+                    queue.push_back(q);
+                }
+            }
+        }
+        Distances { data, max }
+    }
+}
+
+/// zone matrix and flood fill state of one kernel, kept between voxels so it is only allocated
+/// once per thread
+#[derive(Default)]
+pub(crate) struct Scratch {
+    zones: SizeMatrix,
+    /// voxels of the window already in a zone, by their index in the window
+    visited: Vec<bool>,
+    stack: Vec<[usize; 3]>,
+}
+
+/// writes every feature, in the order of [`GLDZMFeature`], of the kernel in `window` to `out`
+pub(crate) fn compute(
+    levels: &Levels,
+    distances: &Distances,
+    window: &Window,
+    n_bins: usize,
+    s: &mut Scratch,
+    out: &mut [f64],
+) {
+    let n_voxels = window.n_voxels();
+    s.zones.reset(n_bins, distances.max as usize);
+    s.visited.clear();
+    s.visited.resize(window.box_len(), false);
+
+    for p in window.voxels() {
+        if s.visited[window.local_index(p)] {
+            continue;
+        }
+        let level = levels.at(p);
+        s.visited[window.local_index(p)] = true;
+        s.stack.push(p);
+        let mut distance = u32::MAX;
+        while let Some(q) = s.stack.pop() {
+            distance = distance.min(distances.data[levels.index(q)]);
+            for dir in DIRECTIONS {
+                for dir in [dir, dir.map(|d| -d)] {
+                    let Some(n) = window.step(q, dir) else {
+                        continue;
+                    };
+                    let i = window.local_index(n);
+                    if !s.visited[i] && levels.at(n) == level {
+                        s.visited[i] = true;
+                        s.stack.push(n);
+                    }
+                }
+            }
+        }
+        s.zones.add(level, distance as usize);
+    }
+
+    s.zones.features(n_voxels as f64, out);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::{assert_features, Phantom};
+
+    #[test]
+    fn distances_grow_towards_the_middle() {
+        let distances = Distances::to_edge(None, [5, 5, 1], true);
+        assert_eq!(distances.data[2 + 5 * 2], 3);
+        assert_eq!(distances.data[1 + 5 * 2], 2);
+        assert_eq!(distances.data[0], 1);
+        assert_eq!(distances.max, 3);
+    }
+
+    /// zones of the example in the pyradiomics documentation, by their distance to the edge of
+    /// the slice
+    #[test]
+    fn pyradiomics_example() {
+        let phantom = Phantom::pyradiomics();
+        let distances = Distances::to_edge(None, [5, 5, 1], true);
+        let mut out = [0.; 16];
+        compute(
+            &phantom.levels,
+            &distances,
+            &phantom.window(),
+            5,
+            &mut Scratch::default(),
+            &mut out,
+        );
+        assert_features(
+            &out,
+            &[
+                0.931818181818,
+                1.27272727273,
+                2.45454545455,
+                0.223140495868,
+                9.18181818182,
+                0.834710743802,
+                0.44,
+                1.68595041322,
+                0.0826446280992,
+                2.23127025461,
+                0.188939393939,
+                13.,
+                0.120757575758,
+                12.9318181818,
+                0.461666666667,
+                13.2727272727,
+            ],
+        );
+    }
+}
@@ -0,0 +1,122 @@
+//! Gray level run length matrix features. Runs are counted along all 13 directions of the kernel
+//! into a single matrix, as in the merged 3D variant of the IBSI reference manual, or along the 4
+//! in-plane ones of a kernel kept to a slice. Gray levels
+//! are numbered from 1 in the formulas.
+
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter, EnumString};
+
+use super::matrix::SizeMatrix;
+use super::{Levels, Window};
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    EnumIter,
+    Display,
+    EnumString,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum GLRLMFeature {
+    ShortRunEmphasis,
+    LongRunEmphasis,
+    GrayLevelNonUniformity,
+    GrayLevelNonUniformityNormalized,
+    RunLengthNonUniformity,
+    RunLengthNonUniformityNormalized,
+    RunPercentage,
+    GrayLevelVariance,
+    RunLengthVariance,
+    RunEntropy,
+    LowGrayLevelRunEmphasis,
+    HighGrayLevelRunEmphasis,
+    ShortRunLowGrayLevelEmphasis,
+    ShortRunHighGrayLevelEmphasis,
+    LongRunLowGrayLevelEmphasis,
+    LongRunHighGrayLevelEmphasis,
+}
+
+/// writes every feature, in the order of [`GLRLMFeature`], of the kernel in `window` to `out`
+pub(crate) fn compute(
+    levels: &Levels,
+    window: &Window,
+    n_bins: usize,
+    runs: &mut SizeMatrix,
+    out: &mut [f64],
+) {
+    let max_len = (0..3)
+        .map(|d| window.hi[d] - window.lo[d])
+        .max()
+        .unwrap_or(1);
+    runs.reset(n_bins, max_len);
+
+    for &dir in window.directions() {
+        for p in window.voxels() {
+            let level = levels.at(p);
+            // only count runs from their first voxel
+            let back = [0, 1, 2].map(|d| -dir[d]);
+            if window.step(p, back).is_some_and(|q| levels.at(q) == level) {
+                continue;
+            }
+            let mut len = 1;
+            let mut q = p;
+            while let Some(next) = window.step(q, dir).filter(|&n| levels.at(n) == level) {
+                len += 1;
+                q = next;
+            }
+            runs.add(level, len);
+        }
+    }
+
+    runs.features((window.n_voxels() * window.directions().len()) as f64, out);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::{assert_features, Phantom};
+
+    /// runs along the 4 directions within the slice, merged into one matrix
+    #[test]
+    fn pyradiomics_example() {
+        let phantom = Phantom::pyradiomics();
+        let mut out = [0.; 16];
+        compute(
+            &phantom.levels,
+            &phantom.window(),
+            5,
+            &mut SizeMatrix::default(),
+            &mut out,
+        );
+        assert_features(
+            &out,
+            &[
+                0.896405228758,
+                1.62352941176,
+                20.1058823529,
+                0.236539792388,
+                65.1882352941,
+                0.766920415225,
+                0.85,
+                1.48290657439,
+                0.239446366782,
+                2.82743261916,
+                0.252950980392,
+                9.85882352941,
+                0.212984114016,
+                9.23006535948,
+                0.489797385621,
+                13.5764705882,
+            ],
+        );
+    }
+}
@@ -0,0 +1,138 @@
+//! Gray level size zone matrix features. A zone is a group of voxels of the same gray level
+//! joined through any of their 26 neighbours, counted by size within the kernel. Gray levels are
+//! numbered from 1 in the formulas.
+
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter, EnumString};
+
+use super::matrix::SizeMatrix;
+use super::{Levels, Window, DIRECTIONS};
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    EnumIter,
+    Display,
+    EnumString,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum GLSZMFeature {
+    SmallZoneEmphasis,
+    LargeZoneEmphasis,
+    GrayLevelNonUniformity,
+    GrayLevelNonUniformityNormalized,
+    ZoneSizeNonUniformity,
+    ZoneSizeNonUniformityNormalized,
+    ZonePercentage,
+    GrayLevelVariance,
+    ZoneSizeVariance,
+    ZoneSizeEntropy,
+    LowGrayLevelZoneEmphasis,
+    HighGrayLevelZoneEmphasis,
+    SmallZoneLowGrayLevelEmphasis,
+    SmallZoneHighGrayLevelEmphasis,
+    LargeZoneLowGrayLevelEmphasis,
+    LargeZoneHighGrayLevelEmphasis,
+}
+
+/// zone matrix and flood fill state of one kernel, kept between voxels so it is only allocated
+/// once per thread
+#[derive(Default)]
+pub(crate) struct Scratch {
+    zones: SizeMatrix,
+    /// voxels of the window already in a zone, by their index in the window
+    visited: Vec<bool>,
+    stack: Vec<[usize; 3]>,
+}
+
+/// writes every feature, in the order of [`GLSZMFeature`], of the kernel in `window` to `out`
+pub(crate) fn compute(
+    levels: &Levels,
+    window: &Window,
+    n_bins: usize,
+    s: &mut Scratch,
+    out: &mut [f64],
+) {
+    let n_voxels = window.n_voxels();
+    s.zones.reset(n_bins, n_voxels);
+    s.visited.clear();
+    s.visited.resize(window.box_len(), false);
+
+    for p in window.voxels() {
+        if s.visited[window.local_index(p)] {
+            continue;
+        }
+        let level = levels.at(p);
+        s.visited[window.local_index(p)] = true;
+        s.stack.push(p);
+        let mut size = 0;
+        while let Some(q) = s.stack.pop() {
+            size += 1;
+            for dir in DIRECTIONS {
+                for dir in [dir, dir.map(|d| -d)] {
+                    let Some(n) = window.step(q, dir) else {
+                        continue;
+                    };
+                    let i = window.local_index(n);
+                    if !s.visited[i] && levels.at(n) == level {
+                        s.visited[i] = true;
+                        s.stack.push(n);
+                    }
+                }
+            }
+        }
+        s.zones.add(level, size);
+    }
+
+    s.zones.features(n_voxels as f64, out);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::{assert_features, Phantom};
+
+    /// zones of the example in the pyradiomics documentation, which lists its matrix
+    #[test]
+    fn pyradiomics_example() {
+        let phantom = Phantom::pyradiomics();
+        let mut out = [0.; 16];
+        compute(
+            &phantom.levels,
+            &phantom.window(),
+            5,
+            &mut Scratch::default(),
+            &mut out,
+        );
+        assert_features(
+            &out,
+            &[
+                0.591237373737,
+                7.72727272727,
+                2.45454545455,
+                0.223140495868,
+                3.90909090909,
+                0.355371900826,
+                0.44,
+                1.68595041322,
+                2.56198347107,
+                3.02716911844,
+                0.188939393939,
+                13.,
+                0.0589569304153,
+                9.96204545455,
+                2.43830808081,
+                53.6363636364,
+            ],
+        );
+    }
+}
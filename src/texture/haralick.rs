@@ -0,0 +1,96 @@
+//! Haralick features of the co-occurrence matrix that the glcm crate doesn't map. The maximal
+//! correlation coefficient of pyradiomics (`glcm_MCC`) is found for the matrix of each of the 13
+//! directions on its own and averaged over the directions, as pyradiomics averages its angles,
+//! from the same matrices as [`super::cooccurrence`]. The informational measures of correlation
+//! are mapped by the glcm crate as `imc1` and `imc2`. Directions left out of the
+//! [`Angles`](super::cooccurrence::Angles) of a run are left out of the average, and matrices at
+//! several distances are combined as for the GLCM features.
+
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter, EnumString};
+
+use super::cooccurrence::{self, Offsets, Stat};
+use super::{Levels, Window, DIRECTIONS};
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    EnumIter,
+    Display,
+    EnumString,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum HaralickFeature {
+    /// maximal correlation coefficient
+    Mcc,
+}
+
+/// matrices of one kernel, kept between voxels so they are only allocated once per thread
+#[derive(Default)]
+pub(crate) struct Scratch {
+    glcm: cooccurrence::Scratch,
+    by_direction: Vec<f64>,
+}
+
+/// writes every feature, in the order of [`HaralickFeature`], of the kernel in `window` to `out`
+pub(crate) fn compute(
+    levels: &Levels,
+    window: &Window,
+    offsets: Offsets,
+    s: &mut Scratch,
+    out: &mut [f64],
+) {
+    s.by_direction.resize(DIRECTIONS.len(), 0.);
+    cooccurrence::compute(
+        levels,
+        window,
+        offsets,
+        &[Stat::Mcc],
+        &mut s.glcm,
+        &mut s.by_direction,
+    );
+    // directions left out or the kernel is too thin for are NaN
+    out[0] = offsets.directions.combine(s.by_direction.iter().copied());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::cooccurrence::{
+        Angles, DirectionAggregation, DistanceAggregation, DistanceWeighting, DEFAULT_DISTANCE,
+    };
+    use crate::texture::{assert_features, Phantom};
+
+    /// the mean of the coefficients of the 4 matrices within the slice, of the eigenvalues of `Q`
+    /// found from its characteristic polynomial
+    #[test]
+    fn pyradiomics_example() {
+        let phantom = Phantom::pyradiomics();
+        let offsets = Offsets {
+            angles: Angles::ALL,
+            distances: &[DEFAULT_DISTANCE],
+            aggregation: DistanceAggregation::Mean,
+            directions: DirectionAggregation::Mean,
+            weighting: DistanceWeighting::None,
+            spacing: [1.; 3],
+        };
+        let mut out = [0.];
+        compute(
+            &phantom.levels,
+            &phantom.window(),
+            offsets,
+            &mut Scratch::default(),
+            &mut out,
+        );
+        assert_features(&out, &[0.539003545831]);
+    }
+}
@@ -0,0 +1,213 @@
+//! 3D local binary pattern features. Every voxel of the volume gets a pattern from 26 samples on
+//! the sphere of radius 1 voxel around it, one along each neighbour direction, interpolated
+//! trilinearly from the intensities and clamped at the edges of the volume. The pattern is
+//! encoded as the number of samples at least as bright as the voxel, which no rotation of the
+//! sphere changes. The features summarize the codes of the voxels of the kernel. Like the
+//! first-order features, patterns come from the intensities rather than the gray levels.
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter, EnumString};
+
+use super::{Levels, Window, DIRECTIONS};
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    EnumIter,
+    Display,
+    EnumString,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum LBPFeature {
+    Mean,
+    Variance,
+    Entropy,
+    Energy,
+}
+
+/// codes run from 0 to the number of samples
+pub(crate) const N_CODES: usize = 2 * DIRECTIONS.len() + 1;
+
+/// relative error of an interpolated intensity
+const ROUNDING: f64 = 1e-12;
+
+/// the rotation invariant pattern of every voxel of `vol`, x fastest, sampled along
+/// `directions`, all of [`DIRECTIONS`] or the in-plane ones
+pub(crate) fn codes(vol: &[f64], shape: [usize; 3], directions: &[[isize; 3]]) -> Vec<u8> {
+    let [nx, ny, nz] = shape;
+    let offsets: Vec<[f64; 3]> = directions
+        .iter()
+        .flat_map(|dir| [*dir, dir.map(|d| -d)])
+        .map(|dir| {
+            let len = dir.iter().map(|d| (d * d) as f64).sum::<f64>().sqrt();
+            dir.map(|d| d as f64 / len)
+        })
+        .collect();
+    (0..nz)
+        .into_par_iter()
+        .flat_map_iter(|z| {
+            let offsets = &offsets;
+            (0..ny).flat_map(move |y| {
+                (0..nx).map(move |x| {
+                    let centre = vol[x + nx * (y + ny * z)];
+                    offsets
+                        .iter()
+                        .filter(|o| {
+                            let p = [x as f64 + o[0], y as f64 + o[1], z as f64 + o[2]];
+                            // interpolating a flat region can be off by rounding
+                            interpolate(vol, shape, p) - centre >= -ROUNDING * centre.abs()
+                        })
+                        .count() as u8
+                })
+            })
+        })
+        .collect()
+}
+
+/// trilinear interpolation at `p`, in voxels, clamped to the volume
+fn interpolate(vol: &[f64], shape: [usize; 3], p: [f64; 3]) -> f64 {
+    let p = [0, 1, 2].map(|d| p[d].clamp(0., (shape[d] - 1) as f64));
+    let lo = p.map(|c| c.floor() as usize);
+    let t = [0, 1, 2].map(|d| p[d] - lo[d] as f64);
+    let mut value = 0.;
+    for corner in 0..8 {
+        let mut weight = 1.;
+        let mut q = [0; 3];
+        for d in 0..3 {
+            let up = corner >> d & 1 == 1;
+            weight *= if up { t[d] } else { 1. - t[d] };
+            q[d] = if up {
+                (lo[d] + 1).min(shape[d] - 1)
+            } else {
+                lo[d]
+            };
+        }
+        if weight > 0. {
+            value += weight * vol[q[0] + shape[0] * (q[1] + shape[1] * q[2])];
+        }
+    }
+    value
+}
+
+/// histogram of the codes of one kernel, kept between voxels so it is only allocated once per
+/// thread
+#[derive(Default)]
+pub(crate) struct Scratch {
+    counts: [usize; N_CODES],
+}
+
+/// writes every feature, in the order of [`LBPFeature`], of the codes of the kernel in `window`
+/// to `out`. `levels` only gives the layout of the volume
+pub(crate) fn compute(
+    levels: &Levels,
+    codes: &[u8],
+    window: &Window,
+    s: &mut Scratch,
+    out: &mut [f64],
+) {
+    s.counts = [0; N_CODES];
+    for p in window.voxels() {
+        s.counts[codes[levels.index(p)] as usize] += 1;
+    }
+    let n = window.n_voxels() as f64;
+    let [mut mean, mut entropy, mut energy] = [0.; 3];
+    for (code, count) in s.counts.iter().enumerate().filter(|(_, c)| **c > 0) {
+        let p = *count as f64 / n;
+        mean += p * code as f64;
+        entropy -= p * p.log2();
+        energy += p * p;
+    }
+    let variance: f64 = s
+        .counts
+        .iter()
+        .enumerate()
+        .map(|(code, count)| *count as f64 / n * (code as f64 - mean).powi(2))
+        .sum();
+
+    out[0] = mean;
+    out[1] = variance;
+    out[2] = entropy;
+    out[3] = energy;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::{assert_features, Phantom};
+
+    const SHAPE: [usize; 3] = [3, 3, 3];
+
+    /// the features of the codes of `intensities` over the whole of a 3 by 3 by 3 phantom
+    fn features(intensities: &[f64]) -> [f64; 4] {
+        let phantom = Phantom::new(&[1; 27], SHAPE);
+        let codes = codes(intensities, SHAPE, &DIRECTIONS);
+        let mut out = [0.; 4];
+        compute(
+            &phantom.levels,
+            &codes,
+            &phantom.window(),
+            &mut Scratch::default(),
+            &mut out,
+        );
+        out
+    }
+
+    /// entropy in bits of the code probabilities `p`
+    fn entropy(p: &[f64]) -> f64 {
+        -p.iter().map(|p| p * p.log2()).sum::<f64>()
+    }
+
+    #[test]
+    fn a_constant_volume_is_all_one_pattern() {
+        // every sample is as bright as the voxel
+        assert_eq!(codes(&[4.2; 27], SHAPE, &DIRECTIONS), [26; 27]);
+        assert_features(&features(&[4.2; 27]), &[26., 0., 0., 1.]);
+    }
+
+    #[test]
+    fn a_bright_point() {
+        // every sample around the point is darker, and the point is darker than none of them
+        let mut vol = [0.; 27];
+        vol[13] = 1.;
+        let codes = codes(&vol, SHAPE, &DIRECTIONS);
+        assert_eq!(codes[13], 0);
+        assert!(codes.iter().enumerate().all(|(i, c)| i == 13 || *c == 26));
+        let (p0, p26) = (1. / 27., 26. / 27.);
+        let mean = 26. * p26;
+        assert_features(
+            &features(&vol),
+            &[
+                mean,
+                p0 * mean * mean + p26 * (26. - mean).powi(2),
+                entropy(&[p0, p26]),
+                p0 * p0 + p26 * p26,
+            ],
+        );
+    }
+
+    #[test]
+    fn a_ramp() {
+        // 9 samples lie ahead along x and 8 level with the voxel. On the first plane the samples
+        // behind are clamped to it, so all 26 count
+        let vol: Vec<f64> = (0..27).map(|i| (i % 3) as f64).collect();
+        let codes = codes(&vol, SHAPE, &DIRECTIONS);
+        for (i, code) in codes.iter().enumerate() {
+            assert_eq!(*code, if i % 3 == 0 { 26 } else { 17 }, "voxel {i}");
+        }
+        let (p17, p26) = (2. / 3., 1. / 3.);
+        assert_features(
+            &features(&vol),
+            &[20., p17 * 9. + p26 * 36., entropy(&[p17, p26]), 5. / 9.],
+        );
+    }
+}
@@ -0,0 +1,122 @@
+//! Matrices of gray level against size, the run lengths of a GLRLM, the zone sizes of a GLSZM,
+//! the zone distances of a GLDZM or the dependence counts of an NGLDM. The families compute the
+//! same features from them, only named after runs, zone sizes, distances or dependences.
+
+/// counts of each gray level and size in one kernel, kept between voxels so it is only
+/// allocated once per thread
+#[derive(Default)]
+pub(crate) struct SizeMatrix {
+    /// largest size counted, sizes start at 1
+    max_size: usize,
+    /// `level * max_size + size - 1`
+    counts: Vec<u32>,
+    /// cells of `counts` that are not zero
+    touched: Vec<usize>,
+    by_level: Vec<f64>,
+    by_size: Vec<f64>,
+}
+
+/// number of features [`SizeMatrix::features`] writes
+pub(crate) const N_FEATURES: usize = 16;
+
+impl SizeMatrix {
+    /// empties the matrix for `n_bins` gray levels and sizes up to `max_size`
+    pub fn reset(&mut self, n_bins: usize, max_size: usize) {
+        for &cell in &self.touched {
+            self.counts[cell] = 0;
+        }
+        self.touched.clear();
+        self.max_size = max_size;
+        if self.counts.len() < n_bins * max_size {
+            self.counts.resize(n_bins * max_size, 0);
+        }
+        self.by_level.clear();
+        self.by_level.resize(n_bins, 0.);
+        self.by_size.clear();
+        self.by_size.resize(max_size, 0.);
+    }
+
+    pub fn add(&mut self, level: u16, size: usize) {
+        let cell = level as usize * self.max_size + size - 1;
+        if self.counts[cell] == 0 {
+            self.touched.push(cell);
+        }
+        self.counts[cell] += 1;
+    }
+
+    /// writes the features of the matrix to `out` in the order shared by the GLRLM, GLSZM and
+    /// GLDZM feature enums: short/long emphasis, gray level and size non-uniformity (plain and
+    /// normalized), percentage, gray level and size variance, entropy, then the low/high gray
+    /// level emphases. The percentage is the number of runs or zones over `n_possible`, the most
+    /// there could be
+    pub fn features(&mut self, n_possible: f64, out: &mut [f64]) {
+        let max_size = self.max_size;
+        let mut n = 0.;
+        let [mut se, mut le, mut lge, mut hge] = [0.; 4];
+        let [mut slge, mut shge, mut llge, mut lhge] = [0.; 4];
+        for &cell in &self.touched {
+            let p = self.counts[cell] as f64;
+            let level = cell / max_size;
+            let i = (level + 1) as f64;
+            let j = (cell % max_size + 1) as f64;
+            n += p;
+            self.by_level[level] += p;
+            self.by_size[cell % max_size] += p;
+            se += p / (j * j);
+            le += p * j * j;
+            lge += p / (i * i);
+            hge += p * i * i;
+            slge += p / (i * i * j * j);
+            shge += p * i * i / (j * j);
+            llge += p * j * j / (i * i);
+            lhge += p * i * i * j * j;
+        }
+
+        let mut mean_level = 0.;
+        let mut mean_size = 0.;
+        for &cell in &self.touched {
+            let p = self.counts[cell] as f64 / n;
+            mean_level += p * (cell / max_size + 1) as f64;
+            mean_size += p * (cell % max_size + 1) as f64;
+        }
+        let [mut level_var, mut size_var, mut entropy] = [0.; 3];
+        for &cell in &self.touched {
+            let p = self.counts[cell] as f64 / n;
+            level_var += p * ((cell / max_size + 1) as f64 - mean_level).powi(2);
+            size_var += p * ((cell % max_size + 1) as f64 - mean_size).powi(2);
+            entropy -= p * p.log2();
+        }
+
+        let gln: f64 = self.by_level.iter().map(|x| x * x).sum();
+        let sn: f64 = self.by_size.iter().map(|x| x * x).sum();
+        let values: [f64; N_FEATURES] = [
+            se / n,
+            le / n,
+            gln / n,
+            gln / (n * n),
+            sn / n,
+            sn / (n * n),
+            n / n_possible,
+            level_var,
+            size_var,
+            entropy,
+            lge / n,
+            hge / n,
+            slge / n,
+            shge / n,
+            llge / n,
+            lhge / n,
+        ];
+        out.copy_from_slice(&values);
+    }
+
+    /// sum of the squared probabilities of the cells, large when a few combinations of gray
+    /// level and size dominate
+    pub fn energy(&self) -> f64 {
+        let n: f64 = self.touched.iter().map(|&c| self.counts[c] as f64).sum();
+        self.touched
+            .iter()
+            .map(|&c| (self.counts[c] as f64 / n).powi(2))
+            .sum()
+    }
+}
@@ -0,0 +1,1133 @@
+//! Texture feature families computed by radmap itself, voxel by voxel over the same cubic kernel
+//! as the GLCM mapper of the glcm crate. Gray levels come from the same linear binning the GLCM
+//! mapper does on its own, so every family of a run sees the same levels.
+//!
+//! GLCM features keep their plain names (`contrast`), features of the other families are
+//! prefixed with the family (`glrlm_short_run_emphasis`) as several families share feature
+//! names. The kernel of a voxel is every voxel within the kernel radius that lies inside the
+//! volume, whether or not it is in the mask. Voxels outside the mask are not mapped and are 0 in
+//! the maps. First-order statistics, percentiles, local binary patterns and the fractal
+//! dimension of the intensities are mapped over the same kernels, as the `firstorder`,
+//! `percentile`, `lbp` and `fractal` families. The `haralick` family holds the co-occurrence
+//! features the glcm crate lacks, from matrices radmap builds itself.
+//!
+//! The `gldm` features are those of pyradiomics and the `ngldm` features those of the IBSI, from
+//! the same dependence matrix. Only the NGLDM has a neighbourhood distance other than 1.
+//!
+//! GLCM features are also mapped along each of the 13 directions on their own when asked, see
+//! [`cooccurrence`], written as `<feature>_dir<n>` with `n` from 01 to 13 in the order of
+//! [`DIRECTIONS`], or summed up over the directions as `<feature>_anisotropy_<variance|range>`.
+//!
+//! With [`DiscretizationScope::Local`] every kernel is binned on its own before its features are
+//! computed. The glcm crate only bins the whole volume, so radmap then maps the GLCM features
+//! itself, as the average of their values along the directions. The same goes for GLCM features
+//! limited to some of the directions with [`Angles`] or paired over more than one step, the glcm
+//! crate always takes all 13 directions at a distance of 1. Maps of GLCM and Haralick features
+//! paired at other distances are named after them, e.g. `contrast_d2` or `contrast_d1-2-3_mean`
+//! for the mean over several distances, see [`TextureOpts::cooccurrence_tag`].
+//!
+//! Mapped slice-wise, for thick slice acquisitions, every kernel is kept to the axial slice of its
+//! voxel. Neighbours are then only found in-plane: co-occurrence matrices take the in-plane
+//! directions, runs, zones and local binary patterns are found within the slice, and the GLCM
+//! features are mapped by radmap as the glcm crate only maps 3D kernels. Binning still covers the
+//! whole volume.
+//!
+//! With [`KernelShape::Sphere`] the kernel is every voxel whose centre lies within a radius in mm
+//! of the voxel mapped, measured with the voxel spacing of the header, so kernels cover the same
+//! neighbourhood along every axis however anisotropic the voxels are. Runs, zones and pairs of
+//! voxels are then found within the sphere only, and the GLCM features are mapped by radmap.
+
+pub mod catalog;
+pub mod cooccurrence;
+pub mod firstorder;
+pub mod fractal;
+pub mod gldm;
+pub mod gldzm;
+pub mod glrlm;
+pub mod glszm;
+pub mod haralick;
+pub mod lbp;
+mod matrix;
+pub mod ngldm;
+pub mod ngtdm;
+pub mod percentile;
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use array_lib::ArrayDim;
+use glcm::core::GLCMFeature;
+use glcm::ui::MapOpts;
+use rayon::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use strum::IntoEnumIterator;
+
+use crate::discretize::{Binning, DiscretizationScope};
+use crate::filter::FilterOpts;
+use cooccurrence::{
+    Angles, Anisotropy, DirectionAggregation, DistanceAggregation, DistanceWeighting, Offsets,
+};
+use firstorder::FirstOrderFeature;
+use fractal::FractalFeature;
+use gldm::GLDMFeature;
+use gldzm::GLDZMFeature;
+use glrlm::GLRLMFeature;
+use glszm::GLSZMFeature;
+use haralick::HaralickFeature;
+use lbp::LBPFeature;
+use ngldm::NGLDMFeature;
+use ngtdm::NGTDMFeature;
+use percentile::PercentileFeature;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Family {
+    Glcm,
+    Glrlm,
+    Glszm,
+    Gldzm,
+    Ngtdm,
+    Gldm,
+    Ngldm,
+    FirstOrder,
+    Percentile,
+    Lbp,
+    Fractal,
+    Haralick,
+}
+
+impl Family {
+    pub const ALL: [Family; 12] = [
+        Family::Glcm,
+        Family::Glrlm,
+        Family::Glszm,
+        Family::Gldzm,
+        Family::Ngtdm,
+        Family::Gldm,
+        Family::Ngldm,
+        Family::FirstOrder,
+        Family::Percentile,
+        Family::Lbp,
+        Family::Fractal,
+        Family::Haralick,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Family::Glcm => "glcm",
+            Family::Glrlm => "glrlm",
+            Family::Glszm => "glszm",
+            Family::Gldzm => "gldzm",
+            Family::Ngtdm => "ngtdm",
+            Family::Gldm => "gldm",
+            Family::Ngldm => "ngldm",
+            Family::FirstOrder => "firstorder",
+            Family::Percentile => "percentile",
+            Family::Lbp => "lbp",
+            Family::Fractal => "fractal",
+            Family::Haralick => "haralick",
+        }
+    }
+
+    /// e.g. `GLRLM (gray level run length matrix)`
+    pub fn label(&self) -> &'static str {
+        match self {
+            Family::Glcm => "GLCM (gray level co-occurrence matrix)",
+            Family::Glrlm => "GLRLM (gray level run length matrix)",
+            Family::Glszm => "GLSZM (gray level size zone matrix)",
+            Family::Gldzm => "GLDZM (gray level distance zone matrix)",
+            Family::Ngtdm => "NGTDM (neighbourhood gray tone difference matrix)",
+            Family::Gldm => "GLDM (gray level dependence matrix)",
+            Family::Ngldm => "NGLDM (neighbouring gray level dependence matrix)",
+            Family::FirstOrder => "first order (intensity statistics)",
+            Family::Percentile => "percentile (intensity percentiles)",
+            Family::Lbp => "LBP (3D local binary patterns)",
+            Family::Fractal => "fractal (box counting dimension)",
+            Family::Haralick => "Haralick (maximal correlation coefficient)",
+        }
+    }
+
+    pub fn features(&self) -> Vec<Feature> {
+        match self {
+            Family::Glcm => GLCMFeature::iter().map(Feature::Glcm).collect(),
+            Family::Glrlm => GLRLMFeature::iter().map(Feature::Glrlm).collect(),
+            Family::Glszm => GLSZMFeature::iter().map(Feature::Glszm).collect(),
+            Family::Gldzm => GLDZMFeature::iter().map(Feature::Gldzm).collect(),
+            Family::Ngtdm => NGTDMFeature::iter().map(Feature::Ngtdm).collect(),
+            Family::Gldm => GLDMFeature::iter().map(Feature::Gldm).collect(),
+            Family::Ngldm => NGLDMFeature::iter().map(Feature::Ngldm).collect(),
+            Family::FirstOrder => FirstOrderFeature::iter().map(Feature::FirstOrder).collect(),
+            Family::Percentile => PercentileFeature::iter().map(Feature::Percentile).collect(),
+            Family::Lbp => LBPFeature::iter().map(Feature::Lbp).collect(),
+            Family::Fractal => FractalFeature::iter().map(Feature::Fractal).collect(),
+            Family::Haralick => HaralickFeature::iter().map(Feature::Haralick).collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for Family {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for Family {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Family {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        String::deserialize(d)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl FromStr for Family {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Family::ALL
+            .into_iter()
+            .find(|f| f.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<&str> = Family::ALL.iter().map(|f| f.as_str()).collect();
+                format!(
+                    "unknown feature family {s}, expected one of {}",
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// a feature of any family. Displays as the name used on the command line and in output names
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Feature {
+    Glcm(GLCMFeature),
+    Glrlm(GLRLMFeature),
+    Glszm(GLSZMFeature),
+    Gldzm(GLDZMFeature),
+    Ngtdm(NGTDMFeature),
+    Gldm(GLDMFeature),
+    Ngldm(NGLDMFeature),
+    FirstOrder(FirstOrderFeature),
+    Percentile(PercentileFeature),
+    Lbp(LBPFeature),
+    Fractal(FractalFeature),
+    Haralick(HaralickFeature),
+}
+
+impl Feature {
+    /// every feature of every family, family by family
+    pub fn all() -> Vec<Feature> {
+        Family::ALL.iter().flat_map(|f| f.features()).collect()
+    }
+
+    pub fn family(&self) -> Family {
+        match self {
+            Feature::Glcm(_) => Family::Glcm,
+            Feature::Glrlm(_) => Family::Glrlm,
+            Feature::Glszm(_) => Family::Glszm,
+            Feature::Gldzm(_) => Family::Gldzm,
+            Feature::Ngtdm(_) => Family::Ngtdm,
+            Feature::Gldm(_) => Family::Gldm,
+            Feature::Ngldm(_) => Family::Ngldm,
+            Feature::FirstOrder(_) => Family::FirstOrder,
+            Feature::Percentile(_) => Family::Percentile,
+            Feature::Lbp(_) => Family::Lbp,
+            Feature::Fractal(_) => Family::Fractal,
+            Feature::Haralick(_) => Family::Haralick,
+        }
+    }
+
+    /// name within the family, without the family prefix
+    pub fn name(&self) -> String {
+        match self {
+            Feature::Glcm(f) => f.to_string().to_lowercase(),
+            Feature::Glrlm(f) => f.to_string(),
+            Feature::Glszm(f) => f.to_string(),
+            Feature::Gldzm(f) => f.to_string(),
+            Feature::Ngtdm(f) => f.to_string(),
+            Feature::Gldm(f) => f.to_string(),
+            Feature::Ngldm(f) => f.to_string(),
+            Feature::FirstOrder(f) => f.to_string(),
+            Feature::Percentile(f) => f.to_string(),
+            Feature::Lbp(f) => f.to_string(),
+            Feature::Fractal(f) => f.to_string(),
+            Feature::Haralick(f) => f.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for Feature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Feature::Glcm(_) => f.write_str(&self.name()),
+            _ => write!(f, "{}_{}", self.family(), self.name()),
+        }
+    }
+}
+
+/// features are written by name, as on the command line
+impl Serialize for Feature {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Feature {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        String::deserialize(d)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl FromStr for Feature {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        if let Ok(f) = GLCMFeature::from_str(&s) {
+            return Ok(Feature::Glcm(f));
+        }
+        for family in &Family::ALL[1..] {
+            let Some(name) = s
+                .strip_prefix(family.as_str())
+                .and_then(|n| n.strip_prefix('_'))
+            else {
+                continue;
+            };
+            if let Some(f) = family.features().into_iter().find(|f| f.name() == name) {
+                return Ok(f);
+            }
+        }
+        Err(format!("unknown feature {s}"))
+    }
+}
+
+/// the selected features of the families radmap maps itself, by alias. GLCM features are
+/// selected in [`MapOpts`], which goes to the glcm crate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TextureOpts {
+    pub features: HashMap<Feature, String>,
+    /// largest difference in gray levels of a neighbour that still depends on a voxel, for the
+    /// GLDM features
+    #[serde(default)]
+    pub gldm_alpha: usize,
+    /// the coarseness of the NGLDM features, like the GLDM alpha
+    #[serde(default)]
+    pub ngldm_alpha: usize,
+    /// how many steps from a voxel its neighbours reach for the NGLDM features
+    #[serde(default = "default_ngldm_distance")]
+    pub ngldm_distance: usize,
+    /// filtered copies of the input to write or map next to it
+    #[serde(default)]
+    pub filters: FilterOpts,
+    /// also map the selected GLCM features along each direction on its own
+    #[serde(default)]
+    pub glcm_per_direction: bool,
+    /// also map how the selected GLCM features change with direction
+    #[serde(default)]
+    pub glcm_anisotropy: Option<Anisotropy>,
+    /// bin the whole volume once, or each kernel on its own
+    #[serde(default)]
+    pub discretization: DiscretizationScope,
+    /// the directions co-occurrence matrices are counted along, for the GLCM and Haralick
+    /// features
+    #[serde(default)]
+    pub glcm_angles: Angles,
+    /// steps between the voxels paired in the co-occurrence matrices, sorted
+    #[serde(default = "default_glcm_distances")]
+    pub glcm_distances: Vec<usize>,
+    /// how the matrices at several distances are combined
+    #[serde(default)]
+    pub glcm_aggregation: DistanceAggregation,
+    /// how the features along each direction are summed up in the map of a GLCM or Haralick
+    /// feature
+    #[serde(default)]
+    pub glcm_direction_aggregation: DirectionAggregation,
+    /// how much the pairs pooled in a merged co-occurrence matrix count, from the distance
+    /// between their voxels in mm
+    #[serde(default)]
+    pub glcm_weighting: DistanceWeighting,
+    /// keep the kernels and the neighbours of their voxels within the axial slice of the voxel
+    /// mapped, for thick slice acquisitions where 3D neighbourhoods mean little
+    #[serde(default)]
+    pub slice_wise: bool,
+    /// the radius of the kernel along x, y and z when it isn't the same along every axis, in
+    /// place of the kernel radius of the [`MapOpts`]
+    #[serde(default)]
+    pub kernel_radii: Option<[usize; 3]>,
+    /// a cube of voxels, or a sphere in mm in place of the kernel radius
+    #[serde(default)]
+    pub kernel_shape: KernelShape,
+    /// voxel spacing in mm of the volume mapped, from its header, which spherical kernels and
+    /// distance weights are measured with. 1 mm along every axis when unknown
+    #[serde(skip)]
+    pub voxel_spacing: Option<[f64; 3]>,
+}
+
+/// the shape of the kernels, written `cube` or `sphere:<radius in mm>`, e.g. `sphere:4.5`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum KernelShape {
+    /// every voxel within the kernel radius along each axis, counted in voxels
+    #[default]
+    Cube,
+    /// every voxel whose centre lies within `radius_mm` of the voxel mapped
+    Sphere { radius_mm: f64 },
+}
+
+impl KernelShape {
+    pub fn is_sphere(&self) -> bool {
+        matches!(self, KernelShape::Sphere { .. })
+    }
+
+    /// the radius in voxels along x, y and z of the box around a sphere on voxels of `spacing`
+    pub fn sphere_radii(radius_mm: f64, spacing: [f64; 3]) -> [usize; 3] {
+        spacing.map(|s| (radius_mm / s + SPHERE_TOLERANCE).floor() as usize)
+    }
+}
+
+/// slack in voxels for centres that lie on the sphere, which rounding could leave out
+const SPHERE_TOLERANCE: f64 = 1e-9;
+
+impl std::fmt::Display for KernelShape {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KernelShape::Cube => f.write_str("cube"),
+            KernelShape::Sphere { radius_mm } => write!(f, "sphere:{radius_mm}"),
+        }
+    }
+}
+
+impl FromStr for KernelShape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("cube") {
+            return Ok(KernelShape::Cube);
+        }
+        let radius = s
+            .split_once(':')
+            .filter(|(shape, _)| shape.trim().eq_ignore_ascii_case("sphere"))
+            .map(|(_, radius)| radius.trim().trim_end_matches("mm"))
+            .ok_or_else(|| {
+                format!("unknown kernel shape {s}, expected cube or sphere:<radius in mm>")
+            })?;
+        let radius_mm: f64 = radius
+            .parse()
+            .map_err(|_| format!("could not read \"{radius}\" as the radius of a sphere in mm"))?;
+        Ok(KernelShape::Sphere { radius_mm })
+    }
+}
+
+impl Serialize for KernelShape {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for KernelShape {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        String::deserialize(d)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+fn default_ngldm_distance() -> usize {
+    ngldm::DEFAULT_DISTANCE
+}
+
+fn default_glcm_distances() -> Vec<usize> {
+    vec![cooccurrence::DEFAULT_DISTANCE]
+}
+
+impl Default for TextureOpts {
+    fn default() -> Self {
+        TextureOpts {
+            features: HashMap::new(),
+            gldm_alpha: 0,
+            ngldm_alpha: 0,
+            ngldm_distance: ngldm::DEFAULT_DISTANCE,
+            filters: FilterOpts::default(),
+            glcm_per_direction: false,
+            glcm_anisotropy: None,
+            discretization: DiscretizationScope::Global,
+            glcm_angles: Angles::ALL,
+            glcm_distances: default_glcm_distances(),
+            glcm_aggregation: DistanceAggregation::Mean,
+            glcm_direction_aggregation: DirectionAggregation::Mean,
+            glcm_weighting: DistanceWeighting::None,
+            slice_wise: false,
+            kernel_radii: None,
+            kernel_shape: KernelShape::Cube,
+            voxel_spacing: None,
+        }
+    }
+}
+
+impl TextureOpts {
+    /// whether the GLCM features are mapped along each direction, to write, sum up or average
+    /// in place of the glcm crate
+    pub fn maps_glcm_directions(&self) -> bool {
+        self.glcm_per_direction || self.glcm_anisotropy.is_some() || self.averages_glcm_directions()
+    }
+
+    /// whether the options need the GLCM features averaged from their maps along each direction,
+    /// as the glcm crate bins the whole volume, takes every direction at a distance of 1,
+    /// averages them and maps 3D kernels. They are averaged for other options too when radmap
+    /// computes every selected feature, see [`crate::mapper`]
+    pub fn averages_glcm_directions(&self) -> bool {
+        self.discretization.is_local()
+            || !self.glcm_angles.is_all()
+            || self.glcm_distances != [cooccurrence::DEFAULT_DISTANCE]
+            || self.slice_wise
+            || self.kernel_radii.is_some()
+            || self.kernel_shape.is_sphere()
+            || self.glcm_direction_aggregation != DirectionAggregation::Mean
+            || self.glcm_weighting != DistanceWeighting::None
+    }
+
+    /// the radius of the kernel along x, y and z, `radius` along each axis unless there is a
+    /// radius per axis or a sphere, and none along z when mapping slice-wise. A sphere is bounded
+    /// by its radius in voxels along each axis
+    pub fn kernel_radius(&self, radius: usize) -> [usize; 3] {
+        let [rx, ry, rz] = self.radii().unwrap_or([radius; 3]);
+        match self.slice_wise {
+            true => [rx, ry, 0],
+            false => [rx, ry, rz],
+        }
+    }
+
+    /// the radius per axis, of the box around the sphere for spherical kernels
+    fn radii(&self) -> Option<[usize; 3]> {
+        match self.kernel_shape {
+            KernelShape::Cube => self.kernel_radii,
+            KernelShape::Sphere { radius_mm } => Some(KernelShape::sphere_radii(
+                radius_mm,
+                self.voxel_spacing.unwrap_or([1.; 3]),
+            )),
+        }
+    }
+
+    /// the kernel voxels are mapped over, of `radius` unless [`TextureOpts::kernel_radius`]
+    /// says otherwise
+    pub(crate) fn kernel(&self, radius: usize) -> Kernel {
+        let radius = self.kernel_radius(radius);
+        let ball = match self.kernel_shape {
+            KernelShape::Cube => None,
+            KernelShape::Sphere { radius_mm } => {
+                let spacing = self.voxel_spacing.unwrap_or([1.; 3]);
+                let [wx, wy, wz] = radius.map(|r| 2 * r + 1);
+                let limit = radius_mm * radius_mm * (1. + SPHERE_TOLERANCE);
+                let mut ball = Vec::with_capacity(wx * wy * wz);
+                for z in 0..wz {
+                    for y in 0..wy {
+                        for x in 0..wx {
+                            let d2: f64 = [x, y, z]
+                                .into_iter()
+                                .enumerate()
+                                .map(|(d, o)| ((o as f64 - radius[d] as f64) * spacing[d]).powi(2))
+                                .sum();
+                            ball.push(d2 <= limit);
+                        }
+                    }
+                }
+                Some(ball)
+            }
+        };
+        Kernel { radius, ball }
+    }
+
+    /// a radius per axis as kept in `kernel_radii`, None when it is the same along every axis,
+    /// which is a cube the glcm crate can map
+    pub fn anisotropic_radii(radii: Option<[usize; 3]>) -> Option<[usize; 3]> {
+        radii.filter(|[rx, ry, rz]| !(rx == ry && ry == rz))
+    }
+
+    /// whether kernels are kept to the slice of their voxel, mapping slice-wise or with no
+    /// radius along z
+    fn planar(&self) -> bool {
+        self.slice_wise || self.radii().is_some_and(|[_, _, rz]| rz == 0)
+    }
+
+    /// the [`DIRECTIONS`] neighbours are found along, the in-plane ones when kernels are kept
+    /// to a slice
+    pub fn directions(&self) -> &'static [[isize; 3]] {
+        match self.planar() {
+            true => &DIRECTIONS[..N_IN_PLANE],
+            false => &DIRECTIONS,
+        }
+    }
+
+    /// the directions the co-occurrence matrices are counted along, those of `glcm_angles` that
+    /// lie in the axial plane when kernels are kept to a slice
+    pub fn angles(&self) -> Angles {
+        match self.planar() {
+            true => self.glcm_angles.intersection(Angles::IN_PLANE),
+            false => self.glcm_angles,
+        }
+    }
+
+    /// the pairs of voxels counted in the co-occurrence matrices
+    pub(crate) fn offsets(&self) -> Offsets<'_> {
+        Offsets {
+            angles: self.angles(),
+            distances: &self.glcm_distances,
+            aggregation: self.glcm_aggregation,
+            directions: self.glcm_direction_aggregation,
+            weighting: self.glcm_weighting,
+            spacing: self.voxel_spacing.unwrap_or([1.; 3]),
+        }
+    }
+
+    /// what the names of the maps of GLCM and Haralick features are tagged with when their
+    /// voxels are paired at other distances than 1: `d2` for a single distance, `d1-2-3_mean`
+    /// or `d1-2-3_merged` for several
+    pub fn cooccurrence_tag(&self) -> Option<String> {
+        let distances: Vec<String> = self.glcm_distances.iter().map(|d| d.to_string()).collect();
+        match self.glcm_distances.as_slice() {
+            [cooccurrence::DEFAULT_DISTANCE] => None,
+            [d] => Some(format!("d{d}")),
+            _ => Some(format!(
+                "d{}_{}",
+                distances.join("-"),
+                self.glcm_aggregation
+            )),
+        }
+    }
+
+    /// the name of the maps of `feature` selected under `alias`, tagged with
+    /// [`TextureOpts::cooccurrence_tag`] for GLCM and Haralick features
+    pub fn output_alias(&self, feature: Feature, alias: &str) -> String {
+        tag_alias(feature, alias, self.cooccurrence_tag().as_deref())
+    }
+
+    /// splits a selection of features of any family into the GLCM features for [`MapOpts`] and
+    /// the rest
+    pub fn split(features: &HashMap<Feature, String>) -> (HashMap<GLCMFeature, String>, Self) {
+        let mut glcm = HashMap::new();
+        let mut texture = TextureOpts::default();
+        for (feature, alias) in features {
+            match feature {
+                Feature::Glcm(f) => {
+                    glcm.insert(*f, alias.clone());
+                }
+                _ => {
+                    texture.features.insert(*feature, alias.clone());
+                }
+            }
+        }
+        (glcm, texture)
+    }
+
+    /// families with at least one selected feature
+    pub fn families(&self) -> Vec<Family> {
+        let mut families: Vec<Family> = self.features.keys().map(|f| f.family()).collect();
+        families.sort();
+        families.dedup();
+        families
+    }
+
+    /// whether some selected family is of the intensities rather than the gray levels, so they
+    /// must reach the mappers next to any gray levels binned ahead of them
+    pub fn maps_intensities(&self) -> bool {
+        self.families().iter().any(|f| {
+            matches!(
+                f,
+                Family::FirstOrder | Family::Percentile | Family::Lbp | Family::Fractal
+            )
+        })
+    }
+}
+
+/// `alias` followed by `tag` for the GLCM and Haralick features, which are paired as it says
+pub fn tag_alias(feature: Feature, alias: &str, tag: Option<&str>) -> String {
+    match (feature.family(), tag) {
+        (Family::Glcm | Family::Haralick, Some(tag)) => format!("{alias}_{tag}"),
+        _ => alias.to_string(),
+    }
+}
+
+/// every selected feature, GLCM or not, with its alias, sorted by alias
+pub fn selected_features(opts: &MapOpts, texture: &TextureOpts) -> Vec<(Feature, String)> {
+    let mut features: Vec<(Feature, String)> = opts
+        .features
+        .iter()
+        .map(|(f, alias)| (Feature::Glcm(*f), alias.clone()))
+        .chain(texture.features.iter().map(|(f, a)| (*f, a.clone())))
+        .collect();
+    features.sort_by(|a, b| a.1.cmp(&b.1));
+    features
+}
+
+/// gray levels of a block of a volume, x fastest. The block is the whole volume, or a single
+/// kernel when kernels are binned on their own
+#[derive(Default)]
+pub(crate) struct Levels {
+    data: Vec<u16>,
+    /// shape of the whole volume
+    pub shape: [usize; 3],
+    /// first voxel and shape of the block
+    origin: [usize; 3],
+    extent: [usize; 3],
+    /// intensities of a kernel binned on its own, kept so the buffer is only allocated once
+    intensities: Vec<f64>,
+}
+
+impl Levels {
+    /// levels of every voxel of a volume of `shape`
+    fn whole(data: Vec<u16>, shape: [usize; 3]) -> Self {
+        Levels {
+            data,
+            shape,
+            origin: [0; 3],
+            extent: shape,
+            intensities: vec![],
+        }
+    }
+
+    /// index of `p` in the whole volume, for the intensities and other volumes of its shape
+    pub fn index(&self, [x, y, z]: [usize; 3]) -> usize {
+        x + self.shape[0] * (y + self.shape[1] * z)
+    }
+
+    /// level of `p`, which must lie in the block
+    pub fn at(&self, p: [usize; 3]) -> u16 {
+        let [x, y, z] = [0, 1, 2].map(|d| p[d] - self.origin[d]);
+        self.data[x + self.extent[0] * (y + self.extent[1] * z)]
+    }
+
+    /// bins the intensities of the voxels of `window` in `vol`, a volume of `shape`, on their own
+    /// into `n_bins`, replacing the block held before. The block is the box around the kernel,
+    /// its voxels outside a spherical kernel are left out of the binning
+    fn bin_kernel(&mut self, vol: &[f64], shape: [usize; 3], window: &Window, n_bins: usize) {
+        self.shape = shape;
+        self.origin = window.lo;
+        self.extent = [0, 1, 2].map(|d| window.hi[d] - window.lo[d]);
+        let mut intensities = std::mem::take(&mut self.intensities);
+        intensities.clear();
+        intensities.extend(window.box_voxels().map(|p| match window.contains(p) {
+            true => vol[self.index(p)],
+            false => f64::NAN,
+        }));
+        Binning::new(n_bins).discretize_into(&intensities, &mut self.data);
+        self.intensities = intensities;
+    }
+}
+
+/// where the gray levels of the kernels come from
+pub(crate) enum Binned<'a> {
+    /// the whole volume binned once
+    Global(Levels),
+    /// the intensities of a volume of `shape`, binned kernel by kernel into `n_bins`
+    Local {
+        vol: &'a [f64],
+        shape: [usize; 3],
+        n_bins: usize,
+    },
+}
+
+impl Binned<'_> {
+    fn shape(&self) -> [usize; 3] {
+        match self {
+            Binned::Global(levels) => levels.shape,
+            Binned::Local { shape, .. } => *shape,
+        }
+    }
+}
+
+/// the shape of the kernels of a run, the same around every voxel
+pub(crate) struct Kernel {
+    /// radius along x, y and z of the box around the kernel
+    radius: [usize; 3],
+    /// whether each voxel of the box lies within a spherical kernel, x fastest. None for a box
+    ball: Option<Vec<bool>>,
+}
+
+/// the kernel around a voxel, cut off at the edges of the volume. `lo` is inclusive and `hi`
+/// exclusive, the bounds of the box around the kernel
+pub(crate) struct Window<'a> {
+    pub lo: [usize; 3],
+    pub hi: [usize; 3],
+    centre: [usize; 3],
+    kernel: &'a Kernel,
+    n_voxels: usize,
+}
+
+impl<'a> Window<'a> {
+    fn around(centre: [usize; 3], kernel: &'a Kernel, shape: [usize; 3]) -> Self {
+        let radius = kernel.radius;
+        let mut window = Window {
+            lo: [0, 1, 2].map(|d| centre[d].saturating_sub(radius[d])),
+            hi: [0, 1, 2].map(|d| (centre[d] + radius[d] + 1).min(shape[d])),
+            centre,
+            kernel,
+            n_voxels: 0,
+        };
+        window.n_voxels = match kernel.ball {
+            Some(_) => window.voxels().count(),
+            None => window.box_len(),
+        };
+        window
+    }
+
+    /// whether `p`, a voxel of the box, is in the kernel
+    pub fn contains(&self, p: [usize; 3]) -> bool {
+        let Some(ball) = &self.kernel.ball else {
+            return true;
+        };
+        let [x, y, z] = [0, 1, 2].map(|d| p[d] + self.kernel.radius[d] - self.centre[d]);
+        let [wx, wy, _] = self.kernel.radius.map(|r| 2 * r + 1);
+        ball[x + wx * (y + wy * z)]
+    }
+
+    /// whether the kernel was kept to the slice of its voxel, so only in-plane directions lead
+    /// anywhere
+    fn planar(&self) -> bool {
+        self.kernel.radius[2] == 0
+    }
+
+    /// the [`DIRECTIONS`] within the kernel, the in-plane ones when it is kept to a slice
+    pub fn directions(&self) -> &'static [[isize; 3]] {
+        match self.planar() {
+            true => &DIRECTIONS[..N_IN_PLANE],
+            false => &DIRECTIONS,
+        }
+    }
+
+    /// index of `p` among the voxels of the box around the kernel, x fastest, below
+    /// [`Window::box_len`]
+    pub fn local_index(&self, p: [usize; 3]) -> usize {
+        let [wx, wy, _] = [0, 1, 2].map(|d| self.hi[d] - self.lo[d]);
+        (p[0] - self.lo[0]) + wx * ((p[1] - self.lo[1]) + wy * (p[2] - self.lo[2]))
+    }
+
+    /// voxels of the box around the kernel, all of them in the kernel unless it is a sphere
+    pub fn box_len(&self) -> usize {
+        (0..3).map(|d| self.hi[d] - self.lo[d]).product()
+    }
+
+    pub fn n_voxels(&self) -> usize {
+        self.n_voxels
+    }
+
+    /// the voxel one step from `p` along `dir`, if it is inside the kernel
+    pub fn step(&self, p: [usize; 3], dir: [isize; 3]) -> Option<[usize; 3]> {
+        let mut q = [0; 3];
+        for d in 0..3 {
+            let c = p[d].checked_add_signed(dir[d])?;
+            if c < self.lo[d] || c >= self.hi[d] {
+                return None;
+            }
+            q[d] = c;
+        }
+        Some(q).filter(|&q| self.contains(q))
+    }
+
+    /// the voxels of the kernel
+    pub fn voxels(&self) -> impl Iterator<Item = [usize; 3]> + '_ {
+        self.box_voxels().filter(|&p| self.contains(p))
+    }
+
+    /// every voxel of the box around the kernel
+    fn box_voxels(&self) -> impl Iterator<Item = [usize; 3]> + '_ {
+        (self.lo[2]..self.hi[2]).flat_map(move |z| {
+            (self.lo[1]..self.hi[1])
+                .flat_map(move |y| (self.lo[0]..self.hi[0]).map(move |x| [x, y, z]))
+        })
+    }
+}
+
+/// the 13 directions joining a voxel to its 26 neighbours, one of each opposite pair, as x, y, z
+pub const DIRECTIONS: [[isize; 3]; 13] = [
+    [1, 0, 0],
+    [0, 1, 0],
+    [1, 1, 0],
+    [1, -1, 0],
+    [0, 0, 1],
+    [1, 0, 1],
+    [1, 0, -1],
+    [0, 1, 1],
+    [0, 1, -1],
+    [1, 1, 1],
+    [1, 1, -1],
+    [1, -1, 1],
+    [1, -1, -1],
+];
+
+/// the first directions of [`DIRECTIONS`] lie within an axial slice
+pub const N_IN_PLANE: usize = 4;
+
+/// maps the selected texture features of `vol`, which is binned as the GLCM mapper would with
+/// the bins of `opts`. The first-order, percentile, LBP and fractal features are of
+/// `intensities`, which are `vol` itself unless it holds gray levels binned ahead of the mappers.
+/// Adds the voxels of every family mapped to `progress`
+pub fn map_texture(
+    opts: &MapOpts,
+    texture: &TextureOpts,
+    vol: &[f64],
+    intensities: &[f64],
+    mask: Option<&[f64]>,
+    dims: &ArrayDim,
+    progress: &AtomicUsize,
+) -> Vec<(Feature, Vec<f32>)> {
+    let binned = binned(opts, texture.discretization, vol, dims);
+    let shape = binned.shape();
+    let n_vox: usize = shape.iter().product();
+    let n_bins = opts.n_bins;
+    let run = || {
+        let mut maps = vec![];
+        for family in texture.families() {
+            let features = family.features();
+            let (n, r) = (features.len(), &texture.kernel(opts.kernel_radius));
+            let family_maps = match family {
+                Family::Glcm => unreachable!("GLCM features are mapped by the glcm crate"),
+                Family::Glrlm => sweep(&binned, mask, r, n, progress, |l, w, s, o| {
+                    glrlm::compute(l, w, n_bins, s, o)
+                }),
+                Family::Glszm => sweep(&binned, mask, r, n, progress, |l, w, s, o| {
+                    glszm::compute(l, w, n_bins, s, o)
+                }),
+                Family::Gldzm => {
+                    let distances = gldzm::Distances::to_edge(mask, shape, texture.planar());
+                    sweep(&binned, mask, r, n, progress, |l, w, s, o| {
+                        gldzm::compute(l, &distances, w, n_bins, s, o)
+                    })
+                }
+                Family::Ngtdm => sweep(&binned, mask, r, n, progress, |l, w, s, o| {
+                    ngtdm::compute(l, w, n_bins, s, o)
+                }),
+                Family::Gldm => sweep(&binned, mask, r, n, progress, |l, w, s, o| {
+                    gldm::compute(l, w, n_bins, texture.gldm_alpha, s, o)
+                }),
+                Family::Ngldm => sweep(&binned, mask, r, n, progress, |l, w, s, o| {
+                    let (alpha, distance) = (texture.ngldm_alpha, texture.ngldm_distance);
+                    ngldm::compute(l, w, n_bins, alpha, distance, s, o)
+                }),
+                Family::FirstOrder => sweep(&binned, mask, r, n, progress, |l, w, s, o| {
+                    firstorder::compute(l, intensities, w, n_bins, s, o)
+                }),
+                Family::Percentile => sweep(&binned, mask, r, n, progress, |l, w, s, o| {
+                    percentile::compute(l, intensities, w, s, o)
+                }),
+                Family::Lbp => {
+                    let codes = lbp::codes(&intensities[..n_vox], shape, texture.directions());
+                    sweep(&binned, mask, r, n, progress, |l, w, s, o| {
+                        lbp::compute(l, &codes, w, s, o)
+                    })
+                }
+                Family::Fractal => {
+                    let range = intensities[..n_vox]
+                        .iter()
+                        .filter(|x| x.is_finite())
+                        .fold([f64::INFINITY, f64::NEG_INFINITY], |[lo, hi], &x| {
+                            [lo.min(x), hi.max(x)]
+                        });
+                    sweep(&binned, mask, r, n, progress, |l, w, s, o| {
+                        fractal::compute(l, intensities, range, w, s, o)
+                    })
+                }
+                Family::Haralick => sweep(&binned, mask, r, n, progress, |l, w, s, o| {
+                    haralick::compute(l, w, texture.offsets(), s, o)
+                }),
+            };
+            maps.extend(
+                features
+                    .into_iter()
+                    .zip(family_maps)
+                    .filter(|(f, _)| texture.features.contains_key(f)),
+            );
+        }
+        maps
+    };
+    in_pool(opts.max_threads, run)
+}
+
+/// maps the selected GLCM features of `vol` along each of the 13 [`DIRECTIONS`] on its own,
+/// binned as [`map_texture`] does. Returns the 13 maps of every feature, in the order of the
+/// directions, NaN along directions left out of [`TextureOpts::angles`]. Adds the voxels mapped to
+/// `progress`
+pub fn map_glcm_directions(
+    opts: &MapOpts,
+    texture: &TextureOpts,
+    vol: &[f64],
+    mask: Option<&[f64]>,
+    dims: &ArrayDim,
+    progress: &AtomicUsize,
+) -> Vec<(Feature, Vec<Vec<f32>>)> {
+    let mut features: Vec<GLCMFeature> = opts.features.keys().copied().collect();
+    features.sort_by_key(|f| *f as usize);
+    let stats: Vec<cooccurrence::Stat> = features
+        .iter()
+        .map(|f| cooccurrence::Stat::of(*f).expect("checked when the options were built"))
+        .collect();
+    let binned = binned(opts, texture.discretization, vol, dims);
+    let offsets = texture.offsets();
+    let n = stats.len() * DIRECTIONS.len();
+    let maps = in_pool(opts.max_threads, || {
+        sweep(
+            &binned,
+            mask,
+            &texture.kernel(opts.kernel_radius),
+            n,
+            progress,
+            |l, w, s, o| cooccurrence::compute(l, w, offsets, &stats, s, o),
+        )
+    });
+    let mut maps = maps.into_iter();
+    features
+        .into_iter()
+        .map(|f| {
+            let by_direction = maps.by_ref().take(DIRECTIONS.len()).collect();
+            (Feature::Glcm(f), by_direction)
+        })
+        .collect()
+}
+
+/// gray levels of `vol`, binned as the GLCM mapper would with the bins of `opts`, or left to be
+/// binned kernel by kernel
+fn binned<'a>(
+    opts: &MapOpts,
+    scope: DiscretizationScope,
+    vol: &'a [f64],
+    dims: &ArrayDim,
+) -> Binned<'a> {
+    let shape = [0, 1, 2].map(|d| dims.shape_ns()[d]);
+    let n_vox: usize = shape.iter().product();
+    match scope {
+        DiscretizationScope::Global => Binned::Global(Levels::whole(
+            Binning::new(opts.n_bins).discretize(&vol[..n_vox]),
+            shape,
+        )),
+        DiscretizationScope::Local => Binned::Local {
+            vol: &vol[..n_vox],
+            shape,
+            n_bins: opts.n_bins,
+        },
+    }
+}
+
+/// runs `f` on at most `max_threads` threads, or the global pool without a limit
+fn in_pool<T: Send>(max_threads: Option<usize>, f: impl FnOnce() -> T + Send) -> T {
+    match max_threads {
+        Some(n) => rayon::ThreadPoolBuilder::new()
+            .num_threads(n)
+            .build()
+            .expect("failed to start the worker threads")
+            .install(f),
+        None => f(),
+    }
+}
+
+/// computes `n_out` features of every voxel in the mask from its kernel, one map per feature.
+/// Slices are mapped in parallel, each thread reusing its own scratch space and, when kernels are
+/// binned on their own, the levels of its kernel
+fn sweep<S: Default + Send>(
+    binned: &Binned,
+    mask: Option<&[f64]>,
+    kernel: &Kernel,
+    n_out: usize,
+    progress: &AtomicUsize,
+    compute: impl Fn(&Levels, &Window, &mut S, &mut [f64]) + Sync,
+) -> Vec<Vec<f32>> {
+    let shape = binned.shape();
+    let [nx, ny, nz] = shape;
+    let slice_len = nx * ny;
+    let slices: Vec<Vec<f32>> = (0..nz)
+        .into_par_iter()
+        .map_init(<(S, Levels)>::default, |(scratch, local), z| {
+            let mut slice = vec![0f32; n_out * slice_len];
+            let mut out = vec![0f64; n_out];
+            for y in 0..ny {
+                for x in 0..nx {
+                    let i = x + nx * y;
+                    if mask.is_some_and(|m| m[z * slice_len + i] == 0.) {
+                        continue;
+                    }
+                    let window = Window::around([x, y, z], kernel, shape);
+                    let levels = match binned {
+                        Binned::Global(levels) => levels,
+                        &Binned::Local { vol, n_bins, .. } => {
+                            local.bin_kernel(vol, shape, &window, n_bins);
+                            &*local
+                        }
+                    };
+                    compute(levels, &window, scratch, &mut out);
+                    for (k, v) in out.iter().enumerate() {
+                        slice[k * slice_len + i] = *v as f32;
+                    }
+                }
+            }
+            progress.fetch_add(slice_len, Ordering::Relaxed);
+            slice
+        })
+        .collect();
+    (0..n_out)
+        .map(|k| {
+            slices
+                .iter()
+                .flat_map(|s| &s[k * slice_len..(k + 1) * slice_len])
+                .copied()
+                .collect()
+        })
+        .collect()
+}
+
+/// the gray levels of a small volume and a kernel reaching all of it, for the reference values of
+/// the families
+#[cfg(test)]
+pub(crate) struct Phantom {
+    pub levels: Levels,
+    kernel: Kernel,
+}
+
+#[cfg(test)]
+impl Phantom {
+    /// gray levels numbered from 1 as in the formulas, x fastest
+    pub fn new(levels: &[u16], shape: [usize; 3]) -> Self {
+        Phantom {
+            levels: Levels::whole(levels.iter().map(|l| l - 1).collect(), shape),
+            kernel: Kernel {
+                radius: shape.map(|n| n - 1),
+                ball: None,
+            },
+        }
+    }
+
+    /// the 5 by 5 image of the GLSZM example in the pyradiomics documentation, a single slice so
+    /// the families count in 2D like pyradiomics does for it
+    pub fn pyradiomics() -> Self {
+        #[rustfmt::skip]
+        let levels = [
+            5, 2, 5, 4, 4,
+            3, 3, 3, 1, 3,
+            2, 1, 1, 1, 3,
+            4, 2, 2, 2, 3,
+            3, 5, 3, 3, 2,
+        ];
+        Phantom::new(&levels, [5, 5, 1])
+    }
+
+    /// the kernel around the first voxel, which reaches every voxel
+    pub fn window(&self) -> Window<'_> {
+        Window::around([0; 3], &self.kernel, self.levels.shape)
+    }
+}
+
+/// checks features against reference values given to 12 significant digits
+#[cfg(test)]
+pub(crate) fn assert_features(got: &[f64], expected: &[f64]) {
+    assert_eq!(got.len(), expected.len());
+    for (k, (g, e)) in got.iter().zip(expected).enumerate() {
+        assert!(
+            (g - e).abs() <= 1e-10 * e.abs().max(1.),
+            "feature {k}: {g} != {e}"
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! Neighbouring gray level dependence matrix features, as the IBSI defines them. A neighbour
+//! depends on a voxel when their gray levels differ by at most alpha, the coarseness, and the
+//! dependence count of a voxel is the number of voxels within the neighbourhood distance of it,
+//! in steps along any of the 26 neighbour directions, that lie inside the kernel and depend on it.
+//! Gray levels are numbered from 1 and dependence counts are one more than the count in the
+//! formulas, the voxel itself counting as dependent, like in pyradiomics.
+//!
+//! The GLDM features are the same matrix at a distance of 1, under the pyradiomics names and
+//! without the features pyradiomics leaves out.
+
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter, EnumString};
+
+use super::matrix::{self, SizeMatrix};
+use super::{Levels, Window};
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    EnumIter,
+    Display,
+    EnumString,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum NGLDMFeature {
+    LowDependenceEmphasis,
+    HighDependenceEmphasis,
+    LowGrayLevelCountEmphasis,
+    HighGrayLevelCountEmphasis,
+    LowDependenceLowGrayLevelEmphasis,
+    LowDependenceHighGrayLevelEmphasis,
+    HighDependenceLowGrayLevelEmphasis,
+    HighDependenceHighGrayLevelEmphasis,
+    GrayLevelNonUniformity,
+    GrayLevelNonUniformityNormalized,
+    DependenceCountNonUniformity,
+    DependenceCountNonUniformityNormalized,
+    DependenceCountPercentage,
+    GrayLevelVariance,
+    DependenceCountVariance,
+    DependenceCountEntropy,
+    DependenceCountEnergy,
+}
+
+/// the features of a [`SizeMatrix`] that make up the NGLDM features but the energy, in their
+/// order
+const FROM_MATRIX: [usize; 16] = [0, 1, 10, 11, 12, 13, 14, 15, 2, 3, 4, 5, 6, 7, 8, 9];
+
+/// number of NGLDM features
+pub(crate) const N_FEATURES: usize = FROM_MATRIX.len() + 1;
+
+pub const DEFAULT_DISTANCE: usize = 1;
+
+/// dependence matrix and neighbourhood of one kernel, kept between voxels so it is only
+/// allocated once per thread
+#[derive(Default)]
+pub(crate) struct Scratch {
+    dependences: SizeMatrix,
+    features: [f64; matrix::N_FEATURES],
+    offsets: Vec<[isize; 3]>,
+}
+
+/// writes every feature, in the order of [`NGLDMFeature`], of the kernel in `window` to `out`
+pub(crate) fn compute(
+    levels: &Levels,
+    window: &Window,
+    n_bins: usize,
+    alpha: usize,
+    distance: usize,
+    s: &mut Scratch,
+    out: &mut [f64],
+) {
+    let reach = distance as isize;
+    let side = 2 * distance + 1;
+    // every step within the distance, found again only when the distance changes
+    if s.offsets.len() != side * side * side - 1 {
+        s.offsets.clear();
+        for z in -reach..=reach {
+            for y in -reach..=reach {
+                for x in -reach..=reach {
+                    if [x, y, z] != [0, 0, 0] {
+                        s.offsets.push([x, y, z]);
+                    }
+                }
+            }
+        }
+    }
+
+    s.dependences.reset(n_bins, side * side * side);
+    for p in window.voxels() {
+        let level = levels.at(p);
+        let mut count = 1;
+        for &offset in &s.offsets {
+            if window
+                .step(p, offset)
+                .is_some_and(|q| levels.at(q).abs_diff(level) as usize <= alpha)
+            {
+                count += 1;
+            }
+        }
+        s.dependences.add(level, count);
+    }
+
+    s.dependences
+        .features(window.n_voxels() as f64, &mut s.features);
+    for (o, &i) in out.iter_mut().zip(&FROM_MATRIX) {
+        *o = s.features[i];
+    }
+    out[FROM_MATRIX.len()] = s.dependences.energy();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::{assert_features, Phantom};
+
+    #[test]
+    fn pyradiomics_example() {
+        let phantom = Phantom::pyradiomics();
+        let mut out = [0.; N_FEATURES];
+        compute(
+            &phantom.levels,
+            &phantom.window(),
+            5,
+            0,
+            DEFAULT_DISTANCE,
+            &mut Scratch::default(),
+            &mut out,
+        );
+        assert_features(
+            &out,
+            &[
+                0.3725,
+                5.56,
+                0.2723,
+                9.28,
+                0.0591364197531,
+                5.15472222222,
+                2.14285555556,
+                35.12,
+                6.04,
+                0.2416,
+                7.96,
+                0.3184,
+                1.,
+                1.44,
+                0.72,
+                3.3834651896,
+                0.1072,
+            ],
+        );
+    }
+}
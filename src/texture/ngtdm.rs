@@ -0,0 +1,174 @@
+//! Neighbourhood gray tone difference matrix features. Each voxel of the kernel is compared to
+//! the mean gray level of its 26 neighbours that lie inside the kernel, and the differences are
+//! summed per gray level. Gray levels are numbered from 1 in the formulas. Where a formula
+//! divides by zero, as on a kernel of a single gray level, the feature is 0, except coarseness,
+//! which is 10^6 like in pyradiomics.
+
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter, EnumString};
+
+use super::{Levels, Window, DIRECTIONS};
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    EnumIter,
+    Display,
+    EnumString,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum NGTDMFeature {
+    Coarseness,
+    Contrast,
+    Busyness,
+    Complexity,
+    Strength,
+}
+
+/// coarseness of a kernel without any gray tone differences
+const MAX_COARSENESS: f64 = 1e6;
+
+/// sums per gray level of one kernel, kept between voxels so they are only allocated once per
+/// thread
+#[derive(Default)]
+pub(crate) struct Scratch {
+    /// number of voxels of each gray level with at least one neighbour
+    n: Vec<f64>,
+    /// summed absolute difference to the neighbourhood mean of each gray level
+    s: Vec<f64>,
+    /// gray levels present, numbered from 0
+    present: Vec<usize>,
+}
+
+/// writes every feature, in the order of [`NGTDMFeature`], of the kernel in `window` to `out`
+pub(crate) fn compute(
+    levels: &Levels,
+    window: &Window,
+    n_bins: usize,
+    m: &mut Scratch,
+    out: &mut [f64],
+) {
+    m.n.clear();
+    m.n.resize(n_bins, 0.);
+    m.s.clear();
+    m.s.resize(n_bins, 0.);
+    m.present.clear();
+
+    let mut n_voxels = 0.;
+    for p in window.voxels() {
+        let mut sum = 0.;
+        let mut count = 0;
+        for dir in DIRECTIONS {
+            for dir in [dir, dir.map(|d| -d)] {
+                if let Some(q) = window.step(p, dir) {
+                    sum += levels.at(q) as f64;
+                    count += 1;
+                }
+            }
+        }
+        if count == 0 {
+            continue;
+        }
+        let level = levels.at(p) as usize;
+        if m.n[level] == 0. {
+            m.present.push(level);
+        }
+        m.n[level] += 1.;
+        m.s[level] += (level as f64 - sum / count as f64).abs();
+        n_voxels += 1.;
+    }
+
+    let n_levels = m.present.len() as f64;
+    let p = |i: usize| m.n[i] / n_voxels;
+    let gray = |i: usize| (i + 1) as f64;
+    let mut ps_sum = 0.;
+    let mut s_sum = 0.;
+    for &i in &m.present {
+        ps_sum += p(i) * m.s[i];
+        s_sum += m.s[i];
+    }
+    let [mut contrast, mut busyness_denom, mut complexity, mut strength] = [0.; 4];
+    for &i in &m.present {
+        for &j in &m.present {
+            let d = gray(i) - gray(j);
+            contrast += p(i) * p(j) * d * d;
+            busyness_denom += (gray(i) * p(i) - gray(j) * p(j)).abs();
+            complexity += d.abs() * (p(i) * m.s[i] + p(j) * m.s[j]) / (p(i) + p(j));
+            strength += (p(i) + p(j)) * d * d;
+        }
+    }
+
+    out[0] = if ps_sum > 0. {
+        1. / ps_sum
+    } else {
+        MAX_COARSENESS
+    };
+    out[1] = if n_levels > 1. {
+        contrast / (n_levels * (n_levels - 1.)) * s_sum / n_voxels
+    } else {
+        0.
+    };
+    out[2] = if busyness_denom > 0. {
+        ps_sum / busyness_denom
+    } else {
+        0.
+    };
+    out[3] = if n_voxels > 0. {
+        complexity / n_voxels
+    } else {
+        0.
+    };
+    out[4] = if s_sum > 0. { strength / s_sum } else { 0. };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::{assert_features, Phantom};
+
+    #[test]
+    fn pyradiomics_example() {
+        let phantom = Phantom::pyradiomics();
+        let mut out = [0.; 5];
+        compute(
+            &phantom.levels,
+            &phantom.window(),
+            5,
+            &mut Scratch::default(),
+            &mut out,
+        );
+        assert_features(
+            &out,
+            &[
+                0.200561572403,
+                0.146016,
+                0.635969387755,
+                8.25052844933,
+                1.37278106509,
+            ],
+        );
+    }
+
+    #[test]
+    fn single_gray_level_is_coarsest() {
+        let phantom = Phantom::new(&[2; 8], [2, 2, 2]);
+        let mut out = [0.; 5];
+        compute(
+            &phantom.levels,
+            &phantom.window(),
+            4,
+            &mut Scratch::default(),
+            &mut out,
+        );
+        assert_eq!(out, [MAX_COARSENESS, 0., 0., 0., 0.]);
+    }
+}
@@ -0,0 +1,111 @@
+//! Local percentiles of the intensities in the kernel, robust to outliers where the first-order
+//! mean and variance are not, and cheap surrogates for texture. Like the first-order features
+//! they come from the intensities, not the gray levels, so the number of bins doesn't change
+//! them. Percentiles are interpolated linearly between the sorted intensities, as
+//! `numpy.percentile` does by default and pyradiomics with it.
+
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter, EnumString};
+
+use super::{Levels, Window};
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    EnumIter,
+    Display,
+    EnumString,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum PercentileFeature {
+    P10,
+    /// the median
+    P50,
+    P90,
+    /// 75th less 25th percentile
+    InterquartileRange,
+}
+
+/// intensities of one kernel, kept between voxels so they are only allocated once per thread
+#[derive(Default)]
+pub(crate) struct Scratch {
+    sorted: Vec<f64>,
+}
+
+/// writes every feature, in the order of [`PercentileFeature`], of the kernel in `window` to
+/// `out`. `vol` holds the intensities the levels were binned from
+pub(crate) fn compute(
+    levels: &Levels,
+    vol: &[f64],
+    window: &Window,
+    s: &mut Scratch,
+    out: &mut [f64],
+) {
+    s.sorted.clear();
+    s.sorted
+        .extend(window.voxels().map(|p| vol[levels.index(p)]));
+    s.sorted.sort_unstable_by(f64::total_cmp);
+
+    let at = |q: f64| {
+        let rank = q * (s.sorted.len() - 1) as f64;
+        let (lo, hi) = (rank.floor() as usize, rank.ceil() as usize);
+        s.sorted[lo] + (rank - lo as f64) * (s.sorted[hi] - s.sorted[lo])
+    };
+    out[0] = at(0.1);
+    out[1] = at(0.5);
+    out[2] = at(0.9);
+    out[3] = at(0.75) - at(0.25);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::{assert_features, Phantom};
+
+    fn percentiles(phantom: &Phantom, intensities: &[f64]) -> [f64; 4] {
+        let mut out = [0.; 4];
+        compute(
+            &phantom.levels,
+            intensities,
+            &phantom.window(),
+            &mut Scratch::default(),
+            &mut out,
+        );
+        out
+    }
+
+    /// 4 voxels of level 1, 6 of 2, 9 of 3, 3 of 4 and 3 of 5, as `numpy.percentile` gives them
+    #[test]
+    fn pyradiomics_example() {
+        let phantom = Phantom::pyradiomics();
+        let intensities: Vec<f64> = phantom.levels.data.iter().map(|l| *l as f64 + 1.).collect();
+        assert_features(&percentiles(&phantom, &intensities), &[1., 3., 4.6, 1.]);
+    }
+
+    #[test]
+    fn percentiles_interpolate_between_intensities() {
+        // in any order, and an outlier only moves the upper percentiles
+        let phantom = Phantom::new(&[1; 4], [4, 1, 1]);
+        let expected = [1.3, 2.5, 3. + 0.7 * 7., (3. + 0.25 * 7.) - 1.75];
+        assert_features(&percentiles(&phantom, &[3., 10., 1., 2.]), &expected);
+        assert_features(
+            &percentiles(&phantom, &[3., 4., 1., 2.]),
+            &[1.3, 2.5, 3.7, 3.25 - 1.75],
+        );
+    }
+
+    #[test]
+    fn a_constant_kernel_is_its_intensity() {
+        let phantom = Phantom::new(&[1; 27], [3, 3, 3]);
+        assert_features(&percentiles(&phantom, &[-2.5; 27]), &[-2.5, -2.5, -2.5, 0.]);
+    }
+}
@@ -0,0 +1,112 @@
+//! Icon in the system tray that the GUI hides in while a run goes on with its window closed, with
+//! a menu showing the progress and entries to cancel the run and bring the window back. Windows
+//! and macOS always have a tray. Linux desktops often don't, so there is no icon and the GUI
+//! minimizes the window instead.
+
+/// what was picked from the menu of the icon
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrayAction {
+    Restore,
+    Cancel,
+}
+
+pub use platform::Tray;
+
+#[cfg(any(windows, target_os = "macos"))]
+mod platform {
+    use tray_icon::menu::{Menu, MenuEvent, MenuItem};
+    use tray_icon::{Icon, TrayIcon, TrayIconBuilder};
+
+    use super::TrayAction;
+
+    /// the icon, removed from the tray when dropped
+    pub struct Tray {
+        _icon: TrayIcon,
+        status: MenuItem,
+        restore: MenuItem,
+        cancel: MenuItem,
+    }
+
+    impl Tray {
+        /// puts an icon of `width` by `height` RGBA pixels in the tray. None if there is no tray
+        pub fn new(rgba: Vec<u8>, width: u32, height: u32) -> Option<Self> {
+            let status = MenuItem::new("starting ...", false, None);
+            let restore = MenuItem::new("Show RadMap", true, None);
+            let cancel = MenuItem::new("Cancel the run", true, None);
+            let menu = Menu::new();
+            let built = menu
+                .append_items(&[&status, &restore, &cancel])
+                .map_err(|e| e.to_string())
+                .and_then(|_| Icon::from_rgba(rgba, width, height).map_err(|e| e.to_string()))
+                .and_then(|icon| {
+                    TrayIconBuilder::new()
+                        .with_menu(Box::new(menu))
+                        .with_tooltip("RadMap")
+                        .with_icon(icon)
+                        .build()
+                        .map_err(|e| e.to_string())
+                });
+            match built {
+                Ok(icon) => Some(Tray {
+                    _icon: icon,
+                    status,
+                    restore,
+                    cancel,
+                }),
+                Err(e) => {
+                    eprintln!("failed to put radmap in the system tray: {e}");
+                    None
+                }
+            }
+        }
+
+        /// the line at the top of the menu, like how far along the run is
+        pub fn set_status(&self, status: &str) {
+            self.status.set_text(status);
+        }
+
+        /// turns off the cancel entry once there is nothing left to cancel
+        pub fn set_cancellable(&self, cancellable: bool) {
+            self.cancel.set_enabled(cancellable);
+        }
+
+        /// the next entry picked from the menu, if any
+        pub fn poll(&self) -> Option<TrayAction> {
+            while let Ok(event) = MenuEvent::receiver().try_recv() {
+                if event.id == *self.restore.id() {
+                    return Some(TrayAction::Restore);
+                }
+                if event.id == *self.cancel.id() {
+                    return Some(TrayAction::Cancel);
+                }
+            }
+            None
+        }
+    }
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+mod platform {
+    use super::TrayAction;
+
+    /// never made, there is no tray to put it in
+    pub enum Tray {}
+
+    impl Tray {
+        pub fn new(_rgba: Vec<u8>, _width: u32, _height: u32) -> Option<Self> {
+            None
+        }
+
+        pub fn set_status(&self, _status: &str) {
+            match *self {}
+        }
+
+        pub fn set_cancellable(&self, _cancellable: bool) {
+            match *self {}
+        }
+
+        pub fn poll(&self) -> Option<TrayAction> {
+            match *self {}
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Opt-in, local-only usage statistics.
+//!
+//! Nothing is recorded until the user enables collection, and nothing ever leaves the machine:
+//! the counters live in a plain text file in the user data directory that can be inspected,
+//! exported or deleted at any time. Only these counters are kept:
+//!
+//! * `runs.<app>`: number of completed runs per front end (`radmap` or `radmap-gui`)
+//! * `feature.<name>`: number of times each feature was computed
+//! * `volume_voxels.<bucket>`: number of input volumes per size bucket (e.g. `1M-10M`)
+//!
+//! No paths, file names, header fields or image data are stored.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use glcm::ui::MapOpts;
+
+use crate::texture::{selected_features, TextureOpts};
+
+const USAGE_FILE_NAME: &str = "usage_stats.txt";
+
+/// where the counters are stored. `RADMAP_USAGE_FILE` overrides the default location in the user
+/// data directory
+pub fn usage_file() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("RADMAP_USAGE_FILE") {
+        return Some(PathBuf::from(path));
+    }
+    data_dir().map(|d| d.join(USAGE_FILE_NAME))
+}
+
+/// radmap's directory in the user data directory
+pub(crate) fn data_dir() -> Option<PathBuf> {
+    let data_dir = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if let Some(xdg) = std::env::var_os("XDG_DATA_HOME") {
+        Some(PathBuf::from(xdg))
+    } else {
+        std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".local").join("share"))
+    };
+    data_dir.map(|d| d.join("radmap"))
+}
+
+/// collection is enabled exactly when the usage file exists
+pub fn is_enabled() -> bool {
+    usage_file().is_some_and(|f| f.is_file())
+}
+
+pub fn enable() -> Result<PathBuf, String> {
+    let file = usage_file().ok_or("unable to determine the user data directory")?;
+    if !file.is_file() {
+        UsageStats::default().save(&file)?;
+    }
+    Ok(file)
+}
+
+/// stops collection and deletes everything collected so far
+pub fn disable() -> Result<(), String> {
+    if let Some(file) = usage_file().filter(|f| f.is_file()) {
+        fs::remove_file(&file).map_err(|e| format!("failed to remove {}: {e}", file.display()))?;
+    }
+    Ok(())
+}
+
+/// adds a completed run to the counters if collection is enabled. Failing to record is never an
+/// error worth interrupting the user for, so problems are only printed
+pub fn record_run(app: &str, opts: &MapOpts, texture: &TextureOpts, n_voxels: usize) {
+    let Some(file) = usage_file().filter(|f| f.is_file()) else {
+        return;
+    };
+    let mut stats = UsageStats::load(&file).unwrap_or_default();
+    stats.increment(format!("runs.{app}"));
+    for (f, _) in selected_features(opts, texture) {
+        stats.increment(format!("feature.{f}"));
+    }
+    stats.increment(format!("volume_voxels.{}", size_bucket(n_voxels)));
+    if let Err(e) = stats.save(&file) {
+        eprintln!("failed to update usage statistics: {e}");
+    }
+}
+
+fn size_bucket(n_voxels: usize) -> &'static str {
+    match n_voxels {
+        0..1_000_000 => "<1M",
+        1_000_000..10_000_000 => "1M-10M",
+        10_000_000..100_000_000 => "10M-100M",
+        _ => ">=100M",
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct UsageStats {
+    pub counters: BTreeMap<String, u64>,
+}
+
+impl UsageStats {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        let counters = text
+            .lines()
+            .filter_map(|l| l.split_once('='))
+            .filter_map(|(k, v)| Some((k.trim().to_string(), v.trim().parse().ok()?)))
+            .collect();
+        Ok(UsageStats { counters })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("failed to create {}: {e}", parent.display()))?;
+        }
+        let mut text = String::from("# radmap local usage statistics, see `radmap --help`\n");
+        for (k, v) in &self.counters {
+            text.push_str(&format!("{k}={v}\n"));
+        }
+        fs::write(path, text).map_err(|e| format!("failed to write {}: {e}", path.display()))
+    }
+
+    /// writes the counters as a two column csv for sharing
+    pub fn export_csv(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        let mut text = String::from("counter,count\n");
+        for (k, v) in &self.counters {
+            text.push_str(&format!("{k},{v}\n"));
+        }
+        fs::write(path, text).map_err(|e| format!("failed to write {}: {e}", path.display()))
+    }
+
+    fn increment(&mut self, key: String) {
+        *self.counters.entry(key).or_insert(0) += 1;
+    }
+}
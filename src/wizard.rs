@@ -0,0 +1,241 @@
+//! Interactive prompts that build a full `radmap` command line step by step.
+
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::discretize::BinEdges;
+use crate::header::{describe_kernel, voxel_spacing};
+use crate::io::{format_names, is_in_directory, is_volume};
+use crate::options::{DEFAULT_KERNEL_RADIUS, DEFAULT_N_BINS};
+use crate::texture::{Family, Feature};
+
+pub struct Wizard<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Wizard<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Wizard { input, output }
+    }
+
+    /// asks for every option and returns the arguments for the resulting command, not
+    /// including the program name. None if input ends before all questions are answered
+    pub fn run(&mut self) -> Option<Vec<String>> {
+        self.say("radmap wizard. Press enter to accept the default shown in [brackets]\n");
+
+        let input_vol = self.ask_path("input volume (nrrd or nifti)", false)?;
+        let mask = self.ask_path("mask (leave empty to process every voxel)", true);
+        // outputs may not be written next to the inputs without --allow-in-place
+        let default_out = input_vol.parent().map(|dir| dir.join("radmap_out"));
+        let inputs: Vec<&Path> = [Some(input_vol.as_path()), mask.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect();
+        let output_dir = self.ask_output_dir(default_out, &inputs)?;
+
+        self.say("available features:\n");
+        for family in Family::ALL {
+            let names: Vec<String> = family.features().iter().map(|f| f.to_string()).collect();
+            self.say(&format!("  {}: {}\n", family.label(), names.join(", ")));
+        }
+        let features = self.ask_features()?;
+
+        let n_bins = self.ask_number("number of bins", DEFAULT_N_BINS, 2)?;
+        let spacing = voxel_spacing(&input_vol);
+        for r in 1..=3 {
+            self.say(&format!(
+                "  radius {r}: {}\n",
+                describe_kernel([r; 3], spacing)
+            ));
+        }
+        let kernel_radius = self.ask_number("kernel radius", DEFAULT_KERNEL_RADIUS, 1)?;
+        let bin_edges = self.ask_bin_edges()?;
+        let max_threads = self.ask_optional_number("max threads (leave empty to use all cores)")?;
+
+        let mut args = vec![
+            input_vol.to_string_lossy().to_string(),
+            output_dir.to_string_lossy().to_string(),
+        ];
+        if let Some(mask) = mask {
+            args.extend(["--mask".to_string(), mask.to_string_lossy().to_string()]);
+        }
+        match features {
+            None => args.push("--all-features".to_string()),
+            Some(features) => {
+                for f in features {
+                    args.extend(["--feature".to_string(), f]);
+                }
+            }
+        }
+        args.extend(["--n-bins".to_string(), n_bins.to_string()]);
+        args.extend(["--kernel-radius".to_string(), kernel_radius.to_string()]);
+        if bin_edges != BinEdges::default() {
+            args.push(format!("--bin-edges={bin_edges}"));
+        }
+        if let Some(threads) = max_threads {
+            args.extend(["--max-threads".to_string(), threads.to_string()]);
+        }
+        Some(args)
+    }
+
+    /// yes/no question, None if input ended
+    pub fn confirm(&mut self, question: &str, default: bool) -> Option<bool> {
+        let hint = if default { "Y/n" } else { "y/N" };
+        loop {
+            let answer = self.ask(&format!("{question} [{hint}]"))?;
+            match answer.to_lowercase().as_str() {
+                "" => return Some(default),
+                "y" | "yes" => return Some(true),
+                "n" | "no" => return Some(false),
+                _ => self.say("please answer y or n\n"),
+            }
+        }
+    }
+
+    fn say(&mut self, text: &str) {
+        let _ = write!(self.output, "{text}");
+        let _ = self.output.flush();
+    }
+
+    /// prints the prompt and reads a trimmed line, None at end of input
+    fn ask(&mut self, prompt: &str) -> Option<String> {
+        self.say(&format!("{prompt}: "));
+        let mut line = String::new();
+        match self.input.read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.trim().to_string()),
+        }
+    }
+
+    /// an existing nrrd or nifti. An optional path may be left empty
+    fn ask_path(&mut self, prompt: &str, optional: bool) -> Option<PathBuf> {
+        loop {
+            let answer = self.ask(prompt)?;
+            if answer.is_empty() {
+                if optional {
+                    return None;
+                }
+                continue;
+            }
+            let path = PathBuf::from(answer);
+            if !path.is_file() {
+                self.say(&format!("{} does not exist\n", path.display()));
+            } else if !is_volume(&path) {
+                self.say(&format!("only {} files are supported\n", format_names()));
+            } else {
+                return Some(path);
+            }
+        }
+    }
+
+    /// refuses the directory holding one of the `inputs`
+    fn ask_output_dir(&mut self, default: Option<PathBuf>, inputs: &[&Path]) -> Option<PathBuf> {
+        loop {
+            let prompt = match &default {
+                Some(d) => format!("output directory [{}]", d.display()),
+                None => "output directory".to_string(),
+            };
+            let answer = self.ask(&prompt)?;
+            let dir = match (answer.is_empty(), &default) {
+                (true, Some(d)) => d.clone(),
+                (true, None) => continue,
+                (false, _) => PathBuf::from(answer),
+            };
+            if inputs.iter().any(|input| is_in_directory(input, &dir)) {
+                self.say(&format!(
+                    "{} holds an input, choose another directory\n",
+                    dir.display()
+                ));
+                continue;
+            }
+            if dir.is_dir() {
+                return Some(dir);
+            }
+            if self.confirm(
+                &format!("{} does not exist, create it?", dir.display()),
+                true,
+            )? {
+                match std::fs::create_dir_all(&dir) {
+                    Ok(_) => return Some(dir),
+                    Err(e) => self.say(&format!("failed to create {}: {e}\n", dir.display())),
+                }
+            }
+        }
+    }
+
+    /// None for all GLCM features, otherwise the chosen feature names
+    fn ask_features(&mut self) -> Option<Option<Vec<String>>> {
+        loop {
+            let answer = self.ask("features, separated by commas [all GLCM features]")?;
+            if answer.is_empty() || answer.eq_ignore_ascii_case("all") {
+                return Some(None);
+            }
+            let chosen: Vec<String> = answer
+                .split(',')
+                .map(|f| f.trim().to_lowercase())
+                .filter(|f| !f.is_empty())
+                .collect();
+            match chosen.iter().find(|f| Feature::from_str(f).is_err()) {
+                Some(unknown) => self.say(&format!("unknown feature {unknown}\n")),
+                None => return Some(Some(chosen)),
+            }
+        }
+    }
+
+    fn ask_number(&mut self, prompt: &str, default: usize, min: usize) -> Option<usize> {
+        loop {
+            let answer = self.ask(&format!("{prompt} [{default}]"))?;
+            if answer.is_empty() {
+                return Some(default);
+            }
+            match answer.parse::<usize>() {
+                Ok(n) if n >= min => return Some(n),
+                _ => self.say(&format!("please enter a whole number of at least {min}\n")),
+            }
+        }
+    }
+
+    fn ask_optional_number(&mut self, prompt: &str) -> Option<Option<usize>> {
+        loop {
+            let answer = self.ask(prompt)?;
+            if answer.is_empty() {
+                return Some(None);
+            }
+            match answer.parse::<usize>() {
+                Ok(n) if n >= 1 => return Some(Some(n)),
+                _ => self.say("please enter a whole number of at least 1\n"),
+            }
+        }
+    }
+
+    fn ask_bin_edges(&mut self) -> Option<BinEdges> {
+        for edges in BinEdges::ALL {
+            self.say(&format!("  {edges}: {}\n", edges.describe()));
+        }
+        loop {
+            let answer = self.ask(&format!("bin edges [{}]", BinEdges::default()))?;
+            if answer.is_empty() {
+                return Some(BinEdges::default());
+            }
+            match BinEdges::from_str(&answer) {
+                Ok(edges) => return Some(edges),
+                Err(e) => self.say(&format!("{e}\n")),
+            }
+        }
+    }
+}
+
+/// quotes an argument for display in a POSIX shell if it needs it
+pub fn shell_quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+    if plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
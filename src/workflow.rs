@@ -0,0 +1,188 @@
+//! Process and rule definitions for pipeline frameworks, wrapping `radmap container-entrypoint`
+//! so pipelines get its json line logs and exit codes (see [`crate::failure`]) for free.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::discretize::BinEdges;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkflowKind {
+    Nextflow,
+    Snakemake,
+}
+
+impl WorkflowKind {
+    pub const ALL: [WorkflowKind; 2] = [WorkflowKind::Nextflow, WorkflowKind::Snakemake];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WorkflowKind::Nextflow => "nextflow",
+            WorkflowKind::Snakemake => "snakemake",
+        }
+    }
+}
+
+impl fmt::Display for WorkflowKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for WorkflowKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        WorkflowKind::ALL
+            .into_iter()
+            .find(|k| k.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<&str> = WorkflowKind::ALL.iter().map(|k| k.as_str()).collect();
+                format!("unknown workflow {s}, expected one of {}", names.join(", "))
+            })
+    }
+}
+
+/// defaults written into the generated definition. Pipelines can override all of them
+#[derive(Debug, Clone)]
+pub struct WorkflowParams {
+    pub n_bins: usize,
+    pub kernel_radius: usize,
+    /// None for all features
+    pub features: Option<Vec<String>>,
+    pub bin_edges: BinEdges,
+}
+
+impl WorkflowParams {
+    fn features(&self) -> String {
+        self.features
+            .as_ref()
+            .map(|f| f.join(","))
+            .unwrap_or("all".to_string())
+    }
+}
+
+pub fn emit(kind: WorkflowKind, params: &WorkflowParams) -> String {
+    let template = match kind {
+        WorkflowKind::Nextflow => NEXTFLOW,
+        WorkflowKind::Snakemake => SNAKEMAKE,
+    };
+    template
+        .replace("@VERSION@", env!("CARGO_PKG_VERSION"))
+        .replace("@N_BINS@", &params.n_bins.to_string())
+        .replace("@KERNEL_RADIUS@", &params.kernel_radius.to_string())
+        .replace("@FEATURES@", &params.features())
+        .replace("@BIN_EDGES@", params.bin_edges.as_str())
+}
+
+const NEXTFLOW: &str = r#"// radmap feature maps as a Nextflow DSL2 process, generated by radmap @VERSION@
+//
+// input:  tuple val(id), path(volume), path(mask). Pass file('NO_MASK') to map every voxel
+// output: the feature maps and provenance of the case, and its json line log
+//
+// exit codes: 1 internal error, 2 invalid options, 3 bad input, 4 bad mask,
+//             5 out of memory, 6 cancelled, 7 write failure, 8 degenerate results
+
+params.radmap_n_bins = @N_BINS@
+params.radmap_kernel_radius = @KERNEL_RADIUS@
+params.radmap_features = '@FEATURES@'
+params.radmap_bin_edges = '@BIN_EDGES@'
+params.radmap_cpus = 4
+params.radmap_memory = 16.GB
+
+process RADMAP {
+    tag "${id}"
+    cpus params.radmap_cpus
+    memory { params.radmap_memory * task.attempt }
+    // running out of memory is retried with more memory, unreadable inputs are skipped
+    errorStrategy {
+        task.exitStatus == 5 ? 'retry' : task.exitStatus in [3, 4] ? 'ignore' : 'terminate'
+    }
+    maxRetries 2
+
+    input:
+    tuple val(id), path(volume), path(mask)
+
+    output:
+    tuple val(id), path("out/*"), emit: maps
+    path "${id}.radmap.jsonl", emit: log
+
+    script:
+    def features = params.radmap_features == 'all'
+        ? 'all_features = true'
+        : 'feature = [' + params.radmap_features.tokenize(',').collect { "\"${it.trim()}\"" }.join(', ') + ']'
+    def link_mask = mask.name == 'NO_MASK'
+        ? ''
+        : "ln -s ../${mask} in/mask.${mask.name.substring(mask.name.indexOf('.') + 1)}"
+    """
+    mkdir -p in out
+    ln -s ../${volume} in/${volume.name}
+    ${link_mask}
+    cat > radmap.toml <<'TOML'
+    n_bins = ${params.radmap_n_bins}
+    kernel_radius = ${params.radmap_kernel_radius}
+    bin_edges = "${params.radmap_bin_edges}"
+    max_threads = ${task.cpus}
+    ${features}
+    TOML
+    radmap container-entrypoint --in-dir in --out-dir out --config radmap.toml > ${id}.radmap.jsonl
+    """
+}
+"#;
+
+const SNAKEMAKE: &str = r#"# radmap feature maps as a Snakemake rule, generated by radmap @VERSION@
+#
+# maps config["radmap_volume"] (default inputs/{case}.nii.gz) into radmap/{case}, using
+# config["radmap_mask"] (default inputs/{case}_mask.nii.gz) as the mask when it exists
+#
+# exit codes: 1 internal error, 2 invalid options, 3 bad input, 4 bad mask,
+#             5 out of memory, 6 cancelled, 7 write failure, 8 degenerate results
+# the json line log of each case is written to logs/radmap/{case}.jsonl
+
+import os
+
+
+def _radmap_mask(wildcards):
+    path = config.get("radmap_mask", "inputs/{case}_mask.nii.gz").format(case=wildcards.case)
+    return [path] if os.path.exists(path) else []
+
+
+def _radmap_options(wildcards, threads):
+    features = str(config.get("radmap_features", "@FEATURES@"))
+    if features == "all":
+        selection = "all_features = true"
+    else:
+        selection = "feature = [" + ", ".join(f'"{f.strip()}"' for f in features.split(",")) + "]"
+    return [
+        f"n_bins = {config.get('radmap_n_bins', @N_BINS@)}",
+        f"kernel_radius = {config.get('radmap_kernel_radius', @KERNEL_RADIUS@)}",
+        f"bin_edges = \"{config.get('radmap_bin_edges', '@BIN_EDGES@')}\"",
+        f"max_threads = {threads}",
+        selection,
+    ]
+
+
+rule radmap:
+    input:
+        volume=config.get("radmap_volume", "inputs/{case}.nii.gz"),
+        mask=_radmap_mask,
+    output:
+        directory("radmap/{case}"),
+    log:
+        "logs/radmap/{case}.jsonl",
+    threads: 4
+    resources:
+        # exit code 5 is out of memory, retries get more
+        mem_mb=lambda wildcards, attempt: 16000 * attempt,
+    retries: 2
+    params:
+        options=_radmap_options,
+    shell:
+        """
+        work=$(mktemp -d)
+        mkdir -p $work/in {output}
+        ln -s "$(realpath {input.volume})" $work/in/{wildcards.case}.nii.gz
+        for m in {input.mask}; do ln -s "$(realpath $m)" $work/in/{wildcards.case}_mask.nii.gz; done
+        printf '%s\\n' {params.options:q} > $work/radmap.toml
+        radmap container-entrypoint --in-dir $work/in --out-dir {output} --config $work/radmap.toml > {log}
+        """
+"#;
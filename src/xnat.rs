@@ -0,0 +1,211 @@
+//! Pulls imaging sessions from an XNAT server through its REST API and pushes feature maps back
+//! as an assessor of the session.
+//!
+//! The server and credentials come from the environment: `XNAT_HOST` (e.g.
+//! `https://xnat.example.org`), `XNAT_USER` and `XNAT_PASS`. An XNAT alias token can be used as
+//! the user and password. DICOM series are converted to nifti with `dcm2niix`, which must be on
+//! the `PATH`.
+
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+/// resource holding the radmap outputs on the assessor
+const RESOURCE: &str = "RADMAP";
+
+/// a session on the server, written `xnat://project/subject/session`
+#[derive(Debug, Clone)]
+pub struct XnatSession {
+    pub project: String,
+    pub subject: String,
+    pub session: String,
+}
+
+impl XnatSession {
+    fn rest_path(&self) -> String {
+        format!(
+            "data/projects/{}/subjects/{}/experiments/{}",
+            self.project, self.subject, self.session
+        )
+    }
+}
+
+impl fmt::Display for XnatSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "xnat://{}/{}/{}",
+            self.project, self.subject, self.session
+        )
+    }
+}
+
+impl FromStr for XnatSession {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rest = s
+            .strip_prefix("xnat://")
+            .ok_or_else(|| format!("{s} is not an xnat:// address"))?;
+        let parts: Vec<&str> = rest.trim_end_matches('/').split('/').collect();
+        let valid = |p: &&str| {
+            !p.is_empty()
+                && p.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+        };
+        match parts.as_slice() {
+            [project, subject, session] if parts.iter().all(valid) => Ok(XnatSession {
+                project: project.to_string(),
+                subject: subject.to_string(),
+                session: session.to_string(),
+            }),
+            _ => Err(format!("expected xnat://project/subject/session, got {s}")),
+        }
+    }
+}
+
+pub struct XnatClient {
+    host: String,
+    auth: String,
+    agent: ureq::Agent,
+}
+
+impl XnatClient {
+    pub fn from_env() -> Result<Self, String> {
+        let var = |name: &str| std::env::var(name).map_err(|_| format!("{name} is not set"));
+        let host = var("XNAT_HOST")?.trim_end_matches('/').to_string();
+        let credentials = format!("{}:{}", var("XNAT_USER")?, var("XNAT_PASS")?);
+        Ok(XnatClient {
+            host,
+            auth: format!("Basic {}", STANDARD.encode(credentials)),
+            agent: ureq::Agent::new(),
+        })
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{path}", self.host)
+    }
+
+    /// downloads the DICOM files of the session's scans (all scans if none are given) and
+    /// unpacks them under `dest`
+    pub fn download_scans(
+        &self,
+        session: &XnatSession,
+        scans: &[String],
+        dest: &Path,
+    ) -> Result<(), String> {
+        let scans = if scans.is_empty() {
+            "ALL".to_string()
+        } else {
+            scans.join(",")
+        };
+        let url = self.url(&format!("{}/scans/{scans}/files", session.rest_path()));
+        let response = self
+            .agent
+            .get(&url)
+            .set("Authorization", &self.auth)
+            .query("format", "zip")
+            .call()
+            .map_err(|e| format!("failed to download {session}: {e}"))?;
+
+        std::fs::create_dir_all(dest)
+            .map_err(|e| format!("failed to create {}: {e}", dest.display()))?;
+        let zip_path = dest.join("scans.zip");
+        let mut zip_file = BufWriter::new(
+            File::create(&zip_path)
+                .map_err(|e| format!("failed to create {}: {e}", zip_path.display()))?,
+        );
+        std::io::copy(&mut response.into_reader(), &mut zip_file)
+            .map_err(|e| format!("failed to download {session}: {e}"))?;
+        drop(zip_file);
+
+        let mut archive = File::open(&zip_path)
+            .map_err(|e| e.to_string())
+            .and_then(|f| zip::ZipArchive::new(f).map_err(|e| e.to_string()))
+            .map_err(|e| format!("failed to open {}: {e}", zip_path.display()))?;
+        archive
+            .extract(dest)
+            .map_err(|e| format!("failed to unpack {}: {e}", zip_path.display()))?;
+        let _ = std::fs::remove_file(&zip_path);
+        Ok(())
+    }
+
+    /// creates the assessor `label` on the session and uploads every file of `dir` to it
+    pub fn upload_assessor(
+        &self,
+        session: &XnatSession,
+        label: &str,
+        dir: &Path,
+    ) -> Result<(), String> {
+        let assessor = format!("{}/assessors/{label}", session.rest_path());
+        self.put(&assessor, Some(("xsiType", "xnat:qcAssessmentData")), None)?;
+        let resource = format!("{assessor}/resources/{RESOURCE}");
+        self.put(&resource, None, None)?;
+
+        let entries =
+            std::fs::read_dir(dir).map_err(|e| format!("failed to list {}: {e}", dir.display()))?;
+        for path in entries.filter_map(|e| e.ok().map(|e| e.path())) {
+            if !path.is_file() {
+                continue;
+            }
+            let name = path.file_name().unwrap().to_string_lossy().to_string();
+            let file =
+                File::open(&path).map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+            self.put(
+                &format!("{resource}/files/{name}"),
+                Some(("inbody", "true")),
+                Some(file),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn put(
+        &self,
+        path: &str,
+        query: Option<(&str, &str)>,
+        body: Option<File>,
+    ) -> Result<(), String> {
+        let mut request = self
+            .agent
+            .put(&self.url(path))
+            .set("Authorization", &self.auth);
+        if let Some((param, value)) = query {
+            request = request.query(param, value);
+        }
+        let result = match body {
+            Some(file) => request.send(file),
+            None => request.call(),
+        };
+        result.map_err(|e| format!("upload to {path} failed: {e}"))?;
+        Ok(())
+    }
+}
+
+/// converts every DICOM series under `dicom_dir` to a compressed nifti in `nifti_dir` and
+/// returns the new files
+pub fn convert_dicom(dicom_dir: &Path, nifti_dir: &Path) -> Result<Vec<PathBuf>, String> {
+    std::fs::create_dir_all(nifti_dir)
+        .map_err(|e| format!("failed to create {}: {e}", nifti_dir.display()))?;
+    let status = Command::new("dcm2niix")
+        .args(["-z", "y", "-b", "n", "-f", "%s_%d", "-o"])
+        .arg(nifti_dir)
+        .arg(dicom_dir)
+        .status()
+        .map_err(|e| format!("failed to run dcm2niix, is it installed? {e}"))?;
+    if !status.success() {
+        return Err(format!("dcm2niix failed with {status}"));
+    }
+    let mut volumes: Vec<PathBuf> = std::fs::read_dir(nifti_dir)
+        .map_err(|e| format!("failed to list {}: {e}", nifti_dir.display()))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.to_string_lossy().ends_with(".nii.gz"))
+        .collect();
+    volumes.sort();
+    Ok(volumes)
+}
@@ -0,0 +1,123 @@
+use std::path::{Path, PathBuf};
+
+use radmap::header::voxel_spacing;
+use radmap::nrrd::{
+    needs_detached_reader, read_detached_nrrd, relink_data_file, write_nrrd_like, NrrdHeader,
+};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join("nrrd")
+        .join(name)
+}
+
+#[test]
+fn reads_gzip_data_file() {
+    let path = fixture("gzip_data.nhdr");
+    assert!(needs_detached_reader(&path));
+    let (data, dims, _) = read_detached_nrrd(&path).unwrap();
+    assert_eq!(dims.shape(), &[2, 3, 2]);
+    assert_eq!(data, (0..12).map(|v| v as f64).collect::<Vec<_>>());
+}
+
+#[test]
+fn reads_data_file_in_another_directory() {
+    let path = fixture("headers/relative.nhdr");
+    assert!(needs_detached_reader(&path));
+    let (data, dims, _) = read_detached_nrrd(&path).unwrap();
+    assert_eq!(dims.shape(), &[2, 3, 2]);
+    assert_eq!(data, (0..12).map(|v| v as f64 * 0.5).collect::<Vec<_>>());
+    assert_eq!(voxel_spacing(&path), Some([1., 1., 3.]));
+}
+
+#[test]
+fn missing_data_file_is_an_error() {
+    let dir = std::env::temp_dir().join("radmap_missing_data_file");
+    std::fs::create_dir_all(&dir).unwrap();
+    let header = dir.join("missing.nhdr");
+    std::fs::write(
+        &header,
+        "NRRD0004\ntype: float\ndimension: 3\nsizes: 2 2 2\nencoding: gzip\ndata file: nope.raw.gz\n\n",
+    )
+    .unwrap();
+    let err = read_detached_nrrd(&header).unwrap_err();
+    assert!(err.contains("nope.raw.gz"), "{err}");
+}
+
+#[test]
+fn space_vectors_may_hold_spaces() {
+    let dir = std::env::temp_dir().join("radmap_spaced_vectors");
+    std::fs::create_dir_all(&dir).unwrap();
+    let header = dir.join("spaced.nhdr");
+    std::fs::write(
+        &header,
+        "NRRD0004\ntype: float\ndimension: 3\nsizes: 2 2 2\nspace: left-posterior-superior\n\
+         space directions: (0.5, 0, 0) (0, 0.5, 0) (0, 0, 2)\nspace origin: (1, 2, 3)\n\
+         encoding: raw\ndata file: spaced.raw\n\n",
+    )
+    .unwrap();
+    assert_eq!(voxel_spacing(&header), Some([0.5, 0.5, 2.]));
+    assert_eq!(
+        NrrdHeader::read(&header).unwrap().voxel_to_lps(),
+        Some([[0.5, 0., 0., 1.], [0., 0.5, 0., 2.], [0., 0., 2., 3.]])
+    );
+}
+
+#[test]
+fn relinked_header_reads_data_file_next_to_it() {
+    let path = fixture("headers/relative.nhdr");
+    let data_file = NrrdHeader::read(&path).unwrap().data_file().unwrap();
+    let name = data_file.file_name().unwrap().to_str().unwrap();
+    // lay the pair out the way a server stores an upload
+    let dir = std::env::temp_dir().join("radmap_relinked_header");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::copy(&data_file, dir.join(name)).unwrap();
+    let header = std::fs::read_to_string(&path).unwrap();
+    std::fs::write(dir.join("relative.nhdr"), relink_data_file(&header, name)).unwrap();
+
+    let (data, dims, relinked) = read_detached_nrrd(dir.join("relative.nhdr")).unwrap();
+    assert_eq!(relinked.field("data file"), Some(name));
+    assert_eq!(dims.shape(), &[2, 3, 2]);
+    assert_eq!(data, (0..12).map(|v| v as f64 * 0.5).collect::<Vec<_>>());
+}
+
+#[test]
+fn data_file_name_may_hold_spaces() {
+    let path = fixture("headers/relative.nhdr");
+    let data_file = NrrdHeader::read(&path).unwrap().data_file().unwrap();
+    let dir = std::env::temp_dir().join("radmap_spaced_data_file");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::copy(&data_file, dir.join("scan 01.raw")).unwrap();
+    let header = std::fs::read_to_string(&path).unwrap();
+    std::fs::write(
+        dir.join("scan.nhdr"),
+        relink_data_file(&header, "scan 01.raw"),
+    )
+    .unwrap();
+
+    let (data, _, header) = read_detached_nrrd(dir.join("scan.nhdr")).unwrap();
+    assert_eq!(header.data_file(), Some(dir.join("scan 01.raw")));
+    assert_eq!(data, (0..12).map(|v| v as f64 * 0.5).collect::<Vec<_>>());
+}
+
+#[test]
+fn outputs_keep_spatial_fields() {
+    let (data, dims, header) = read_detached_nrrd(fixture("gzip_data.nhdr")).unwrap();
+    let out_dir = std::env::temp_dir().join("radmap_detached_nrrd_output");
+    std::fs::create_dir_all(&out_dir).unwrap();
+    let vol: Vec<f32> = data.iter().map(|v| *v as f32).collect();
+    write_nrrd_like(out_dir.join("map"), &vol, dims, &header).unwrap();
+
+    let written = NrrdHeader::read(out_dir.join("map.nrrd")).unwrap();
+    assert_eq!(written.field("type"), Some("float"));
+    assert_eq!(written.field("encoding"), Some("raw"));
+    assert_eq!(written.field("sizes"), Some("2 3 2"));
+    assert_eq!(
+        written.field("space directions"),
+        Some("(0.5,0,0) (0,0.5,0) (0,0,2)")
+    );
+    assert_eq!(written.field("data file"), None);
+    assert_eq!(written.kv_pairs, header.kv_pairs);
+}
@@ -0,0 +1,34 @@
+use radmap::discretize::{integer_levels, level_indices, MAX_INTEGER_LEVELS};
+
+#[test]
+fn levels_are_the_distinct_values() {
+    // spans 1001 values but only holds 3
+    let vol = [0., 1000., 500., 0., f64::NAN];
+    assert_eq!(integer_levels(&vol), Some(vec![0., 500., 1000.]));
+}
+
+#[test]
+fn images_with_many_values_are_binned() {
+    let vol: Vec<f64> = (0..=MAX_INTEGER_LEVELS).map(|v| v as f64).collect();
+    assert_eq!(integer_levels(&vol), None);
+    assert_eq!(
+        integer_levels(&vol[..MAX_INTEGER_LEVELS]).map(|l| l.len()),
+        Some(MAX_INTEGER_LEVELS)
+    );
+}
+
+#[test]
+fn constant_and_fractional_inputs_are_binned() {
+    assert_eq!(integer_levels(&[3., 3., 3.]), None);
+    assert_eq!(integer_levels(&[1., 2.5]), None);
+}
+
+#[test]
+fn level_indices_close_the_gaps() {
+    let vol = [10., -2., 7., f64::NAN, 10.];
+    let levels = integer_levels(&vol).unwrap();
+    let gray = level_indices(&vol, &levels);
+    assert_eq!(gray[..3], [2., 0., 1.]);
+    assert!(gray[3].is_nan());
+    assert_eq!(gray[4], 2.);
+}
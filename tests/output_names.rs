@@ -0,0 +1,27 @@
+use std::path::Path;
+
+use radmap::io::{input_stem, output_path};
+
+#[test]
+fn compound_extensions_are_stripped_as_a_unit() {
+    assert_eq!(input_stem(Path::new("/data/case.nii.gz")), "case");
+    assert_eq!(input_stem(Path::new("/data/case.NII.GZ")), "case");
+    assert_eq!(input_stem(Path::new("case.nii")), "case");
+    assert_eq!(input_stem(Path::new("case.nrrd")), "case");
+    assert_eq!(input_stem(Path::new("case.nhdr")), "case");
+}
+
+#[test]
+fn only_the_volume_extension_is_stripped() {
+    assert_eq!(input_stem(Path::new("sub-01.T1w.nii.gz")), "sub-01.T1w");
+    assert_eq!(input_stem(Path::new("scan.v2.gz")), "scan.v2");
+}
+
+#[test]
+fn output_names_keep_the_stem() {
+    let stem = input_stem(Path::new("case.nii.gz"));
+    assert_eq!(
+        output_path(Path::new("out"), &stem, "firstorder_mean"),
+        Path::new("out").join("case_firstorder_mean")
+    );
+}
@@ -0,0 +1,24 @@
+use radmap::discretize::ranks;
+
+#[test]
+fn ties_share_the_average_of_their_ranks() {
+    assert_eq!(ranks(&[3., 1., 3., 2., 3.], None), [3., 0., 3., 1., 3.]);
+    assert_eq!(ranks(&[5., 5.], None), [0.5, 0.5]);
+}
+
+#[test]
+fn non_finite_voxels_are_left_out() {
+    let r = ranks(&[2., f64::NAN, 1., f64::INFINITY], None);
+    assert_eq!(r[0], 1.);
+    assert!(r[1].is_nan());
+    assert_eq!(r[2], 0.);
+    assert_eq!(r[3], f64::INFINITY);
+}
+
+#[test]
+fn voxels_outside_the_mask_rank_between_the_masked_ones() {
+    let vol = [1., 2., 3., 4.];
+    let mask = [1., 0., 1., 1.];
+    // 2 lies between the masked 1 and 3, ranked 0 and 1
+    assert_eq!(ranks(&vol, Some(&mask)), [0., 0.5, 1., 2.]);
+}
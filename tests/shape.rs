@@ -0,0 +1,85 @@
+use std::f64::consts::SQRT_2;
+
+use radmap::shape::shape_features;
+
+fn assert_close(got: f64, expected: f64) {
+    assert!(
+        (got - expected).abs() <= 1e-9 * expected.abs().max(1.),
+        "{got} != {expected}"
+    );
+}
+
+/// mask of the voxels of a volume of `shape` for which `inside` holds, x fastest
+fn mask(shape: [usize; 3], inside: impl Fn([usize; 3]) -> bool) -> Vec<f64> {
+    (0..shape[2])
+        .flat_map(|z| (0..shape[1]).flat_map(move |y| (0..shape[0]).map(move |x| [x, y, z])))
+        .map(|p| if inside(p) { 1. } else { 0. })
+        .collect()
+}
+
+/// a cube of 3 voxels a side in the middle of a 5 voxel volume. The mesh is the inner cube
+/// between the voxel centres, 2 a side, grown by half a voxel: its faces pushed out by 0.5, its
+/// edges cut by strips 0.5√2 wide and its corners by triangles of side 0.5√2
+#[test]
+fn cube() {
+    let shape = [5; 3];
+    let m = mask(shape, |p| p.iter().all(|&c| (1..4).contains(&c)));
+    let (a, h) = (2., 0.5);
+    let volume = a * a * a + 6. * a * a * h + 6. * a * h * h + 4. / 3. * h * h * h;
+    let area = 6. * a * a + 12. * a * h * SQRT_2 + 4. * 3f64.sqrt() * h * h;
+
+    let f = shape_features(&m, shape, [1.; 3]).unwrap();
+    assert_close(f.voxel_volume, 27.);
+    assert_close(f.mesh_volume, volume);
+    assert_close(f.surface_area, area);
+    assert_close(f.surface_volume_ratio, area / volume);
+    // between opposite corners of the flat parts of opposite faces
+    assert_close(f.maximum_3d_diameter, 17f64.sqrt());
+    assert_close(f.elongation, 1.);
+    assert_close(f.flatness, 1.);
+}
+
+#[test]
+fn spacing_scales_the_cube() {
+    let shape = [5; 3];
+    let m = mask(shape, |p| p.iter().all(|&c| (1..4).contains(&c)));
+    let unit = shape_features(&m, shape, [1.; 3]).unwrap();
+    let f = shape_features(&m, shape, [2.; 3]).unwrap();
+    assert_close(f.voxel_volume, 8. * unit.voxel_volume);
+    assert_close(f.mesh_volume, 8. * unit.mesh_volume);
+    assert_close(f.surface_area, 4. * unit.surface_area);
+    assert_close(f.maximum_3d_diameter, 2. * unit.maximum_3d_diameter);
+    assert_close(f.sphericity, unit.sphericity);
+}
+
+/// a single voxel is an octahedron with its vertices half a voxel from the centre
+#[test]
+fn single_voxel() {
+    let shape = [3; 3];
+    let m = mask(shape, |p| p == [1, 1, 1]);
+    let f = shape_features(&m, shape, [1.; 3]).unwrap();
+    assert_close(f.mesh_volume, 1. / 6.);
+    assert_close(f.surface_area, 3f64.sqrt());
+    assert_close(f.maximum_3d_diameter, 1.);
+    assert_close(f.elongation, 1.);
+    assert_close(f.flatness, 1.);
+}
+
+/// a voxelized sphere is as long along every axis, and close to but less round than a sphere
+#[test]
+fn sphere() {
+    let shape = [21; 3];
+    let m = mask(shape, |p| {
+        p.iter().map(|&c| (c as f64 - 10.).powi(2)).sum::<f64>() <= 64.
+    });
+    let f = shape_features(&m, shape, [1.; 3]).unwrap();
+    assert_close(f.elongation, 1.);
+    assert_close(f.flatness, 1.);
+    assert!(f.sphericity > 0.9 && f.sphericity < 1., "{}", f.sphericity);
+    assert!(f.maximum_3d_diameter > 16. && f.maximum_3d_diameter <= 17.);
+}
+
+#[test]
+fn empty_mask_is_an_error() {
+    assert!(shape_features(&[0.; 8], [2; 3], [1.; 3]).is_err());
+}